@@ -1,14 +1,16 @@
+// The lints of clippy.toml are only meant for the library, see the Readme
+#![allow(clippy::disallowed_macros, clippy::disallowed_methods)]
 use criterion::{criterion_group, criterion_main, Criterion};
 use ruzstd::FrameDecoder;
 
 fn criterion_benchmark(c: &mut Criterion) {
     let mut fr = FrameDecoder::new();
-    let mut target_slice = &mut vec![0u8; 1024 * 1024 * 200];
+    let target_slice = &mut vec![0u8; 1024 * 1024 * 200];
     let src = include_bytes!("../decodecorpus_files/z000033.zst");
 
     c.bench_function("decode_all_slice", |b| {
         b.iter(|| {
-            fr.decode_all(src, &mut target_slice).unwrap();
+            fr.decode_all(src, target_slice).unwrap();
         })
     });
 }
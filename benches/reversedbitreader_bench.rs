@@ -1,3 +1,5 @@
+// The lints of clippy.toml are only meant for the library, see the Readme
+#![allow(clippy::disallowed_macros, clippy::disallowed_methods)]
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use rand::{Rng, SeedableRng};
 use ruzstd::decoding::bit_reader_reverse::BitReaderReversed;
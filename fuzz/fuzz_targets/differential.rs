@@ -0,0 +1,8 @@
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate ruzstd;
+use ruzstd::fuzzing::differential;
+
+fuzz_target!(|data: &[u8]| {
+    differential(data);
+});
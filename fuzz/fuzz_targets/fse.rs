@@ -1,8 +1,8 @@
 #![no_main]
 #[macro_use] extern crate libfuzzer_sys;
 extern crate ruzstd;
-use ruzstd::fse::round_trip;
+use ruzstd::fuzzing::fse_round_trip;
 
 fuzz_target!(|data: &[u8]| {
-    round_trip(data);
+    fse_round_trip(data);
 });
\ No newline at end of file
@@ -1,8 +1,8 @@
 #![no_main]
 #[macro_use] extern crate libfuzzer_sys;
 extern crate ruzstd;
-use ruzstd::huff0::round_trip;
+use ruzstd::fuzzing::huff0_round_trip;
 
 fuzz_target!(|data: &[u8]| {
-    round_trip(data);
+    huff0_round_trip(data);
 });
\ No newline at end of file
@@ -1,116 +1,309 @@
+// The lints of clippy.toml are only meant for the library, see the Readme
+#![allow(clippy::disallowed_macros, clippy::disallowed_methods)]
 extern crate ruzstd;
+use std::convert::TryFrom;
 use std::fs::File;
+use std::io::BufRead;
 use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::IsTerminal;
 use std::io::Read;
-use std::io::Seek;
-use std::io::SeekFrom;
 use std::io::Write;
+use std::num::NonZeroU32;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
+use ruzstd::capabilities::capabilities;
+use ruzstd::decoding::dictionary::Dictionary;
+use ruzstd::encoding::Adapt;
 use ruzstd::encoding::CompressionLevel;
+use ruzstd::encoding::EncodeOptions;
 use ruzstd::encoding::FrameCompressor;
+use ruzstd::encoding::ZstdLevel;
+use ruzstd::encoding::{MAX_WINDOW_LOG, MIN_WINDOW_LOG};
+use ruzstd::frame::InspectFrameError;
 use ruzstd::frame::ReadFrameHeaderError;
 use ruzstd::frame_decoder::FrameDecoderError;
 
+/// The file name used for stdin and stdout
+const STDIO: &str = "-";
+
+/// Printed for `-h` and `--help`
+const USAGE: &str = "\
+Usage: zstd [OPTIONS...] [FILE... | -] [-o OUTPUT]
+
+Compresses or decompresses the files, or stdin to stdout without any files.
+Short flags can be combined, e.g. -dc is -d -c.
+
+  -#              Compression level 1 to 19 (default: 3)
+  --fast[=#]      Negative compression level, faster and larger (default: 1)
+  --ultra         Allow levels 20 to 22
+  --long[=#]      Window log of the compression, also lifts the window limit
+                  of the decompression (default: 27)
+  --adapt         Raise and lower the level with the speed of the output
+  --check         Write a checksum into the frames (default)
+  --no-check      Do not write a checksum
+  -D DICT         Compress or decompress with a dictionary
+  --patch-from=F  Compress or decompress as a patch against the file F
+  -d, --decompress
+  -z, --compress
+  -t, --test      Check the integrity of compressed files
+  -l, --list      Print information about compressed files
+  -c, --stdout    Write to stdout
+  -o OUTPUT       Write the only input file to OUTPUT
+  -f, --force     Overwrite existing files, write compressed data to a terminal
+  -k, --keep      Keep the source files (default)
+  --rm            Remove the source files after they were processed
+  -r              Process the files in directories recursively
+  --exclude=PAT   Skip files and directories matching PAT in recursive mode
+  -T#, --threads=#
+                  Process up to # files in parallel (default: 1). Every file is
+                  processed by a single thread, so one large file gets no
+                  faster. 0 uses one thread per physical core, or per logical
+                  CPU where the cores are not known
+  -b#             Benchmark level #
+  -e#             Benchmark all levels from -b# up to level #
+  -q, --quiet     Only print errors
+  -v, --verbose   Print the progress of -d and -t even if stderr is not a
+                  terminal
+  -h, --help      Print this help
+";
+
 struct StateTracker {
     bytes_used: u64,
     frames_used: usize,
     valid_checksums: usize,
     invalid_checksums: usize,
     file_pos: u64,
-    file_size: u64,
+    file_size: Option<u64>,
     old_percentage: i8,
+    /// Print the progress and a summary of the frames to stderr
+    progress: bool,
+}
+
+struct Options {
+    decompress: bool,
+    /// Only print information about the frames in the files
+    list: bool,
+    /// Only decompress the files to check their integrity, without writing the output anywhere
+    test: bool,
+    /// Walk directories and process all files in them
+    recursive: bool,
+    /// Files and directories whose name matches one of these patterns are skipped in recursive mode
+    exclude: Vec<String>,
+    /// How many files are processed in parallel
+    threads: usize,
+    /// Benchmark the levels from the first to the last one (inclusive) instead of writing any output
+    benchmark: Option<(u8, u8)>,
+    stdout: bool,
+    /// Overwrite existing files and write compressed data to a terminal
+    force: bool,
+    /// Remove the source file after it was (de)compressed successfully
+    remove_source: bool,
+    output: Option<String>,
+    /// The dictionary given with `-D`
+    dictionary: Option<String>,
+    /// The older version of the file the compressed data is a patch against
+    patch_from: Option<String>,
+    /// The window log given with `--long`, which also raises the window size the decoder accepts
+    window_log: Option<u8>,
+    /// Write a checksum into compressed frames
+    checksum: bool,
+    /// Raise and lower the level with the speed of the output, given with `--adapt`
+    adapt: bool,
+    /// Only print errors, given with `-q`
+    quiet: bool,
+    /// Print the progress even if stderr is not a terminal, given with `-v`
+    verbose: bool,
+    level: CompressionLevel,
+    file_paths: Vec<String>,
+}
+
+/// Counts the bytes going through a reader or writer
+struct Counting<T> {
+    inner: T,
+    count: u64,
 }
 
-fn decompress(flags: &[String], file_paths: &[String]) {
-    if !flags.contains(&"-d".to_owned()) {
-        eprintln!("This zstd implementation only supports decompression. Please add a \"-d\" flag");
-        return;
+impl<T> Counting<T> {
+    fn new(inner: T) -> Self {
+        Counting { inner, count: 0 }
     }
+}
 
-    if !flags.contains(&"-c".to_owned()) {
-        eprintln!("This zstd implementation only supports output on the stdout. Please add a \"-c\" flag and pipe the output into a file");
-        return;
+impl<T: Read> Read for Counting<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
     }
+}
 
-    if flags.len() != 2 {
-        eprintln!(
-            "No flags other than -d and -c are currently implemented. Flags used: {:?}",
-            flags
-        );
-        return;
+impl<T: Write> Write for Counting<T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
     }
 
-    let mut frame_dec = ruzstd::FrameDecoder::new();
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
 
-    for path in file_paths {
+/// Open the input file, or stdin for [STDIO]. Also returns the size of the file if it is known.
+fn open_input(path: &str) -> Result<(Box<dyn Read>, Option<u64>), String> {
+    if path == STDIO {
+        Ok((Box::new(std::io::stdin().lock()), None))
+    } else {
+        let file = File::open(path).map_err(|e| format!("{path}: {e}"))?;
+        let size = file.metadata().map_err(|e| format!("{path}: {e}"))?.len();
+        Ok((Box::new(file), Some(size)))
+    }
+}
+
+/// (De)compress the file at `path` into the output chosen by the options
+fn process_file(
+    options: &Options,
+    path: &str,
+    frame_dec: &mut ruzstd::FrameDecoder,
+) -> Result<(), String> {
+    if !options.decompress && !options.force && path.ends_with(".zst") {
+        return Err(format!("{path} already has .zst suffix -- ignored"));
+    }
+    let output_path = output_path(options, path)?;
+    let mut output = open_output(options, &output_path)?;
+
+    let result = if options.decompress {
+        decompress_file(options, path, frame_dec, &mut output)
+    } else {
+        compress_file(options, path, &output_path, &mut output)
+    };
+    drop(output);
+    if result.is_err() && output_path != STDIO {
+        // Don't leave incomplete files behind
+        let _ = std::fs::remove_file(&output_path);
+    }
+    result?;
+
+    remove_source(options, path, &output_path)
+}
+
+fn decompress_file(
+    options: &Options,
+    path: &str,
+    frame_dec: &mut ruzstd::FrameDecoder,
+    mut output: &mut dyn Write,
+) -> Result<(), String> {
+    // Like zstd, only show the progress to people watching it, so scripts can read stderr
+    let progress = !options.quiet && (options.verbose || std::io::stderr().is_terminal());
+    if progress {
         eprintln!("File: {}", path);
-        let mut f = File::open(path).unwrap();
-
-        let mut tracker = StateTracker {
-            bytes_used: 0,
-            frames_used: 0,
-            valid_checksums: 0,
-            invalid_checksums: 0,
-            file_size: f.metadata().unwrap().len(),
-            file_pos: 0,
-            old_percentage: -1,
-        };
+    }
+    let (input, file_size) = open_input(path)?;
+    let mut input = BufReader::new(Counting::new(input));
+
+    let mut tracker = StateTracker {
+        bytes_used: 0,
+        frames_used: 0,
+        valid_checksums: 0,
+        invalid_checksums: 0,
+        file_size,
+        file_pos: 0,
+        old_percentage: -1,
+        progress,
+    };
 
-        let batch_size = 1024 * 1024 * 10;
-        let mut result = vec![0; batch_size];
-
-        while tracker.file_pos < tracker.file_size {
-            match frame_dec.reset(&mut f) {
-                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
-                    magic_number: magic_num,
-                    length: skip_size,
-                })) => {
-                    eprintln!("Found a skippable frame with magic number: {magic_num} and size: {skip_size}");
-                    tracker.file_pos = f.stream_position().unwrap();
-                    tracker.file_pos += skip_size as u64;
-                    f.seek(SeekFrom::Current(skip_size as i64)).unwrap();
-                    continue;
+    let batch_size = 1024 * 1024 * 10;
+    let mut result = vec![0; batch_size];
+
+    while !input.fill_buf().map_err(|e| e.to_string())?.is_empty() {
+        match frame_dec.reset(&mut input) {
+            Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                magic_number: magic_num,
+                length: skip_size,
+            })) => {
+                if progress {
+                    eprintln!(
+                        "Found a skippable frame with magic number: {magic_num} and size: {skip_size}"
+                    );
                 }
-                other => other.unwrap(),
+                std::io::copy(
+                    &mut input.by_ref().take(u64::from(skip_size)),
+                    &mut std::io::sink(),
+                )
+                .map_err(|e| e.to_string())?;
+                continue;
+            }
+            Err(FrameDecoderError::DictNotProvided { dict_id }) => {
+                return Err(format!(
+                    "{path}: needs the dictionary with ID {dict_id}, provide it with -D"
+                ));
             }
+            Err(FrameDecoderError::WindowSizeTooBig { requested, .. }) => {
+                // The smallest window log whose window is at least as large as requested
+                let window_log = u64::BITS - (requested - 1).leading_zeros();
+                return Err(format!(
+                    "{path}: needs a window of {requested} bytes, allow it with --long={window_log}"
+                ));
+            }
+            other => other.map_err(|e| e.to_string())?,
+        }
+        if options.patch_from.is_some() {
+            frame_dec
+                .force_dict(PATCH_DICT_ID)
+                .map_err(|e| e.to_string())?;
+        }
 
-            tracker.frames_used += 1;
+        tracker.frames_used += 1;
 
-            while !frame_dec.is_finished() {
-                frame_dec
-                    .decode_blocks(&mut f, ruzstd::BlockDecodingStrategy::UptoBytes(batch_size))
-                    .unwrap();
+        while !frame_dec.is_finished() {
+            frame_dec
+                .decode_blocks(
+                    &mut input,
+                    ruzstd::BlockDecodingStrategy::UptoBytes(batch_size),
+                )
+                .map_err(|e| e.to_string())?;
 
-                if frame_dec.can_collect() > batch_size {
-                    let x = frame_dec.read(result.as_mut_slice()).unwrap();
-                    tracker.file_pos = f.stream_position().unwrap();
-                    do_something(&result[..x], &mut tracker);
-                }
+            if frame_dec.can_collect() > batch_size {
+                let x = frame_dec
+                    .read(result.as_mut_slice())
+                    .map_err(|e| e.to_string())?;
+                tracker.file_pos = input_position(&input);
+                do_something(&result[..x], &mut output, &mut tracker)?;
             }
+        }
 
-            // handle the last chunk of data
-            while frame_dec.can_collect() > 0 {
-                let x = frame_dec.read(result.as_mut_slice()).unwrap();
-                tracker.file_pos = f.stream_position().unwrap();
-                do_something(&result[..x], &mut tracker);
-            }
+        // handle the last chunk of data
+        while frame_dec.can_collect() > 0 {
+            let x = frame_dec
+                .read(result.as_mut_slice())
+                .map_err(|e| e.to_string())?;
+            tracker.file_pos = input_position(&input);
+            do_something(&result[..x], &mut output, &mut tracker)?;
+        }
 
-            #[cfg(feature = "hash")]
-            if let Some(chksum) = frame_dec.get_checksum_from_data() {
-                if frame_dec.get_calculated_checksum().unwrap() != chksum {
-                    tracker.invalid_checksums += 1;
-                    eprintln!(
-                        "Checksum did not match in frame {}! From data: {}, calculated while decoding: {}",
-                        tracker.frames_used,
-                        chksum,
-                        frame_dec.get_calculated_checksum().unwrap()
-                    );
-                } else {
-                    tracker.valid_checksums += 1;
-                }
+        #[cfg(feature = "hash")]
+        if let (Some(chksum), Some(calculated)) = (
+            frame_dec.get_checksum_from_data(),
+            frame_dec.get_calculated_checksum(),
+        ) {
+            if calculated != chksum {
+                tracker.invalid_checksums += 1;
+                eprintln!(
+                    "Checksum did not match in frame {}! From data: {}, calculated while decoding: {}",
+                    tracker.frames_used,
+                    chksum,
+                    calculated
+                );
+            } else {
+                tracker.valid_checksums += 1;
             }
         }
+    }
+    output.flush().map_err(|e| e.to_string())?;
 
+    if progress {
         eprintln!(
             "\nDecoded frames: {}  bytes: {}",
             tracker.frames_used, tracker.bytes_used
@@ -125,42 +318,839 @@ fn decompress(flags: &[String], file_paths: &[String]) {
             );
         }
     }
+    if tracker.invalid_checksums > 0 {
+        return Err(format!("{path}: checksum mismatch"));
+    }
+    Ok(())
 }
 
-fn main() {
-    let mut file_paths: Vec<_> = std::env::args().filter(|f| !f.starts_with('-')).collect();
-    let flags: Vec<_> = std::env::args().filter(|f| f.starts_with('-')).collect();
-    file_paths.remove(0);
-
-    if flags.is_empty() {
-        for path in file_paths {
-            let file = std::fs::File::open(&path).unwrap();
-            let input_len = file.metadata().unwrap().len() as usize;
-            let file = BufReader::new(file);
-            let mut output = Vec::new();
-            let mut encoder = FrameCompressor::new(file, &mut output, CompressionLevel::Fastest);
-            encoder.compress();
-            println!(
-                "Compressed {path:} from {} to {} ({}%)",
-                input_len,
-                output.len(),
-                output.len() * 100 / input_len
+/// Decompress the file at `path` without writing the result anywhere, verifying the checksums
+fn test_file(
+    options: &Options,
+    path: &str,
+    frame_dec: &mut ruzstd::FrameDecoder,
+) -> Result<(), String> {
+    match decompress_file(options, path, frame_dec, &mut std::io::sink()) {
+        Ok(()) => {
+            if !options.quiet {
+                eprintln!("{path}: OK");
+            }
+            Ok(())
+        }
+        Err(err) => Err(format!("{err}\n{path}: FAILED")),
+    }
+}
+
+/// How long each level is benchmarked in each direction
+const BENCH_DURATION: Duration = Duration::from_secs(1);
+
+/// Compress and decompress the file at `path` in memory with every level from `first` to `last`
+/// and print the ratio and the speed in both directions
+fn benchmark_file(
+    path: &str,
+    first: u8,
+    last: u8,
+    frame_dec: &mut ruzstd::FrameDecoder,
+) -> Result<(), String> {
+    let (mut input, _) = open_input(path)?;
+    let mut data = Vec::new();
+    input
+        .read_to_end(&mut data)
+        .map_err(|e| format!("{path}: {e}"))?;
+
+    let mut compressed = Vec::new();
+    let mut decompressed = Vec::with_capacity(data.len());
+    for zstd_level in first..=last {
+        let level = CompressionLevel::from(ZstdLevel::try_from(zstd_level).unwrap());
+        if !capabilities().supports_level(i32::from(zstd_level)) {
+            println!("{zstd_level:>2}#{path} : level is not implemented yet");
+            continue;
+        }
+        let options = EncodeOptions {
+            level,
+            ultra: true,
+            ..EncodeOptions::default()
+        };
+
+        let compress_time = measure(|| {
+            compressed.clear();
+            FrameCompressor::with_options(data.as_slice(), &mut compressed, options.clone())
+                .compress()
+                .map_err(|e| format!("{path}: {e}"))
+        })?;
+        let decompress_time = measure(|| {
+            decompressed.clear();
+            frame_dec
+                .decode_all_to_vec(&compressed, &mut decompressed)
+                .map_err(|e| format!("{path}: {e}"))
+        })?;
+        if decompressed != data {
+            return Err(format!(
+                "{path}: decompressed data differs from the input at level {zstd_level}"
+            ));
+        }
+
+        let mb_per_s = |time: Duration| data.len() as f64 / time.as_secs_f64() / 1_000_000.0;
+        println!(
+            "{zstd_level:>2}#{path} : {:>10} -> {:>10} ({:.3}), {:>7.1} MB/s, {:>7.1} MB/s",
+            data.len(),
+            compressed.len(),
+            data.len() as f64 / compressed.len() as f64,
+            mb_per_s(compress_time),
+            mb_per_s(decompress_time),
+        );
+    }
+    Ok(())
+}
+
+/// Run `f` repeatedly for at least [BENCH_DURATION] and return the average time one run took
+fn measure(mut f: impl FnMut() -> Result<(), String>) -> Result<Duration, String> {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs == 0 || start.elapsed() < BENCH_DURATION {
+        f()?;
+        runs += 1;
+    }
+    Ok(start.elapsed() / runs)
+}
+
+/// Parse the level of `-b#` or `-e#`
+fn parse_bench_level(level: &str) -> Result<u8, String> {
+    match level.parse::<u8>() {
+        Ok(level @ 1..=22) => Ok(level),
+        _ => Err(format!("Invalid benchmark level {level}, use 1 to 22")),
+    }
+}
+
+/// The ID the reference of `--patch-from` is registered with in the decoder.
+/// Real dictionaries never use 0, so it can not be confused with them.
+const PATCH_DICT_ID: u32 = 0;
+
+/// Read the reference of `--patch-from` at `path` and make it available to the decoder
+fn load_patch_reference(path: &str, frame_dec: &mut ruzstd::FrameDecoder) -> Result<(), String> {
+    let reference = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    frame_dec
+        .add_dict(Dictionary::from_raw_content(PATCH_DICT_ID, reference))
+        .map_err(|e| format!("{path}: {e}"))
+}
+
+/// Read the dictionary at `path`
+fn read_dictionary(path: &str) -> Result<Dictionary, String> {
+    let raw = std::fs::read(path).map_err(|e| format!("{path}: {e}"))?;
+    Dictionary::decode_dict(&raw).map_err(|e| format!("{path}: {e}"))
+}
+
+/// Read the dictionary at `path` and make it available to the decoder
+fn load_dictionary(path: &str, frame_dec: &mut ruzstd::FrameDecoder) -> Result<(), String> {
+    let dict = read_dictionary(path)?;
+    frame_dec.add_dict(dict).map_err(|e| format!("{path}: {e}"))
+}
+
+/// Print one line with information about the frames in the file at `path`, without decompressing them
+fn list_file(path: &str) -> Result<(), String> {
+    let (input, _) = open_input(path)?;
+    let mut input = BufReader::new(input);
+
+    let mut frames = 0;
+    let mut skips = 0;
+    let mut compressed = 0;
+    // Only known if every frame stores its content size
+    let mut decompressed = Some(0);
+    let mut frames_with_checksum = 0;
+    let mut dict_ids = Vec::new();
+    while !input
+        .fill_buf()
+        .map_err(|e| format!("{path}: {e}"))?
+        .is_empty()
+    {
+        match ruzstd::frame::inspect_frame(&mut input) {
+            Ok(info) => {
+                frames += 1;
+                compressed += info.compressed_size;
+                decompressed = decompressed
+                    .zip(info.decompressed_size())
+                    .map(|(a, b)| a + b);
+                if info.checksum.is_some() {
+                    frames_with_checksum += 1;
+                }
+                if let Some(id) = info.header.dictionary_id() {
+                    if !dict_ids.contains(&id) {
+                        dict_ids.push(id);
+                    }
+                }
+            }
+            Err(InspectFrameError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                length,
+                ..
+            })) => {
+                let skipped = std::io::copy(
+                    &mut input.by_ref().take(u64::from(length)),
+                    &mut std::io::sink(),
+                )
+                .map_err(|e| format!("{path}: {e}"))?;
+                if skipped != u64::from(length) {
+                    return Err(format!("{path}: truncated skippable frame"));
+                }
+                skips += 1;
+                // magic number and length field
+                compressed += 8 + skipped;
+            }
+            Err(e) => return Err(format!("{path}: {e}")),
+        }
+    }
+
+    let check = match frames_with_checksum {
+        0 => "None",
+        n if n == frames => "XXH64",
+        _ => "Mixed",
+    };
+    let dict = if dict_ids.is_empty() {
+        "-".to_owned()
+    } else {
+        dict_ids
+            .iter()
+            .map(|id| id.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let (uncompressed, ratio) = match decompressed {
+        Some(size) if frames > 0 => (
+            human_size(size),
+            format!("{:.3}", size as f64 / compressed.max(1) as f64),
+        ),
+        _ => ("-".to_owned(), "-".to_owned()),
+    };
+    println!(
+        "{frames:>6} {skips:>5} {:>12} {uncompressed:>12} {ratio:>7} {check:>5} {dict:>10}  {path}",
+        human_size(compressed),
+    );
+    Ok(())
+}
+
+/// Format a size in bytes with a binary unit, like the zstd CLI does
+fn human_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if size < 1024 {
+        return format!("{size} B");
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.2} {}", UNITS[unit])
+}
+
+/// How many bytes of the input have actually been consumed by the decoder
+fn input_position<R: Read>(input: &BufReader<Counting<R>>) -> u64 {
+    input.get_ref().count - input.buffer().len() as u64
+}
+
+fn compress_file(
+    options: &Options,
+    path: &str,
+    output_path: &str,
+    output: &mut dyn Write,
+) -> Result<(), String> {
+    let (input, _) = open_input(path)?;
+    let mut input = Counting::new(BufReader::new(input));
+    let mut output = Counting::new(output);
+    let mut encode_options = EncodeOptions {
+        level: options.level,
+        // The flags were checked already: levels above 19 need --ultra, large windows --long
+        ultra: true,
+        ..EncodeOptions::cli_default()
+    };
+    #[cfg(feature = "hash")]
+    {
+        encode_options.checksum = options.checksum;
+    }
+    if options.window_log.is_some() {
+        encode_options.window_log = options.window_log;
+    }
+    if options.adapt {
+        encode_options.adapt = Some(Adapt::default());
+    }
+    let mut encoder = FrameCompressor::with_options(&mut input, &mut output, encode_options);
+    if let Some(dictionary) = &options.dictionary {
+        encoder.set_dictionary(Some(read_dictionary(dictionary)?));
+    }
+    if let Some(reference) = &options.patch_from {
+        encoder.set_patch_reference(
+            std::fs::read(reference).map_err(|e| format!("{reference}: {e}"))?,
+        );
+    }
+    encoder.compress().map_err(|e| e.to_string())?;
+    output.flush().map_err(|e| e.to_string())?;
+
+    if !options.quiet {
+        eprintln!(
+            "{path} : {:.2}% ({} => {} bytes, {output_path})",
+            output.count as f64 * 100.0 / input.count.max(1) as f64,
+            input.count,
+            output.count,
+        );
+    }
+    Ok(())
+}
+
+/// The file the result of (de)compressing `path` is written to, [STDIO] for stdout
+fn output_path(options: &Options, path: &str) -> Result<String, String> {
+    if let Some(output) = &options.output {
+        return Ok(output.clone());
+    }
+    if options.stdout || path == STDIO {
+        return Ok(STDIO.to_owned());
+    }
+    if !options.decompress {
+        return Ok(format!("{path}.zst"));
+    }
+    if let Some(stem) = path.strip_suffix(".zst") {
+        Ok(stem.to_owned())
+    } else if let Some(stem) = path.strip_suffix(".tzst") {
+        Ok(format!("{stem}.tar"))
+    } else {
+        Err(format!("{path}: unknown suffix -- ignored"))
+    }
+}
+
+fn open_output(options: &Options, output_path: &str) -> Result<Box<dyn Write>, String> {
+    if output_path == STDIO {
+        if !options.decompress && !options.force && std::io::stdout().is_terminal() {
+            return Err(
+                "Refusing to write compressed data to the terminal, use -f to force it".to_owned(),
             );
         }
+        return Ok(Box::new(BufWriter::new(std::io::stdout().lock())));
+    }
+    // create_new checks for an existing file and creates it in one step
+    let file = if options.force {
+        File::create(output_path)
     } else {
-        decompress(&flags, &file_paths);
+        File::create_new(output_path)
+    };
+    let file = file.map_err(|e| match e.kind() {
+        std::io::ErrorKind::AlreadyExists => {
+            format!("{output_path} already exists, use -f to overwrite it")
+        }
+        _ => format!("{output_path}: {e}"),
+    })?;
+    Ok(Box::new(BufWriter::new(file)))
+}
+
+/// Remove the source file after it was processed, if requested and the output went into another file
+fn remove_source(options: &Options, path: &str, output_path: &str) -> Result<(), String> {
+    if options.remove_source && path != STDIO && output_path != STDIO {
+        std::fs::remove_file(path).map_err(|e| format!("{path}: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Levels above this need the `--ultra` flag, like in the zstd CLI
+const MAX_LEVEL_WITHOUT_ULTRA: u8 = 19;
+
+/// Parse `-#`, `--fast[=#]` and `--ultra` out of the flags and return the level and the remaining flags
+fn parse_level(flags: Vec<String>) -> Result<(CompressionLevel, Vec<String>), String> {
+    let mut level = None;
+    let mut fast = None;
+    let mut ultra = false;
+    let mut other_flags = Vec::new();
+    for flag in flags {
+        if flag == "--ultra" {
+            ultra = true;
+        } else if flag == "--fast" || flag.starts_with("--fast=") {
+            let speed = flag.strip_prefix("--fast=").unwrap_or("1");
+            let speed = speed
+                .parse::<u32>()
+                .ok()
+                .and_then(NonZeroU32::new)
+                .ok_or_else(|| format!("Invalid value in {flag}"))?;
+            fast = Some(speed);
+        } else if let Some(number) = flag.strip_prefix('-').and_then(|n| n.parse::<u8>().ok()) {
+            level = Some(number);
+        } else {
+            other_flags.push(flag);
+        }
+    }
+
+    let level = match (level, fast) {
+        (_, Some(speed)) => CompressionLevel::Negative(speed),
+        (None, None) => CompressionLevel::Default,
+        (Some(0), None) => return Err("Level 0 is not supported, use 1 to 22".to_owned()),
+        (Some(level @ 1..=MAX_LEVEL_WITHOUT_ULTRA), None) => zstd_level(level)?,
+        (Some(level @ 20..=22), None) if ultra => zstd_level(level)?,
+        (Some(level @ 20..=22), None) => {
+            return Err(format!("Level {level} needs the --ultra flag"));
+        }
+        (Some(level), None) => return Err(format!("Level {level} is too high, use 1 to 22")),
+    };
+    Ok((level, other_flags))
+}
+
+/// The library level for the zstd level `level`, if the library implements it
+fn zstd_level(level: u8) -> Result<CompressionLevel, String> {
+    if !capabilities().supports_level(i32::from(level)) {
+        return Err(format!(
+            "Level {level} is not implemented by this build of the library"
+        ));
+    }
+    Ok(CompressionLevel::from(ZstdLevel::try_from(level).unwrap()))
+}
+
+/// Split combined short flags like `-dc` into `-d` and `-c`, like zstd does. Levels (`-19`) and the
+/// numbers of `-T#`, `-b#` and `-e#` stay in one flag, `-o` and `-D` still take the next argument.
+fn split_short_flags(args: impl Iterator<Item = String>) -> Vec<String> {
+    let mut split = Vec::new();
+    let mut takes_value = false;
+    for arg in args {
+        if takes_value || !arg.starts_with('-') || arg.starts_with("--") || arg.len() <= 2 {
+            takes_value = !takes_value && matches!(arg.as_str(), "-o" | "-D" | "--exclude");
+            split.push(arg);
+            continue;
+        }
+        let mut rest = &arg[1..];
+        while let Some(flag) = rest.chars().next() {
+            // The flags with a number keep the digits that follow them
+            let start = if matches!(flag, 'T' | 'b' | 'e') {
+                1
+            } else {
+                0
+            };
+            let len = if start == 1 || flag.is_ascii_digit() {
+                rest[start..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .map_or(rest.len(), |digits| start + digits)
+            } else {
+                flag.len_utf8()
+            };
+            split.push(format!("-{}", &rest[..len]));
+            rest = &rest[len..];
+        }
+        takes_value = matches!(split.last().map(String::as_str), Some("-o" | "-D"));
+    }
+    split
+}
+
+fn parse_options(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut file_paths = Vec::new();
+    let mut flags = Vec::new();
+    let mut output = None;
+    let mut dictionary = None;
+    let mut patch_from = None;
+    let mut exclude = Vec::new();
+    let mut args = split_short_flags(args).into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            print!("{USAGE}");
+            std::process::exit(0);
+        } else if arg == "-o" {
+            output = Some(args.next().ok_or("-o needs a file name")?);
+        } else if arg == "-D" {
+            dictionary = Some(args.next().ok_or("-D needs a file name")?);
+        } else if let Some(path) = arg.strip_prefix("--patch-from=") {
+            patch_from = Some(path.to_owned());
+        } else if arg == "--exclude" {
+            exclude.push(args.next().ok_or("--exclude needs a pattern")?);
+        } else if let Some(pattern) = arg.strip_prefix("--exclude=") {
+            exclude.push(pattern.to_owned());
+        } else if arg.starts_with('-') && arg != STDIO {
+            flags.push(arg);
+        } else {
+            file_paths.push(arg);
+        }
+    }
+
+    let (level, flags) = parse_level(flags)?;
+    let mut options = Options {
+        decompress: false,
+        list: false,
+        test: false,
+        recursive: false,
+        exclude,
+        threads: 1,
+        benchmark: None,
+        stdout: false,
+        force: false,
+        remove_source: false,
+        output,
+        dictionary,
+        patch_from,
+        window_log: None,
+        checksum: true,
+        adapt: false,
+        quiet: false,
+        verbose: false,
+        level,
+        file_paths,
+    };
+    let mut bench_first = None;
+    let mut bench_last = None;
+    for flag in flags {
+        if let Some(level) = flag.strip_prefix("-b") {
+            bench_first = Some(parse_bench_level(level)?);
+            continue;
+        }
+        if let Some(level) = flag.strip_prefix("-e") {
+            bench_last = Some(parse_bench_level(level)?);
+            continue;
+        }
+        match flag.as_str() {
+            "-z" | "--compress" => options.decompress = false,
+            "-d" | "--decompress" => options.decompress = true,
+            "-l" | "--list" => options.list = true,
+            "-t" | "--test" => options.test = true,
+            "-r" => options.recursive = true,
+            "-c" | "--stdout" => options.stdout = true,
+            "-f" | "--force" => options.force = true,
+            "-k" | "--keep" => options.remove_source = false,
+            "--rm" => options.remove_source = true,
+            "--check" => options.checksum = true,
+            "--no-check" => options.checksum = false,
+            "--adapt" => options.adapt = true,
+            "-q" | "--quiet" => options.quiet = true,
+            "-v" | "--verbose" => options.verbose = true,
+            "--long" => options.window_log = Some(DEFAULT_LONG_WINDOW_LOG),
+            _ if flag.starts_with("--long=") => {
+                options.window_log = Some(parse_window_log(&flag["--long=".len()..])?);
+            }
+            _ => {
+                let threads = flag
+                    .strip_prefix("-T")
+                    .or_else(|| flag.strip_prefix("--threads="))
+                    .ok_or_else(|| format!("Unknown flag {flag}"))?;
+                options.threads = parse_threads(threads)?;
+            }
+        }
+    }
+
+    match (bench_first, bench_last) {
+        (Some(first), last) => {
+            let last = last.unwrap_or(first);
+            if last < first {
+                return Err(format!("-e{last} must not be lower than -b{first}"));
+            }
+            options.benchmark = Some((first, last));
+        }
+        (None, Some(_)) => return Err("-e# can only be used together with -b#".to_owned()),
+        (None, None) => {}
+    }
+
+    // Without any files, read from stdin and write to stdout
+    if options.file_paths.is_empty() {
+        options.file_paths.push(STDIO.to_owned());
+    }
+    if options.test {
+        options.decompress = true;
+    }
+    if options.recursive {
+        let mut file_paths = Vec::new();
+        for path in &options.file_paths {
+            collect_files(&options, path, &mut file_paths)?;
+        }
+        options.file_paths = file_paths;
+    }
+    if options.dictionary.is_some() && options.patch_from.is_some() {
+        return Err("-D and --patch-from can not be used together".to_owned());
+    }
+    if options.list && options.file_paths == [STDIO] {
+        return Err("--list does not support reading from stdin".to_owned());
+    }
+    if options.output.is_some() && options.file_paths.len() > 1 {
+        return Err("-o can only be used with a single input file".to_owned());
+    }
+    Ok(options)
+}
+
+/// The window log used by `--long` without a value, like the reference implementation does
+const DEFAULT_LONG_WINDOW_LOG: u8 = 27;
+
+/// Parse the value of `--long=#`
+fn parse_window_log(window_log: &str) -> Result<u8, String> {
+    match window_log.parse::<u8>() {
+        Ok(log) if (MIN_WINDOW_LOG..=MAX_WINDOW_LOG).contains(&log) => Ok(log),
+        _ => Err(format!(
+            "Invalid window log {window_log}, use {MIN_WINDOW_LOG} to {MAX_WINDOW_LOG}"
+        )),
+    }
+}
+
+/// Parse the value of `-T#`, where 0 means one thread per physical core
+fn parse_threads(threads: &str) -> Result<usize, String> {
+    match threads.parse::<usize>() {
+        // Like zstd, one thread per physical core, but not more than the process may run on
+        Ok(0) => {
+            let logical = std::thread::available_parallelism().map_or(1, |n| n.get());
+            Ok(physical_cores().map_or(logical, |cores| cores.min(logical)))
+        }
+        Ok(threads) => Ok(threads),
+        Err(_) => Err(format!("Invalid number of threads {threads}")),
+    }
+}
+
+/// The number of physical cores, from the CPU topology that Linux lists in sysfs. `None` if it
+/// is not available, e.g. on other systems.
+fn physical_cores() -> Option<usize> {
+    let mut cores = std::collections::BTreeSet::new();
+    for entry in std::fs::read_dir("/sys/devices/system/cpu").ok()? {
+        let path = entry.ok()?.path();
+        let is_cpu = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("cpu"))
+            .is_some_and(|number| number.parse::<usize>().is_ok());
+        // Offline CPUs have no topology
+        let read = |file: &str| std::fs::read_to_string(path.join("topology").join(file)).ok();
+        if let (true, Some(package), Some(core)) =
+            (is_cpu, read("physical_package_id"), read("core_id"))
+        {
+            cores.insert((package.trim().to_owned(), core.trim().to_owned()));
+        }
+    }
+    (!cores.is_empty()).then_some(cores.len())
+}
+
+/// Add `path` to `file_paths`, or all files in it if it is a directory.
+///
+/// Directory entries are visited in sorted order, so batch jobs process files in the same order every time.
+fn collect_files(
+    options: &Options,
+    path: &str,
+    file_paths: &mut Vec<String>,
+) -> Result<(), String> {
+    if path == STDIO {
+        file_paths.push(path.to_owned());
+        return Ok(());
+    }
+    // Symlinks are not followed, they could form loops
+    let metadata = std::fs::symlink_metadata(path).map_err(|e| format!("{path}: {e}"))?;
+    if !metadata.is_dir() {
+        file_paths.push(path.to_owned());
+        return Ok(());
     }
+
+    let mut entries = std::fs::read_dir(path)
+        .and_then(|entries| entries.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| format!("{path}: {e}"))?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if options
+            .exclude
+            .iter()
+            .any(|pattern| matches_pattern(pattern, &name))
+        {
+            continue;
+        }
+        let entry_path = entry.path();
+        let entry_path = entry_path
+            .to_str()
+            .ok_or_else(|| format!("{}: not a valid UTF-8 path", entry_path.display()))?;
+        let file_type = entry
+            .file_type()
+            .map_err(|e| format!("{entry_path}: {e}"))?;
+        if file_type.is_dir() {
+            collect_files(options, entry_path, file_paths)?;
+        } else if file_type.is_file() {
+            // Only pick up the files that can actually be processed
+            let compressed = entry_path.ends_with(".zst") || entry_path.ends_with(".tzst");
+            if compressed == options.decompress {
+                file_paths.push(entry_path.to_owned());
+            }
+        }
+    }
+    Ok(())
 }
 
-fn do_something(data: &[u8], s: &mut StateTracker) {
+/// Match `name` against a shell style pattern, where `*` matches any number of characters and `?` a single one
+fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Position after the last `*` in the pattern and the name position it was tried at
+    let mut backtrack = None;
+    let (mut p, mut n) = (0, 0);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    };
+
+    if options.list {
+        println!(
+            "{:>6} {:>5} {:>12} {:>12} {:>7} {:>5} {:>10}  Filename",
+            "Frames", "Skips", "Compressed", "Uncompressed", "Ratio", "Check", "Dict"
+        );
+    }
+
+    // The frames of a single file are (de)compressed on one thread, so multiple threads
+    // only help when there are multiple files. Output to stdout has to stay in order.
+    let to_stdout = options.stdout || options.output.as_deref() == Some(STDIO);
+    let threads = if options.list || options.benchmark.is_some() || to_stdout {
+        1
+    } else {
+        options.threads.clamp(1, options.file_paths.len().max(1))
+    };
+    if options.threads > 1 && options.file_paths.len() == 1 && !options.quiet {
+        eprintln!("Multithreaded (de)compression of a single file is not implemented yet, using one thread");
+    }
+
+    let next_file = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                if !process_files(&options, &next_file) {
+                    failed.store(true, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    if failed.into_inner() {
+        std::process::exit(1);
+    }
+}
+
+/// Process files from the list until all have been taken by this or other threads.
+/// Returns false if any file failed.
+fn process_files(options: &Options, next_file: &AtomicUsize) -> bool {
+    let mut frame_dec = ruzstd::FrameDecoder::new();
+    if let Some(window_log) = options.window_log {
+        frame_dec.set_max_window_size(1 << window_log);
+    }
+    // Like zstd, decode the frames of every level, including the ultra levels
+    frame_dec.set_ultra(true);
+    if let Some(path) = &options.dictionary {
+        if let Err(err) = load_dictionary(path, &mut frame_dec) {
+            eprintln!("{err}");
+            return false;
+        }
+    }
+    if let Some(path) = &options.patch_from {
+        if let Err(err) = load_patch_reference(path, &mut frame_dec) {
+            eprintln!("{err}");
+            return false;
+        }
+    }
+
+    let mut ok = true;
+    while let Some(path) = options
+        .file_paths
+        .get(next_file.fetch_add(1, Ordering::Relaxed))
+    {
+        let result = if options.list {
+            list_file(path)
+        } else if let Some((first, last)) = options.benchmark {
+            benchmark_file(path, first, last, &mut frame_dec)
+        } else if options.test {
+            test_file(options, path, &mut frame_dec)
+        } else {
+            process_file(options, path, &mut frame_dec)
+        };
+        if let Err(err) = result {
+            eprintln!("{err}");
+            ok = false;
+        }
+    }
+    ok
+}
+
+fn do_something(data: &[u8], output: &mut impl Write, s: &mut StateTracker) -> Result<(), String> {
     //Do something. Like writing it to a file or to stdout...
-    std::io::stdout().write_all(data).unwrap();
+    output.write_all(data).map_err(|e| e.to_string())?;
     s.bytes_used += data.len() as u64;
 
-    let percentage = (s.file_pos * 100) / s.file_size;
-    if percentage as i8 != s.old_percentage {
-        eprint!("\r");
-        eprint!("{} % done", percentage);
-        s.old_percentage = percentage as i8;
+    if let Some(file_size) = s.file_size.filter(|_| s.progress) {
+        let percentage = (s.file_pos * 100) / file_size.max(1);
+        if percentage as i8 != s.old_percentage {
+            eprint!("\r");
+            eprint!("{} % done", percentage);
+            s.old_percentage = percentage as i8;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_options, parse_threads, split_short_flags, CompressionLevel};
+
+    fn args<'a>(args: &'a [&str]) -> impl Iterator<Item = String> + 'a {
+        args.iter().map(|arg| arg.to_string())
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let options = parse_options(args(&["-dc", "file.zst"])).unwrap();
+        assert!(options.decompress && options.stdout);
+        assert_eq!(options.file_paths, ["file.zst"]);
+
+        let options = parse_options(args(&["--rm", "-kf", "file"])).unwrap();
+        assert!(options.force && !options.remove_source);
+
+        let options = parse_options(args(&["-dT3", "-co", "out", "file.zst"])).unwrap();
+        assert!(options.decompress && options.stdout);
+        assert_eq!(options.threads, 3);
+        assert_eq!(options.output.as_deref(), Some("out"));
+
+        let options = parse_options(args(&["-c", "file"])).unwrap();
+        assert_eq!(options.level, CompressionLevel::Default);
+
+        let options = parse_options(args(&["-qd", "file.zst"])).unwrap();
+        assert!(options.quiet && options.decompress && !options.verbose);
+        let options = parse_options(args(&["-tv", "file.zst"])).unwrap();
+        assert!(options.verbose && options.test && !options.quiet);
+
+        let options = parse_options(args(&["-19c", "file"])).unwrap();
+        assert!(options.stdout);
+        assert_eq!(options.level.to_string(), "19");
+        assert!(parse_options(args(&["-20", "file"])).is_err());
+    }
+
+    #[test]
+    fn split_flags() {
+        assert_eq!(
+            split_short_flags(args(&["-1dT4", "-fo", "-out", "-b1e3", "-", "--rm"])),
+            ["-1", "-d", "-T4", "-f", "-o", "-out", "-b1", "-e3", "-", "--rm"]
+        );
+        assert_eq!(
+            split_short_flags(args(&["-D", "-dict", "-19"])),
+            ["-D", "-dict", "-19"]
+        );
+    }
+
+    #[test]
+    fn threads() {
+        assert_eq!(parse_threads("4"), Ok(4));
+        assert!(parse_threads("four").is_err());
+        let all = parse_threads("0").unwrap();
+        let logical = std::thread::available_parallelism().map_or(1, |n| n.get());
+        assert!((1..=logical).contains(&all), "{} {}", all, logical);
     }
 }
@@ -1,3 +1,5 @@
+// The lints of clippy.toml are only meant for the library, see the Readme
+#![allow(clippy::disallowed_macros, clippy::disallowed_methods)]
 extern crate ruzstd;
 use std::fs::File;
 use std::io::{Read, Write};
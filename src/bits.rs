@@ -0,0 +1,48 @@
+//! How the bit streams of the format are laid out in bytes.
+//!
+//! Zstd stores bit streams little endian: the first bit of a stream is the lowest bit of its first
+//! byte, and a value that does not fit into the rest of a byte continues in the lowest bits of the
+//! next one. The bit readers and the [BitWriter](crate::encoding::bit_writer::BitWriter) collect
+//! bits in a `u64` and only convert between it and bytes with the functions here, so the streams
+//! are the same on big endian targets like s390x or powerpc.
+
+use crate::fallible::{AllocError, TryVec};
+use alloc::vec::Vec;
+
+/// The value of up to 8 `bytes`, the first byte in the lowest bits.
+#[inline(always)]
+pub(crate) fn load_le(bytes: &[u8]) -> u64 {
+    let mut buf = [0; 8];
+    buf[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(buf)
+}
+
+/// Append the lowest `len` bytes of `value` to `output`, the lowest byte first.
+#[inline(always)]
+pub(crate) fn store_le(value: u64, len: usize, output: &mut Vec<u8>) -> Result<(), AllocError> {
+    output.try_extend_from_slice(&value.to_le_bytes()[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_le, store_le};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn lowest_byte_first() {
+        assert_eq!(load_le(&[0x01, 0x02, 0x03]), 0x03_02_01);
+        assert_eq!(
+            load_le(&[0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01]),
+            0x01_23_45_67_89_AB_CD_EF
+        );
+        assert_eq!(load_le(&[]), 0);
+
+        let mut output = Vec::new();
+        store_le(0x01_23_45_67_89_AB_CD_EF, 3, &mut output).unwrap();
+        store_le(0x01_23_45_67_89_AB_CD_EF, 8, &mut output).unwrap();
+        assert_eq!(
+            output,
+            [0xEF, 0xCD, 0xAB, 0xEF, 0xCD, 0xAB, 0x89, 0x67, 0x45, 0x23, 0x01]
+        );
+    }
+}
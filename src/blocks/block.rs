@@ -1,5 +1,10 @@
 //! Block header definitions.
 
+use crate::decoding::block_decoder::{
+    BlockHeaderReadError, BlockSizeError, ABSOLUTE_MAXIMUM_BLOCK_SIZE,
+};
+use crate::io::Read;
+
 /// There are 4 different kinds of blocks, and the type of block influences the meaning of `Block_Size`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BlockType {
@@ -27,6 +32,20 @@ impl core::fmt::Display for BlockType {
 
 /// A representation of a single block header. As well as containing a frame header,
 /// each Zstandard frame contains one or more blocks.
+///
+/// [BlockHeader::parse] and [BlockHeader::serialize] read and write the 3 bytes of a header, so
+/// tools that walk the blocks of a frame do not need to know their bit layout.
+///
+/// ```
+/// use ruzstd::blocks::block::{BlockHeader, BlockType};
+///
+/// let header = BlockHeader::new(true, BlockType::RLE, 1000);
+/// let serialized = header.serialize().unwrap();
+/// assert_eq!(BlockHeader::parse(&serialized[..]).unwrap(), header);
+/// // The RLE block is followed by the single byte that is repeated
+/// assert_eq!(header.content_size, 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BlockHeader {
     /// Whether this block is the last block in the frame.
     /// It may be followed by an optional `Content_Checksum` if it is.
@@ -40,3 +59,127 @@ pub struct BlockHeader {
     /// this value will be 1.
     pub content_size: u32,
 }
+
+impl BlockHeader {
+    /// The number of bytes a block header takes up.
+    pub const SIZE: usize = 3;
+
+    /// The header of a block of the given type, whose `Block_Size` field is `block_size`: the size
+    /// of the content for [BlockType::Raw] and [BlockType::RLE] blocks, the size of the compressed
+    /// data for [BlockType::Compressed] blocks.
+    pub fn new(last_block: bool, block_type: BlockType, block_size: u32) -> Self {
+        let (decompressed_size, content_size) = match block_type {
+            BlockType::Raw => (block_size, block_size),
+            BlockType::RLE => (block_size, 1),
+            BlockType::Compressed => (0, block_size),
+            BlockType::Reserved => (0, 0),
+        };
+        BlockHeader {
+            last_block,
+            block_type,
+            decompressed_size,
+            content_size,
+        }
+    }
+
+    /// Read a block header from `source`.
+    ///
+    /// Fails for reserved block types and block sizes above the 128 KiB that the format allows.
+    pub fn parse(mut source: impl Read) -> Result<Self, BlockHeaderReadError> {
+        let mut buf = [0; Self::SIZE];
+        source.read_exact(&mut buf)?;
+
+        let block_type = match (buf[0] >> 1) & 0x3 {
+            0 => BlockType::Raw,
+            1 => BlockType::RLE,
+            2 => BlockType::Compressed,
+            _ => return Err(BlockHeaderReadError::FoundReservedBlock),
+        };
+        //push out type and last_block flags. Retain 5 bit
+        let block_size =
+            u32::from(buf[0] >> 3) | (u32::from(buf[1]) << 5) | (u32::from(buf[2]) << 13);
+        if block_size > ABSOLUTE_MAXIMUM_BLOCK_SIZE {
+            return Err(BlockSizeError::BlockSizeTooLarge { size: block_size }.into());
+        }
+        Ok(Self::new(buf[0] & 0x1 == 1, block_type, block_size))
+    }
+
+    /// The value of the `Block_Size` field of the header.
+    pub fn block_size(&self) -> u32 {
+        match self.block_type {
+            BlockType::RLE => self.decompressed_size,
+            _ => self.content_size,
+        }
+    }
+
+    /// Return the encoded binary representation of this header.
+    ///
+    /// `None` for [BlockType::Reserved], and if the block size does not fit into the 21 bits of the
+    /// `Block_Size` field.
+    pub fn serialize(&self) -> Option<[u8; 3]> {
+        vprintln!("Serializing block with the header: {self:?}");
+        let encoded_block_type = match self.block_type {
+            BlockType::Raw => 0,
+            BlockType::RLE => 1,
+            BlockType::Compressed => 2,
+            BlockType::Reserved => return None,
+        };
+        let block_size = self.block_size();
+        if block_size >= 1 << 21 {
+            return None;
+        }
+        let mut block_header = block_size << 3;
+        block_header |= encoded_block_type << 1;
+        block_header |= self.last_block as u32;
+        let [b0, b1, b2, _] = block_header.to_le_bytes();
+        Some([b0, b1, b2])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BlockHeader, BlockType};
+    use crate::decoding::block_decoder::BlockHeaderReadError;
+
+    #[test]
+    fn roundtrip() {
+        for block_type in [BlockType::Raw, BlockType::RLE, BlockType::Compressed] {
+            for block_size in [0, 1, 69, 1 << 16, 128 * 1024] {
+                for last_block in [false, true] {
+                    let header = BlockHeader::new(last_block, block_type, block_size);
+                    let parsed = BlockHeader::parse(&header.serialize().unwrap()[..]).unwrap();
+                    assert_eq!(parsed, header);
+                    assert_eq!(parsed.block_size(), block_size);
+                }
+            }
+        }
+        assert_eq!(
+            BlockHeader::new(true, BlockType::Compressed, 69).serialize(),
+            Some([0x2D, 0x02, 0x00])
+        );
+        assert_eq!(
+            BlockHeader::new(true, BlockType::Reserved, 69).serialize(),
+            None
+        );
+        assert_eq!(
+            BlockHeader::new(true, BlockType::Raw, 1 << 21).serialize(),
+            None
+        );
+    }
+
+    #[test]
+    fn invalid_headers() {
+        assert!(matches!(
+            BlockHeader::parse(&[0b110, 0, 0][..]),
+            Err(BlockHeaderReadError::FoundReservedBlock)
+        ));
+        assert!(matches!(
+            BlockHeader::parse(&[0, 0, 0x20][..]),
+            Err(BlockHeaderReadError::BlockSizeError(_))
+        ));
+        assert!(matches!(
+            BlockHeader::parse(&[0, 0][..]),
+            Err(BlockHeaderReadError::ReadError(_))
+        ));
+    }
+}
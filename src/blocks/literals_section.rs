@@ -7,16 +7,16 @@ use super::super::decoding::bit_reader::{BitReader, GetBitsError};
 /// This is the first of those two sections. A literal is just any arbitrary data, and it is copied by the sequences section
 pub struct LiteralsSection {
     /// - If this block is of type [LiteralsSectionType::Raw], then the data is `regenerated_bytes`
-    ///     bytes long, and it contains the raw literals data to be used during the second section,
-    ///     the sequences section.
+    ///   bytes long, and it contains the raw literals data to be used during the second section,
+    ///   the sequences section.
     /// - If this block is of type [LiteralsSectionType::RLE],
-    ///     then the literal consists of a single byte repeated `regenerated_size` times.
+    ///   then the literal consists of a single byte repeated `regenerated_size` times.
     /// - For types [LiteralsSectionType::Compressed] or [LiteralsSectionType::Treeless],
-    ///     then this is the size of the decompressed data.
+    ///   then this is the size of the decompressed data.
     pub regenerated_size: u32,
     /// - For types [LiteralsSectionType::Raw] and [LiteralsSectionType::RLE], this value is not present.
     /// - For types [LiteralsSectionType::Compressed] and [LiteralsSectionType::Treeless], this value will
-    ///     be set to the size of the compressed data.
+    ///   be set to the size of the compressed data.
     pub compressed_size: Option<u32>,
     /// This value will be either 1 stream or 4 streams if the literal is of type
     /// [LiteralsSectionType::Compressed] or [LiteralsSectionType::Treeless], and it
@@ -27,6 +27,7 @@ pub struct LiteralsSection {
 }
 
 /// The way which a literal section is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LiteralsSectionType {
     /// Literals are stored uncompressed.
     Raw,
@@ -133,14 +134,11 @@ impl LiteralsSection {
                         // regenerated_size uses 12 bits
                         Ok(2)
                     }
-                    3 => {
-                        // size_format uses 2 bit
+                    _ => {
+                        // size_format is 3, uses 2 bit
                         // regenerated_size uses 20 bits
                         Ok(3)
                     }
-                    _ => panic!(
-                        "This is a bug in the program. There should only be values between 0..3"
-                    ),
                 }
             }
             LiteralsSectionType::Compressed | LiteralsSectionType::Treeless => {
@@ -154,14 +152,11 @@ impl LiteralsSection {
                         // both regenerated and compressed sizes use 14 bit
                         Ok(4)
                     }
-                    3 => {
+                    _ => {
+                        // size_format is 3
                         // both regenerated and compressed sizes use 18 bit
                         Ok(5)
                     }
-
-                    _ => panic!(
-                        "This is a bug in the program. There should only be values between 0..3"
-                    ),
                 }
             }
         }
@@ -198,31 +193,18 @@ impl LiteralsSection {
                         self.regenerated_size = (u32::from(raw[0]) >> 4) + (u32::from(raw[1]) << 4);
                         Ok(2)
                     }
-                    3 => {
-                        // size_format uses 2 bit
+                    _ => {
+                        // size_format is 3, uses 2 bit
                         // regenerated_size uses 20 bits
                         self.regenerated_size = (u32::from(raw[0]) >> 4)
                             + (u32::from(raw[1]) << 4)
                             + (u32::from(raw[2]) << 12);
                         Ok(3)
                     }
-                    _ => panic!(
-                        "This is a bug in the program. There should only be values between 0..3"
-                    ),
                 }
             }
             LiteralsSectionType::Compressed | LiteralsSectionType::Treeless => {
-                match size_format {
-                    0 => {
-                        self.num_streams = Some(1);
-                    }
-                    1..=3 => {
-                        self.num_streams = Some(4);
-                    }
-                    _ => panic!(
-                        "This is a bug in the program. There should only be values between 0..3"
-                    ),
-                };
+                self.num_streams = Some(if size_format == 0 { 1 } else { 4 });
 
                 match size_format {
                     0 | 1 => {
@@ -251,7 +233,8 @@ impl LiteralsSection {
                             Some((u32::from(raw[2]) >> 2) + (u32::from(raw[3]) << 6));
                         Ok(4)
                     }
-                    3 => {
+                    _ => {
+                        // size_format is 3
                         // both regenerated and compressed sizes use 18 bit
 
                         // 4 from first, full second, six from third byte
@@ -267,10 +250,6 @@ impl LiteralsSection {
                         );
                         Ok(5)
                     }
-
-                    _ => panic!(
-                        "This is a bug in the program. There should only be values between 0..3"
-                    ),
                 }
             }
         }
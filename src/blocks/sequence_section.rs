@@ -40,10 +40,143 @@ impl core::fmt::Display for Sequence {
     }
 }
 
+/// The three most recently used offsets, which sequences can refer to with the offset values 1-3.
+///
+/// The history starts out as `[1, 4, 8]` at the beginning of each frame (or with the offsets
+/// stored in the dictionary) and is carried over from one compressed block to the next.
+/// The encoder and decoder both use this type, so they always agree on how an offset value is
+/// resolved and how the history is updated.
+///
+/// <https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#repeat-offsets>
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffsetHistory([u32; 3]);
+
+impl OffsetHistory {
+    /// The history every frame without a dictionary starts with.
+    pub const INITIAL: [u32; 3] = [1, 4, 8];
+
+    /// Create a history with the initial values defined by the format.
+    pub const fn new() -> Self {
+        Self(Self::INITIAL)
+    }
+
+    /// Create a history from previously stored offsets, e.g. the ones found in a dictionary.
+    pub const fn from_offsets(offsets: [u32; 3]) -> Self {
+        Self(offsets)
+    }
+
+    /// The repeat offsets, most recently used first.
+    pub const fn offsets(&self) -> [u32; 3] {
+        self.0
+    }
+
+    /// Reset the history to the initial values, as it needs to be at the start of a new frame.
+    pub fn reset(&mut self) {
+        self.0 = Self::INITIAL;
+    }
+
+    /// Resolve the offset value of a sequence to the actual offset and update the history.
+    ///
+    /// Returns 0 if the sequence referred to the most recent offset minus one and that
+    /// offset was 1, which means the data is corrupted.
+    pub fn decode(&mut self, offset_value: u32, lit_len: u32) -> u32 {
+        let hist = &mut self.0;
+        let actual_offset = if lit_len > 0 {
+            match offset_value {
+                1..=3 => hist[offset_value as usize - 1],
+                _ => {
+                    //new offset
+                    offset_value - 3
+                }
+            }
+        } else {
+            match offset_value {
+                1..=2 => hist[offset_value as usize],
+                3 => hist[0] - 1,
+                _ => {
+                    //new offset
+                    offset_value - 3
+                }
+            }
+        };
+
+        //update history
+        if lit_len > 0 {
+            match offset_value {
+                1 => {
+                    //nothing
+                }
+                2 => {
+                    hist[1] = hist[0];
+                    hist[0] = actual_offset;
+                }
+                _ => {
+                    hist[2] = hist[1];
+                    hist[1] = hist[0];
+                    hist[0] = actual_offset;
+                }
+            }
+        } else {
+            match offset_value {
+                1 => {
+                    hist[1] = hist[0];
+                    hist[0] = actual_offset;
+                }
+                _ => {
+                    hist[2] = hist[1];
+                    hist[1] = hist[0];
+                    hist[0] = actual_offset;
+                }
+            }
+        }
+
+        actual_offset
+    }
+
+    /// Find the offset value a sequence with the (non zero) `offset` has to use and update the history.
+    ///
+    /// This prefers the repeat codes 1-3 whenever the offset is present in the history.
+    #[allow(clippy::disallowed_macros)]
+    pub fn encode(&mut self, offset: u32, lit_len: u32) -> u32 {
+        debug_assert!(offset > 0, "Offsets must not be zero");
+        let hist = self.0;
+        // Without literals the repeat codes are shifted by one
+        let offset_value = if lit_len > 0 {
+            if offset == hist[0] {
+                1
+            } else if offset == hist[1] {
+                2
+            } else if offset == hist[2] {
+                3
+            } else {
+                offset + 3
+            }
+        } else if offset == hist[1] {
+            1
+        } else if offset == hist[2] {
+            2
+        } else if offset == hist[0].wrapping_sub(1) {
+            3
+        } else {
+            offset + 3
+        };
+        let decoded = self.decode(offset_value, lit_len);
+        debug_assert_eq!(decoded, offset);
+        offset_value
+    }
+}
+
+impl Default for OffsetHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// This byte defines the compression mode of each symbol type
 #[derive(Copy, Clone)]
 pub struct CompressionModes(u8);
 /// The compression mode used for symbol compression
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ModeType {
     /// A predefined FSE distribution table is used, and no distribution table
     /// will be present.
@@ -60,14 +193,13 @@ pub enum ModeType {
 }
 
 impl CompressionModes {
-    /// Deserialize a two bit mode value into a [ModeType]
+    /// Deserialize a two bit mode value into a [ModeType]. Higher bits of `m` are ignored.
     pub fn decode_mode(m: u8) -> ModeType {
-        match m {
+        match m & 0b11 {
             0 => ModeType::Predefined,
             1 => ModeType::RLE,
             2 => ModeType::FSECompressed,
-            3 => ModeType::Repeat,
-            _ => panic!("This can never happen"),
+            _ => ModeType::Repeat,
         }
     }
     /// Read the compression mode of the literal lengths field.
@@ -180,3 +312,60 @@ impl SequencesHeader {
         Ok(bytes_read)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::OffsetHistory;
+
+    #[test]
+    fn offset_history_decode() {
+        let mut hist = OffsetHistory::from_offsets([10, 20, 30]);
+        // with literals the repeat codes map to the history directly
+        assert_eq!(hist.decode(1, 5), 10);
+        assert_eq!(hist.offsets(), [10, 20, 30]);
+        assert_eq!(hist.decode(2, 5), 20);
+        assert_eq!(hist.offsets(), [20, 10, 30]);
+        assert_eq!(hist.decode(3, 5), 30);
+        assert_eq!(hist.offsets(), [30, 20, 10]);
+        // without literals they are shifted by one
+        assert_eq!(hist.decode(1, 0), 20);
+        assert_eq!(hist.offsets(), [20, 30, 10]);
+        assert_eq!(hist.decode(2, 0), 10);
+        assert_eq!(hist.offsets(), [10, 20, 30]);
+        assert_eq!(hist.decode(3, 0), 9);
+        assert_eq!(hist.offsets(), [9, 10, 20]);
+        // new offsets
+        assert_eq!(hist.decode(103, 0), 100);
+        assert_eq!(hist.offsets(), [100, 9, 10]);
+
+        hist.reset();
+        assert_eq!(hist, OffsetHistory::new());
+        assert_eq!(hist.offsets(), [1, 4, 8]);
+    }
+
+    #[test]
+    fn offset_history_encode_matches_decode() {
+        let mut encoder_hist = OffsetHistory::new();
+        let mut decoder_hist = OffsetHistory::new();
+        let mut state = 0x1234_5678_u32;
+        let mut used_repeat_codes = 0;
+        for _ in 0..10_000 {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            // a small set of offsets, so the repeat codes get used often
+            let offset = 1 + state % 12;
+            let lit_len = (state >> 8) % 3;
+
+            let offset_value = encoder_hist.encode(offset, lit_len);
+            if offset_value <= 3 {
+                used_repeat_codes += 1;
+            } else {
+                assert_eq!(offset_value, offset + 3);
+            }
+            assert_eq!(decoder_hist.decode(offset_value, lit_len), offset);
+            assert_eq!(encoder_hist, decoder_hist);
+        }
+        assert!(used_repeat_codes > 1000);
+    }
+}
@@ -0,0 +1,112 @@
+//! What this build of the crate can do, see [capabilities].
+//!
+//! Applications that exchange frames with peers running other zstd implementations can use the
+//! report to pick settings both sides understand, e.g. a compression level this crate implements
+//! or a window size the decoder accepts, instead of finding out from an error.
+
+use crate::frame_decoder::DEFAULT_MAX_WINDOW_SIZE;
+
+/// The features of this implementation, see [capabilities].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Capabilities {
+    /// The version of this crate.
+    pub version: &'static str,
+    /// Whether frames can be compressed, which needs the `encoder` feature.
+    pub encoder: bool,
+    /// Whether checksums of the content are verified by the decoder and can be written by the
+    /// encoder, which needs the `hash` feature. Without it frames with checksums are still decoded.
+    pub checksums: bool,
+    /// Whether frames that were compressed with a dictionary can be decoded, and compressed if
+    /// there is an encoder.
+    pub dictionaries: bool,
+    /// Whether the seek tables of the seekable format of the zstd contrib directory are read, to
+    /// decompress byte ranges with [seekable](crate::seekable). Writing them is not supported yet.
+    pub seekable: bool,
+    /// The largest window the decoder accepts unless it is raised with
+    /// [FrameDecoder::set_max_window_size](crate::frame_decoder::FrameDecoder::set_max_window_size).
+    pub default_max_decoder_window_size: u64,
+    /// The largest window log the encoder can be configured with, `None` without an encoder.
+    pub max_encoder_window_log: Option<u8>,
+}
+
+impl Capabilities {
+    /// Whether the encoder compresses at the zstd level `level`, with
+    /// [EncodeOptions::ultra](crate::encoding::EncodeOptions::ultra) for levels 20 to 22.
+    /// Always `false` without an encoder.
+    pub fn supports_level(&self, level: i32) -> bool {
+        #[cfg(feature = "encoder")]
+        {
+            use crate::encoding::{
+                level_implemented, CompressionLevel, ZstdLevel, MAX_ACCELERATION,
+            };
+            use core::convert::TryFrom;
+            use core::num::NonZeroU32;
+
+            let level = if level < 0 {
+                NonZeroU32::new(level.unsigned_abs())
+                    .filter(|n| n.get() <= MAX_ACCELERATION)
+                    .map(CompressionLevel::Negative)
+            } else {
+                ZstdLevel::try_from(level).ok().map(CompressionLevel::from)
+            };
+            level.is_some_and(|level| level_implemented(level, true))
+        }
+        #[cfg(not(feature = "encoder"))]
+        {
+            let _ = level;
+            false
+        }
+    }
+}
+
+/// Report what this build of the crate supports, which depends on the enabled features.
+///
+/// ```
+/// let capabilities = ruzstd::capabilities::capabilities();
+/// assert!(capabilities.dictionaries);
+/// if capabilities.encoder {
+///     assert!(capabilities.supports_level(1));
+///     assert!(!capabilities.supports_level(23));
+/// }
+/// ```
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        encoder: cfg!(feature = "encoder"),
+        checksums: cfg!(feature = "hash"),
+        dictionaries: true,
+        seekable: true,
+        default_max_decoder_window_size: DEFAULT_MAX_WINDOW_SIZE,
+        #[cfg(feature = "encoder")]
+        max_encoder_window_log: Some(crate::encoding::MAX_WINDOW_LOG),
+        #[cfg(not(feature = "encoder"))]
+        max_encoder_window_log: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::capabilities;
+
+    #[test]
+    fn report() {
+        let capabilities = capabilities();
+        assert_eq!(capabilities.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(capabilities.checksums, cfg!(feature = "hash"));
+        assert!(capabilities.seekable);
+
+        #[cfg(feature = "encoder")]
+        {
+            for level in [-131_072, -5, -1, 0, 1, 2, 3, 19, 20, 21, 22] {
+                assert!(capabilities.supports_level(level), "{}", level);
+            }
+            for level in [-131_073, 23] {
+                assert!(!capabilities.supports_level(level), "{}", level);
+            }
+            assert_eq!(capabilities.max_encoder_window_log, Some(41));
+        }
+        #[cfg(not(feature = "encoder"))]
+        assert!(!capabilities.supports_level(1));
+    }
+}
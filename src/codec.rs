@@ -0,0 +1,369 @@
+//! Sans-IO encoder and decoder in the shape of the codecs of the `async-compression` crate.
+//!
+//! The [Encode] and [Decode] traits have the methods `async-compression` drives its codecs with:
+//! each call gets the input and output as [PartialBuffer]s, uses as much of them as it can and
+//! advances them past what it used. Nothing is read or written by the codecs themselves, so an
+//! async reader or writer (or any other event loop) can move the data and call them whenever
+//! there is input or room for output. A backend for `async-compression` forwards its codec traits
+//! to these.
+//!
+//! ```
+//! # #[cfg(feature = "encoder")] {
+//! use ruzstd::codec::{Decode, Decoder, Encode, Encoder, PartialBuffer};
+//! use ruzstd::encoding::CompressionLevel;
+//!
+//! let mut encoder = Encoder::new(CompressionLevel::Fastest).unwrap();
+//! let mut input = PartialBuffer::new(&b"Hello, World!"[..]);
+//! let mut output = PartialBuffer::new([0; 100]);
+//! encoder.encode(&mut input, &mut output).unwrap();
+//! assert!(input.unwritten().is_empty());
+//! while !encoder.finish(&mut output).unwrap() {}
+//!
+//! let mut decoder = Decoder::new();
+//! let mut input = PartialBuffer::new(output.written());
+//! let mut output = PartialBuffer::new([0; 100]);
+//! assert!(decoder.decode(&mut input, &mut output).unwrap());
+//! assert_eq!(output.written(), b"Hello, World!");
+//! # }
+//! ```
+
+use crate::io::{Error, ErrorKind};
+use crate::raw::{Decompress, FlushDecompress, Status};
+use crate::streaming_decoder::to_io_error;
+#[cfg(feature = "encoder")]
+use crate::{
+    encoding::{CompressionLevel, EncodeError, EncodeOptions},
+    raw::{Compress, FlushCompress},
+};
+
+/// A buffer of which the start was used, i.e. read from or written to, and the rest was not.
+#[derive(Debug, Default)]
+pub struct PartialBuffer<B> {
+    buffer: B,
+    index: usize,
+}
+
+impl<B: AsRef<[u8]>> PartialBuffer<B> {
+    /// Wrap `buffer`, of which nothing was used yet.
+    pub fn new(buffer: B) -> Self {
+        Self { buffer, index: 0 }
+    }
+
+    /// The part of the buffer that was used.
+    pub fn written(&self) -> &[u8] {
+        &self.buffer.as_ref()[..self.index]
+    }
+
+    /// The part of the buffer that was not used yet.
+    pub fn unwritten(&self) -> &[u8] {
+        &self.buffer.as_ref()[self.index..]
+    }
+
+    /// Mark the next `amount` bytes as used, but not more than the buffer holds.
+    pub fn advance(&mut self, amount: usize) {
+        self.index = usize::min(
+            self.index.saturating_add(amount),
+            self.buffer.as_ref().len(),
+        );
+    }
+
+    /// The underlying buffer.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.buffer
+    }
+
+    /// Unwrap the underlying buffer.
+    pub fn into_inner(self) -> B {
+        self.buffer
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> PartialBuffer<B> {
+    /// The part of the buffer that was not used yet, to write into.
+    pub fn unwritten_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer.as_mut()[self.index..]
+    }
+}
+
+/// A sans-IO compressor, driven like the encoders of `async-compression`.
+pub trait Encode {
+    /// Compress from `input` into `output`, as much as fits.
+    fn encode(
+        &mut self,
+        input: &mut PartialBuffer<impl AsRef<[u8]>>,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<(), Error>;
+
+    /// Write everything that was passed to [Encode::encode] so far into `output`, in a form the
+    /// decoder can decompress. Returns whether that is done, otherwise it has to be called again
+    /// with more room.
+    fn flush(
+        &mut self,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error>;
+
+    /// End the stream. Returns whether all of it was written into `output`, otherwise it has to
+    /// be called again with more room.
+    fn finish(
+        &mut self,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error>;
+}
+
+/// A sans-IO decompressor, driven like the decoders of `async-compression`.
+pub trait Decode {
+    /// Prepare for a new stream, forgetting the rest of the current one.
+    fn reinit(&mut self) -> Result<(), Error>;
+
+    /// Decompress from `input` into `output`, as much as fits. Returns whether the input so far
+    /// ended with a complete frame and all of its content was written.
+    fn decode(
+        &mut self,
+        input: &mut PartialBuffer<impl AsRef<[u8]>>,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error>;
+
+    /// Write everything that was decompressed so far into `output`. Returns whether that is done,
+    /// otherwise it has to be called again with more room.
+    fn flush(
+        &mut self,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error>;
+
+    /// The input has ended. Returns whether all content was written into `output`, otherwise it
+    /// has to be called again with more room. Fails with [ErrorKind::UnexpectedEof] if the input
+    /// ended within a frame.
+    fn finish(
+        &mut self,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error>;
+}
+
+/// Compresses into one frame, see [Encode] and the [module documentation](self).
+#[cfg(feature = "encoder")]
+pub struct Encoder {
+    compress: Compress,
+}
+
+#[cfg(feature = "encoder")]
+impl Encoder {
+    /// Start a frame with the given level, see [Compress::new].
+    pub fn new(level: CompressionLevel) -> Result<Self, EncodeError> {
+        Compress::new(level).map(|compress| Self { compress })
+    }
+
+    /// Start a frame with the given options, see [Compress::with_options].
+    pub fn with_options(options: EncodeOptions) -> Result<Self, EncodeError> {
+        Compress::with_options(options).map(|compress| Self { compress })
+    }
+
+    /// Compress with `flush` and advance the buffers past what was used.
+    fn compress(
+        &mut self,
+        input: &[u8],
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+        flush: FlushCompress,
+    ) -> Result<(usize, Status), Error> {
+        let (total_in, total_out) = (self.compress.total_in(), self.compress.total_out());
+        let status = self
+            .compress
+            .compress(input, output.unwritten_mut(), flush)
+            .map_err(crate::encoding::to_io_error)?;
+        output.advance((self.compress.total_out() - total_out) as usize);
+        Ok(((self.compress.total_in() - total_in) as usize, status))
+    }
+}
+
+#[cfg(feature = "encoder")]
+impl Encode for Encoder {
+    fn encode(
+        &mut self,
+        input: &mut PartialBuffer<impl AsRef<[u8]>>,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<(), Error> {
+        let (consumed, _) = self.compress(input.unwritten(), output, FlushCompress::None)?;
+        input.advance(consumed);
+        Ok(())
+    }
+
+    fn flush(
+        &mut self,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error> {
+        self.compress(&[], output, FlushCompress::Sync)?;
+        // The compressor only stops before the output is full once it has nothing left
+        Ok(!output.unwritten().is_empty())
+    }
+
+    fn finish(
+        &mut self,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error> {
+        let (_, status) = self.compress(&[], output, FlushCompress::Finish)?;
+        Ok(status == Status::StreamEnd)
+    }
+}
+
+/// Decompresses frames, see [Decode] and the [module documentation](self).
+#[derive(Default)]
+pub struct Decoder {
+    decompress: Decompress,
+}
+
+impl Decoder {
+    /// Create a decoder that is waiting for the first frame.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a decoder that decodes with `decoder`, see [Decompress::with_decoder].
+    pub fn with_decoder(decoder: crate::frame_decoder::FrameDecoder) -> Self {
+        Self {
+            decompress: Decompress::with_decoder(decoder),
+        }
+    }
+
+    /// Decompress and advance the buffers past what was used.
+    fn decompress(
+        &mut self,
+        input: &[u8],
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<(usize, Status), Error> {
+        let (total_in, total_out) = (self.decompress.total_in(), self.decompress.total_out());
+        let status = self
+            .decompress
+            .decompress(input, output.unwritten_mut(), FlushDecompress::None)
+            .map_err(to_io_error)?;
+        output.advance((self.decompress.total_out() - total_out) as usize);
+        Ok(((self.decompress.total_in() - total_in) as usize, status))
+    }
+}
+
+impl Decode for Decoder {
+    fn reinit(&mut self) -> Result<(), Error> {
+        self.decompress.reset();
+        Ok(())
+    }
+
+    fn decode(
+        &mut self,
+        input: &mut PartialBuffer<impl AsRef<[u8]>>,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error> {
+        let (consumed, status) = self.decompress(input.unwritten(), output)?;
+        input.advance(consumed);
+        Ok(status == Status::StreamEnd)
+    }
+
+    fn flush(
+        &mut self,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error> {
+        self.decompress(&[], output)?;
+        // The decompressor only stops before the output is full once it has nothing left
+        Ok(!output.unwritten().is_empty())
+    }
+
+    fn finish(
+        &mut self,
+        output: &mut PartialBuffer<impl AsRef<[u8]> + AsMut<[u8]>>,
+    ) -> Result<bool, Error> {
+        match self.decompress(&[], output)? {
+            (_, Status::StreamEnd) => Ok(true),
+            _ if output.unwritten().is_empty() => Ok(false),
+            _ => Err(Error::from(ErrorKind::UnexpectedEof)),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::{Decode, Decoder, Encode, Encoder, PartialBuffer};
+    use crate::encoding::CompressionLevel;
+    use crate::io::ErrorKind;
+    use alloc::vec::Vec;
+
+    /// Drive the codecs like `async-compression` does, with a reader and a writer that hand out
+    /// and take `chunk_size` bytes at a time
+    fn round_trip(data: &[u8], chunk_size: usize) -> Vec<u8> {
+        let mut encoder = Encoder::new(CompressionLevel::Fastest).unwrap();
+        let mut compressed = Vec::new();
+        let mut output = PartialBuffer::new(alloc::vec![0; chunk_size]);
+        for chunk in data.chunks(chunk_size) {
+            let mut input = PartialBuffer::new(chunk);
+            while !input.unwritten().is_empty() {
+                encoder.encode(&mut input, &mut output).unwrap();
+                compressed.extend_from_slice(output.written());
+                output = PartialBuffer::new(output.into_inner());
+            }
+        }
+        loop {
+            let done = encoder.finish(&mut output).unwrap();
+            compressed.extend_from_slice(output.written());
+            output = PartialBuffer::new(output.into_inner());
+            if done {
+                break;
+            }
+        }
+
+        let mut decoder = Decoder::new();
+        let mut decompressed = Vec::new();
+        let mut end = false;
+        for chunk in compressed.chunks(chunk_size) {
+            let mut input = PartialBuffer::new(chunk);
+            loop {
+                end = decoder.decode(&mut input, &mut output).unwrap();
+                let full = output.unwritten().is_empty();
+                decompressed.extend_from_slice(output.written());
+                output = PartialBuffer::new(output.into_inner());
+                if input.unwritten().is_empty() && !full {
+                    break;
+                }
+            }
+        }
+        assert!(end);
+        while !decoder.finish(&mut output).unwrap() {
+            decompressed.extend_from_slice(output.written());
+            output = PartialBuffer::new(output.into_inner());
+        }
+        decompressed.extend_from_slice(output.written());
+        decompressed
+    }
+
+    #[test]
+    fn driven_in_chunks() {
+        let data = crate::test_utils::text_like(200_000, 1);
+        for chunk_size in [1, 100, 8192, 1 << 20] {
+            assert_eq!(round_trip(&data, chunk_size), data);
+        }
+    }
+
+    #[test]
+    fn flush_and_truncation() {
+        let mut encoder = Encoder::new(CompressionLevel::Fastest).unwrap();
+        let mut output = PartialBuffer::new([0; 200]);
+        encoder
+            .encode(&mut PartialBuffer::new(b"flushed"), &mut output)
+            .unwrap();
+        assert!(encoder.flush(&mut output).unwrap());
+
+        // The flushed content can be decoded, but the stream is not complete
+        let mut decoder = Decoder::new();
+        let mut input = PartialBuffer::new(output.written());
+        let mut decompressed = PartialBuffer::new([0; 200]);
+        assert!(!decoder.decode(&mut input, &mut decompressed).unwrap());
+        assert_eq!(decompressed.written(), b"flushed");
+        assert!(decoder.flush(&mut decompressed).unwrap());
+        let err = decoder.finish(&mut decompressed).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+
+        decoder.reinit().unwrap();
+        let frame = crate::compress(b"complete");
+        let mut decompressed = PartialBuffer::new([0; 200]);
+        assert!(decoder
+            .decode(&mut PartialBuffer::new(frame), &mut decompressed)
+            .unwrap());
+        assert!(decoder.finish(&mut decompressed).unwrap());
+        assert_eq!(decompressed.written(), b"complete");
+    }
+}
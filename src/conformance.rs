@@ -0,0 +1,639 @@
+//! Check compressed data against the format specification ([RFC 8878](https://www.rfc-editor.org/rfc/rfc8878)).
+//!
+//! Unlike the decoder, which stops at the first problem it encounters, the [Checker] walks the
+//! whole frame and reports every violation it finds, each with the byte offset it was found at and
+//! the rule of the specification that was broken. This is useful to validate the output of other
+//! zstd implementations.
+//!
+//! The structure of a frame (header, block headers, checksum) is always checked completely. The
+//! content of the blocks is decoded to check literals, sequences, the frame content size and the
+//! checksum. Decoding can not continue after a block with invalid content, so only the first
+//! content violation of a frame is reported.
+//!
+//! ```
+//! use ruzstd::conformance::{check_frame, Rule};
+//!
+//! // A frame with the reserved bit set and a single, empty raw block
+//! let frame = [0x28, 0xB5, 0x2F, 0xFD, 0x08, 0x00, 0x01, 0x00, 0x00];
+//! let report = check_frame(&frame);
+//! assert_eq!(report.violations.len(), 1);
+//! assert_eq!(report.violations[0].rule, Rule::ReservedBit);
+//! assert_eq!(report.violations[0].offset, 4);
+//! ```
+
+// Exempt from the allocation part of the no-panic check, see the Readme: the report is collected
+// as it goes and there is no error to return when an allocation fails
+#![allow(clippy::disallowed_methods)]
+
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+use crate::blocks::block::{BlockHeader, BlockType};
+use crate::decoding::block_decoder::{self, DecodeBlockContentError, DecompressBlockError};
+use crate::decoding::dictionary::Dictionary;
+use crate::decoding::scratch::DecoderScratch;
+use crate::frame::{self, MAGIC_NUM};
+
+/// The largest block a frame may contain, regardless of the window size.
+const ABSOLUTE_MAXIMUM_BLOCK_SIZE: u64 = 128 * 1024;
+
+/// A rule of the format specification that compressed data can violate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Rule {
+    /// Zstandard frames start with the magic number `0xFD2FB528`.
+    MagicNumber,
+    /// The data ended before the frame was complete.
+    Truncated,
+    /// The reserved bit of the frame header descriptor must be zero.
+    ReservedBit,
+    /// The decompressed content must be exactly as large as the frame header declares.
+    FrameContentSize,
+    /// The block type 3 is reserved.
+    ReservedBlockType,
+    /// Blocks must not be larger than the window size or 128 KiB, whichever is smaller.
+    BlockMaximumSize,
+    /// The literals section of a compressed block must be valid.
+    LiteralsSection,
+    /// The sequences section of a compressed block must be valid.
+    SequencesSection,
+    /// Sequences must only reference data that was already decoded.
+    SequenceExecution,
+    /// The checksum must match the decompressed content.
+    ContentChecksum,
+    /// Skippable frames must contain as many bytes as their header declares.
+    SkippableFrame,
+}
+
+impl Rule {
+    /// The section of RFC 8878 that defines the rule.
+    pub fn section(&self) -> &'static str {
+        match self {
+            Rule::MagicNumber => "3.1.1",
+            Rule::Truncated => "3.1.1",
+            Rule::ReservedBit => "3.1.1.1.1.4",
+            Rule::FrameContentSize => "3.1.1.1.4",
+            Rule::ReservedBlockType => "3.1.1.2.2",
+            Rule::BlockMaximumSize => "3.1.1.2.4",
+            Rule::LiteralsSection => "3.1.1.3.1",
+            Rule::SequencesSection => "3.1.1.3.2",
+            Rule::SequenceExecution => "3.1.1.4",
+            Rule::ContentChecksum => "3.1.1",
+            Rule::SkippableFrame => "3.1.2",
+        }
+    }
+
+    /// The name of the field or concept the rule is about, as used in the specification.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rule::MagicNumber => "Magic_Number",
+            Rule::Truncated => "Zstandard frames",
+            Rule::ReservedBit => "Reserved_Bit",
+            Rule::FrameContentSize => "Frame_Content_Size",
+            Rule::ReservedBlockType => "Block_Type",
+            Rule::BlockMaximumSize => "Block_Maximum_Size",
+            Rule::LiteralsSection => "Literals_Section",
+            Rule::SequencesSection => "Sequences_Section",
+            Rule::SequenceExecution => "Sequence Execution",
+            Rule::ContentChecksum => "Content_Checksum",
+            Rule::SkippableFrame => "Skippable frames",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RFC 8878 section {} ({})", self.section(), self.name())
+    }
+}
+
+/// A single violation of the specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// The offset of the field or block the violation was found in.
+    pub offset: usize,
+    pub rule: Rule,
+    pub description: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "At byte {}: {} [{}]",
+            self.offset, self.description, self.rule
+        )
+    }
+}
+
+/// The result of checking a single frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameReport {
+    pub violations: Vec<Violation>,
+    /// How many bytes the frame occupies. If the frame is truncated, this is the length of the data.
+    pub frame_len: usize,
+    /// Whether the content of the blocks was decoded. This is not possible if the frame needs a
+    /// dictionary that was not added to the [Checker], or if its structure is broken.
+    pub content_checked: bool,
+}
+
+impl FrameReport {
+    /// True if no violations were found.
+    pub fn is_conforming(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// Checks frames for violations of the specification, see the [module documentation](self).
+#[derive(Default)]
+pub struct Checker {
+    dicts: BTreeMap<u32, Arc<Dictionary>>,
+}
+
+/// Check the frame at the start of `data` without any dictionaries.
+pub fn check_frame(data: &[u8]) -> FrameReport {
+    Checker::new().check_frame(data)
+}
+
+/// Check all frames in `data` without any dictionaries.
+pub fn check_all(data: &[u8]) -> Vec<Violation> {
+    Checker::new().check_all(data)
+}
+
+/// A block header and where it was found, collected while walking the frame.
+struct BlockInfo {
+    offset: usize,
+    header: BlockHeader,
+    /// The block already broke a structural rule, its content is not checked.
+    invalid: bool,
+}
+
+impl Checker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a dictionary that frames can reference by its ID.
+    pub fn add_dict(&mut self, dict: impl Into<Arc<Dictionary>>) {
+        let dict = dict.into();
+        self.dicts.insert(dict.id, dict);
+    }
+
+    /// Check all frames in `data`, including skippable frames.
+    ///
+    /// The offsets of the violations are relative to the start of `data`. Checking stops at the
+    /// first position that does not start with a magic number, because the end of the previous
+    /// frame can not be found reliably after that.
+    pub fn check_all(&self, data: &[u8]) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        let mut position = 0;
+        while position < data.len() {
+            let report = self.check_frame(&data[position..]);
+            let stop = report
+                .violations
+                .iter()
+                .any(|violation| violation.rule == Rule::MagicNumber);
+            violations.extend(report.violations.into_iter().map(|mut violation| {
+                violation.offset += position;
+                violation
+            }));
+            if stop {
+                break;
+            }
+            position += report.frame_len;
+        }
+        violations
+    }
+
+    /// Check the frame at the start of `data`. Data after the end of the frame is ignored.
+    pub fn check_frame(&self, data: &[u8]) -> FrameReport {
+        let mut report = FrameReport {
+            violations: Vec::new(),
+            frame_len: data.len(),
+            content_checked: false,
+        };
+        let Some(magic_number) = read_le(data, 0, 4) else {
+            report.push(0, Rule::Truncated, "The data ends inside the magic number");
+            return report;
+        };
+        let magic_number = magic_number as u32;
+        if magic_number & !0xF == crate::skippable_frame::SKIPPABLE_MAGIC_NUM {
+            check_skippable_frame(data, &mut report);
+            return report;
+        }
+        if magic_number != MAGIC_NUM {
+            report.push(
+                0,
+                Rule::MagicNumber,
+                format!("Found magic number 0x{:08X}", magic_number),
+            );
+            return report;
+        }
+
+        let (frame, header_size) = match frame::read_frame_header(data) {
+            Ok(header) => header,
+            Err(_) => {
+                report.push(4, Rule::Truncated, "The data ends inside the frame header");
+                return report;
+            }
+        };
+        let header = frame.header;
+        let descriptor = &header.descriptor;
+        if descriptor.reserved_flag() {
+            report.push(
+                4,
+                Rule::ReservedBit,
+                "The reserved bit of the frame header descriptor is set",
+            );
+        }
+        // The size of the fields was already validated by read_frame_header
+        let fcs_len = descriptor.frame_content_size_bytes().unwrap_or(0) as usize;
+        let fcs_offset = usize::from(header_size) - fcs_len;
+        let window_size = if descriptor.single_segment_flag() {
+            header.frame_content_size()
+        } else {
+            // Not FrameHeader::window_size, which rejects the largest valid window
+            let window_descriptor = data[5];
+            let window_base = 1u64 << (10 + (window_descriptor >> 3));
+            window_base + (window_base / 8) * u64::from(window_descriptor & 0x7)
+        };
+        let block_maximum_size = u64::min(window_size, ABSOLUTE_MAXIMUM_BLOCK_SIZE);
+
+        // Walk the block headers, the structure can be checked without decoding anything
+        let mut blocks = Vec::new();
+        let mut position = usize::from(header_size);
+        let mut complete = false;
+        while let Some(raw) = read_le(data, position, 3) {
+            let raw = raw as u32;
+            let last_block = raw & 1 == 1;
+            let block_size = raw >> 3;
+            let block_type = match (raw >> 1) & 0x3 {
+                0 => BlockType::Raw,
+                1 => BlockType::RLE,
+                2 => BlockType::Compressed,
+                _ => {
+                    report.push(
+                        position,
+                        Rule::ReservedBlockType,
+                        "Found a block with the reserved block type 3",
+                    );
+                    // The size of reserved blocks has no defined meaning, the walk can not continue
+                    return report;
+                }
+            };
+            let mut invalid = false;
+            if u64::from(block_size) > block_maximum_size {
+                report.push(
+                    position,
+                    Rule::BlockMaximumSize,
+                    format!(
+                        "The block size of {} bytes exceeds the maximum of {} bytes",
+                        block_size, block_maximum_size
+                    ),
+                );
+                invalid = true;
+            }
+            let content_len = match block_type {
+                BlockType::RLE => 1,
+                _ => block_size as usize,
+            };
+            if data.len() - position - 3 < content_len {
+                report.push(position, Rule::Truncated, "The data ends inside a block");
+                return report;
+            }
+            blocks.push(BlockInfo {
+                offset: position,
+                header: BlockHeader {
+                    last_block,
+                    block_type,
+                    decompressed_size: match block_type {
+                        BlockType::Compressed => 0,
+                        _ => block_size,
+                    },
+                    content_size: content_len as u32,
+                },
+                invalid,
+            });
+            position += 3 + content_len;
+            if last_block {
+                complete = true;
+                break;
+            }
+        }
+        if !complete {
+            report.push(
+                position,
+                Rule::Truncated,
+                "The data ends before the last block",
+            );
+            return report;
+        }
+        let checksum = if descriptor.content_checksum_flag() {
+            match read_le(data, position, 4) {
+                Some(checksum) => {
+                    position += 4;
+                    Some((position - 4, checksum as u32))
+                }
+                None => {
+                    report.push(
+                        position,
+                        Rule::Truncated,
+                        "The data ends inside the checksum",
+                    );
+                    return report;
+                }
+            }
+        } else {
+            None
+        };
+        report.frame_len = position;
+
+        // Decode the content to check the blocks, the content size and the checksum
+        let dict = match header.dictionary_id() {
+            Some(id) => match self.dicts.get(&id) {
+                Some(dict) => Some(dict),
+                None => return report,
+            },
+            None => None,
+        };
+        let mut scratch = DecoderScratch::new(usize::try_from(window_size).unwrap_or(usize::MAX));
+        if let Some(dict) = dict {
+            if scratch.init_from_dict(dict).is_err() {
+                return report;
+            }
+        }
+        let mut decompressed_size = 0u64;
+        for block in &blocks {
+            if block.invalid {
+                return report;
+            }
+            let mut block_decoder = block_decoder::new();
+            let mut source = &data[block.offset..];
+            if block_decoder.read_block_header(&mut source).is_err() {
+                return report;
+            }
+            let before = scratch.buffer.len();
+            if let Err(err) =
+                block_decoder.decode_block_content(&block.header, &mut scratch, &mut source)
+            {
+                report.push(block.offset + 3, content_rule(&err), err.to_string());
+                return report;
+            }
+            let block_output = (scratch.buffer.len() - before) as u64;
+            if block_output > block_maximum_size {
+                report.push(
+                    block.offset,
+                    Rule::BlockMaximumSize,
+                    format!(
+                        "The block decompresses to {} bytes, more than the maximum of {} bytes",
+                        block_output, block_maximum_size
+                    ),
+                );
+            }
+            decompressed_size += block_output;
+            // Only the window has to be kept around, the rest is only needed for the checksum
+            scratch.buffer.drain_to_window_size();
+        }
+        scratch.buffer.drain();
+        report.content_checked = true;
+
+        if fcs_len > 0 && header.frame_content_size() != decompressed_size {
+            report.push(
+                fcs_offset,
+                Rule::FrameContentSize,
+                format!(
+                    "The header declares {} bytes of content, but the blocks decompress to {} bytes",
+                    header.frame_content_size(),
+                    decompressed_size
+                ),
+            );
+        }
+        #[cfg(feature = "hash")]
+        if let Some((offset, checksum)) = checksum {
+            use core::hash::Hasher;
+
+            let calculated = scratch.buffer.hash.finish() as u32;
+            if calculated != checksum {
+                report.push(
+                    offset,
+                    Rule::ContentChecksum,
+                    format!(
+                        "The checksum 0x{:08X} does not match the content, which has the checksum 0x{:08X}",
+                        checksum, calculated
+                    ),
+                );
+            }
+        }
+        #[cfg(not(feature = "hash"))]
+        let _ = checksum;
+        report
+    }
+}
+
+impl FrameReport {
+    fn push(&mut self, offset: usize, rule: Rule, description: impl Into<String>) {
+        self.violations.push(Violation {
+            offset,
+            rule,
+            description: description.into(),
+        });
+    }
+}
+
+fn check_skippable_frame(data: &[u8], report: &mut FrameReport) {
+    let Some(length) = read_le(data, 4, 4) else {
+        report.push(
+            4,
+            Rule::Truncated,
+            "The data ends inside the skippable frame header",
+        );
+        return;
+    };
+    if data.len() - 8 < length as usize {
+        report.push(
+            4,
+            Rule::SkippableFrame,
+            format!(
+                "The skippable frame declares {} bytes of data, but only {} bytes follow",
+                length,
+                data.len() - 8
+            ),
+        );
+        return;
+    }
+    report.frame_len = 8 + length as usize;
+}
+
+/// The rule that is broken by a block that failed to decode.
+fn content_rule(err: &DecodeBlockContentError) -> Rule {
+    let DecodeBlockContentError::DecompressBlockError(err) = err else {
+        return Rule::Truncated;
+    };
+    match err {
+        DecompressBlockError::SequencesHeaderParseError(_)
+        | DecompressBlockError::DecodeSequenceError(_) => Rule::SequencesSection,
+        DecompressBlockError::ExecuteSequencesError(_) => Rule::SequenceExecution,
+        _ => Rule::LiteralsSection,
+    }
+}
+
+/// Read a little endian number of `len` bytes at `position`, if the data is long enough.
+fn read_le(data: &[u8], position: usize, len: usize) -> Option<u64> {
+    let bytes = data.get(position..position.checked_add(len)?)?;
+    Some(
+        bytes
+            .iter()
+            .rev()
+            .fold(0, |value, byte| (value << 8) | u64::from(*byte)),
+    )
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::{check_all, check_frame, Checker, Rule};
+    use crate::decoding::dictionary::Dictionary;
+    use crate::encoding::{CompressionLevel, FrameCompressor};
+    use alloc::vec::Vec;
+
+    fn compress(data: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        FrameCompressor::new(data, &mut compressed, CompressionLevel::Fastest)
+            .compress()
+            .unwrap();
+        compressed
+    }
+
+    fn rules(report: &super::FrameReport) -> Vec<(usize, Rule)> {
+        report
+            .violations
+            .iter()
+            .map(|violation| (violation.offset, violation.rule))
+            .collect()
+    }
+
+    #[test]
+    fn valid_frames() {
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 7 + i / 5000) as u8).collect();
+        let compressed = compress(&data);
+        let report = check_frame(&compressed);
+        assert!(report.is_conforming(), "{:?}", report.violations);
+        assert!(report.content_checked);
+        assert_eq!(report.frame_len, compressed.len());
+
+        // Frames of the reference implementation, with checksums and content sizes
+        let reference = include_bytes!("../decodecorpus_files/z000033.zst");
+        let report = check_frame(reference);
+        assert!(report.is_conforming(), "{:?}", report.violations);
+        assert!(report.content_checked);
+
+        let mut skippable = Vec::new();
+        crate::skippable_frame::SkippableFrame::new(3, alloc::vec![1, 2, 3])
+            .unwrap()
+            .write(&mut skippable)
+            .unwrap();
+        let mut all = compressed.clone();
+        all.extend_from_slice(&skippable);
+        all.extend_from_slice(&compressed);
+        assert!(check_all(&all).is_empty());
+    }
+
+    #[test]
+    fn reports_all_structural_violations() {
+        // Reserved bit, a raw block that is larger than the window of 1 KiB and a content size
+        // that does not match, all in one frame
+        let mut frame = alloc::vec![0x28, 0xB5, 0x2F, 0xFD, 0b0100_1000, 0x00, 0x00, 0x00];
+        let block_size = 2000u32;
+        frame.extend_from_slice(&((block_size << 3) | 1).to_le_bytes()[..3]);
+        frame.resize(frame.len() + block_size as usize, 7);
+        let report = check_frame(&frame);
+        assert_eq!(
+            rules(&report),
+            [(4, Rule::ReservedBit), (8, Rule::BlockMaximumSize)]
+        );
+        assert!(!report.content_checked);
+
+        // With a fitting window, the content size mismatch is found by decoding
+        frame[5] = 0x08;
+        let report = check_frame(&frame);
+        assert_eq!(
+            rules(&report),
+            [(4, Rule::ReservedBit), (6, Rule::FrameContentSize)]
+        );
+        assert!(report.content_checked);
+
+        // Reserved block type
+        let mut reserved = frame[..8].to_vec();
+        reserved.extend_from_slice(&[0b111, 0, 0]);
+        assert_eq!(
+            rules(&check_frame(&reserved)),
+            [(4, Rule::ReservedBit), (8, Rule::ReservedBlockType)]
+        );
+
+        // Truncated frames and a bad magic number stop the walk through multiple frames
+        let valid = compress(b"some data some data some data");
+        let mut all = valid.clone();
+        all.extend_from_slice(&valid[..valid.len() - 2]);
+        let violations = check_all(&all);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].rule, Rule::Truncated);
+        let mut all = valid.clone();
+        all.extend_from_slice(&[1, 2, 3, 4, 5]);
+        all.extend_from_slice(&valid);
+        let violations = check_all(&all);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(
+            (violations[0].offset, violations[0].rule),
+            (valid.len(), Rule::MagicNumber)
+        );
+    }
+
+    #[test]
+    fn content_violations() {
+        let data = crate::test_utils::text_like(20_000, 1);
+        let compressed = compress(&data);
+        // The first block starts after the 6 byte frame header and is compressed
+        let mut broken = compressed.clone();
+        for byte in &mut broken[9..20] {
+            *byte = 0xFF;
+        }
+        let report = check_frame(&broken);
+        assert_eq!(report.violations.len(), 1, "{:?}", report.violations);
+        assert_eq!(report.violations[0].offset, 9);
+        assert!(matches!(
+            report.violations[0].rule,
+            Rule::LiteralsSection | Rule::SequencesSection | Rule::SequenceExecution
+        ));
+
+        // A wrong checksum in a frame of the reference implementation
+        #[cfg(feature = "hash")]
+        {
+            let mut reference = include_bytes!("../decodecorpus_files/z000033.zst").to_vec();
+            let last = reference.len() - 1;
+            reference[last] ^= 1;
+            assert_eq!(
+                rules(&check_frame(&reference)),
+                [(last - 3, Rule::ContentChecksum)]
+            );
+        }
+    }
+
+    #[test]
+    fn dictionaries() {
+        let dict_raw = include_bytes!("../dict_tests/dictionary");
+        let compressed = include_bytes!("../dict_tests/files/ModemManager.service.zst");
+        let report = check_frame(compressed);
+        assert!(report.is_conforming());
+        assert!(!report.content_checked);
+
+        let mut checker = Checker::new();
+        checker.add_dict(Dictionary::decode_dict(dict_raw).unwrap());
+        let report = checker.check_frame(compressed);
+        assert!(report.is_conforming(), "{:?}", report.violations);
+        assert!(report.content_checked);
+    }
+}
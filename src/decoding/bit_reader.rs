@@ -60,13 +60,13 @@ impl<'s> BitReader<'s> {
         self.idx
     }
 
+    /// Return the last `n` bits that were read, so they are read again. At most the bits that
+    /// were read so far are returned.
     pub fn return_bits(&mut self, n: usize) {
-        if n > self.idx {
-            panic!("Cant return this many bits");
-        }
-        self.idx -= n;
+        self.idx = self.idx.saturating_sub(n);
     }
 
+    #[allow(clippy::disallowed_macros)]
     pub fn get_bits(&mut self, n: usize) -> Result<u64, GetBitsError> {
         if n > 64 {
             return Err(GetBitsError::TooManyBits {
@@ -102,14 +102,8 @@ impl<'s> BitReader<'s> {
             let full_bytes_needed = (n - bits_left_in_current_byte) / 8;
             let bits_in_last_byte_needed = n - bits_left_in_current_byte - full_bytes_needed * 8;
 
-            assert!(
-                bits_left_in_current_byte + full_bytes_needed * 8 + bits_in_last_byte_needed == n
-            );
-
             let mut bit_shift = bits_left_in_current_byte; //this many bits are already set in value
 
-            assert!(self.idx % 8 == 0);
-
             //collect full bytes
             for _ in 0..full_bytes_needed {
                 value |= u64::from(self.source[self.idx / 8]) << bit_shift;
@@ -117,8 +111,6 @@ impl<'s> BitReader<'s> {
                 bit_shift += 8;
             }
 
-            assert!(n - bit_shift == bits_in_last_byte_needed);
-
             if bits_in_last_byte_needed > 0 {
                 let val_las_byte =
                     u64::from(self.source[self.idx / 8]) & ((1 << bits_in_last_byte_needed) - 1);
@@ -127,7 +119,7 @@ impl<'s> BitReader<'s> {
             }
         }
 
-        assert!(self.idx == old_idx + n);
+        debug_assert_eq!(self.idx, old_idx + n);
 
         Ok(value)
     }
@@ -1,7 +1,5 @@
-use core::convert::TryInto;
-
 pub use super::bit_reader::GetBitsError;
-use crate::io::Read;
+use crate::bits::load_le;
 
 /// Zstandard encodes some types of data in a way that the data must be read
 /// back to front to decode it properly. `BitReaderReversed` provides a
@@ -36,7 +34,7 @@ impl<'s> BitReaderReversed<'s> {
     fn refill_container(&mut self) {
         let byte_idx = self.byte_idx() as usize;
 
-        let retain_bytes = (self.bits_in_container + 7) / 8;
+        let retain_bytes = self.bits_in_container.div_ceil(8);
         let want_to_read_bits = 64 - (retain_bytes * 8);
 
         // if there are >= 8 byte left to read we go a fast path:
@@ -54,11 +52,7 @@ impl<'s> BitReaderReversed<'s> {
     #[inline(always)]
     fn refill_fast(&mut self, byte_idx: usize, retain_bytes: u8, want_to_read_bits: u8) {
         let load_from_byte_idx = byte_idx - 7 + retain_bytes as usize;
-        let tmp_bytes: [u8; 8] = (&self.source[load_from_byte_idx..][..8])
-            .try_into()
-            .unwrap();
-        let refill = u64::from_le_bytes(tmp_bytes);
-        self.bit_container = refill;
+        self.bit_container = load_le(&self.source[load_from_byte_idx..][..8]);
         self.bits_in_container += want_to_read_bits;
         self.idx -= want_to_read_bits as isize;
     }
@@ -66,21 +60,18 @@ impl<'s> BitReaderReversed<'s> {
     #[cold]
     fn refill_slow(&mut self, byte_idx: usize, want_to_read_bits: u8) {
         let can_read_bits = isize::min(want_to_read_bits as isize, self.idx);
-        let can_read_bytes = can_read_bits / 8;
-        let mut tmp_bytes = [0u8; 8];
-        let offset @ 1..=8 = can_read_bytes as usize else {
-            unreachable!()
-        };
+        // The container is refilled in whole bytes and has room for at least one more
+        let offset = (can_read_bits as usize / 8).clamp(1, 8);
         let bits_read = offset * 8;
 
-        let _ = (&self.source[byte_idx - (offset - 1)..]).read_exact(&mut tmp_bytes[0..offset]);
+        let refill = load_le(&self.source[byte_idx - (offset - 1)..][..offset]);
         self.bits_in_container += bits_read as u8;
         self.idx -= bits_read as isize;
         if offset < 8 {
             self.bit_container <<= bits_read;
-            self.bit_container |= u64::from_le_bytes(tmp_bytes);
+            self.bit_container |= refill;
         } else {
-            self.bit_container = u64::from_le_bytes(tmp_bytes);
+            self.bit_container = refill;
         }
     }
 
@@ -105,6 +96,7 @@ impl<'s> BitReaderReversed<'s> {
     }
 
     #[cold]
+    #[allow(clippy::disallowed_macros)]
     fn get_bits_cold(&mut self, n: u8) -> u64 {
         let n = u8::min(n, 56);
         let signed_n = n as isize;
@@ -136,6 +128,7 @@ impl<'s> BitReaderReversed<'s> {
 
     /// Same as calling get_bits three times but slightly more performant
     #[inline(always)]
+    #[allow(clippy::disallowed_macros)]
     pub fn get_bits_triple(&mut self, n1: u8, n2: u8, n3: u8) -> (u64, u64, u64) {
         let sum = n1 as usize + n2 as usize + n3 as usize;
         if sum == 0 {
@@ -171,6 +164,7 @@ impl<'s> BitReaderReversed<'s> {
     }
 
     #[cold]
+    #[allow(clippy::disallowed_macros)]
     fn get_bits_triple_cold(&mut self, n1: u8, n2: u8, n3: u8, sum: u8) -> (u64, u64, u64) {
         let sum_signed = sum as isize;
 
@@ -211,6 +205,7 @@ impl<'s> BitReaderReversed<'s> {
     }
 
     #[inline(always)]
+    #[allow(clippy::disallowed_macros)]
     fn get_bits_unchecked(&mut self, n: u8) -> u64 {
         let shift_by = self.bits_in_container - n;
         let mask = (1u64 << n) - 1u64;
@@ -3,6 +3,7 @@ use super::super::blocks::block::BlockType;
 use super::super::blocks::literals_section::LiteralsSection;
 use super::super::blocks::literals_section::LiteralsSectionType;
 use super::super::blocks::sequence_section::SequencesHeader;
+use super::decodebuffer::DecodeBufferError;
 use super::literals_section_decoder::{decode_literals, DecompressLiteralsError};
 use super::sequence_execution::ExecuteSequencesError;
 use super::sequence_section_decoder::decode_sequences;
@@ -11,11 +12,13 @@ use crate::blocks::literals_section::LiteralsSectionParseError;
 use crate::blocks::sequence_section::SequencesHeaderParseError;
 use crate::decoding::scratch::DecoderScratch;
 use crate::decoding::sequence_execution::execute_sequences;
+use crate::fallible::{AllocError, TryVec};
 use crate::io::{self, Read};
 
 pub struct BlockDecoder {
-    header_buffer: [u8; 3],
     internal_state: DecoderState,
+    /// The literals type and the sequences header of the last compressed block
+    pub(crate) last_sections: Option<(LiteralsSectionType, SequencesHeader)>,
 }
 
 enum DecoderState {
@@ -138,6 +141,14 @@ pub enum DecompressBlockError {
     SequencesHeaderParseError(SequencesHeaderParseError),
     DecodeSequenceError(DecodeSequenceError),
     ExecuteSequencesError(ExecuteSequencesError),
+    WrongNumberOfLiterals {
+        expected: u32,
+        got: usize,
+    },
+    /// There is not enough memory to copy the block content out of the reader.
+    OutOfMemory {
+        size: usize,
+    },
 }
 
 #[cfg(feature = "std")]
@@ -176,10 +187,26 @@ impl core::fmt::Display for DecompressBlockError {
             DecompressBlockError::SequencesHeaderParseError(e) => write!(f, "{:?}", e),
             DecompressBlockError::DecodeSequenceError(e) => write!(f, "{:?}", e),
             DecompressBlockError::ExecuteSequencesError(e) => write!(f, "{:?}", e),
+            DecompressBlockError::WrongNumberOfLiterals { expected, got } => {
+                write!(
+                    f,
+                    "Wrong number of literals: {}, Should have been: {}",
+                    got, expected
+                )
+            }
+            DecompressBlockError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for the block content", size)
+            }
         }
     }
 }
 
+impl From<AllocError> for DecompressBlockError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
 impl From<io::Error> for DecompressBlockError {
     fn from(val: io::Error) -> Self {
         Self::BlockContentReadError(val)
@@ -221,8 +248,15 @@ impl From<ExecuteSequencesError> for DecompressBlockError {
 pub enum DecodeBlockContentError {
     DecoderStateIsFailed,
     ExpectedHeaderOfPreviousBlock,
-    ReadError { step: BlockType, source: io::Error },
+    ReadError {
+        step: BlockType,
+        source: io::Error,
+    },
     DecompressBlockError(DecompressBlockError),
+    /// The header has the reserved block type, which [BlockHeader::parse] already rejects.
+    ReservedBlockType,
+    /// The content of a raw or RLE block could not be added to the decode buffer.
+    DecodeBufferError(DecodeBufferError),
 }
 
 #[cfg(feature = "std")]
@@ -231,6 +265,7 @@ impl std::error::Error for DecodeBlockContentError {
         match self {
             DecodeBlockContentError::ReadError { step: _, source } => Some(source),
             DecodeBlockContentError::DecompressBlockError(source) => Some(source),
+            DecodeBlockContentError::DecodeBufferError(source) => Some(source),
             _ => None,
         }
     }
@@ -254,10 +289,20 @@ impl core::fmt::Display for DecodeBlockContentError {
                 write!(f, "Error while reading bytes for {}: {}", step, source,)
             }
             DecodeBlockContentError::DecompressBlockError(e) => write!(f, "{:?}", e),
+            DecodeBlockContentError::ReservedBlockType => {
+                write!(f, "Blocks of the reserved type can not be decoded")
+            }
+            DecodeBlockContentError::DecodeBufferError(e) => write!(f, "{}", e),
         }
     }
 }
 
+impl From<DecodeBufferError> for DecodeBlockContentError {
+    fn from(val: DecodeBufferError) -> Self {
+        Self::DecodeBufferError(val)
+    }
+}
+
 impl From<DecompressBlockError> for DecodeBlockContentError {
     fn from(val: DecompressBlockError) -> Self {
         Self::DecompressBlockError(val)
@@ -268,11 +313,11 @@ impl From<DecompressBlockError> for DecodeBlockContentError {
 pub fn new() -> BlockDecoder {
     BlockDecoder {
         internal_state: DecoderState::ReadyToDecodeNextHeader,
-        header_buffer: [0u8; 3],
+        last_sections: None,
     }
 }
 
-const ABSOLUTE_MAXIMUM_BLOCK_SIZE: u32 = 128 * 1024;
+pub(crate) const ABSOLUTE_MAXIMUM_BLOCK_SIZE: u32 = 128 * 1024;
 
 impl BlockDecoder {
     pub fn decode_block_content(
@@ -292,7 +337,7 @@ impl BlockDecoder {
         let block_type = header.block_type;
         match block_type {
             BlockType::RLE => {
-                const BATCH_SIZE: usize = 512;
+                const BATCH_SIZE: usize = crate::STACK_BUFFER_SIZE;
                 let mut buf = [0u8; BATCH_SIZE];
                 let full_reads = header.decompressed_size / BATCH_SIZE as u32;
                 let single_read_size = header.decompressed_size % BATCH_SIZE as u32;
@@ -310,15 +355,15 @@ impl BlockDecoder {
                 }
 
                 for _ in 0..full_reads {
-                    workspace.buffer.push(&buf[..]);
+                    workspace.buffer.push(&buf[..])?;
                 }
                 let smaller = &mut buf[..single_read_size as usize];
-                workspace.buffer.push(smaller);
+                workspace.buffer.push(smaller)?;
 
                 Ok(1)
             }
             BlockType::Raw => {
-                const BATCH_SIZE: usize = 128 * 1024;
+                const BATCH_SIZE: usize = crate::STACK_BUFFER_SIZE;
                 let mut buf = [0u8; BATCH_SIZE];
                 let full_reads = header.decompressed_size / BATCH_SIZE as u32;
                 let single_read_size = header.decompressed_size % BATCH_SIZE as u32;
@@ -330,7 +375,7 @@ impl BlockDecoder {
                             source: err,
                         }
                     })?;
-                    workspace.buffer.push(&buf[..]);
+                    workspace.buffer.push(&buf[..])?;
                 }
 
                 let smaller = &mut buf[..single_read_size as usize];
@@ -340,15 +385,13 @@ impl BlockDecoder {
                         step: block_type,
                         source: err,
                     })?;
-                workspace.buffer.push(smaller);
+                workspace.buffer.push(smaller)?;
 
                 self.internal_state = DecoderState::ReadyToDecodeNextHeader;
                 Ok(u64::from(header.decompressed_size))
             }
 
-            BlockType::Reserved => {
-                panic!("How did you even get this. The decoder should error out if it detects a reserved-type block");
-            }
+            BlockType::Reserved => Err(DecodeBlockContentError::ReservedBlockType),
 
             BlockType::Compressed => {
                 self.decompress_block(header, workspace, source)?;
@@ -359,7 +402,36 @@ impl BlockDecoder {
         }
     }
 
-    fn decompress_block(
+    /// Like [BlockDecoder::decode_block_content], but takes the block content directly from `source`
+    /// and advances it past the block. Raw and compressed blocks are not copied into an intermediate
+    /// buffer before they are decoded.
+    pub(crate) fn decode_block_content_from_slice(
+        &mut self,
+        header: &BlockHeader,
+        workspace: &mut DecoderScratch,
+        source: &mut &[u8],
+    ) -> Result<u64, DecodeBlockContentError> {
+        let content_size = header.content_size as usize;
+        if !matches!(self.internal_state, DecoderState::ReadyToDecodeNextBody)
+            || source.len() < content_size
+        {
+            // Let the reader based path report the error
+            return self.decode_block_content(header, workspace, source);
+        }
+        let (content, rest) = source.split_at(content_size);
+        match header.block_type {
+            BlockType::Raw => workspace.buffer.push(content)?,
+            BlockType::Compressed => {
+                self.decompress_block_from_slice(header, workspace, content)?
+            }
+            _ => return self.decode_block_content(header, workspace, source),
+        }
+        *source = rest;
+        self.internal_state = DecoderState::ReadyToDecodeNextHeader;
+        Ok(u64::from(header.content_size))
+    }
+
+    pub(crate) fn decompress_block(
         &mut self,
         header: &BlockHeader,
         workspace: &mut DecoderScratch, //reuse this as often as possible. Not only if the trees are reused but also reuse the allocations when building new trees
@@ -367,11 +439,24 @@ impl BlockDecoder {
     ) -> Result<(), DecompressBlockError> {
         workspace
             .block_content_buffer
-            .resize(header.content_size as usize, 0);
+            .try_resize(header.content_size as usize, 0)?;
 
         source.read_exact(workspace.block_content_buffer.as_mut_slice())?;
-        let raw = workspace.block_content_buffer.as_slice();
+        let raw = core::mem::take(&mut workspace.block_content_buffer);
+        let result = self.decompress_block_from_slice(header, workspace, &raw);
+        workspace.block_content_buffer = raw;
+        result
+    }
 
+    /// Like [BlockDecoder::decompress_block], but parses the block directly from `raw`, which holds
+    /// exactly the `header.content_size` bytes of the block, instead of copying it out of a reader first.
+    #[allow(clippy::disallowed_macros)]
+    pub(crate) fn decompress_block_from_slice(
+        &mut self,
+        header: &BlockHeader,
+        workspace: &mut DecoderScratch,
+        raw: &[u8],
+    ) -> Result<(), DecompressBlockError> {
         let mut section = LiteralsSection::new();
         let bytes_in_literals_header = section.parse_from_header(raw)?;
         let raw = &raw[bytes_in_literals_header as usize..];
@@ -384,10 +469,10 @@ impl BlockDecoder {
 
         let upper_limit_for_literals = match section.compressed_size {
             Some(x) => x as usize,
+            // Only raw and RLE sections have no compressed size
             None => match section.ls_type {
                 LiteralsSectionType::RLE => 1,
-                LiteralsSectionType::Raw => section.regenerated_size as usize,
-                _ => panic!("Bug in this library"),
+                _ => section.regenerated_size as usize,
             },
         };
 
@@ -408,13 +493,18 @@ impl BlockDecoder {
             raw_literals,
             &mut workspace.literals_buffer,
         )?;
-        assert!(
-            section.regenerated_size == workspace.literals_buffer.len() as u32,
-            "Wrong number of literals: {}, Should have been: {}",
-            workspace.literals_buffer.len(),
-            section.regenerated_size
-        );
-        assert!(bytes_used_in_literals_section == upper_limit_for_literals as u32);
+        if section.regenerated_size as usize != workspace.literals_buffer.len() {
+            return Err(DecompressBlockError::WrongNumberOfLiterals {
+                expected: section.regenerated_size,
+                got: workspace.literals_buffer.len(),
+            });
+        }
+        if bytes_used_in_literals_section as usize != upper_limit_for_literals {
+            return Err(DecompressBlockError::MalformedSectionHeader {
+                expected_len: upper_limit_for_literals,
+                remaining_bytes: bytes_used_in_literals_section as usize,
+            });
+        }
 
         let raw = &raw[upper_limit_for_literals..];
         vprintln!("Slice for sequences with headers: {}", raw.len());
@@ -428,12 +518,12 @@ impl BlockDecoder {
             raw.len()
         );
 
-        assert!(
+        debug_assert_eq!(
             u32::from(bytes_in_literals_header)
                 + bytes_used_in_literals_section
                 + u32::from(bytes_in_sequence_header)
-                + raw.len() as u32
-                == header.content_size
+                + raw.len() as u32,
+            header.content_size
         );
         vprintln!("Slice for sequences: {}", raw.len());
 
@@ -454,10 +544,14 @@ impl BlockDecoder {
                     },
                 ));
             }
-            workspace.buffer.push(&workspace.literals_buffer);
+            workspace
+                .buffer
+                .push(&workspace.literals_buffer)
+                .map_err(ExecuteSequencesError::from)?;
             workspace.sequences.clear();
         }
 
+        self.last_sections = Some((section.ls_type, seq_section));
         Ok(())
     }
 
@@ -465,7 +559,7 @@ impl BlockDecoder {
     /// the deserialized header and the number of bytes read.
     pub fn read_block_header(
         &mut self,
-        mut r: impl Read,
+        r: impl Read,
     ) -> Result<(BlockHeader, u8), BlockHeaderReadError> {
         //match self.internal_state {
         //    DecoderState::ReadyToDecodeNextHeader => {/* Happy :) */},
@@ -473,77 +567,10 @@ impl BlockDecoder {
         //    DecoderState::ReadyToDecodeNextBody => return Err(format!("Cant decode next block header, while expecting to decode the body of the previous block. Results will be nonsense")),
         //}
 
-        r.read_exact(&mut self.header_buffer[0..3])?;
-
-        let btype = self.block_type()?;
-        if let BlockType::Reserved = btype {
-            return Err(BlockHeaderReadError::FoundReservedBlock);
-        }
-
-        let block_size = self.block_content_size()?;
-        let decompressed_size = match btype {
-            BlockType::Raw => block_size,
-            BlockType::RLE => block_size,
-            BlockType::Reserved => 0, //should be caught above, this is an error state
-            BlockType::Compressed => 0, //unknown but will be smaller than 128kb (or window_size if that is smaller than 128kb)
-        };
-        let content_size = match btype {
-            BlockType::Raw => block_size,
-            BlockType::Compressed => block_size,
-            BlockType::RLE => 1,
-            BlockType::Reserved => 0, //should be caught above, this is an error state
-        };
-
-        let last_block = self.is_last();
-
-        self.reset_buffer();
+        let header = BlockHeader::parse(r)?;
         self.internal_state = DecoderState::ReadyToDecodeNextBody;
 
         //just return 3. Blockheaders always take 3 bytes
-        Ok((
-            BlockHeader {
-                last_block,
-                block_type: btype,
-                decompressed_size,
-                content_size,
-            },
-            3,
-        ))
-    }
-
-    fn reset_buffer(&mut self) {
-        self.header_buffer[0] = 0;
-        self.header_buffer[1] = 0;
-        self.header_buffer[2] = 0;
-    }
-
-    fn is_last(&self) -> bool {
-        self.header_buffer[0] & 0x1 == 1
-    }
-
-    fn block_type(&self) -> Result<BlockType, BlockTypeError> {
-        let t = (self.header_buffer[0] >> 1) & 0x3;
-        match t {
-            0 => Ok(BlockType::Raw),
-            1 => Ok(BlockType::RLE),
-            2 => Ok(BlockType::Compressed),
-            3 => Ok(BlockType::Reserved),
-            other => Err(BlockTypeError::InvalidBlocktypeNumber { num: other }),
-        }
-    }
-
-    fn block_content_size(&self) -> Result<u32, BlockSizeError> {
-        let val = self.block_content_size_unchecked();
-        if val > ABSOLUTE_MAXIMUM_BLOCK_SIZE {
-            Err(BlockSizeError::BlockSizeTooLarge { size: val })
-        } else {
-            Ok(val)
-        }
-    }
-
-    fn block_content_size_unchecked(&self) -> u32 {
-        u32::from(self.header_buffer[0] >> 3) //push out type and last_block flags. Retain 5 bit
-            | (u32::from(self.header_buffer[1]) << 5)
-            | (u32::from(self.header_buffer[2]) << 13)
+        Ok((header, BlockHeader::SIZE as u8))
     }
 }
@@ -0,0 +1,222 @@
+//! Decompression of single blocks without a frame around them, see [BlockDecompressor].
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use super::block_decoder::{
+    self, BlockDecoder, BlockSizeError, DecompressBlockError, ABSOLUTE_MAXIMUM_BLOCK_SIZE,
+};
+use super::decodebuffer::DecodeBufferError;
+use super::dictionary::{Dictionary, DictionaryDecodeError};
+use super::scratch::DecoderScratch;
+use crate::blocks::block::{BlockHeader, BlockType};
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DecodeBlockError {
+    BlockSizeError(BlockSizeError),
+    DecompressBlockError(DecompressBlockError),
+    /// The decompressed block could not be added to the output or the history.
+    DecodeBufferError(DecodeBufferError),
+    /// The tables of the dictionary could not be copied.
+    DictionaryDecodeError(DictionaryDecodeError),
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeBlockError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DecodeBlockError::BlockSizeError(source) => Some(source),
+            DecodeBlockError::DecompressBlockError(source) => Some(source),
+            DecodeBlockError::DecodeBufferError(source) => Some(source),
+            DecodeBlockError::DictionaryDecodeError(source) => Some(source),
+        }
+    }
+}
+
+impl core::fmt::Display for DecodeBlockError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            DecodeBlockError::BlockSizeError(e) => write!(f, "{}", e),
+            DecodeBlockError::DecompressBlockError(e) => write!(f, "{}", e),
+            DecodeBlockError::DecodeBufferError(e) => write!(f, "{}", e),
+            DecodeBlockError::DictionaryDecodeError(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<BlockSizeError> for DecodeBlockError {
+    fn from(val: BlockSizeError) -> Self {
+        Self::BlockSizeError(val)
+    }
+}
+
+impl From<DecompressBlockError> for DecodeBlockError {
+    fn from(val: DecompressBlockError) -> Self {
+        Self::DecompressBlockError(val)
+    }
+}
+
+impl From<DecodeBufferError> for DecodeBlockError {
+    fn from(val: DecodeBufferError) -> Self {
+        Self::DecodeBufferError(val)
+    }
+}
+
+impl From<DictionaryDecodeError> for DecodeBlockError {
+    fn from(val: DictionaryDecodeError) -> Self {
+        Self::DictionaryDecodeError(val)
+    }
+}
+
+/// Decompresses blocks made by a [BlockCompressor](crate::encoding::BlockCompressor).
+///
+/// The blocks have to be decompressed in the order they were compressed in, with the same window
+/// size (and dictionary, if one was used). Blocks that were stored uncompressed have to be added
+/// with [BlockDecompressor::insert_block] in their place.
+pub struct BlockDecompressor {
+    decoder: BlockDecoder,
+    scratch: DecoderScratch,
+}
+
+impl BlockDecompressor {
+    /// Create a decompressor that keeps the last `window_size` bytes around for blocks to reference.
+    pub fn new(window_size: usize) -> Self {
+        Self {
+            decoder: block_decoder::new(),
+            scratch: DecoderScratch::new(window_size),
+        }
+    }
+
+    /// Create a decompressor for blocks that may also reference the content of `dictionary`.
+    ///
+    /// Fails if there is not enough memory to copy the tables of the dictionary.
+    pub fn with_dictionary(
+        window_size: usize,
+        dictionary: impl Into<Arc<Dictionary>>,
+    ) -> Result<Self, DecodeBlockError> {
+        let mut decompressor = Self::new(window_size);
+        decompressor.scratch.init_from_dict(&dictionary.into())?;
+        Ok(decompressor)
+    }
+
+    /// Decompress the compressed block `block` (without the block header) and append the content to `output`.
+    ///
+    /// Returns the number of bytes that were appended.
+    pub fn decompress_block(
+        &mut self,
+        block: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<usize, DecodeBlockError> {
+        if block.len() > ABSOLUTE_MAXIMUM_BLOCK_SIZE as usize {
+            return Err(BlockSizeError::BlockSizeTooLarge {
+                size: u32::try_from(block.len()).unwrap_or(u32::MAX),
+            }
+            .into());
+        }
+        let header = BlockHeader::new(false, BlockType::Compressed, block.len() as u32);
+        let before = self.scratch.buffer.len();
+        self.decoder
+            .decompress_block_from_slice(&header, &mut self.scratch, block)?;
+        let decompressed = self.scratch.buffer.len() - before;
+        self.scratch.buffer.copy_last_to(decompressed, output)?;
+        self.scratch.buffer.discard_to_window_size();
+        Ok(decompressed)
+    }
+
+    /// Add `data` to the history, for blocks that were stored uncompressed.
+    pub fn insert_block(&mut self, data: &[u8]) -> Result<(), DecodeBlockError> {
+        self.scratch.buffer.push(data)?;
+        self.scratch.buffer.discard_to_window_size();
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::BlockDecompressor;
+    use crate::decoding::dictionary::Dictionary;
+    use crate::encoding::BlockCompressor;
+    use alloc::vec::Vec;
+
+    /// Compress the blocks one by one, store the ones that don't compress as they are
+    /// and decompress all of them again
+    fn roundtrip(
+        blocks: &[&[u8]],
+        mut compressor: BlockCompressor,
+        mut decompressor: BlockDecompressor,
+    ) -> usize {
+        let mut stored = 0;
+        for block in blocks {
+            let mut compressed = Vec::new();
+            let mut decompressed = Vec::new();
+            if compressor.compress_block(block, &mut compressed).unwrap() {
+                assert!(compressed.len() < block.len());
+                let len = decompressor
+                    .decompress_block(&compressed, &mut decompressed)
+                    .unwrap();
+                assert_eq!(len, block.len());
+                assert_eq!(&decompressed, block);
+            } else {
+                assert!(compressed.is_empty());
+                decompressor.insert_block(block).unwrap();
+                stored += 1;
+            }
+        }
+        stored
+    }
+
+    #[test]
+    fn blocks_reference_earlier_blocks() {
+        let mut state = 0x2545_f491_u32;
+        let text: Vec<u8> = (0..120_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let random = &text[..100_000];
+        let blocks = [random, &text[50_000..], random, &text[..1000]];
+        let stored = roundtrip(
+            &blocks,
+            BlockCompressor::new(1 << 20),
+            BlockDecompressor::new(1 << 20),
+        );
+        // Only the first block has nothing to reference
+        assert_eq!(stored, 1);
+
+        // Only the last block finds a match within the window
+        let stored = roundtrip(
+            &blocks,
+            BlockCompressor::new(110_000),
+            BlockDecompressor::new(110_000),
+        );
+        assert_eq!(stored, 3);
+    }
+
+    #[test]
+    fn dictionary() {
+        let raw_dict = include_bytes!("../../dict_tests/dictionary");
+        let data = include_bytes!("../../dict_tests/files/ModemManager.service");
+        let dict = Dictionary::decode_dict(raw_dict).unwrap();
+
+        let mut plain = Vec::new();
+        assert!(BlockCompressor::new(1 << 17)
+            .compress_block(data, &mut plain)
+            .unwrap());
+        let mut compressed = Vec::new();
+        let mut compressor = BlockCompressor::with_dictionary(1 << 17, &dict);
+        assert!(compressor.compress_block(data, &mut compressed).unwrap());
+        assert!(compressed.len() < plain.len());
+
+        let mut decompressor = BlockDecompressor::with_dictionary(1 << 17, dict).unwrap();
+        let mut decompressed = Vec::new();
+        decompressor
+            .decompress_block(&compressed, &mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+    }
+}
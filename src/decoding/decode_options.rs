@@ -0,0 +1,53 @@
+//! Settings that control how a [StreamingDecoder](crate::streaming_decoder::StreamingDecoder) decodes.
+
+use crate::frame_decoder::DEFAULT_MAX_WINDOW_SIZE;
+
+/// What to do with the checksum at the end of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ChecksumPolicy {
+    /// Do not look at the checksum.
+    Ignore,
+    /// Compare the checksum with the decoded content and fail with
+    /// [FrameDecoderError::ChecksumMismatch](crate::frame_decoder::FrameDecoderError::ChecksumMismatch)
+    /// if they differ. Requires the `hash` feature.
+    Verify,
+    /// Compare the checksum with the decoded content, but keep going if they differ and record the
+    /// frame in [StreamingDecoder::checksum_mismatches](crate::streaming_decoder::StreamingDecoder::checksum_mismatches)
+    /// instead, for tools that want to salvage as much data as possible. Requires the `hash` feature.
+    Report,
+}
+
+/// Settings for decoding, see [StreamingDecoder::with_options](crate::streaming_decoder::StreamingDecoder::with_options).
+#[derive(Debug, Clone)]
+pub struct DecodeOptions {
+    /// The largest window size frames may request, see
+    /// [FrameDecoder::set_max_window_size](crate::frame_decoder::FrameDecoder::set_max_window_size).
+    pub max_window_size: u64,
+    /// Expect frames without the magic number in front, see
+    /// [FrameDecoder::set_magicless](crate::frame_decoder::FrameDecoder::set_magicless).
+    pub magicless: bool,
+    /// Decode frames whose dictionary was not provided as if it was empty, see
+    /// [FrameDecoder::set_decode_without_dict](crate::frame_decoder::FrameDecoder::set_decode_without_dict).
+    pub decode_without_dict: bool,
+    /// What to do with the checksums of frames that have one.
+    #[cfg(feature = "hash")]
+    pub checksum: ChecksumPolicy,
+    /// Keep decoding the frames that follow the first one, and skip skippable frames in between,
+    /// like the zstd command line tool does. Anything else that follows a frame is reported as
+    /// [FrameDecoderError::TrailingData](crate::frame_decoder::FrameDecoderError::TrailingData).
+    pub multi_frame: bool,
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self {
+            max_window_size: DEFAULT_MAX_WINDOW_SIZE,
+            magicless: false,
+            decode_without_dict: false,
+            #[cfg(feature = "hash")]
+            checksum: ChecksumPolicy::Ignore,
+            multi_frame: false,
+        }
+    }
+}
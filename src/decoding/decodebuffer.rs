@@ -1,13 +1,20 @@
 use crate::io::{Error, Read, Write};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::convert::TryFrom;
 #[cfg(feature = "hash")]
 use core::hash::Hasher;
+use core::mem::MaybeUninit;
 
+use super::block_decoder::ABSOLUTE_MAXIMUM_BLOCK_SIZE;
+use super::dictionary::Dictionary;
 use super::ringbuffer::RingBuffer;
+use crate::fallible::{AllocError, TryVec};
 
 pub struct DecodeBuffer {
     buffer: RingBuffer,
-    pub dict_content: Vec<u8>,
+    /// The dictionary of the frame, shared with the decoders of other frames
+    dictionary: Option<Arc<Dictionary>>,
 
     pub window_size: usize,
     total_output_counter: u64,
@@ -20,6 +27,7 @@ pub struct DecodeBuffer {
 pub enum DecodeBufferError {
     NotEnoughBytesInDictionary { got: usize, need: usize },
     OffsetTooBig { offset: usize, buf_len: usize },
+    OutOfMemory { size: usize },
 }
 
 #[cfg(feature = "std")]
@@ -38,10 +46,19 @@ impl core::fmt::Display for DecodeBufferError {
             DecodeBufferError::OffsetTooBig { offset, buf_len } => {
                 write!(f, "offset: {} bigger than buffer: {}", offset, buf_len,)
             }
+            DecodeBufferError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for the buffer", size)
+            }
         }
     }
 }
 
+impl From<AllocError> for DecodeBufferError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
 impl Read for DecodeBuffer {
     fn read(&mut self, target: &mut [u8]) -> Result<usize, Error> {
         let max_amount = self.can_drain_to_window_size().unwrap_or(0);
@@ -61,7 +78,7 @@ impl DecodeBuffer {
     pub fn new(window_size: usize) -> DecodeBuffer {
         DecodeBuffer {
             buffer: RingBuffer::new(),
-            dict_content: Vec::new(),
+            dictionary: None,
             window_size,
             total_output_counter: 0,
             #[cfg(feature = "hash")]
@@ -69,29 +86,91 @@ impl DecodeBuffer {
         }
     }
 
-    pub fn reset(&mut self, window_size: usize) {
+    /// Let the frame reference the content of `dictionary` in front of its own content.
+    pub fn set_dictionary(&mut self, dictionary: Option<Arc<Dictionary>>) {
+        self.dictionary = dictionary;
+    }
+
+    /// The content of the dictionary of the frame, empty without a dictionary.
+    pub fn dict_content(&self) -> &[u8] {
+        self.dictionary
+            .as_deref()
+            .map_or(&[], |dictionary| &dictionary.dict_content)
+    }
+
+    /// Clear the buffer and allocate space for a window of `window_size` bytes and a block behind
+    /// it, or only for the content if its size is known and smaller. Blocks are at most as large as
+    /// the window, so small windows only need space for small blocks.
+    ///
+    /// The space is allocated exactly, so large windows do not take up to twice their size.
+    pub fn reset(
+        &mut self,
+        window_size: usize,
+        content_size: Option<u64>,
+    ) -> Result<(), DecodeBufferError> {
         self.window_size = window_size;
         self.buffer.clear();
-        self.buffer.reserve(self.window_size);
-        self.dict_content.clear();
+        let block_size = window_size.min(ABSOLUTE_MAXIMUM_BLOCK_SIZE as usize);
+        let mut capacity = window_size.saturating_add(block_size);
+        if let Some(content_size) = content_size {
+            capacity = capacity.min(usize::try_from(content_size).unwrap_or(usize::MAX));
+        }
+        self.buffer
+            .try_reserve_exact(capacity)
+            .map_err(|size| DecodeBufferError::OutOfMemory { size })?;
+        self.dictionary = None;
         self.total_output_counter = 0;
         #[cfg(feature = "hash")]
         {
             self.hash = twox_hash::XxHash64::with_seed(0);
         }
+        Ok(())
     }
 
     pub fn len(&self) -> usize {
         self.buffer.len()
     }
 
+    /// The bytes in the buffer, which may wrap around the end of the ring buffer.
+    pub(crate) fn as_slices(&self) -> (&[u8], &[u8]) {
+        self.buffer.as_slices()
+    }
+
+    /// The bytes in the buffer as one slice, moving them if they wrap around the end of the ring
+    /// buffer.
+    pub(crate) fn make_contiguous(&mut self) -> &[u8] {
+        self.buffer.make_contiguous()
+    }
+
+    /// The number of bytes that were decoded into this buffer since the last reset.
+    pub(crate) fn total_output_counter(&self) -> u64 {
+        self.total_output_counter
+    }
+
+    /// Fill a buffer that was just reset with `content`, as if `total_output_counter` bytes had been
+    /// decoded into it and all but `content` had been drained already.
+    pub(crate) fn restore(
+        &mut self,
+        content: &[u8],
+        total_output_counter: u64,
+    ) -> Result<(), DecodeBufferError> {
+        self.buffer
+            .extend(content)
+            .map_err(|size| DecodeBufferError::OutOfMemory { size })?;
+        self.total_output_counter = total_output_counter;
+        Ok(())
+    }
+
     pub fn is_empty(&self) -> bool {
         self.buffer.is_empty()
     }
 
-    pub fn push(&mut self, data: &[u8]) {
-        self.buffer.extend(data);
+    pub fn push(&mut self, data: &[u8]) -> Result<(), DecodeBufferError> {
+        self.buffer
+            .extend(data)
+            .map_err(|size| DecodeBufferError::OutOfMemory { size })?;
         self.total_output_counter += data.len() as u64;
+        Ok(())
     }
 
     pub fn repeat(&mut self, offset: usize, match_length: usize) -> Result<(), DecodeBufferError> {
@@ -102,7 +181,9 @@ impl DecodeBuffer {
             let start_idx = buf_len - offset;
             let end_idx = start_idx + match_length;
 
-            self.buffer.reserve(match_length);
+            self.buffer
+                .try_reserve(match_length)
+                .map_err(|size| DecodeBufferError::OutOfMemory { size })?;
             if end_idx > buf_len {
                 // We need to copy in chunks.
                 self.repeat_in_chunks(offset, match_length, start_idx);
@@ -167,25 +248,33 @@ impl DecodeBuffer {
         if self.total_output_counter <= self.window_size as u64 {
             // at least part of that repeat is from the dictionary content
             let bytes_from_dict = offset - self.buffer.len();
+            let dict_content: &[u8] = self
+                .dictionary
+                .as_deref()
+                .map_or(&[], |dictionary| &dictionary.dict_content);
 
-            if bytes_from_dict > self.dict_content.len() {
+            if bytes_from_dict > dict_content.len() {
                 return Err(DecodeBufferError::NotEnoughBytesInDictionary {
-                    got: self.dict_content.len(),
+                    got: dict_content.len(),
                     need: bytes_from_dict,
                 });
             }
 
             if bytes_from_dict < match_length {
-                let dict_slice = &self.dict_content[self.dict_content.len() - bytes_from_dict..];
-                self.buffer.extend(dict_slice);
+                let dict_slice = &dict_content[dict_content.len() - bytes_from_dict..];
+                self.buffer
+                    .extend(dict_slice)
+                    .map_err(|size| DecodeBufferError::OutOfMemory { size })?;
 
                 self.total_output_counter += bytes_from_dict as u64;
                 return self.repeat(self.buffer.len(), match_length - bytes_from_dict);
             } else {
-                let low = self.dict_content.len() - bytes_from_dict;
+                let low = dict_content.len() - bytes_from_dict;
                 let high = low + match_length;
-                let dict_slice = &self.dict_content[low..high];
-                self.buffer.extend(dict_slice);
+                let dict_slice = &dict_content[low..high];
+                self.buffer
+                    .extend(dict_slice)
+                    .map_err(|size| DecodeBufferError::OutOfMemory { size })?;
             }
             Ok(())
         } else {
@@ -212,6 +301,9 @@ impl DecodeBuffer {
 
     /// Drain as much as possible while retaining enough so that decoding si still possible with the required window_size
     /// At best call only if can_drain_to_window_size reports a 'high' number of bytes to reduce allocations
+    ///
+    /// Like collecting into a `Vec`, this aborts if there is not enough memory, see the Readme.
+    #[allow(clippy::disallowed_methods)]
     pub fn drain_to_window_size(&mut self) -> Option<Vec<u8>> {
         //TODO investigate if it is possible to return the std::vec::Drain iterator directly without collecting here
         match self.can_drain_to_window_size() {
@@ -235,7 +327,39 @@ impl DecodeBuffer {
         }
     }
 
+    /// Append the last `amount` bytes of the buffer to `target`, without draining them
+    pub fn copy_last_to(
+        &self,
+        amount: usize,
+        target: &mut Vec<u8>,
+    ) -> Result<(), DecodeBufferError> {
+        let (slice1, slice2) = self.buffer.as_slices();
+        target.try_room(amount)?;
+        if amount > slice2.len() {
+            target.try_extend_from_slice(&slice1[slice1.len() + slice2.len() - amount..])?;
+            target.try_extend_from_slice(slice2)?;
+        } else {
+            target.try_extend_from_slice(&slice2[slice2.len() - amount..])?;
+        }
+        Ok(())
+    }
+
+    /// Drop everything that is not needed to keep the window, without draining it anywhere
+    pub fn discard_to_window_size(&mut self) {
+        if let Some(amount) = self.can_drain_to_window_size() {
+            self.buffer.drop_first_n(amount);
+        }
+    }
+
+    /// Drop the whole buffer, without draining it anywhere
+    pub fn discard(&mut self) {
+        self.buffer.clear();
+    }
+
     /// drain the buffer completely
+    ///
+    /// Like collecting into a `Vec`, this aborts if there is not enough memory, see the Readme.
+    #[allow(clippy::disallowed_methods)]
     pub fn drain(&mut self) -> Vec<u8> {
         let (slice1, slice2) = self.buffer.as_slices();
         #[cfg(feature = "hash")]
@@ -271,6 +395,33 @@ impl DecodeBuffer {
     /// Semantics of write_bytes:
     /// Should dump as many of the provided bytes as possible to whatever sink until no bytes are left or an error is encountered
     /// Return how many bytes have actually been dumped to the sink.
+    /// Like [Read::read], but `target` does not have to be initialized. Returns the number of bytes
+    /// that were written to the start of `target`.
+    pub fn read_uninit(&mut self, target: &mut [MaybeUninit<u8>]) -> usize {
+        let max_amount = self.can_drain_to_window_size().unwrap_or(0);
+        self.drain_to_uninit(max_amount.min(target.len()), target)
+    }
+
+    /// Like [DecodeBuffer::read_all], but `target` does not have to be initialized. Returns the
+    /// number of bytes that were written to the start of `target`.
+    pub fn read_all_uninit(&mut self, target: &mut [MaybeUninit<u8>]) -> usize {
+        let amount = self.buffer.len().min(target.len());
+        self.drain_to_uninit(amount, target)
+    }
+
+    fn drain_to_uninit(&mut self, amount: usize, target: &mut [MaybeUninit<u8>]) -> usize {
+        let mut written = 0;
+        // Writing into the target never fails
+        let _ = self.drain_to(amount, |buf| {
+            for (dst, src) in target[written..].iter_mut().zip(buf) {
+                dst.write(*src);
+            }
+            written += buf.len();
+            (buf.len(), Ok(()))
+        });
+        written
+    }
+
     fn drain_to(
         &mut self,
         amount: usize,
@@ -383,7 +534,7 @@ mod tests {
         };
 
         let mut decode_buf = DecodeBuffer::new(100);
-        decode_buf.push(b"0123456789");
+        decode_buf.push(b"0123456789").unwrap();
         decode_buf.repeat(10, 90).unwrap();
         let repeats = 1000;
         for _ in 0..repeats {
@@ -433,7 +584,7 @@ mod tests {
         };
 
         let mut decode_buf = DecodeBuffer::new(100);
-        decode_buf.push(b"0123456789");
+        decode_buf.push(b"0123456789").unwrap();
         decode_buf.repeat(10, 90).unwrap();
         let repeats = 1000;
         for _ in 0..repeats {
@@ -1,8 +1,8 @@
 use alloc::vec::Vec;
-use core::convert::TryInto;
 
 use crate::decoding::scratch::FSEScratch;
 use crate::decoding::scratch::HuffmanScratch;
+use crate::fallible::{try_to_vec, AllocError};
 use crate::fse::FSETableError;
 use crate::huff0::HuffmanTableError;
 
@@ -37,12 +37,34 @@ pub struct Dictionary {
     pub offset_hist: [u32; 3],
 }
 
+impl core::fmt::Debug for Dictionary {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // The entropy tables are large and not interesting to look at
+        f.debug_struct("Dictionary")
+            .field("id", &self.id)
+            .field("dict_content_len", &self.dict_content.len())
+            .field("offset_hist", &self.offset_hist)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum DictionaryDecodeError {
-    BadMagicNum { got: [u8; 4] },
+    BadMagicNum {
+        got: [u8; 4],
+    },
     FSETableError(FSETableError),
     HuffmanTableError(HuffmanTableError),
+    /// The dictionary ends before its header or the offset history.
+    NotEnoughBytes {
+        have: usize,
+        need: usize,
+    },
+    /// There is not enough memory for the content of the dictionary.
+    OutOfMemory {
+        size: usize,
+    },
 }
 
 #[cfg(feature = "std")]
@@ -68,10 +90,26 @@ impl core::fmt::Display for DictionaryDecodeError {
             }
             DictionaryDecodeError::FSETableError(e) => write!(f, "{:?}", e),
             DictionaryDecodeError::HuffmanTableError(e) => write!(f, "{:?}", e),
+            DictionaryDecodeError::NotEnoughBytes { have, need } => {
+                write!(
+                    f,
+                    "The dictionary is too short, {} bytes are needed but only {} are left",
+                    need, have,
+                )
+            }
+            DictionaryDecodeError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for the dictionary", size)
+            }
         }
     }
 }
 
+impl From<AllocError> for DictionaryDecodeError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
 impl From<FSETableError> for DictionaryDecodeError {
     fn from(val: FSETableError) -> Self {
         Self::FSETableError(val)
@@ -88,6 +126,22 @@ impl From<HuffmanTableError> for DictionaryDecodeError {
 pub const MAGIC_NUM: [u8; 4] = [0x37, 0xA4, 0x30, 0xEC];
 
 impl Dictionary {
+    /// Create a "raw content" dictionary, which only consists of content and has no entropy tables.
+    ///
+    /// Frames that use such a dictionary (for example patches created with
+    /// [FrameCompressor::set_patch_reference](crate::encoding::FrameCompressor::set_patch_reference))
+    /// usually do not carry a dictionary ID. Add the dictionary with any `id` and select it with
+    /// [FrameDecoder::force_dict](crate::frame_decoder::FrameDecoder::force_dict) after the frame header was read.
+    pub fn from_raw_content(id: u32, dict_content: Vec<u8>) -> Dictionary {
+        Dictionary {
+            id,
+            fse: FSEScratch::new(),
+            huf: HuffmanScratch::new(),
+            dict_content,
+            offset_hist: [1, 4, 8],
+        }
+    }
+
     /// Parses the dictionary from `raw` and set the tables
     /// it returns the dict_id for checking with the frame's `dict_id``
     pub fn decode_dict(raw: &[u8]) -> Result<Dictionary, DictionaryDecodeError> {
@@ -99,14 +153,11 @@ impl Dictionary {
             offset_hist: [2, 4, 8],
         };
 
-        let magic_num: [u8; 4] = raw[..4].try_into().expect("optimized away");
+        let [magic_num, dict_id] = read_u32s(raw)?;
         if magic_num != MAGIC_NUM {
             return Err(DictionaryDecodeError::BadMagicNum { got: magic_num });
         }
-
-        let dict_id = raw[4..8].try_into().expect("optimized away");
-        let dict_id = u32::from_le_bytes(dict_id);
-        new_dict.id = dict_id;
+        new_dict.id = u32::from_le_bytes(dict_id);
 
         let raw_tables = &raw[8..];
 
@@ -131,22 +182,25 @@ impl Dictionary {
         )?;
         let raw_tables = &raw_tables[ll_size..];
 
-        let offset1 = raw_tables[0..4].try_into().expect("optimized away");
-        let offset1 = u32::from_le_bytes(offset1);
-
-        let offset2 = raw_tables[4..8].try_into().expect("optimized away");
-        let offset2 = u32::from_le_bytes(offset2);
-
-        let offset3 = raw_tables[8..12].try_into().expect("optimized away");
-        let offset3 = u32::from_le_bytes(offset3);
-
-        new_dict.offset_hist[0] = offset1;
-        new_dict.offset_hist[1] = offset2;
-        new_dict.offset_hist[2] = offset3;
+        new_dict.offset_hist = read_u32s(raw_tables)?.map(u32::from_le_bytes);
 
         let raw_content = &raw_tables[12..];
-        new_dict.dict_content.extend(raw_content);
+        new_dict.dict_content = try_to_vec(raw_content)?;
 
         Ok(new_dict)
     }
 }
+
+/// The first `N` groups of 4 bytes of `raw`.
+fn read_u32s<const N: usize>(raw: &[u8]) -> Result<[[u8; 4]; N], DictionaryDecodeError> {
+    let mut words = [[0; 4]; N];
+    let mut chunks = raw.chunks_exact(4);
+    for word in &mut words {
+        let chunk = chunks.next().ok_or(DictionaryDecodeError::NotEnoughBytes {
+            have: raw.len(),
+            need: N * 4,
+        })?;
+        word.copy_from_slice(chunk);
+    }
+    Ok(words)
+}
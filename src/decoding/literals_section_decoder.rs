@@ -4,6 +4,7 @@
 use super::super::blocks::literals_section::{LiteralsSection, LiteralsSectionType};
 use super::bit_reader_reverse::{BitReaderReversed, GetBitsError};
 use super::scratch::HuffmanScratch;
+use crate::fallible::{AllocError, TryVec};
 use crate::huff0::{HuffmanDecoder, HuffmanDecoderError, HuffmanTableError};
 use alloc::vec::Vec;
 
@@ -16,11 +17,28 @@ pub enum DecompressLiteralsError {
     HuffmanTableError(HuffmanTableError),
     HuffmanDecoderError(HuffmanDecoderError),
     UninitializedHuffmanTable,
-    MissingBytesForJumpHeader { got: usize },
-    MissingBytesForLiterals { got: usize, needed: usize },
-    ExtraPadding { skipped_bits: i32 },
-    BitstreamReadMismatch { read_til: isize, expected: isize },
-    DecodedLiteralCountMismatch { decoded: usize, expected: usize },
+    MissingBytesForJumpHeader {
+        got: usize,
+    },
+    MissingBytesForLiterals {
+        got: usize,
+        needed: usize,
+    },
+    ExtraPadding {
+        skipped_bits: i32,
+    },
+    BitstreamReadMismatch {
+        read_til: isize,
+        expected: isize,
+    },
+    DecodedLiteralCountMismatch {
+        decoded: usize,
+        expected: usize,
+    },
+    /// There is not enough memory for the literals.
+    OutOfMemory {
+        size: usize,
+    },
 }
 
 #[cfg(feature = "std")]
@@ -86,10 +104,19 @@ impl core::fmt::Display for DecompressLiteralsError {
                     decoded, expected,
                 )
             }
+            DecompressLiteralsError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for the literals", size)
+            }
         }
     }
 }
 
+impl From<AllocError> for DecompressLiteralsError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
 impl From<HuffmanDecoderError> for DecompressLiteralsError {
     fn from(val: HuffmanDecoderError) -> Self {
         Self::HuffmanDecoderError(val)
@@ -117,11 +144,11 @@ pub fn decode_literals(
 ) -> Result<u32, DecompressLiteralsError> {
     match section.ls_type {
         LiteralsSectionType::Raw => {
-            target.extend(&source[0..section.regenerated_size as usize]);
+            target.try_extend_from_slice(&source[0..section.regenerated_size as usize])?;
             Ok(section.regenerated_size)
         }
         LiteralsSectionType::RLE => {
-            target.resize(target.len() + section.regenerated_size as usize, source[0]);
+            target.try_resize(target.len() + section.regenerated_size as usize, source[0])?;
             Ok(1)
         }
         LiteralsSectionType::Compressed | LiteralsSectionType::Treeless => {
@@ -148,7 +175,7 @@ fn decompress_literals(
     let compressed_size = section.compressed_size.ok_or(err::MissingCompressedSize)? as usize;
     let num_streams = section.num_streams.ok_or(err::MissingNumStreams)?;
 
-    target.reserve(section.regenerated_size as usize);
+    target.try_room(section.regenerated_size as usize)?;
     let source = &source[0..compressed_size];
     let mut bytes_read = 0;
 
@@ -158,10 +185,8 @@ fn decompress_literals(
             bytes_read += scratch.table.build_decoder(source)?;
             vprintln!("Built huffman table using {} bytes", bytes_read);
         }
-        LiteralsSectionType::Treeless => {
-            if scratch.table.max_num_bits == 0 {
-                return Err(err::UninitializedHuffmanTable);
-            }
+        LiteralsSectionType::Treeless if scratch.table.max_num_bits == 0 => {
+            return Err(err::UninitializedHuffmanTable);
         }
         _ => { /* nothing to do, huffman tree has been provided by previous block */ }
     }
@@ -211,7 +236,7 @@ fn decompress_literals(
             decoder.init_state(&mut br);
 
             while br.bits_remaining() > -(scratch.table.max_num_bits as isize) {
-                target.push(decoder.decode_symbol());
+                target.try_push(decoder.decode_symbol())?;
                 decoder.next_state(&mut br);
             }
             if br.bits_remaining() != -(scratch.table.max_num_bits as isize) {
@@ -224,8 +249,7 @@ fn decompress_literals(
 
         bytes_read += source.len() as u32;
     } else {
-        //just decode the one stream
-        assert!(num_streams == 1);
+        //just decode the one stream, parsed sections have either one or four
         let mut decoder = HuffmanDecoder::new(&scratch.table);
         let mut br = BitReaderReversed::new(source);
         let mut skipped_bits = 0;
@@ -242,7 +266,7 @@ fn decompress_literals(
         }
         decoder.init_state(&mut br);
         while br.bits_remaining() > -(scratch.table.max_num_bits as isize) {
-            target.push(decoder.decode_symbol());
+            target.try_push(decoder.decode_symbol())?;
             decoder.next_state(&mut br);
         }
         bytes_read += source.len() as u32;
@@ -4,6 +4,8 @@
 pub mod bit_reader;
 pub mod bit_reader_reverse;
 pub mod block_decoder;
+pub mod block_decompressor;
+pub mod decode_options;
 pub mod decodebuffer;
 pub mod dictionary;
 pub mod literals_section_decoder;
@@ -12,3 +14,5 @@ mod ringbuffer;
 pub mod scratch;
 pub mod sequence_execution;
 pub mod sequence_section_decoder;
+pub(crate) mod snapshot;
+pub mod table_vec;
@@ -1,3 +1,5 @@
+#[cfg(test)]
+use alloc::alloc::handle_alloc_error;
 use alloc::alloc::{alloc, dealloc};
 use core::{alloc::Layout, ptr::NonNull, slice};
 
@@ -19,10 +21,12 @@ pub struct RingBuffer {
     tail: usize,
 }
 
-// SAFETY: RingBuffer does not hold any thread specific values -> it can be sent to another thread -> RingBuffer is Send
+// SAFETY: RingBuffer owns its allocation like a Vec<u8> does and does not hold any thread specific
+// values -> it can be sent to another thread -> RingBuffer is Send
 unsafe impl Send for RingBuffer {}
 
-// SAFETY: Ringbuffer does not provide unsyncronized interior mutability which makes &RingBuffer Send -> RingBuffer is Sync
+// SAFETY: Ringbuffer does not provide unsyncronized interior mutability, all methods that write to
+// the buffer take &mut self -> &RingBuffer is Send -> RingBuffer is Sync
 unsafe impl Sync for RingBuffer {}
 
 impl RingBuffer {
@@ -63,27 +67,70 @@ impl RingBuffer {
     }
 
     /// Ensure that there's space for `amount` elements in the buffer.
+    #[cfg(test)]
     pub fn reserve(&mut self, amount: usize) {
+        if let Err(size) = self.try_reserve(amount) {
+            // Like the collections of alloc, running out of memory aborts
+            handle_alloc_error(Layout::array::<u8>(size).unwrap_or(Layout::new::<u8>()));
+        }
+    }
+
+    /// Ensure that there's space for `amount` elements in the buffer.
+    ///
+    /// Returns the size of the allocation that failed if there is not enough memory.
+    pub fn try_reserve(&mut self, amount: usize) -> Result<(), usize> {
         let free = self.free();
         if free >= amount {
-            return;
+            return Ok(());
+        }
+
+        self.reserve_amortized(amount - free)
+    }
+
+    /// Ensure that there's space for `amount` elements in the buffer, without rounding the capacity
+    /// up to the next power of two like [RingBuffer::try_reserve] does. For large buffers whose
+    /// size is known in advance, where rounding up could almost double the memory.
+    ///
+    /// Returns the size of the allocation that failed if there is not enough memory.
+    pub fn try_reserve_exact(&mut self, amount: usize) -> Result<(), usize> {
+        let free = self.free();
+        if free >= amount {
+            return Ok(());
         }
 
-        self.reserve_amortized(amount - free);
+        // Always have at least 1 unused element as the sentinel.
+        let new_cap = self
+            .len()
+            .checked_add(amount)
+            .and_then(|cap| cap.checked_add(1))
+            .ok_or(usize::MAX)?;
+        self.grow(new_cap)
     }
 
     #[inline(never)]
     #[cold]
-    fn reserve_amortized(&mut self, amount: usize) {
+    fn reserve_amortized(&mut self, amount: usize) -> Result<(), usize> {
+        // Always have at least 1 unused element as the sentinel.
+        let new_cap = self
+            .cap
+            .checked_add(amount)
+            .and_then(usize::checked_next_power_of_two)
+            .map(|cap| usize::max(self.cap.next_power_of_two(), cap))
+            .and_then(|cap| cap.checked_add(1))
+            .ok_or(usize::MAX)?;
+        self.grow(new_cap)
+    }
+
+    /// Move the content into a new allocation of `new_cap` bytes, which has to be larger than the
+    /// content.
+    #[inline(never)]
+    #[cold]
+    #[allow(clippy::disallowed_macros)]
+    fn grow(&mut self, new_cap: usize) -> Result<(), usize> {
+        debug_assert!(new_cap > self.len());
         // SAFETY: if we were succesfully able to construct this layout when we allocated then it's also valid do so now
         let current_layout = unsafe { Layout::array::<u8>(self.cap).unwrap_unchecked() };
 
-        // Always have at least 1 unused element as the sentinel.
-        let new_cap = usize::max(
-            self.cap.next_power_of_two(),
-            (self.cap + amount).next_power_of_two(),
-        ) + 1;
-
         // Check that the capacity isn't bigger than isize::MAX, which is the max allowed by LLVM, or that
         // we are on a >= 64 bit system which will never allow that much memory to be allocated
         #[allow(clippy::assertions_on_constants)]
@@ -91,15 +138,13 @@ impl RingBuffer {
             debug_assert!(usize::BITS >= 64 || new_cap < isize::MAX as usize);
         }
 
-        let new_layout = Layout::array::<u8>(new_cap)
-            .unwrap_or_else(|_| panic!("Could not create layout for u8 array of size {}", new_cap));
+        let new_layout = Layout::array::<u8>(new_cap).map_err(|_| new_cap)?;
 
-        // alloc the new memory region and panic if alloc fails
-        // TODO maybe rework this to generate an error?
+        // alloc the new memory region and report if alloc fails
         let new_buf = unsafe {
             let new_buf = alloc(new_layout);
 
-            NonNull::new(new_buf).expect("Allocating new space for the ringbuffer failed")
+            NonNull::new(new_buf).ok_or(new_cap)?
         };
 
         // If we had data before, copy it over to the newly alloced memory region
@@ -124,16 +169,21 @@ impl RingBuffer {
         // SAFETY: Upholds invariant 1: the buffer was just allocated correctly
         self.buf = new_buf;
         self.cap = new_cap;
+        Ok(())
     }
 
+    /// Append `byte` to the end of `self`.
+    ///
+    /// Returns the size of the allocation that failed if there is not enough memory.
     #[allow(dead_code)]
-    pub fn push_back(&mut self, byte: u8) {
-        self.reserve(1);
+    pub fn push_back(&mut self, byte: u8) -> Result<(), usize> {
+        self.try_reserve(1)?;
 
         // SAFETY: Upholds invariant 2 by writing initialized memory
         unsafe { self.buf.as_ptr().add(self.tail).write(byte) };
         // SAFETY: Upholds invariant 3 by wrapping `tail` around
         self.tail = (self.tail + 1) % self.cap;
+        Ok(())
     }
 
     /// Fetch the byte stored at the selected index from the buffer, returning it, or
@@ -150,16 +200,19 @@ impl RingBuffer {
         }
     }
     /// Append the provided data to the end of `self`.
-    pub fn extend(&mut self, data: &[u8]) {
+    ///
+    /// Returns the size of the allocation that failed if there is not enough memory.
+    #[allow(clippy::disallowed_macros)]
+    pub fn extend(&mut self, data: &[u8]) -> Result<(), usize> {
         let len = data.len();
         let ptr = data.as_ptr();
         if len == 0 {
-            return;
+            return Ok(());
         }
 
-        self.reserve(len);
+        self.try_reserve(len)?;
 
-        debug_assert!(self.len() + len <= self.cap - 1);
+        debug_assert!(self.len() + len < self.cap);
         debug_assert!(self.free() >= len, "free: {} len: {}", self.free(), len);
 
         let ((f1_ptr, f1_len), (f2_ptr, f2_len)) = self.free_slice_parts();
@@ -183,10 +236,12 @@ impl RingBuffer {
         }
         // SAFETY: Upholds invariant 3 by wrapping `tail` around.
         self.tail = (self.tail + len) % self.cap;
+        Ok(())
     }
 
     /// Advance head past `amount` elements, effectively removing
     /// them from the buffer.
+    #[allow(clippy::disallowed_macros)]
     pub fn drop_first_n(&mut self, amount: usize) {
         debug_assert!(amount <= self.len());
         let amount = usize::min(amount, self.len());
@@ -235,6 +290,31 @@ impl RingBuffer {
         }
     }
 
+    /// Move the content to the start of the buffer if it wraps around its end, and return all of it
+    /// as one slice.
+    ///
+    /// The content is rotated in place, so this does not allocate.
+    pub fn make_contiguous(&mut self) -> &[u8] {
+        if self.as_slices().1.is_empty() {
+            return self.as_slices().0;
+        }
+        let len = self.len();
+        unsafe {
+            // SAFETY: The content wraps around, so the free space is `tail..head`. Initializing it
+            // makes all `cap` bytes of the allocation initialized, which rotates the content
+            // `head..cap, 0..tail` to `0..len`
+            self.buf
+                .as_ptr()
+                .add(self.tail)
+                .write_bytes(0, self.head - self.tail);
+            slice::from_raw_parts_mut(self.buf.as_ptr(), self.cap).rotate_left(self.head);
+        }
+        // SAFETY: Upholds invariants 2 and 3, `0..len` now holds the content and `len < cap`
+        self.head = 0;
+        self.tail = len;
+        self.as_slices().0
+    }
+
     // SAFETY: other code relies on this producing the lengths of free zones
     // at the beginning/end of the buffer. Everything else must be initialized
     /// Returns the size of the two unoccupied sections of memory used by the buffer.
@@ -267,7 +347,7 @@ impl RingBuffer {
     }
 
     /// Copies elements from the provided range to the end of the buffer.
-    #[allow(dead_code)]
+    #[cfg(test)]
     pub fn extend_from_within(&mut self, start: usize, len: usize) {
         if start + len > self.len() {
             panic!(
@@ -353,6 +433,7 @@ impl RingBuffer {
     /// SAFETY:
     /// Needs start + len <= self.len()
     /// And more then len reserved space
+    #[allow(clippy::disallowed_macros)]
     pub unsafe fn extend_from_within_unchecked_branchless(&mut self, start: usize, len: usize) {
         // data slices in raw parts
         let ((s1_ptr, s1_len), (s2_ptr, s2_len)) = self.data_slice_parts();
@@ -437,6 +518,7 @@ impl Drop for RingBuffer {
 ///
 /// If that isn't possible we just fall back to ptr::copy_nonoverlapping
 #[inline(always)]
+#[allow(clippy::disallowed_macros)]
 unsafe fn copy_bytes_overshooting(
     src: (*const u8, usize),
     dst: (*mut u8, usize),
@@ -636,7 +718,7 @@ mod tests {
         rb.reserve(15);
         assert_eq!(17, rb.cap);
 
-        rb.extend(b"0123456789");
+        rb.extend(b"0123456789").unwrap();
         assert_eq!(rb.len(), 10);
         assert_eq!(rb.as_slices().0, b"0123456789");
         assert_eq!(rb.as_slices().1, b"");
@@ -666,7 +748,7 @@ mod tests {
         assert_eq!(rb.as_slices().0, b"5");
         assert_eq!(rb.as_slices().1, b"6");
 
-        rb.extend(b"0123456789");
+        rb.extend(b"0123456789").unwrap();
         assert_eq!(rb.len(), 12);
         assert_eq!(rb.as_slices().0, b"5");
         assert_eq!(rb.as_slices().1, b"60123456789");
@@ -676,10 +758,14 @@ mod tests {
         assert_eq!(rb.as_slices().0, b"9");
         assert_eq!(rb.as_slices().1, b"");
 
-        rb.extend(b"0123456789");
+        rb.extend(b"0123456789").unwrap();
         assert_eq!(rb.len(), 11);
         assert_eq!(rb.as_slices().0, b"9012345");
         assert_eq!(rb.as_slices().1, b"6789");
+
+        assert_eq!(rb.make_contiguous(), b"90123456789");
+        assert_eq!(rb.as_slices().1, b"");
+        assert_eq!(rb.make_contiguous(), b"90123456789");
     }
 
     #[test]
@@ -688,14 +774,14 @@ mod tests {
         let mut rb = RingBuffer::new();
         rb.reserve(16);
         assert_eq!(17, rb.cap);
-        rb.extend(b"0123456789012345");
+        rb.extend(b"0123456789012345").unwrap();
         assert_eq!(17, rb.cap);
         assert_eq!(16, rb.len());
         assert_eq!(0, rb.free());
         rb.drop_first_n(16);
         assert_eq!(0, rb.len());
         assert_eq!(16, rb.free());
-        rb.extend(b"0123456789012345");
+        rb.extend(b"0123456789012345").unwrap();
         assert_eq!(16, rb.len());
         assert_eq!(0, rb.free());
         assert_eq!(17, rb.cap);
@@ -705,9 +791,9 @@ mod tests {
         rb.clear();
 
         // data in both slices and then reserve
-        rb.extend(b"0123456789012345");
+        rb.extend(b"0123456789012345").unwrap();
         rb.drop_first_n(8);
-        rb.extend(b"67890123");
+        rb.extend(b"67890123").unwrap();
         assert_eq!(16, rb.len());
         assert_eq!(0, rb.free());
         assert_eq!(17, rb.cap);
@@ -723,7 +809,7 @@ mod tests {
         rb.clear();
 
         // fill exactly, then extend from within
-        rb.extend(b"0123456789012345");
+        rb.extend(b"0123456789012345").unwrap();
         rb.extend_from_within(0, 16);
         assert_eq!(32, rb.len());
         assert_eq!(0, rb.free());
@@ -734,7 +820,7 @@ mod tests {
         // extend from within cases
         let mut rb = RingBuffer::new();
         rb.reserve(8);
-        rb.extend(b"01234567");
+        rb.extend(b"01234567").unwrap();
         rb.drop_first_n(5);
         rb.extend_from_within(0, 3);
         assert_eq!(4, rb.as_slices().0.len());
@@ -759,13 +845,43 @@ mod tests {
 
         let mut rb = RingBuffer::new();
         rb.reserve(8);
-        rb.extend(b"11111111");
+        rb.extend(b"11111111").unwrap();
         rb.drop_first_n(7);
-        rb.extend(b"111");
+        rb.extend(b"111").unwrap();
         assert_eq!(2, rb.as_slices().0.len());
         assert_eq!(2, rb.as_slices().1.len());
         rb.extend_from_within(0, 4);
         assert_eq!(b"11", rb.as_slices().0);
         assert_eq!(b"111111", rb.as_slices().1);
     }
+
+    #[test]
+    fn try_reserve_too_much() {
+        let mut rb = RingBuffer::new();
+        rb.extend(b"0123").unwrap();
+        assert!(rb.try_reserve(isize::MAX as usize).is_err());
+        // The buffer is still usable
+        rb.extend(b"4567").unwrap();
+        assert_eq!(rb.as_slices().0, b"01234567");
+    }
+
+    #[test]
+    fn try_reserve_exact() {
+        let mut rb = RingBuffer::new();
+        rb.try_reserve_exact(1000).unwrap();
+        assert_eq!(rb.cap, 1001);
+        rb.extend(&[1; 600]).unwrap();
+        rb.drop_first_n(500);
+        rb.extend(&[2; 600]).unwrap();
+        // The content wraps around and is moved into the new allocation in order
+        rb.try_reserve_exact(2000).unwrap();
+        assert_eq!(rb.cap, 2701);
+        assert_eq!(rb.len(), 700);
+        assert_eq!(rb.as_slices().0[..100], [1; 100]);
+        assert_eq!(rb.as_slices().0[100..], [2; 600]);
+        // Enough space is left already
+        rb.try_reserve_exact(10).unwrap();
+        assert_eq!(rb.cap, 2701);
+        assert!(rb.try_reserve_exact(usize::MAX).is_err());
+    }
 }
@@ -1,10 +1,11 @@
 //! Structures that wrap around various decoders to make decoding easier.
 
-use super::super::blocks::sequence_section::Sequence;
-use super::decodebuffer::DecodeBuffer;
-use crate::decoding::dictionary::Dictionary;
-use crate::fse::FSETable;
+use super::super::blocks::sequence_section::{OffsetHistory, Sequence};
+use super::decodebuffer::{DecodeBuffer, DecodeBufferError};
+use crate::decoding::dictionary::{Dictionary, DictionaryDecodeError};
+use crate::fse::{FSETable, FSETableError};
 use crate::huff0::HuffmanTable;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use crate::blocks::sequence_section::{
@@ -19,7 +20,7 @@ pub struct DecoderScratch {
     pub fse: FSEScratch,
 
     pub buffer: DecodeBuffer,
-    pub offset_hist: [u32; 3],
+    pub offset_hist: OffsetHistory,
 
     pub literals_buffer: Vec<u8>,
     pub sequences: Vec<Sequence>,
@@ -41,7 +42,7 @@ impl DecoderScratch {
                 ml_rle: None,
             },
             buffer: DecodeBuffer::new(window_size),
-            offset_hist: [1, 4, 8],
+            offset_hist: OffsetHistory::new(),
 
             block_content_buffer: Vec::new(),
             literals_buffer: Vec::new(),
@@ -49,14 +50,16 @@ impl DecoderScratch {
         }
     }
 
-    pub fn reset(&mut self, window_size: usize) {
-        self.offset_hist = [1, 4, 8];
+    pub fn reset(
+        &mut self,
+        window_size: usize,
+        content_size: Option<u64>,
+    ) -> Result<(), DecodeBufferError> {
+        self.offset_hist.reset();
         self.literals_buffer.clear();
         self.sequences.clear();
         self.block_content_buffer.clear();
 
-        self.buffer.reset(window_size);
-
         self.fse.literal_lengths.reset();
         self.fse.match_lengths.reset();
         self.fse.offsets.reset();
@@ -65,16 +68,16 @@ impl DecoderScratch {
         self.fse.of_rle = None;
 
         self.huf.table.reset();
+
+        self.buffer.reset(window_size, content_size)
     }
 
-    pub fn init_from_dict(&mut self, dict: &Dictionary) {
-        self.fse.reinit_from(&dict.fse);
-        self.huf.table.reinit_from(&dict.huf.table);
-        self.offset_hist = dict.offset_hist;
-        self.buffer.dict_content.clear();
-        self.buffer
-            .dict_content
-            .extend_from_slice(&dict.dict_content);
+    pub fn init_from_dict(&mut self, dict: &Arc<Dictionary>) -> Result<(), DictionaryDecodeError> {
+        self.fse.reinit_from(&dict.fse)?;
+        self.huf.table.reinit_from(&dict.huf.table)?;
+        self.offset_hist = OffsetHistory::from_offsets(dict.offset_hist);
+        self.buffer.set_dictionary(Some(dict.clone()));
+        Ok(())
     }
 }
 
@@ -117,13 +120,14 @@ impl FSEScratch {
         }
     }
 
-    pub fn reinit_from(&mut self, other: &Self) {
-        self.offsets.reinit_from(&other.offsets);
-        self.literal_lengths.reinit_from(&other.literal_lengths);
-        self.match_lengths.reinit_from(&other.match_lengths);
+    pub fn reinit_from(&mut self, other: &Self) -> Result<(), FSETableError> {
+        self.offsets.reinit_from(&other.offsets)?;
+        self.literal_lengths.reinit_from(&other.literal_lengths)?;
+        self.match_lengths.reinit_from(&other.match_lengths)?;
         self.of_rle = other.of_rle;
         self.ll_rle = other.ll_rle;
         self.ml_rle = other.ml_rle;
+        Ok(())
     }
 }
 
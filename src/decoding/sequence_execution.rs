@@ -45,6 +45,7 @@ impl From<DecodeBufferError> for ExecuteSequencesError {
 }
 
 /// Take the provided decoder and execute the sequences stored within
+#[allow(clippy::disallowed_macros)]
 pub fn execute_sequences(scratch: &mut DecoderScratch) -> Result<(), ExecuteSequencesError> {
     let mut literals_copy_counter = 0;
     let old_buffer_size = scratch.buffer.len();
@@ -64,10 +65,10 @@ pub fn execute_sequences(scratch: &mut DecoderScratch) -> Result<(), ExecuteSequ
             let literals = &scratch.literals_buffer[literals_copy_counter..high];
             literals_copy_counter += seq.ll as usize;
 
-            scratch.buffer.push(literals);
+            scratch.buffer.push(literals)?;
         }
 
-        let actual_offset = do_offset_history(seq.of, seq.ll, &mut scratch.offset_hist);
+        let actual_offset = scratch.offset_hist.decode(seq.of, seq.ll);
         if actual_offset == 0 {
             return Err(ExecuteSequencesError::ZeroOffset);
         }
@@ -82,77 +83,15 @@ pub fn execute_sequences(scratch: &mut DecoderScratch) -> Result<(), ExecuteSequ
     }
     if literals_copy_counter < scratch.literals_buffer.len() {
         let rest_literals = &scratch.literals_buffer[literals_copy_counter..];
-        scratch.buffer.push(rest_literals);
+        scratch.buffer.push(rest_literals)?;
         seq_sum += rest_literals.len() as u32;
     }
 
     let diff = scratch.buffer.len() - old_buffer_size;
-    assert!(
-        seq_sum as usize == diff,
+    debug_assert_eq!(
+        seq_sum as usize, diff,
         "Seq_sum: {} is different from the difference in buffersize: {}",
-        seq_sum,
-        diff
+        seq_sum, diff
     );
     Ok(())
 }
-
-/// Update the most recently used offsets to reflect the provided offset value, and return the
-/// "actual" offset needed because offsets are not stored in a raw way, some transformations are needed
-/// before you get a functional number.
-fn do_offset_history(offset_value: u32, lit_len: u32, scratch: &mut [u32; 3]) -> u32 {
-    let actual_offset = if lit_len > 0 {
-        match offset_value {
-            1..=3 => scratch[offset_value as usize - 1],
-            _ => {
-                //new offset
-                offset_value - 3
-            }
-        }
-    } else {
-        match offset_value {
-            1..=2 => scratch[offset_value as usize],
-            3 => scratch[0] - 1,
-            _ => {
-                //new offset
-                offset_value - 3
-            }
-        }
-    };
-
-    //update history
-    if lit_len > 0 {
-        match offset_value {
-            1 => {
-                //nothing
-            }
-            2 => {
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-            _ => {
-                scratch[2] = scratch[1];
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-        }
-    } else {
-        match offset_value {
-            1 => {
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-            2 => {
-                scratch[2] = scratch[1];
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-            _ => {
-                scratch[2] = scratch[1];
-                scratch[1] = scratch[0];
-                scratch[0] = actual_offset;
-            }
-        }
-    }
-
-    actual_offset
-}
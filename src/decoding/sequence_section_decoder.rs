@@ -6,6 +6,7 @@ use super::scratch::FSEScratch;
 use crate::blocks::sequence_section::{
     MAX_LITERAL_LENGTH_CODE, MAX_MATCH_LENGTH_CODE, MAX_OFFSET_CODE,
 };
+use crate::fallible::{AllocError, TryVec};
 use crate::fse::{FSEDecoder, FSEDecoderError, FSETableError};
 use alloc::vec::Vec;
 
@@ -15,15 +16,25 @@ pub enum DecodeSequenceError {
     GetBitsError(GetBitsError),
     FSEDecoderError(FSEDecoderError),
     FSETableError(FSETableError),
-    ExtraPadding { skipped_bits: i32 },
-    UnsupportedOffset { offset_code: u8 },
+    ExtraPadding {
+        skipped_bits: i32,
+    },
+    UnsupportedOffset {
+        offset_code: u8,
+    },
     ZeroOffset,
     NotEnoughBytesForNumSequences,
-    ExtraBits { bits_remaining: isize },
+    ExtraBits {
+        bits_remaining: isize,
+    },
     MissingCompressionMode,
     MissingByteForRleLlTable,
     MissingByteForRleOfTable,
     MissingByteForRleMlTable,
+    /// There is not enough memory for the sequences.
+    OutOfMemory {
+        size: usize,
+    },
 }
 
 #[cfg(feature = "std")]
@@ -79,10 +90,19 @@ impl core::fmt::Display for DecodeSequenceError {
             DecodeSequenceError::MissingByteForRleMlTable => {
                 write!(f, "Need a byte to read for RLE ml table")
             }
+            DecodeSequenceError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for the sequences", size)
+            }
         }
     }
 }
 
+impl From<AllocError> for DecodeSequenceError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
 impl From<GetBitsError> for DecodeSequenceError {
     fn from(val: GetBitsError) -> Self {
         Self::GetBitsError(val)
@@ -158,22 +178,22 @@ fn decode_sequences_with_rle(
     }
 
     target.clear();
-    target.reserve(section.num_sequences as usize);
+    target.try_room(section.num_sequences as usize)?;
 
     for _seq_idx in 0..section.num_sequences {
         //get the codes from either the RLE byte or from the decoder
-        let ll_code = if scratch.ll_rle.is_some() {
-            scratch.ll_rle.unwrap()
+        let ll_code = if let Some(ll_rle) = scratch.ll_rle {
+            ll_rle
         } else {
             ll_dec.decode_symbol()
         };
-        let ml_code = if scratch.ml_rle.is_some() {
-            scratch.ml_rle.unwrap()
+        let ml_code = if let Some(ml_rle) = scratch.ml_rle {
+            ml_rle
         } else {
             ml_dec.decode_symbol()
         };
-        let of_code = if scratch.of_rle.is_some() {
-            scratch.of_rle.unwrap()
+        let of_code = if let Some(of_rle) = scratch.of_rle {
+            of_rle
         } else {
             of_dec.decode_symbol()
         };
@@ -205,11 +225,11 @@ fn decode_sequences_with_rle(
             return Err(DecodeSequenceError::ZeroOffset);
         }
 
-        target.push(Sequence {
+        target.try_push(Sequence {
             ll: ll_value + ll_add as u32,
             ml: ml_value + ml_add as u32,
             of: offset,
-        });
+        })?;
 
         if target.len() < section.num_sequences as usize {
             //println!(
@@ -257,7 +277,7 @@ fn decode_sequences_without_rle(
     ml_dec.init_state(br)?;
 
     target.clear();
-    target.reserve(section.num_sequences as usize);
+    target.try_room(section.num_sequences as usize)?;
 
     for _seq_idx in 0..section.num_sequences {
         let ll_code = ll_dec.decode_symbol();
@@ -280,11 +300,11 @@ fn decode_sequences_without_rle(
             return Err(DecodeSequenceError::ZeroOffset);
         }
 
-        target.push(Sequence {
+        target.try_push(Sequence {
             ll: ll_value + ll_add as u32,
             ml: ml_value + ml_add as u32,
             of: offset,
-        });
+        })?;
 
         if target.len() < section.num_sequences as usize {
             //println!(
@@ -315,7 +335,7 @@ fn decode_sequences_without_rle(
 /// by the Zstandard reference document. Returns a tuple of (value, number of bits).
 ///
 /// <https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#appendix-a---decoding-tables-for-predefined-codes>
-fn lookup_ll_code(code: u8) -> (u32, u8) {
+pub(crate) fn lookup_ll_code(code: u8) -> (u32, u8) {
     match code {
         0..=15 => (u32::from(code), 0),
         16 => (16, 1),
@@ -337,8 +357,8 @@ fn lookup_ll_code(code: u8) -> (u32, u8) {
         32 => (8192, 13),
         33 => (16384, 14),
         34 => (32768, 15),
-        35 => (65536, 16),
-        _ => unreachable!("Illegal literal length code was: {}", code),
+        // 35, the tables hold no codes above MAX_LITERAL_LENGTH_CODE
+        _ => (65536, 16),
     }
 }
 
@@ -346,7 +366,7 @@ fn lookup_ll_code(code: u8) -> (u32, u8) {
 /// by the Zstandard reference document. Returns a tuple of (value, number of bits).
 ///
 /// <https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#appendix-a---decoding-tables-for-predefined-codes>
-fn lookup_ml_code(code: u8) -> (u32, u8) {
+pub(crate) fn lookup_ml_code(code: u8) -> (u32, u8) {
     match code {
         0..=31 => (u32::from(code) + 3, 0),
         32 => (35, 1),
@@ -369,8 +389,8 @@ fn lookup_ml_code(code: u8) -> (u32, u8) {
         49 => (8195, 13),
         50 => (16387, 14),
         51 => (32771, 15),
-        52 => (65539, 16),
-        _ => unreachable!("Illegal match length code was: {}", code),
+        // 52, the tables hold no codes above MAX_MATCH_LENGTH_CODE
+        _ => (65539, 16),
     }
 }
 
@@ -0,0 +1,280 @@
+//! The byte format of decoder snapshots, see [FrameDecoder::suspend](crate::frame_decoder::FrameDecoder::suspend).
+//!
+//! All numbers are little endian. The entropy tables are stored as the probabilities and weights
+//! they were built from, and are rebuilt when the snapshot is read.
+
+use alloc::vec::Vec;
+use core::convert::{TryFrom, TryInto};
+
+use super::decodebuffer::DecodeBufferError;
+use super::scratch::DecoderScratch;
+use crate::blocks::sequence_section::OffsetHistory;
+use crate::fallible::{try_with_capacity, AllocError, TryVec};
+use crate::frame_decoder::FrameDecoderError;
+use crate::fse::{FSETable, FSETableError};
+use crate::huff0::HuffmanTableError;
+
+/// Written in front of every snapshot, followed by the version of the format.
+pub(crate) const SNAPSHOT_MAGIC: [u8; 4] = *b"RZDS";
+pub(crate) const SNAPSHOT_VERSION: u8 = 1;
+
+/// The largest accuracy log and number of symbols of the literal length table.
+const LL_LIMITS: (u8, u32) = (9, 36);
+/// The largest accuracy log and number of symbols of the match length table.
+const ML_LIMITS: (u8, u32) = (9, 53);
+/// The largest accuracy log and number of symbols of the offset table.
+const OF_LIMITS: (u8, u32) = (8, 32);
+
+/// Writes a snapshot, [SnapshotWriter::finish] reports if the output could not grow.
+pub(crate) struct SnapshotWriter<'a> {
+    output: &'a mut Vec<u8>,
+    result: Result<(), AllocError>,
+}
+
+impl<'a> SnapshotWriter<'a> {
+    pub(crate) fn new(output: &'a mut Vec<u8>) -> Self {
+        let mut writer = Self {
+            output,
+            result: Ok(()),
+        };
+        writer.put(&SNAPSHOT_MAGIC);
+        writer.u8(SNAPSHOT_VERSION);
+        writer
+    }
+
+    /// Whether everything was written.
+    pub(crate) fn finish(self) -> Result<(), AllocError> {
+        self.result
+    }
+
+    fn put(&mut self, data: &[u8]) {
+        if self.result.is_ok() {
+            self.result = self.output.try_extend_from_slice(data);
+        }
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.put(&[value]);
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.put(&value.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.put(&value.to_le_bytes());
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(u8::from(value));
+    }
+
+    pub(crate) fn option_u8(&mut self, value: Option<u8>) {
+        self.bool(value.is_some());
+        self.u8(value.unwrap_or(0));
+    }
+
+    pub(crate) fn option_u32(&mut self, value: Option<u32>) {
+        self.bool(value.is_some());
+        self.u32(value.unwrap_or(0));
+    }
+
+    /// A length followed by the bytes of all `parts`.
+    pub(crate) fn bytes(&mut self, parts: &[&[u8]]) {
+        self.u64(parts.iter().map(|part| part.len() as u64).sum());
+        for part in parts {
+            self.put(part);
+        }
+    }
+}
+
+/// Reads what a [SnapshotWriter] wrote, every method returns `None` if the snapshot ended early
+/// or contains a value that is out of range.
+pub(crate) struct SnapshotReader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Option<Self> {
+        let mut reader = Self { input };
+        if reader.take(4)? != SNAPSHOT_MAGIC || reader.u8()? != SNAPSHOT_VERSION {
+            return None;
+        }
+        Some(reader)
+    }
+
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.input.len() < len {
+            return None;
+        }
+        let (taken, rest) = self.input.split_at(len);
+        self.input = rest;
+        Some(taken)
+    }
+
+    pub(crate) fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    pub(crate) fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn option_u8(&mut self) -> Option<Option<u8>> {
+        let some = self.bool()?;
+        let value = self.u8()?;
+        Some(some.then_some(value))
+    }
+
+    pub(crate) fn option_u32(&mut self) -> Option<Option<u32>> {
+        let some = self.bool()?;
+        let value = self.u32()?;
+        Some(some.then_some(value))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = usize::try_from(self.u64()?).ok()?;
+        self.take(len)
+    }
+
+    /// Whether everything was read.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.input.is_empty()
+    }
+}
+
+fn write_fse_table(writer: &mut SnapshotWriter<'_>, table: &FSETable, rle: Option<u8>) {
+    writer.option_u8(rle);
+    writer.u8(table.accuracy_log);
+    writer.u32(table.symbol_probabilities.len() as u32);
+    for probability in &table.symbol_probabilities {
+        writer.u32(*probability as u32);
+    }
+}
+
+/// Read a table with at most `max_symbols` symbols and an accuracy log of at most `max_log`.
+fn read_fse_table(
+    reader: &mut SnapshotReader<'_>,
+    table: &mut FSETable,
+    (max_log, max_symbols): (u8, u32),
+) -> Result<Option<u8>, FrameDecoderError> {
+    let rle = reader
+        .option_u8()
+        .ok_or(FrameDecoderError::InvalidSnapshot)?;
+    if rle.is_some_and(|symbol| u32::from(symbol) >= max_symbols) {
+        return Err(FrameDecoderError::InvalidSnapshot);
+    }
+    let accuracy_log = reader.u8().ok_or(FrameDecoderError::InvalidSnapshot)?;
+    let len = reader.u32().ok_or(FrameDecoderError::InvalidSnapshot)?;
+    if len > max_symbols {
+        return Err(FrameDecoderError::InvalidSnapshot);
+    }
+    let mut probabilities = try_with_capacity(len as usize)?;
+    for _ in 0..len {
+        let probability = reader.u32().ok_or(FrameDecoderError::InvalidSnapshot)?;
+        probabilities.try_push(probability as i32)?;
+    }
+    // Tables are only built if the probabilities add up, which the table construction relies on
+    let sum: u64 = probabilities
+        .iter()
+        .map(|p| u64::from(p.unsigned_abs()))
+        .sum();
+    if accuracy_log == 0 {
+        table.reset();
+    } else if accuracy_log > max_log
+        || probabilities.iter().any(|&p| p < -1)
+        || sum != 1 << accuracy_log
+    {
+        return Err(FrameDecoderError::InvalidSnapshot);
+    } else {
+        table
+            .build_from_probabilities(accuracy_log, &probabilities)
+            .map_err(|err| match err {
+                FSETableError::OutOfMemory { size } => {
+                    FrameDecoderError::BufferOutOfMemory { size }
+                }
+                _ => FrameDecoderError::InvalidSnapshot,
+            })?;
+    }
+    Ok(rle)
+}
+
+/// Write the parts of `scratch` that carry over from one block to the next: the decoded data that
+/// was not drained yet, the repeat offsets and the entropy tables.
+pub(crate) fn write_scratch(writer: &mut SnapshotWriter<'_>, scratch: &DecoderScratch) {
+    writer.u64(scratch.buffer.total_output_counter());
+    let (first, second) = scratch.buffer.as_slices();
+    writer.bytes(&[first, second]);
+    for offset in scratch.offset_hist.offsets() {
+        writer.u32(offset);
+    }
+    write_fse_table(writer, &scratch.fse.literal_lengths, scratch.fse.ll_rle);
+    write_fse_table(writer, &scratch.fse.match_lengths, scratch.fse.ml_rle);
+    write_fse_table(writer, &scratch.fse.offsets, scratch.fse.of_rle);
+    writer.bytes(&[scratch.huf.table.weights()]);
+}
+
+/// Read what [write_scratch] wrote into a `scratch` that was reset for a frame with a window of
+/// `window_size` bytes (and initialized from its dictionary, if it has one).
+pub(crate) fn read_scratch(
+    reader: &mut SnapshotReader<'_>,
+    scratch: &mut DecoderScratch,
+    window_size: u64,
+) -> Result<(), FrameDecoderError> {
+    let total_output_counter = reader.u64().ok_or(FrameDecoderError::InvalidSnapshot)?;
+    let content = reader.bytes().ok_or(FrameDecoderError::InvalidSnapshot)?;
+    // FrameDecoder::suspend never writes more than the window
+    if content.len() as u64 > total_output_counter || content.len() as u64 > window_size {
+        return Err(FrameDecoderError::InvalidSnapshot);
+    }
+    scratch
+        .buffer
+        .restore(content, total_output_counter)
+        .map_err(|err| match err {
+            DecodeBufferError::OutOfMemory { size } => {
+                FrameDecoderError::BufferOutOfMemory { size }
+            }
+            _ => FrameDecoderError::InvalidSnapshot,
+        })?;
+    let mut offsets = [0; 3];
+    for offset in &mut offsets {
+        *offset = reader
+            .u32()
+            .filter(|&offset| offset != 0)
+            .ok_or(FrameDecoderError::InvalidSnapshot)?;
+    }
+    scratch.offset_hist = OffsetHistory::from_offsets(offsets);
+    scratch.fse.ll_rle = read_fse_table(reader, &mut scratch.fse.literal_lengths, LL_LIMITS)?;
+    scratch.fse.ml_rle = read_fse_table(reader, &mut scratch.fse.match_lengths, ML_LIMITS)?;
+    scratch.fse.of_rle = read_fse_table(reader, &mut scratch.fse.offsets, OF_LIMITS)?;
+    let weights = reader.bytes().ok_or(FrameDecoderError::InvalidSnapshot)?;
+    if weights.is_empty() {
+        scratch.huf.table.reset();
+    } else if weights.len() > 255 {
+        return Err(FrameDecoderError::InvalidSnapshot);
+    } else {
+        scratch
+            .huf
+            .table
+            .build_from_weights(weights)
+            .map_err(|err| match err {
+                HuffmanTableError::OutOfMemory { size } => {
+                    FrameDecoderError::BufferOutOfMemory { size }
+                }
+                _ => FrameDecoderError::InvalidSnapshot,
+            })?;
+    }
+    Ok(())
+}
@@ -0,0 +1,169 @@
+//! The storage of the decoding tables, see [TableVec].
+
+#[cfg(feature = "small-target")]
+use crate::fallible::try_vec;
+use crate::fallible::AllocError;
+#[cfg(not(feature = "small-target"))]
+use crate::fallible::TryVec;
+use alloc::vec::Vec;
+use core::ops::{Deref, DerefMut};
+
+/// The entries of a decoding table, which dereferences to a slice of them.
+///
+/// By default this is a `Vec` that grows to the size of the table. With the `small-target` feature
+/// it is an array of `N` entries on the heap, allocated when the table is built for the first time
+/// and kept until it is dropped, so building tables again never allocates and tables of more than
+/// `N` entries are rejected.
+pub struct TableVec<T, const N: usize> {
+    /// With the `small-target` feature, always either empty or `N` entries long.
+    items: Vec<T>,
+    #[cfg(feature = "small-target")]
+    len: usize,
+}
+
+impl<T, const N: usize> TableVec<T, N> {
+    /// An empty table, which does not allocate yet.
+    pub const fn new() -> Self {
+        Self {
+            items: Vec::new(),
+            #[cfg(feature = "small-target")]
+            len: 0,
+        }
+    }
+}
+
+#[cfg(not(feature = "small-target"))]
+impl<T: Copy + Default, const N: usize> TableVec<T, N> {
+    pub(crate) fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub(crate) fn try_push(&mut self, value: T) -> Result<(), AllocError> {
+        self.items.try_push(value)
+    }
+
+    pub(crate) fn try_resize(&mut self, len: usize, value: T) -> Result<(), AllocError> {
+        self.items.try_resize(len, value)
+    }
+
+    pub(crate) fn try_extend_from_slice(&mut self, data: &[T]) -> Result<(), AllocError> {
+        self.items.try_extend_from_slice(data)
+    }
+}
+
+#[cfg(feature = "small-target")]
+impl<T: Copy + Default, const N: usize> TableVec<T, N> {
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub(crate) fn try_push(&mut self, value: T) -> Result<(), AllocError> {
+        self.try_resize(self.len + 1, value)
+    }
+
+    pub(crate) fn try_resize(&mut self, len: usize, value: T) -> Result<(), AllocError> {
+        let old_len = self.len;
+        self.grow(len)?;
+        if len > old_len {
+            self.items[old_len..len].fill(value);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn try_extend_from_slice(&mut self, data: &[T]) -> Result<(), AllocError> {
+        let old_len = self.len;
+        self.grow(old_len.saturating_add(data.len()))?;
+        self.items[old_len..self.len].copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Set the length to `len`, allocating the array if it is not there yet.
+    fn grow(&mut self, len: usize) -> Result<(), AllocError> {
+        if len > N {
+            return Err(AllocError::of::<T>(len));
+        }
+        if self.items.is_empty() {
+            self.items = try_vec(T::default(), N)?;
+        }
+        self.len = len;
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> Deref for TableVec<T, N> {
+    type Target = [T];
+
+    #[cfg(not(feature = "small-target"))]
+    fn deref(&self) -> &[T] {
+        &self.items
+    }
+
+    #[cfg(feature = "small-target")]
+    fn deref(&self) -> &[T] {
+        &self.items[..self.len]
+    }
+}
+
+impl<T, const N: usize> DerefMut for TableVec<T, N> {
+    #[cfg(not(feature = "small-target"))]
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.items
+    }
+
+    #[cfg(feature = "small-target")]
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.items[..self.len]
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a TableVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, const N: usize> Default for TableVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: core::fmt::Debug, const N: usize> core::fmt::Debug for TableVec<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TableVec;
+
+    #[test]
+    fn grows_like_a_vec() {
+        let mut table = TableVec::<u8, 8>::new();
+        assert!(table.is_empty());
+        table.try_push(1).unwrap();
+        table.try_extend_from_slice(&[2, 3]).unwrap();
+        table.try_resize(5, 9).unwrap();
+        assert_eq!(&*table, &[1, 2, 3, 9, 9]);
+        table[0] = 4;
+        table.try_resize(2, 0).unwrap();
+        assert_eq!(&*table, &[4, 2]);
+        table.clear();
+        table.try_resize(3, 7).unwrap();
+        assert_eq!(&*table, &[7, 7, 7]);
+    }
+
+    #[cfg(feature = "small-target")]
+    #[test]
+    fn rejects_more_than_its_capacity() {
+        let mut table = TableVec::<u32, 4>::new();
+        table.try_resize(4, 0).unwrap();
+        assert!(table.try_push(1).is_err());
+        assert!(table.try_extend_from_slice(&[1]).is_err());
+        assert_eq!(table.len(), 4);
+    }
+}
@@ -0,0 +1,688 @@
+//! Describe the structure of compressed data, down to the tables of each block, see [dissect].
+//!
+//! Unlike the [conformance](crate::conformance) checker, the dissection does not decode the
+//! content of the blocks and does not judge whether the data is valid. It lists every part of a
+//! frame with its byte offset, which helps to understand what another implementation produced, or
+//! where two implementations start to disagree. The [Display](core::fmt::Display) implementation
+//! prints the dissection as an indented tree.
+//!
+//! ```
+//! use ruzstd::blocks::block::BlockType;
+//! use ruzstd::dissect::dissect;
+//!
+//! // A frame with a single RLE block of 4 times the byte 0x61
+//! let frame = [0x28, 0xB5, 0x2F, 0xFD, 0x20, 0x04, 0x23, 0x00, 0x00, 0x61];
+//! let dissection = dissect(&frame);
+//! assert!(dissection.error.is_none());
+//! assert_eq!(dissection.size, frame.len());
+//! assert_eq!(dissection.blocks[0].block_type, BlockType::RLE);
+//! assert_eq!(dissection.blocks[0].offset, 6);
+//! println!("{}", dissection);
+//! ```
+
+// Exempt from the allocation part of the no-panic check, see the Readme: the dissection is built
+// as it goes and there is no error to return when an allocation fails
+#![allow(clippy::disallowed_methods)]
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::blocks::block::{BlockHeader, BlockType};
+use crate::blocks::literals_section::{LiteralsSection, LiteralsSectionType};
+use crate::blocks::sequence_section::{ModeType, SequencesHeader};
+use crate::decoding::sequence_section_decoder::{LL_MAX_LOG, ML_MAX_LOG, OF_MAX_LOG};
+use crate::frame::{self, ReadFrameHeaderError};
+use crate::frame_utils::FrameKind;
+use crate::fse::FSETable;
+use crate::huff0::HuffmanTable;
+
+/// The accuracy logs of the predefined literal length, offset and match length tables.
+const PREDEFINED_ACCURACY_LOGS: [u8; 3] = [6, 5, 6];
+
+/// The structure of one frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FrameDissection {
+    /// The offset of the frame in the data.
+    pub offset: usize,
+    /// The number of bytes that belong to the frame, as far as it could be dissected.
+    pub size: usize,
+    pub kind: FrameKind,
+    /// The frame header, `None` for skippable frames.
+    pub header: Option<HeaderDissection>,
+    pub blocks: Vec<BlockDissection>,
+    /// The `Content_Checksum` at the end of the frame, if it has one.
+    pub checksum: Option<u32>,
+    /// Why the dissection stopped before the end of the frame, if it did.
+    pub error: Option<DissectionError>,
+}
+
+/// The fields of a frame header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HeaderDissection {
+    /// The size of the header, including the magic number.
+    pub size: usize,
+    /// The `Frame_Header_Descriptor`.
+    pub descriptor: u8,
+    pub single_segment: bool,
+    pub has_checksum: bool,
+    /// The window size, which is the content size for single segment frames.
+    pub window_size: Option<u64>,
+    pub dictionary_id: Option<u32>,
+    pub content_size: Option<u64>,
+}
+
+/// A block and, for compressed blocks, its sections.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BlockDissection {
+    /// The offset of the block header.
+    pub offset: usize,
+    pub block_type: BlockType,
+    pub last_block: bool,
+    /// The `Block_Size` field: the size of the content after the header, for RLE blocks the size of
+    /// the decompressed content.
+    pub block_size: u32,
+    pub literals: Option<LiteralsDissection>,
+    pub sequences: Option<SequencesDissection>,
+}
+
+/// The literals section of a compressed block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct LiteralsDissection {
+    /// The offset of the literals section header.
+    pub offset: usize,
+    pub header_size: usize,
+    pub section_type: LiteralsSectionType,
+    pub regenerated_size: u32,
+    /// The size of the Huffman table description and the streams, for compressed literals.
+    pub compressed_size: Option<u32>,
+    pub huffman_table: Option<HuffmanTableDissection>,
+    /// The sizes of the Huffman coded streams, for compressed literals.
+    pub stream_sizes: Vec<usize>,
+}
+
+/// A Huffman table description at the start of compressed literals.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HuffmanTableDissection {
+    pub offset: usize,
+    pub size: usize,
+    /// The length of the longest code.
+    pub max_num_bits: u8,
+    /// The weight of every symbol, starting with symbol 0.
+    pub weights: Vec<u8>,
+}
+
+/// The sequences section of a compressed block.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SequencesDissection {
+    /// The offset of the sequences section header.
+    pub offset: usize,
+    pub header_size: usize,
+    pub num_sequences: u32,
+    /// The tables for literal lengths, offsets and match lengths, in this order. Empty if the
+    /// block has no sequences.
+    pub tables: Vec<SequenceTableDissection>,
+    /// The offset of the bit stream with the sequences.
+    pub bitstream_offset: usize,
+    pub bitstream_size: usize,
+}
+
+/// How the literal lengths, offsets or match lengths of a block are coded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SequenceTableDissection {
+    pub mode: ModeType,
+    /// The offset of the table description, which is empty for predefined and repeated tables.
+    pub offset: usize,
+    pub size: usize,
+    /// The accuracy log of predefined and FSE compressed tables.
+    pub accuracy_log: Option<u8>,
+    /// The probabilities of an FSE compressed table, -1 stands for "less than 1".
+    pub probabilities: Vec<i32>,
+    /// The only code of an RLE table.
+    pub rle_code: Option<u8>,
+}
+
+/// Where and why the dissection of a frame stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DissectionError {
+    pub offset: usize,
+    pub description: String,
+}
+
+impl fmt::Display for DissectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: {}", self.offset, self.description)
+    }
+}
+
+/// Describe the frame at the start of `data`.
+///
+/// Data that follows the frame is ignored, use [dissect_all] for data with several frames.
+pub fn dissect(data: &[u8]) -> FrameDissection {
+    dissect_at(data, 0)
+}
+
+/// Describe all frames in `data`, until the end of the data or the first frame that could not be
+/// dissected completely.
+pub fn dissect_all(data: &[u8]) -> Vec<FrameDissection> {
+    let mut frames = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        let frame = dissect_at(data, offset);
+        offset += frame.size;
+        let failed = frame.error.is_some();
+        frames.push(frame);
+        if failed {
+            break;
+        }
+    }
+    frames
+}
+
+fn dissect_at(data: &[u8], offset: usize) -> FrameDissection {
+    let mut frame = FrameDissection {
+        offset,
+        size: 0,
+        kind: FrameKind::Zstd,
+        header: None,
+        blocks: Vec::new(),
+        checksum: None,
+        error: None,
+    };
+    if let Err(error) = dissect_frame(data, &mut frame) {
+        frame.error = Some(error);
+    }
+    frame
+}
+
+fn dissect_frame(data: &[u8], frame: &mut FrameDissection) -> Result<(), DissectionError> {
+    let start = frame.offset;
+    let mut source = &data[start..];
+    let (header, header_size) = match frame::read_frame_header(&mut source) {
+        Ok(header) => header,
+        Err(ReadFrameHeaderError::SkipFrame {
+            magic_number,
+            length,
+        }) => {
+            frame.kind = FrameKind::Skippable { magic_number };
+            frame.size = 8 + length as usize;
+            if source.len() < length as usize {
+                frame.size = data.len() - start;
+                return Err(error(data.len(), "the skippable frame is truncated"));
+            }
+            return Ok(());
+        }
+        Err(e) => return Err(error(start, e)),
+    };
+    let header = &header.header;
+    let content_size = match header.descriptor.frame_content_size_bytes() {
+        Ok(0) | Err(_) => None,
+        Ok(_) => Some(header.frame_content_size()),
+    };
+    frame.header = Some(HeaderDissection {
+        size: usize::from(header_size),
+        descriptor: header.descriptor.0,
+        single_segment: header.descriptor.single_segment_flag(),
+        has_checksum: header.descriptor.content_checksum_flag(),
+        window_size: header.window_size().ok(),
+        dictionary_id: header.dictionary_id(),
+        content_size,
+    });
+    let mut offset = start + usize::from(header_size);
+    frame.size = offset - start;
+
+    loop {
+        let block_header = BlockHeader::parse(&data[offset..]).map_err(|e| error(offset, e))?;
+        let content_offset = offset + BlockHeader::SIZE;
+        let content_end = content_offset + block_header.content_size as usize;
+        let mut block = BlockDissection {
+            offset,
+            block_type: block_header.block_type,
+            last_block: block_header.last_block,
+            block_size: block_header.block_size(),
+            literals: None,
+            sequences: None,
+        };
+        if content_end > data.len() {
+            frame.blocks.push(block);
+            frame.size = data.len() - start;
+            return Err(error(data.len(), "the block is truncated"));
+        }
+        let result = match block_header.block_type {
+            BlockType::Compressed => {
+                dissect_compressed(data, content_offset, content_end, &mut block)
+            }
+            _ => Ok(()),
+        };
+        frame.blocks.push(block);
+        frame.size = content_end - start;
+        result?;
+        offset = content_end;
+        if block_header.last_block {
+            break;
+        }
+    }
+
+    if header.descriptor.content_checksum_flag() {
+        let checksum = data
+            .get(offset..offset + 4)
+            .ok_or_else(|| error(data.len(), "the checksum is truncated"))?;
+        frame.checksum = Some(u32::from_le_bytes([
+            checksum[0],
+            checksum[1],
+            checksum[2],
+            checksum[3],
+        ]));
+        frame.size += 4;
+    }
+    Ok(())
+}
+
+fn dissect_compressed(
+    data: &[u8],
+    start: usize,
+    end: usize,
+    block: &mut BlockDissection,
+) -> Result<(), DissectionError> {
+    let mut section = LiteralsSection::new();
+    let header_size = usize::from(
+        section
+            .parse_from_header(&data[start..end])
+            .map_err(|e| error(start, e))?,
+    );
+    let literals_start = start + header_size;
+    let literals_size = match section.ls_type {
+        LiteralsSectionType::Raw => section.regenerated_size as usize,
+        LiteralsSectionType::RLE => 1,
+        LiteralsSectionType::Compressed | LiteralsSectionType::Treeless => {
+            section.compressed_size.unwrap_or(0) as usize
+        }
+    };
+    let literals_end = literals_start + literals_size;
+    let mut literals = LiteralsDissection {
+        offset: start,
+        header_size,
+        section_type: section.ls_type,
+        regenerated_size: section.regenerated_size,
+        compressed_size: section.compressed_size,
+        huffman_table: None,
+        stream_sizes: Vec::new(),
+    };
+    if literals_end > end {
+        block.literals = Some(literals);
+        return Err(error(end, "the literals do not fit into the block"));
+    }
+
+    let mut streams_start = literals_start;
+    if section.ls_type == LiteralsSectionType::Compressed {
+        let mut table = HuffmanTable::new();
+        let size = table
+            .build_decoder(&data[literals_start..literals_end])
+            .map_err(|e| error(literals_start, e))? as usize;
+        literals.huffman_table = Some(HuffmanTableDissection {
+            offset: literals_start,
+            size,
+            max_num_bits: table.max_num_bits,
+            weights: table.weights().to_vec(),
+        });
+        streams_start += size;
+    }
+    match section.num_streams {
+        Some(4)
+            if matches!(
+                section.ls_type,
+                LiteralsSectionType::Compressed | LiteralsSectionType::Treeless
+            ) =>
+        {
+            let jump_table = data
+                .get(streams_start..streams_start + 6)
+                .filter(|_| streams_start + 6 <= literals_end)
+                .ok_or_else(|| error(streams_start, "the jump table is truncated"))?;
+            let sizes = [
+                usize::from(u16::from_le_bytes([jump_table[0], jump_table[1]])),
+                usize::from(u16::from_le_bytes([jump_table[2], jump_table[3]])),
+                usize::from(u16::from_le_bytes([jump_table[4], jump_table[5]])),
+            ];
+            let first_three: usize = sizes.iter().sum();
+            let rest = (literals_end - streams_start - 6)
+                .checked_sub(first_three)
+                .ok_or_else(|| error(streams_start, "the streams are larger than the literals"))?;
+            literals.stream_sizes = sizes.to_vec();
+            literals.stream_sizes.push(rest);
+        }
+        Some(_)
+            if matches!(
+                section.ls_type,
+                LiteralsSectionType::Compressed | LiteralsSectionType::Treeless
+            ) =>
+        {
+            literals.stream_sizes.push(literals_end - streams_start);
+        }
+        _ => {}
+    }
+    block.literals = Some(literals);
+
+    let mut header = SequencesHeader::new();
+    let header_size = usize::from(
+        header
+            .parse_from_header(&data[literals_end..end])
+            .map_err(|e| error(literals_end, e))?,
+    );
+    let mut sequences = SequencesDissection {
+        offset: literals_end,
+        header_size,
+        num_sequences: header.num_sequences,
+        tables: Vec::new(),
+        bitstream_offset: literals_end + header_size,
+        bitstream_size: 0,
+    };
+    let mut offset = literals_end + header_size;
+    if let (Some(modes), true) = (header.modes, header.num_sequences > 0) {
+        let modes = [modes.ll_mode(), modes.of_mode(), modes.ml_mode()];
+        let max_logs = [LL_MAX_LOG, OF_MAX_LOG, ML_MAX_LOG];
+        for i in 0..3 {
+            let mode = modes[i];
+            let mut table = SequenceTableDissection {
+                mode,
+                offset,
+                size: 0,
+                accuracy_log: None,
+                probabilities: Vec::new(),
+                rle_code: None,
+            };
+            match mode {
+                ModeType::Predefined => table.accuracy_log = Some(PREDEFINED_ACCURACY_LOGS[i]),
+                ModeType::RLE => {
+                    let code = *data[offset..end]
+                        .first()
+                        .ok_or_else(|| error(offset, "the RLE code is missing"))?;
+                    table.rle_code = Some(code);
+                    table.size = 1;
+                }
+                ModeType::FSECompressed => {
+                    let mut fse = FSETable::new(255);
+                    table.size = fse
+                        .build_decoder(&data[offset..end], max_logs[i])
+                        .map_err(|e| error(offset, e))?;
+                    table.accuracy_log = Some(fse.accuracy_log);
+                    table.probabilities = fse.symbol_probabilities.to_vec();
+                }
+                ModeType::Repeat => {}
+            }
+            offset += table.size;
+            sequences.tables.push(table);
+        }
+    }
+    sequences.bitstream_offset = offset;
+    sequences.bitstream_size = end - offset;
+    block.sequences = Some(sequences);
+    Ok(())
+}
+
+fn error(offset: usize, description: impl ToString) -> DissectionError {
+    DissectionError {
+        offset,
+        description: description.to_string(),
+    }
+}
+
+fn mode_name(mode: &ModeType) -> &'static str {
+    match mode {
+        ModeType::Predefined => "predefined",
+        ModeType::RLE => "RLE",
+        ModeType::FSECompressed => "FSE compressed",
+        ModeType::Repeat => "repeated",
+    }
+}
+
+impl fmt::Display for FrameDissection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FrameKind::Skippable { magic_number } => writeln!(
+                f,
+                "skippable frame at {}, {} bytes, magic number {:#010X}",
+                self.offset, self.size, magic_number
+            )?,
+            FrameKind::Zstd => writeln!(f, "zstd frame at {}, {} bytes", self.offset, self.size)?,
+        }
+        if let Some(header) = &self.header {
+            write!(
+                f,
+                "  header: {} bytes, descriptor {:#04X}",
+                header.size, header.descriptor
+            )?;
+            if let Some(window_size) = header.window_size {
+                write!(f, ", window size {}", window_size)?;
+            }
+            if header.single_segment {
+                write!(f, ", single segment")?;
+            }
+            if let Some(content_size) = header.content_size {
+                write!(f, ", content size {}", content_size)?;
+            }
+            if let Some(id) = header.dictionary_id {
+                write!(f, ", dictionary {}", id)?;
+            }
+            if header.has_checksum {
+                write!(f, ", checksum")?;
+            }
+            writeln!(f)?;
+        }
+        for (index, block) in self.blocks.iter().enumerate() {
+            write!(
+                f,
+                "  block {} at {}: {}, block size {}",
+                index, block.offset, block.block_type, block.block_size
+            )?;
+            if block.last_block {
+                write!(f, ", last")?;
+            }
+            writeln!(f)?;
+            if let Some(literals) = &block.literals {
+                write!(
+                    f,
+                    "    literals at {}: {}, header {} bytes, regenerated size {}",
+                    literals.offset,
+                    literals.section_type,
+                    literals.header_size,
+                    literals.regenerated_size
+                )?;
+                if let Some(size) = literals.compressed_size {
+                    write!(f, ", compressed size {}", size)?;
+                }
+                if !literals.stream_sizes.is_empty() {
+                    write!(f, ", streams {:?}", literals.stream_sizes)?;
+                }
+                writeln!(f)?;
+                if let Some(table) = &literals.huffman_table {
+                    writeln!(
+                        f,
+                        "      Huffman table at {}: {} bytes, {} weights, max {} bits",
+                        table.offset,
+                        table.size,
+                        table.weights.len(),
+                        table.max_num_bits
+                    )?;
+                }
+            }
+            if let Some(sequences) = &block.sequences {
+                writeln!(
+                    f,
+                    "    sequences at {}: {} sequences, header {} bytes",
+                    sequences.offset, sequences.num_sequences, sequences.header_size
+                )?;
+                let names = ["literal lengths", "offsets", "match lengths"];
+                for (name, table) in names.iter().zip(&sequences.tables) {
+                    write!(f, "      {}: {}", name, mode_name(&table.mode))?;
+                    if table.size > 0 {
+                        write!(f, " at {}, {} bytes", table.offset, table.size)?;
+                    }
+                    if let Some(log) = table.accuracy_log {
+                        write!(f, ", accuracy log {}", log)?;
+                    }
+                    if let Some(code) = table.rle_code {
+                        write!(f, ", code {}", code)?;
+                    }
+                    writeln!(f)?;
+                }
+                writeln!(
+                    f,
+                    "      bit stream at {}: {} bytes",
+                    sequences.bitstream_offset, sequences.bitstream_size
+                )?;
+            }
+        }
+        if let Some(checksum) = self.checksum {
+            writeln!(f, "  checksum: {:#010X}", checksum)?;
+        }
+        if let Some(error) = &self.error {
+            writeln!(f, "  stopped {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dissect, dissect_all, FrameDissection};
+    use crate::blocks::block::BlockType;
+    use crate::blocks::literals_section::LiteralsSectionType;
+    use crate::blocks::sequence_section::ModeType;
+    use crate::frame_utils::FrameKind;
+
+    /// Every part of the frame is accounted for
+    fn assert_complete(frame: &FrameDissection, data: &[u8]) {
+        assert!(frame.error.is_none(), "{}", frame);
+        let header = frame.header.as_ref().unwrap();
+        let mut offset = frame.offset + header.size;
+        for block in &frame.blocks {
+            assert_eq!(block.offset, offset);
+            offset += 3;
+            offset += match block.block_type {
+                BlockType::RLE => 1,
+                _ => block.block_size as usize,
+            };
+            if let (Some(literals), Some(sequences)) = (&block.literals, &block.sequences) {
+                let huffman = literals.huffman_table.as_ref().map_or(0, |t| t.size);
+                let jump_table = if literals.stream_sizes.len() == 4 {
+                    6
+                } else {
+                    0
+                };
+                let streams: usize = literals.stream_sizes.iter().sum();
+                let literals_size = match literals.section_type {
+                    LiteralsSectionType::Raw => literals.regenerated_size as usize,
+                    LiteralsSectionType::RLE => 1,
+                    _ => huffman + jump_table + streams,
+                };
+                assert_eq!(
+                    sequences.offset,
+                    literals.offset + literals.header_size + literals_size
+                );
+                let tables: usize = sequences.tables.iter().map(|t| t.size).sum();
+                assert_eq!(
+                    sequences.bitstream_offset,
+                    sequences.offset + sequences.header_size + tables
+                );
+                assert_eq!(
+                    sequences.bitstream_offset + sequences.bitstream_size,
+                    offset
+                );
+            }
+        }
+        if frame.checksum.is_some() {
+            offset += 4;
+        }
+        assert_eq!(offset, frame.offset + frame.size);
+        assert!(offset <= data.len());
+    }
+
+    #[test]
+    fn corpus_frames() {
+        let data = include_bytes!("../decodecorpus_files/z000094.zst");
+        let frame = dissect(data);
+        assert_complete(&frame, data);
+        assert_eq!(frame.size, data.len());
+        assert_eq!(frame.blocks.len(), 5);
+        let types: alloc::vec::Vec<_> = frame.blocks.iter().map(|b| b.block_type).collect();
+        assert!(types.contains(&BlockType::Raw) && types.contains(&BlockType::RLE));
+        let literals: alloc::vec::Vec<_> = frame
+            .blocks
+            .iter()
+            .filter_map(|b| b.literals.as_ref())
+            .collect();
+        assert!(literals
+            .iter()
+            .any(|l| l.section_type == LiteralsSectionType::Treeless && l.stream_sizes.len() == 4));
+        let compressed = literals
+            .iter()
+            .find(|l| l.section_type == LiteralsSectionType::Compressed)
+            .unwrap();
+        let table = compressed.huffman_table.as_ref().unwrap();
+        assert!(table.max_num_bits > 0 && table.max_num_bits <= 11);
+        assert_eq!(compressed.stream_sizes.len(), 1);
+        let modes: alloc::vec::Vec<_> = frame
+            .blocks
+            .iter()
+            .filter_map(|b| b.sequences.as_ref())
+            .flat_map(|s| s.tables.iter().map(|t| t.mode))
+            .collect();
+        for mode in [ModeType::Predefined, ModeType::RLE, ModeType::FSECompressed] {
+            assert!(modes.contains(&mode));
+        }
+
+        let others: [&[u8]; 4] = [
+            include_bytes!("../decodecorpus_files/z000053.zst"),
+            include_bytes!("../decodecorpus_files/z000083.zst"),
+            include_bytes!("../decodecorpus_files/z000017.zst"),
+            include_bytes!("../decodecorpus_files/z000034.zst"),
+        ];
+        for data in others {
+            assert_complete(&dissect(data), data);
+        }
+    }
+
+    #[test]
+    fn several_frames() {
+        let mut data = include_bytes!("../decodecorpus_files/z000083.zst").to_vec();
+        let first = data.len();
+        data.extend_from_slice(&[0x52, 0x2A, 0x4D, 0x18, 2, 0, 0, 0, 0xAA, 0xBB]);
+        data.extend_from_slice(include_bytes!("../decodecorpus_files/z000019.zst"));
+        let frames = dissect_all(&data);
+        assert_eq!(frames.len(), 3);
+        assert_complete(&frames[0], &data);
+        assert_eq!(
+            frames[1].kind,
+            FrameKind::Skippable {
+                magic_number: 0x184D_2A52
+            }
+        );
+        assert_eq!((frames[1].offset, frames[1].size), (first, 10));
+        assert_complete(&frames[2], &data);
+        assert_eq!(frames[2].offset + frames[2].size, data.len());
+
+        let text = alloc::format!("{}", frames[0]);
+        assert!(text.starts_with("zstd frame at 0"));
+        assert!(text.contains("literals at"), "{}", text);
+    }
+
+    #[test]
+    fn truncated() {
+        let data = include_bytes!("../decodecorpus_files/z000094.zst");
+        let frame = dissect(&data[..100]);
+        let error = frame.error.as_ref().unwrap();
+        assert_eq!(error.offset, 100);
+        assert_eq!(frame.size, 100);
+        assert!(!frame.blocks.is_empty());
+
+        let frame = dissect(b"not zstd");
+        assert_eq!(frame.error.unwrap().offset, 0);
+    }
+}
@@ -0,0 +1,204 @@
+//! Compression effort that follows the speed of the output, see [Adapt].
+
+use alloc::vec::Vec;
+use core::num::NonZeroU32;
+use core::time::Duration;
+
+use super::{CompressionLevel, Strategy, MAX_ACCELERATION};
+use crate::fallible::{AllocError, TryVec};
+
+/// The strategies an [Adapt] encoder moves through, from the fastest to the most thorough.
+const STRATEGIES: [Strategy; 8] = [
+    Strategy::Fast,
+    Strategy::DFast,
+    Strategy::Greedy,
+    Strategy::Lazy,
+    Strategy::Lazy2,
+    Strategy::BtLazy2,
+    Strategy::BtOpt,
+    Strategy::BtUltra,
+];
+
+/// Adapt the compression level to the speed of the output while a frame is compressed, like the
+/// `--adapt` flag of the zstd command line tool. Set with
+/// [EncodeOptions::adapt](super::EncodeOptions::adapt), needs the `std` feature for the clock.
+///
+/// After each block, the encoder compares the time it spent compressing with the time the
+/// writer took to take the output. If writing takes more than twice as long, the writer is the
+/// bottleneck and there is time to compress harder, which also leaves less to write. If
+/// compressing takes more than twice as long, the encoder holds up the pipeline and compresses
+/// faster. One step is taken per block, on a ladder that goes from
+/// [CompressionLevel::Negative] with `max_acceleration`, halving it down to 1, over
+/// [CompressionLevel::Fastest] with the strategies from [Strategy::Fast] to `strongest`.
+/// The level and strategy of the options are where the encoder starts.
+///
+/// The frames are ordinary frames, decoders do not notice the changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adapt {
+    /// The acceleration of the fastest step, 0 to stay at [CompressionLevel::Fastest] or above.
+    pub max_acceleration: u32,
+    /// The strategy of the most thorough step. [Strategy::RowHash] takes the place of
+    /// [Strategy::Lazy].
+    pub strongest: Strategy,
+}
+
+impl Default for Adapt {
+    /// From [CompressionLevel::Negative] with an acceleration of 8 to [Strategy::Lazy2].
+    fn default() -> Self {
+        Self {
+            max_acceleration: 8,
+            strongest: Strategy::Lazy2,
+        }
+    }
+}
+
+/// The position of `strategy` among [STRATEGIES].
+fn rank(strategy: Strategy) -> usize {
+    let strategy = match strategy {
+        Strategy::RowHash => Strategy::Lazy,
+        strategy => strategy,
+    };
+    STRATEGIES
+        .iter()
+        .position(|known| *known == strategy)
+        .unwrap_or(0)
+}
+
+/// Moves an encoder along the ladder of an [Adapt], see [Adapter::block_done].
+pub(crate) struct Adapter {
+    /// The acceleration and strategy of each step, the fastest first
+    steps: Vec<(u32, Strategy)>,
+    current: usize,
+    /// The time the output took during the current block
+    output_time: Duration,
+}
+
+impl Adapter {
+    /// Start at the step of `level` and `strategy`.
+    pub(crate) fn new(
+        adapt: Adapt,
+        level: CompressionLevel,
+        strategy: Strategy,
+    ) -> Result<Self, AllocError> {
+        let mut steps = Vec::new();
+        let mut acceleration = adapt.max_acceleration.min(MAX_ACCELERATION);
+        while acceleration > 0 {
+            steps.try_push((acceleration, Strategy::Fast))?;
+            acceleration /= 2;
+        }
+        let strongest = rank(adapt.strongest);
+        steps.try_extend(
+            STRATEGIES[..strongest]
+                .iter()
+                .map(|strategy| (0, *strategy)),
+        )?;
+        steps.try_push((0, adapt.strongest))?;
+
+        let current = match level {
+            CompressionLevel::Uncompressed => 0,
+            CompressionLevel::Negative(n) => steps
+                .iter()
+                .position(|(acceleration, _)| *acceleration <= n.get())
+                .unwrap_or(0),
+            _ => steps
+                .iter()
+                .rposition(|(acceleration, step)| {
+                    *acceleration == 0 && rank(*step) <= rank(strategy)
+                })
+                .unwrap_or(0),
+        };
+        Ok(Self {
+            steps,
+            current,
+            output_time: Duration::ZERO,
+        })
+    }
+
+    /// The level and strategy of the current step.
+    pub(crate) fn step(&self) -> (CompressionLevel, Strategy) {
+        let (acceleration, strategy) = self.steps[self.current];
+        let level = NonZeroU32::new(acceleration)
+            .map_or(CompressionLevel::Fastest, CompressionLevel::Negative);
+        (level, strategy)
+    }
+
+    /// Count `time` that the output took towards the current block.
+    pub(crate) fn add_output_time(&mut self, time: Duration) {
+        self.output_time += time;
+    }
+
+    /// Take a step after a block that took `time` in total, returns whether the step changed.
+    pub(crate) fn block_done(&mut self, time: Duration) -> bool {
+        let output_time = core::mem::take(&mut self.output_time);
+        let compress_time = time.saturating_sub(output_time);
+        let previous = self.current;
+        if output_time > compress_time * 2 {
+            self.current = usize::min(self.current + 1, self.steps.len() - 1);
+        } else if compress_time > output_time * 2 {
+            self.current = self.current.saturating_sub(1);
+        }
+        self.current != previous
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Adapt, Adapter};
+    use crate::encoding::{CompressionLevel, Strategy};
+    use core::num::NonZeroU32;
+    use core::time::Duration;
+
+    #[test]
+    fn ladder() {
+        let negative = |n| CompressionLevel::Negative(NonZeroU32::new(n).unwrap());
+        let mut adapter =
+            Adapter::new(Adapt::default(), CompressionLevel::Fastest, Strategy::Fast).unwrap();
+        assert_eq!(adapter.step(), (CompressionLevel::Fastest, Strategy::Fast));
+
+        // Slow output moves up to the strongest strategy and stays there
+        let slow_output = |adapter: &mut Adapter| {
+            adapter.add_output_time(Duration::from_millis(9));
+            adapter.block_done(Duration::from_millis(10))
+        };
+        for strategy in [
+            Strategy::DFast,
+            Strategy::Greedy,
+            Strategy::Lazy,
+            Strategy::Lazy2,
+        ] {
+            assert!(slow_output(&mut adapter));
+            assert_eq!(adapter.step(), (CompressionLevel::Fastest, strategy));
+        }
+        assert!(!slow_output(&mut adapter));
+
+        // Balanced times keep the step
+        adapter.add_output_time(Duration::from_millis(5));
+        assert!(!adapter.block_done(Duration::from_millis(10)));
+
+        // Fast output moves down to the largest acceleration
+        for _ in 0..4 {
+            assert!(adapter.block_done(Duration::from_millis(10)));
+        }
+        assert_eq!(adapter.step(), (CompressionLevel::Fastest, Strategy::Fast));
+        for acceleration in [1, 2, 4, 8] {
+            assert!(adapter.block_done(Duration::from_millis(10)));
+            assert_eq!(adapter.step(), (negative(acceleration), Strategy::Fast));
+        }
+        assert!(!adapter.block_done(Duration::from_millis(10)));
+
+        // The starting point is the closest step
+        let adapt = Adapt {
+            max_acceleration: 100,
+            strongest: Strategy::RowHash,
+        };
+        let adapter = Adapter::new(adapt, negative(10), Strategy::Fast).unwrap();
+        assert_eq!(adapter.step(), (negative(6), Strategy::Fast));
+        let adapter = Adapter::new(adapt, CompressionLevel::Fastest, Strategy::BtUltra).unwrap();
+        assert_eq!(
+            adapter.step(),
+            (CompressionLevel::Fastest, Strategy::RowHash)
+        );
+        let adapter = Adapter::new(adapt, CompressionLevel::Uncompressed, Strategy::Fast).unwrap();
+        assert_eq!(adapter.step(), (negative(100), Strategy::Fast));
+    }
+}
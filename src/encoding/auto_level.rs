@@ -0,0 +1,93 @@
+//! Picks the level and strategy for [CompressionLevel::Auto] from a sample of the input.
+
+use hashbrown::HashSet;
+
+use super::{CompressionLevel, Strategy};
+use crate::fallible::AllocError;
+use crate::huff0::huff0_encoder::HuffmanTable;
+
+/// How much of the beginning of the input is looked at.
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// Estimate how well the input that starts with `sample` compresses, and pick a level and
+/// strategy for it.
+///
+/// Two things are measured: how many positions start with 5 bytes that occurred before, which is
+/// about what matches can cover, and how many bits a Huffman code needs per byte, which is what
+/// the remaining literals cost. Input without either kind of redundancy, e.g. data that is
+/// compressed or encrypted already, is stored uncompressed. Input with a lot of repetition
+/// compresses well with the fastest search already, everything in between gets a more thorough one.
+pub(crate) fn choose(sample: &[u8]) -> Result<(CompressionLevel, Strategy), AllocError> {
+    let sample = &sample[..usize::min(sample.len(), SAMPLE_SIZE)];
+    if sample.len() < 1024 {
+        // Too little to tell, and too little for the choice to matter
+        return Ok((CompressionLevel::Fastest, Strategy::Fast));
+    }
+
+    let mut seen = HashSet::new();
+    seen.try_reserve(sample.len())
+        .map_err(|_| AllocError::of::<&[u8]>(sample.len()))?;
+    let positions = sample.len() - 4;
+    // Reserved above, so inserting never grows the set
+    #[allow(clippy::disallowed_methods)]
+    let repeated = sample.windows(5).filter(|key| !seen.insert(*key)).count();
+
+    let mut counts = [0; 256];
+    for byte in sample {
+        counts[*byte as usize] += 1;
+    }
+    // In hundredths of a bit per byte
+    let literal_cost = if counts.iter().filter(|count| **count > 0).count() < 2 {
+        0
+    } else {
+        HuffmanTable::try_build_from_data(sample)?
+            .count_bits(&counts)
+            .map_or(800, |bits| bits * 100 / sample.len())
+    };
+
+    Ok(if repeated * 20 < positions && literal_cost >= 780 {
+        (CompressionLevel::Uncompressed, Strategy::Fast)
+    } else if repeated * 2 > positions {
+        (CompressionLevel::Fastest, Strategy::Fast)
+    } else {
+        (CompressionLevel::Fastest, Strategy::Lazy)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::choose;
+    use crate::encoding::{CompressionLevel, Strategy};
+    use crate::test_utils::lcg_bytes;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn picks_by_compressibility() {
+        let noise = lcg_bytes(100_000, 7);
+        assert_eq!(
+            choose(&noise).unwrap(),
+            (CompressionLevel::Uncompressed, Strategy::Fast)
+        );
+
+        let repetitive = b"Hello, World! ".repeat(5000);
+        assert_eq!(
+            choose(&repetitive).unwrap(),
+            (CompressionLevel::Fastest, Strategy::Fast)
+        );
+
+        let mut text = Vec::new();
+        text.extend_from_slice(include_bytes!(
+            "../../dict_tests/files/NetworkManager.service"
+        ));
+        text.extend_from_slice(include_bytes!(
+            "../../dict_tests/files/ModemManager.service"
+        ));
+        text.extend_from_slice(include_bytes!("../../dict_tests/files/libvirtd.service"));
+        assert_eq!(choose(&text).unwrap().0, CompressionLevel::Fastest);
+
+        assert_eq!(
+            choose(b"tiny").unwrap(),
+            (CompressionLevel::Fastest, Strategy::Fast)
+        );
+    }
+}
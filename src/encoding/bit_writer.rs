@@ -1,10 +1,12 @@
 //! Use [BitWriter] to write an arbitrary amount of bits into a buffer.
+use crate::bits::store_le;
+use crate::fallible::{AllocError, TryVec};
 use alloc::vec::Vec;
 
 /// An interface for writing an arbitrary number of bits into a buffer. Write new bits into the buffer with `write_bits`, and
 /// obtain the output using `dump`.
 #[derive(Debug)]
-pub(crate) struct BitWriter<V: AsMut<Vec<u8>>> {
+pub struct BitWriter<V: AsMut<Vec<u8>>> {
     /// The buffer that's filled with bits
     output: V,
     /// holds a partially filled byte which gets put in outpu when it's fill with a write_bits call
@@ -13,6 +15,9 @@ pub(crate) struct BitWriter<V: AsMut<Vec<u8>>> {
     /// The index pointing to the next unoccupied bit. Effectively just
     /// the number of bits that have been written into the buffer so far.
     bit_idx: usize,
+    /// Whether all bytes made it into the output. After an allocation failed, nothing more is
+    /// written to it.
+    result: Result<(), AllocError>,
 }
 
 impl BitWriter<Vec<u8>> {
@@ -23,39 +28,66 @@ impl BitWriter<Vec<u8>> {
             partial: 0,
             bits_in_partial: 0,
             bit_idx: 0,
+            result: Ok(()),
         }
     }
 }
 
-impl BitWriter<&mut Vec<u8>> {}
+impl Default for BitWriter<Vec<u8>> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl<V: AsMut<Vec<u8>>> BitWriter<V> {
-    /// Initialize a new writer.
-    pub fn from<'a>(mut output: V) -> BitWriter<V> {
+    /// Initialize a new writer that appends to `output`.
+    pub fn from(mut output: V) -> BitWriter<V> {
         BitWriter {
             bit_idx: output.as_mut().len() * 8,
             output,
             partial: 0,
             bits_in_partial: 0,
+            result: Ok(()),
         }
     }
 
+    /// Whether an allocation failed, so the output misses bytes that were written.
+    pub fn allocation_failed(&self) -> bool {
+        self.result.is_err()
+    }
+
+    /// Like [BitWriter::allocation_failed], with the error of the failed allocation.
+    #[cfg_attr(not(feature = "encoder"), allow(dead_code))]
+    pub(crate) fn check(&self) -> Result<(), AllocError> {
+        self.result
+    }
+
+    /// The number of bits in the output, including the ones that were there before.
     pub fn index(&self) -> usize {
         self.bit_idx + self.bits_in_partial
     }
 
+    /// Overwrite `num_bits` already written bits at bit index `idx` with the lower `num_bits` of `bits`.
     pub fn change_bits(&mut self, idx: usize, bits: impl Into<u64>, num_bits: usize) {
         self.change_bits_64(idx, bits.into(), num_bits);
     }
 
+    /// Like [BitWriter::change_bits], with the bits given as `u64`.
+    ///
+    /// The changed bits must lie in bytes that were already flushed, otherwise the output is
+    /// indexed out of bounds. Does nothing once an allocation failed.
+    #[allow(clippy::disallowed_macros)]
     pub fn change_bits_64(&mut self, mut idx: usize, mut bits: u64, mut num_bits: usize) {
         self.flush();
-        assert!(idx + num_bits < self.index());
-        assert!(self.index() - (idx + num_bits) > self.bits_in_partial);
+        if self.allocation_failed() {
+            return;
+        }
+        debug_assert!(idx + num_bits < self.index());
+        debug_assert!(self.index() - (idx + num_bits) > self.bits_in_partial);
 
-        if idx % 8 != 0 {
+        if !idx.is_multiple_of(8) {
             let bits_in_first_byte = 8 - (idx % 8);
-            assert!(bits_in_first_byte <= num_bits);
+            debug_assert!(bits_in_first_byte <= num_bits);
             self.output.as_mut()[idx / 8] &= 0xFFu8 >> bits_in_first_byte;
             let new_bits = (bits << (8 - bits_in_first_byte)) as u8;
             self.output.as_mut()[idx / 8] |= new_bits;
@@ -79,38 +111,50 @@ impl<V: AsMut<Vec<u8>>> BitWriter<V> {
         }
     }
 
+    /// Append whole bytes. If the output does not end on a byte boundary, it is padded with zero
+    /// bits first.
     pub fn append_bytes(&mut self, data: &[u8]) {
-        if self.misaligned() != 0 {
-            panic!("Don't append bytes when writer is misaligned")
-        }
+        self.pad_to_byte();
         self.flush();
-        self.output.as_mut().extend_from_slice(data);
+        self.put(data);
         self.bit_idx += data.len() * 8;
     }
 
+    /// Append `data` to the output, unless an allocation failed before.
+    fn put(&mut self, data: &[u8]) {
+        if self.result.is_ok() {
+            self.result = self.output.as_mut().try_extend_from_slice(data);
+        }
+    }
+
+    /// Append the lowest `len` bytes of `value` to the output, unless an allocation failed before.
+    fn put_le(&mut self, value: u64, len: usize) {
+        if self.result.is_ok() {
+            self.result = store_le(value, len, self.output.as_mut());
+        }
+    }
+
+    /// Move all completed bytes into the output.
     pub fn flush(&mut self) {
         let full_bytes = self.bits_in_partial / 8;
-        self.output
-            .as_mut()
-            .extend_from_slice(&self.partial.to_le_bytes()[..full_bytes]);
+        self.put_le(self.partial, full_bytes);
         self.partial >>= full_bytes * 8;
         self.bits_in_partial -= full_bytes * 8;
         self.bit_idx += full_bytes * 8;
     }
 
-    /// Write the lower `num_bits` from `bits` into the writer
+    /// Write the lower `num_bits` from `bits` into the writer, the bits above them are ignored
     pub fn write_bits(&mut self, bits: impl Into<u64>, num_bits: usize) {
         self.write_bits_64(bits.into(), num_bits);
     }
 
     #[cold]
+    #[allow(clippy::disallowed_macros)]
     fn write_bits_64_cold(&mut self, bits: u64, num_bits: usize) {
         let bits_free_in_partial = 64 - self.bits_in_partial;
         let part = bits << (64 - bits_free_in_partial);
         let merged = self.partial | part;
-        self.output
-            .as_mut()
-            .extend_from_slice(&merged.to_le_bytes());
+        self.put_le(merged, 8);
         self.bit_idx += 64;
         self.partial = 0;
         self.bits_in_partial = 0;
@@ -119,8 +163,7 @@ impl<V: AsMut<Vec<u8>>> BitWriter<V> {
         let mut bits = bits >> bits_free_in_partial;
 
         while num_bits / 8 > 0 {
-            let byte = bits as u8;
-            self.output.as_mut().push(byte);
+            self.put(&[bits as u8]);
             num_bits -= 8;
             self.bit_idx += 8;
             bits >>= 8;
@@ -134,14 +177,17 @@ impl<V: AsMut<Vec<u8>>> BitWriter<V> {
         }
     }
 
+    /// Like [BitWriter::write_bits], with the bits given as `u64`.
     pub fn write_bits_64(&mut self, bits: u64, num_bits: usize) {
         if num_bits == 0 {
             return;
         }
 
-        if bits > 0 {
-            debug_assert!(bits.ilog2() <= num_bits as u32);
-        }
+        let bits = if num_bits < 64 {
+            bits & ((1 << num_bits) - 1)
+        } else {
+            bits
+        };
 
         // fill partial byte first
         if num_bits + self.bits_in_partial < 64 {
@@ -154,23 +200,29 @@ impl<V: AsMut<Vec<u8>>> BitWriter<V> {
         }
     }
 
-    /// Returns the populated buffer that you've been writing bits into.
+    /// Returns the populated buffer that you've been writing bits into. If the output does not end
+    /// on a byte boundary, it is padded with zero bits.
     ///
     /// This function consumes the writer, so it cannot be used after
-    /// dumping
+    /// dumping. Check [BitWriter::allocation_failed] before, the output is incomplete if it
+    /// returns true.
+    #[allow(clippy::disallowed_macros)]
     pub fn dump(mut self) -> V {
-        if self.misaligned() != 0 {
-            panic!("`dump` was called on a bit writer but an even number of bytes weren't written into the buffer. Was: {}", self.index())
-        }
+        self.pad_to_byte();
         self.flush();
         debug_assert_eq!(self.partial, 0);
         self.output
     }
 
+    /// Write zero bits until the output ends on a byte boundary.
+    fn pad_to_byte(&mut self) {
+        self.write_bits(0u8, self.misaligned());
+    }
+
     /// Returns how many bits are missing for an even byte
     pub fn misaligned(&self) -> usize {
         let idx = self.index();
-        if idx % 8 == 0 {
+        if idx.is_multiple_of(8) {
             0
         } else {
             8 - (idx % 8)
@@ -303,20 +355,30 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn catches_unaligned_dump() {
-        // Write a single bit in then dump it, making sure
-        // the correct error is returned
+    fn pads_unaligned_output() {
+        let mut bw = BitWriter::new();
+        bw.write_bits(1u8, 1);
+        assert_eq!(vec![0b0000_0001], bw.dump());
+
         let mut bw = BitWriter::new();
-        bw.write_bits(0u8, 1);
-        bw.dump();
+        bw.write_bits(0b11u8, 2);
+        bw.append_bytes(&[0xAB]);
+        assert_eq!(vec![0b0000_0011, 0xAB], bw.dump());
     }
 
     #[test]
-    #[should_panic]
     fn catches_dirty_upper_bits() {
         let mut bw = BitWriter::new();
         bw.write_bits(10u8, 1);
+        bw.write_bits(0b111u8, 2);
+        assert_eq!(vec![0b0000_0110], bw.dump());
+
+        // Also when the bits do not fit into the partial word
+        let mut bw = BitWriter::new();
+        bw.write_bits(0u64, 60);
+        bw.write_bits(u64::MAX, 8);
+        bw.write_bits(0u8, 4);
+        assert_eq!(vec![0, 0, 0, 0, 0, 0, 0, 0xF0, 0x0F], bw.dump());
     }
 
     #[test]
@@ -0,0 +1,124 @@
+use alloc::vec::Vec;
+
+use super::blocks::{compress_block, EntropyTables};
+use super::EncodeError;
+use super::{match_generator::MatchGenerator, util::WindowBounds};
+use crate::blocks::sequence_section::OffsetHistory;
+use crate::decoding::dictionary::Dictionary;
+
+/// The most data a single block may contain, in bytes.
+pub const BLOCK_SIZE_MAX: usize = 128 * 1024;
+
+/// Compresses single blocks without any frame around them, for formats that bring their own container.
+///
+/// The blocks form a sequence like the blocks of a frame: later blocks may reference data of earlier
+/// ones, as long as it lies within the window. They have to be decompressed in the same order by a
+/// [BlockDecompressor](crate::decoding::block_decompressor::BlockDecompressor) with the same window
+/// size (and dictionary, if one is used).
+///
+/// ```
+/// use ruzstd::encoding::BlockCompressor;
+/// use ruzstd::decoding::block_decompressor::BlockDecompressor;
+///
+/// let mut compressor = BlockCompressor::new(1 << 20);
+/// let mut decompressor = BlockDecompressor::new(1 << 20);
+///
+/// let data = b"Hello, Hello, Hello, Hello, Hello, Hello, World!".repeat(10);
+/// let mut compressed = Vec::new();
+/// let mut decompressed = Vec::new();
+/// if compressor.compress_block(&data, &mut compressed).unwrap() {
+///     decompressor.decompress_block(&compressed, &mut decompressed).unwrap();
+/// } else {
+///     // The data does not compress, so it has to be stored as it is
+///     decompressor.insert_block(&data).unwrap();
+///     decompressed.extend_from_slice(&data);
+/// }
+/// assert_eq!(decompressed, data);
+/// ```
+pub struct BlockCompressor {
+    window_size: u64,
+    matcher: MatchGenerator,
+    offset_hist: OffsetHistory,
+    tables: EntropyTables,
+    /// The number of bytes in front of the next block, including the dictionary content
+    history: u64,
+}
+
+impl BlockCompressor {
+    /// Create a compressor whose blocks reference at most the last `window_size` bytes.
+    pub fn new(window_size: usize) -> Self {
+        Self {
+            window_size: window_size as u64,
+            matcher: MatchGenerator::new(window_size),
+            offset_hist: OffsetHistory::new(),
+            tables: EntropyTables::default(),
+            history: 0,
+        }
+    }
+
+    /// Create a compressor whose blocks may also reference the content of `dictionary`.
+    pub fn with_dictionary(window_size: usize, dictionary: &Dictionary) -> Self {
+        let mut compressor = Self::new(window_size);
+        // Only the end of the dictionary fits into the window, next to the data
+        let content = &dictionary.dict_content;
+        let end = &content[content.len().saturating_sub(window_size.saturating_sub(1))..];
+        // Smaller than the window, so it always fits
+        let _ = compressor.insert_block(end);
+        compressor.offset_hist = OffsetHistory::from_offsets(dictionary.offset_hist);
+        compressor
+    }
+
+    /// Compress `data` into a compressed block (without the block header) and append it to `output`.
+    ///
+    /// Returns `false` and leaves `output` untouched if the block would not be smaller than `data`.
+    /// The data then has to be stored uncompressed, and handed to
+    /// [BlockDecompressor::insert_block](crate::decoding::block_decompressor::BlockDecompressor::insert_block)
+    /// on the decompressing side. It is part of the history of this compressor either way.
+    ///
+    /// Fails with [EncodeError::InputTooLarge] if `data` is larger than [BLOCK_SIZE_MAX]. It also has
+    /// to be smaller than the window.
+    pub fn compress_block(
+        &mut self,
+        data: &[u8],
+        output: &mut Vec<u8>,
+    ) -> Result<bool, EncodeError> {
+        if data.len() > BLOCK_SIZE_MAX {
+            return Err(EncodeError::InputTooLarge {
+                size: data.len(),
+                max: BLOCK_SIZE_MAX,
+            });
+        }
+        let start = output.len();
+        let previous_offset_hist = self.offset_hist;
+        let previous_tables = self.tables.try_clone()?;
+        compress_block(
+            &mut self.matcher,
+            &mut self.offset_hist,
+            &mut self.tables,
+            WindowBounds {
+                window_size: self.window_size,
+                history: self.history,
+            },
+            data,
+            output,
+        )?;
+        self.history += data.len() as u64;
+        if output.len() - start >= data.len() {
+            // The decompressor never sees the sequences of this block
+            self.offset_hist = previous_offset_hist;
+            self.tables = previous_tables;
+            output.truncate(start);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    /// Add `data` to the history without compressing it, e.g. for data that is stored in some other way.
+    ///
+    /// Fails with [EncodeError::InputTooLarge] if `data` is not smaller than the window.
+    pub fn insert_block(&mut self, data: &[u8]) -> Result<(), EncodeError> {
+        self.matcher.add_data_no_matching(data)?;
+        self.history += data.len() as u64;
+        Ok(())
+    }
+}
@@ -1,108 +1,225 @@
 use alloc::vec::Vec;
 
 use crate::{
+    blocks::sequence_section::OffsetHistory,
     encoding::{
         bit_writer::BitWriter,
         match_generator::{MatchGenerator, Sequence},
+        util::WindowBounds,
+        EncodeError,
+    },
+    fallible::{AllocError, TryVec},
+    fse::fse_encoder::{
+        default_ll_table, default_ml_table, default_of_table, normalize_counts,
+        try_build_table_from_probabilities, FSEEncoder, FSETable,
     },
-    fse::fse_encoder::{default_ll_table, default_ml_table, default_of_table, FSETable, State},
     huff0::huff0_encoder,
 };
 
-pub fn compress_block<'a>(matcher: &mut MatchGenerator<'a>, data: &'a [u8], output: &mut Vec<u8>) {
-    matcher.add_data(data);
+/// The entropy tables a decoder keeps from the earlier compressed blocks of a frame, which later
+/// blocks may reuse instead of describing new tables.
+///
+/// Like the offset history, they carry over from one compressed block to the next and have to be
+/// put back if a block is not stored compressed after all.
+#[derive(Default)]
+pub(crate) struct EntropyTables {
+    huffman: Option<huff0_encoder::HuffmanTable>,
+    literal_lengths: Option<FSETable>,
+    offsets: Option<FSETable>,
+    match_lengths: Option<FSETable>,
+}
+
+impl EntropyTables {
+    /// A copy to put back if a block is not stored compressed after all.
+    pub(crate) fn try_clone(&self) -> Result<Self, AllocError> {
+        let fse = |table: &Option<FSETable>| table.as_ref().map(FSETable::try_clone).transpose();
+        Ok(EntropyTables {
+            huffman: self
+                .huffman
+                .as_ref()
+                .map(huff0_encoder::HuffmanTable::try_clone)
+                .transpose()?,
+            literal_lengths: fse(&self.literal_lengths)?,
+            offsets: fse(&self.offsets)?,
+            match_lengths: fse(&self.match_lengths)?,
+        })
+    }
+}
+
+/// The modes of the sequence tables, see [the format](https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#symbol-compression-modes)
+const PREDEFINED_MODE: u8 = 0;
+const RLE_MODE: u8 = 1;
+const FSE_COMPRESSED_MODE: u8 = 2;
+const REPEAT_MODE: u8 = 3;
+
+/// Blocks up to this size only reuse entropy tables, because describing new ones costs more time
+/// and often more bytes than it saves for so little data. This keeps the overhead of small
+/// messages low.
+const SMALL_BLOCK_SIZE: usize = 1024;
+
+/// Fewer literals are only Huffman coded with the table of an earlier block, a new table would
+/// cost about as much as it saves.
+const MIN_HUFFMAN_LITERALS: usize = 64;
+
+const LL_MAX_LOG: u8 = 9;
+const OF_MAX_LOG: u8 = 8;
+const ML_MAX_LOG: u8 = 9;
+
+/// Compress `data` into a compressed block (without the block header).
+///
+/// `offset_hist` has to be the offset history left behind by the previous compressed block of
+/// the same frame. It is updated with the sequences emitted for this block, like `tables` is
+/// updated with the entropy tables of this block.
+/// Every sequence is checked against the `bounds`, see [WindowBounds::check_sequence].
+pub fn compress_block(
+    matcher: &mut MatchGenerator,
+    offset_hist: &mut OffsetHistory,
+    tables: &mut EntropyTables,
+    bounds: WindowBounds,
+    data: &[u8],
+    output: &mut Vec<u8>,
+) -> Result<(), EncodeError> {
+    matcher.add_data(data)?;
     let mut literals_vec = Vec::new();
     let mut sequences = Vec::new();
-    loop {
-        let Some(seq) = matcher.next_sequence() else {
-            break;
-        };
-
+    let mut block_pos = 0;
+    while let Some(seq) = matcher.next_sequence() {
         match seq {
-            Sequence::Literals { literals } => literals_vec.extend_from_slice(literals),
+            Sequence::Literals { literals } => literals_vec.try_extend_from_slice(literals)?,
             Sequence::Triple {
                 literals,
                 offset,
                 match_len,
             } => {
-                literals_vec.extend_from_slice(literals);
-                sequences.push(crate::blocks::sequence_section::Sequence {
-                    ll: literals.len() as u32,
+                literals_vec.try_extend_from_slice(literals)?;
+                block_pos += literals.len();
+                bounds.check_sequence(offset, block_pos)?;
+                block_pos += match_len;
+                let ll = literals.len() as u32;
+                sequences.try_push(crate::blocks::sequence_section::Sequence {
+                    ll,
                     ml: match_len as u32,
-                    of: offset as u32,
-                });
+                    of: offset_hist.encode(offset as u32, ll),
+                })?;
             }
         }
     }
 
+    write_sections(
+        &literals_vec,
+        &sequences,
+        tables,
+        data.len() > SMALL_BLOCK_SIZE,
+        !matcher.accelerated(),
+        output,
+    )
+}
+
+/// Write the literals section with `literals` and the sequences section with `sequences`, which
+/// together make up a compressed block.
+///
+/// New entropy tables are only described with `new_tables`, and the literals are only Huffman
+/// coded with `huffman`.
+fn write_sections(
+    literals: &[u8],
+    sequences: &[crate::blocks::sequence_section::Sequence],
+    tables: &mut EntropyTables,
+    new_tables: bool,
+    huffman: bool,
+    output: &mut Vec<u8>,
+) -> Result<(), EncodeError> {
     // literals section
 
     let mut writer = BitWriter::from(output);
-    if literals_vec.len() > 1024 {
-        compress_literals(&literals_vec, &mut writer);
+    let compress =
+        literals.len() >= MIN_HUFFMAN_LITERALS || (tables.huffman.is_some() && !new_tables);
+    if literals.len() > 1 && literals.iter().all(|x| *x == literals[0]) {
+        rle_literals(literals, &mut writer);
+    } else if compress && huffman {
+        compress_literals(literals, &mut tables.huffman, new_tables, &mut writer)?;
     } else {
-        raw_literals(&literals_vec, &mut writer);
+        raw_literals(literals, &mut writer);
     }
 
     // sequences section
 
-    if sequences.is_empty() {
-        writer.write_bits(0u8, 8);
-    } else {
-        encode_seqnum(sequences.len(), &mut writer);
-
-        // use standard FSE tables
-        writer.write_bits(0u8, 8);
-
-        let ll_table: FSETable = default_ll_table();
-        let ml_table: FSETable = default_ml_table();
-        let of_table: FSETable = default_of_table();
+    encode_seqnum(sequences.len(), &mut writer)?;
+    if !sequences.is_empty() {
+        let mut ll_counts = [0; 36];
+        let mut of_counts = [0; 32];
+        let mut ml_counts = [0; 53];
+        for sequence in sequences {
+            ll_counts[encode_literal_length(sequence.ll)?.0 as usize] += 1;
+            of_counts[encode_offset(sequence.of).0 as usize] += 1;
+            ml_counts[encode_match_len(sequence.ml)?.0 as usize] += 1;
+        }
+        let mut descriptions = Vec::new();
+        let (ll_mode, ll_table) = choose_table(
+            &ll_counts,
+            default_ll_table()?,
+            &mut tables.literal_lengths,
+            LL_MAX_LOG,
+            new_tables,
+            &mut descriptions,
+        )?;
+        let (of_mode, of_table) = choose_table(
+            &of_counts,
+            default_of_table()?,
+            &mut tables.offsets,
+            OF_MAX_LOG,
+            new_tables,
+            &mut descriptions,
+        )?;
+        let (ml_mode, ml_table) = choose_table(
+            &ml_counts,
+            default_ml_table()?,
+            &mut tables.match_lengths,
+            ML_MAX_LOG,
+            new_tables,
+            &mut descriptions,
+        )?;
+        writer.write_bits((ll_mode << 6) | (of_mode << 4) | (ml_mode << 2), 8);
+        writer.append_bytes(&descriptions);
 
         let sequence = sequences[sequences.len() - 1];
-        let (ll_code, ll_add_bits, ll_num_bits) = encode_literal_length(sequence.ll);
+        let (ll_code, ll_add_bits, ll_num_bits) = encode_literal_length(sequence.ll)?;
         let (of_code, of_add_bits, of_num_bits) = encode_offset(sequence.of);
-        let (ml_code, ml_add_bits, ml_num_bits) = encode_match_len(sequence.ml);
-        let mut ll_state: &State = ll_table.start_state(ll_code);
-        let mut ml_state: &State = ml_table.start_state(ml_code);
-        let mut of_state: &State = of_table.start_state(of_code);
+        let (ml_code, ml_add_bits, ml_num_bits) = encode_match_len(sequence.ml)?;
+        // Streams in RLE mode have no table and no state
+        let mut ll_state = ll_table.map(|table| (table, table.start_state(ll_code)));
+        let mut ml_state = ml_table.map(|table| (table, table.start_state(ml_code)));
+        let mut of_state = of_table.map(|table| (table, table.start_state(of_code)));
 
         writer.write_bits(ll_add_bits, ll_num_bits);
         writer.write_bits(ml_add_bits, ml_num_bits);
         writer.write_bits(of_add_bits, of_num_bits);
 
         // encode backwards so the decoder reads the first sequence first
-        for sequence in (0..=sequences.len() - 2).rev() {
-            let sequence = sequences[sequence];
-            let (ll_code, ll_add_bits, ll_num_bits) = encode_literal_length(sequence.ll);
+        for sequence in sequences[..sequences.len() - 1].iter().rev().copied() {
+            let (ll_code, ll_add_bits, ll_num_bits) = encode_literal_length(sequence.ll)?;
             let (of_code, of_add_bits, of_num_bits) = encode_offset(sequence.of);
-            let (ml_code, ml_add_bits, ml_num_bits) = encode_match_len(sequence.ml);
+            let (ml_code, ml_add_bits, ml_num_bits) = encode_match_len(sequence.ml)?;
 
-            {
-                let next = of_table.next_state(of_code, of_state.index);
-                let diff = of_state.index - next.baseline;
-                writer.write_bits(diff as u64, next.num_bits as usize);
-                of_state = next;
-            }
-            {
-                let next = ml_table.next_state(ml_code, ml_state.index);
-                let diff = ml_state.index - next.baseline;
-                writer.write_bits(diff as u64, next.num_bits as usize);
-                ml_state = next;
-            }
-            {
-                let next = ll_table.next_state(ll_code, ll_state.index);
-                let diff = ll_state.index - next.baseline;
-                writer.write_bits(diff as u64, next.num_bits as usize);
-                ll_state = next;
+            for (state, code) in [
+                (&mut of_state, of_code),
+                (&mut ml_state, ml_code),
+                (&mut ll_state, ll_code),
+            ] {
+                if let Some((table, state)) = state {
+                    let next = table.next_state(code, state.index);
+                    let diff = state.index - next.baseline;
+                    writer.write_bits(diff as u64, next.num_bits as usize);
+                    *state = next;
+                }
             }
 
             writer.write_bits(ll_add_bits, ll_num_bits);
             writer.write_bits(ml_add_bits, ml_num_bits);
             writer.write_bits(of_add_bits, of_num_bits);
         }
-        writer.write_bits(ml_state.index as u64, ml_table.table_size.ilog2() as usize);
-        writer.write_bits(of_state.index as u64, of_table.table_size.ilog2() as usize);
-        writer.write_bits(ll_state.index as u64, ll_table.table_size.ilog2() as usize);
+        for (table, state) in [ml_state, of_state, ll_state].iter().flatten() {
+            writer.write_bits(state.index as u64, table.accuracy_log() as usize);
+        }
 
         let bits_to_fill = writer.misaligned();
         if bits_to_fill == 0 {
@@ -112,32 +229,119 @@ pub fn compress_block<'a>(matcher: &mut MatchGenerator<'a>, data: &'a [u8], outp
         }
     }
     writer.flush();
+    writer.check()?;
+    Ok(())
+}
+
+/// Pick the cheapest way to encode the codes of one sequence stream, which occur as often as
+/// `counts` says: the `default` table, a single repeated code, a new table or the `previous` one.
+/// A new table is only considered with `new_table`.
+///
+/// Appends the table description to `descriptions`, updates `previous` to the table the decoder
+/// uses from now on and returns the mode together with that table, which is `None` in RLE mode.
+fn choose_table<'t>(
+    counts: &[usize],
+    default: FSETable,
+    previous: &'t mut Option<FSETable>,
+    max_log: u8,
+    new_table: bool,
+    descriptions: &mut Vec<u8>,
+) -> Result<(u8, Option<&'t FSETable>), EncodeError> {
+    let mut used = counts.iter().enumerate().filter(|(_, count)| **count > 0);
+    let Some((first, _)) = used.next() else {
+        return Err(EncodeError::Internal {
+            message: "a table was chosen for a stream without codes",
+        });
+    };
+    let distinct = 1 + used.count();
+    if distinct == 1 {
+        descriptions.try_push(first as u8)?;
+        // A decoder could repeat the RLE code, but repeating tables is all the encoder does
+        *previous = None;
+        return Ok((RLE_MODE, None));
+    }
+
+    // Every table costs the bits of the codes and the initial state
+    let cost = |table: &FSETable| {
+        table
+            .estimate_bits(counts)
+            .map(|bits| bits + usize::from(table.accuracy_log()))
+    };
+    let predefined = cost(&default).unwrap_or(usize::MAX);
+    let repeat = previous.as_ref().and_then(cost).unwrap_or(usize::MAX);
+    // Offset codes above 28 have no probability in the predefined table
+    if !new_table && predefined.min(repeat) < usize::MAX {
+        return Ok(if repeat < predefined {
+            (REPEAT_MODE, previous.as_ref())
+        } else {
+            (PREDEFINED_MODE, Some(previous.insert(default)))
+        });
+    }
+
+    let sequences: usize = counts.iter().sum();
+    let acc_log = (sequences.ilog2() as u8)
+        .max(distinct.next_power_of_two().ilog2() as u8)
+        .clamp(5, max_log);
+    let table = try_build_table_from_probabilities(&normalize_counts(counts, acc_log)?, acc_log)?;
+    let mut description = BitWriter::new();
+    let mut encoder = FSEEncoder::new(table, &mut description);
+    encoder.write_table();
+    let table = encoder.into_table();
+    description.check()?;
+    let description = description.dump();
+    let new = cost(&table).ok_or(EncodeError::Internal {
+        message: "a table built for the codes can not encode them",
+    })? + 8 * description.len();
+
+    Ok(if new < predefined.min(repeat) {
+        descriptions.try_extend_from_slice(&description)?;
+        (FSE_COMPRESSED_MODE, Some(previous.insert(table)))
+    } else if repeat < predefined {
+        (REPEAT_MODE, previous.as_ref())
+    } else {
+        (PREDEFINED_MODE, Some(previous.insert(default)))
+    })
 }
 
-fn encode_seqnum(seqnum: usize, writer: &mut BitWriter<impl AsMut<Vec<u8>>>) {
-    const UPPER_LIMIT: usize = 0xFFFF + 0x7F00;
+/// The most sequences the header of a sequence section can announce. A block has fewer, since every
+/// match of a sequence is at least 3 bytes long and a block holds at most 128 KiB.
+const MAX_SEQUENCES: usize = 0xFFFF + 0x7F00;
+
+/// Write the number of sequences in as few bytes as possible: 1 byte up to 127, 2 bytes up to
+/// 0x7EFF and 3 bytes up to [MAX_SEQUENCES]. Blocks without sequences only write this 0 byte, no
+/// compression modes follow it.
+fn encode_seqnum(
+    seqnum: usize,
+    writer: &mut BitWriter<impl AsMut<Vec<u8>>>,
+) -> Result<(), EncodeError> {
     match seqnum {
-        1..=127 => writer.write_bits(seqnum as u32, 8),
-        128..=0x7FFF => {
-            let upper = ((seqnum >> 8) | 0x80) as u8;
+        0..=127 => writer.write_bits(seqnum as u32, 8),
+        128..=0x7EFF => {
+            let upper = ((seqnum >> 8) + 0x80) as u8;
             let lower = seqnum as u8;
             writer.write_bits(upper, 8);
             writer.write_bits(lower, 8);
         }
-        0x8000..=UPPER_LIMIT => {
+        0x7F00..=MAX_SEQUENCES => {
+            // The lower byte comes first here
             let encode = seqnum - 0x7F00;
-            let upper = (encode >> 8) as u8;
-            let lower = encode as u8;
             writer.write_bits(255u8, 8);
-            writer.write_bits(upper, 8);
-            writer.write_bits(lower, 8);
+            writer.write_bits(encode as u8, 8);
+            writer.write_bits((encode >> 8) as u8, 8);
+        }
+        _ => {
+            return Err(EncodeError::Internal {
+                message: "more sequences than fit into a block",
+            })
         }
-        _ => unreachable!(),
     }
+    Ok(())
 }
 
-fn encode_literal_length(len: u32) -> (u8, u32, usize) {
-    match len {
+/// The code of a literal length and its extra bits. Literal lengths of up to 131071 bytes have a
+/// code, which covers the longest run of literals in front of a match of a block.
+fn encode_literal_length(len: u32) -> Result<(u8, u32, usize), EncodeError> {
+    Ok(match len {
         0..=15 => (len as u8, 0, 0),
         16..=17 => (16, len - 16, 1),
         18..=19 => (17, len - 18, 1),
@@ -155,17 +359,27 @@ fn encode_literal_length(len: u32) -> (u8, u32, usize) {
         1024..=2047 => (29, len - 1024, 10),
         2048..=4095 => (30, len - 2048, 11),
         4096..=8191 => (31, len - 4096, 12),
-        8192..=16383 => (31, len - 8192, 13),
-        16384..=32767 => (32, len - 16384, 14),
+        8192..=16383 => (32, len - 8192, 13),
+        16384..=32767 => (33, len - 16384, 14),
         32768..=65535 => (34, len - 32768, 15),
         65536..=131071 => (35, len - 65536, 16),
-        131072.. => unreachable!(),
-    }
+        131072.. => {
+            return Err(EncodeError::Internal {
+                message: "a literal length does not fit into a block",
+            })
+        }
+    })
 }
 
-fn encode_match_len(len: u32) -> (u8, u32, usize) {
-    match len {
-        0..=2 => unreachable!(),
+/// The code of a match length and its extra bits. Match lengths of 3 up to 131074 bytes have a
+/// code, which covers the longest match of a block.
+fn encode_match_len(len: u32) -> Result<(u8, u32, usize), EncodeError> {
+    Ok(match len {
+        0..=2 => {
+            return Err(EncodeError::Internal {
+                message: "a match is shorter than 3 bytes",
+            })
+        }
         3..=34 => (len as u8 - 3, 0, 0),
         35..=36 => (32, len - 35, 1),
         37..=38 => (33, len - 37, 1),
@@ -187,9 +401,13 @@ fn encode_match_len(len: u32) -> (u8, u32, usize) {
         8195..=16386 => (49, len - 8195, 13),
         16387..=32770 => (50, len - 16387, 14),
         32771..=65538 => (51, len - 32771, 15),
-        65539..=131074 => (52, len - 32771, 16),
-        131075.. => unreachable!(),
-    }
+        65539..=131074 => (52, len - 65539, 16),
+        131075.. => {
+            return Err(EncodeError::Internal {
+                message: "a match length does not fit into a block",
+            })
+        }
+    })
 }
 
 fn encode_offset(len: u32) -> (u8, u32, usize) {
@@ -201,36 +419,687 @@ fn encode_offset(len: u32) -> (u8, u32, usize) {
 // TODO find usecase fot this
 #[allow(dead_code)]
 fn raw_literals(literals: &[u8], writer: &mut BitWriter<&mut Vec<u8>>) {
-    writer.write_bits(0u8, 2);
-    writer.write_bits(0b11u8, 2);
-    writer.write_bits(literals.len() as u32, 20);
+    write_regenerated_size(0, literals.len(), writer);
     writer.append_bytes(literals);
 }
 
-fn compress_literals(literals: &[u8], writer: &mut BitWriter<&mut Vec<u8>>) {
-    writer.write_bits(2u8, 2); // compressed literals type
+/// Write `literals`, which are all the same byte, as a single byte that is repeated.
+fn rle_literals(literals: &[u8], writer: &mut BitWriter<&mut Vec<u8>>) {
+    write_regenerated_size(1, literals.len(), writer);
+    writer.append_bytes(&literals[..1]);
+}
+
+/// Write the header of a raw or RLE literals section of the given type with `size` literals, in
+/// 1 byte up to 31 literals, 2 bytes up to 4095 literals and 3 bytes above that.
+fn write_regenerated_size(literals_type: u8, size: usize, writer: &mut BitWriter<&mut Vec<u8>>) {
+    writer.write_bits(literals_type, 2);
+    match size {
+        // The size format only takes up one bit here
+        0..32 => {
+            writer.write_bits(0u8, 1);
+            writer.write_bits(size as u32, 5);
+        }
+        32..4096 => {
+            writer.write_bits(0b01u8, 2);
+            writer.write_bits(size as u32, 12);
+        }
+        _ => {
+            writer.write_bits(0b11u8, 2);
+            writer.write_bits(size as u32, 20);
+        }
+    }
+}
+
+/// The size of the header of a raw or RLE literals section, see [write_regenerated_size].
+fn regenerated_size_header(size: usize) -> usize {
+    match size {
+        0..32 => 1,
+        32..4096 => 2,
+        _ => 3,
+    }
+}
 
-    let encoder_table = huff0_encoder::HuffmanTable::build_from_data(literals);
+/// The `Size_Format` of a compressed literals section with `size` literals, the number of bits of
+/// its two size fields and whether the literals are split into four streams.
+///
+/// Up to 1023 literals are stored in a single stream, which needs neither the 6 byte jump table nor
+/// the end marks of the other three streams, and has the smallest header.
+fn compressed_size_format(size: usize) -> (u8, usize, bool) {
+    match size {
+        0..1024 => (0b00, 10, false),
+        1024..16384 => (0b10, 14, true),
+        _ => (0b11, 18, true),
+    }
+}
 
-    let (size_format, size_bits) = match literals.len() {
-        0..6 => (0b00u8, 10),
-        6..1024 => (0b01, 10),
-        1024..16384 => (0b10, 14),
-        16384..262144 => (0b11, 18),
-        _ => unimplemented!("too many literals"),
+/// Write `literals` Huffman compressed, with a new table or with `previous`, the table of the last
+/// compressed literals, whichever is smaller. `previous` is updated to the table that was used.
+/// Without `new_table` only `previous` is considered. Literals that do not get smaller are written
+/// raw.
+/// Build tables for the literals with `counts` and every useful maximum code length, and return
+/// the one that needs the fewest bits for its description and the encoded literals together.
+///
+/// Few literals do not pay for the larger description of long codes, so the code lengths only go
+/// up to the ones a table for `literal_count` literals could make use of.
+fn cheapest_table(
+    counts: &[usize; 256],
+    literal_count: usize,
+) -> Result<Option<(huff0_encoder::HuffmanTable, usize)>, EncodeError> {
+    let symbols = counts
+        .iter()
+        .rposition(|count| *count > 0)
+        .map_or(0, |x| x + 1);
+    let used = counts.iter().filter(|count| **count > 0).count();
+    if used < 2 {
+        return Ok(None);
+    }
+    let min_bits = used.ilog2() as u8 + 1;
+    let max_bits = (literal_count.ilog2() as u8 + 1).clamp(min_bits, 11);
+    let mut best: Option<(huff0_encoder::HuffmanTable, usize)> = None;
+    for max_num_bits in min_bits..=max_bits {
+        let table = huff0_encoder::HuffmanTable::try_build_from_counts_with_max_bits(
+            &counts[..symbols],
+            max_num_bits,
+        )?;
+        // A frame with an invalid table would be rejected by decoders
+        if table.validate().is_err() {
+            continue;
+        }
+        let mut description = BitWriter::new();
+        huff0_encoder::HuffmanEncoder::new(table.try_clone()?, &mut description).write_table()?;
+        description.check()?;
+        let Some(bits) = table.count_bits(counts) else {
+            continue;
+        };
+        let bits = bits + description.index();
+        if best.as_ref().is_none_or(|(_, best)| bits < *best) {
+            best = Some((table, bits));
+        }
+    }
+    Ok(best)
+}
+
+fn compress_literals(
+    literals: &[u8],
+    previous: &mut Option<huff0_encoder::HuffmanTable>,
+    new_table: bool,
+    writer: &mut BitWriter<&mut Vec<u8>>,
+) -> Result<(), EncodeError> {
+    const MAX_LITERALS: usize = (1 << 18) - 1;
+    if literals.len() > MAX_LITERALS {
+        return Err(EncodeError::InputTooLarge {
+            size: literals.len(),
+            max: MAX_LITERALS,
+        });
+    }
+    if literals.iter().all(|x| *x == literals[0]) {
+        // Huffman tables need at least two symbols, longer runs are RLE literals already
+        raw_literals(literals, writer);
+        return Ok(());
+    }
+
+    let mut counts = [0; 256];
+    for literal in literals {
+        counts[*literal as usize] += 1;
+    }
+    let repeat = previous
+        .as_ref()
+        .and_then(|table| Some((table, table.count_bits(&counts)?)));
+    let new = if new_table {
+        cheapest_table(&counts, literals.len())?
+    } else {
+        None
+    };
+    let (table, treeless, bits) = match (new, repeat) {
+        (Some((_, new)), Some((table, repeat))) if repeat <= new => {
+            (table.try_clone()?, true, repeat)
+        }
+        (Some((table, new)), _) => (table, false, new),
+        (None, Some((table, repeat))) => (table.try_clone()?, true, repeat),
+        (None, None) => {
+            raw_literals(literals, writer);
+            return Ok(());
+        }
+    };
+    let (size_format, size_bits, four_streams) = compressed_size_format(literals.len());
+    // Each stream ends with a padded end mark, and four streams need a jump table of 6 bytes.
+    // The compressed size field is only as large as the one for the regenerated size, so literals
+    // that do not get smaller could not even be described.
+    let streams_size = if four_streams {
+        bits.div_ceil(8) + 4 + 6
+    } else {
+        bits.div_ceil(8) + 1
     };
+    let header_size = (4 + 2 * size_bits).div_ceil(8);
+    if streams_size >= literals.len()
+        || header_size + streams_size >= regenerated_size_header(literals.len()) + literals.len()
+    {
+        raw_literals(literals, writer);
+        return Ok(());
+    }
+
+    // compressed or treeless literals type
+    writer.write_bits(if treeless { 3u8 } else { 2u8 }, 2);
 
     writer.write_bits(size_format, 2);
     writer.write_bits(literals.len() as u32, size_bits);
     let size_index = writer.index();
     writer.write_bits(0u32, size_bits);
     let index_before = writer.index();
-    let mut encoder = huff0_encoder::HuffmanEncoder::new(encoder_table, writer);
-    if size_format == 0 {
-        encoder.encode(literals)
-    } else {
-        encoder.encode4x(literals)
-    };
+    let mut encoder = huff0_encoder::HuffmanEncoder::new(table.try_clone()?, writer);
+    match (four_streams, treeless) {
+        (false, false) => encoder.encode(literals)?,
+        (false, true) => encoder.encode_treeless(literals),
+        (true, false) => encoder.encode4x(literals)?,
+        (true, true) => encoder.encode4x_treeless(literals)?,
+    }
     let encoded_len = (writer.index() - index_before) / 8;
     writer.change_bits(size_index, encoded_len as u64, size_bits);
+    *previous = Some(table);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        choose_table, encode_literal_length, encode_match_len, encode_seqnum, FSE_COMPRESSED_MODE,
+        MAX_SEQUENCES, PREDEFINED_MODE, REPEAT_MODE, RLE_MODE,
+    };
+    use crate::blocks::sequence_section::SequencesHeader;
+    use crate::decoding::block_decompressor::BlockDecompressor;
+    use crate::decoding::sequence_section_decoder::{lookup_ll_code, lookup_ml_code};
+    use crate::encoding::bit_writer::BitWriter;
+    use crate::encoding::{BlockCompressor, EncodeOptions, FrameCompressor, IncrementalEncoder};
+    use crate::fse::fse_encoder::default_ll_table;
+    use crate::test_utils::lcg_bytes;
+    use crate::FrameDecoder;
+    use alloc::vec::Vec;
+
+    /// Compress `data` as a frame and decode it with this crate and the zstd library
+    fn roundtrip(data: &[u8]) -> usize {
+        roundtrip_with(data, EncodeOptions::default())
+    }
+
+    fn roundtrip_with(data: &[u8], options: EncodeOptions) -> usize {
+        let mut compressed = Vec::new();
+        FrameCompressor::with_options(data, &mut compressed, options)
+            .compress()
+            .unwrap();
+        let mut decoded = Vec::with_capacity(data.len());
+        FrameDecoder::new()
+            .decode_all_to_vec(&compressed, &mut decoded)
+            .unwrap();
+        assert!(decoded == data);
+        let mut decoded = Vec::new();
+        zstd::stream::copy_decode(compressed.as_slice(), &mut decoded).unwrap();
+        assert!(decoded == data);
+        compressed.len()
+    }
+
+    /// Decode a compressed `block` (without block header) with this crate and the zstd library
+    fn decode_block(block: &[u8]) -> Vec<u8> {
+        use crate::blocks::block::{BlockHeader, BlockType};
+        use crate::encoding::FrameHeader;
+
+        let mut decompressed = Vec::new();
+        BlockDecompressor::new(128 * 1024)
+            .decompress_block(block, &mut decompressed)
+            .unwrap();
+
+        let mut frame = Vec::new();
+        FrameHeader {
+            frame_content_size: Some(decompressed.len() as u64),
+            single_segment: false,
+            content_checksum: false,
+            dictionary_id: None,
+            window_size: Some(128 * 1024),
+        }
+        .serialize(&mut frame)
+        .unwrap();
+        let header = BlockHeader::new(true, BlockType::Compressed, block.len() as u32);
+        frame.extend_from_slice(&header.serialize().unwrap());
+        frame.extend_from_slice(block);
+        assert!(zstd::stream::decode_all(frame.as_slice()).unwrap() == decompressed);
+        decompressed
+    }
+
+    #[test]
+    fn longest_literal_runs_and_matches() {
+        // A run of literals past the 16 bit literal length codes, followed by a match that is
+        // just as long, in a single block
+        let mut block = lcg_bytes(65_600, 1);
+        block.extend_from_within(..65_000);
+        assert!(block.len() <= 128 * 1024);
+        let mut compressor = BlockCompressor::new(1 << 20);
+        let mut decompressor = BlockDecompressor::new(1 << 20);
+        let mut compressed = Vec::new();
+        assert!(compressor.compress_block(&block, &mut compressed).unwrap());
+        let mut decompressed = Vec::new();
+        decompressor
+            .decompress_block(&compressed, &mut decompressed)
+            .unwrap();
+        assert!(decompressed == block);
+
+        // A whole block of literals in front of a match
+        let mut data = lcg_bytes(128 * 1024 - 100, 2);
+        data.extend_from_slice(&[7; 100]);
+        data.extend_from_within(..1000);
+        roundtrip(&data);
+
+        // The longest match a block can hold
+        let mut data = lcg_bytes(1000, 3);
+        data.resize(data.len() + 300_000, 5);
+        roundtrip(&data);
+    }
+
+    #[test]
+    fn blocks_without_sequences() {
+        // Literals from a small alphabet compress, but hardly have any 5 byte repeats
+        let data: Vec<u8> = lcg_bytes(4000, 4).iter().map(|x| x % 32).collect();
+        let mut compressor = BlockCompressor::new(1 << 20);
+        let mut compressed = Vec::new();
+        assert!(compressor.compress_block(&data, &mut compressed).unwrap());
+        let mut decompressed = Vec::new();
+        BlockDecompressor::new(1 << 20)
+            .decompress_block(&compressed, &mut decompressed)
+            .unwrap();
+        assert!(decompressed == data);
+        roundtrip(&data);
+        roundtrip(&[]);
+        roundtrip(&lcg_bytes(2000, 5));
+    }
+
+    #[test]
+    fn incompressible_literals() {
+        // Literals that Huffman coding makes larger must not overflow the compressed size field
+        for len in [1025, 16_383, 16_384, 100_000, 128 * 1024] {
+            roundtrip(&lcg_bytes(len, len as u64));
+        }
+    }
+
+    #[test]
+    fn sequence_counts() {
+        for (count, size) in [
+            (0, 1),
+            (1, 1),
+            (127, 1),
+            (128, 2),
+            (0x7EFF, 2),
+            (0x7F00, 3),
+            (0x7FFF, 3),
+            (0x8000, 3),
+            (0xFFFF, 3),
+            (MAX_SEQUENCES, 3),
+        ] {
+            let mut writer = BitWriter::new();
+            encode_seqnum(count, &mut writer).unwrap();
+            assert_eq!(writer.index(), size * 8, "{}", count);
+            // The compression modes follow the number of sequences
+            if count > 0 {
+                writer.write_bits(0u8, 8);
+            }
+            let mut header = SequencesHeader::new();
+            header.parse_from_header(&writer.dump()).unwrap();
+            assert_eq!(header.num_sequences as usize, count);
+        }
+
+        // About as many sequences as fit into one block, from a short match after every literal
+        let data: Vec<u8> = (0..128 * 1024 / 6)
+            .flat_map(|i: u32| [i as u8, b'h', b'e', b'l', b'l', b'o'])
+            .collect();
+        roundtrip(&data);
+    }
+
+    #[test]
+    fn length_codes() {
+        // Every length has to decode to itself with the tables of the decoder
+        for len in 0..131_072 {
+            let (code, add_bits, num_bits) = encode_literal_length(len).unwrap();
+            assert_eq!(lookup_ll_code(code), (len - add_bits, num_bits as u8));
+        }
+        for len in 3..131_075 {
+            let (code, add_bits, num_bits) = encode_match_len(len).unwrap();
+            assert_eq!(lookup_ml_code(code), (len - add_bits, num_bits as u8));
+        }
+        // Lengths without a code are reported instead of panicking
+        assert!(encode_literal_length(131_072).is_err());
+        assert!(encode_match_len(2).is_err());
+        assert!(encode_match_len(131_075).is_err());
+        assert!(encode_seqnum(MAX_SEQUENCES + 1, &mut BitWriter::new()).is_err());
+    }
+
+    #[test]
+    fn sequence_count_boundaries() {
+        use super::{write_sections, EntropyTables};
+        use crate::blocks::sequence_section::{OffsetHistory, Sequence};
+
+        // Blocks with exactly `count` sequences, each of them a literal that the match repeats, up
+        // to a whole block of 4 byte sequences
+        for count in [0, 1, 127, 128, 0x7EFF, 0x7F00, 0x7FFF, 0x8000] {
+            let mut data = Vec::new();
+            let mut literals = Vec::new();
+            let mut sequences = Vec::new();
+            let mut offset_hist = OffsetHistory::new();
+            for i in 0..count {
+                let byte = (i % 251) as u8;
+                data.extend_from_slice(&[byte; 4]);
+                literals.push(byte);
+                sequences.push(Sequence {
+                    ll: 1,
+                    ml: 3,
+                    of: offset_hist.encode(1, 1),
+                });
+            }
+            let mut block = Vec::new();
+            write_sections(
+                &literals,
+                &sequences,
+                &mut EntropyTables::default(),
+                true,
+                true,
+                &mut block,
+            )
+            .unwrap();
+
+            assert!(decode_block(&block) == data, "{}", count);
+        }
+    }
+
+    #[test]
+    fn literals_section_formats() {
+        use super::{write_sections, EntropyTables};
+        use crate::blocks::literals_section::{LiteralsSection, LiteralsSectionType};
+
+        let text =
+            |len: usize| -> Vec<u8> { lcg_bytes(len, 6).iter().map(|x| b'a' + x % 8).collect() };
+        for (literals, huffman, ls_type, header_size, streams) in [
+            (Vec::new(), true, LiteralsSectionType::Raw, 1, None),
+            (lcg_bytes(31, 7), true, LiteralsSectionType::Raw, 1, None),
+            (lcg_bytes(32, 7), true, LiteralsSectionType::Raw, 2, None),
+            (lcg_bytes(4095, 7), true, LiteralsSectionType::Raw, 2, None),
+            (lcg_bytes(4096, 7), true, LiteralsSectionType::Raw, 3, None),
+            (text(100_000), false, LiteralsSectionType::Raw, 3, None),
+            (alloc::vec![7; 2], true, LiteralsSectionType::RLE, 1, None),
+            (alloc::vec![7; 31], false, LiteralsSectionType::RLE, 1, None),
+            (alloc::vec![7; 32], true, LiteralsSectionType::RLE, 2, None),
+            (
+                alloc::vec![7; 4096],
+                true,
+                LiteralsSectionType::RLE,
+                3,
+                None,
+            ),
+            (
+                alloc::vec![7; 128 * 1024],
+                true,
+                LiteralsSectionType::RLE,
+                3,
+                None,
+            ),
+            (text(100), true, LiteralsSectionType::Compressed, 3, Some(1)),
+            (
+                text(1023),
+                true,
+                LiteralsSectionType::Compressed,
+                3,
+                Some(1),
+            ),
+            (
+                text(1024),
+                true,
+                LiteralsSectionType::Compressed,
+                4,
+                Some(4),
+            ),
+            (
+                text(16383),
+                true,
+                LiteralsSectionType::Compressed,
+                4,
+                Some(4),
+            ),
+            (
+                text(16384),
+                true,
+                LiteralsSectionType::Compressed,
+                5,
+                Some(4),
+            ),
+            (
+                text(128 * 1024),
+                true,
+                LiteralsSectionType::Compressed,
+                5,
+                Some(4),
+            ),
+        ] {
+            let mut block = Vec::new();
+            write_sections(
+                &literals,
+                &[],
+                &mut EntropyTables::default(),
+                true,
+                huffman,
+                &mut block,
+            )
+            .unwrap();
+            let mut section = LiteralsSection::new();
+            assert_eq!(
+                section.parse_from_header(&block).unwrap(),
+                header_size,
+                "{} {:?}",
+                literals.len(),
+                ls_type
+            );
+            assert_eq!(section.ls_type, ls_type);
+            assert_eq!(section.regenerated_size as usize, literals.len());
+            assert_eq!(section.num_streams, streams);
+            assert!(decode_block(&block) == literals);
+        }
+
+        // Treeless literals use the same formats
+        let mut tables = EntropyTables::default();
+        let mut block = Vec::new();
+        write_sections(&text(20_000), &[], &mut tables, true, true, &mut block).unwrap();
+        for (len, header_size, streams) in [(1000, 3, 1), (2000, 4, 4)] {
+            let literals = text(len);
+            let mut block = Vec::new();
+            write_sections(&literals, &[], &mut tables, false, true, &mut block).unwrap();
+            let mut section = LiteralsSection::new();
+            assert_eq!(section.parse_from_header(&block).unwrap(), header_size);
+            assert_eq!(section.ls_type, LiteralsSectionType::Treeless);
+            assert_eq!(section.num_streams, Some(streams));
+        }
+    }
+
+    #[test]
+    fn reuses_tables_that_still_fit() {
+        let mut counts = [0; 36];
+        counts[0] = 900;
+        counts[3] = 90;
+        counts[20] = 10;
+        let mut previous = None;
+        let mut descriptions = Vec::new();
+        let (mode, _) = choose_table(
+            &counts,
+            default_ll_table().unwrap(),
+            &mut previous,
+            9,
+            true,
+            &mut descriptions,
+        )
+        .unwrap();
+        assert_eq!(mode, FSE_COMPRESSED_MODE);
+        assert!(previous.is_some());
+        let described = descriptions.len();
+
+        // The same distribution does not need a new description
+        let (mode, _) = choose_table(
+            &counts,
+            default_ll_table().unwrap(),
+            &mut previous,
+            9,
+            true,
+            &mut descriptions,
+        )
+        .unwrap();
+        assert_eq!(mode, REPEAT_MODE);
+        assert_eq!(descriptions.len(), described);
+
+        // A code the previous table has no probability for can not be repeated
+        counts[30] = 500;
+        let (mode, _) = choose_table(
+            &counts,
+            default_ll_table().unwrap(),
+            &mut previous,
+            9,
+            true,
+            &mut descriptions,
+        )
+        .unwrap();
+        assert_eq!(mode, FSE_COMPRESSED_MODE);
+
+        let mut counts = [0; 36];
+        counts[7] = 10;
+        let (mode, table) = choose_table(
+            &counts,
+            default_ll_table().unwrap(),
+            &mut previous,
+            9,
+            true,
+            &mut descriptions,
+        )
+        .unwrap();
+        assert_eq!(mode, RLE_MODE);
+        assert!(table.is_none());
+        assert_eq!(descriptions.last(), Some(&7));
+        assert!(previous.is_none());
+    }
+
+    #[test]
+    fn small_blocks_reuse_tables() {
+        let mut counts = [0; 36];
+        counts[0] = 300;
+        counts[3] = 30;
+        let mut previous = None;
+        let mut descriptions = Vec::new();
+        let (mode, _) = choose_table(
+            &counts,
+            default_ll_table().unwrap(),
+            &mut previous,
+            9,
+            false,
+            &mut descriptions,
+        )
+        .unwrap();
+        assert_eq!(mode, PREDEFINED_MODE);
+        assert!(descriptions.is_empty());
+
+        choose_table(
+            &counts,
+            default_ll_table().unwrap(),
+            &mut previous,
+            9,
+            true,
+            &mut descriptions,
+        )
+        .unwrap();
+        let (mode, _) = choose_table(
+            &counts,
+            default_ll_table().unwrap(),
+            &mut previous,
+            9,
+            false,
+            &mut descriptions,
+        )
+        .unwrap();
+        assert_eq!(mode, REPEAT_MODE);
+
+        // Messages of all sizes around the limit
+        let mut text = Vec::new();
+        text.extend_from_slice(include_bytes!(
+            "../../../dict_tests/files/NetworkManager.service"
+        ));
+        text.extend_from_slice(include_bytes!(
+            "../../../dict_tests/files/ModemManager.service"
+        ));
+        text.extend_from_slice(include_bytes!("../../../dict_tests/files/libvirtd.service"));
+        text.extend_from_slice(include_bytes!(
+            "../../../dict_tests/files/systemd-resolved.service"
+        ));
+        for len in [0, 1, 5, 6, 100, 500, 1000, 1024, 1025, 2000, text.len()] {
+            roundtrip(&text[..len]);
+        }
+
+        // Small blocks after a large one can reuse its tables
+        let compress = |first_block: usize| {
+            let mut compressed = Vec::new();
+            let mut encoder =
+                IncrementalEncoder::start(&mut compressed, &EncodeOptions::default(), None)
+                    .unwrap();
+            encoder.write_chunk(&text[..first_block]).unwrap();
+            for chunk in text[first_block..].chunks(300) {
+                encoder.write_chunk(chunk).unwrap();
+            }
+            encoder.finish(&[]).unwrap();
+            let mut decoded = Vec::new();
+            zstd::stream::copy_decode(compressed.as_slice(), &mut decoded).unwrap();
+            assert!(decoded == text);
+            compressed.len()
+        };
+        for first_block in [300, 3000] {
+            compress(first_block);
+        }
+    }
+
+    #[test]
+    fn huffman_table_log() {
+        use super::cheapest_table;
+        use crate::huff0::huff0_encoder::{HuffmanEncoder, HuffmanTable};
+
+        // The weights of a table add up to a power of two that is as large as its longest code
+        let longest = |table: &HuffmanTable| {
+            let total: u32 = table
+                .weights()
+                .iter()
+                .filter(|weight| **weight > 0)
+                .map(|weight| 1 << (weight - 1))
+                .sum();
+            total.ilog2()
+        };
+        let skewed = |len: usize| -> Vec<u8> {
+            lcg_bytes(len, 3)
+                .iter()
+                .map(|x| (x.leading_zeros() * 4 + u32::from(x & 3)) as u8)
+                .collect()
+        };
+        let mut table_logs = Vec::new();
+        for len in [64, 500, 5000, 100_000] {
+            let literals = skewed(len);
+            let mut counts = [0; 256];
+            for literal in &literals {
+                counts[*literal as usize] += 1;
+            }
+            let (table, bits) = cheapest_table(&counts, literals.len()).unwrap().unwrap();
+            assert!(table.validate().is_ok());
+
+            // Never worse than the table with the default code lengths
+            let default = HuffmanTable::build_from_data(&literals);
+            let mut description = BitWriter::new();
+            HuffmanEncoder::new(default.clone(), &mut description)
+                .write_table()
+                .unwrap();
+            assert!(bits <= default.count_bits(&counts).unwrap() + description.index());
+            table_logs.push(longest(&table));
+        }
+        // Few literals do not pay for the description of long codes
+        assert!(table_logs[1..].iter().all(|log| *log > table_logs[0]));
+
+        let mut compressed = Vec::new();
+        let data = skewed(100_000);
+        FrameCompressor::with_options(&data[..], &mut compressed, EncodeOptions::default())
+            .compress()
+            .unwrap();
+        assert_eq!(zstd::stream::decode_all(&compressed[..]).unwrap(), data);
+    }
 }
@@ -0,0 +1,239 @@
+//! Cutting input at content-defined boundaries, for frames that stay the same when data around
+//! them changes. See [Chunker].
+
+/// Random values for each byte, that the rolling hash adds up.
+const GEAR: [u64; 256] = gear_table();
+
+/// Fill the table with the output of a splitmix64 generator, so it is the same on every target.
+const fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0;
+    let mut index = 0;
+    while index < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut value = state;
+        value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[index] = value ^ (value >> 31);
+        index += 1;
+    }
+    table
+}
+
+/// Cuts data into chunks whose boundaries depend on the content around them, like FastCDC.
+///
+/// A rolling hash over the last 64 bytes decides where a chunk ends. Inserting or removing data
+/// only changes the chunks around the edit, the boundaries before and after it are found again, so
+/// backup and deduplication systems that compress each chunk into a frame of its own (see
+/// [FrameCompressor::set_chunker](super::FrameCompressor::set_chunker)) see the same frames for
+/// unchanged data. Decoders do not need to know about it, the frames are ordinary zstd frames.
+///
+/// Chunks are between `min_size` and `max_size` bytes long, only the last one may be shorter.
+/// Boundaries are harder to hit before `avg_size` and easier after it, which keeps most chunks
+/// close to `avg_size`.
+///
+/// ```
+/// use ruzstd::encoding::Chunker;
+///
+/// let data: Vec<u8> = (0..200_000u32).map(|i| (i.wrapping_mul(i) >> 7) as u8).collect();
+/// let chunker = Chunker::new(2048, 8192, 32 * 1024);
+/// let chunks: Vec<&[u8]> = chunker.chunks(&data).collect();
+/// assert_eq!(chunks.concat(), data);
+/// assert!(chunks.iter().all(|chunk| chunk.len() <= 32 * 1024));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chunker {
+    min_size: usize,
+    avg_size: usize,
+    max_size: usize,
+    /// The bits of the hash that have to be zero before `avg_size`
+    strict_mask: u64,
+    /// The bits of the hash that have to be zero after `avg_size`
+    loose_mask: u64,
+}
+
+impl Chunker {
+    /// Cut chunks of `min_size` to `max_size` bytes, most of them about `avg_size` bytes long.
+    ///
+    /// Sizes that break `64 <= min_size <= avg_size <= max_size` are raised until they hold.
+    pub fn new(min_size: usize, avg_size: usize, max_size: usize) -> Self {
+        let min_size = min_size.max(64);
+        let avg_size = avg_size.max(min_size);
+        let max_size = max_size.max(avg_size);
+        let bits = avg_size.ilog2();
+        Self {
+            min_size,
+            avg_size,
+            max_size,
+            strict_mask: u64::MAX << (64 - (bits + 1).min(63)),
+            loose_mask: u64::MAX << (64 - (bits - 1)),
+        }
+    }
+
+    /// The smallest chunk size, apart from the last chunk.
+    pub fn min_size(&self) -> usize {
+        self.min_size
+    }
+
+    /// The size most chunks are close to.
+    pub fn avg_size(&self) -> usize {
+        self.avg_size
+    }
+
+    /// The largest chunk size.
+    pub fn max_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// The length of the first chunk of `data`, where the next one starts.
+    ///
+    /// All of `data` if it ends before a boundary is found, so with data that arrives piece by
+    /// piece, a result of `data.len()` below [Chunker::max_size] only means that the chunk goes on
+    /// until more data is known.
+    pub fn next_boundary(&self, data: &[u8]) -> usize {
+        self.find_boundary(&mut ChunkState::default(), data)
+            .unwrap_or(data.len())
+    }
+
+    /// Look for the end of the current chunk in `data`, which follows the data `state` has seen.
+    ///
+    /// Returns the length of the part of `data` that still belongs to the chunk, and starts the
+    /// next chunk in `state`, if the chunk ends in `data`. Cutting data that arrives piece by
+    /// piece like this gives the same chunks as [Chunker::chunks] does for all of it.
+    pub(crate) fn find_boundary(&self, state: &mut ChunkState, data: &[u8]) -> Option<usize> {
+        for (index, byte) in data.iter().enumerate() {
+            let position = state.len;
+            state.len += 1;
+            if position < self.min_size {
+                continue;
+            }
+            state.hash = (state.hash << 1).wrapping_add(GEAR[usize::from(*byte)]);
+            let mask = if position < self.avg_size {
+                self.strict_mask
+            } else {
+                self.loose_mask
+            };
+            if state.hash & mask == 0 || state.len == self.max_size {
+                *state = ChunkState::default();
+                return Some(index + 1);
+            }
+        }
+        None
+    }
+
+    /// Cut all of `data` into chunks.
+    pub fn chunks<'a>(&'a self, mut data: &'a [u8]) -> impl Iterator<Item = &'a [u8]> + 'a {
+        core::iter::from_fn(move || {
+            if data.is_empty() {
+                return None;
+            }
+            let (chunk, rest) = data.split_at(self.next_boundary(data));
+            data = rest;
+            Some(chunk)
+        })
+    }
+}
+
+/// How far a [Chunker] got into the current chunk, see [Chunker::find_boundary].
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ChunkState {
+    /// The number of bytes of the chunk seen so far
+    len: usize,
+    /// The rolling hash over the bytes behind the smallest chunk size
+    hash: u64,
+}
+
+impl Default for Chunker {
+    /// Chunks of 16 KiB to 256 KiB, about 64 KiB on average.
+    fn default() -> Self {
+        Self::new(16 * 1024, 64 * 1024, 256 * 1024)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Chunker;
+    use crate::test_utils::lcg_bytes;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn sizes() {
+        let chunker = Chunker::new(1024, 4096, 16 * 1024);
+        let data = lcg_bytes(1 << 20, 1);
+        let chunks: Vec<&[u8]> = chunker.chunks(&data).collect();
+        assert_eq!(chunks.concat(), data);
+        let (last, chunks) = chunks.split_last().unwrap();
+        assert!(!last.is_empty());
+        assert!(chunks
+            .iter()
+            .all(|chunk| (1024..=16 * 1024).contains(&chunk.len())));
+        let average = data.len() / (chunks.len() + 1);
+        assert!((2048..8192).contains(&average), "{}", average);
+
+        // Data without boundaries is cut at the largest size
+        let zeros = alloc::vec![0; 100_000];
+        let lengths: Vec<usize> = chunker.chunks(&zeros).map(<[u8]>::len).collect();
+        assert!(lengths[..lengths.len() - 1]
+            .iter()
+            .all(|len| *len == 16 * 1024));
+        assert_eq!(chunker.chunks(&[]).count(), 0);
+        assert_eq!(chunker.next_boundary(&zeros[..1000]), 1000);
+    }
+
+    #[test]
+    fn stable_boundaries() {
+        let chunker = Chunker::new(1024, 4096, 16 * 1024);
+        let data = lcg_bytes(300_000, 2);
+        let mut edited = data[..100_000].to_vec();
+        edited.extend_from_slice(b"inserted in the middle");
+        edited.extend_from_slice(&data[100_000..]);
+
+        let chunks: Vec<&[u8]> = chunker.chunks(&data).collect();
+        let edited_chunks: Vec<&[u8]> = chunker.chunks(&edited).collect();
+        let unchanged = edited_chunks
+            .iter()
+            .filter(|chunk| chunks.contains(chunk))
+            .count();
+        // Only the chunks around the edit differ
+        assert!(
+            unchanged + 3 >= chunks.len(),
+            "{} of {}",
+            unchanged,
+            chunks.len()
+        );
+    }
+
+    #[test]
+    fn pieces() {
+        let chunker = Chunker::new(1024, 4096, 16 * 1024);
+        let data = lcg_bytes(200_000, 3);
+        let expected: Vec<usize> = chunker.chunks(&data).map(<[u8]>::len).collect();
+
+        // Feeding the data in odd pieces finds the same boundaries
+        let mut state = super::ChunkState::default();
+        let mut lengths = Vec::new();
+        let mut current = 0;
+        for piece in data.chunks(777) {
+            let mut piece = piece;
+            while let Some(end) = chunker.find_boundary(&mut state, piece) {
+                lengths.push(current + end);
+                current = 0;
+                piece = &piece[end..];
+            }
+            current += piece.len();
+        }
+        if current > 0 {
+            lengths.push(current);
+        }
+        assert_eq!(lengths, expected);
+    }
+
+    #[test]
+    fn unordered_sizes() {
+        let chunker = Chunker::new(4096, 1024, 16 * 1024);
+        assert_eq!(chunker.avg_size(), 4096);
+        let chunker = Chunker::new(0, 0, 0);
+        assert_eq!(chunker.min_size(), 64);
+        assert_eq!(chunker.max_size(), 64);
+    }
+}
@@ -0,0 +1,190 @@
+//! Settings that control how frames are encoded.
+
+use alloc::sync::Arc;
+
+use super::match_generator::SearchParams;
+use super::{CompressionLevel, EncoderDictionary, Strategy};
+use crate::metrics::CodecMetrics;
+
+/// The window log the zstd CLI uses for its default level 3.
+const CLI_DEFAULT_WINDOW_LOG: u8 = 21;
+
+/// Settings for encoding frames, see [FrameCompressor::with_options](super::FrameCompressor::with_options).
+///
+/// The [Default] writes frames without a checksum or content size, which keeps them as small as
+/// possible. [EncodeOptions::cli_default] writes frames like the zstd command line tool does.
+///
+/// Options are meant to be set up once and reused: cloning them is cheap, the dictionary is shared.
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    pub level: CompressionLevel,
+    /// Search for matches with this strategy instead of the one of the level, e.g. to combine a
+    /// large window with a fast strategy. Not used with [CompressionLevel::Uncompressed].
+    pub strategy: Option<Strategy>,
+    /// Append the lower 32 bits of the XXH64 hash of the content to each frame, so decoders can
+    /// detect corruption.
+    #[cfg(feature = "hash")]
+    pub checksum: bool,
+    /// Declare the size of the decompressed content in the frame header.
+    ///
+    /// Frames whose content fits into the window are then written as single segment frames
+    /// without a window descriptor, which decoders size their window to the content for.
+    pub content_size: bool,
+    /// Use a window of at least `2^window_log` bytes, see
+    /// [FrameCompressor::set_window_log](super::FrameCompressor::set_window_log).
+    pub window_log: Option<u8>,
+    /// Keep at most `2^hash_log` positions in the hash table of each block, like the `hashLog`
+    /// parameter of the zstd library. Blocks get up to two slots per byte below this limit, which
+    /// is 17 by default, so with the chain table the tables of a full block take 8 bytes per byte
+    /// of input. Between 6 and 30.
+    pub hash_log: Option<u8>,
+    /// Only compare earlier positions up to `2^chain_log` bytes back, and keep the chain table of
+    /// each block for at most `2^chain_log` positions (17 by default), like the `chainLog`
+    /// parameter of the zstd library limits its chain table. Between 6 and 30.
+    pub chain_log: Option<u8>,
+    /// Compare up to `2^search_log` earlier positions to find the longest match, like the
+    /// `searchLog` parameter of the zstd library. [Strategy::Fast] takes the first match it finds
+    /// and does not use it. Between 1 and 30.
+    pub search_log: Option<u8>,
+    /// Only use matches of at least `min_match` bytes, like the `minMatch` parameter of the zstd
+    /// library. Between 5 and 7: the library also accepts 3 and 4, but the tables here are indexed
+    /// by the first 5 bytes of each position, so shorter matches can not be found.
+    pub min_match: Option<u8>,
+    /// Stop comparing earlier positions once a match of `target_length` bytes is found, like the
+    /// `targetLength` parameter of the zstd library does for its lazy strategies. At most
+    /// 131072, 0 keeps the default of searching all positions.
+    pub target_length: Option<u32>,
+    /// Compress with this dictionary, see
+    /// [FrameCompressor::set_dictionary](super::FrameCompressor::set_dictionary). Its search
+    /// tables are built once and shared by every frame that is compressed with it.
+    pub dictionary: Option<Arc<EncoderDictionary>>,
+    /// Put at most this many bytes of the input into one block, see
+    /// [FrameCompressor::set_block_size](super::FrameCompressor::set_block_size).
+    pub block_size: Option<usize>,
+    /// Leave out the magic number at the start of each frame, which saves 4 bytes per frame.
+    /// Such frames can only be decoded by decoders that expect them, see
+    /// [FrameDecoder::set_magicless](crate::frame_decoder::FrameDecoder::set_magicless).
+    pub magicless: bool,
+    /// Allow compression levels 20 to 22 and window logs above
+    /// [MAX_WINDOW_LOG_WITHOUT_ULTRA](super::MAX_WINDOW_LOG_WITHOUT_ULTRA), like the `--ultra` flag of
+    /// the zstd command line tool. Decoders need as much memory as the window is large, up to 128 MiB
+    /// for level 22, so they need [FrameDecoder::set_ultra](crate::frame_decoder::FrameDecoder::set_ultra)
+    /// or a higher [limit](crate::frame_decoder::FrameDecoder::set_max_window_size) for such frames.
+    pub ultra: bool,
+    /// Report the bytes, blocks and time spent encoding to this collector.
+    pub metrics: Option<Arc<dyn CodecMetrics>>,
+    /// Start over at content-defined points, like the `--rsyncable` flag of the zstd command line
+    /// tool: blocks end there, and the data behind them references neither the data nor the
+    /// entropy tables and repeat offsets in front of them. An edit then only changes the
+    /// compressed output up to the next such point, which lets rsync and deduplicating storage
+    /// transfer or store the rest once. The points are about 256 KiB apart, the frames stay
+    /// ordinary frames that compress a bit worse.
+    pub rsyncable: bool,
+    /// Cut the content into jobs of `job_size` bytes, like the `jobSize` parameter of the zstd
+    /// library does for its worker threads. Blocks end at the end of each job, and the data of a
+    /// job references neither the entropy tables and repeat offsets of the job in front of it nor
+    /// its data, except for the last part that [overlap_log](EncodeOptions::overlap_log) keeps.
+    /// Smaller jobs compress a bit worse. Between 512 KiB and 1 GiB.
+    ///
+    /// This only changes the layout of the frame: the jobs are compressed one after another on
+    /// the calling thread, so they do not make compression any faster.
+    pub job_size: Option<u32>,
+    /// How much of the window each job of [job_size](EncodeOptions::job_size) keeps from the job in
+    /// front of it, like the `overlapLog` parameter of the zstd library: 9 keeps the whole window,
+    /// every step below halves it, 1 keeps nothing and 0 keeps the default of 6. At most 9.
+    pub overlap_log: Option<u8>,
+    /// Raise and lower the level while compressing, depending on how fast the output takes the
+    /// compressed data, see [Adapt](super::Adapt).
+    #[cfg(feature = "std")]
+    pub adapt: Option<super::Adapt>,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            level: CompressionLevel::Fastest,
+            strategy: None,
+            #[cfg(feature = "hash")]
+            checksum: false,
+            content_size: false,
+            window_log: None,
+            hash_log: None,
+            chain_log: None,
+            search_log: None,
+            min_match: None,
+            target_length: None,
+            dictionary: None,
+            block_size: None,
+            magicless: false,
+            ultra: false,
+            metrics: None,
+            rsyncable: false,
+            job_size: None,
+            overlap_log: None,
+            #[cfg(feature = "std")]
+            adapt: None,
+        }
+    }
+}
+
+impl EncodeOptions {
+    /// The settings the zstd command line tool uses by default: level 3, a checksum, the content
+    /// size and the window of level 3.
+    ///
+    /// The frame headers describe the frames the same way as the ones of the CLI, only the
+    /// compressed blocks differ.
+    pub fn cli_default() -> Self {
+        Self {
+            level: CompressionLevel::Default,
+            #[cfg(feature = "hash")]
+            checksum: true,
+            content_size: true,
+            window_log: Some(CLI_DEFAULT_WINDOW_LOG),
+            ..Self::default()
+        }
+    }
+}
+
+/// The parameters of [EncodeOptions] that change how a [Strategy] searches for matches, kept by
+/// encoders whose strategy changes while they compress.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SearchOverrides {
+    hash_log: Option<u8>,
+    chain_log: Option<u8>,
+    search_log: Option<u8>,
+    min_match: Option<u8>,
+    target_length: Option<u32>,
+}
+
+impl SearchOverrides {
+    pub(crate) fn new(options: &EncodeOptions) -> Self {
+        Self {
+            hash_log: options.hash_log,
+            chain_log: options.chain_log,
+            search_log: options.search_log,
+            min_match: options.min_match,
+            target_length: options.target_length.filter(|length| *length > 0),
+        }
+    }
+
+    /// The search parameters `search` with the overrides on top.
+    pub(crate) fn apply(self, mut search: SearchParams) -> SearchParams {
+        if let Some(hash_log) = self.hash_log {
+            search.hash_log = u32::from(hash_log);
+        }
+        if let Some(chain_log) = self.chain_log {
+            search.chain_log = u32::from(chain_log);
+            search.max_distance = 1 << chain_log;
+        }
+        if let (Some(search_log), true) = (self.search_log, search.chain_depth > 0) {
+            search.chain_depth = 1 << search_log;
+        }
+        if let Some(min_match) = self.min_match {
+            search.min_match = usize::from(min_match);
+        }
+        if let Some(target_length) = self.target_length {
+            search.target_length = target_length as usize;
+        }
+        search
+    }
+}
@@ -0,0 +1,96 @@
+use alloc::sync::Arc;
+
+use super::match_generator::DictionaryMatcher;
+use super::{EncodeError, Strategy};
+use crate::decoding::dictionary::Dictionary;
+use crate::fallible::{or_abort, AllocError};
+
+/// A [Dictionary] that is prepared for compressing with it, like a `ZSTD_CDict` of the zstd library.
+///
+/// The tables that find matches in the content of the dictionary are built once when it is created,
+/// and every frame that is compressed with it searches them directly. Compressing many small
+/// messages with a large dictionary then only costs as much as the messages themselves. Share it
+/// between encoders with an [Arc], see [EncodeOptions::dictionary](super::EncodeOptions::dictionary).
+///
+/// ```
+/// use std::sync::Arc;
+/// use ruzstd::decoding::dictionary::Dictionary;
+/// use ruzstd::encoding::{EncodeOptions, EncoderDictionary, FrameCompressor, Strategy};
+///
+/// let content = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n".repeat(10);
+/// let dictionary = Arc::new(EncoderDictionary::new(
+///     Dictionary::from_raw_content(1, content),
+///     Strategy::Fast,
+/// ));
+/// for message in [&b"GET /index.html HTTP/1.1\r\n"[..], b"Host: example.com\r\n"] {
+///     let options = EncodeOptions {
+///         dictionary: Some(dictionary.clone()),
+///         ..EncodeOptions::default()
+///     };
+///     let mut compressed = Vec::new();
+///     FrameCompressor::with_options(message, &mut compressed, options)
+///         .compress()
+///         .unwrap();
+/// }
+/// ```
+pub struct EncoderDictionary {
+    dictionary: Dictionary,
+    strategy: Strategy,
+    matcher: Arc<DictionaryMatcher>,
+}
+
+impl EncoderDictionary {
+    /// Build the tables to search the content of `dictionary` the way `strategy` does.
+    ///
+    /// Frames that are compressed with another strategy can use the dictionary as well, but find
+    /// matches in its content only as well as `strategy` would.
+    ///
+    /// Aborts if there is not enough memory for the tables, like the collections of `alloc` do,
+    /// see [EncoderDictionary::try_new].
+    #[allow(clippy::disallowed_methods)]
+    pub fn new(dictionary: Dictionary, strategy: Strategy) -> Self {
+        or_abort(Self::build(dictionary, strategy))
+    }
+
+    /// Like [EncoderDictionary::new], but fails with [EncodeError::OutOfMemory] if there is not
+    /// enough memory for the tables.
+    pub fn try_new(dictionary: Dictionary, strategy: Strategy) -> Result<Self, EncodeError> {
+        Ok(Self::build(dictionary, strategy)?)
+    }
+
+    fn build(dictionary: Dictionary, strategy: Strategy) -> Result<Self, AllocError> {
+        let matcher = DictionaryMatcher::new(&dictionary.dict_content, strategy.search_params())?;
+        // There is no fallible version of `Arc::new` on stable Rust, see the Readme
+        #[allow(clippy::disallowed_methods)]
+        let matcher = Arc::new(matcher);
+        Ok(Self {
+            dictionary,
+            strategy,
+            matcher,
+        })
+    }
+
+    /// The dictionary this was built from.
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dictionary
+    }
+
+    /// The strategy the content of the dictionary is searched with.
+    pub fn strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub(crate) fn matcher(&self) -> &Arc<DictionaryMatcher> {
+        &self.matcher
+    }
+}
+
+impl core::fmt::Debug for EncoderDictionary {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // The search tables are large and not interesting to look at
+        f.debug_struct("EncoderDictionary")
+            .field("dictionary", &self.dictionary)
+            .field("strategy", &self.strategy)
+            .finish_non_exhaustive()
+    }
+}
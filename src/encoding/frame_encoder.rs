@@ -1,19 +1,41 @@
 //! Utilities and interfaces for encoding an entire frame.
 
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::convert::{TryFrom, TryInto};
+use core::num::NonZeroU32;
+use core::str::FromStr;
 
-use super::{
-    block_header::BlockHeader,
-    blocks::{compress_block, compress_raw_block},
-    frame_header::FrameHeader,
-    match_generator::MatchGenerator,
-};
+use super::frame_header::window_descriptor;
+use super::{Chunker, EncodeOptions, EncoderDictionary, IncrementalEncoder, Strategy};
 
-use crate::io::{Read, Write};
+use crate::decoding::dictionary::Dictionary;
+use crate::fallible::AllocError;
+use crate::huff0::huff0_encoder::HuffmanEncoderError;
+use crate::io::{Error, Read, Write};
+use crate::metrics::CodecMetrics;
 
-/// Blocks cannot be larger than 128KB in size.
-const MAX_BLOCK_SIZE: usize = 128 * 1024 - 20;
+/// The most input the encoder puts into one block, and the largest block size that can be passed to
+/// [FrameCompressor::set_block_size]. Blocks cannot be larger than 128KB in size.
+pub const MAX_BLOCK_SIZE: usize = 128 * 1024 - 20;
+/// The magic number of the skippable frames `pzstd` stores the compressed size of the following frame in.
+const PZSTD_MAGIC_NUM: u32 = 0x184D_2A50;
+/// The window size declared in the frame header of frames whose content is larger or of unknown
+/// size, sequences never reach further back than this. See [frame_window_size].
+pub(super) const WINDOW_SIZE: u64 = 128 * 1024;
+
+/// The smallest window log that can be passed to [FrameCompressor::set_window_log].
+pub const MIN_WINDOW_LOG: u8 = 10;
+/// The largest window log that can be passed to [FrameCompressor::set_window_log], the format
+/// does not allow larger windows.
+pub const MAX_WINDOW_LOG: u8 = 41;
+/// The largest window log that can be used without [EncodeOptions::ultra], which allows windows of
+/// up to 8 MiB like the levels up to 19 of the reference implementation use.
+pub const MAX_WINDOW_LOG_WITHOUT_ULTRA: u8 = 23;
+
+/// The largest acceleration of [CompressionLevel::Negative], like the lowest level of zstd,
+/// -131072. Larger values compress like it.
+pub const MAX_ACCELERATION: u32 = 128 * 1024;
 
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ZstdLevel(u8);
@@ -27,14 +49,16 @@ macro_rules! zl_from_int_impls {
                 if val <= 22 {
                     Ok(Self(val))
                 } else {
-                    0u8.try_into()
+                    // TryFromIntError can not be constructed directly
+                    u8::try_from(u16::MAX).map(Self)
                 }
             }
         }
 
         impl From<ZstdLevel> for $t {
             fn from(val: ZstdLevel) -> $t {
-                val.0.try_into().unwrap()
+                // Levels are at most 22, which every integer type holds
+                val.0.try_into().unwrap_or_default()
             }
         }
     )*};
@@ -48,7 +72,11 @@ impl From<CompressionLevel> for ZstdLevel {
             CompressionLevel::Default => Self(3),
             CompressionLevel::Better => Self(7),
             CompressionLevel::Best => Self(11),
-            CompressionLevel::Other(zl) => zl
+            CompressionLevel::Other(zl) => zl,
+            // Has no number of its own, zstd uses level 3 when none is given
+            CompressionLevel::Auto => Self(3),
+            // There are no negative zstd levels, level 1 is the closest one
+            CompressionLevel::Negative(_) => Self(1),
         }
     }
 }
@@ -62,6 +90,7 @@ zl_from_int_impls! {
 /// and resulting compression ratios. Faster compression will result
 /// in worse compression ratios, and vice versa.
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum CompressionLevel {
     /// This level does not compress the data at all, and simply wraps
     /// it in a Zstandard frame.
@@ -76,21 +105,48 @@ pub enum CompressionLevel {
     /// or the one used by the official compressor when no level
     /// is specified.
     ///
-    /// UNIMPLEMENTED
+    /// EXPERIMENTAL IMPLEMENTED, searches with [Strategy::DFast]
     Default,
 
     /// This level is roughly equivalent to Zstd level 7.
     ///
-    /// UNIMPLEMENTED
+    /// EXPERIMENTAL IMPLEMENTED, searches with [Strategy::Lazy]
     Better,
 
     /// This level is roughly equivalent to Zstd level 11.
     ///
-    /// UNIMPLEMENTED
+    /// EXPERIMENTAL IMPLEMENTED, searches with [Strategy::Lazy2]
     Best,
 
     /// This level allows to set a custom Zstd level between 0~22.
+    ///
+    /// Levels 20 to 22 need [EncodeOptions::ultra]. They use the windows of the reference
+    /// implementation (32, 64 and 128 MiB) and, unless [EncodeOptions::strategy] is set, search
+    /// with [Strategy::BtUltra], comparing up to 512, 1024 and 2048 earlier positions. Decoders
+    /// need [set_ultra](crate::frame_decoder::FrameDecoder::set_ultra) for the window of level 22.
+    ///
+    /// Levels 2 to 19 use the windows the reference implementation uses for large inputs (1 MiB at
+    /// 2, 2 MiB at 3 to 8, 4 MiB at 9 to 16 and 8 MiB from 17 on) and, unless
+    /// [EncodeOptions::strategy] is set, search with the strategy it picks for them:
+    /// [Strategy::DFast] at 2 and 3, [Strategy::Greedy] at 4 and 5, [Strategy::Lazy] at 6 to 8,
+    /// [Strategy::Lazy2] at 9 to 12 and [Strategy::BtLazy2] from 13 on.
     Other(ZstdLevel),
+
+    /// Look at the beginning of the input (up to 64 KiB of the first data that is written) to
+    /// estimate how well it compresses, and pick a level and [Strategy] for it. Data that does not
+    /// compress is stored uncompressed, other data gets a search that fits it.
+    ///
+    /// A strategy set with [EncodeOptions::strategy] is kept. For tools that compress arbitrary
+    /// files and have no way to ask for a level.
+    Auto,
+
+    /// The negative level `-n` of zstd (`zstd --fast=n`), which trades ratio for speed.
+    ///
+    /// Compresses like [CompressionLevel::Fastest], but after each position without a match the
+    /// search skips `n - 1` positions, and more the longer no match was found. Only the start and
+    /// the end of each match are remembered for later matches, and literals are stored without
+    /// Huffman coding. Levels below `-`[MAX_ACCELERATION] compress like that level.
+    Negative(NonZeroU32),
 }
 impl CompressionLevel {
     pub fn normalize_mut(&mut self) {
@@ -99,7 +155,7 @@ impl CompressionLevel {
         }
     }
     pub fn normalize(&self) -> Self {
-        let mut out = self.clone();
+        let mut out = *self;
         out.normalize_mut();
         out
     }
@@ -118,6 +174,326 @@ impl From<ZstdLevel> for CompressionLevel {
     }
 }
 
+impl core::fmt::Display for ZstdLevel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FromStr for ZstdLevel {
+    type Err = ParseLevelError;
+
+    /// Parse a level between 0 and 22.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let level: i64 = s.trim().parse().map_err(|_| ParseLevelError::Unknown)?;
+        ZstdLevel::try_from(level).map_err(|_| ParseLevelError::OutOfRange { level })
+    }
+}
+
+/// Formats the level like [FromStr] expects it: the named levels by their lowercase name,
+/// the others as their number.
+impl core::fmt::Display for CompressionLevel {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.normalize() {
+            CompressionLevel::Uncompressed => write!(f, "uncompressed"),
+            CompressionLevel::Fastest => write!(f, "fastest"),
+            CompressionLevel::Default => write!(f, "default"),
+            CompressionLevel::Better => write!(f, "better"),
+            CompressionLevel::Best => write!(f, "best"),
+            CompressionLevel::Other(level) => write!(f, "{}", level),
+            CompressionLevel::Auto => write!(f, "auto"),
+            CompressionLevel::Negative(n) => write!(f, "-{}", n),
+        }
+    }
+}
+
+impl FromStr for CompressionLevel {
+    type Err = ParseLevelError;
+
+    /// Parse one of the names `uncompressed`, `fastest`, `default`, `better`, `best` and `auto`
+    /// (ignoring case) or a zstd level between -131072 and 22. Numbers of named levels turn into
+    /// the named level, e.g. `"3"` is parsed as [CompressionLevel::Default], negative numbers
+    /// into [CompressionLevel::Negative].
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let named = [
+            ("uncompressed", CompressionLevel::Uncompressed),
+            ("fastest", CompressionLevel::Fastest),
+            ("default", CompressionLevel::Default),
+            ("better", CompressionLevel::Better),
+            ("best", CompressionLevel::Best),
+            ("auto", CompressionLevel::Auto),
+        ];
+        if let Some((_, level)) = named.iter().find(|(name, _)| name.eq_ignore_ascii_case(s)) {
+            return Ok(*level);
+        }
+        match s.parse::<ZstdLevel>() {
+            Err(ParseLevelError::OutOfRange { level })
+                if (-i64::from(MAX_ACCELERATION)..0).contains(&level) =>
+            {
+                let n = u32::try_from(-level).ok().and_then(NonZeroU32::new);
+                n.map(CompressionLevel::Negative)
+                    .ok_or(ParseLevelError::OutOfRange { level })
+            }
+            parsed => parsed.map(CompressionLevel::from),
+        }
+    }
+}
+
+/// A string could not be parsed as a [CompressionLevel] or [ZstdLevel].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ParseLevelError {
+    /// The string is neither a level name nor a number.
+    Unknown,
+    /// The number is not a zstd level: between -131072 and 22 for [CompressionLevel], between 0
+    /// and 22 for [ZstdLevel].
+    OutOfRange { level: i64 },
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseLevelError {}
+
+impl core::fmt::Display for ParseLevelError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ParseLevelError::Unknown => write!(
+                f,
+                "Expected a number or one of uncompressed, fastest, default, better, best, auto"
+            ),
+            ParseLevelError::OutOfRange { level } => {
+                write!(f, "Level {} is out of range", level)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum EncodeError {
+    /// The requested compression level is not implemented yet.
+    UnsupportedLevel {
+        level: CompressionLevel,
+    },
+    FailedToReadInput(Error),
+    FailedToWriteOutput(Error),
+    /// The input, or a part of it, is too big to be encoded with the current configuration.
+    InputTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// The encoder tried to produce a Huffman table or stream that violates the format.
+    /// This is a bug in this library.
+    HuffmanEncoderError(HuffmanEncoderError),
+    /// An internal invariant of the encoder was violated. This is a bug in this library.
+    Internal {
+        message: &'static str,
+    },
+    /// The content of a frame does not have the size that was declared in its header,
+    /// see [IncrementalEncoder::start].
+    ContentSizeMismatch {
+        declared: u64,
+        actual: u64,
+    },
+    /// The options can not be used, e.g. because the window log or the block size are out of
+    /// range. [FrameCompressorBuilder::build] reports the same problems before compressing.
+    InvalidConfig(ConfigError),
+    /// A [FrameHeader](crate::encoding::FrameHeader) describes a frame that can not exist.
+    InvalidFrameHeader {
+        message: &'static str,
+    },
+    /// A buffer or table of the encoder could not be allocated.
+    OutOfMemory {
+        size: usize,
+    },
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EncodeError::FailedToReadInput(source) => Some(source),
+            EncodeError::FailedToWriteOutput(source) => Some(source),
+            EncodeError::HuffmanEncoderError(source) => Some(source),
+            EncodeError::InvalidConfig(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl core::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EncodeError::UnsupportedLevel { level } => {
+                write!(f, "Compression level {:?} is not implemented yet", level)
+            }
+            EncodeError::FailedToReadInput(e) => {
+                write!(f, "Failed to read the uncompressed data: {}", e)
+            }
+            EncodeError::FailedToWriteOutput(e) => {
+                write!(f, "Failed to write the compressed data: {}", e)
+            }
+            EncodeError::InputTooLarge { size, max } => {
+                write!(
+                    f,
+                    "Input of {} bytes is too large for this configuration, at most {} bytes are supported",
+                    size, max
+                )
+            }
+            EncodeError::HuffmanEncoderError(e) => {
+                write!(f, "Internal error while encoding literals: {}", e)
+            }
+            EncodeError::Internal { message } => {
+                write!(f, "Internal encoder error: {}", message)
+            }
+            EncodeError::ContentSizeMismatch { declared, actual } => {
+                write!(
+                    f,
+                    "The frame header declares {} bytes of content, but {} bytes were written",
+                    declared, actual
+                )
+            }
+            EncodeError::InvalidConfig(e) => {
+                write!(f, "Invalid encoder configuration: {}", e)
+            }
+            EncodeError::InvalidFrameHeader { message } => {
+                write!(f, "Invalid frame header: {}", message)
+            }
+            EncodeError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for the encoder", size)
+            }
+        }
+    }
+}
+
+impl From<HuffmanEncoderError> for EncodeError {
+    fn from(val: HuffmanEncoderError) -> Self {
+        Self::HuffmanEncoderError(val)
+    }
+}
+
+impl From<ConfigError> for EncodeError {
+    fn from(val: ConfigError) -> Self {
+        Self::InvalidConfig(val)
+    }
+}
+
+impl From<AllocError> for EncodeError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
+/// A [FrameCompressorBuilder] was configured with settings that can not be used together,
+/// or that this library does not support.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The requested compression level is not implemented yet.
+    UnsupportedLevel { level: CompressionLevel },
+    /// The window log is outside of [MIN_WINDOW_LOG]..=[MAX_WINDOW_LOG].
+    InvalidWindowLog { window_log: u8 },
+    /// The block size is zero or larger than [MAX_BLOCK_SIZE].
+    InvalidBlockSize { block_size: usize },
+    /// A checksum was requested, but the `hash` feature that calculates it is disabled.
+    ChecksumUnsupported,
+    /// The level or window log needs a window larger than [MAX_WINDOW_LOG_WITHOUT_ULTRA] allows, but
+    /// [EncodeOptions::ultra] is not set.
+    UltraRequired {
+        level: CompressionLevel,
+        window_log: u8,
+    },
+    /// The content of the dictionary does not fit into the window, so the frames could not
+    /// reference all of it.
+    DictionaryLargerThanWindow {
+        dictionary_size: usize,
+        window_size: u64,
+    },
+    /// The frame size for `pzstd` output is zero.
+    InvalidPzstdFrameSize,
+    /// `pzstd` output precedes each frame with a skippable frame, which decoders can not tell apart
+    /// from a frame without magic number.
+    MagiclessSkippableFrames,
+    /// A search parameter like [EncodeOptions::hash_log] is outside of `min..=max`.
+    InvalidParameter {
+        parameter: &'static str,
+        value: u32,
+        min: u32,
+        max: u32,
+    },
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConfigError {}
+
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ConfigError::UnsupportedLevel { level } => {
+                write!(f, "Compression level {:?} is not implemented yet", level)
+            }
+            ConfigError::InvalidWindowLog { window_log } => {
+                write!(
+                    f,
+                    "Window log {} is invalid, it has to be between {} and {}",
+                    window_log, MIN_WINDOW_LOG, MAX_WINDOW_LOG
+                )
+            }
+            ConfigError::InvalidBlockSize { block_size } => {
+                write!(
+                    f,
+                    "Block size {} is invalid, it has to be between 1 and {}",
+                    block_size, MAX_BLOCK_SIZE
+                )
+            }
+            ConfigError::ChecksumUnsupported => {
+                write!(
+                    f,
+                    "Checksums can only be written with the \"hash\" feature enabled"
+                )
+            }
+            ConfigError::UltraRequired { level, window_log } => {
+                write!(
+                    f,
+                    "Compression level {:?} with window log {} needs more than 8 MiB of memory to decode, allow it with EncodeOptions::ultra",
+                    level, window_log
+                )
+            }
+            ConfigError::DictionaryLargerThanWindow {
+                dictionary_size,
+                window_size,
+            } => {
+                write!(
+                    f,
+                    "The dictionary content of {} bytes does not fit into the window of {} bytes",
+                    dictionary_size, window_size
+                )
+            }
+            ConfigError::InvalidPzstdFrameSize => {
+                write!(f, "Frames need to contain at least one byte")
+            }
+            ConfigError::MagiclessSkippableFrames => {
+                write!(
+                    f,
+                    "Frames without magic number can not be preceded by the skippable frames of pzstd"
+                )
+            }
+            ConfigError::InvalidParameter {
+                parameter,
+                value,
+                min,
+                max,
+            } => {
+                write!(
+                    f,
+                    "The {} {} is invalid, it has to be between {} and {}",
+                    parameter, value, min, max
+                )
+            }
+        }
+    }
+}
+
 /// An interface for compressing arbitrary data with the ZStandard compression algorithm.
 ///
 /// `FrameCompressor` will generally be used by:
@@ -127,18 +503,21 @@ impl From<ZstdLevel> for CompressionLevel {
 /// # Examples
 /// ```
 /// use ruzstd::encoding::{FrameCompressor, CompressionLevel};
-/// let mock_data = &[0x1, 0x2, 0x3, 0x4];
+/// let mock_data: &[u8] = &[0x1, 0x2, 0x3, 0x4];
+/// let mut output = Vec::new();
 /// // Initialize a compressor.
-/// let compressor = FrameCompressor::new(mock_data, CompressionLevel::Uncompressed);
+/// let mut compressor = FrameCompressor::new(mock_data, &mut output, CompressionLevel::Uncompressed);
 ///
-/// let mut output = Vec::new();
 /// // `compress` writes the compressed output into the provided buffer.
-/// compressor.compress(&mut output);
+/// compressor.compress().unwrap();
 /// ```
 pub struct FrameCompressor<R: Read, W: Write> {
     uncompressed_data: R,
     compressed_data: W,
-    compression_level: CompressionLevel,
+    options: EncodeOptions,
+    pzstd_frame_size: Option<usize>,
+    chunker: Option<Chunker>,
+    patch_reference: Vec<u8>,
 }
 
 impl<R: Read, W: Write> FrameCompressor<R, W> {
@@ -146,238 +525,2133 @@ impl<R: Read, W: Write> FrameCompressor<R, W> {
     pub fn new(
         uncompressed_data: R,
         compressed_data: W,
-        mut compression_level: CompressionLevel,
+        compression_level: CompressionLevel,
+    ) -> FrameCompressor<R, W> {
+        Self::with_options(
+            uncompressed_data,
+            compressed_data,
+            EncodeOptions {
+                level: compression_level,
+                ..EncodeOptions::default()
+            },
+        )
+    }
+
+    /// Create a new `FrameCompressor` that encodes frames with the given options.
+    ///
+    /// Invalid options, like a window log or block size that is out of range, make
+    /// [FrameCompressor::compress] return [EncodeError::InvalidConfig].
+    pub fn with_options(
+        uncompressed_data: R,
+        compressed_data: W,
+        mut options: EncodeOptions,
     ) -> FrameCompressor<R, W> {
-        compression_level.normalize_mut();
+        options.level.normalize_mut();
         Self {
             uncompressed_data,
             compressed_data,
-            compression_level,
+            options,
+            pzstd_frame_size: None,
+            chunker: None,
+            patch_reference: Vec::new(),
         }
     }
 
-    /// Compress the uncompressed data into a valid Zstd frame and write it into the provided buffer
-    pub fn compress(&mut self) {
-        let mut output = Vec::with_capacity(1024 * 130);
-        let output = &mut output;
-        let header = FrameHeader {
-            frame_content_size: None,
-            single_segment: false,
-            content_checksum: false,
-            dictionary_id: None,
-            window_size: Some(256 * 1024),
-        };
-        header.serialize(output);
+    /// The options frames are encoded with.
+    pub fn options(&self) -> &EncodeOptions {
+        &self.options
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.compressed_data
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.compressed_data
+    }
+
+    /// Gets a reference to the reader the uncompressed data is read from.
+    pub fn source(&self) -> &R {
+        &self.uncompressed_data
+    }
+
+    /// Gets a mutable reference to the reader the uncompressed data is read from.
+    pub fn source_mut(&mut self) -> &mut R {
+        &mut self.uncompressed_data
+    }
+
+    /// Destructures this object into the inner writer.
+    pub fn into_inner(self) -> W {
+        self.compressed_data
+    }
+
+    /// Destructures this object into both the inner reader and writer.
+    pub fn into_parts(self) -> (R, W) {
+        (self.uncompressed_data, self.compressed_data)
+    }
 
+    /// Compress the uncompressed data into a valid Zstd frame and write it into the provided buffer
+    ///
+    /// Returns [EncodeError::UnsupportedLevel] for compression levels that are not implemented yet,
+    /// and for levels 20 to 22 without [EncodeOptions::ultra]. Returns [EncodeError::InvalidConfig]
+    /// before reading any input if the options are invalid.
+    pub fn compress(&mut self) -> Result<(), EncodeError> {
+        check_sizes(&self.options)?;
+        if self.pzstd_frame_size == Some(0) {
+            return Err(ConfigError::InvalidPzstdFrameSize.into());
+        }
         // TODO dont read input completely into memory here, work on a window of input
         let mut uncompressed_data = Vec::new();
         self.uncompressed_data
             .read_to_end(&mut uncompressed_data)
-            .unwrap();
-        let uncompressed_data = uncompressed_data;
-        let mut matcher = MatchGenerator::new(1024 * 128);
-
-        // Special handling is needed for compression of a totally empty file (why you'd want to do that, I don't know)
-        if uncompressed_data.is_empty() {
-            let header = BlockHeader {
-                last_block: true,
-                block_type: crate::blocks::block::BlockType::Raw,
-                block_size: 0,
-            };
-            // Write the header, then the block
-            header.serialize(output);
-        }
-
-        match self.compression_level {
-            CompressionLevel::Uncompressed => {
-                // Blocks are compressed by writing a header, then writing
-                // the block in repetition until the last block is reached.
-                let mut index = 0;
-                while index < uncompressed_data.len() {
-                    let last_block = index + MAX_BLOCK_SIZE >= uncompressed_data.len();
-                    // We read till the end of the data, or till the max block size, whichever comes sooner
-                    let block_size = if last_block {
-                        uncompressed_data.len() - index
-                    } else {
-                        MAX_BLOCK_SIZE
-                    };
-                    let header = BlockHeader {
-                        last_block,
-                        block_type: crate::blocks::block::BlockType::Raw,
-                        block_size: block_size.try_into().unwrap(),
-                    };
-                    // Write the header, then the block
-                    header.serialize(output);
-                    compress_raw_block(&uncompressed_data[index..(index + block_size)], output);
-                    index += block_size;
-                }
-            }
-            CompressionLevel::Fastest => {
-                let mut index = 0;
-                while index < uncompressed_data.len() {
-                    let last_block = index + MAX_BLOCK_SIZE >= uncompressed_data.len();
-                    // We read till the end of the data, or till the max block size, whichever comes sooner
-                    let block_size = if last_block {
-                        uncompressed_data.len() - index
-                    } else {
-                        MAX_BLOCK_SIZE
-                    };
-
-                    let uncompressed = &uncompressed_data[index..(index + block_size)];
-
-                    if uncompressed.iter().all(|x| uncompressed[0].eq(x)) {
-                        matcher.add_data_no_matching(uncompressed);
-                        let header = BlockHeader {
-                            last_block,
-                            block_type: crate::blocks::block::BlockType::RLE,
-                            block_size: uncompressed.len().try_into().unwrap(),
-                        };
-                        // Write the header, then the block
-                        header.serialize(output);
-                        output.push(uncompressed[0]);
-                    } else {
-                        let mut compressed = Vec::new();
-                        compress_block(&mut matcher, uncompressed, &mut compressed);
-                        if compressed.len() >= MAX_BLOCK_SIZE {
-                            let header = BlockHeader {
-                                last_block,
-                                block_type: crate::blocks::block::BlockType::Raw,
-                                block_size: block_size.try_into().unwrap(),
-                            };
-                            // Write the header, then the block
-                            header.serialize(output);
-                            compress_raw_block(uncompressed, output);
-                        } else {
-                            let header = BlockHeader {
-                                last_block,
-                                block_type: crate::blocks::block::BlockType::Compressed,
-                                block_size: (compressed.len()).try_into().unwrap(),
-                            };
-                            // Write the header, then the block
-                            header.serialize(output);
-                            output.extend(compressed);
-                        }
-                    }
-                    index += block_size;
-                    self.compressed_data.write_all(output).unwrap();
-                    output.clear();
-                }
-            }
+            .map_err(EncodeError::FailedToReadInput)?;
 
-            CompressionLevel::Default => {
-                unimplemented!();
+        let prefix = Prefix::new(self.options.dictionary.as_deref(), &self.patch_reference);
+        if let Some(chunker) = &self.chunker {
+            // Even empty input needs one frame
+            let empty: &[u8] = &[];
+            let chunks = chunker
+                .chunks(&uncompressed_data)
+                .chain(uncompressed_data.is_empty().then_some(empty));
+            for chunk in chunks {
+                compress_frame(&self.options, chunk, prefix, &mut self.compressed_data)?;
             }
+            return Ok(());
+        }
+        let Some(frame_size) = self.pzstd_frame_size else {
+            return compress_frame(
+                &self.options,
+                &uncompressed_data,
+                prefix,
+                &mut self.compressed_data,
+            );
+        };
+        let mut frame = Vec::new();
+        // Even empty input needs one frame
+        let empty: &[u8] = &[];
+        let chunks = uncompressed_data
+            .chunks(frame_size)
+            .chain(uncompressed_data.is_empty().then_some(empty));
+        for chunk in chunks {
+            frame.clear();
+            compress_frame(&self.options, chunk, prefix, &mut frame)?;
+            let frame_len = u32::try_from(frame.len()).map_err(|_| EncodeError::InputTooLarge {
+                size: chunk.len(),
+                max: u32::MAX as usize,
+            })?;
+            let mut size_hint = [0u8; 12];
+            size_hint[0..4].copy_from_slice(&PZSTD_MAGIC_NUM.to_le_bytes());
+            size_hint[4..8].copy_from_slice(&4u32.to_le_bytes());
+            size_hint[8..12].copy_from_slice(&frame_len.to_le_bytes());
+            self.compressed_data
+                .write_all(&size_hint)
+                .map_err(EncodeError::FailedToWriteOutput)?;
+            self.compressed_data
+                .write_all(&frame)
+                .map_err(EncodeError::FailedToWriteOutput)?;
+        }
+        Ok(())
+    }
 
-            CompressionLevel::Better => {
-                unimplemented!();
-            }
+    /// Split the input into independent frames of `frame_size` uncompressed bytes, in the format
+    /// written by `pzstd`.
+    ///
+    /// Each frame is preceded by a skippable frame that contains the compressed size of the frame,
+    /// which allows `pzstd` to decompress the frames in parallel. Other decoders just skip the hints.
+    /// Frames do not reference data in earlier frames, so smaller frames compress worse.
+    ///
+    /// `None` (the default) writes a single frame. [FrameCompressor::compress] returns
+    /// [EncodeError::InvalidConfig] if `frame_size` is zero.
+    pub fn set_pzstd_frame_size(&mut self, frame_size: Option<usize>) {
+        self.pzstd_frame_size = frame_size;
+        if frame_size.is_some() {
+            self.chunker = None;
+        }
+    }
 
-            CompressionLevel::Best => {
-                unimplemented!();
-            }
+    /// Split the input into independent frames at the content-defined boundaries that `chunker`
+    /// finds, one frame per chunk.
+    ///
+    /// Unchanged parts of the input end up in the same frames when other parts are edited, which
+    /// lets backup and deduplication systems store them once. The frames are ordinary zstd frames
+    /// that follow each other. Like with [pzstd frames](FrameCompressor::set_pzstd_frame_size),
+    /// frames do not reference data in earlier frames, so they compress worse than a single one.
+    ///
+    /// `None` (the default) writes a single frame. Replaces the `pzstd` frame size, if one was set.
+    pub fn set_chunker(&mut self, chunker: Option<Chunker>) {
+        self.chunker = chunker;
+        if chunker.is_some() {
+            self.pzstd_frame_size = None;
+        }
+    }
 
-            CompressionLevel::Other(level) => {
-                unimplemented!("zstd level {} is not implemented yet", level.0);
-            }
+    /// Compress the input as a patch against `reference`, like `zstd --patch-from` does.
+    ///
+    /// The reference is used as a prefix that sequences can match against, so data that is
+    /// shared with the reference (e.g. an older version of the same file) is encoded very cheaply.
+    /// The window is enlarged to cover the reference and the input.
+    ///
+    /// Decoders need the same reference to decompress the result: add it as a raw content
+    /// dictionary ([Dictionary::from_raw_content](crate::decoding::dictionary::Dictionary::from_raw_content))
+    /// and select it with [FrameDecoder::force_dict](crate::frame_decoder::FrameDecoder::force_dict)
+    /// after reading each frame header. An empty reference (the default) disables this.
+    ///
+    /// Replaces the [dictionary](FrameCompressor::set_dictionary), if one was set.
+    pub fn set_patch_reference(&mut self, reference: Vec<u8>) {
+        self.patch_reference = reference;
+        self.options.dictionary = None;
+    }
+
+    /// Compress the input with a dictionary. The ID of the dictionary is written into the frame
+    /// header, so decoders know which dictionary they need.
+    ///
+    /// Only the content of the dictionary is used, as a prefix that sequences can match against,
+    /// and the repeat offsets it stores. Parts of the content that are further away from the
+    /// input than the window reaches can not be referenced.
+    ///
+    /// The tables to search the content are built for the [strategy](EncodeOptions::strategy)
+    /// that is set when this is called. To compress many inputs with the same dictionary, build
+    /// them once with an [EncoderDictionary] and use [FrameCompressor::set_encoder_dictionary].
+    ///
+    /// Replaces the [patch reference](FrameCompressor::set_patch_reference), if one was set.
+    /// Aborts like [EncoderDictionary::new] if there is not enough memory for the tables.
+    #[allow(clippy::disallowed_methods)]
+    pub fn set_dictionary(&mut self, dictionary: Option<Dictionary>) {
+        let strategy = self.options.strategy.unwrap_or(Strategy::Fast);
+        self.set_encoder_dictionary(
+            dictionary.map(|dictionary| Arc::new(EncoderDictionary::new(dictionary, strategy))),
+        );
+    }
+
+    /// Compress the input with a dictionary whose search tables were built before, see
+    /// [FrameCompressor::set_dictionary].
+    pub fn set_encoder_dictionary(&mut self, dictionary: Option<Arc<EncoderDictionary>>) {
+        if dictionary.is_some() {
+            self.patch_reference = Vec::new();
         }
-        self.compressed_data.write_all(output).unwrap();
+        self.options.dictionary = dictionary;
+    }
+
+    /// Put at most `block_size` bytes of the input into one block. Smaller blocks let decoders
+    /// produce output in smaller steps, but compress worse.
+    ///
+    /// `None` (the default) uses [MAX_BLOCK_SIZE]. [FrameCompressor::compress] returns
+    /// [EncodeError::InvalidConfig] if `block_size` is zero or larger than [MAX_BLOCK_SIZE].
+    pub fn set_block_size(&mut self, block_size: Option<usize>) {
+        self.options.block_size = block_size;
+    }
+
+    /// Use a window of at least `2^window_log` bytes, regardless of the compression level.
+    ///
+    /// A larger window lets sequences reference data further back, which helps with long range
+    /// redundancy in big inputs. Decoders need as much memory as the window is large, so they may
+    /// refuse frames with big windows (see [FrameDecoder::set_max_window_size](crate::frame_decoder::FrameDecoder::set_max_window_size)).
+    /// With a [patch reference](FrameCompressor::set_patch_reference) the window is always large
+    /// enough to cover the reference and the input.
+    ///
+    /// `None` (the default) uses the window size of the compression level.
+    /// [FrameCompressor::compress] returns [EncodeError::InvalidConfig] if `window_log` is outside
+    /// of [MIN_WINDOW_LOG]..=[MAX_WINDOW_LOG], or larger than [MAX_WINDOW_LOG_WITHOUT_ULTRA]
+    /// without [EncodeOptions::ultra].
+    pub fn set_window_log(&mut self, window_log: Option<u8>) {
+        self.options.window_log = window_log;
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use alloc::vec;
+/// Configures a [FrameCompressor] step by step. Unlike the constructors of [FrameCompressor],
+/// invalid settings are reported as a [ConfigError] by [FrameCompressorBuilder::build]
+/// instead of by [FrameCompressor::compress].
+///
+/// # Examples
+/// ```
+/// use ruzstd::encoding::{CompressionLevel, FrameCompressorBuilder};
+/// let data: &[u8] = &[0x1, 0x2, 0x3, 0x4];
+/// let mut output = Vec::new();
+/// let mut compressor = FrameCompressorBuilder::new()
+///     .level(CompressionLevel::Fastest)
+///     .window_log(20)
+///     .content_size(true)
+///     .build(data, &mut output)
+///     .unwrap();
+/// compressor.compress().unwrap();
+/// ```
+#[derive(Default)]
+pub struct FrameCompressorBuilder {
+    options: EncodeOptions,
+    checksum: bool,
+    pzstd_frame_size: Option<usize>,
+    chunker: Option<Chunker>,
+    /// Prepared in [FrameCompressorBuilder::build], once the strategy is known
+    dictionary: Option<Dictionary>,
+}
 
-    use super::FrameCompressor;
-    use crate::{frame::MAGIC_NUM, FrameDecoder};
-    use alloc::vec::Vec;
+impl FrameCompressorBuilder {
+    /// Start with the [default options](EncodeOptions::default).
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    #[test]
-    fn frame_starts_with_magic_num() {
-        let mock_data = [1_u8, 2, 3].as_slice();
-        let mut output: Vec<u8> = Vec::new();
-        let mut compressor = FrameCompressor::new(
-            mock_data,
-            &mut output,
-            super::CompressionLevel::Uncompressed,
-        );
-        compressor.compress();
-        assert!(output.starts_with(&MAGIC_NUM.to_le_bytes()));
+    /// Replace all settings with `options`, e.g. to start from [EncodeOptions::cli_default].
+    pub fn options(mut self, options: EncodeOptions) -> Self {
+        #[cfg(feature = "hash")]
+        {
+            self.checksum = options.checksum;
+        }
+        self.options = options;
+        self.dictionary = None;
+        self
     }
 
-    #[test]
-    fn very_simple_raw_compress() {
-        let mock_data = [1_u8, 2, 3].as_slice();
-        let mut output: Vec<u8> = Vec::new();
-        let mut compressor = FrameCompressor::new(
-            mock_data,
-            &mut output,
-            super::CompressionLevel::Uncompressed,
-        );
-        compressor.compress();
+    /// The compression level, [CompressionLevel::Fastest] by default.
+    pub fn level(mut self, level: CompressionLevel) -> Self {
+        self.options.level = level;
+        self
     }
 
-    #[test]
-    fn very_simple_compress() {
-        let mut mock_data = vec![0; 1 << 17];
-        mock_data.extend(vec![1; (1 << 17) - 1]);
-        mock_data.extend(vec![2; (1 << 18) - 1]);
-        mock_data.extend(vec![2; 1 << 17]);
-        mock_data.extend(vec![3; (1 << 17) - 1]);
-        let mut output: Vec<u8> = Vec::new();
-        let mut compressor = FrameCompressor::new(
-            mock_data.as_slice(),
-            &mut output,
-            super::CompressionLevel::Uncompressed,
-        );
-        compressor.compress();
+    /// Search for matches with `strategy` instead of the one of the level, see [EncodeOptions::strategy].
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.options.strategy = Some(strategy);
+        self
+    }
 
-        let mut decoder = FrameDecoder::new();
-        let mut decoded = Vec::with_capacity(mock_data.len());
-        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
-        assert_eq!(mock_data, decoded);
+    /// Append a checksum of the content to each frame, see [EncodeOptions].
+    /// Requires the `hash` feature.
+    pub fn checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+        self
+    }
 
-        let mut decoded = Vec::new();
-        zstd::stream::copy_decode(output.as_slice(), &mut decoded).unwrap();
-        assert_eq!(mock_data, decoded);
+    /// Use a window of at least `2^window_log` bytes, see [FrameCompressor::set_window_log].
+    pub fn window_log(mut self, window_log: u8) -> Self {
+        self.options.window_log = Some(window_log);
+        self
     }
 
-    #[test]
-    fn rle_compress() {
-        let mock_data = vec![0; 1 << 19];
-        let mut output: Vec<u8> = Vec::new();
-        let mut compressor = FrameCompressor::new(
-            mock_data.as_slice(),
-            &mut output,
-            super::CompressionLevel::Uncompressed,
-        );
-        compressor.compress();
+    /// Keep at most `2^hash_log` positions in the hash table of each block, see
+    /// [EncodeOptions::hash_log].
+    pub fn hash_log(mut self, hash_log: u8) -> Self {
+        self.options.hash_log = Some(hash_log);
+        self
+    }
 
-        let mut decoder = FrameDecoder::new();
-        let mut decoded = Vec::with_capacity(mock_data.len());
-        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
-        assert_eq!(mock_data, decoded);
+    /// Only compare earlier positions up to `2^chain_log` bytes back, see [EncodeOptions::chain_log].
+    pub fn chain_log(mut self, chain_log: u8) -> Self {
+        self.options.chain_log = Some(chain_log);
+        self
     }
 
-    #[test]
-    fn aaa_compress() {
-        let mock_data = vec![0, 1, 3, 4, 5];
-        let mut output: Vec<u8> = Vec::new();
-        let mut compressor = FrameCompressor::new(
-            mock_data.as_slice(),
-            &mut output,
-            super::CompressionLevel::Uncompressed,
-        );
-        compressor.compress();
+    /// Compare up to `2^search_log` earlier positions, see [EncodeOptions::search_log].
+    pub fn search_log(mut self, search_log: u8) -> Self {
+        self.options.search_log = Some(search_log);
+        self
+    }
 
-        let mut decoder = FrameDecoder::new();
-        let mut decoded = Vec::with_capacity(mock_data.len());
-        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
-        assert_eq!(mock_data, decoded);
+    /// Only use matches of at least `min_match` bytes, see [EncodeOptions::min_match].
+    pub fn min_match(mut self, min_match: u8) -> Self {
+        self.options.min_match = Some(min_match);
+        self
+    }
 
-        let mut decoded = Vec::new();
-        zstd::stream::copy_decode(output.as_slice(), &mut decoded).unwrap();
-        assert_eq!(mock_data, decoded);
+    /// Stop searching once a match of `target_length` bytes is found, see
+    /// [EncodeOptions::target_length].
+    pub fn target_length(mut self, target_length: u32) -> Self {
+        self.options.target_length = Some(target_length);
+        self
+    }
+
+    /// Compress with a dictionary, see [FrameCompressor::set_dictionary]. Its search tables are
+    /// built for the strategy of the compressor.
+    pub fn dictionary(mut self, dictionary: Dictionary) -> Self {
+        self.dictionary = Some(dictionary);
+        self.options.dictionary = None;
+        self
+    }
+
+    /// Compress with a dictionary whose search tables were built before, see
+    /// [FrameCompressor::set_encoder_dictionary].
+    pub fn encoder_dictionary(mut self, dictionary: Arc<EncoderDictionary>) -> Self {
+        self.options.dictionary = Some(dictionary);
+        self.dictionary = None;
+        self
+    }
+
+    /// Put at most `block_size` bytes of the input into one block, see
+    /// [FrameCompressor::set_block_size].
+    pub fn block_size(mut self, block_size: usize) -> Self {
+        self.options.block_size = Some(block_size);
+        self
+    }
+
+    /// Allow levels 20 to 22 and window logs above [MAX_WINDOW_LOG_WITHOUT_ULTRA], see [EncodeOptions::ultra].
+    pub fn ultra(mut self, ultra: bool) -> Self {
+        self.options.ultra = ultra;
+        self
+    }
+
+    /// Leave out the magic number at the start of each frame, see [EncodeOptions::magicless].
+    pub fn magicless(mut self, magicless: bool) -> Self {
+        self.options.magicless = magicless;
+        self
+    }
+
+    /// Declare the size of the decompressed content in each frame header.
+    pub fn content_size(mut self, content_size: bool) -> Self {
+        self.options.content_size = content_size;
+        self
+    }
+
+    /// Report metrics to `metrics`, see [EncodeOptions::metrics].
+    pub fn metrics(mut self, metrics: Arc<dyn CodecMetrics>) -> Self {
+        self.options.metrics = Some(metrics);
+        self
+    }
+
+    /// Start over at content-defined points within frames, see [EncodeOptions::rsyncable].
+    pub fn rsyncable(mut self, rsyncable: bool) -> Self {
+        self.options.rsyncable = rsyncable;
+        self
+    }
+
+    /// Cut the content into jobs of `job_size` bytes, see [EncodeOptions::job_size].
+    pub fn job_size(mut self, job_size: u32) -> Self {
+        self.options.job_size = Some(job_size);
+        self
+    }
+
+    /// Keep part of the window between jobs, see [EncodeOptions::overlap_log].
+    pub fn overlap_log(mut self, overlap_log: u8) -> Self {
+        self.options.overlap_log = Some(overlap_log);
+        self
+    }
+
+    /// Adapt the level to the speed of the output, see [EncodeOptions::adapt].
+    #[cfg(feature = "std")]
+    pub fn adapt(mut self, adapt: Option<super::Adapt>) -> Self {
+        self.options.adapt = adapt;
+        self
+    }
+
+    /// Split the input into frames in the format of `pzstd`, see [FrameCompressor::set_pzstd_frame_size].
+    pub fn pzstd_frame_size(mut self, frame_size: Option<usize>) -> Self {
+        self.pzstd_frame_size = frame_size;
+        self
+    }
+
+    /// Split the input into one frame per content-defined chunk, see [FrameCompressor::set_chunker].
+    /// Takes precedence over [FrameCompressorBuilder::pzstd_frame_size].
+    pub fn chunker(mut self, chunker: Option<Chunker>) -> Self {
+        self.chunker = chunker;
+        self
+    }
+
+    /// Check the settings and create a [FrameCompressor] that reads from `uncompressed_data`
+    /// and writes to `compressed_data`.
+    pub fn build<R: Read, W: Write>(
+        self,
+        uncompressed_data: R,
+        compressed_data: W,
+    ) -> Result<FrameCompressor<R, W>, ConfigError> {
+        let mut options = self.options;
+        options.level.normalize_mut();
+        match options.level {
+            CompressionLevel::Uncompressed
+            | CompressionLevel::Fastest
+            | CompressionLevel::Auto
+            | CompressionLevel::Negative(_) => {}
+            level => match ultra_window_log(level) {
+                Some(window_log) if !options.ultra => {
+                    return Err(ConfigError::UltraRequired { level, window_log })
+                }
+                Some(_) => {}
+                None if standard_level(level).is_some() => {}
+                None => return Err(ConfigError::UnsupportedLevel { level }),
+            },
+        }
+        check_sizes(&options)?;
+        #[cfg(feature = "hash")]
+        {
+            options.checksum = self.checksum;
+        }
+        #[cfg(not(feature = "hash"))]
+        if self.checksum {
+            return Err(ConfigError::ChecksumUnsupported);
+        }
+        if let Some(dictionary) = self.dictionary {
+            let strategy = options.strategy.unwrap_or(Strategy::Fast);
+            // Aborts like EncoderDictionary::new, see FrameCompressorBuilder::dictionary
+            #[allow(clippy::disallowed_methods)]
+            let dictionary = Arc::new(EncoderDictionary::new(dictionary, strategy));
+            options.dictionary = Some(dictionary);
+        }
+        if let Some(dictionary) = &options.dictionary {
+            let window_size = min_window_size(&options);
+            let dictionary_size = dictionary.dictionary().dict_content.len();
+            if dictionary_size as u64 >= window_size {
+                return Err(ConfigError::DictionaryLargerThanWindow {
+                    dictionary_size,
+                    window_size,
+                });
+            }
+        }
+        match self.pzstd_frame_size {
+            Some(0) => return Err(ConfigError::InvalidPzstdFrameSize),
+            Some(_) if options.magicless => return Err(ConfigError::MagiclessSkippableFrames),
+            _ => {}
+        }
+        let mut compressor =
+            FrameCompressor::with_options(uncompressed_data, compressed_data, options);
+        compressor.set_pzstd_frame_size(self.pzstd_frame_size);
+        compressor.set_chunker(self.chunker);
+        Ok(compressor)
+    }
+}
+
+/// Check the window log, block size and search parameters of `options`.
+pub(super) fn check_sizes(options: &EncodeOptions) -> Result<(), ConfigError> {
+    if let Some(window_log) = options.window_log {
+        if !(MIN_WINDOW_LOG..=MAX_WINDOW_LOG).contains(&window_log) {
+            return Err(ConfigError::InvalidWindowLog { window_log });
+        }
+        if window_log > MAX_WINDOW_LOG_WITHOUT_ULTRA && !options.ultra {
+            return Err(ConfigError::UltraRequired {
+                level: options.level,
+                window_log,
+            });
+        }
+    }
+    if let Some(block_size) = options.block_size {
+        if !(1..=MAX_BLOCK_SIZE).contains(&block_size) {
+            return Err(ConfigError::InvalidBlockSize { block_size });
+        }
+    }
+    // The same limits as in the reference implementation, except for the minimum match length,
+    // which can not be shorter than the 5 bytes the match finder indexes
+    let parameters = [
+        ("hash log", options.hash_log.map(u32::from), 6, 30),
+        ("chain log", options.chain_log.map(u32::from), 6, 30),
+        ("search log", options.search_log.map(u32::from), 1, 30),
+        (
+            "minimum match length",
+            options.min_match.map(u32::from),
+            5,
+            7,
+        ),
+        ("target length", options.target_length, 0, 128 * 1024),
+        ("job size", options.job_size, 512 * 1024, 1 << 30),
+        ("overlap log", options.overlap_log.map(u32::from), 0, 9),
+    ];
+    for (parameter, value, min, max) in parameters {
+        if let Some(value) = value.filter(|value| !(min..=max).contains(value)) {
+            return Err(ConfigError::InvalidParameter {
+                parameter,
+                value,
+                min,
+                max,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The window log of levels 20 to 22, like in the reference implementation. `None` for all other
+/// levels.
+pub(super) fn ultra_window_log(level: CompressionLevel) -> Option<u8> {
+    match level {
+        CompressionLevel::Other(level) => match u8::from(level) {
+            20 => Some(25),
+            21 => Some(26),
+            22 => Some(27),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The search log of levels 20 to 22, like in the reference implementation: [Strategy::BtUltra]
+/// compares `2^search_log` earlier positions at these levels. `None` for all other levels.
+pub(super) fn ultra_search_log(level: CompressionLevel) -> Option<u8> {
+    ultra_window_log(level).map(|window_log| window_log - 16)
+}
+
+/// The number of `level` if it is one of the levels 2 to 19, which differ only in their window
+/// and strategy.
+fn standard_level(level: CompressionLevel) -> Option<u8> {
+    match level.normalize() {
+        CompressionLevel::Default
+        | CompressionLevel::Better
+        | CompressionLevel::Best
+        | CompressionLevel::Other(_) => {
+            let level = u8::from(ZstdLevel::from(level));
+            (2..=19).contains(&level).then_some(level)
+        }
+        _ => None,
+    }
+}
+
+/// The window log of `level` if it is one of the levels 2 to 22, like in the reference
+/// implementation for large inputs. `None` for the other levels, which keep [WINDOW_SIZE].
+fn level_window_log(level: CompressionLevel) -> Option<u8> {
+    match standard_level(level) {
+        Some(2) => Some(20),
+        Some(3..=8) => Some(21),
+        Some(9..=16) => Some(22),
+        Some(_) => Some(23),
+        None => ultra_window_log(level),
+    }
+}
+
+/// The strategy `level` searches with if [EncodeOptions::strategy] is not set, roughly the one
+/// the reference implementation picks for large inputs.
+pub(super) fn default_strategy(level: CompressionLevel) -> Strategy {
+    if ultra_window_log(level).is_some() {
+        return Strategy::BtUltra;
+    }
+    match standard_level(level) {
+        Some(2..=3) => Strategy::DFast,
+        Some(4..=5) => Strategy::Greedy,
+        Some(6..=8) => Strategy::Lazy,
+        Some(9..=12) => Strategy::Lazy2,
+        Some(_) => Strategy::BtLazy2,
+        None => Strategy::Fast,
+    }
+}
+
+/// Whether frames can be compressed at `level`, with [EncodeOptions::ultra] set to `ultra`.
+pub(crate) fn level_implemented(level: CompressionLevel, ultra: bool) -> bool {
+    match level.normalize() {
+        CompressionLevel::Uncompressed
+        | CompressionLevel::Fastest
+        | CompressionLevel::Auto
+        | CompressionLevel::Negative(_) => true,
+        level => standard_level(level).is_some() || ultra && ultra_window_log(level).is_some(),
+    }
+}
+
+/// The window size frames are encoded with at least, given the window log and level of `options`.
+pub(super) fn min_window_size(options: &EncodeOptions) -> u64 {
+    match options
+        .window_log
+        .or_else(|| level_window_log(options.level.normalize()))
+    {
+        Some(log) => u64::max(WINDOW_SIZE, 1 << log),
+        None => WINDOW_SIZE,
+    }
+}
+
+/// The window size that frames compressed with `options` declare, given the size of their content
+/// if it is known in advance.
+///
+/// Decoders need this much memory for the window, so formats that embed frames can use it to budget
+/// for them. Without an explicit [EncodeOptions::window_log], the window of the compression level
+/// shrinks to the content and the dictionary in front of it, because no sequence can reach further
+/// back than that. The size is rounded up to the next one that the `Window_Descriptor` of the frame
+/// header can describe, which is at least 1 KiB.
+///
+/// ```
+/// use ruzstd::encoding::{frame_window_size, EncodeOptions};
+///
+/// let options = EncodeOptions::default();
+/// assert_eq!(frame_window_size(&options, Some(100)), 1024);
+/// assert_eq!(frame_window_size(&options, Some(5000)), 5120);
+/// assert_eq!(frame_window_size(&options, None), 128 * 1024);
+/// ```
+pub fn frame_window_size(options: &EncodeOptions, content_size: Option<u64>) -> u64 {
+    let mut window_size = min_window_size(options);
+    if let (None, Some(content_size)) = (options.window_log, content_size) {
+        let dictionary_size = options
+            .dictionary
+            .as_ref()
+            .map_or(0, |dictionary| dictionary.dictionary().dict_content.len());
+        // The match generator needs one byte of headroom on top
+        window_size = u64::min(
+            window_size,
+            content_size.saturating_add(dictionary_size as u64 + 1),
+        );
+    }
+    window_descriptor(window_size).1
+}
+
+/// The block size down to which [compress_bound] holds.
+pub const MIN_BOUNDED_BLOCK_SIZE: usize = 1024;
+
+/// The most bytes a frame with `input_size` bytes of content can take, the same bound as
+/// `ZSTD_compressBound` of the reference implementation.
+///
+/// Blocks that would not get smaller are stored raw, so at every level the content grows by at
+/// most the frame header, the 3 byte headers of its blocks and the checksum. This makes it safe to
+/// compress into a buffer of this size. It holds for the output of [FrameCompressor::compress]
+/// and of an [IncrementalEncoder] that gets all content at once, with all options except block
+/// sizes below [MIN_BOUNDED_BLOCK_SIZE] and [pzstd frames](FrameCompressor::set_pzstd_frame_size).
+/// Both add more headers.
+///
+/// ```
+/// use ruzstd::encoding::{compress_bound, CompressionLevel, FrameCompressor};
+///
+/// let data = [0xA5; 1000];
+/// let mut output = Vec::new();
+/// FrameCompressor::new(&data[..], &mut output, CompressionLevel::Uncompressed)
+///     .compress()
+///     .unwrap();
+/// assert!(output.len() <= compress_bound(data.len()));
+/// ```
+pub const fn compress_bound(input_size: usize) -> usize {
+    let small_input_margin = if input_size < 128 * 1024 {
+        (128 * 1024 - input_size) >> 11
+    } else {
+        0
+    };
+    input_size
+        .saturating_add(input_size >> 8)
+        .saturating_add(small_input_margin)
+}
+
+/// Data in front of the content of a frame that sequences can reference.
+#[derive(Clone, Copy)]
+pub(super) enum Prefix<'a> {
+    None,
+    Dictionary(&'a EncoderDictionary),
+    PatchReference(&'a [u8]),
+}
+
+impl<'a> Prefix<'a> {
+    fn new(dictionary: Option<&'a EncoderDictionary>, patch_reference: &'a [u8]) -> Self {
+        match dictionary {
+            Some(dictionary) => Prefix::Dictionary(dictionary),
+            None if !patch_reference.is_empty() => Prefix::PatchReference(patch_reference),
+            None => Prefix::None,
+        }
+    }
+}
+
+/// Compress `uncompressed_data` into a single frame and write it into `compressed_data`.
+fn compress_frame(
+    options: &EncodeOptions,
+    uncompressed_data: &[u8],
+    prefix: Prefix<'_>,
+    compressed_data: impl Write,
+) -> Result<(), EncodeError> {
+    let mut min_window_size = frame_window_size(options, Some(uncompressed_data.len() as u64));
+    // With a reference the window has to cover the reference and all data, so every part of
+    // the reference stays reachable. The match generator needs one byte of headroom on top.
+    if let Prefix::PatchReference(reference) = prefix {
+        min_window_size = u64::max(
+            min_window_size,
+            reference.len() as u64 + uncompressed_data.len() as u64 + 1,
+        );
+    }
+    let content_size = options
+        .content_size
+        .then_some(uncompressed_data.len() as u64);
+    IncrementalEncoder::start_frame(
+        compressed_data,
+        options,
+        prefix,
+        min_window_size,
+        content_size,
+    )?
+    .finish(uncompressed_data)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::FrameCompressor;
+    use crate::test_utils::{lcg_bytes, runs, text_like};
+    use crate::{frame::MAGIC_NUM, FrameDecoder};
+    use alloc::vec::Vec;
+
+    #[test]
+    fn frame_starts_with_magic_num() {
+        let mock_data = [1_u8, 2, 3].as_slice();
+        let mut output: Vec<u8> = Vec::new();
+        let mut compressor = FrameCompressor::new(
+            mock_data,
+            &mut output,
+            super::CompressionLevel::Uncompressed,
+        );
+        compressor.compress().unwrap();
+        assert!(output.starts_with(&MAGIC_NUM.to_le_bytes()));
+    }
+
+    #[test]
+    fn very_simple_raw_compress() {
+        let mock_data = [1_u8, 2, 3].as_slice();
+        let mut output: Vec<u8> = Vec::new();
+        let mut compressor = FrameCompressor::new(
+            mock_data,
+            &mut output,
+            super::CompressionLevel::Uncompressed,
+        );
+        compressor.compress().unwrap();
+    }
+
+    #[test]
+    fn very_simple_compress() {
+        let mut mock_data = vec![0; 1 << 17];
+        mock_data.extend(vec![1; (1 << 17) - 1]);
+        mock_data.extend(vec![2; (1 << 18) - 1]);
+        mock_data.extend(vec![2; 1 << 17]);
+        mock_data.extend(vec![3; (1 << 17) - 1]);
+        let mut output: Vec<u8> = Vec::new();
+        let mut compressor = FrameCompressor::new(
+            mock_data.as_slice(),
+            &mut output,
+            super::CompressionLevel::Uncompressed,
+        );
+        compressor.compress().unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(mock_data.len());
+        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+        assert_eq!(mock_data, decoded);
+
+        let mut decoded = Vec::new();
+        zstd::stream::copy_decode(output.as_slice(), &mut decoded).unwrap();
+        assert_eq!(mock_data, decoded);
+    }
+
+    #[test]
+    fn rle_compress() {
+        let mock_data = vec![0; 1 << 19];
+        let mut output: Vec<u8> = Vec::new();
+        let mut compressor = FrameCompressor::new(
+            mock_data.as_slice(),
+            &mut output,
+            super::CompressionLevel::Uncompressed,
+        );
+        compressor.compress().unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(mock_data.len());
+        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+        assert_eq!(mock_data, decoded);
+    }
+
+    #[test]
+    fn aaa_compress() {
+        let mock_data = vec![0, 1, 3, 4, 5];
+        let mut output: Vec<u8> = Vec::new();
+        let mut compressor = FrameCompressor::new(
+            mock_data.as_slice(),
+            &mut output,
+            super::CompressionLevel::Uncompressed,
+        );
+        compressor.compress().unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(mock_data.len());
+        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+        assert_eq!(mock_data, decoded);
+
+        let mut decoded = Vec::new();
+        zstd::stream::copy_decode(output.as_slice(), &mut decoded).unwrap();
+        assert_eq!(mock_data, decoded);
+    }
+
+    #[test]
+    fn errors() {
+        use super::{CompressionLevel, EncodeError, ZstdLevel};
+        use core::convert::TryFrom;
+
+        let mock_data = [1_u8, 2, 3].as_slice();
+        let mut output: Vec<u8> = Vec::new();
+        let twenty = CompressionLevel::from(ZstdLevel::try_from(20).unwrap());
+        let mut compressor = FrameCompressor::new(mock_data, &mut output, twenty);
+        assert!(matches!(
+            compressor.compress(),
+            Err(EncodeError::UnsupportedLevel { level }) if level == twenty
+        ));
+        assert!(output.is_empty());
+
+        struct FailingWriter;
+        impl crate::io::Write for FailingWriter {
+            fn write(&mut self, _: &[u8]) -> Result<usize, crate::io::Error> {
+                Err(crate::io::Error::from(crate::io::ErrorKind::Other))
+            }
+            fn flush(&mut self) -> Result<(), crate::io::Error> {
+                Ok(())
+            }
+        }
+        let mut compressor =
+            FrameCompressor::new(mock_data, FailingWriter, CompressionLevel::Fastest);
+        assert!(matches!(
+            compressor.compress(),
+            Err(EncodeError::FailedToWriteOutput(_))
+        ));
+    }
+
+    #[test]
+    fn pzstd_frames() {
+        use super::{CompressionLevel, PZSTD_MAGIC_NUM};
+        use crate::frame::{inspect_frame, InspectFrameError, ReadFrameHeaderError};
+        use core::convert::TryInto;
+
+        let mock_data = text_like(300_000, 1);
+        for (frame_size, frames) in [(100_000, 3), (128 * 1024, 3), (1 << 20, 1)] {
+            let mut output: Vec<u8> = Vec::new();
+            let mut compressor =
+                FrameCompressor::new(mock_data.as_slice(), &mut output, CompressionLevel::Fastest);
+            compressor.set_pzstd_frame_size(Some(frame_size));
+            compressor.compress().unwrap();
+
+            // every frame is preceded by a hint with its compressed size
+            let mut remaining = output.as_slice();
+            for _ in 0..frames {
+                match inspect_frame(&mut remaining) {
+                    Err(InspectFrameError::ReadFrameHeaderError(
+                        ReadFrameHeaderError::SkipFrame {
+                            magic_number: PZSTD_MAGIC_NUM,
+                            length: 4,
+                        },
+                    )) => {}
+                    _ => panic!("Expected a pzstd size hint"),
+                }
+                let size_hint = u32::from_le_bytes(remaining[..4].try_into().unwrap());
+                remaining = &remaining[4..];
+                let info = inspect_frame(&mut remaining).unwrap();
+                assert_eq!(info.compressed_size, u64::from(size_hint));
+            }
+            assert!(remaining.is_empty());
+
+            let mut decoder = FrameDecoder::new();
+            let mut decoded = Vec::with_capacity(mock_data.len());
+            decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+            assert_eq!(mock_data, decoded);
+        }
+
+        // empty input still produces a frame
+        let mut output: Vec<u8> = Vec::new();
+        let mut compressor = FrameCompressor::new(&[][..], &mut output, CompressionLevel::Fastest);
+        compressor.set_pzstd_frame_size(Some(1000));
+        compressor.compress().unwrap();
+        assert_eq!(output[..4], PZSTD_MAGIC_NUM.to_le_bytes());
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::new();
+        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn chunked_frames() {
+        use super::{Chunker, CompressionLevel, FrameCompressorBuilder};
+        use crate::frame::inspect_frame;
+
+        let mut data = Vec::new();
+        for pair in lcg_bytes(200_000, 3).chunks_exact(2) {
+            if data.len() >= 300_000 {
+                break;
+            }
+            let words: [&[u8]; 6] = [b"chunk ", b"frame ", b"backup ", b"of ", b"the ", b"data\n"];
+            data.extend_from_slice(words[usize::from(pair[0]) % 6]);
+            if pair[1] & 7 == 0 {
+                data.push(pair[1]);
+            }
+        }
+        let mut edited = data[..150_000].to_vec();
+        edited.extend_from_slice(b"an edit in the middle");
+        edited.extend_from_slice(&data[150_000..]);
+
+        let compress = |data: &[u8]| {
+            let mut output = Vec::new();
+            FrameCompressorBuilder::new()
+                .level(CompressionLevel::Fastest)
+                .content_size(true)
+                .pzstd_frame_size(Some(1000))
+                .chunker(Some(Chunker::new(2048, 8192, 32 * 1024)))
+                .build(data, &mut output)
+                .unwrap()
+                .compress()
+                .unwrap();
+            assert_eq!(zstd::stream::decode_all(output.as_slice()).unwrap(), data);
+            let mut frames = Vec::new();
+            let mut remaining = output.as_slice();
+            while !remaining.is_empty() {
+                let size = inspect_frame(&mut &remaining[..]).unwrap().compressed_size as usize;
+                frames.push(remaining[..size].to_vec());
+                remaining = &remaining[size..];
+            }
+            frames
+        };
+        let frames = compress(&data);
+        let edited_frames = compress(&edited);
+        assert!(frames.len() > 10);
+        let unchanged = edited_frames
+            .iter()
+            .filter(|frame| frames.contains(frame))
+            .count();
+        // Only the frames around the edit differ
+        assert!(
+            unchanged + 3 >= frames.len(),
+            "{} of {}",
+            unchanged,
+            frames.len()
+        );
+
+        let mut output = Vec::new();
+        let mut compressor = FrameCompressor::new(&[][..], &mut output, CompressionLevel::Fastest);
+        compressor.set_chunker(Some(Chunker::default()));
+        compressor.compress().unwrap();
+        assert!(zstd::stream::decode_all(output.as_slice())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn patch_reference() {
+        use super::CompressionLevel;
+        use crate::decoding::dictionary::Dictionary;
+        use crate::frame_decoder::BlockDecodingStrategy;
+
+        // An "old version" and a "new version" with a few changes spread over the file
+        let mut state = 0x1234_5678_u32;
+        let reference: Vec<u8> = (0..400_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        let mut new_version = reference.clone();
+        for idx in (1000..new_version.len()).step_by(50_000) {
+            new_version[idx] ^= 0xFF;
+        }
+        new_version.extend_from_slice(b"appended at the end");
+
+        let mut plain = Vec::new();
+        FrameCompressor::new(
+            new_version.as_slice(),
+            &mut plain,
+            CompressionLevel::Fastest,
+        )
+        .compress()
+        .unwrap();
+        let mut patch = Vec::new();
+        let mut compressor = FrameCompressor::new(
+            new_version.as_slice(),
+            &mut patch,
+            CompressionLevel::Fastest,
+        );
+        compressor.set_patch_reference(reference.clone());
+        compressor.compress().unwrap();
+        assert!(
+            patch.len() * 50 < plain.len(),
+            "{} {}",
+            patch.len(),
+            plain.len()
+        );
+
+        let mut decoder = FrameDecoder::new();
+        decoder
+            .add_dict(Dictionary::from_raw_content(0, reference))
+            .unwrap();
+        let mut source = patch.as_slice();
+        decoder.reset(&mut source).unwrap();
+        decoder.force_dict(0).unwrap();
+        decoder
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+        assert_eq!(decoder.collect().unwrap(), new_version);
+
+        // without the reference the patch can not be decoded
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(new_version.len());
+        assert!(decoder.decode_all_to_vec(&patch, &mut decoded).is_err());
+    }
+
+    #[test]
+    fn dictionary() {
+        use super::CompressionLevel;
+        use crate::decoding::dictionary::Dictionary;
+
+        let raw_dict = include_bytes!("../../dict_tests/dictionary");
+        let data = include_bytes!("../../dict_tests/files/ModemManager.service");
+
+        let mut plain = Vec::new();
+        FrameCompressor::new(&data[..], &mut plain, CompressionLevel::Fastest)
+            .compress()
+            .unwrap();
+        let mut compressed = Vec::new();
+        let mut compressor =
+            FrameCompressor::new(&data[..], &mut compressed, CompressionLevel::Fastest);
+        compressor.set_dictionary(Some(Dictionary::decode_dict(raw_dict).unwrap()));
+        compressor.compress().unwrap();
+        assert!(compressed.len() < plain.len());
+
+        let dict = Dictionary::decode_dict(raw_dict).unwrap();
+        let frame = crate::frame::read_frame_header(compressed.as_slice())
+            .unwrap()
+            .0;
+        assert_eq!(frame.header.dictionary_id(), Some(dict.id));
+
+        // the dictionary is picked by the ID in the frame header
+        let mut decoder = FrameDecoder::new();
+        decoder.add_dict(dict).unwrap();
+        let mut decoded = Vec::with_capacity(data.len());
+        decoder
+            .decode_all_to_vec(&compressed, &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn encoder_dictionary() {
+        use super::{
+            CompressionLevel, EncodeOptions, EncoderDictionary, FrameCompressorBuilder, Strategy,
+        };
+        use crate::decoding::dictionary::Dictionary;
+        use crate::encoding::IncrementalEncoder;
+        use alloc::sync::Arc;
+
+        let raw_dict = include_bytes!("../../dict_tests/dictionary");
+        let decode = |compressed: &[u8], len: usize| {
+            let mut decoder = FrameDecoder::new();
+            decoder
+                .add_dict(Dictionary::decode_dict(raw_dict).unwrap())
+                .unwrap();
+            let mut decoded = Vec::with_capacity(len);
+            decoder.decode_all_to_vec(compressed, &mut decoded).unwrap();
+            let reference = zstd::bulk::Decompressor::with_dictionary(raw_dict)
+                .unwrap()
+                .decompress(compressed, len)
+                .unwrap();
+            assert_eq!(reference, decoded);
+            decoded
+        };
+
+        // Prepared once, it compresses like a dictionary that is prepared for each frame
+        let dictionary = Arc::new(EncoderDictionary::new(
+            Dictionary::decode_dict(raw_dict).unwrap(),
+            Strategy::Lazy,
+        ));
+        for data in [
+            &include_bytes!("../../dict_tests/files/ModemManager.service")[..],
+            include_bytes!("../../dict_tests/files/NetworkManager.service"),
+            include_bytes!("../../dict_tests/files/libvirtd.service"),
+        ] {
+            let options = EncodeOptions {
+                strategy: Some(Strategy::Lazy),
+                dictionary: Some(dictionary.clone()),
+                ..EncodeOptions::default()
+            };
+            let mut prepared = Vec::new();
+            FrameCompressor::with_options(data, &mut prepared, options)
+                .compress()
+                .unwrap();
+            let mut unprepared = Vec::new();
+            FrameCompressorBuilder::new()
+                .strategy(Strategy::Lazy)
+                .dictionary(Dictionary::decode_dict(raw_dict).unwrap())
+                .build(data, &mut unprepared)
+                .unwrap()
+                .compress()
+                .unwrap();
+            assert_eq!(prepared, unprepared);
+            assert_eq!(decode(&prepared, data.len()), data);
+        }
+
+        // Only the end of the dictionary is in reach of a small window, and it leaves the window
+        // as the data comes in
+        let content = &dictionary.dictionary().dict_content;
+        let mut data = content[content.len() - 3000..].to_vec();
+        data.extend_from_slice(&content[content.len() - 600..]);
+        data.extend_from_slice(&content[..5000]);
+        let options = EncodeOptions {
+            level: CompressionLevel::Fastest,
+            window_log: Some(12),
+            dictionary: Some(dictionary),
+            ..EncodeOptions::default()
+        };
+        let mut encoder = IncrementalEncoder::start(Vec::new(), &options, None).unwrap();
+        for chunk in data.chunks(700) {
+            encoder.write_chunk(chunk).unwrap();
+        }
+        let compressed = encoder.finish(&[]).unwrap();
+        assert!(compressed.len() < data.len() / 2);
+        assert_eq!(decode(&compressed, data.len()), data);
+    }
+
+    #[test]
+    fn negative_levels() {
+        use super::{CompressionLevel, MAX_ACCELERATION};
+        use core::num::NonZeroU32;
+
+        let mut data = Vec::new();
+        for file in [
+            &include_bytes!("../../dict_tests/files/ModemManager.service")[..],
+            include_bytes!("../../dict_tests/files/NetworkManager.service"),
+            include_bytes!("../../dict_tests/files/libvirtd.service"),
+            include_bytes!("../../dict_tests/files/systemd-resolved.service"),
+        ] {
+            data.extend_from_slice(file);
+        }
+        data.extend((0..20_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+        data.extend_from_slice(&data.clone()[..3000]);
+
+        let compress = |level: CompressionLevel| {
+            let mut output = Vec::new();
+            FrameCompressor::new(data.as_slice(), &mut output, level)
+                .compress()
+                .unwrap();
+            let mut decoded = Vec::with_capacity(data.len());
+            FrameDecoder::new()
+                .decode_all_to_vec(&output, &mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+            let mut decoded = Vec::new();
+            zstd::stream::copy_decode(output.as_slice(), &mut decoded).unwrap();
+            assert_eq!(decoded, data);
+            output.len()
+        };
+
+        // Faster levels find fewer matches, but still some
+        let mut previous = compress(CompressionLevel::Fastest);
+        for n in [1, 3, 7, 1000, MAX_ACCELERATION + 1] {
+            let size = compress(CompressionLevel::Negative(NonZeroU32::new(n).unwrap()));
+            assert!(size >= previous, "level -{} {} {}", n, size, previous);
+            previous = size;
+        }
+        assert!(previous <= data.len() + 100);
+        let seven = compress(CompressionLevel::Negative(NonZeroU32::new(7).unwrap()));
+        assert!(seven < data.len() * 3 / 4);
+    }
+
+    #[test]
+    fn parse_levels() {
+        use super::{CompressionLevel, ParseLevelError, ZstdLevel};
+        use alloc::string::ToString;
+        use core::convert::TryFrom;
+        use core::num::NonZeroU32;
+
+        assert_eq!("fastest".parse(), Ok(CompressionLevel::Fastest));
+        assert_eq!(" Best ".parse(), Ok(CompressionLevel::Best));
+        assert_eq!("AUTO".parse(), Ok(CompressionLevel::Auto));
+        assert_eq!(CompressionLevel::Auto.to_string(), "auto");
+        assert_eq!("3".parse(), Ok(CompressionLevel::Default));
+        let nineteen = ZstdLevel::try_from(19).unwrap();
+        assert_eq!("19".parse(), Ok(CompressionLevel::Other(nineteen)));
+        assert_eq!("19".parse(), Ok(nineteen));
+        assert_eq!(
+            "23".parse::<CompressionLevel>(),
+            Err(ParseLevelError::OutOfRange { level: 23 })
+        );
+        assert_eq!(
+            "-1".parse::<ZstdLevel>(),
+            Err(ParseLevelError::OutOfRange { level: -1 })
+        );
+        assert_eq!(
+            "fast".parse::<CompressionLevel>(),
+            Err(ParseLevelError::Unknown)
+        );
+        let five = CompressionLevel::Negative(NonZeroU32::new(5).unwrap());
+        assert_eq!("-5".parse(), Ok(five));
+        assert_eq!(five.to_string(), "-5");
+        assert_eq!(
+            "-131073".parse::<CompressionLevel>(),
+            Err(ParseLevelError::OutOfRange { level: -131073 })
+        );
+
+        for level in 0..=22u8 {
+            let level = CompressionLevel::from(ZstdLevel::try_from(level).unwrap());
+            assert_eq!(level.to_string().parse(), Ok(level));
+        }
+        assert_eq!(CompressionLevel::Uncompressed.to_string(), "uncompressed");
+        assert_eq!(CompressionLevel::Other(nineteen).to_string(), "19");
+        let seven = CompressionLevel::Other(ZstdLevel::try_from(7).unwrap());
+        assert_eq!(seven.to_string(), "better");
+    }
+
+    #[test]
+    fn builder() {
+        use super::{CompressionLevel, ConfigError, FrameCompressorBuilder};
+        use crate::decoding::dictionary::Dictionary;
+
+        let data: &[u8] = &[1, 2, 3];
+        assert!(FrameCompressorBuilder::new()
+            .level(CompressionLevel::Best)
+            .build(data, Vec::new())
+            .is_ok());
+        assert!(matches!(
+            FrameCompressorBuilder::new()
+                .window_log(42)
+                .build(data, Vec::new()),
+            Err(ConfigError::InvalidWindowLog { window_log: 42 })
+        ));
+        assert!(matches!(
+            FrameCompressorBuilder::new()
+                .block_size(0)
+                .build(data, Vec::new()),
+            Err(ConfigError::InvalidBlockSize { block_size: 0 })
+        ));
+        #[cfg(not(feature = "hash"))]
+        assert!(matches!(
+            FrameCompressorBuilder::new()
+                .checksum(true)
+                .build(data, Vec::new()),
+            Err(ConfigError::ChecksumUnsupported)
+        ));
+        assert!(matches!(
+            FrameCompressorBuilder::new()
+                .dictionary(Dictionary::from_raw_content(7, alloc::vec![0; 200_000]))
+                .window_log(17)
+                .build(data, Vec::new()),
+            Err(ConfigError::DictionaryLargerThanWindow {
+                dictionary_size: 200_000,
+                window_size: 131_072
+            })
+        ));
+        assert!(FrameCompressorBuilder::new()
+            .dictionary(Dictionary::from_raw_content(7, alloc::vec![0; 200_000]))
+            .window_log(18)
+            .build(data, Vec::new())
+            .is_ok());
+        assert!(matches!(
+            FrameCompressorBuilder::new()
+                .pzstd_frame_size(Some(0))
+                .build(data, Vec::new()),
+            Err(ConfigError::InvalidPzstdFrameSize)
+        ));
+        assert!(matches!(
+            FrameCompressorBuilder::new()
+                .magicless(true)
+                .pzstd_frame_size(Some(1000))
+                .build(data, Vec::new()),
+            Err(ConfigError::MagiclessSkippableFrames)
+        ));
+
+        let reference = text_like(1000, 2);
+        let mut output = Vec::new();
+        let mut compressor = FrameCompressorBuilder::new()
+            .level(CompressionLevel::Fastest)
+            .window_log(12)
+            .content_size(true)
+            .dictionary(Dictionary::from_raw_content(7, reference.clone()))
+            .build(&reference[100..900], &mut output)
+            .unwrap();
+        assert_eq!(compressor.options().window_log, Some(12));
+        compressor.compress().unwrap();
+        assert!(output.len() < 100, "{}", output.len());
+
+        let frame = crate::frame::read_frame_header(output.as_slice())
+            .unwrap()
+            .0;
+        assert_eq!(frame.header.dictionary_id(), Some(7));
+        assert_eq!(frame.header.frame_content_size(), 800);
+        let mut decoder = FrameDecoder::new();
+        decoder
+            .add_dict(Dictionary::from_raw_content(7, reference.clone()))
+            .unwrap();
+        let mut decoded = Vec::with_capacity(800);
+        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+        assert_eq!(decoded, &reference[100..900]);
+    }
+
+    #[test]
+    fn strategies() {
+        use super::{FrameCompressorBuilder, Strategy};
+
+        let mut data = Vec::new();
+        for file in [
+            &include_bytes!("../../dict_tests/files/ModemManager.service")[..],
+            include_bytes!("../../dict_tests/files/NetworkManager.service"),
+            include_bytes!("../../dict_tests/files/NetworkManager-dispatcher.service"),
+            include_bytes!("../../dict_tests/files/NetworkManager-wait-online.service"),
+            include_bytes!("../../dict_tests/files/dbus-org.freedesktop.login1.service"),
+            include_bytes!("../../dict_tests/files/systemd-networkd.service"),
+            include_bytes!("../../dict_tests/files/systemd-resolved.service"),
+            include_bytes!("../../dict_tests/files/libvirtd.service"),
+        ] {
+            data.extend_from_slice(file);
+        }
+        let compress = |strategy: Option<Strategy>| {
+            let mut builder = FrameCompressorBuilder::new().window_log(22);
+            if let Some(strategy) = strategy {
+                builder = builder.strategy(strategy);
+            }
+            let mut output = Vec::new();
+            builder
+                .build(data.as_slice(), &mut output)
+                .unwrap()
+                .compress()
+                .unwrap();
+            let mut decoded = Vec::with_capacity(data.len());
+            FrameDecoder::new()
+                .decode_all_to_vec(&output, &mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+            output.len()
+        };
+
+        let fast = compress(None);
+        assert_eq!(compress(Some(Strategy::Fast)), fast);
+        let greedy = compress(Some(Strategy::Greedy));
+        let lazy2 = compress(Some(Strategy::Lazy2));
+        assert!(greedy < fast, "{} >= {}", greedy, fast);
+        assert!(lazy2 <= greedy, "{} > {}", lazy2, greedy);
+        let row_hash = compress(Some(Strategy::RowHash));
+        assert!(row_hash < fast, "{} >= {}", row_hash, fast);
+        for strategy in [
+            Strategy::DFast,
+            Strategy::BtLazy2,
+            Strategy::BtOpt,
+            Strategy::BtUltra,
+        ] {
+            compress(Some(strategy));
+        }
+    }
+
+    #[test]
+    fn parameter_overrides() {
+        use super::{ConfigError, FrameCompressorBuilder, Strategy};
+
+        let mut data = Vec::new();
+        for file in [
+            &include_bytes!("../../dict_tests/files/ModemManager.service")[..],
+            include_bytes!("../../dict_tests/files/NetworkManager.service"),
+            include_bytes!("../../dict_tests/files/systemd-networkd.service"),
+            include_bytes!("../../dict_tests/files/libvirtd.service"),
+        ] {
+            data.extend_from_slice(file);
+        }
+        let compress = |builder: FrameCompressorBuilder| {
+            let mut output = Vec::new();
+            builder
+                .build(data.as_slice(), &mut output)
+                .unwrap()
+                .compress()
+                .unwrap();
+            assert_eq!(zstd::decode_all(output.as_slice()).unwrap(), data);
+            output.len()
+        };
+
+        // Each parameter replaces the one of the strategy and the rest is kept
+        let lazy2 = compress(FrameCompressorBuilder::new().strategy(Strategy::Lazy2));
+        let shallow = compress(
+            FrameCompressorBuilder::new()
+                .strategy(Strategy::Lazy2)
+                .search_log(1),
+        );
+        assert!(lazy2 < shallow, "{} >= {}", lazy2, shallow);
+        let long_matches = compress(
+            FrameCompressorBuilder::new()
+                .strategy(Strategy::Lazy2)
+                .min_match(7),
+        );
+        assert!(lazy2 < long_matches, "{} >= {}", lazy2, long_matches);
+        let row_hash = compress(FrameCompressorBuilder::new().strategy(Strategy::RowHash));
+        let small_table = compress(
+            FrameCompressorBuilder::new()
+                .strategy(Strategy::RowHash)
+                .hash_log(6),
+        );
+        assert!(row_hash < small_table, "{} >= {}", row_hash, small_table);
+        compress(
+            FrameCompressorBuilder::new()
+                .strategy(Strategy::Lazy)
+                .chain_log(10)
+                .target_length(16),
+        );
+        // 0 keeps the default, like in the reference implementation
+        assert_eq!(
+            compress(
+                FrameCompressorBuilder::new()
+                    .strategy(Strategy::Lazy2)
+                    .target_length(0)
+            ),
+            lazy2
+        );
+
+        for (builder, parameter) in [
+            (FrameCompressorBuilder::new().hash_log(5), "hash log"),
+            (FrameCompressorBuilder::new().chain_log(31), "chain log"),
+            (FrameCompressorBuilder::new().search_log(0), "search log"),
+            (
+                FrameCompressorBuilder::new().min_match(8),
+                "minimum match length",
+            ),
+            (
+                FrameCompressorBuilder::new().min_match(4),
+                "minimum match length",
+            ),
+            (
+                FrameCompressorBuilder::new().target_length(1 << 20),
+                "target length",
+            ),
+            (FrameCompressorBuilder::new().job_size(1000), "job size"),
+            (FrameCompressorBuilder::new().overlap_log(10), "overlap log"),
+        ] {
+            assert!(matches!(
+                builder.build(data.as_slice(), Vec::new()),
+                Err(ConfigError::InvalidParameter { parameter: name, .. }) if name == parameter
+            ));
+        }
+    }
+
+    #[test]
+    fn auto_level() {
+        use super::{CompressionLevel, FrameCompressorBuilder, Strategy};
+
+        let compress = |data: &[u8], level: CompressionLevel, strategy: Option<Strategy>| {
+            let mut builder = FrameCompressorBuilder::new().level(level);
+            if let Some(strategy) = strategy {
+                builder = builder.strategy(strategy);
+            }
+            let mut output = Vec::new();
+            builder
+                .build(data, &mut output)
+                .unwrap()
+                .compress()
+                .unwrap();
+            let mut decoded = Vec::with_capacity(data.len());
+            FrameDecoder::new()
+                .decode_all_to_vec(&output, &mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+            output
+        };
+
+        // Noise is stored without trying to compress it
+        let noise = lcg_bytes(300_000, 1);
+        let compressed = compress(&noise, CompressionLevel::Auto, None);
+        // Three bytes of block header per 128 KiB block and a short frame header
+        assert!(compressed.len() <= noise.len() + 3 * 3 + 18);
+
+        // Text gets a more thorough search, unless a strategy is given
+        let mut text = Vec::new();
+        for file in [
+            &include_bytes!("../../dict_tests/files/ModemManager.service")[..],
+            include_bytes!("../../dict_tests/files/NetworkManager.service"),
+            include_bytes!("../../dict_tests/files/libvirtd.service"),
+            include_bytes!("../../dict_tests/files/systemd-resolved.service"),
+        ] {
+            text.extend_from_slice(file);
+        }
+        let lazy = compress(&text, CompressionLevel::Fastest, Some(Strategy::Lazy));
+        assert_eq!(compress(&text, CompressionLevel::Auto, None), lazy);
+        let fast = compress(&text, CompressionLevel::Fastest, None);
+        assert_eq!(
+            compress(&text, CompressionLevel::Auto, Some(Strategy::Fast)),
+            fast
+        );
+
+        // Empty input still makes a frame
+        compress(&[], CompressionLevel::Auto, None);
+    }
+
+    #[test]
+    fn ultra() {
+        use super::{
+            CompressionLevel, ConfigError, EncodeError, FrameCompressorBuilder, ZstdLevel,
+        };
+        use crate::encoding::{EncodeOptions, IncrementalEncoder};
+        use crate::frame_decoder::FrameDecoderError;
+        use core::convert::TryFrom;
+
+        let level = |level: u8| CompressionLevel::from(ZstdLevel::try_from(level).unwrap());
+        let data: &[u8] = &[1, 2, 3];
+        assert!(matches!(
+            FrameCompressorBuilder::new()
+                .level(level(22))
+                .build(data, Vec::new()),
+            Err(ConfigError::UltraRequired { window_log: 27, .. })
+        ));
+        assert!(matches!(
+            FrameCompressorBuilder::new()
+                .window_log(24)
+                .build(data, Vec::new()),
+            Err(ConfigError::UltraRequired { window_log: 24, .. })
+        ));
+        assert!(matches!(
+            IncrementalEncoder::start(
+                Vec::new(),
+                &EncodeOptions {
+                    level: level(20),
+                    ..EncodeOptions::default()
+                },
+                None
+            ),
+            Err(EncodeError::UnsupportedLevel { .. })
+        ));
+
+        let data = text_like(100_000, 3);
+        for (zstd_level, window_log) in [(20, 25), (21, 26), (22, 27)] {
+            // Only frames of unknown size get the whole window of the level
+            let options = EncodeOptions {
+                level: level(zstd_level),
+                ultra: true,
+                ..EncodeOptions::default()
+            };
+            let output = IncrementalEncoder::start(Vec::new(), &options, None)
+                .unwrap()
+                .finish(&data)
+                .unwrap();
+            let frame = crate::frame::read_frame_header(output.as_slice())
+                .unwrap()
+                .0;
+            assert_eq!(frame.header.window_size().unwrap(), 1 << window_log);
+
+            let mut sized = Vec::new();
+            FrameCompressorBuilder::new()
+                .level(level(zstd_level))
+                .ultra(true)
+                .build(data.as_slice(), &mut sized)
+                .unwrap()
+                .compress()
+                .unwrap();
+            let frame = crate::frame::read_frame_header(sized.as_slice()).unwrap().0;
+            assert_eq!(frame.header.window_size().unwrap(), 106_496);
+            let mut decoded = Vec::with_capacity(data.len());
+            FrameDecoder::new()
+                .decode_all_to_vec(&sized, &mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+
+            let mut decoder = FrameDecoder::new();
+            if window_log == 27 {
+                // Larger than decoders accept by default
+                assert!(matches!(
+                    decoder.decode_all_to_vec(&output, &mut Vec::new()),
+                    Err(FrameDecoderError::WindowSizeTooBig { .. })
+                ));
+                decoder.set_ultra(true);
+            }
+            let mut decoded = Vec::with_capacity(data.len());
+            decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+            assert_eq!(decoded, data);
+        }
+
+        // The ultra levels search deeper than Fastest, so they find the older, longer matches
+        let mut data = Vec::new();
+        let mut phrases: Vec<Vec<u8>> = Vec::new();
+        for pair in lcg_bytes(4000, 1).chunks_exact(2) {
+            let pick = usize::from(u16::from_le_bytes([pair[0], pair[1]]));
+            if phrases.len() < 64 || pick.is_multiple_of(4) {
+                let phrase = (0..8 + pick % 24)
+                    .map(|i| (pick >> (i % 8)) as u8)
+                    .collect();
+                phrases.push(phrase);
+            }
+            data.extend_from_slice(&phrases[pick % phrases.len()]);
+        }
+        let compress = |level: CompressionLevel| {
+            let mut output = Vec::new();
+            FrameCompressorBuilder::new()
+                .level(level)
+                .ultra(true)
+                .build(data.as_slice(), &mut output)
+                .unwrap()
+                .compress()
+                .unwrap();
+            output
+        };
+        let fastest = compress(CompressionLevel::Fastest);
+        let ultra = compress(level(22));
+        assert!(
+            ultra.len() < fastest.len(),
+            "{} {}",
+            ultra.len(),
+            fastest.len()
+        );
+        let mut decoded = Vec::with_capacity(data.len());
+        FrameDecoder::new()
+            .decode_all_to_vec(&ultra, &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn levels_2_to_19() {
+        use super::{default_strategy, CompressionLevel, FrameCompressorBuilder, ZstdLevel};
+        use crate::encoding::Strategy;
+        use core::convert::TryFrom;
+
+        let level = |level: u8| CompressionLevel::from(ZstdLevel::try_from(level).unwrap());
+        assert_eq!(default_strategy(level(2)), Strategy::DFast);
+        assert_eq!(default_strategy(CompressionLevel::Default), Strategy::DFast);
+        assert_eq!(default_strategy(CompressionLevel::Better), Strategy::Lazy);
+        assert_eq!(default_strategy(CompressionLevel::Best), Strategy::Lazy2);
+        assert_eq!(default_strategy(level(19)), Strategy::BtLazy2);
+
+        let mut data = Vec::new();
+        for file in [
+            &include_bytes!("../../dict_tests/files/ModemManager.service")[..],
+            include_bytes!("../../dict_tests/files/NetworkManager.service"),
+            include_bytes!("../../dict_tests/files/libvirtd.service"),
+            include_bytes!("../../dict_tests/files/systemd-resolved.service"),
+        ] {
+            data.extend_from_slice(file);
+        }
+        let compress = |level: CompressionLevel| {
+            let mut output = Vec::new();
+            FrameCompressorBuilder::new()
+                .level(level)
+                .build(data.as_slice(), &mut output)
+                .unwrap()
+                .compress()
+                .unwrap();
+            output
+        };
+        let fastest = compress(CompressionLevel::Fastest);
+        for zstd_level in 2..=19 {
+            let output = compress(level(zstd_level));
+            assert!(output.len() <= fastest.len(), "{}", zstd_level);
+            let mut decoded = Vec::with_capacity(data.len());
+            FrameDecoder::new()
+                .decode_all_to_vec(&output, &mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data, "{}", zstd_level);
+        }
+        assert!(compress(level(19)).len() < fastest.len());
+    }
+
+    #[test]
+    fn reused_options() {
+        use super::EncodeOptions;
+        use crate::frame_decoder::BlockDecodingStrategy;
+
+        let options = EncodeOptions {
+            block_size: Some(1000),
+            magicless: true,
+            content_size: true,
+            ..EncodeOptions::default()
+        };
+        let inputs = [runs(10_000, 4), text_like(2500, 5)];
+        for input in &inputs {
+            let mut compressed = Vec::new();
+            FrameCompressor::with_options(input.as_slice(), &mut compressed, options.clone())
+                .compress()
+                .unwrap();
+            assert_ne!(compressed[..4], crate::frame::MAGIC_NUM.to_le_bytes());
+
+            let mut decoder = FrameDecoder::new();
+            let mut decoded = Vec::new();
+            assert!(decoder
+                .decode_all_to_vec(&compressed, &mut decoded)
+                .is_err());
+
+            decoder.set_magicless(true);
+            let mut source = compressed.as_slice();
+            decoder.reset(&mut source).unwrap();
+            assert_eq!(decoder.content_size(), input.len() as u64);
+            while !decoder.is_finished() {
+                decoder
+                    .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+                    .unwrap();
+            }
+            assert_eq!(decoder.blocks_decoded(), input.len().div_ceil(1000));
+            assert_eq!(&decoder.collect().unwrap(), input);
+        }
+
+        let mut output = Vec::new();
+        let options = EncodeOptions {
+            block_size: Some(super::MAX_BLOCK_SIZE + 1),
+            ..EncodeOptions::default()
+        };
+        assert!(matches!(
+            FrameCompressor::with_options(&[][..], &mut output, options).compress(),
+            Err(super::EncodeError::InvalidConfig(
+                super::ConfigError::InvalidBlockSize { .. }
+            ))
+        ));
+        assert!(output.is_empty());
+    }
+
+    #[cfg(all(feature = "std", feature = "hash"))]
+    #[test]
+    fn cli_default() {
+        use super::EncodeOptions;
+
+        let data = text_like(3_000_000, 6);
+        let mut compressed = Vec::new();
+        FrameCompressor::with_options(
+            data.as_slice(),
+            &mut compressed,
+            EncodeOptions::cli_default(),
+        )
+        .compress()
+        .unwrap();
+
+        // The zstd CLI enables the checksum and knows the size of the files it compresses
+        let mut reference = zstd::bulk::Compressor::new(3).unwrap();
+        reference
+            .set_parameter(zstd::zstd_safe::CParameter::ChecksumFlag(true))
+            .unwrap();
+        let reference = reference.compress(&data).unwrap();
+        // Magic number, frame header descriptor, window descriptor and the 4 byte content size
+        assert_eq!(compressed[..10], reference[..10]);
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(data.len());
+        decoder
+            .decode_all_to_vec(&compressed, &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+        assert_eq!(
+            decoder.get_checksum_from_data(),
+            decoder.get_calculated_checksum()
+        );
+    }
+
+    #[test]
+    fn window_log() {
+        use super::CompressionLevel;
+        use crate::frame_decoder::FrameDecoderError;
+
+        // The repetition is further away than the default window reaches
+        let mut state = 0x8765_4321_u32;
+        let mut data: Vec<u8> = (0..300_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        data.extend_from_within(..);
+
+        let mut default_window = Vec::new();
+        FrameCompressor::new(
+            data.as_slice(),
+            &mut default_window,
+            CompressionLevel::Fastest,
+        )
+        .compress()
+        .unwrap();
+        let mut large_window = Vec::new();
+        let mut compressor = FrameCompressor::new(
+            data.as_slice(),
+            &mut large_window,
+            CompressionLevel::Fastest,
+        );
+        compressor.set_window_log(Some(20));
+        compressor.compress().unwrap();
+        assert!(
+            large_window.len() * 3 < default_window.len() * 2,
+            "{} {}",
+            large_window.len(),
+            default_window.len()
+        );
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(data.len());
+        decoder
+            .decode_all_to_vec(&large_window, &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        // decoders with a lower limit refuse the frame
+        let mut decoder = FrameDecoder::new();
+        decoder.set_max_window_size(1 << 19);
+        assert!(matches!(
+            decoder.reset(large_window.as_slice()),
+            Err(FrameDecoderError::WindowSizeTooBig {
+                requested: 0x10_0000,
+                max: 0x8_0000
+            })
+        ));
+    }
+
+    #[test]
+    fn larger_windows_do_not_grow_output() {
+        use super::{EncodeOptions, FrameCompressorBuilder};
+        use alloc::string::ToString;
+
+        // Like `seq 1 100000`, every line shares most of its bytes with the lines right before it
+        let mut data = Vec::new();
+        for line in 1..=100_000u32 {
+            data.extend_from_slice(line.to_string().as_bytes());
+            data.push(b'\n');
+        }
+        let compress = |options: EncodeOptions| {
+            let mut output = Vec::new();
+            FrameCompressorBuilder::new()
+                .options(options)
+                .build(data.as_slice(), &mut output)
+                .unwrap()
+                .compress()
+                .unwrap();
+            output.len()
+        };
+        let default_window = compress(EncodeOptions::default());
+        for window_log in [17, 20, 21, 23] {
+            let size = compress(EncodeOptions {
+                window_log: Some(window_log),
+                ..EncodeOptions::default()
+            });
+            assert!(
+                size <= default_window,
+                "window log {}: {} > {}",
+                window_log,
+                size,
+                default_window
+            );
+        }
+        // The checksum and the content size take 8 more bytes
+        let cli_default = compress(EncodeOptions::cli_default());
+        assert!(
+            cli_default <= default_window + 8,
+            "{} > {}",
+            cli_default,
+            default_window
+        );
+    }
+
+    #[test]
+    fn frame_window_size() {
+        use super::{frame_window_size, EncodeOptions, EncoderDictionary, Strategy, ZstdLevel};
+        use crate::decoding::dictionary::Dictionary;
+        use alloc::sync::Arc;
+        use core::convert::TryFrom;
+
+        let options = EncodeOptions::default();
+        assert_eq!(frame_window_size(&options, None), 128 * 1024);
+        assert_eq!(frame_window_size(&options, Some(0)), 1024);
+        assert_eq!(frame_window_size(&options, Some(1023)), 1024);
+        assert_eq!(frame_window_size(&options, Some(1024)), 1024 + 128);
+        assert_eq!(frame_window_size(&options, Some(1 << 20)), 128 * 1024);
+        assert_eq!(frame_window_size(&options, Some(u64::MAX)), 128 * 1024);
+
+        // An explicit window log is kept
+        let options = EncodeOptions {
+            window_log: Some(20),
+            ..EncodeOptions::default()
+        };
+        assert_eq!(frame_window_size(&options, Some(10)), 1 << 20);
+
+        // Levels 2 to 19 use the windows of the reference implementation for large inputs
+        for (level, window_log) in [
+            (2, 20),
+            (3, 21),
+            (8, 21),
+            (9, 22),
+            (16, 22),
+            (17, 23),
+            (19, 23),
+        ] {
+            let options = EncodeOptions {
+                level: super::CompressionLevel::Other(ZstdLevel::try_from(level).unwrap()),
+                ..EncodeOptions::default()
+            };
+            assert_eq!(frame_window_size(&options, None), 1 << window_log);
+            assert_eq!(frame_window_size(&options, Some(100)), 1024);
+        }
+
+        // The dictionary has to fit into the window next to the content
+        let dictionary =
+            Dictionary::decode_dict(include_bytes!("../../dict_tests/dictionary")).unwrap();
+        let dictionary_size = dictionary.dict_content.len() as u64;
+        let options = EncodeOptions {
+            dictionary: Some(Arc::new(EncoderDictionary::new(dictionary, Strategy::Fast))),
+            ..EncodeOptions::default()
+        };
+        let window_size = frame_window_size(&options, Some(100));
+        assert!(window_size > dictionary_size + 100);
+        assert!(window_size < 128 * 1024);
+
+        // Frames with small content declare small windows and still decode everywhere
+        let data = b"a small frame with a small window ".repeat(100);
+        let mut compressed = Vec::new();
+        FrameCompressor::new(
+            data.as_slice(),
+            &mut compressed,
+            super::CompressionLevel::Fastest,
+        )
+        .compress()
+        .unwrap();
+        let frame = crate::frame::read_frame_header(compressed.as_slice())
+            .unwrap()
+            .0;
+        assert_eq!(frame.header.window_size().unwrap(), 3584);
+        let mut decoded = Vec::new();
+        zstd::stream::copy_decode(compressed.as_slice(), &mut decoded).unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn single_segment() {
+        use super::FrameCompressorBuilder;
+        use crate::decoding::dictionary::Dictionary;
+
+        // Content that fits into the window is written without a window descriptor
+        for size in [0, 100, 3000, 200_000] {
+            let data: Vec<u8> = (0..size).map(|i| (i % 13 + i / 1000) as u8).collect();
+            let mut compressed = Vec::new();
+            FrameCompressorBuilder::new()
+                .content_size(true)
+                .build(data.as_slice(), &mut compressed)
+                .unwrap()
+                .compress()
+                .unwrap();
+            let (frame, header_size) =
+                crate::frame::read_frame_header(compressed.as_slice()).unwrap();
+            assert_eq!(
+                frame.header.descriptor.single_segment_flag(),
+                size <= 128 * 1024
+            );
+            if size <= 255 {
+                // Magic number, descriptor and a single byte for the content size
+                assert_eq!(header_size, 6);
+            }
+            assert_eq!(zstd::decode_all(compressed.as_slice()).unwrap(), data);
+        }
+
+        // Sequences can still reach into the whole dictionary
+        let dictionary = include_bytes!("../../dict_tests/dictionary");
+        let data = include_bytes!("../../dict_tests/files/ModemManager.service");
+        let mut compressed = Vec::new();
+        FrameCompressorBuilder::new()
+            .content_size(true)
+            .dictionary(Dictionary::decode_dict(dictionary).unwrap())
+            .build(&data[..], &mut compressed)
+            .unwrap()
+            .compress()
+            .unwrap();
+        let frame = crate::frame::read_frame_header(compressed.as_slice())
+            .unwrap()
+            .0;
+        assert!(frame.header.descriptor.single_segment_flag());
+        let mut decoder = zstd::bulk::Decompressor::with_dictionary(dictionary).unwrap();
+        assert_eq!(
+            decoder.decompress(&compressed, data.len()).unwrap(),
+            &data[..]
+        );
+    }
+
+    #[test]
+    fn repeat_offsets_across_blocks() {
+        // The same few offsets appear in every block, so the repeat offset history of the
+        // previous block is used right away in the next one.
+        let mut mock_data = Vec::new();
+        for random in lcg_bytes(1 << 20, 1).chunks_exact(2) {
+            if mock_data.len() >= 1 << 19 {
+                break;
+            }
+            mock_data.push(random[0]);
+            if mock_data.len() > 64 && random[1] & 3 == 0 {
+                let start = mock_data.len() - [17, 33, 64][usize::from(random[1] >> 2) % 3];
+                mock_data.extend_from_within(start..start + 10);
+            }
+        }
+        let mut output: Vec<u8> = Vec::new();
+        let mut compressor = FrameCompressor::new(
+            mock_data.as_slice(),
+            &mut output,
+            super::CompressionLevel::Fastest,
+        );
+        compressor.compress().unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(mock_data.len());
+        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+        assert_eq!(mock_data, decoded);
+
+        let mut decoded = Vec::new();
+        zstd::stream::copy_decode(output.as_slice(), &mut decoded).unwrap();
+        assert_eq!(mock_data, decoded);
+    }
+
+    #[test]
+    fn compress_bound() {
+        use super::{
+            compress_bound, CompressionLevel, EncodeOptions, IncrementalEncoder,
+            MIN_BOUNDED_BLOCK_SIZE,
+        };
+        use crate::decoding::dictionary::Dictionary;
+        use crate::encoding::FrameCompressorBuilder;
+        use core::convert::TryFrom;
+        use core::num::NonZeroU32;
+
+        // Random bytes, random bytes with short copies that barely pay as matches, and
+        // compressible data with incompressible stretches
+        let mut random_bytes = lcg_bytes(4_000_000, 7).into_iter();
+        let mut next = move || random_bytes.next().unwrap();
+        let mut inputs = Vec::new();
+        for len in [
+            0, 1, 2, 100, 1023, 1025, 5000, 65_536, 131_052, 131_072, 300_000,
+        ] {
+            let random: Vec<u8> = (0..len).map(|_| next()).collect();
+            let mut short_copies = Vec::new();
+            while short_copies.len() < len {
+                if short_copies.len() > 300 && next() % 4 == 0 {
+                    let start = short_copies.len() - 4 - usize::from(next());
+                    short_copies.extend_from_within(start..start + 4);
+                } else {
+                    short_copies.push(next());
+                }
+            }
+            short_copies.truncate(len);
+            let mixed: Vec<u8> = random
+                .iter()
+                .enumerate()
+                .map(|(i, x)| if i / 4000 % 2 == 0 { *x } else { (i % 9) as u8 })
+                .collect();
+            inputs.extend([random, short_copies, mixed]);
+        }
+
+        let levels = [
+            CompressionLevel::Uncompressed,
+            CompressionLevel::Fastest,
+            CompressionLevel::Auto,
+            CompressionLevel::Negative(NonZeroU32::new(5).unwrap()),
+            CompressionLevel::Other(super::ZstdLevel::try_from(20).unwrap()),
+        ];
+        for input in &inputs {
+            let bound = compress_bound(input.len());
+            for (index, level) in levels.iter().enumerate() {
+                let dictionary = Dictionary::from_raw_content(1, input[..input.len() / 3].to_vec());
+                let builder = FrameCompressorBuilder::new()
+                    .level(*level)
+                    .ultra(true)
+                    .content_size(index % 2 == 0)
+                    .dictionary(dictionary)
+                    .block_size([MIN_BOUNDED_BLOCK_SIZE, 4000, super::MAX_BLOCK_SIZE][index % 3]);
+                #[cfg(feature = "hash")]
+                let builder = builder.checksum(true);
+                let mut output = Vec::new();
+                builder
+                    .build(input.as_slice(), &mut output)
+                    .unwrap()
+                    .compress()
+                    .unwrap();
+                assert!(
+                    output.len() <= bound,
+                    "{} bytes at level {:?} took {} bytes, more than the bound of {}",
+                    input.len(),
+                    level,
+                    output.len(),
+                    bound
+                );
+
+                let options = EncodeOptions {
+                    level: *level,
+                    ultra: true,
+                    ..EncodeOptions::default()
+                };
+                let encode = |options: &EncodeOptions| {
+                    IncrementalEncoder::start(Vec::new(), options, None)
+                        .unwrap()
+                        .finish(input)
+                        .unwrap()
+                };
+                let output = encode(&options);
+                assert!(output.len() <= bound);
+                // No block is larger than it would be raw
+                let uncompressed = encode(&EncodeOptions {
+                    level: CompressionLevel::Uncompressed,
+                    ..options
+                });
+                assert!(output.len() <= uncompressed.len());
+            }
+        }
+        assert_eq!(compress_bound(0), 64);
+        assert_eq!(compress_bound(128 * 1024), 128 * 1024 + 512);
+        assert_eq!(compress_bound(usize::MAX), usize::MAX);
     }
 
     #[cfg(feature = "std")]
@@ -421,7 +2695,7 @@ mod tests {
                 &mut output,
                 crate::encoding::CompressionLevel::Uncompressed,
             );
-            compressor.compress();
+            compressor.compress().unwrap();
             output
         }
 
@@ -435,7 +2709,7 @@ mod tests {
                 &mut output,
                 crate::encoding::CompressionLevel::Uncompressed,
             );
-            compressor.compress();
+            compressor.compress().unwrap();
             output
         }
 
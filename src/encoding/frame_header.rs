@@ -1,15 +1,48 @@
 //! Utilities and representations for a frame header.
+//!
+//! Only the encoding of the `Frame_Content_Size` field is used without the `encoder` feature,
+//! to rewrite the headers of existing frames.
+#[cfg(feature = "encoder")]
 use crate::encoding::{
     bit_writer::BitWriter,
     util::{find_min_size, minify_val},
+    EncodeError,
 };
-use crate::frame;
+#[cfg(feature = "encoder")]
+use crate::fallible::TryVec;
+#[cfg(feature = "encoder")]
+use crate::frame::{self, ReadFrameHeaderError};
+#[cfg(feature = "encoder")]
+use crate::io::Read;
+#[cfg(feature = "encoder")]
 use alloc::vec::Vec;
 
-/// A header for a single Zstandard frame.
+/// A header for a single Zstandard frame, with the fields it declares.
+///
+/// Unlike [frame::FrameHeader], which describes a header that was read, this one can be built
+/// from its fields and written with [FrameHeader::serialize], e.g. by container formats that
+/// write the frame header themselves. [FrameHeader::parse] reads one back.
+///
+/// ```
+/// use ruzstd::encoding::FrameHeader;
+///
+/// let header = FrameHeader {
+///     frame_content_size: Some(1000),
+///     single_segment: false,
+///     content_checksum: true,
+///     dictionary_id: None,
+///     window_size: Some(1024),
+/// };
+/// let mut serialized = Vec::new();
+/// header.serialize(&mut serialized).unwrap();
+/// let (parsed, size) = FrameHeader::parse(serialized.as_slice()).unwrap();
+/// assert_eq!(parsed, header);
+/// assert_eq!(usize::from(size), serialized.len());
+/// ```
 ///
 /// <https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#frame_header>
-#[derive(Debug)]
+#[cfg(feature = "encoder")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FrameHeader {
     /// Optionally, the original (uncompressed) size of the data within the frame in bytes.
     /// If not present, `window_size` must be set.
@@ -21,41 +54,75 @@ pub struct FrameHeader {
     /// at the end of the frame.
     pub content_checksum: bool,
     /// If a dictionary ID is provided, the ID of that dictionary.
-    pub dictionary_id: Option<u64>,
+    pub dictionary_id: Option<u32>,
     /// The minimum memory buffer required to compress a frame. If not present,
     /// `single_segment` will be set to true. If present, this value must be greater than 1KB
     /// and less than 3.75TB. Encoders should not generate a frame that requires a window size larger than
     /// 8mb.
+    ///
+    /// Window sizes that the `Window_Descriptor` can not describe exactly are rounded up.
     pub window_size: Option<u64>,
 }
 
+#[cfg(feature = "encoder")]
 impl FrameHeader {
-    /// Writes the serialized frame header into the provided buffer.
+    /// Read a frame header, including the magic number in front of it, from `source`.
+    ///
+    /// Returns the header and the number of bytes that were read. Serializing the header again
+    /// describes the same frame, but may store the fields in fewer bytes than the original.
+    pub fn parse(source: impl Read) -> Result<(Self, u8), ReadFrameHeaderError> {
+        let (frame, size) = frame::read_frame_header(source)?;
+        Ok((Self::from(&frame.header), size))
+    }
+
+    /// Writes the serialized frame header, starting with the magic number, into the provided buffer.
     ///
     /// The returned header *does include* a frame header descriptor.
-    pub fn serialize(self, output: &mut Vec<u8>) {
+    ///
+    /// Fails with [EncodeError::InvalidFrameHeader] if `single_segment` is set without a
+    /// `frame_content_size`, or if it is not set and there is no `window_size` or one larger than
+    /// the format can describe. Nothing is written then.
+    pub fn serialize(&self, output: &mut Vec<u8>) -> Result<(), EncodeError> {
         vprintln!("Serializing frame with header: {self:?}");
+        let invalid = |message| EncodeError::InvalidFrameHeader { message };
+        let window_descriptor = match (self.single_segment, self.window_size) {
+            (true, _) if self.frame_content_size.is_none() => {
+                return Err(invalid("a single segment frame needs a frame content size"));
+            }
+            (true, _) => None,
+            (false, None) => {
+                return Err(invalid(
+                    "a frame without single segment needs a window size",
+                ))
+            }
+            (false, Some(window_size)) if window_size > MAX_DESCRIBED_WINDOW_SIZE => {
+                return Err(invalid("the window size is too large for the format"));
+            }
+            (false, Some(window_size)) => Some(window_descriptor(window_size).0),
+        };
+
         // https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#frame_header
         // Magic Number:
-        output.extend_from_slice(&frame::MAGIC_NUM.to_le_bytes());
+        output.try_extend_from_slice(&frame::MAGIC_NUM.to_le_bytes())?;
 
         // `Frame_Header_Descriptor`:
-        output.push(self.descriptor());
+        output.try_push(self.descriptor())?;
 
         // `Window_Descriptor
-        // TODO: https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#window_descriptor
-        if !self.single_segment {
-            let exponent = 7;
-            output.push(exponent << 3);
+        if let Some(descriptor) = window_descriptor {
+            output.try_push(descriptor)?;
         }
 
         if let Some(id) = self.dictionary_id {
-            output.extend(minify_val(id));
+            let (id, len) = minify_val(u64::from(id));
+            output.try_extend_from_slice(&id[..len])?;
         }
 
         if let Some(frame_content_size) = self.frame_content_size {
-            output.extend(minify_val_fcs(frame_content_size));
+            let (fcs, len) = minify_val_fcs(frame_content_size, self.single_segment);
+            output.try_extend_from_slice(&fcs[..len])?;
         }
+        Ok(())
     }
 
     /// Generate a serialized frame header descriptor for the frame header.
@@ -82,12 +149,12 @@ impl FrameHeader {
 
         // `Dictionary_ID_flag`:
         if let Some(id) = self.dictionary_id {
-            let flag_value: u8 = match find_min_size(id) {
+            let flag_value: u8 = match find_min_size(u64::from(id)) {
                 0 => 0,
                 1 => 1,
                 2 => 2,
-                4 => 3,
-                _ => panic!(),
+                // 4 bytes, a u32 needs no more
+                _ => 3,
             };
             bw.write_bits(flag_value, 2);
         } else {
@@ -115,24 +182,18 @@ impl FrameHeader {
         // and the `Frame_Content_Size` field must be present in the header.
         // If this flag is not set, the `Window_Descriptor` field must be present in the frame header.
         if self.single_segment {
-            assert!(self.frame_content_size.is_some(), "if the `single_segment` flag is set to true, then a frame content size must be provided");
             bw.write_bits(1u8, 1);
         } else {
-            assert!(
-                self.window_size.is_some(),
-                "if the `single_segment` flag is set to false, then a window size must be provided"
-            );
             bw.write_bits(0u8, 1);
         }
 
         if let Some(frame_content_size) = self.frame_content_size {
-            let field_size = find_min_size(frame_content_size);
-            let flag_value: u8 = match field_size {
+            let flag_value: u8 = match fcs_field_size(frame_content_size, self.single_segment) {
                 1 => 0,
                 2 => 1,
                 4 => 2,
-                3 => 8,
-                _ => panic!(),
+                // 8 bytes
+                _ => 3,
             };
 
             bw.write_bits(flag_value, 2);
@@ -145,21 +206,77 @@ impl FrameHeader {
     }
 }
 
-/// Identical to [`minify_val`], but it implements the following edge case:
+#[cfg(feature = "encoder")]
+impl From<&frame::FrameHeader> for FrameHeader {
+    fn from(header: &frame::FrameHeader) -> Self {
+        let descriptor = &header.descriptor;
+        let single_segment = descriptor.single_segment_flag();
+        let has_content_size = descriptor.frame_content_size_bytes().unwrap_or(0) != 0;
+        Self {
+            frame_content_size: has_content_size.then(|| header.frame_content_size()),
+            single_segment,
+            content_checksum: descriptor.content_checksum_flag(),
+            dictionary_id: header.dictionary_id(),
+            window_size: (!single_segment).then(|| header.described_window_size()),
+        }
+    }
+}
+
+/// The largest window a `Window_Descriptor` can describe, 15/8 * 2^41 bytes.
+#[cfg(feature = "encoder")]
+const MAX_DESCRIBED_WINDOW_SIZE: u64 = (1 << 41) + 7 * (1 << 38);
+
+/// Find the smallest `Window_Descriptor` that describes a window of at least `window_size` bytes.
+///
+/// Returns the descriptor and the window size it describes. Sizes beyond
+/// [MAX_DESCRIBED_WINDOW_SIZE] get the largest descriptor.
+///
+/// https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#window_descriptor
+#[cfg(feature = "encoder")]
+pub(crate) fn window_descriptor(window_size: u64) -> (u8, u64) {
+    for exponent in 0..=31u8 {
+        let window_base = 1u64 << (10 + exponent);
+        for mantissa in 0..8u8 {
+            let size = window_base + (window_base / 8) * u64::from(mantissa);
+            if size >= window_size {
+                return ((exponent << 3) | mantissa, size);
+            }
+        }
+    }
+    (0xFF, MAX_DESCRIBED_WINDOW_SIZE)
+}
+
+/// The number of bytes the `Frame_Content_Size` field needs to store `val`.
+///
+/// A single byte can only be used in single segment frames, because the flag value 0 means
+/// that there is no field at all otherwise. Two byte fields store the value with an offset of 256,
+/// so they can hold 256 to 65791.
+pub(crate) fn fcs_field_size(val: u64, single_segment: bool) -> usize {
+    match val {
+        0..=255 if single_segment => 1,
+        256..=65791 => 2,
+        0..=0xFFFF_FFFF => 4,
+        _ => 8,
+    }
+}
+
+/// Similar to [`minify_val`], but it implements the following edge case:
 ///
 /// > When FCS_Field_Size is 1, 4 or 8 bytes, the value is read directly. When FCS_Field_Size is 2, the offset of 256 is added.
 ///
 /// https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#frame_content_size
-fn minify_val_fcs(val: u64) -> Vec<u8> {
-    let new_size = find_min_size(val);
+///
+/// Returns the bytes together with how many of them are used.
+pub(crate) fn minify_val_fcs(val: u64, single_segment: bool) -> ([u8; 8], usize) {
+    let new_size = fcs_field_size(val, single_segment);
     let mut val = val;
     if new_size == 2 {
         val -= 256;
     }
-    val.to_le_bytes()[0..new_size].to_vec()
+    (val.to_le_bytes(), new_size)
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "encoder"))]
 mod tests {
     use super::FrameHeader;
     use crate::frame::{read_frame_header, FrameDescriptor};
@@ -192,7 +309,7 @@ mod tests {
         };
 
         let mut serialized_header = Vec::new();
-        header.serialize(&mut serialized_header);
+        header.serialize(&mut serialized_header).unwrap();
         let parsed_header = read_frame_header(serialized_header.as_slice())
             .unwrap()
             .0
@@ -202,7 +319,6 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
     fn catches_single_segment_no_fcs() {
         let header = FrameHeader {
             frame_content_size: None,
@@ -213,11 +329,11 @@ mod tests {
         };
 
         let mut serialized_header = Vec::new();
-        header.serialize(&mut serialized_header);
+        assert!(header.serialize(&mut serialized_header).is_err());
+        assert!(serialized_header.is_empty());
     }
 
     #[test]
-    #[should_panic]
     fn catches_single_segment_no_winsize() {
         let header = FrameHeader {
             frame_content_size: Some(7),
@@ -228,6 +344,106 @@ mod tests {
         };
 
         let mut serialized_header = Vec::new();
-        header.serialize(&mut serialized_header);
+        assert!(header.serialize(&mut serialized_header).is_err());
+
+        let header = FrameHeader {
+            window_size: Some(u64::MAX),
+            ..header
+        };
+        assert!(header.serialize(&mut serialized_header).is_err());
+        assert!(serialized_header.is_empty());
+    }
+
+    #[test]
+    fn frame_content_size() {
+        for single_segment in [true, false] {
+            for fcs in [0, 1, 255, 256, 65535, 65791, 65792, 1 << 32, u64::MAX] {
+                let header = FrameHeader {
+                    frame_content_size: Some(fcs),
+                    single_segment,
+                    content_checksum: false,
+                    dictionary_id: None,
+                    window_size: Some(1024),
+                };
+                let mut serialized_header = Vec::new();
+                header.serialize(&mut serialized_header).unwrap();
+                let parsed_header = read_frame_header(serialized_header.as_slice())
+                    .unwrap()
+                    .0
+                    .header;
+                assert_eq!(parsed_header.frame_content_size(), fcs);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_serialized() {
+        let mut headers = Vec::new();
+        for dictionary_id in [None, Some(1), Some(300), Some(70_000), Some(u32::MAX)] {
+            for (frame_content_size, single_segment, window_size) in [
+                (Some(0), true, None),
+                (Some(300), true, None),
+                (None, false, Some(1024)),
+                (Some(u64::MAX), false, Some(1 << 30)),
+            ] {
+                headers.push(FrameHeader {
+                    frame_content_size,
+                    single_segment,
+                    content_checksum: dictionary_id.is_some(),
+                    dictionary_id,
+                    window_size,
+                });
+            }
+        }
+        for header in headers {
+            let mut serialized = Vec::new();
+            header.serialize(&mut serialized).unwrap();
+            let (parsed, size) = FrameHeader::parse(serialized.as_slice()).unwrap();
+            assert_eq!(parsed, header);
+            assert_eq!(usize::from(size), serialized.len());
+        }
+
+        // Headers that were not written by this encoder describe the same frame after a roundtrip
+        let frame = include_bytes!("../../decodecorpus_files/z000089.zst");
+        let (header, _) = FrameHeader::parse(&frame[..]).unwrap();
+        let (original, _) = read_frame_header(&frame[..]).unwrap();
+        assert_eq!(
+            header.window_size,
+            Some(original.header.window_size().unwrap())
+        );
+        let mut serialized = Vec::new();
+        header.serialize(&mut serialized).unwrap();
+        assert_eq!(FrameHeader::parse(serialized.as_slice()).unwrap().0, header);
+    }
+
+    #[test]
+    fn window_descriptor() {
+        use super::window_descriptor;
+
+        assert_eq!(window_descriptor(0), (0, 1024));
+        assert_eq!(window_descriptor(1024), (0, 1024));
+        assert_eq!(window_descriptor(1025), (1, 1024 + 128));
+        assert_eq!(window_descriptor(128 * 1024), (7 << 3, 128 * 1024));
+        assert_eq!(window_descriptor(100_000_000), ((16 << 3) | 4, 100_663_296));
+
+        for window_size in [1024, 5000, 128 * 1024, 1 << 27, 3 << 29] {
+            let header = FrameHeader {
+                frame_content_size: None,
+                single_segment: false,
+                content_checksum: false,
+                dictionary_id: None,
+                window_size: Some(window_size),
+            };
+            let mut serialized_header = Vec::new();
+            header.serialize(&mut serialized_header).unwrap();
+            let parsed_header = read_frame_header(serialized_header.as_slice())
+                .unwrap()
+                .0
+                .header;
+            assert_eq!(
+                parsed_header.window_size().unwrap(),
+                window_descriptor(window_size).1
+            );
+        }
     }
 }
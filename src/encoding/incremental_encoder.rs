@@ -0,0 +1,838 @@
+//! A low level encoder that writes a frame piece by piece.
+
+use alloc::vec::Vec;
+use core::convert::{TryFrom, TryInto};
+use core::ops::Range;
+
+use super::{
+    auto_level,
+    blocks::{compress_block, EntropyTables},
+    frame_encoder::{
+        check_sizes, default_strategy, frame_window_size, level_implemented, ultra_search_log,
+        Prefix, MAX_ACCELERATION, MAX_BLOCK_SIZE,
+    },
+    frame_header::{window_descriptor, FrameHeader},
+    match_generator::{MatchGenerator, SearchParams},
+    util::WindowBounds,
+    ChunkState, Chunker, CompressionLevel, EncodeError, EncodeOptions, SearchOverrides, Strategy,
+};
+use crate::blocks::block::{BlockHeader, BlockType};
+use crate::blocks::sequence_section::OffsetHistory;
+use crate::fallible::try_with_capacity;
+use crate::io::Write;
+use crate::metrics::{CodecMetrics, Stage, StageTimer};
+use alloc::sync::Arc;
+#[cfg(feature = "std")]
+use {super::adapt::Adapter, std::time::Instant};
+
+/// Writes a single frame in three phases: [IncrementalEncoder::start] writes the frame header,
+/// [IncrementalEncoder::write_chunk] compresses a chunk of the content into blocks and
+/// [IncrementalEncoder::finish] writes the last blocks and the checksum.
+///
+/// Each chunk ends a block, so the caller decides where blocks end (chunks that are larger than
+/// the block size are split into several blocks, and long runs of one byte get RLE blocks of their
+/// own). Sequences still reference the content of
+/// earlier chunks, as far as the window reaches.
+///
+/// # Examples
+/// ```
+/// use ruzstd::encoding::{EncodeOptions, IncrementalEncoder};
+/// let mut output = Vec::new();
+/// let mut encoder = IncrementalEncoder::start(&mut output, &EncodeOptions::default(), None).unwrap();
+/// encoder.write_chunk(b"Hello, ").unwrap();
+/// encoder.write_chunk(b"World").unwrap();
+/// encoder.finish(b"!").unwrap();
+///
+/// let mut decoded = Vec::with_capacity(13);
+/// ruzstd::FrameDecoder::new()
+///     .decode_all_to_vec(&output, &mut decoded)
+///     .unwrap();
+/// assert_eq!(decoded, b"Hello, World!");
+/// ```
+pub struct IncrementalEncoder<W: Write> {
+    output: W,
+    /// [CompressionLevel::Auto] until the first data arrives
+    level: CompressionLevel,
+    /// Whether [CompressionLevel::Auto] picks the strategy, too
+    auto_strategy: bool,
+    search_overrides: SearchOverrides,
+    max_block_size: usize,
+    window_size: u64,
+    matcher: MatchGenerator,
+    offset_hist: OffsetHistory,
+    /// The entropy tables of the earlier compressed blocks of the frame
+    tables: EntropyTables,
+    /// The number of bytes in front of the next block, including the prefix
+    history: u64,
+    content_size: u64,
+    declared_content_size: Option<u64>,
+    #[cfg(feature = "hash")]
+    hasher: Option<twox_hash::XxHash64>,
+    metrics: Option<Arc<dyn CodecMetrics>>,
+    /// Finds the points where [EncodeOptions::rsyncable] starts over
+    sync_points: Option<(Chunker, ChunkState)>,
+    /// The size of the jobs of [EncodeOptions::job_size] and how many bytes of the window each
+    /// one keeps from the job in front of it
+    jobs: Option<(usize, usize)>,
+    /// The bytes that are left in the current job
+    job_remaining: usize,
+    /// Picks the level after each block for [EncodeOptions::adapt]
+    #[cfg(feature = "std")]
+    adapter: Option<Adapter>,
+    /// Scratch space for compressed blocks, whose size has to be known before the header is written.
+    /// Reused between blocks so it never grows past the largest block that was compressed.
+    compressed: Vec<u8>,
+}
+
+impl<W: Write> IncrementalEncoder<W> {
+    /// Write the header of a new frame into `output`.
+    ///
+    /// The frame declares `content_size` in its header if it is given, and [finish](IncrementalEncoder::finish)
+    /// fails if the content does not match it. `options.content_size` is not used, the encoder can not
+    /// know the size in advance. A known `content_size` also makes the window as small as
+    /// [frame_window_size](super::frame_window_size) says.
+    ///
+    /// Returns [EncodeError::UnsupportedLevel] for compression levels that are not implemented yet,
+    /// and for levels 20 to 22 without [EncodeOptions::ultra]. Returns [EncodeError::InvalidConfig]
+    /// if `options.window_log` or `options.block_size` are invalid.
+    pub fn start(
+        output: W,
+        options: &EncodeOptions,
+        content_size: Option<u64>,
+    ) -> Result<Self, EncodeError> {
+        check_sizes(options)?;
+        let min_window_size = frame_window_size(options, content_size);
+        let prefix = match options.dictionary.as_deref() {
+            Some(dictionary) => Prefix::Dictionary(dictionary),
+            None => Prefix::None,
+        };
+        Self::start_frame(output, options, prefix, min_window_size, content_size)
+    }
+
+    /// Start a frame with a window of at least `min_window_size` bytes, in which sequences
+    /// may reference the `prefix`.
+    pub(super) fn start_frame(
+        mut output: W,
+        options: &EncodeOptions,
+        prefix: Prefix<'_>,
+        min_window_size: u64,
+        content_size: Option<u64>,
+    ) -> Result<Self, EncodeError> {
+        let level = options.level.normalize();
+        if !level_implemented(level, options.ultra) {
+            return Err(EncodeError::UnsupportedLevel { level });
+        }
+        let (_, window_size) = window_descriptor(min_window_size);
+        // The match generator only allocates what it needs, so clamping on small targets is fine
+        let mut matcher = MatchGenerator::new(usize::try_from(window_size).unwrap_or(usize::MAX));
+        let strategy = options.strategy.unwrap_or_else(|| default_strategy(level));
+        #[cfg(feature = "std")]
+        let adapter = options
+            .adapt
+            .map(|adapt| Adapter::new(adapt, level, strategy))
+            .transpose()?;
+        // The adapter takes over from the level, also from CompressionLevel::Auto
+        #[cfg(feature = "std")]
+        let (level, strategy) = adapter.as_ref().map_or((level, strategy), Adapter::step);
+        let search_overrides = SearchOverrides::new(options);
+        matcher.set_search(search_params(search_overrides, level, strategy));
+        let (history, dictionary_id, offsets) = match prefix {
+            Prefix::None => (0, None, OffsetHistory::INITIAL),
+            Prefix::PatchReference(reference) => {
+                if !reference.is_empty() {
+                    matcher.add_data_no_matching(reference)?;
+                }
+                (reference.len(), None, OffsetHistory::INITIAL)
+            }
+            Prefix::Dictionary(encoder_dictionary) => {
+                // Only the end of the dictionary fits into the window, next to the data
+                let dictionary = encoder_dictionary.dictionary();
+                let content = &dictionary.dict_content;
+                let reachable = usize::try_from(window_size - 1).unwrap_or(usize::MAX);
+                let first = content.len().saturating_sub(reachable);
+                if first < content.len() {
+                    matcher.set_dictionary(encoder_dictionary.matcher().clone(), first)?;
+                }
+                (
+                    content.len() - first,
+                    Some(dictionary.id).filter(|id| *id != 0),
+                    dictionary.offset_hist,
+                )
+            }
+        };
+
+        #[cfg(feature = "hash")]
+        let checksum = options.checksum;
+        #[cfg(not(feature = "hash"))]
+        let checksum = false;
+        // Like the reference implementation, frames whose content fits into the window leave out
+        // the window descriptor. Decoders then use the content size as the window, which is all
+        // they have to allocate, and sequences can still reach into the dictionary.
+        let single_segment = content_size.is_some_and(|size| size <= window_size);
+        let mut buffer = try_with_capacity(18)?;
+        let header = FrameHeader {
+            frame_content_size: content_size,
+            single_segment,
+            content_checksum: checksum,
+            dictionary_id,
+            window_size: (!single_segment).then_some(window_size),
+        };
+        header.serialize(&mut buffer)?;
+        if options.magicless {
+            buffer.drain(..4);
+        }
+        output
+            .write_all(&buffer)
+            .map_err(EncodeError::FailedToWriteOutput)?;
+        if let Some(metrics) = &options.metrics {
+            metrics.bytes_out(buffer.len() as u64);
+        }
+
+        trace_event!(
+            level = ?level,
+            strategy = ?strategy,
+            window_size,
+            content_size,
+            dictionary_id,
+            checksum,
+            "frame start"
+        );
+        Ok(Self {
+            output,
+            level,
+            auto_strategy: options.strategy.is_none(),
+            search_overrides,
+            max_block_size: options.block_size.unwrap_or(MAX_BLOCK_SIZE),
+            window_size,
+            matcher,
+            // The repeat offsets start fresh in every frame (or with the ones of the dictionary)
+            // and carry over between compressed blocks
+            offset_hist: OffsetHistory::from_offsets(offsets),
+            tables: EntropyTables::default(),
+            history: history as u64,
+            content_size: 0,
+            declared_content_size: content_size,
+            #[cfg(feature = "hash")]
+            hasher: checksum.then(|| twox_hash::XxHash64::with_seed(0)),
+            metrics: options.metrics.clone(),
+            sync_points: options
+                .rsyncable
+                .then(|| (rsync_chunker(), ChunkState::default())),
+            jobs: options.job_size.map(|job_size| {
+                let overlap = overlap_size(window_size, options.overlap_log.unwrap_or(0));
+                (job_size as usize, overlap)
+            }),
+            job_remaining: options.job_size.map_or(0, |job_size| job_size as usize),
+            #[cfg(feature = "std")]
+            adapter,
+            compressed: Vec::new(),
+        })
+    }
+
+    /// Compress `chunk` into one or more blocks and write them to the output.
+    ///
+    /// Empty chunks are ignored.
+    pub fn write_chunk(&mut self, chunk: &[u8]) -> Result<(), EncodeError> {
+        self.write_blocks(chunk, false)
+    }
+
+    /// Compress `chunk` into the last blocks of the frame and write them, followed by the checksum
+    /// if one was requested. An empty `chunk` ends the frame with an empty block.
+    ///
+    /// Returns the output, or [EncodeError::ContentSizeMismatch] if the content does not match
+    /// the size that was declared in the header.
+    pub fn finish(mut self, chunk: &[u8]) -> Result<W, EncodeError> {
+        self.write_blocks(chunk, true)?;
+        if let Some(declared) = self.declared_content_size {
+            if declared != self.content_size {
+                return Err(EncodeError::ContentSizeMismatch {
+                    declared,
+                    actual: self.content_size,
+                });
+            }
+        }
+        #[cfg(feature = "hash")]
+        if let Some(hasher) = &self.hasher {
+            use core::hash::Hasher;
+
+            // Only the lower 32 bits are stored
+            self.write_output(&(hasher.finish() as u32).to_le_bytes())?;
+            if let Some(metrics) = &self.metrics {
+                metrics.bytes_out(4);
+            }
+        }
+        trace_event!(content_size = self.content_size, "frame end");
+        Ok(self.output)
+    }
+
+    /// The number of content bytes that were written into the frame so far.
+    pub fn content_size(&self) -> u64 {
+        self.content_size
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.output
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Destructures this object into the inner writer without ending the frame.
+    ///
+    /// The output is not a complete frame, use [IncrementalEncoder::finish] for that.
+    pub fn into_inner(self) -> W {
+        self.output
+    }
+
+    /// The most content that is put into one block.
+    pub(crate) fn max_block_size(&self) -> usize {
+        self.max_block_size
+    }
+
+    /// Flush the output.
+    pub(super) fn flush_output(&mut self) -> Result<(), crate::io::Error> {
+        self.output.flush()
+    }
+
+    fn write_blocks(&mut self, data: &[u8], last: bool) -> Result<(), EncodeError> {
+        if let Some(declared) = self.declared_content_size {
+            if self.content_size + data.len() as u64 > declared {
+                return Err(EncodeError::ContentSizeMismatch {
+                    declared,
+                    actual: self.content_size + data.len() as u64,
+                });
+            }
+        }
+        #[cfg(feature = "hash")]
+        if let Some(hasher) = &mut self.hasher {
+            use core::hash::Hasher;
+            hasher.write(data);
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.bytes_in(data.len() as u64);
+        }
+
+        if self.level == CompressionLevel::Auto && !data.is_empty() {
+            let (level, strategy) = auto_level::choose(data)?;
+            self.level = level;
+            if self.auto_strategy {
+                self.matcher
+                    .set_search(search_params(self.search_overrides, level, strategy));
+            }
+            trace_event!(level = ?level, strategy = ?strategy, "picked level");
+        }
+
+        // The last block can only be marked in its header, so without data it has to be empty
+        if data.is_empty() && last {
+            return self.write_raw_block(&[], true);
+        }
+
+        let Some((job_size, overlap)) = self.jobs else {
+            return self.write_job(data, last);
+        };
+        let mut data = data;
+        loop {
+            if self.job_remaining == 0 && !data.is_empty() {
+                trace_event!(content_size = self.content_size, "job start");
+                self.start_over(overlap)?;
+                self.job_remaining = job_size;
+            }
+            let (job, rest) = data.split_at(usize::min(self.job_remaining, data.len()));
+            self.job_remaining -= job.len();
+            if rest.is_empty() {
+                return self.write_job(job, last);
+            }
+            self.write_job(job, false)?;
+            data = rest;
+        }
+    }
+
+    /// Write the part of a job in `data`, starting over at the sync points of
+    /// [EncodeOptions::rsyncable].
+    fn write_job(&mut self, data: &[u8], last: bool) -> Result<(), EncodeError> {
+        let mut data = data;
+        while let Some(end) = self
+            .sync_points
+            .as_mut()
+            .and_then(|(chunker, state)| chunker.find_boundary(state, data))
+        {
+            self.write_section(&data[..end], last && end == data.len())?;
+            trace_event!(content_size = self.content_size, "sync point");
+            self.start_over(0)?;
+            data = &data[end..];
+        }
+        if !data.is_empty() {
+            self.write_section(data, last)?;
+        }
+        Ok(())
+    }
+
+    /// Cut `data` into blocks of at most the block size and write them.
+    fn write_section(&mut self, data: &[u8], last: bool) -> Result<(), EncodeError> {
+        let mut index = 0;
+        while index < data.len() {
+            let last_block = last && index + self.max_block_size >= data.len();
+            // We read till the end of the data, or till the max block size, whichever comes sooner
+            let block_size = usize::min(data.len() - index, self.max_block_size);
+            self.write_block(&data[index..index + block_size], last_block)?;
+            index += block_size;
+        }
+        Ok(())
+    }
+
+    /// Forget everything the following blocks could depend on at a sync point of
+    /// [EncodeOptions::rsyncable] or the start of a job, except for the last `keep` bytes of
+    /// the window, so they come out the same whatever is in front of those.
+    fn start_over(&mut self, keep: usize) -> Result<(), EncodeError> {
+        self.matcher.reset_keeping(keep)?;
+        self.tables = EntropyTables::default();
+        // The decoder keeps the repeat offsets from before the sync point. No match has offset 0,
+        // so repeat codes are only used again for offsets that were sent after it.
+        self.offset_hist = OffsetHistory::from_offsets([0; 3]);
+        Ok(())
+    }
+
+    fn write_block(&mut self, uncompressed: &[u8], last_block: bool) -> Result<(), EncodeError> {
+        trace_span!("encode_block", size = uncompressed.len(), last_block);
+        #[cfg(feature = "std")]
+        let start = self.adapter.as_ref().map(|_| Instant::now());
+        let mut rest = uncompressed;
+        if self.level != CompressionLevel::Uncompressed {
+            // Long runs of one byte get RLE blocks of their own, matching them piece by piece
+            // costs more
+            while let Some(run) = find_run(rest) {
+                if run.start > 0 {
+                    self.write_segment(&rest[..run.start], false)?;
+                }
+                let last_segment = last_block && run.end == rest.len();
+                self.write_segment(&rest[run.clone()], last_segment)?;
+                rest = &rest[run.end..];
+            }
+        }
+        if !rest.is_empty() {
+            self.write_segment(rest, last_block)?;
+        }
+        self.content_size += uncompressed.len() as u64;
+        #[cfg(feature = "std")]
+        if let (Some(adapter), Some(start)) = (&mut self.adapter, start) {
+            if adapter.block_done(start.elapsed()) {
+                let (level, strategy) = adapter.step();
+                self.level = level;
+                self.matcher
+                    .set_search(search_params(self.search_overrides, level, strategy));
+                trace_event!(level = ?level, strategy = ?strategy, "adapted level");
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a part of a block as one block: raw if the level says so, as an RLE block if it is
+    /// one byte repeated, and as a compressed block otherwise.
+    fn write_segment(&mut self, uncompressed: &[u8], last_block: bool) -> Result<(), EncodeError> {
+        let timer = self.metrics.as_ref().map(|_| StageTimer::start());
+        if self.level == CompressionLevel::Uncompressed {
+            self.write_raw_block(uncompressed, last_block)?;
+        } else if uncompressed.iter().all(|x| uncompressed[0].eq(x)) {
+            self.matcher.add_run_no_matching(uncompressed)?;
+            self.write_header(BlockType::RLE, uncompressed.len(), last_block)?;
+            self.write_output(&uncompressed[..1])?;
+        } else {
+            self.compressed.clear();
+            let previous_offset_hist = self.offset_hist;
+            let previous_tables = self.tables.try_clone()?;
+            compress_block(
+                &mut self.matcher,
+                &mut self.offset_hist,
+                &mut self.tables,
+                WindowBounds {
+                    window_size: self.window_size,
+                    history: self.history,
+                },
+                uncompressed,
+                &mut self.compressed,
+            )?;
+            // A raw block is never larger than its content, which keeps frames within
+            // compress_bound. Blocks can not be larger than the window either, which is smaller
+            // than 128 KiB for small frames.
+            if self.compressed.len() >= uncompressed.len()
+                || self.compressed.len() as u64 > self.window_size
+            {
+                // The decoder never sees the sequences of this block
+                self.offset_hist = previous_offset_hist;
+                self.tables = previous_tables;
+                trace_event!(
+                    compressed_size = self.compressed.len(),
+                    "compressed block is not smaller, storing it raw"
+                );
+                self.write_raw_block(uncompressed, last_block)?;
+            } else {
+                self.write_header(BlockType::Compressed, self.compressed.len(), last_block)?;
+                let compressed = core::mem::take(&mut self.compressed);
+                let written = self.write_output(&compressed);
+                self.compressed = compressed;
+                written?;
+            }
+        }
+        self.history += uncompressed.len() as u64;
+        if let (Some(metrics), Some(timer)) = (&self.metrics, timer) {
+            timer.finish(metrics.as_ref(), Stage::EncodeBlock);
+        }
+        Ok(())
+    }
+
+    /// Write the block straight from the input, without copying it.
+    fn write_raw_block(
+        &mut self,
+        uncompressed: &[u8],
+        last_block: bool,
+    ) -> Result<(), EncodeError> {
+        self.write_header(BlockType::Raw, uncompressed.len(), last_block)?;
+        self.write_output(uncompressed)
+    }
+
+    fn write_header(
+        &mut self,
+        block_type: BlockType,
+        block_size: usize,
+        last_block: bool,
+    ) -> Result<(), EncodeError> {
+        let too_large = || EncodeError::InputTooLarge {
+            size: block_size,
+            max: MAX_BLOCK_SIZE,
+        };
+        let header = BlockHeader::new(
+            last_block,
+            block_type,
+            block_size.try_into().map_err(|_| too_large())?,
+        );
+        if let Some(metrics) = &self.metrics {
+            let payload_size = match block_type {
+                BlockType::RLE => 1,
+                _ => block_size,
+            };
+            metrics.block(block_type);
+            metrics.bytes_out(3 + payload_size as u64);
+        }
+        self.write_output(&header.serialize().ok_or_else(too_large)?)
+    }
+
+    fn write_output(&mut self, data: &[u8]) -> Result<(), EncodeError> {
+        #[cfg(feature = "std")]
+        let start = self.adapter.as_ref().map(|_| Instant::now());
+        self.output
+            .write_all(data)
+            .map_err(EncodeError::FailedToWriteOutput)?;
+        #[cfg(feature = "std")]
+        if let (Some(adapter), Some(start)) = (&mut self.adapter, start) {
+            adapter.add_output_time(start.elapsed());
+        }
+        Ok(())
+    }
+
+    /// The level and strategy [EncodeOptions::adapt] compresses the next block with, `None`
+    /// without it.
+    #[cfg(feature = "std")]
+    pub fn adapted_level(&self) -> Option<(CompressionLevel, Strategy)> {
+        self.adapter.as_ref().map(Adapter::step)
+    }
+}
+
+/// How the match generator searches at `level` with `strategy`, and the search parameters of the
+/// options on top.
+fn search_params(
+    overrides: SearchOverrides,
+    level: CompressionLevel,
+    strategy: Strategy,
+) -> SearchParams {
+    let mut search = strategy.search_params();
+    if let CompressionLevel::Negative(n) = level {
+        search.acceleration = n.get().min(MAX_ACCELERATION);
+    }
+    // Levels 20 to 22 search deeper than the strategy does on its own
+    if let (Some(search_log), Strategy::BtUltra) = (ultra_search_log(level), strategy) {
+        search.chain_depth = 1 << search_log;
+    }
+    overrides.apply(search)
+}
+
+/// The sync points of [EncodeOptions::rsyncable], about 256 KiB apart.
+fn rsync_chunker() -> Chunker {
+    Chunker::new(32 * 1024, 256 * 1024, 1024 * 1024)
+}
+
+/// The bytes of a window of `window_size` bytes that each job of [EncodeOptions::job_size] keeps
+/// with [EncodeOptions::overlap_log], like in the reference implementation.
+fn overlap_size(window_size: u64, overlap_log: u8) -> usize {
+    let overlap_log = if overlap_log == 0 { 6 } else { overlap_log };
+    if overlap_log <= 1 {
+        return 0;
+    }
+    // The matcher needs room for at least one more byte
+    let overlap = u64::min(window_size >> (9 - overlap_log), window_size - 1);
+    usize::try_from(overlap).unwrap_or(usize::MAX)
+}
+
+/// Runs of one byte of at least this length are cut out of their block into an RLE block.
+const MIN_RLE_RUN: usize = 4096;
+
+/// The first run of one byte in `data` that is at least [MIN_RLE_RUN] long.
+fn find_run(data: &[u8]) -> Option<Range<usize>> {
+    let mut start = 0;
+    for index in 1..=data.len() {
+        if index == data.len() || data[index] != data[start] {
+            if index - start >= MIN_RLE_RUN {
+                return Some(start..index);
+            }
+            start = index;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IncrementalEncoder;
+    use crate::encoding::{EncodeError, EncodeOptions};
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use crate::test_utils::lcg_bytes;
+    use alloc::vec::Vec;
+
+    /// Decode the frame block by block, returns the content and the number of blocks
+    fn decode(frame: &[u8]) -> (Vec<u8>, usize) {
+        let mut decoder = FrameDecoder::new();
+        let mut source = frame;
+        decoder.reset(&mut source).unwrap();
+        while !decoder.is_finished() {
+            decoder
+                .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+                .unwrap();
+        }
+        assert!(source.is_empty());
+        (decoder.collect().unwrap(), decoder.blocks_decoded())
+    }
+
+    #[test]
+    fn chunks_end_blocks() {
+        let chunk: Vec<u8> = (0..5000u32).map(|i| (i * 31 % 253) as u8).collect();
+        let mut output = Vec::new();
+        let mut encoder =
+            IncrementalEncoder::start(&mut output, &EncodeOptions::default(), Some(15_000))
+                .unwrap();
+        encoder.write_chunk(&chunk).unwrap();
+        encoder.write_chunk(&[]).unwrap();
+        let after_first = encoder.content_size();
+        encoder.write_chunk(&chunk).unwrap();
+        encoder.finish(&chunk).unwrap();
+        assert_eq!(after_first, 5000);
+        // the repeated chunks are encoded as matches into the first one
+        assert!(output.len() < 5000 + 100, "{}", output.len());
+
+        let (decoded, blocks) = decode(&output);
+        assert_eq!(blocks, 3);
+        assert_eq!(decoded, chunk.repeat(3));
+
+        // small blocks split chunks
+        let options = EncodeOptions {
+            block_size: Some(2000),
+            ..EncodeOptions::default()
+        };
+        let mut output = Vec::new();
+        let mut encoder = IncrementalEncoder::start(&mut output, &options, None).unwrap();
+        encoder.write_chunk(&chunk).unwrap();
+        encoder.finish(&[]).unwrap();
+        let (decoded, blocks) = decode(&output);
+        assert_eq!(blocks, 4);
+        assert_eq!(decoded, chunk);
+    }
+
+    #[test]
+    fn content_size() {
+        let mut output = Vec::new();
+        let mut encoder =
+            IncrementalEncoder::start(&mut output, &EncodeOptions::default(), Some(10)).unwrap();
+        encoder.write_chunk(&[1; 6]).unwrap();
+        assert!(matches!(
+            encoder.write_chunk(&[2; 6]),
+            Err(EncodeError::ContentSizeMismatch {
+                declared: 10,
+                actual: 12
+            })
+        ));
+        assert!(matches!(
+            encoder.finish(&[3; 2]),
+            Err(EncodeError::ContentSizeMismatch {
+                declared: 10,
+                actual: 8
+            })
+        ));
+
+        let mut output = Vec::new();
+        IncrementalEncoder::start(&mut output, &EncodeOptions::default(), Some(0))
+            .unwrap()
+            .finish(&[])
+            .unwrap();
+        assert_eq!(decode(&output), (Vec::new(), 1));
+    }
+
+    #[test]
+    fn runs_get_rle_blocks() {
+        let text: Vec<u8> = (0..3000u32).map(|i| (i * 31 % 253) as u8).collect();
+        let mut chunk = text.clone();
+        chunk.extend_from_slice(&[0; 10_000]);
+        chunk.extend_from_slice(&text);
+        chunk.extend_from_slice(&[7; super::MIN_RLE_RUN - 1]);
+        chunk.extend_from_slice(&text);
+        chunk.extend_from_slice(&[1; 5000]);
+
+        let mut output = Vec::new();
+        let mut encoder =
+            IncrementalEncoder::start(&mut output, &EncodeOptions::default(), None).unwrap();
+        encoder.write_chunk(&chunk).unwrap();
+        encoder.finish(&chunk).unwrap();
+        // The text, the run of zeros and the rest up to the run of ones, in each chunk
+        let (decoded, blocks) = decode(&output);
+        assert_eq!(blocks, 8);
+        assert_eq!(decoded, chunk.repeat(2));
+        assert!(output.len() < 3000 + 200, "{}", output.len());
+    }
+
+    #[test]
+    fn rsyncable() {
+        const WORDS: [&[u8]; 8] = [
+            b"zstd ",
+            b"frame ",
+            b"block ",
+            b"window ",
+            b"offset ",
+            b"literal ",
+            b"match ",
+            b"\n",
+        ];
+        let mut data = Vec::new();
+        for pair in lcg_bytes(2_000_000, 7).chunks_exact(2) {
+            if data.len() >= 1_200_000 {
+                break;
+            }
+            data.extend_from_slice(WORDS[usize::from(pair[0] >> 5)]);
+            data.push(pair[1]);
+        }
+        let mut edited = data[..300_000].to_vec();
+        edited.extend_from_slice(b"a small edit near the start");
+        edited.extend_from_slice(&data[300_000..]);
+
+        let compress = |data: &[u8], rsyncable: bool| {
+            let options = EncodeOptions {
+                rsyncable,
+                ..EncodeOptions::default()
+            };
+            let output = IncrementalEncoder::start(Vec::new(), &options, None)
+                .unwrap()
+                .finish(data)
+                .unwrap();
+            assert_eq!(zstd::decode_all(output.as_slice()).unwrap(), data);
+            assert_eq!(decode(&output).0, data);
+            output
+        };
+        // The bytes at the end of both outputs that are the same
+        let common_suffix = |a: &[u8], b: &[u8]| {
+            a.iter()
+                .rev()
+                .zip(b.iter().rev())
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+
+        let original = compress(&data, true);
+        let changed = compress(&edited, true);
+        // Everything behind the first sync point after the edit is the same
+        let suffix = common_suffix(&original, &changed);
+        assert!(
+            suffix > original.len() / 2,
+            "{} of {}",
+            suffix,
+            original.len()
+        );
+        // The sync points cost little
+        let plain = compress(&data, false);
+        assert!(original.len() < plain.len() + plain.len() / 20);
+        assert!(common_suffix(&plain, &compress(&edited, false)) < 100);
+    }
+
+    #[test]
+    fn jobs() {
+        let part = lcg_bytes(150_000, 3);
+        let data = part.repeat(4);
+
+        let compress = |job_size: Option<u32>, overlap_log: Option<u8>| {
+            let options = EncodeOptions {
+                window_log: Some(20),
+                job_size,
+                overlap_log,
+                ..EncodeOptions::default()
+            };
+            let mut output = Vec::new();
+            let mut encoder = IncrementalEncoder::start(&mut output, &options, None).unwrap();
+            // The jobs do not depend on where the chunks end
+            for chunk in data.chunks(100_000) {
+                encoder.write_chunk(chunk).unwrap();
+            }
+            encoder.finish(&[]).unwrap();
+            assert_eq!(decode(&output).0, data);
+            output.len()
+        };
+        // Keeping the whole window finds the repetitions of the part across the jobs
+        let plain = compress(None, None);
+        let whole_window = compress(Some(512 * 1024), Some(9));
+        assert!(whole_window < part.len() + 1000, "{}", whole_window);
+        assert!(whole_window < plain + 100, "{} {}", whole_window, plain);
+        // Keeping nothing stores the part again in the second job
+        let nothing = compress(Some(512 * 1024), Some(1));
+        assert!(nothing > part.len() + part.len() / 2, "{}", nothing);
+        let default = compress(Some(512 * 1024), None);
+        assert_eq!(default, compress(Some(512 * 1024), Some(0)));
+        // The overlap log changes the output: the default keeps 128 KiB of the window, which
+        // misses the start of each repetition
+        assert!(
+            whole_window < default && default < nothing,
+            "{} {} {}",
+            whole_window,
+            default,
+            nothing
+        );
+
+        assert_eq!(super::overlap_size(1 << 20, 9), (1 << 20) - 1);
+        assert_eq!(super::overlap_size(1 << 20, 6), 1 << 17);
+        assert_eq!(super::overlap_size(1 << 20, 0), 1 << 17);
+        assert_eq!(super::overlap_size(1 << 20, 1), 0);
+    }
+
+    #[cfg(feature = "hash")]
+    #[test]
+    fn checksum() {
+        let options = EncodeOptions {
+            checksum: true,
+            ..EncodeOptions::default()
+        };
+        let mut output = Vec::new();
+        let mut encoder = IncrementalEncoder::start(&mut output, &options, None).unwrap();
+        for i in 0..10u8 {
+            encoder.write_chunk(&[i; 300]).unwrap();
+        }
+        encoder.finish(b"end").unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(3003);
+        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+        assert_eq!(decoded.len(), 3003);
+        assert_eq!(
+            decoder.get_checksum_from_data(),
+            decoder.get_calculated_checksum()
+        );
+        assert!(decoder.get_checksum_from_data().is_some());
+    }
+}
@@ -1,20 +1,334 @@
-use hashbrown::HashMap;
+//! The LZ77 layer of the encoder: finds repeated data and splits the input into literals and
+//! matches, without any entropy coding.
+//!
+//! [MatchGenerator] can be used on its own, e.g. to build a custom format with a different entropy
+//! backend, or to study how data is parsed:
+//!
+//! ```
+//! use ruzstd::encoding::match_generator::{MatchGenerator, Sequence};
+//! use ruzstd::encoding::Strategy;
+//!
+//! let mut matcher = MatchGenerator::new(1 << 16);
+//! matcher.set_strategy(Strategy::Lazy);
+//! matcher.add_data(b"Hello, Hello, Hello, World!").unwrap();
+//!
+//! let mut decoded = Vec::new();
+//! while let Some(sequence) = matcher.next_sequence() {
+//!     match sequence {
+//!         Sequence::Triple { literals, offset, match_len } => {
+//!             decoded.extend_from_slice(literals);
+//!             for _ in 0..match_len {
+//!                 decoded.push(decoded[decoded.len() - offset]);
+//!             }
+//!         }
+//!         Sequence::Literals { literals } => decoded.extend_from_slice(literals),
+//!     }
+//! }
+//! assert_eq!(decoded, b"Hello, Hello, Hello, World!");
+//! ```
 
+use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+use super::{EncodeError, Strategy};
+use crate::fallible::{try_to_vec, try_vec, AllocError, TryVec};
 
 const MIN_MATCH_LEN: usize = 5;
 
-struct WindowEntry<'data> {
-    data: &'data [u8],
-    suffixes: HashMap<[u8; MIN_MATCH_LEN], usize>,
+/// After this many positions without a match, an accelerated search skips one more position
+/// after each, like the fast levels of zstd.
+const STEP_INCREASE: usize = 128;
+
+/// Marks the end of a hash chain.
+const NO_CANDIDATE: usize = usize::MAX;
+
+/// Marks an empty slot in the hash and chain tables of a [WindowEntry].
+const NO_POSITION: u32 = u32::MAX;
+
+/// The default of [SearchParams::hash_log] and [SearchParams::chain_log], so the tables of a
+/// window entry that holds a whole block have one slot per position.
+const DEFAULT_TABLE_LOG: u32 = 17;
+
+/// The hash table of a window entry has at least `2^MIN_HASH_LOG` slots, unless
+/// [SearchParams::hash_log] is smaller, so keys of short entries rarely share a slot.
+const MIN_HASH_LOG: u32 = 8;
+
+/// The number of positions in each row of a [RowTable], one bit of a `u16` mask per position.
+const ROW_SIZE: usize = 16;
+
+/// The prime zstd multiplies 5 byte keys with to hash them.
+const PRIME_5_BYTES: u64 = 889_523_592_379;
+
+/// The hash of `key` with `hash_log` bits, which has to be between 1 and 64.
+fn hash(key: &[u8; MIN_MATCH_LEN], hash_log: u32) -> usize {
+    let mut bytes = [0u8; 8];
+    bytes[..MIN_MATCH_LEN].copy_from_slice(key);
+    let value = u64::from_le_bytes(bytes) << (64 - 8 * MIN_MATCH_LEN);
+    (value.wrapping_mul(PRIME_5_BYTES) >> (64 - hash_log)) as usize
+}
+
+/// A hash table whose buckets are rows of the [ROW_SIZE] most recent positions with that hash,
+/// like the row match finder of the zstd library.
+///
+/// Next to each position an 8 bit tag with more bits of the hash is kept, so a search compares the
+/// tags of a whole row at once and only looks at the data of positions whose tag matches. The
+/// table has a fixed size and the oldest position of a row is overwritten when a new one is added.
+struct RowTable {
+    row_log: u32,
+    tags: Vec<[u8; ROW_SIZE]>,
+    positions: Vec<[usize; ROW_SIZE]>,
+    /// The slot in each row that was written last
+    heads: Vec<u8>,
+}
+
+impl RowTable {
+    /// A table with about one slot per position of `data_len` bytes, but at most `2^hash_log`.
+    fn new(data_len: usize, hash_log: u32) -> Result<Self, AllocError> {
+        let rows = (data_len / ROW_SIZE)
+            .next_power_of_two()
+            .min(1 << hash_log.saturating_sub(ROW_SIZE.trailing_zeros()));
+        Ok(Self {
+            row_log: rows.trailing_zeros(),
+            tags: try_vec([0; ROW_SIZE], rows)?,
+            positions: try_vec([NO_CANDIDATE; ROW_SIZE], rows)?,
+            heads: try_vec(0, rows)?,
+        })
+    }
+
+    /// The row and the tag of `key`.
+    fn hash(&self, key: &[u8; MIN_MATCH_LEN]) -> (usize, u8) {
+        let hash = hash(key, self.row_log + 8);
+        (hash >> 8, hash as u8)
+    }
+
+    fn insert(&mut self, key: &[u8; MIN_MATCH_LEN], idx: usize) {
+        let (row, tag) = self.hash(key);
+        let head = (usize::from(self.heads[row]) + ROW_SIZE - 1) % ROW_SIZE;
+        self.tags[row][head] = tag;
+        self.positions[row][head] = idx;
+        self.heads[row] = head as u8;
+    }
+
+    /// The positions in the row of `key` whose tag matches, the most recent first.
+    fn candidates(&self, key: &[u8; MIN_MATCH_LEN]) -> impl Iterator<Item = usize> + '_ {
+        let (row, tag) = self.hash(key);
+        let mut mask = 0u16;
+        for (slot, &slot_tag) in self.tags[row].iter().enumerate() {
+            mask |= u16::from(slot_tag == tag) << slot;
+        }
+        // Bit i of the mask is the i-th most recent slot
+        let head = usize::from(self.heads[row]);
+        let mut mask = mask.rotate_right(head as u32);
+        core::iter::from_fn(move || {
+            if mask == 0 {
+                return None;
+            }
+            let newer = mask.trailing_zeros() as usize;
+            mask &= mask - 1;
+            Some(self.positions[row][(head + newer) % ROW_SIZE])
+        })
+        .filter(|&idx| idx != NO_CANDIDATE)
+    }
+}
+
+/// A piece of the window with the tables to find earlier positions in it.
+///
+/// The tables have a fixed size, given by the length of the data and the [SearchParams] it was
+/// added with: a hash table with about two slots per position, but at most `2^hash_log` slots, and a
+/// chain table that keeps the previous position with the same hash for at most the `2^chain_log`
+/// most recent positions. Positions that share a slot replace each other, so candidates may have a
+/// different key.
+struct WindowEntry {
+    data: Vec<u8>,
     base_offset: usize,
+    hash_log: u32,
+    /// The most recent position of each hash
+    last: Vec<u32>,
+    /// The first position of each hash, only without chains
+    first: Vec<u32>,
+    /// The previous position with the same hash, for position `idx` in slot `idx % chain.len()`
+    chain: Vec<u32>,
+    /// The most recent position that was inserted, older ones whose chain slot was taken over by
+    /// a newer position end their chain
+    newest: usize,
+    /// Replaces the other tables for searches with the row match finder
+    rows: Option<RowTable>,
+}
+
+impl WindowEntry {
+    fn new(data: &[u8], search: SearchParams) -> Result<Self, AllocError> {
+        let chained = search.chained();
+        let hash_log = usize::BITS
+            .saturating_sub(data.len().leading_zeros())
+            .saturating_add(1)
+            .max(MIN_HASH_LOG)
+            .min(search.hash_log)
+            .max(1);
+        let slots = if search.rows { 0 } else { 1 << hash_log };
+        let chain_len = data.len().min(1 << search.chain_log.min(usize::BITS - 1));
+        Ok(Self {
+            data: try_to_vec(data)?,
+            base_offset: 0,
+            hash_log,
+            last: try_vec(NO_POSITION, slots)?,
+            first: if chained {
+                Vec::new()
+            } else {
+                try_vec(NO_POSITION, slots)?
+            },
+            chain: if chained {
+                try_vec(NO_POSITION, chain_len)?
+            } else {
+                Vec::new()
+            },
+            newest: 0,
+            rows: search
+                .rows
+                .then(|| RowTable::new(data.len(), search.hash_log))
+                .transpose()?,
+        })
+    }
+
+    fn chained(&self) -> bool {
+        !self.chain.is_empty()
+    }
+
+    fn insert(&mut self, key: [u8; MIN_MATCH_LEN], idx: usize) {
+        if let Some(rows) = &mut self.rows {
+            rows.insert(&key, idx);
+            return;
+        }
+        // Positions beyond 4 GiB into an entry are not found again
+        let Some(position) = u32::try_from(idx).ok().filter(|&idx| idx != NO_POSITION) else {
+            return;
+        };
+        let slot = hash(&key, self.hash_log);
+        let previous = self.last[slot];
+        if previous != NO_POSITION && previous >= position {
+            return;
+        }
+        if self.chained() {
+            if idx + self.chain.len() <= self.newest {
+                return;
+            }
+            let chain_len = self.chain.len();
+            self.chain[idx % chain_len] = previous;
+        } else if previous == NO_POSITION {
+            self.first[slot] = position;
+        }
+        self.last[slot] = position;
+        self.newest = self.newest.max(idx);
+    }
+
+    /// The positions with the hash of `key`, the most recent first. Without chains or rows only the
+    /// most recent and the first one.
+    fn candidates(&self, key: &[u8; MIN_MATCH_LEN]) -> impl Iterator<Item = usize> + '_ {
+        let slot = hash(key, self.hash_log);
+        let stored = |position: u32| Some(position as usize).filter(|_| position != NO_POSITION);
+        let first = self.first.get(slot).copied().and_then(stored);
+        let last = self.last.get(slot).copied().and_then(stored);
+        let chain = core::iter::successors(last, move |&idx| {
+            let previous = if self.chained() {
+                // The slot of `idx` belongs to a newer position by now
+                if idx + self.chain.len() <= self.newest {
+                    return None;
+                }
+                stored(self.chain[idx % self.chain.len()])
+            } else {
+                first
+            };
+            previous.filter(|&previous| previous < idx)
+        });
+        let key = *key;
+        chain.chain(self.rows.iter().flat_map(move |rows| rows.candidates(&key)))
+    }
+}
+
+/// The content of a dictionary with the tables to search it, built once and shared by every
+/// [MatchGenerator] that compresses with the dictionary.
+pub(crate) struct DictionaryMatcher {
+    entry: WindowEntry,
+}
+
+impl DictionaryMatcher {
+    pub(crate) fn new(content: &[u8], search: SearchParams) -> Result<Self, AllocError> {
+        let mut entry = WindowEntry::new(content, search)?;
+        for (idx, window) in content.windows(MIN_MATCH_LEN).enumerate() {
+            let mut key = [0u8; MIN_MATCH_LEN];
+            key.copy_from_slice(window);
+            entry.insert(key, idx);
+        }
+        Ok(Self { entry })
+    }
+}
+
+/// How hard [MatchGenerator] searches for matches, derived from a [Strategy](super::Strategy).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct SearchParams {
+    /// How many earlier positions with the same prefix are compared in each window entry to find
+    /// the longest match. With 0, the first position with the prefix is the only candidate and the
+    /// first match that is found is taken.
+    pub(crate) chain_depth: usize,
+    /// How many times a match may be given up for a longer one that starts at the next position.
+    pub(crate) lazy_depth: u8,
+    /// Find the earlier positions with a [RowTable] instead of hash chains.
+    pub(crate) rows: bool,
+    /// With more than 0, this many positions are skipped after each position without a match,
+    /// minus one, and one more for every [STEP_INCREASE] positions since the last match.
+    pub(crate) acceleration: u32,
+    /// The hash table or the [RowTable] of a window entry has at most `2^hash_log` slots.
+    pub(crate) hash_log: u32,
+    /// The chain table of a window entry keeps the previous position with the same hash for at
+    /// most the `2^chain_log` most recent positions.
+    pub(crate) chain_log: u32,
+    /// Candidates further back than this are not compared, like the ones that fell out of the
+    /// chain table of the zstd library.
+    pub(crate) max_distance: usize,
+    /// Shorter matches are not used, matches are at least [MIN_MATCH_LEN] long in any case.
+    pub(crate) min_match: usize,
+    /// The search for a position ends once a match of this length is found.
+    pub(crate) target_length: usize,
+}
+
+impl SearchParams {
+    /// Take the first match that is found.
+    pub(crate) const FAST: Self = Self {
+        chain_depth: 0,
+        lazy_depth: 0,
+        rows: false,
+        acceleration: 0,
+        hash_log: DEFAULT_TABLE_LOG,
+        chain_log: DEFAULT_TABLE_LOG,
+        max_distance: usize::MAX,
+        min_match: MIN_MATCH_LEN,
+        target_length: usize::MAX,
+    };
+
+    fn chained(&self) -> bool {
+        self.chain_depth > 0 && !self.rows
+    }
 }
 
-pub(crate) struct MatchGenerator<'data> {
+/// Finds matches for new data in the data that was added before.
+///
+/// The generator keeps its own copy of the window, so callers can hand in data piece by piece
+/// without keeping it around. Data is added with [MatchGenerator::add_data] and then split into
+/// [Sequence]s by calling [MatchGenerator::next_sequence] until it returns `None`. Matches may
+/// reach back into all data that is still in the window, which holds at most the `max_size` bytes
+/// that were passed to [MatchGenerator::new]. The oldest pieces of data are dropped as a whole
+/// to make room for new ones.
+pub struct MatchGenerator {
     max_window_size: usize,
+    search: SearchParams,
     /// Data window we are operating on to find matches
     /// The data we want to find matches for is in the last slice
-    window: Vec<WindowEntry<'data>>,
+    window: Vec<WindowEntry>,
+    /// A dictionary in front of the window and the first position in it that is still in reach.
+    /// It counts towards the window size like an entry and is dropped before the oldest one.
+    dictionary: Option<(Arc<DictionaryMatcher>, usize)>,
+    /// The `base_offset` of the dictionary, as if it was the first window entry
+    dictionary_base_offset: usize,
     window_size: usize,
     /// Index in the last slice that we already processed
     suffix_idx: usize,
@@ -22,209 +336,478 @@ pub(crate) struct MatchGenerator<'data> {
     last_idx_in_sequence: usize,
 }
 
+/// A piece of the data that was added last, see [MatchGenerator::next_sequence].
 #[derive(PartialEq, Eq, Debug)]
-pub(crate) enum Sequence<'data> {
+pub enum Sequence<'data> {
+    /// Bytes that are copied as they are, followed by a match.
     Triple {
         literals: &'data [u8],
+        /// How far back the match starts, counted from the end of `literals`. The match may
+        /// overlap with the data it produces if it is longer than the offset.
         offset: usize,
+        /// The number of bytes to copy, at least 5.
         match_len: usize,
     },
-    Literals {
-        literals: &'data [u8],
-    },
+    /// The bytes at the end of the data that are not followed by a match.
+    Literals { literals: &'data [u8] },
 }
 
-impl<'data> MatchGenerator<'data> {
-    pub(crate) fn new(max_size: usize) -> Self {
+impl MatchGenerator {
+    /// A generator whose window holds at most `max_size` bytes, searching like [Strategy::Fast].
+    pub fn new(max_size: usize) -> Self {
         Self {
             max_window_size: max_size,
+            search: SearchParams::FAST,
             window: Vec::new(),
+            dictionary: None,
+            dictionary_base_offset: 0,
             window_size: 0,
             suffix_idx: 0,
             last_idx_in_sequence: 0,
         }
     }
 
-    pub(crate) fn next_sequence(&mut self) -> Option<Sequence<'data>> {
-        let mut sequence = None;
-
-        while sequence.is_none() {
-            let last_entry = self.window.last().unwrap();
-            let data_slice = last_entry.data;
-            if self.suffix_idx >= data_slice.len() {
+    /// The next piece of the data that was added last, or `None` once all of it was returned.
+    ///
+    /// The pieces cover the data in order, so copying their literals and matches one after
+    /// another gives back the data.
+    pub fn next_sequence(&mut self) -> Option<Sequence<'_>> {
+        loop {
+            let last_entry = self.window.last()?;
+            let data_len = last_entry.data.len();
+            if self.suffix_idx >= data_len {
                 return None;
             }
-            let data_slice = &data_slice[self.suffix_idx..];
+            let data_slice = &last_entry.data[self.suffix_idx..];
 
             if data_slice.len() < MIN_MATCH_LEN {
                 let last_idx_in_sequence = self.last_idx_in_sequence;
-                self.last_idx_in_sequence = last_entry.data.len();
-                self.suffix_idx = last_entry.data.len();
+                self.last_idx_in_sequence = data_len;
+                self.suffix_idx = data_len;
                 return Some(Sequence::Literals {
-                    literals: &last_entry.data[last_idx_in_sequence..],
+                    literals: &self.window.last()?.data[last_idx_in_sequence..],
                 });
             }
 
-            let mut key = [0u8; MIN_MATCH_LEN];
-            key.copy_from_slice(&data_slice[..MIN_MATCH_LEN]);
-
-            for (match_entry_idx, match_entry) in self.window.iter().enumerate() {
-                let is_last = match_entry_idx == self.window.len() - 1;
-                if let Some(match_index) = match_entry.suffixes.get(&key).copied() {
-                    let match_slice = if is_last {
-                        &match_entry.data[match_index..self.suffix_idx]
-                    } else {
-                        &match_entry.data[match_index..]
-                    };
-                    let min_len = usize::min(match_slice.len(), data_slice.len());
-
-                    let mut match_len = 0;
-                    for idx in 0..min_len {
-                        if match_slice[idx] != data_slice[idx] {
-                            break;
+            let mut found = self.find_match(self.suffix_idx);
+            if let Some((_, mut match_len)) = found {
+                // A match that starts a little later may be longer, the skipped bytes become literals
+                for _ in 0..self.search.lazy_depth {
+                    let next = self.suffix_idx + 1;
+                    match self.find_match(next) {
+                        Some((offset, len)) if len > match_len => {
+                            self.add_suffix(self.suffix_idx);
+                            self.suffix_idx = next;
+                            found = Some((offset, len));
+                            match_len = len;
                         }
-                        match_len = idx + 1;
-                    }
-
-                    if match_len >= MIN_MATCH_LEN {
-                        let literals = &last_entry.data[self.last_idx_in_sequence..self.suffix_idx];
-                        let offset = if is_last {
-                            self.suffix_idx - match_index
-                        } else {
-                            match_entry.base_offset - match_index + self.suffix_idx
-                        };
-                        sequence = Some(Sequence::Triple {
-                            literals,
-                            offset,
-                            match_len,
-                        });
-
-                        break;
+                        _ => break,
                     }
                 }
             }
 
-            if let Some(Sequence::Triple { match_len, .. }) = sequence {
-                self.add_suffixes_till(self.suffix_idx + match_len);
+            if let Some((offset, match_len)) = found {
+                let literals_start = self.last_idx_in_sequence;
+                let literals_end = self.suffix_idx;
+                if self.accelerated() {
+                    // Like zstd, only remember the start and the end of the match
+                    self.add_suffix(self.suffix_idx);
+                    let end = self.suffix_idx + match_len - 2;
+                    if end + MIN_MATCH_LEN <= data_len {
+                        self.add_suffix(end);
+                    }
+                } else {
+                    self.add_suffixes_till(self.suffix_idx + match_len);
+                }
                 self.suffix_idx += match_len;
                 self.last_idx_in_sequence = self.suffix_idx;
-            } else {
-                let last_entry = self.window.last_mut().unwrap();
-                if !last_entry.suffixes.contains_key(&key) {
-                    last_entry.suffixes.insert(key, self.suffix_idx);
-                }
-                self.suffix_idx += 1;
+                return Some(Sequence::Triple {
+                    literals: &self.window.last()?.data[literals_start..literals_end],
+                    offset,
+                    match_len,
+                });
+            }
+
+            self.add_suffix(self.suffix_idx);
+            // The last few positions have to be visited to turn them into literals
+            self.suffix_idx = usize::min(
+                self.suffix_idx + self.step(),
+                data_len - (MIN_MATCH_LEN - 1),
+            );
+        }
+    }
+
+    /// How far to move on from a position without a match.
+    fn step(&self) -> usize {
+        match self.search.acceleration {
+            0 => 1,
+            acceleration => {
+                acceleration as usize
+                    + (self.suffix_idx - self.last_idx_in_sequence) / STEP_INCREASE
+            }
+        }
+    }
+
+    /// Whether positions are skipped to find matches faster, which also means literals should
+    /// be stored as they are.
+    pub(crate) fn accelerated(&self) -> bool {
+        self.search.acceleration > 0
+    }
+
+    /// Change how hard matches are searched for, from the next call to [MatchGenerator::next_sequence] on.
+    pub(crate) fn set_search(&mut self, search: SearchParams) {
+        self.search = search;
+    }
+
+    /// Search for matches like `strategy` does, see [Strategy]. Takes effect for the data that is
+    /// added from now on.
+    pub fn set_strategy(&mut self, strategy: Strategy) {
+        self.set_search(strategy.search_params());
+    }
+
+    /// The number of bytes in the window, that matches of new data can reference.
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    /// The most bytes the window holds, as passed to [MatchGenerator::new].
+    pub fn max_window_size(&self) -> usize {
+        self.max_window_size
+    }
+
+    /// Drop all data from the window, including a dictionary, so the data that is added next
+    /// does not reference anything in front of it. The search settings are kept.
+    pub fn reset(&mut self) {
+        let search = self.search;
+        *self = Self::new(self.max_window_size);
+        self.search = search;
+    }
+
+    /// Like [MatchGenerator::reset], but the last `keep` bytes of the window stay in it, so the
+    /// data that is added next can reference them and nothing in front of them.
+    ///
+    /// Fails like [MatchGenerator::add_data] if `keep` is not smaller than the
+    /// [max_window_size](MatchGenerator::max_window_size).
+    pub(crate) fn reset_keeping(&mut self, keep: usize) -> Result<(), EncodeError> {
+        // Kept entry by entry, so the window still drops them one at a time to make room
+        let mut kept = Vec::new();
+        let mut missing = keep;
+        for entry in self.window.iter().rev() {
+            if missing == 0 {
+                break;
+            }
+            let take = usize::min(missing, entry.data.len());
+            kept.try_push(try_to_vec(&entry.data[entry.data.len() - take..])?)?;
+            missing -= take;
+        }
+        self.reset();
+        for data in kept.iter().rev() {
+            self.add_data_no_matching(data)?;
+        }
+        Ok(())
+    }
+
+    /// Find a match for the data at `pos` in the last window entry, in the data in front of it.
+    /// Returns the offset and the length of the match.
+    ///
+    /// The window is searched from the newest data to the oldest, so the first match that is
+    /// found is also the closest one, which has the cheapest offset.
+    fn find_match(&self, pos: usize) -> Option<(usize, usize)> {
+        let last_entry = self.window.last()?;
+        let data_slice = last_entry.data.get(pos..)?;
+        if data_slice.len() < MIN_MATCH_LEN {
+            return None;
+        }
+        let mut key = [0u8; MIN_MATCH_LEN];
+        key.copy_from_slice(&data_slice[..MIN_MATCH_LEN]);
+
+        let mut found: Option<(usize, usize)> = None;
+        // Like the search log of zstd, the number of candidates is limited for the whole window.
+        // Without chains the two candidates of each entry are compared until one of them matches.
+        let mut attempts = match self.search.chain_depth {
+            0 => usize::MAX,
+            chain_depth => chain_depth,
+        };
+        // Matches in the last entry have to start in front of `pos`, but may overlap it
+        for match_index in last_entry
+            .candidates(&key)
+            .filter(|&match_index| match_index < pos)
+            .take(attempts)
+        {
+            attempts -= 1;
+            if pos - match_index > self.search.max_distance {
+                break;
+            }
+            let match_len = common_prefix_len(&last_entry.data[match_index..], data_slice);
+            self.keep_longer(&mut found, pos - match_index, match_len);
+            if self.long_enough(found) {
+                break;
+            }
+        }
+        if self.done(found) || attempts == 0 {
+            return found;
+        }
+
+        for match_entry in self.window[..self.window.len() - 1].iter().rev() {
+            self.search_entry(
+                match_entry,
+                match_entry.base_offset + pos,
+                0,
+                data_slice,
+                &mut attempts,
+                &mut found,
+            );
+            if self.done(found) || attempts == 0 {
+                return found;
             }
         }
 
-        sequence
+        if let Some((dictionary, first)) = &self.dictionary {
+            self.search_entry(
+                &dictionary.entry,
+                self.dictionary_base_offset + pos,
+                *first,
+                data_slice,
+                &mut attempts,
+                &mut found,
+            );
+        }
+        found
+    }
+
+    /// Whether the search can stop with `found`: it is long enough, or without hash chains there
+    /// is a match at all.
+    fn done(&self, found: Option<(usize, usize)>) -> bool {
+        (found.is_some() && self.search.chain_depth == 0) || self.long_enough(found)
+    }
+
+    /// Replace `found` with the match at `offset` if it is worth more, and long enough to be a
+    /// match.
+    ///
+    /// Like the lazy parser of zstd, each byte of a match is worth 4 and each bit of the offset
+    /// costs 1, so a longer match far back does not replace a slightly shorter one close by.
+    fn keep_longer(&self, found: &mut Option<(usize, usize)>, offset: usize, match_len: usize) {
+        if match_len < self.search.min_match.max(MIN_MATCH_LEN) {
+            return;
+        }
+        let gain = |offset: usize, match_len: usize| {
+            4 * match_len as i64 - i64::from(usize::BITS - offset.leading_zeros())
+        };
+        if found.is_none_or(|(found_offset, found_len)| {
+            gain(offset, match_len) > gain(found_offset, found_len)
+        }) {
+            *found = Some((offset, match_len));
+        }
+    }
+
+    /// Whether `found` reaches the target length, so the search can stop.
+    fn long_enough(&self, found: Option<(usize, usize)>) -> bool {
+        found.is_some_and(|(_, len)| len >= self.search.target_length)
+    }
+
+    /// Look for a match for `data_slice` among the candidates in `match_entry` from position
+    /// `first` on, and keep it in `found` if it is worth more. The start of `match_entry` is
+    /// `distance` bytes in front of `data_slice`. Compares at most `attempts` candidates and counts
+    /// them down.
+    fn search_entry(
+        &self,
+        match_entry: &WindowEntry,
+        distance: usize,
+        first: usize,
+        data_slice: &[u8],
+        attempts: &mut usize,
+        found: &mut Option<(usize, usize)>,
+    ) {
+        let mut key = [0u8; MIN_MATCH_LEN];
+        key.copy_from_slice(&data_slice[..MIN_MATCH_LEN]);
+        for match_index in match_entry
+            .candidates(&key)
+            .filter(|&match_index| match_index >= first)
+            .take(*attempts)
+        {
+            *attempts -= 1;
+            let offset = distance - match_index;
+            if offset > self.search.max_distance {
+                break;
+            }
+            let match_len = common_prefix_len(&match_entry.data[match_index..], data_slice);
+            self.keep_longer(found, offset, match_len);
+            if self.long_enough(*found) {
+                break;
+            }
+        }
+    }
+
+    /// Remember the position `idx` of the last window entry as a match candidate, in place of
+    /// earlier ones with the same content unless there are hash chains.
+    fn add_suffix(&mut self, idx: usize) {
+        let Some(last_entry) = self.window.last_mut() else {
+            return;
+        };
+        let mut key = [0u8; MIN_MATCH_LEN];
+        key.copy_from_slice(&last_entry.data[idx..idx + MIN_MATCH_LEN]);
+        last_entry.insert(key, idx);
     }
 
     fn add_suffixes_till(&mut self, idx: usize) {
-        let last_entry = self.window.last_mut().unwrap();
+        let Some(last_entry) = self.window.last_mut() else {
+            return;
+        };
+        if last_entry.data.len() < MIN_MATCH_LEN {
+            return;
+        }
         let last_idx = usize::min(idx, last_entry.data.len() - MIN_MATCH_LEN);
         for idx in self.suffix_idx..=last_idx {
             let mut key = [0u8; MIN_MATCH_LEN];
             key.copy_from_slice(&last_entry.data[idx..idx + MIN_MATCH_LEN]);
-            if !last_entry.suffixes.contains_key(&key) {
-                last_entry.suffixes.insert(key, idx);
-            }
+            last_entry.insert(key, idx);
         }
     }
 
-    pub(crate) fn add_data_no_matching(&mut self, data: &'data [u8]) {
-        self.add_data(data);
+    /// Add `data` to the window without splitting it into sequences, so later data can reference
+    /// it. Useful for data that is stored in some other way, or as a prefix that is known to the
+    /// decoder as well.
+    ///
+    /// Fails like [MatchGenerator::add_data].
+    pub fn add_data_no_matching(&mut self, data: &[u8]) -> Result<(), EncodeError> {
+        self.add_data(data)?;
         self.add_suffixes_till(data.len());
         self.suffix_idx = data.len();
+        Ok(())
+    }
+    /// Like [MatchGenerator::add_data_no_matching] for `data` that is one byte repeated. Every
+    /// position has the same key, so the first and the last one are enough as candidates.
+    pub(crate) fn add_run_no_matching(&mut self, data: &[u8]) -> Result<(), EncodeError> {
+        self.add_data(data)?;
+        if data.len() >= MIN_MATCH_LEN {
+            self.add_suffix(0);
+            self.add_suffix(data.len() - MIN_MATCH_LEN);
+        }
+        self.suffix_idx = data.len();
+        Ok(())
     }
-    pub(crate) fn add_data(&mut self, data: &'data [u8]) {
-        assert!(
-            self.window.is_empty() || self.suffix_idx == self.window.last().unwrap().data.len()
-        );
-        self.reserve(data.len());
+
+    /// Add `data` to the window, the following calls to [MatchGenerator::next_sequence] split it
+    /// into sequences.
+    ///
+    /// Sequences of the data that was added before and were not returned yet are dropped. Fails
+    /// with [EncodeError::InputTooLarge] if `data` is not smaller than the
+    /// [max_window_size](MatchGenerator::max_window_size).
+    pub fn add_data(&mut self, data: &[u8]) -> Result<(), EncodeError> {
+        self.reserve(data.len())?;
 
         if let Some(last_len) = self.window.last().map(|last| last.data.len()) {
             for entry in self.window.iter_mut() {
                 entry.base_offset += last_len;
             }
+            self.dictionary_base_offset += last_len;
         }
 
-        self.window.push(WindowEntry {
-            data,
-            suffixes: HashMap::with_capacity(data.len()),
-            base_offset: 0,
-        });
+        let entry = WindowEntry::new(data, self.search)?;
+        self.window.try_push(entry)?;
         self.window_size += data.len();
         self.suffix_idx = 0;
         self.last_idx_in_sequence = 0;
+        Ok(())
+    }
+
+    /// Let the data that is added from now on reference `dictionary` from position `first` on,
+    /// as if that part of it had been added with [MatchGenerator::add_data_no_matching].
+    /// Has to be called before any data is added.
+    #[allow(clippy::disallowed_macros)]
+    pub(crate) fn set_dictionary(
+        &mut self,
+        dictionary: Arc<DictionaryMatcher>,
+        first: usize,
+    ) -> Result<(), EncodeError> {
+        debug_assert!(self.window.is_empty());
+        let len = dictionary.entry.data.len() - first;
+        self.reserve(len)?;
+        self.window_size += len;
+        self.dictionary_base_offset = dictionary.entry.data.len();
+        self.dictionary = Some((dictionary, first));
+        Ok(())
     }
 
-    fn reserve(&mut self, amount: usize) {
-        assert!(self.max_window_size > amount);
-        let mut removed_slices = 0;
+    /// Make room for `amount` more bytes, which have to be fewer than the window holds.
+    fn reserve(&mut self, amount: usize) -> Result<(), EncodeError> {
+        if amount > 0 && amount >= self.max_window_size {
+            return Err(EncodeError::InputTooLarge {
+                size: amount,
+                max: self.max_window_size.saturating_sub(1),
+            });
+        }
         while self.window_size + amount > self.max_window_size {
+            if let Some((dictionary, first)) = self.dictionary.take() {
+                self.window_size -= dictionary.entry.data.len() - first;
+                continue;
+            }
             let removed = self.window.remove(0);
             self.window_size -= removed.data.len();
-            removed_slices += 1;
-        }
-        if removed_slices == 0 {
-            return;
         }
+        Ok(())
     }
 }
 
+/// The number of bytes at the start of `data` that `earlier` starts with as well.
+fn common_prefix_len(earlier: &[u8], data: &[u8]) -> usize {
+    earlier
+        .iter()
+        .zip(data)
+        .take_while(|(earlier, data)| earlier == data)
+        .count()
+}
+
 #[test]
 fn matches() {
     let mut matcher = MatchGenerator::new(1000);
-    matcher.add_data(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    matcher.add_data(&[0, 0, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
 
+    // Matches may overlap the data they produce
     assert_eq!(
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
-            literals: &[0, 0, 0, 0, 0],
-            offset: 5,
-            match_len: 5
+            literals: &[0],
+            offset: 1,
+            match_len: 9
         }
     );
     assert!(matcher.next_sequence().is_none());
 
-    matcher.add_data(&[
-        1, 2, 3, 4, 5, 6, 1, 2, 3, 4, 5, 6, 1, 2, 3, 4, 5, 6, 0, 0, 0, 0, 0,
-    ]);
+    matcher
+        .add_data(&[
+            1, 2, 3, 4, 5, 6, 1, 2, 3, 4, 5, 6, 1, 2, 3, 4, 5, 6, 0, 0, 0, 0, 0,
+        ])
+        .unwrap();
 
     assert_eq!(
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
             literals: &[1, 2, 3, 4, 5, 6],
             offset: 6,
-            match_len: 6
-        }
-    );
-    assert_eq!(
-        matcher.next_sequence().unwrap(),
-        Sequence::Triple {
-            literals: &[],
-            offset: 12,
-            match_len: 6
+            match_len: 12
         }
     );
     assert_eq!(
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
             literals: &[],
-            offset: 28,
+            offset: 23,
             match_len: 5
         }
     );
     assert!(matcher.next_sequence().is_none());
 
-    matcher.add_data(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0, 0, 0, 0, 0]);
+    // The closest occurrence is used, not the first one
+    matcher
+        .add_data(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0, 0, 0, 0, 0])
+        .unwrap();
 
     assert_eq!(
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
             literals: &[],
-            offset: 23,
+            offset: 11,
             match_len: 6
         }
     );
@@ -232,24 +815,24 @@ fn matches() {
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
             literals: &[7, 8, 9, 10, 11],
-            offset: 44,
+            offset: 16,
             match_len: 5
         }
     );
     assert!(matcher.next_sequence().is_none());
 
-    matcher.add_data(&[0, 0, 0, 0, 0]);
+    matcher.add_data(&[0, 0, 0, 0, 0]).unwrap();
     assert_eq!(
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
             literals: &[],
-            offset: 49,
+            offset: 5,
             match_len: 5
         }
     );
     assert!(matcher.next_sequence().is_none());
 
-    matcher.add_data(&[7, 8, 9, 10, 11]);
+    matcher.add_data(&[7, 8, 9, 10, 11]).unwrap();
     assert_eq!(
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
@@ -260,10 +843,10 @@ fn matches() {
     );
     assert!(matcher.next_sequence().is_none());
 
-    matcher.add_data_no_matching(&[1, 3, 5, 7, 9]);
+    matcher.add_data_no_matching(&[1, 3, 5, 7, 9]).unwrap();
     assert!(matcher.next_sequence().is_none());
 
-    matcher.add_data(&[1, 3, 5, 7, 9]);
+    matcher.add_data(&[1, 3, 5, 7, 9]).unwrap();
     assert_eq!(
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
@@ -273,7 +856,9 @@ fn matches() {
         }
     );
     assert!(matcher.next_sequence().is_none());
-    matcher.add_data(&[0, 0, 11, 13, 15, 17, 19, 11, 13, 15, 17, 19]);
+    matcher
+        .add_data(&[0, 0, 11, 13, 15, 17, 19, 11, 13, 15, 17, 19])
+        .unwrap();
     assert_eq!(
         matcher.next_sequence().unwrap(),
         Sequence::Triple {
@@ -284,3 +869,169 @@ fn matches() {
     );
     assert!(matcher.next_sequence().is_none());
 }
+
+#[test]
+fn row_table_keeps_the_most_recent_positions() {
+    let mut rows = RowTable::new(1000, SearchParams::FAST.hash_log).unwrap();
+    let key = *b"abcde";
+    for idx in 0..20 {
+        rows.insert(&key, idx);
+    }
+    rows.insert(b"fghij", 20);
+    let candidates: Vec<usize> = rows.candidates(&key).collect();
+    let expected: Vec<usize> = (4..20).rev().collect();
+    // The other key may have replaced the oldest position if it landed in the same row
+    assert!(candidates == expected || candidates == expected[..ROW_SIZE - 1]);
+}
+
+#[test]
+fn window_management() {
+    let mut matcher = MatchGenerator::new(100);
+    assert!(matcher.next_sequence().is_none());
+    matcher.set_strategy(Strategy::Lazy2);
+
+    matcher.add_data(b"0123456789").unwrap();
+    assert!(matches!(
+        matcher.next_sequence(),
+        Some(Sequence::Literals {
+            literals: b"0123456789"
+        })
+    ));
+    assert!(matcher.next_sequence().is_none());
+    matcher.add_data_no_matching(&[b'-'; 80]).unwrap();
+    assert_eq!(matcher.window_size(), 90);
+
+    // The first piece is still in reach
+    matcher.add_data(b"0123456789").unwrap();
+    assert_eq!(
+        matcher.next_sequence(),
+        Some(Sequence::Triple {
+            literals: b"",
+            offset: 90,
+            match_len: 10
+        })
+    );
+    assert!(matcher.next_sequence().is_none());
+    assert_eq!(matcher.window_size(), 100);
+
+    // Now it is dropped to make room, only the copy is left
+    matcher.add_data(b"01234").unwrap();
+    assert_eq!(matcher.window_size(), 95);
+    assert_eq!(
+        matcher.next_sequence(),
+        Some(Sequence::Triple {
+            literals: b"",
+            offset: 10,
+            match_len: 5
+        })
+    );
+    assert_eq!(matcher.max_window_size(), 100);
+    assert!(matcher.next_sequence().is_none());
+
+    // Data has to leave room for at least one byte of history
+    assert!(matches!(
+        matcher.add_data(&[0; 100]),
+        Err(EncodeError::InputTooLarge { size: 100, max: 99 })
+    ));
+    assert_eq!(matcher.window_size(), 95);
+
+    // Nothing is in reach after a reset
+    matcher.reset();
+    assert_eq!(matcher.window_size(), 0);
+    matcher.add_data(b"0123456789").unwrap();
+    assert!(matches!(
+        matcher.next_sequence(),
+        Some(Sequence::Literals {
+            literals: b"0123456789"
+        })
+    ));
+}
+
+#[test]
+fn search_limits() {
+    let mut data = b"ABCDEFGHIJ".to_vec();
+    data.extend(100..140u8);
+    data.extend_from_slice(b"ABCDE!");
+    data.extend(150..160u8);
+    data.extend_from_slice(b"ABCDEFGHIJ");
+    let matches = |search: SearchParams| {
+        let mut matcher = MatchGenerator::new(1000);
+        matcher.set_search(search);
+        matcher.add_data(&data).unwrap();
+        let mut matches = Vec::new();
+        while let Some(sequence) = matcher.next_sequence() {
+            if let Sequence::Triple {
+                offset, match_len, ..
+            } = sequence
+            {
+                matches.push((offset, match_len));
+            }
+        }
+        matches
+    };
+
+    let greedy = Strategy::Greedy.search_params();
+    assert_eq!(matches(greedy), [(50, 5), (66, 10)]);
+    // The search stops at the first match that is long enough, the more recent one, and the
+    // rest of the data is found in the older one
+    let target_length = SearchParams {
+        target_length: 5,
+        ..greedy
+    };
+    assert_eq!(matches(target_length), [(50, 5), (16, 5), (66, 5)]);
+    // The longer match is too far back
+    let max_distance = SearchParams {
+        max_distance: 20,
+        ..greedy
+    };
+    assert_eq!(matches(max_distance), [(16, 5)]);
+    // Only the longer match is long enough
+    let min_match = SearchParams {
+        min_match: 7,
+        ..greedy
+    };
+    assert_eq!(matches(min_match), [(66, 10)]);
+}
+
+#[test]
+fn tables_are_bounded_by_the_logs() {
+    let data: Vec<u8> = (0..5000u32).map(|i| (i % 251) as u8).collect();
+    let search = SearchParams {
+        hash_log: 9,
+        chain_log: 6,
+        ..Strategy::Lazy.search_params()
+    };
+    let entry = WindowEntry::new(&data, search).unwrap();
+    assert_eq!(entry.last.len(), 1 << 9);
+    assert_eq!(entry.chain.len(), 1 << 6);
+    assert!(entry.first.is_empty());
+
+    // Short entries get small tables, about two slots per position
+    let entry = WindowEntry::new(&data[..1000], SearchParams::FAST).unwrap();
+    assert_eq!(entry.last.len(), 2048);
+    assert_eq!(entry.first.len(), 2048);
+    assert!(entry.chain.is_empty());
+
+    // Positions whose chain slot was reused end the chain
+    let mut matcher = MatchGenerator::new(10_000);
+    matcher.set_search(search);
+    matcher.add_data(&data).unwrap();
+    let mut decoded = Vec::new();
+    while let Some(sequence) = matcher.next_sequence() {
+        match sequence {
+            Sequence::Triple {
+                literals,
+                offset,
+                match_len,
+            } => {
+                assert_eq!(offset, 251);
+                decoded.extend_from_slice(literals);
+                for _ in 0..match_len {
+                    decoded.push(decoded[decoded.len() - offset]);
+                }
+            }
+            Sequence::Literals { literals } => decoded.extend_from_slice(literals),
+        }
+    }
+    assert_eq!(decoded, data);
+}
@@ -1,10 +1,51 @@
 //! Modules used for compressing/encoding data into the Zstd format.
-// TODO: put behind a feature gate
-pub(crate) mod bit_writer;
-pub(crate) mod block_header;
+//!
+//! Everything but the [bit_writer] needs the `encoder` feature.
+#[cfg(all(feature = "encoder", feature = "std"))]
+mod adapt;
+#[cfg(all(feature = "encoder", feature = "std"))]
+pub use adapt::*;
+#[cfg(feature = "encoder")]
+mod auto_level;
+pub mod bit_writer;
+#[cfg(feature = "encoder")]
+mod block_compressor;
+#[cfg(feature = "encoder")]
+pub use block_compressor::*;
+#[cfg(feature = "encoder")]
 pub(crate) mod blocks;
+#[cfg(feature = "encoder")]
+mod chunker;
+#[cfg(feature = "encoder")]
+pub use chunker::*;
+#[cfg(feature = "encoder")]
+mod encode_options;
+#[cfg(feature = "encoder")]
+pub use encode_options::*;
+#[cfg(feature = "encoder")]
+mod encoder_dictionary;
+#[cfg(feature = "encoder")]
+pub use encoder_dictionary::*;
+#[cfg(feature = "encoder")]
 mod frame_encoder;
+#[cfg(feature = "encoder")]
 pub use frame_encoder::*;
 pub(crate) mod frame_header;
-pub(crate) mod match_generator;
+#[cfg(feature = "encoder")]
+pub use frame_header::FrameHeader;
+#[cfg(feature = "encoder")]
+mod incremental_encoder;
+#[cfg(feature = "encoder")]
+pub use incremental_encoder::*;
+#[cfg(feature = "encoder")]
+pub mod match_generator;
+#[cfg(feature = "encoder")]
+mod streaming_encoder;
+#[cfg(feature = "encoder")]
+pub use streaming_encoder::*;
+#[cfg(feature = "encoder")]
+mod strategy;
+#[cfg(feature = "encoder")]
+pub use strategy::*;
+#[cfg(feature = "encoder")]
 pub(crate) mod util;
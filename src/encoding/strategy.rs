@@ -0,0 +1,68 @@
+//! How the encoder searches for matches, independent of the compression level.
+
+use super::match_generator::SearchParams;
+
+/// The match finding strategies of the zstd library, see [EncodeOptions::strategy](super::EncodeOptions::strategy).
+///
+/// Later strategies search harder, which makes compression slower and the output smaller.
+/// All of them are implemented with the hash chains of this crate's match finder: they differ in
+/// how many earlier positions are compared for each match, and in whether a match is deferred if
+/// the next position has a longer one. [Strategy::RowHash] finds the earlier positions with the
+/// row match finder instead.
+///
+/// There are no binary trees or optimal parsing yet. [Strategy::BtLazy2], [Strategy::BtOpt] and
+/// [Strategy::BtUltra] only exist so levels and settings of the zstd library map onto this enum:
+/// for now they are aliases of [Strategy::Lazy2] that compare more earlier positions, and do not
+/// compress like the strategies of the same name in the zstd library.
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Strategy {
+    /// Take the first match that is found. Used by [CompressionLevel::Fastest](super::CompressionLevel::Fastest).
+    Fast,
+    /// Take the longest match among a few earlier positions.
+    DFast,
+    /// Take the longest match among more earlier positions.
+    Greedy,
+    /// Like [Strategy::Greedy] with a deeper search, and a match is deferred if the next position
+    /// has a longer one.
+    Lazy,
+    /// Like [Strategy::Lazy] with a deeper search, and a match may be deferred twice.
+    Lazy2,
+    /// For now an alias of [Strategy::Lazy2] that compares twice as many earlier positions,
+    /// without a binary tree.
+    BtLazy2,
+    /// For now an alias of [Strategy::Lazy2] that compares four times as many earlier positions,
+    /// without a binary tree or optimal parsing.
+    BtOpt,
+    /// For now an alias of [Strategy::Lazy2] that compares eight times as many earlier positions,
+    /// without a binary tree or optimal parsing.
+    BtUltra,
+    /// Like [Strategy::Lazy], but the earlier positions are looked up in rows of a hash table that
+    /// keep the 16 most recent positions for each hash, like the row match finder of the zstd
+    /// library. Each row is searched by comparing small tags of all its positions at once, so the
+    /// search takes about the same time for every position and the table has a fixed size. Usually
+    /// faster than the hash chains on large inputs, with a similar ratio.
+    RowHash,
+}
+
+impl Strategy {
+    pub(crate) fn search_params(self) -> SearchParams {
+        let (chain_depth, lazy_depth, rows) = match self {
+            Strategy::Fast => (0, 0, false),
+            Strategy::DFast => (4, 0, false),
+            Strategy::Greedy => (16, 0, false),
+            Strategy::Lazy => (32, 1, false),
+            Strategy::Lazy2 => (64, 2, false),
+            Strategy::BtLazy2 => (128, 2, false),
+            Strategy::BtOpt => (256, 2, false),
+            Strategy::BtUltra => (512, 2, false),
+            Strategy::RowHash => (16, 1, true),
+        };
+        SearchParams {
+            chain_depth,
+            lazy_depth,
+            rows,
+            ..SearchParams::FAST
+        }
+    }
+}
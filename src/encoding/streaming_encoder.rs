@@ -0,0 +1,333 @@
+//! An encoder that compresses everything that is written into it.
+
+use alloc::vec::Vec;
+
+use super::{CompressionLevel, EncodeError, EncodeOptions, IncrementalEncoder};
+use crate::fallible::{try_with_capacity, TryVec};
+use crate::io::{Error, Write};
+
+/// High level Zstandard encoder that compresses everything written into it into a single frame.
+///
+/// This encoder implements `io::Write`. Written data is collected until it fills a block, then the
+/// block is compressed and written to the underlying writer, so the encoder never holds more than
+/// one block of uncompressed input (plus the window that later blocks can reference), no matter
+/// how much is written at once.
+///
+/// [flush](Write::flush) ends the current block early and flushes the underlying writer.
+/// The frame is only complete after [StreamingEncoder::finish] was called, dropping the encoder
+/// leaves a truncated frame behind.
+///
+/// Frames are not limited in size: the content is counted in 64 bits, also on 32 bit targets, and
+/// a content size above 4 GiB is declared in the 8 byte form of the header field.
+///
+/// ```
+/// use ruzstd::encoding::{CompressionLevel, StreamingEncoder};
+/// # #[cfg(feature = "std")]
+/// use std::io::Write;
+/// # #[cfg(not(feature = "std"))]
+/// use ruzstd::io::Write;
+///
+/// let mut encoder = StreamingEncoder::new(Vec::new(), CompressionLevel::Fastest).unwrap();
+/// for _ in 0..100 {
+///     encoder.write_all(b"some data, ").unwrap();
+/// }
+/// let compressed = encoder.finish().unwrap();
+/// ```
+pub struct StreamingEncoder<W: Write> {
+    encoder: IncrementalEncoder<W>,
+    /// Input that does not fill a block yet
+    pending: Vec<u8>,
+}
+
+impl<W: Write> StreamingEncoder<W> {
+    /// Start a frame with the given level and the [default options](EncodeOptions::default).
+    ///
+    /// Returns [EncodeError::UnsupportedLevel] for compression levels that are not implemented yet,
+    /// and for levels 20 to 22 without [EncodeOptions::ultra].
+    pub fn new(writer: W, level: CompressionLevel) -> Result<Self, EncodeError> {
+        let options = EncodeOptions {
+            level,
+            ..EncodeOptions::default()
+        };
+        Self::with_options(writer, &options, None)
+    }
+
+    /// Start a frame with the given options, see [IncrementalEncoder::start].
+    ///
+    /// The frame declares `content_size` in its header if it is given.
+    pub fn with_options(
+        writer: W,
+        options: &EncodeOptions,
+        content_size: Option<u64>,
+    ) -> Result<Self, EncodeError> {
+        let encoder = IncrementalEncoder::start(writer, options, content_size)?;
+        Ok(Self {
+            pending: try_with_capacity(encoder.max_block_size())?,
+            encoder,
+        })
+    }
+
+    /// Compress the remaining input, end the frame and return the underlying writer.
+    pub fn finish(self) -> Result<W, EncodeError> {
+        self.encoder.finish(&self.pending)
+    }
+
+    /// Gets a reference to the underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.encoder.get_ref()
+    }
+
+    /// Gets a mutable reference to the underlying writer.
+    ///
+    /// It is inadvisable to directly write to the underlying writer.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.encoder.get_mut()
+    }
+
+    /// The level and strategy the next block is compressed with if the level adapts to the
+    /// output, see [EncodeOptions::adapt].
+    #[cfg(feature = "std")]
+    pub fn adapted_level(&self) -> Option<(CompressionLevel, super::Strategy)> {
+        self.encoder.adapted_level()
+    }
+
+    /// Destructures this object into the inner writer without ending the frame.
+    ///
+    /// Input that was not flushed yet is lost and the output is not a complete frame,
+    /// use [StreamingEncoder::finish] for that.
+    pub fn into_inner(self) -> W {
+        self.encoder.into_inner()
+    }
+}
+
+impl<W: Write> Write for StreamingEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let block_size = self.encoder.max_block_size();
+        // Full blocks can be compressed without copying them first
+        if self.pending.is_empty() && buf.len() >= block_size {
+            let full_blocks = buf.len() - buf.len() % block_size;
+            self.encoder
+                .write_chunk(&buf[..full_blocks])
+                .map_err(to_io_error)?;
+            return Ok(full_blocks);
+        }
+
+        let taken = usize::min(buf.len(), block_size - self.pending.len());
+        self.pending
+            .try_extend_from_slice(&buf[..taken])
+            .map_err(|err| to_io_error(EncodeError::from(err)))?;
+        if self.pending.len() == block_size {
+            self.encoder
+                .write_chunk(&self.pending)
+                .map_err(to_io_error)?;
+            self.pending.clear();
+        }
+        Ok(taken)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if !self.pending.is_empty() {
+            self.encoder
+                .write_chunk(&self.pending)
+                .map_err(to_io_error)?;
+            self.pending.clear();
+        }
+        self.encoder.flush_output()
+    }
+}
+
+pub(crate) fn to_io_error(e: EncodeError) -> Error {
+    match e {
+        // Errors of the underlying writer are passed on unchanged
+        EncodeError::FailedToWriteOutput(e) => e,
+        #[cfg(feature = "std")]
+        e => Error::other(e),
+        #[cfg(not(feature = "std"))]
+        e => Error::new(crate::io::ErrorKind::Other, alloc::boxed::Box::new(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StreamingEncoder;
+    use crate::encoding::{CompressionLevel, EncodeOptions};
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use crate::io::Write;
+    use crate::test_utils::{lcg_bytes, text_like};
+    use alloc::vec::Vec;
+
+    /// Counts the bytes written through it, and the largest single write
+    struct Counting {
+        data: Vec<u8>,
+        largest_write: usize,
+    }
+
+    impl Write for Counting {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, crate::io::Error> {
+            self.largest_write = usize::max(self.largest_write, buf.len());
+            self.data.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), crate::io::Error> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn blocks_are_emitted_while_writing() {
+        let input = text_like(605_000, 1);
+        let options = EncodeOptions {
+            block_size: Some(10_000),
+            ..EncodeOptions::default()
+        };
+        let writer = Counting {
+            data: Vec::new(),
+            largest_write: 0,
+        };
+        let mut encoder = StreamingEncoder::with_options(writer, &options, None).unwrap();
+        // uneven writes that straddle block boundaries
+        for chunk in input.chunks(7777) {
+            encoder.write_all(chunk).unwrap();
+        }
+        encoder.flush().unwrap();
+        encoder.write_all(&input[..100]).unwrap();
+        let written = encoder.finish().unwrap();
+        assert!(written.largest_write <= 10_000 + 3);
+
+        let mut decoder = FrameDecoder::new();
+        let mut source = written.data.as_slice();
+        decoder.reset(&mut source).unwrap();
+        decoder
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .unwrap();
+        // 60 full blocks, the one ended by the flush, the rest
+        assert_eq!(decoder.blocks_decoded(), 62);
+        let mut expected = input.clone();
+        expected.extend_from_slice(&input[..100]);
+        assert_eq!(decoder.collect().unwrap(), expected);
+    }
+
+    #[test]
+    fn large_writes() {
+        let input: Vec<u8> = (0..1_000_000u32).map(|i| (i % 1009) as u8).collect();
+        let mut encoder = StreamingEncoder::new(Vec::new(), CompressionLevel::Fastest).unwrap();
+        encoder.write_all(&input).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert!(compressed.len() < input.len() / 100);
+
+        let mut decoded = Vec::with_capacity(input.len());
+        FrameDecoder::new()
+            .decode_all_to_vec(&compressed, &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, input);
+    }
+
+    #[test]
+    fn writer_accessors() {
+        let mut encoder = StreamingEncoder::new(Vec::new(), CompressionLevel::Fastest).unwrap();
+        let header_len = encoder.get_ref().len();
+        encoder.write_all(b"pending").unwrap();
+        assert_eq!(encoder.get_ref().len(), header_len);
+        encoder.flush().unwrap();
+        assert!(encoder.get_ref().len() > header_len);
+
+        encoder.get_mut().clear();
+        assert!(encoder.into_inner().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn adapts_to_the_output() {
+        use crate::encoding::{Adapt, Strategy};
+        use core::num::NonZeroU32;
+        use std::time::Duration;
+
+        /// Takes its time for every write
+        struct Slow(Vec<u8>);
+
+        impl Write for Slow {
+            fn write(&mut self, buf: &[u8]) -> Result<usize, crate::io::Error> {
+                std::thread::sleep(Duration::from_millis(20));
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> Result<(), crate::io::Error> {
+                Ok(())
+            }
+        }
+
+        let input: Vec<u8> = lcg_bytes(20 * 1024, 1)
+            .iter()
+            .map(|x| b"adapt to the output "[usize::from(*x) % 20])
+            .collect();
+        let options = EncodeOptions {
+            block_size: Some(1024),
+            adapt: Some(Adapt::default()),
+            ..EncodeOptions::default()
+        };
+
+        // A slow writer leaves time to compress harder
+        let mut encoder = StreamingEncoder::with_options(Slow(Vec::new()), &options, None).unwrap();
+        assert_eq!(
+            encoder.adapted_level(),
+            Some((CompressionLevel::Fastest, Strategy::Fast))
+        );
+        encoder.write_all(&input).unwrap();
+        assert_eq!(
+            encoder.adapted_level(),
+            Some((CompressionLevel::Fastest, Strategy::Lazy2))
+        );
+        let compressed = encoder.finish().unwrap().0;
+        assert_eq!(zstd::decode_all(compressed.as_slice()).unwrap(), input);
+
+        // A fast one is held up by compressing, so the encoder speeds up
+        let options = EncodeOptions {
+            strategy: Some(Strategy::Lazy2),
+            ..options
+        };
+        let mut encoder = StreamingEncoder::with_options(Vec::new(), &options, None).unwrap();
+        encoder.write_all(&input).unwrap();
+        let fastest = CompressionLevel::Negative(NonZeroU32::new(8).unwrap());
+        assert_eq!(encoder.adapted_level(), Some((fastest, Strategy::Fast)));
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(zstd::decode_all(compressed.as_slice()).unwrap(), input);
+
+        let encoder = StreamingEncoder::new(Vec::new(), CompressionLevel::Fastest).unwrap();
+        assert_eq!(encoder.adapted_level(), None);
+    }
+
+    #[test]
+    #[ignore = "compresses and decompresses 5 GiB, run it with --ignored"]
+    fn larger_than_4_gib() {
+        use crate::frame::read_frame_header;
+        use crate::io::Read;
+        use crate::streaming_decoder::StreamingDecoder;
+
+        const SIZE: u64 = 5 << 30;
+        const CHUNK: usize = 1 << 20;
+        let options = EncodeOptions {
+            #[cfg(feature = "hash")]
+            checksum: true,
+            ..EncodeOptions::default()
+        };
+        let mut encoder = StreamingEncoder::with_options(Vec::new(), &options, Some(SIZE)).unwrap();
+        // Every chunk starts with its index, so chunks past 4 GiB differ from the ones before
+        let mut chunk = alloc::vec![0; CHUNK];
+        for index in 0..SIZE / CHUNK as u64 {
+            chunk[..8].copy_from_slice(&index.to_le_bytes());
+            encoder.write_all(&chunk).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        let (frame, _) = read_frame_header(compressed.as_slice()).unwrap();
+        assert_eq!(frame.header.frame_content_size(), SIZE);
+
+        let mut decoder = StreamingDecoder::new(compressed.as_slice()).unwrap();
+        for index in 0..SIZE / CHUNK as u64 {
+            decoder.read_exact(&mut chunk).unwrap();
+            assert_eq!(chunk[..8], index.to_le_bytes());
+            assert!(chunk[8..].iter().all(|x| *x == 0));
+        }
+        assert_eq!(decoder.read(&mut chunk).unwrap(), 0);
+    }
+}
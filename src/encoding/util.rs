@@ -1,4 +1,4 @@
-use alloc::vec::Vec;
+use super::EncodeError;
 
 /// Returns the minimum number of bytes needed to represent this value, as
 /// either 1, 2, 4, or 8 bytes. A value of 0 will still return one byte.
@@ -21,18 +21,52 @@ pub fn find_min_size(val: u64) -> usize {
 }
 
 /// Returns the same value, but represented using the smallest number of bytes needed.
-/// Returned vector will be 1, 2, 4, or 8 bytes in length. Zero is represented as 1 byte.
+/// Only the first 1, 2, 4, or 8 of the returned bytes are used, the second value says how many.
+/// Zero is represented as 1 byte.
 ///
 /// Operates in **little-endian**.
-pub fn minify_val(val: u64) -> Vec<u8> {
-    let new_size = find_min_size(val);
-    val.to_le_bytes()[0..new_size].to_vec()
+pub fn minify_val(val: u64) -> ([u8; 8], usize) {
+    (val.to_le_bytes(), find_min_size(val))
+}
+
+/// The range of previous data a sequence in the block that is currently being encoded may reference.
+///
+/// A decoder only keeps the last `window_size` bytes of the frame (and the dictionary content,
+/// as long as the frame is smaller than the window) around, so frames with sequences reaching
+/// further back than that are rejected by libzstd.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct WindowBounds {
+    /// The window size declared in the frame header.
+    pub window_size: u64,
+    /// The number of bytes preceding the current block, including the dictionary content.
+    pub history: u64,
+}
+
+impl WindowBounds {
+    /// Check a sequence that matches `offset` bytes back from position `block_pos` in the current block.
+    ///
+    /// The check is only done in debug builds, and in release builds when the
+    /// `encoder-validation` feature is enabled. A sequence outside of the window is a bug in
+    /// the match generator, so it is reported as [EncodeError::Internal].
+    #[inline(always)]
+    pub fn check_sequence(&self, offset: usize, block_pos: usize) -> Result<(), EncodeError> {
+        if cfg!(any(debug_assertions, feature = "encoder-validation")) {
+            let available = u64::min(self.window_size, self.history + block_pos as u64);
+            if offset == 0 || offset as u64 > available {
+                return Err(EncodeError::Internal {
+                    message: "a sequence references data outside of the window",
+                });
+            }
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::find_min_size;
     use super::minify_val;
+    use super::WindowBounds;
     use alloc::vec;
 
     #[test]
@@ -48,6 +82,10 @@ mod tests {
 
     #[test]
     fn bytes_minified() {
+        let minify_val = |val| {
+            let (bytes, len) = minify_val(val);
+            bytes[..len].to_vec()
+        };
         assert_eq!(minify_val(0), vec![0]);
         assert_eq!(minify_val(0xff), vec![0xff]);
         assert_eq!(minify_val(0xff_ff), vec![0xff, 0xff]);
@@ -57,4 +95,35 @@ mod tests {
             vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
         );
     }
+
+    #[test]
+    fn window_bounds() {
+        let bounds = WindowBounds {
+            window_size: 100,
+            history: 10,
+        };
+        bounds.check_sequence(10, 0).unwrap();
+        bounds.check_sequence(15, 5).unwrap();
+        bounds.check_sequence(100, 500).unwrap();
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "encoder-validation"))]
+    fn window_bounds_history_exceeded() {
+        let bounds = WindowBounds {
+            window_size: 100,
+            history: 10,
+        };
+        assert!(bounds.check_sequence(16, 5).is_err());
+    }
+
+    #[test]
+    #[cfg(any(debug_assertions, feature = "encoder-validation"))]
+    fn window_bounds_window_exceeded() {
+        let bounds = WindowBounds {
+            window_size: 100,
+            history: 1000,
+        };
+        assert!(bounds.check_sequence(101, 0).is_err());
+    }
 }
@@ -0,0 +1,460 @@
+//! A single error type that covers all operations of this crate.
+//!
+//! Every module reports its own, detailed error type. [Error] wraps all of them (every error type
+//! converts into it with `?`), and [Error::category] sorts them into a few broad categories, so
+//! applications can decide how to react without matching on each of the detailed types.
+
+use crate::decoding::block_decoder::{
+    BlockHeaderReadError, DecodeBlockContentError, DecompressBlockError,
+};
+use crate::decoding::block_decompressor::DecodeBlockError;
+use crate::decoding::decodebuffer::DecodeBufferError;
+use crate::decoding::dictionary::DictionaryDecodeError;
+use crate::decoding::literals_section_decoder::DecompressLiteralsError;
+use crate::decoding::sequence_execution::ExecuteSequencesError;
+use crate::decoding::sequence_section_decoder::DecodeSequenceError;
+#[cfg(feature = "encoder")]
+use crate::encoding::{ConfigError, EncodeError, ParseLevelError};
+use crate::frame::{InspectFrameError, ReadFrameHeaderError};
+use crate::frame_decoder::FrameDecoderError;
+#[cfg(feature = "encoder")]
+use crate::frame_utils::RecompressError;
+use crate::frame_utils::{ConcatError, PatchHeaderError, SplitError};
+use crate::fse::FSETableError;
+use crate::huff0::HuffmanTableError;
+use crate::io;
+use crate::seekable::SeekableError;
+#[cfg(feature = "self-test")]
+use crate::self_test::SelfTestError;
+use crate::skippable_frame::SkippableFrameError;
+
+/// The broad category of an [Error].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCategory {
+    /// Reading the input or writing the output failed.
+    Io,
+    /// The input is not valid zstd data, or it ends too early.
+    Corruption,
+    /// The data or the configuration needs a feature that is not implemented.
+    Unsupported,
+    /// A limit was exceeded, e.g. the window size a decoder accepts or the size of an output buffer.
+    Limit,
+    /// The API was used incorrectly, e.g. with invalid settings or without a dictionary that is needed.
+    Usage,
+    /// An internal invariant was violated. This is a bug in this library.
+    Internal,
+}
+
+/// Any error this crate reports, see the [module documentation](self).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Io(io::Error),
+    Decode(FrameDecoderError),
+    DecodeBlock(DecodeBlockError),
+    #[cfg(feature = "encoder")]
+    Encode(EncodeError),
+    #[cfg(feature = "encoder")]
+    Config(ConfigError),
+    #[cfg(feature = "encoder")]
+    ParseLevel(ParseLevelError),
+    Dictionary(DictionaryDecodeError),
+    ReadFrameHeader(ReadFrameHeaderError),
+    InspectFrame(InspectFrameError),
+    SkippableFrame(SkippableFrameError),
+    Concat(ConcatError),
+    Split(SplitError),
+    #[cfg(feature = "encoder")]
+    Recompress(RecompressError),
+    PatchHeader(PatchHeaderError),
+    Seekable(SeekableError),
+    #[cfg(feature = "self-test")]
+    SelfTest(SelfTestError),
+}
+
+impl Error {
+    /// The broad category of this error.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::Io(e) => io_category(e),
+            Error::Decode(e) => decode_category(e),
+            Error::DecodeBlock(e) => match e {
+                DecodeBlockError::BlockSizeError(_) => ErrorCategory::Corruption,
+                DecodeBlockError::DecompressBlockError(e) => decompress_block_category(e),
+                DecodeBlockError::DecodeBufferError(e) => decode_buffer_category(e),
+                DecodeBlockError::DictionaryDecodeError(e) => dictionary_category(e),
+            },
+            #[cfg(feature = "encoder")]
+            Error::Encode(e) => encode_category(e),
+            #[cfg(feature = "encoder")]
+            Error::Config(e) => config_category(e),
+            #[cfg(feature = "encoder")]
+            Error::ParseLevel(_) => ErrorCategory::Usage,
+            Error::Dictionary(e) => dictionary_category(e),
+            Error::ReadFrameHeader(e) => read_frame_header_category(e),
+            Error::InspectFrame(e) => inspect_frame_category(e),
+            Error::SkippableFrame(e) => match e {
+                SkippableFrameError::InvalidMagicVariant { .. }
+                | SkippableFrameError::DataTooLarge { .. } => ErrorCategory::Usage,
+                SkippableFrameError::NotSkippable { .. }
+                | SkippableFrameError::MalformedKeyValues { .. } => ErrorCategory::Corruption,
+                SkippableFrameError::ReadError(e) => io_category(e),
+                SkippableFrameError::WriteError(_) => ErrorCategory::Io,
+                SkippableFrameError::OutOfMemory { .. } => ErrorCategory::Limit,
+            },
+            Error::Concat(e) => match e {
+                ConcatError::InvalidFrame { error, .. } => inspect_frame_category(error),
+                ConcatError::ReadError { error, .. } => io_category(error),
+                ConcatError::WriteError(_) => ErrorCategory::Io,
+            },
+            Error::Split(e) => match e {
+                SplitError::InvalidFrame { error, .. } => inspect_frame_category(error),
+                SplitError::ReadError { error, .. } => io_category(error),
+                SplitError::WriteError(_) => ErrorCategory::Io,
+                SplitError::OutOfMemory { .. } => ErrorCategory::Limit,
+            },
+            #[cfg(feature = "encoder")]
+            Error::Recompress(e) => match e {
+                RecompressError::DecodeError { error, .. } => decode_category(error),
+                RecompressError::ChecksumMismatch { .. } => ErrorCategory::Corruption,
+                RecompressError::EncodeError(e) => encode_category(e),
+                RecompressError::ReadError { error, .. } => io_category(error),
+                RecompressError::WriteError(_) => ErrorCategory::Io,
+            },
+            Error::PatchHeader(e) => match e {
+                PatchHeaderError::InvalidFrame(e) => inspect_frame_category(e),
+                PatchHeaderError::SkippableFrame
+                | PatchHeaderError::NoContentSizeField
+                | PatchHeaderError::ContentSizeDoesNotFit { .. }
+                | PatchHeaderError::NoChecksumField => ErrorCategory::Usage,
+                PatchHeaderError::ReadError(e) => io_category(e),
+                PatchHeaderError::WriteError(_) => ErrorCategory::Io,
+            },
+            Error::Seekable(e) => match e {
+                SeekableError::InvalidFrame { error, .. } => inspect_frame_category(error),
+                SeekableError::DecodeError { error, .. } => decode_category(error),
+                SeekableError::Truncated { .. }
+                | SeekableError::InvalidSeekTable
+                | SeekableError::SizeMismatch { .. } => ErrorCategory::Corruption,
+                SeekableError::MissingContentSize { .. } => ErrorCategory::Unsupported,
+                SeekableError::RangeOutOfBounds { .. } => ErrorCategory::Usage,
+                SeekableError::OutOfMemory { .. } => ErrorCategory::Limit,
+            },
+            #[cfg(feature = "self-test")]
+            // The golden frames are valid, so every failure is a bug on the target
+            Error::SelfTest(_) => ErrorCategory::Internal,
+        }
+    }
+}
+
+/// Input that ends in the middle of a frame is corrupted, all other I/O errors are reported as such.
+fn io_category(e: &io::Error) -> ErrorCategory {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        ErrorCategory::Corruption
+    } else {
+        ErrorCategory::Io
+    }
+}
+
+fn read_frame_header_category(e: &ReadFrameHeaderError) -> ErrorCategory {
+    match e {
+        ReadFrameHeaderError::MagicNumberReadError(e)
+        | ReadFrameHeaderError::FrameDescriptorReadError(e)
+        | ReadFrameHeaderError::WindowDescriptorReadError(e)
+        | ReadFrameHeaderError::DictionaryIdReadError(e)
+        | ReadFrameHeaderError::FrameContentSizeReadError(e) => io_category(e),
+        ReadFrameHeaderError::BadMagicNumber(_)
+        | ReadFrameHeaderError::InvalidFrameDescriptor(_) => ErrorCategory::Corruption,
+        // The caller has to skip these frames
+        ReadFrameHeaderError::SkipFrame { .. } => ErrorCategory::Usage,
+    }
+}
+
+fn block_header_category(e: &BlockHeaderReadError) -> ErrorCategory {
+    match e {
+        BlockHeaderReadError::ReadError(e) => io_category(e),
+        BlockHeaderReadError::FoundReservedBlock
+        | BlockHeaderReadError::BlockTypeError(_)
+        | BlockHeaderReadError::BlockSizeError(_) => ErrorCategory::Corruption,
+    }
+}
+
+fn inspect_frame_category(e: &InspectFrameError) -> ErrorCategory {
+    match e {
+        InspectFrameError::ReadFrameHeaderError(e) => read_frame_header_category(e),
+        InspectFrameError::BlockHeaderReadError(e) => block_header_category(e),
+        InspectFrameError::BlockContentReadError(e) | InspectFrameError::ChecksumReadError(e) => {
+            io_category(e)
+        }
+    }
+}
+
+fn decode_category(e: &FrameDecoderError) -> ErrorCategory {
+    match e {
+        FrameDecoderError::ReadFrameHeaderError(e) => read_frame_header_category(e),
+        FrameDecoderError::FailedToReadBlockHeader(e) => block_header_category(e),
+        FrameDecoderError::FailedToReadBlockBody(e) => match e {
+            DecodeBlockContentError::ReadError { source, .. } => io_category(source),
+            DecodeBlockContentError::DecompressBlockError(e) => decompress_block_category(e),
+            DecodeBlockContentError::DecodeBufferError(e) => decode_buffer_category(e),
+            DecodeBlockContentError::DecoderStateIsFailed
+            | DecodeBlockContentError::ExpectedHeaderOfPreviousBlock
+            | DecodeBlockContentError::ReservedBlockType => ErrorCategory::Usage,
+        },
+        FrameDecoderError::FailedToReadChecksum(e) => io_category(e),
+        FrameDecoderError::FailedToDrainDecodebuffer(_) => ErrorCategory::Io,
+        FrameDecoderError::DictionaryDecodeError(e) => dictionary_category(e),
+        FrameDecoderError::FrameHeaderError(_)
+        | FrameDecoderError::FailedToInitialize(_)
+        | FrameDecoderError::FailedToSkipFrame
+        | FrameDecoderError::TrailingData { .. }
+        | FrameDecoderError::ChecksumMismatch { .. }
+        | FrameDecoderError::InvalidSnapshot => ErrorCategory::Corruption,
+        FrameDecoderError::WindowSizeTooBig { .. }
+        | FrameDecoderError::TargetTooSmall
+        | FrameDecoderError::OutOfMemory { .. }
+        | FrameDecoderError::BufferOutOfMemory { .. } => ErrorCategory::Limit,
+        FrameDecoderError::NotYetInitialized
+        | FrameDecoderError::DictNotProvided { .. }
+        | FrameDecoderError::TooMuchUncollected { .. } => ErrorCategory::Usage,
+    }
+}
+
+/// Running out of memory hits a limit, anything else that goes wrong in a block means that the
+/// block is corrupted.
+fn decompress_block_category(e: &DecompressBlockError) -> ErrorCategory {
+    let out_of_memory = match e {
+        DecompressBlockError::OutOfMemory { .. } => true,
+        DecompressBlockError::DecompressLiteralsError(e) => match e {
+            DecompressLiteralsError::OutOfMemory { .. } => true,
+            DecompressLiteralsError::HuffmanTableError(e) => huffman_table_out_of_memory(e),
+            _ => false,
+        },
+        DecompressBlockError::DecodeSequenceError(e) => match e {
+            DecodeSequenceError::OutOfMemory { .. } => true,
+            DecodeSequenceError::FSETableError(e) => fse_table_out_of_memory(e),
+            _ => false,
+        },
+        DecompressBlockError::ExecuteSequencesError(ExecuteSequencesError::DecodebufferError(
+            e,
+        )) => return decode_buffer_category(e),
+        _ => false,
+    };
+    if out_of_memory {
+        ErrorCategory::Limit
+    } else {
+        ErrorCategory::Corruption
+    }
+}
+
+fn decode_buffer_category(e: &DecodeBufferError) -> ErrorCategory {
+    match e {
+        DecodeBufferError::OutOfMemory { .. } => ErrorCategory::Limit,
+        _ => ErrorCategory::Corruption,
+    }
+}
+
+fn dictionary_category(e: &DictionaryDecodeError) -> ErrorCategory {
+    let out_of_memory = match e {
+        DictionaryDecodeError::OutOfMemory { .. } => true,
+        DictionaryDecodeError::FSETableError(e) => fse_table_out_of_memory(e),
+        DictionaryDecodeError::HuffmanTableError(e) => huffman_table_out_of_memory(e),
+        _ => false,
+    };
+    if out_of_memory {
+        ErrorCategory::Limit
+    } else {
+        ErrorCategory::Corruption
+    }
+}
+
+fn fse_table_out_of_memory(e: &FSETableError) -> bool {
+    matches!(e, FSETableError::OutOfMemory { .. })
+}
+
+fn huffman_table_out_of_memory(e: &HuffmanTableError) -> bool {
+    match e {
+        HuffmanTableError::OutOfMemory { .. } => true,
+        HuffmanTableError::FSETableError(e) => fse_table_out_of_memory(e),
+        _ => false,
+    }
+}
+
+#[cfg(feature = "encoder")]
+fn encode_category(e: &EncodeError) -> ErrorCategory {
+    match e {
+        EncodeError::UnsupportedLevel { .. } => ErrorCategory::Unsupported,
+        EncodeError::FailedToReadInput(_) | EncodeError::FailedToWriteOutput(_) => {
+            ErrorCategory::Io
+        }
+        EncodeError::InputTooLarge { .. } | EncodeError::OutOfMemory { .. } => ErrorCategory::Limit,
+        EncodeError::ContentSizeMismatch { .. } | EncodeError::InvalidFrameHeader { .. } => {
+            ErrorCategory::Usage
+        }
+        EncodeError::HuffmanEncoderError(_) | EncodeError::Internal { .. } => {
+            ErrorCategory::Internal
+        }
+        EncodeError::InvalidConfig(e) => config_category(e),
+    }
+}
+
+#[cfg(feature = "encoder")]
+fn config_category(e: &ConfigError) -> ErrorCategory {
+    match e {
+        ConfigError::UnsupportedLevel { .. } | ConfigError::ChecksumUnsupported => {
+            ErrorCategory::Unsupported
+        }
+        ConfigError::InvalidWindowLog { .. }
+        | ConfigError::InvalidBlockSize { .. }
+        | ConfigError::UltraRequired { .. }
+        | ConfigError::DictionaryLargerThanWindow { .. }
+        | ConfigError::InvalidPzstdFrameSize
+        | ConfigError::MagiclessSkippableFrames
+        | ConfigError::InvalidParameter { .. } => ErrorCategory::Usage,
+    }
+}
+
+/// The wrapped error is shown directly, so [source](std::error::Error::source) skips it and
+/// continues with its source.
+#[cfg(feature = "std")]
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => e.source(),
+            Error::Decode(e) => e.source(),
+            Error::DecodeBlock(e) => e.source(),
+            #[cfg(feature = "encoder")]
+            Error::Encode(e) => e.source(),
+            #[cfg(feature = "encoder")]
+            Error::Config(e) => e.source(),
+            #[cfg(feature = "encoder")]
+            Error::ParseLevel(e) => e.source(),
+            Error::Dictionary(e) => e.source(),
+            Error::ReadFrameHeader(e) => e.source(),
+            Error::InspectFrame(e) => e.source(),
+            Error::SkippableFrame(e) => e.source(),
+            Error::Concat(e) => e.source(),
+            Error::Split(e) => e.source(),
+            #[cfg(feature = "encoder")]
+            Error::Recompress(e) => e.source(),
+            Error::PatchHeader(e) => e.source(),
+            Error::Seekable(e) => e.source(),
+            #[cfg(feature = "self-test")]
+            Error::SelfTest(e) => e.source(),
+        }
+    }
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Decode(e) => write!(f, "{}", e),
+            Error::DecodeBlock(e) => write!(f, "{}", e),
+            #[cfg(feature = "encoder")]
+            Error::Encode(e) => write!(f, "{}", e),
+            #[cfg(feature = "encoder")]
+            Error::Config(e) => write!(f, "{}", e),
+            #[cfg(feature = "encoder")]
+            Error::ParseLevel(e) => write!(f, "{}", e),
+            Error::Dictionary(e) => write!(f, "{}", e),
+            Error::ReadFrameHeader(e) => write!(f, "{}", e),
+            Error::InspectFrame(e) => write!(f, "{}", e),
+            Error::SkippableFrame(e) => write!(f, "{}", e),
+            Error::Concat(e) => write!(f, "{}", e),
+            Error::Split(e) => write!(f, "{}", e),
+            #[cfg(feature = "encoder")]
+            Error::Recompress(e) => write!(f, "{}", e),
+            Error::PatchHeader(e) => write!(f, "{}", e),
+            Error::Seekable(e) => write!(f, "{}", e),
+            #[cfg(feature = "self-test")]
+            Error::SelfTest(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+macro_rules! error_from_impls {
+    ($($(#[$attr:meta])* $variant:ident($t:ty)),* $(,)?) => {$(
+        $(#[$attr])*
+        impl From<$t> for Error {
+            fn from(val: $t) -> Self {
+                Self::$variant(val)
+            }
+        }
+    )*};
+}
+
+error_from_impls! {
+    Io(io::Error),
+    Decode(FrameDecoderError),
+    DecodeBlock(DecodeBlockError),
+    #[cfg(feature = "encoder")]
+    Encode(EncodeError),
+    #[cfg(feature = "encoder")]
+    Config(ConfigError),
+    #[cfg(feature = "encoder")]
+    ParseLevel(ParseLevelError),
+    Dictionary(DictionaryDecodeError),
+    ReadFrameHeader(ReadFrameHeaderError),
+    InspectFrame(InspectFrameError),
+    SkippableFrame(SkippableFrameError),
+    Concat(ConcatError),
+    Split(SplitError),
+    #[cfg(feature = "encoder")]
+    Recompress(RecompressError),
+    PatchHeader(PatchHeaderError),
+    Seekable(SeekableError),
+    #[cfg(feature = "self-test")]
+    SelfTest(SelfTestError),
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::{Error, ErrorCategory};
+    use crate::encoding::{CompressionLevel, ConfigError};
+    use crate::frame_decoder::FrameDecoder;
+
+    fn decode(data: &[u8]) -> Result<(), Error> {
+        let mut output = [0u8; 64];
+        FrameDecoder::new().decode_all(data, &mut output)?;
+        Ok(())
+    }
+
+    #[test]
+    fn categories() {
+        let frame = crate::compress(&[7u8; 100]);
+        assert!(decode(&frame).is_err());
+        assert_eq!(decode(&frame).unwrap_err().category(), ErrorCategory::Limit);
+        let frame = crate::compress(b"small");
+        assert!(decode(&frame).is_ok());
+        assert_eq!(
+            decode(&frame[..frame.len() - 2]).unwrap_err().category(),
+            ErrorCategory::Corruption
+        );
+        assert_eq!(
+            decode(b"not zstd").unwrap_err().category(),
+            ErrorCategory::Corruption
+        );
+        let unsupported = Error::from(ConfigError::UnsupportedLevel {
+            level: CompressionLevel::Best,
+        });
+        assert_eq!(unsupported.category(), ErrorCategory::Unsupported);
+        let parse = Error::from("fast".parse::<CompressionLevel>().unwrap_err());
+        assert_eq!(parse.category(), ErrorCategory::Usage);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn source_chain() {
+        use std::error::Error as _;
+
+        // the frame decoder error is shown, and caused by the frame header error
+        let err = decode(b"not zstd").unwrap_err();
+        assert!(matches!(err, Error::Decode(_)));
+        let source = err.source().unwrap();
+        assert!(source
+            .downcast_ref::<crate::frame::ReadFrameHeaderError>()
+            .is_some());
+    }
+}
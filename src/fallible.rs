@@ -0,0 +1,132 @@
+//! Allocations that report running out of memory as an error instead of aborting.
+//!
+//! With the `no-panic` feature, clippy rejects the methods of `Vec`, `HashMap` and
+//! [RingBuffer](crate::decoding::ringbuffer::RingBuffer) that allocate without reporting failure
+//! (see clippy.toml), so all buffers of the library grow through these functions.
+
+use alloc::alloc::{handle_alloc_error, Layout};
+use alloc::vec::Vec;
+use core::mem::size_of;
+
+/// An allocation failed, because there was not enough memory or the size does not fit into the
+/// address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct AllocError {
+    /// The number of bytes that were requested.
+    pub(crate) size: usize,
+}
+
+impl AllocError {
+    /// The error for an allocation of `count` more elements of type `T`.
+    pub(crate) fn of<T>(count: usize) -> Self {
+        Self {
+            size: count.saturating_mul(size_of::<T>()),
+        }
+    }
+}
+
+/// The value of `result`, or abort like the collections of `alloc` do when an allocation failed.
+///
+/// Only for the public functions that have no way to report the error, the library itself calls
+/// their fallible versions. Those functions are disallowed in clippy.toml as well.
+#[allow(clippy::disallowed_methods)]
+#[cfg_attr(not(feature = "encoder"), allow(dead_code))]
+pub(crate) fn or_abort<T>(result: Result<T, AllocError>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => handle_alloc_error(
+            Layout::array::<u8>(err.size).unwrap_or_else(|_| Layout::new::<u8>()),
+        ),
+    }
+}
+
+/// A `Vec` with room for `capacity` elements.
+#[allow(clippy::disallowed_methods)]
+pub(crate) fn try_with_capacity<T>(capacity: usize) -> Result<Vec<T>, AllocError> {
+    let mut vec = Vec::new();
+    vec.try_reserve_exact(capacity)
+        .map_err(|_| AllocError::of::<T>(capacity))?;
+    Ok(vec)
+}
+
+/// A `Vec` of `len` copies of `value`, like `vec![value; len]`.
+pub(crate) fn try_vec<T: Clone>(value: T, len: usize) -> Result<Vec<T>, AllocError> {
+    let mut vec = try_with_capacity(len)?;
+    vec.try_resize(len, value)?;
+    Ok(vec)
+}
+
+/// A copy of `data` in a new `Vec`, like `<[T]>::to_vec`.
+pub(crate) fn try_to_vec<T: Clone>(data: &[T]) -> Result<Vec<T>, AllocError> {
+    let mut vec = try_with_capacity(data.len())?;
+    vec.try_extend_from_slice(data)?;
+    Ok(vec)
+}
+
+/// The items of `iter` in a new `Vec`, like `Iterator::collect`.
+#[cfg_attr(not(feature = "encoder"), allow(dead_code))]
+pub(crate) fn try_collect<T>(iter: impl IntoIterator<Item = T>) -> Result<Vec<T>, AllocError> {
+    let mut vec = Vec::new();
+    vec.try_extend(iter)?;
+    Ok(vec)
+}
+
+/// The methods of `Vec` that grow it, reporting failed allocations.
+pub(crate) trait TryVec<T> {
+    /// Make room for `additional` more elements, like `Vec::reserve`.
+    fn try_room(&mut self, additional: usize) -> Result<(), AllocError>;
+    /// Like `Vec::push`.
+    fn try_push(&mut self, value: T) -> Result<(), AllocError>;
+    /// Like `Vec::extend_from_slice`.
+    fn try_extend_from_slice(&mut self, data: &[T]) -> Result<(), AllocError>
+    where
+        T: Clone;
+    /// Like `Vec::extend`.
+    #[cfg_attr(not(feature = "encoder"), allow(dead_code))]
+    fn try_extend(&mut self, iter: impl IntoIterator<Item = T>) -> Result<(), AllocError>;
+    /// Like `Vec::resize`.
+    fn try_resize(&mut self, len: usize, value: T) -> Result<(), AllocError>
+    where
+        T: Clone;
+}
+
+#[allow(clippy::disallowed_methods)]
+impl<T> TryVec<T> for Vec<T> {
+    fn try_room(&mut self, additional: usize) -> Result<(), AllocError> {
+        self.try_reserve(additional)
+            .map_err(|_| AllocError::of::<T>(additional))
+    }
+
+    fn try_push(&mut self, value: T) -> Result<(), AllocError> {
+        self.try_room(1)?;
+        self.push(value);
+        Ok(())
+    }
+
+    fn try_extend_from_slice(&mut self, data: &[T]) -> Result<(), AllocError>
+    where
+        T: Clone,
+    {
+        self.try_room(data.len())?;
+        self.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn try_extend(&mut self, iter: impl IntoIterator<Item = T>) -> Result<(), AllocError> {
+        let iter = iter.into_iter();
+        self.try_room(iter.size_hint().0)?;
+        for value in iter {
+            self.try_push(value)?;
+        }
+        Ok(())
+    }
+
+    fn try_resize(&mut self, len: usize, value: T) -> Result<(), AllocError>
+    where
+        T: Clone,
+    {
+        self.try_room(len.saturating_sub(self.len()))?;
+        self.resize(len, value);
+        Ok(())
+    }
+}
@@ -1,4 +1,8 @@
+use crate::blocks::block::BlockHeader;
+use crate::decoding::block_decoder::BlockHeaderReadError;
+use crate::fallible::{AllocError, TryVec};
 use crate::io::{Error, Read};
+use alloc::vec::Vec;
 use core::fmt;
 #[cfg(feature = "std")]
 use std::error::Error as StdError;
@@ -229,14 +233,7 @@ impl FrameHeader {
         if self.descriptor.single_segment_flag() {
             Ok(self.frame_content_size())
         } else {
-            let exp = self.window_descriptor >> 3;
-            let mantissa = self.window_descriptor & 0x7;
-
-            let window_log = 10 + u64::from(exp);
-            let window_base = 1 << window_log;
-            let window_add = (window_base / 8) * u64::from(mantissa);
-
-            let window_size = window_base + window_add;
+            let window_size = self.described_window_size();
 
             if window_size >= MIN_WINDOW_SIZE {
                 if window_size < MAX_WINDOW_SIZE {
@@ -250,6 +247,19 @@ impl FrameHeader {
         }
     }
 
+    /// The window size the `Window_Descriptor` describes, without checking it against the limits
+    /// of the format.
+    pub(crate) fn described_window_size(&self) -> u64 {
+        let exp = self.window_descriptor >> 3;
+        let mantissa = self.window_descriptor & 0x7;
+
+        let window_log = 10 + u64::from(exp);
+        let window_base = 1 << window_log;
+        let window_add = (window_base / 8) * u64::from(mantissa);
+
+        window_base + window_add
+    }
+
     /// The ID (if provided) of the dictionary required to decode this frame.
     pub fn dictionary_id(&self) -> Option<u32> {
         self.dict_id
@@ -259,6 +269,23 @@ impl FrameHeader {
     pub fn frame_content_size(&self) -> u64 {
         self.frame_content_size
     }
+
+    /// Write the header the way it was read, without the magic number in front.
+    pub(crate) fn write_magicless(&self, output: &mut Vec<u8>) -> Result<(), AllocError> {
+        output.try_push(self.descriptor.0)?;
+        if !self.descriptor.single_segment_flag() {
+            output.try_push(self.window_descriptor)?;
+        }
+        let dict_id_len = self.descriptor.dictionary_id_bytes().unwrap_or(0) as usize;
+        output.try_extend_from_slice(&self.dict_id.unwrap_or(0).to_le_bytes()[..dict_id_len])?;
+        let fcs_len = self.descriptor.frame_content_size_bytes().unwrap_or(0) as usize;
+        let fcs = if fcs_len == 2 {
+            self.frame_content_size - 256
+        } else {
+            self.frame_content_size
+        };
+        output.try_extend_from_slice(&fcs.to_le_bytes()[..fcs_len])
+    }
 }
 
 #[derive(Debug)]
@@ -324,27 +351,45 @@ impl From<FrameDescriptorError> for ReadFrameHeaderError {
 }
 
 /// Read a single serialized frame from the reader and return a tuple containing the parsed frame and the number of bytes read.
-pub fn read_frame_header(mut r: impl Read) -> Result<(Frame, u8), ReadFrameHeaderError> {
+pub fn read_frame_header(r: impl Read) -> Result<(Frame, u8), ReadFrameHeaderError> {
+    read_frame_header_with(r, false)
+}
+
+/// Like [read_frame_header], for frames that were written without the magic number in front
+/// (see [EncodeOptions::magicless](crate::encoding::EncodeOptions::magicless)).
+///
+/// Skippable frames can not be recognized without their magic number.
+pub fn read_magicless_frame_header(r: impl Read) -> Result<(Frame, u8), ReadFrameHeaderError> {
+    read_frame_header_with(r, true)
+}
+
+fn read_frame_header_with(
+    mut r: impl Read,
+    magicless: bool,
+) -> Result<(Frame, u8), ReadFrameHeaderError> {
     use ReadFrameHeaderError as err;
     let mut buf = [0u8; 4];
+    let mut bytes_read = 0;
+
+    if !magicless {
+        r.read_exact(&mut buf).map_err(err::MagicNumberReadError)?;
+        bytes_read += 4;
+        let magic_num = u32::from_le_bytes(buf);
+
+        // Skippable frames have a magic number in this interval
+        if (0x184D2A50..=0x184D2A5F).contains(&magic_num) {
+            r.read_exact(&mut buf)
+                .map_err(err::FrameDescriptorReadError)?;
+            let skip_size = u32::from_le_bytes(buf);
+            return Err(ReadFrameHeaderError::SkipFrame {
+                magic_number: magic_num,
+                length: skip_size,
+            });
+        }
 
-    r.read_exact(&mut buf).map_err(err::MagicNumberReadError)?;
-    let mut bytes_read = 4;
-    let magic_num = u32::from_le_bytes(buf);
-
-    // Skippable frames have a magic number in this interval
-    if (0x184D2A50..=0x184D2A5F).contains(&magic_num) {
-        r.read_exact(&mut buf)
-            .map_err(err::FrameDescriptorReadError)?;
-        let skip_size = u32::from_le_bytes(buf);
-        return Err(ReadFrameHeaderError::SkipFrame {
-            magic_number: magic_num,
-            length: skip_size,
-        });
-    }
-
-    if magic_num != MAGIC_NUM {
-        return Err(ReadFrameHeaderError::BadMagicNumber(magic_num));
+        if magic_num != MAGIC_NUM {
+            return Err(ReadFrameHeaderError::BadMagicNumber(magic_num));
+        }
     }
 
     r.read_exact(&mut buf[0..1])
@@ -408,3 +453,124 @@ pub fn read_frame_header(mut r: impl Read) -> Result<(Frame, u8), ReadFrameHeade
 
     Ok((frame, bytes_read as u8))
 }
+
+/// Information about a single Zstandard frame, gathered by [inspect_frame] without decompressing it.
+pub struct FrameInfo {
+    pub header: FrameHeader,
+    /// The number of blocks in the frame.
+    pub block_count: u32,
+    /// The size of the whole frame in bytes, including the magic number, all block headers
+    /// and the checksum.
+    pub compressed_size: u64,
+    /// The `Content_Checksum` stored at the end of the frame, if the frame has one.
+    pub checksum: Option<u32>,
+}
+
+impl FrameInfo {
+    /// The size of the decompressed content, if it is stored in the frame header.
+    pub fn decompressed_size(&self) -> Option<u64> {
+        match self.header.descriptor.frame_content_size_bytes() {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(self.header.frame_content_size()),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum InspectFrameError {
+    ReadFrameHeaderError(ReadFrameHeaderError),
+    BlockHeaderReadError(BlockHeaderReadError),
+    BlockContentReadError(Error),
+    ChecksumReadError(Error),
+}
+
+impl fmt::Display for InspectFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ReadFrameHeaderError(e) => write!(f, "{}", e),
+            Self::BlockHeaderReadError(e) => write!(f, "{}", e),
+            Self::BlockContentReadError(e) => write!(f, "Error while reading block content: {}", e),
+            Self::ChecksumReadError(e) => write!(f, "Error while reading checksum: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl StdError for InspectFrameError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            InspectFrameError::ReadFrameHeaderError(source) => Some(source),
+            InspectFrameError::BlockHeaderReadError(source) => Some(source),
+            InspectFrameError::BlockContentReadError(source) => Some(source),
+            InspectFrameError::ChecksumReadError(source) => Some(source),
+        }
+    }
+}
+
+impl From<ReadFrameHeaderError> for InspectFrameError {
+    fn from(error: ReadFrameHeaderError) -> Self {
+        Self::ReadFrameHeaderError(error)
+    }
+}
+
+impl From<BlockHeaderReadError> for InspectFrameError {
+    fn from(error: BlockHeaderReadError) -> Self {
+        Self::BlockHeaderReadError(error)
+    }
+}
+
+/// Read a whole frame from the reader and collect information about it, without decompressing anything.
+///
+/// Only the frame header and the block headers are parsed, the block contents are skipped.
+/// Like [read_frame_header] this returns [ReadFrameHeaderError::SkipFrame] for skippable frames,
+/// the caller has to skip `length` bytes to get to the next frame.
+pub fn inspect_frame(mut r: impl Read) -> Result<FrameInfo, InspectFrameError> {
+    let (frame, header_size) = read_frame_header(&mut r)?;
+    inspect_frame_content(frame.header, header_size, r)
+}
+
+/// Like [inspect_frame], for a frame whose header was already read with [read_frame_header].
+pub(crate) fn inspect_frame_content(
+    header: FrameHeader,
+    header_size: u8,
+    mut r: impl Read,
+) -> Result<FrameInfo, InspectFrameError> {
+    let mut block_count = 0;
+    let mut compressed_size = u64::from(header_size);
+    let mut buf = [0u8; crate::STACK_BUFFER_SIZE];
+    loop {
+        let block_header = BlockHeader::parse(&mut r)?;
+        block_count += 1;
+        compressed_size += BlockHeader::SIZE as u64 + u64::from(block_header.content_size);
+
+        let mut to_skip = block_header.content_size as usize;
+        while to_skip > 0 {
+            let chunk = usize::min(to_skip, buf.len());
+            r.read_exact(&mut buf[..chunk])
+                .map_err(InspectFrameError::BlockContentReadError)?;
+            to_skip -= chunk;
+        }
+
+        if block_header.last_block {
+            break;
+        }
+    }
+
+    let checksum = if header.descriptor.content_checksum_flag() {
+        let mut checksum = [0u8; 4];
+        r.read_exact(&mut checksum)
+            .map_err(InspectFrameError::ChecksumReadError)?;
+        compressed_size += 4;
+        Some(u32::from_le_bytes(checksum))
+    } else {
+        None
+    };
+
+    Ok(FrameInfo {
+        header,
+        block_count,
+        compressed_size,
+        checksum,
+    })
+}
@@ -5,13 +5,18 @@
 //! and utilities that can be used to decode a frame.
 
 use super::frame;
+use crate::blocks::block::{BlockHeader, BlockType};
 use crate::decoding::dictionary::Dictionary;
 use crate::decoding::scratch::DecoderScratch;
+use crate::decoding::snapshot::{self, SnapshotReader, SnapshotWriter};
 use crate::decoding::{self, dictionary};
+use crate::fallible::{AllocError, TryVec};
 use crate::io::{Error, Read, Write};
-use alloc::collections::BTreeMap;
+use crate::metrics::{CodecMetrics, DecodedBlock, Stage, StageTimer};
+use alloc::sync::Arc;
 use alloc::vec::Vec;
-use core::convert::TryInto;
+use core::convert::TryFrom;
+use core::mem::MaybeUninit;
 #[cfg(feature = "std")]
 use std::error::Error as StdError;
 
@@ -70,7 +75,14 @@ use std::error::Error as StdError;
 /// ```
 pub struct FrameDecoder {
     state: Option<FrameDecoderState>,
-    dicts: BTreeMap<u32, Dictionary>,
+    /// The dictionaries that were added, at most one per ID.
+    dicts: Vec<Arc<Dictionary>>,
+    max_window_size: u64,
+    max_output_size: usize,
+    ultra: bool,
+    magicless: bool,
+    decode_without_dict: bool,
+    metrics: Option<Arc<dyn CodecMetrics>>,
 }
 
 struct FrameDecoderState {
@@ -79,8 +91,12 @@ struct FrameDecoderState {
     frame_finished: bool,
     block_counter: usize,
     bytes_read_counter: u64,
+    /// `bytes_read_counter` when the last call that read from the source started
+    last_call_start: u64,
     check_sum: Option<u32>,
     using_dict: Option<u32>,
+    /// Set when the frame was resumed from a snapshot that did not carry the state of the checksum
+    checksum_unavailable: bool,
 }
 
 pub enum BlockDecodingStrategy {
@@ -94,7 +110,10 @@ pub enum BlockDecodingStrategy {
 pub enum FrameDecoderError {
     ReadFrameHeaderError(frame::ReadFrameHeaderError),
     FrameHeaderError(frame::FrameHeaderError),
-    WindowSizeTooBig { requested: u64 },
+    WindowSizeTooBig {
+        requested: u64,
+        max: u64,
+    },
     DictionaryDecodeError(dictionary::DictionaryDecodeError),
     FailedToReadBlockHeader(decoding::block_decoder::BlockHeaderReadError),
     FailedToReadBlockBody(decoding::block_decoder::DecodeBlockContentError),
@@ -104,7 +123,35 @@ pub enum FrameDecoderError {
     FailedToDrainDecodebuffer(Error),
     FailedToSkipFrame,
     TargetTooSmall,
-    DictNotProvided { dict_id: u32 },
+    DictNotProvided {
+        dict_id: u32,
+    },
+    TrailingData {
+        position: u64,
+    },
+    /// The checksum stored in the frame does not match the decoded content.
+    ChecksumMismatch {
+        expected: u32,
+        calculated: u32,
+    },
+    /// There is not enough memory for the window of the frame.
+    OutOfMemory {
+        window_size: u64,
+    },
+    /// The snapshot passed to [FrameDecoder::resume] was not written by [FrameDecoder::suspend]
+    /// of a compatible version, or it is damaged.
+    InvalidSnapshot,
+    /// There is not enough memory for a buffer of `size` bytes other than the window, e.g. for a
+    /// snapshot or the output of [FrameDecoder::decode_all_to_vec].
+    BufferOutOfMemory {
+        size: usize,
+    },
+    /// [FrameDecoder::suspend] was called with more decoded data that was not collected yet than
+    /// the window of the frame holds. Collecting first leaves at most the window.
+    TooMuchUncollected {
+        uncollected: usize,
+        window_size: u64,
+    },
 }
 
 #[cfg(feature = "std")]
@@ -133,11 +180,11 @@ impl core::fmt::Display for FrameDecoderError {
             FrameDecoderError::FrameHeaderError(e) => {
                 write!(f, "{:?}", e)
             }
-            FrameDecoderError::WindowSizeTooBig { requested } => {
+            FrameDecoderError::WindowSizeTooBig { requested, max } => {
                 write!(
                     f,
                     "Specified window_size is too big; Requested: {}, Max: {}",
-                    requested, MAX_WINDOW_SIZE,
+                    requested, max,
                 )
             }
             FrameDecoderError::DictionaryDecodeError(e) => {
@@ -177,10 +224,56 @@ impl core::fmt::Display for FrameDecoderError {
             FrameDecoderError::DictNotProvided { dict_id } => {
                 write!(f, "Frame header specified dictionary id 0x{:X} that wasnt provided by add_dict() or reset_with_dict()", dict_id)
             }
+            FrameDecoderError::TrailingData { position } => {
+                write!(
+                    f,
+                    "Found data that is not a zstd frame after the last frame at byte {}",
+                    position
+                )
+            }
+            FrameDecoderError::ChecksumMismatch {
+                expected,
+                calculated,
+            } => {
+                write!(
+                    f,
+                    "Checksum mismatch: the frame stores 0x{:08X}, the content hashes to 0x{:08X}",
+                    expected, calculated
+                )
+            }
+            FrameDecoderError::OutOfMemory { window_size } => {
+                write!(
+                    f,
+                    "Failed to allocate the window of the frame ({} bytes)",
+                    window_size
+                )
+            }
+            FrameDecoderError::InvalidSnapshot => {
+                write!(f, "The decoder snapshot is invalid or damaged")
+            }
+            FrameDecoderError::BufferOutOfMemory { size } => {
+                write!(f, "Failed to allocate a buffer of {} bytes", size)
+            }
+            FrameDecoderError::TooMuchUncollected {
+                uncollected,
+                window_size,
+            } => {
+                write!(
+                    f,
+                    "{} bytes were not collected before suspending, more than the window of {} bytes",
+                    uncollected, window_size
+                )
+            }
         }
     }
 }
 
+impl From<AllocError> for FrameDecoderError {
+    fn from(val: AllocError) -> Self {
+        Self::BufferOutOfMemory { size: val.size }
+    }
+}
+
 impl From<dictionary::DictionaryDecodeError> for FrameDecoderError {
     fn from(val: dictionary::DictionaryDecodeError) -> Self {
         Self::DictionaryDecodeError(val)
@@ -205,44 +298,111 @@ impl From<frame::ReadFrameHeaderError> for FrameDecoderError {
     }
 }
 
-const MAX_WINDOW_SIZE: u64 = 1024 * 1024 * 100;
+/// The largest window a [FrameDecoder] accepts unless configured otherwise with
+/// [FrameDecoder::set_max_window_size].
+pub const DEFAULT_MAX_WINDOW_SIZE: u64 = 1024 * 1024 * 100;
+
+/// The window of frames of level 22, the largest one of the compression levels (128 MiB). Decoders
+/// accept it with [FrameDecoder::set_ultra].
+pub const ULTRA_MAX_WINDOW_SIZE: u64 = 1 << 27;
+
+/// The largest window libzstd compresses with, `--long=31` on 64 bit targets (2 GiB). Frames
+/// with long distance matching or `--patch-from` against large files can need windows up to this
+/// size. Pass it to [FrameDecoder::set_max_window_size] to decode them.
+pub const LIBZSTD_MAX_WINDOW_SIZE: u64 = 1 << 31;
+
+/// The largest window decoders accept with the `small-target` feature (8 KiB), whatever
+/// [FrameDecoder::set_max_window_size] and [FrameDecoder::set_ultra] allow.
+pub const SMALL_TARGET_MAX_WINDOW_SIZE: u64 = 8 * 1024;
+
+fn find_dict(dicts: &[Arc<Dictionary>], dict_id: u32) -> Option<&Arc<Dictionary>> {
+    dicts.iter().find(|dict| dict.id == dict_id)
+}
+
+fn check_window_size(window_size: u64, max: u64) -> Result<(), FrameDecoderError> {
+    if window_size > max {
+        return Err(FrameDecoderError::WindowSizeTooBig {
+            requested: window_size,
+            max,
+        });
+    }
+    Ok(())
+}
+
+fn read_header(
+    source: impl Read,
+    magicless: bool,
+) -> Result<(frame::Frame, u8), frame::ReadFrameHeaderError> {
+    if magicless {
+        frame::read_magicless_frame_header(source)
+    } else {
+        frame::read_frame_header(source)
+    }
+}
 
 impl FrameDecoderState {
-    pub fn new(source: impl Read) -> Result<FrameDecoderState, FrameDecoderError> {
-        let (frame, header_size) = frame::read_frame_header(source)?;
-        let window_size = frame.header.window_size()?;
+    pub fn new(
+        source: impl Read,
+        max_window_size: u64,
+        magicless: bool,
+    ) -> Result<FrameDecoderState, FrameDecoderError> {
+        let (frame, header_size) = read_header(source, magicless)?;
+        let mut decoder_scratch = DecoderScratch::new(0);
+        allocate_window(&frame, max_window_size, &mut decoder_scratch)?;
         Ok(FrameDecoderState {
             frame,
             frame_finished: false,
             block_counter: 0,
-            decoder_scratch: DecoderScratch::new(window_size as usize),
+            decoder_scratch,
             bytes_read_counter: u64::from(header_size),
+            last_call_start: 0,
             check_sum: None,
             using_dict: None,
+            checksum_unavailable: false,
         })
     }
 
-    pub fn reset(&mut self, source: impl Read) -> Result<(), FrameDecoderError> {
-        let (frame, header_size) = frame::read_frame_header(source)?;
-        let window_size = frame.header.window_size()?;
-
-        if window_size > MAX_WINDOW_SIZE {
-            return Err(FrameDecoderError::WindowSizeTooBig {
-                requested: window_size,
-            });
-        }
+    pub fn reset(
+        &mut self,
+        source: impl Read,
+        max_window_size: u64,
+        magicless: bool,
+    ) -> Result<(), FrameDecoderError> {
+        let (frame, header_size) = read_header(source, magicless)?;
+        allocate_window(&frame, max_window_size, &mut self.decoder_scratch)?;
 
         self.frame = frame;
         self.frame_finished = false;
         self.block_counter = 0;
-        self.decoder_scratch.reset(window_size as usize);
         self.bytes_read_counter = u64::from(header_size);
+        self.last_call_start = 0;
         self.check_sum = None;
         self.using_dict = None;
+        self.checksum_unavailable = false;
         Ok(())
     }
 }
 
+/// Check the window of `frame` against the limit and reset `scratch` for it, with space for the
+/// window (or the content, if the frame declares a smaller one) allocated up front. Failing
+/// allocations are reported as [FrameDecoderError::OutOfMemory] instead of aborting later.
+fn allocate_window(
+    frame: &frame::Frame,
+    max_window_size: u64,
+    scratch: &mut DecoderScratch,
+) -> Result<(), FrameDecoderError> {
+    let window_size = frame.header.window_size()?;
+    check_window_size(window_size, max_window_size)?;
+    // Windows beyond the address space, on 32 bit targets, can not be allocated either
+    let window =
+        usize::try_from(window_size).map_err(|_| FrameDecoderError::OutOfMemory { window_size })?;
+    let content_size = matches!(frame.header.descriptor.frame_content_size_bytes(), Ok(1..))
+        .then(|| frame.header.frame_content_size());
+    scratch
+        .reset(window, content_size)
+        .map_err(|_| FrameDecoderError::OutOfMemory { window_size })
+}
+
 impl Default for FrameDecoder {
     fn default() -> Self {
         Self::new()
@@ -256,10 +416,105 @@ impl FrameDecoder {
     pub fn new() -> FrameDecoder {
         FrameDecoder {
             state: None,
-            dicts: BTreeMap::new(),
+            dicts: Vec::new(),
+            max_window_size: DEFAULT_MAX_WINDOW_SIZE,
+            max_output_size: 0,
+            ultra: false,
+            magicless: false,
+            decode_without_dict: false,
+            metrics: None,
         }
     }
 
+    /// Set the largest window size (in bytes) that frames may request, which is also roughly the
+    /// amount of memory the decoder will use. Frames with larger windows are rejected with
+    /// [FrameDecoderError::WindowSizeTooBig] when the decoder is reset for them.
+    ///
+    /// The default is [DEFAULT_MAX_WINDOW_SIZE]. Raise it to decode frames that were compressed with
+    /// a large window log, e.g. patches against big reference files, up to [LIBZSTD_MAX_WINDOW_SIZE]
+    /// for frames with long distance matching. Frames of level 22 need [FrameDecoder::set_ultra].
+    ///
+    /// The window and one block are allocated when the decoder is reset for a frame, exactly and
+    /// not rounded up, or only the content if the frame declares a smaller content size. Frames
+    /// whose window can not be allocated are rejected with [FrameDecoderError::OutOfMemory].
+    pub fn set_max_window_size(&mut self, max_window_size: u64) {
+        self.max_window_size = max_window_size;
+    }
+
+    /// Let [FrameDecoder::decode_all_to_vec] grow the output vector for up to `max_output_size`
+    /// bytes of decompressed data. Input that decompresses to more is rejected with
+    /// [FrameDecoderError::TargetTooSmall].
+    ///
+    /// The default is 0: the vector does not grow, and its spare capacity limits how much can be
+    /// decoded, so callers can bound the memory that a decompression bomb takes. Output vectors
+    /// with more spare capacity than the limit can always be filled.
+    pub fn set_max_output_size(&mut self, max_output_size: usize) {
+        self.max_output_size = max_output_size;
+    }
+
+    /// The most decompressed data [FrameDecoder::decode_all_to_vec] accepts, see
+    /// [FrameDecoder::set_max_output_size].
+    pub fn max_output_size(&self) -> usize {
+        self.max_output_size
+    }
+
+    /// The largest window size frames may request, see [FrameDecoder::set_max_window_size].
+    /// [FrameDecoder::set_ultra] raises it, the `small-target` feature limits it to
+    /// [SMALL_TARGET_MAX_WINDOW_SIZE].
+    pub fn max_window_size(&self) -> u64 {
+        let max = if self.ultra {
+            u64::max(self.max_window_size, ULTRA_MAX_WINDOW_SIZE)
+        } else {
+            self.max_window_size
+        };
+        if cfg!(feature = "small-target") {
+            max.min(SMALL_TARGET_MAX_WINDOW_SIZE)
+        } else {
+            max
+        }
+    }
+
+    /// Accept the windows of all compression levels, up to the [ULTRA_MAX_WINDOW_SIZE] of level 22,
+    /// even if [FrameDecoder::set_max_window_size] allows less. The counterpart of
+    /// [EncodeOptions::ultra](crate::encoding::EncodeOptions::ultra), which frames of levels 20 to
+    /// 22 are compressed with. Off by default, because these windows take a lot of memory.
+    pub fn set_ultra(&mut self, ultra: bool) {
+        self.ultra = ultra;
+    }
+
+    /// Expect frames without the magic number in front, as written with
+    /// [EncodeOptions::magicless](crate::encoding::EncodeOptions::magicless).
+    /// Skippable frames can not be told apart from such frames, so they are not supported.
+    pub fn set_magicless(&mut self, magicless: bool) {
+        self.magicless = magicless;
+    }
+
+    /// Whether frames are expected without the magic number, see [FrameDecoder::set_magicless].
+    pub fn magicless(&self) -> bool {
+        self.magicless
+    }
+
+    /// Decode frames that need a dictionary which was not added, instead of failing with
+    /// [FrameDecoderError::DictNotProvided] when the decoder is reset for them.
+    ///
+    /// The frames are decoded as if the dictionary was empty. This works as long as the blocks do not
+    /// reference the dictionary, which is often the case for the first blocks of a frame that was
+    /// compressed with a content-only dictionary. Once a block does reference it, decoding fails or,
+    /// for the entropy tables of a full dictionary, produces garbage. Disabled by default.
+    pub fn set_decode_without_dict(&mut self, decode_without_dict: bool) {
+        self.decode_without_dict = decode_without_dict;
+    }
+
+    /// Whether frames are decoded without their dictionary, see [FrameDecoder::set_decode_without_dict].
+    pub fn decode_without_dict(&self) -> bool {
+        self.decode_without_dict
+    }
+
+    /// Report the bytes, blocks and time spent decoding to `metrics`, or stop reporting with `None`.
+    pub fn set_metrics(&mut self, metrics: Option<Arc<dyn CodecMetrics>>) {
+        self.metrics = metrics;
+    }
+
     /// init() will allocate all needed buffers if it is the first time this decoder is used
     /// else they just reset these buffers with not further allocations
     ///
@@ -278,30 +533,51 @@ impl FrameDecoder {
     /// equivalent to init()
     pub fn reset(&mut self, source: impl Read) -> Result<(), FrameDecoderError> {
         use FrameDecoderError as err;
+        let max_window_size = self.max_window_size();
         let state = match &mut self.state {
             Some(s) => {
-                s.reset(source)?;
+                s.reset(source, max_window_size, self.magicless)?;
                 s
             }
-            None => {
-                self.state = Some(FrameDecoderState::new(source)?);
-                self.state.as_mut().unwrap()
-            }
+            None => self.state.insert(FrameDecoderState::new(
+                source,
+                max_window_size,
+                self.magicless,
+            )?),
         };
         if let Some(dict_id) = state.frame.header.dictionary_id() {
-            let dict = self
-                .dicts
-                .get(&dict_id)
-                .ok_or(err::DictNotProvided { dict_id })?;
-            state.decoder_scratch.init_from_dict(dict);
-            state.using_dict = Some(dict_id);
+            match find_dict(&self.dicts, dict_id) {
+                Some(dict) => {
+                    state.decoder_scratch.init_from_dict(dict)?;
+                    state.using_dict = Some(dict_id);
+                }
+                None if self.decode_without_dict => {}
+                None => return Err(err::DictNotProvided { dict_id }),
+            }
+        }
+        if let Some(metrics) = &self.metrics {
+            metrics.bytes_in(state.bytes_read_counter);
         }
+        trace_event!(
+            window_size = state.decoder_scratch.buffer.window_size,
+            content_size = state.frame.header.frame_content_size(),
+            dictionary_id = state.using_dict,
+            checksum = state.frame.header.descriptor.content_checksum_flag(),
+            "frame start"
+        );
         Ok(())
     }
 
     /// Add a dict to the FrameDecoder that can be used when needed. The FrameDecoder uses the appropriate one dynamically
-    pub fn add_dict(&mut self, dict: Dictionary) -> Result<(), FrameDecoderError> {
-        self.dicts.insert(dict.id, dict);
+    ///
+    /// An `Arc<Dictionary>` can be added to any number of decoders, also on other threads, which
+    /// all use the same tables and content without copying them.
+    pub fn add_dict(&mut self, dict: impl Into<Arc<Dictionary>>) -> Result<(), FrameDecoderError> {
+        let dict = dict.into();
+        match self.dicts.iter_mut().find(|known| known.id == dict.id) {
+            Some(known) => *known = dict,
+            None => self.dicts.try_push(dict)?,
+        }
         Ok(())
     }
 
@@ -311,16 +587,148 @@ impl FrameDecoder {
             return Err(err::NotYetInitialized);
         };
 
-        let dict = self
-            .dicts
-            .get(&dict_id)
-            .ok_or(err::DictNotProvided { dict_id })?;
-        state.decoder_scratch.init_from_dict(dict);
+        let dict = find_dict(&self.dicts, dict_id).ok_or(err::DictNotProvided { dict_id })?;
+        state.decoder_scratch.init_from_dict(dict)?;
         state.using_dict = Some(dict_id);
 
         Ok(())
     }
 
+    /// Write the state of the frame that is decoded at the moment into a snapshot, from which
+    /// [FrameDecoder::resume] continues decoding later, possibly in another process.
+    ///
+    /// The snapshot contains the position in the frame, the decoded data that has not been collected
+    /// yet together with the window, the repeat offsets and the entropy tables, but not the
+    /// dictionaries, which have to be added to the decoder that resumes. The source has to continue
+    /// right after the bytes this decoder has read so far.
+    ///
+    /// The checksum of the frame can only be calculated after resuming if nothing of the frame
+    /// had been collected yet when the snapshot was taken, otherwise
+    /// [FrameDecoder::get_calculated_checksum] returns `None` for the resumed frame.
+    ///
+    /// Fails with [FrameDecoderError::TooMuchUncollected] if more than the window of the frame was
+    /// not collected yet, which [FrameDecoder::collect] brings down to the window. This keeps
+    /// snapshots, and what [FrameDecoder::resume] allocates for them, as small as the window.
+    ///
+    /// ```
+    /// # #[cfg(feature = "encoder")] {
+    /// use ruzstd::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    ///
+    /// let data = b"Hello, World! ".repeat(100_000);
+    /// let compressed = ruzstd::compress(&data);
+    /// let mut source = &compressed[..];
+    /// let mut decoder = FrameDecoder::new();
+    /// decoder.reset(&mut source).unwrap();
+    /// decoder
+    ///     .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+    ///     .unwrap();
+    /// let snapshot = decoder.suspend().unwrap();
+    ///
+    /// let mut resumed = FrameDecoder::new();
+    /// resumed.resume(&snapshot).unwrap();
+    /// resumed
+    ///     .decode_blocks(&mut source, BlockDecodingStrategy::All)
+    ///     .unwrap();
+    /// assert_eq!(resumed.collect().unwrap(), data);
+    /// # }
+    /// ```
+    pub fn suspend(&self) -> Result<Vec<u8>, FrameDecoderError> {
+        let state = self
+            .state
+            .as_ref()
+            .ok_or(FrameDecoderError::NotYetInitialized)?;
+        let uncollected = state.decoder_scratch.buffer.len();
+        let window_size = state.frame.header.window_size()?;
+        if uncollected as u64 > window_size {
+            return Err(FrameDecoderError::TooMuchUncollected {
+                uncollected,
+                window_size,
+            });
+        }
+        let mut header = Vec::new();
+        state.frame.header.write_magicless(&mut header)?;
+
+        let mut snapshot = Vec::new();
+        let mut writer = SnapshotWriter::new(&mut snapshot);
+        writer.bytes(&[&header]);
+        writer.bool(state.frame_finished);
+        writer.u64(state.block_counter as u64);
+        writer.u64(state.bytes_read_counter);
+        writer.option_u32(state.check_sum);
+        writer.option_u32(state.using_dict);
+        #[cfg(feature = "hash")]
+        let checksum_available = !state.checksum_unavailable
+            && state.decoder_scratch.buffer.hash == twox_hash::XxHash64::with_seed(0);
+        #[cfg(not(feature = "hash"))]
+        let checksum_available = false;
+        writer.bool(checksum_available);
+        snapshot::write_scratch(&mut writer, &state.decoder_scratch);
+        writer.finish()?;
+        Ok(snapshot)
+    }
+
+    /// Continue decoding a frame from a snapshot that was taken with [FrameDecoder::suspend].
+    ///
+    /// Like [FrameDecoder::reset], everything in the decoder from a previous frame is lost. The
+    /// dictionary the frame uses has to be added with [FrameDecoder::add_dict] first, and the frame
+    /// may not need a larger window than [FrameDecoder::set_max_window_size] allows.
+    /// If the snapshot can not be used, the decoder has to be reset before it is used again.
+    pub fn resume(&mut self, snapshot: &[u8]) -> Result<(), FrameDecoderError> {
+        let result = self.resume_from(snapshot);
+        if result.is_err() {
+            self.state = None;
+        }
+        result
+    }
+
+    fn resume_from(&mut self, snapshot: &[u8]) -> Result<(), FrameDecoderError> {
+        use FrameDecoderError as err;
+        let mut reader = SnapshotReader::new(snapshot).ok_or(err::InvalidSnapshot)?;
+        let mut header = reader.bytes().ok_or(err::InvalidSnapshot)?;
+        let max_window_size = self.max_window_size();
+        // A damaged header is a damaged snapshot, a window that is too large is not
+        let damaged_header = |e| match e {
+            err::ReadFrameHeaderError(_) | err::FrameHeaderError(_) => err::InvalidSnapshot,
+            e => e,
+        };
+        let state = match &mut self.state {
+            Some(s) => {
+                s.reset(&mut header, max_window_size, true)
+                    .map_err(damaged_header)?;
+                s
+            }
+            None => self.state.insert(
+                FrameDecoderState::new(&mut header, max_window_size, true)
+                    .map_err(damaged_header)?,
+            ),
+        };
+        if !header.is_empty() {
+            return Err(err::InvalidSnapshot);
+        }
+
+        let mut read_state = || {
+            state.frame_finished = reader.bool()?;
+            state.block_counter = usize::try_from(reader.u64()?).ok()?;
+            state.bytes_read_counter = reader.u64()?;
+            state.last_call_start = state.bytes_read_counter;
+            state.check_sum = reader.option_u32()?;
+            state.using_dict = reader.option_u32()?;
+            state.checksum_unavailable = !reader.bool()?;
+            Some(())
+        };
+        read_state().ok_or(err::InvalidSnapshot)?;
+        if let Some(dict_id) = state.using_dict {
+            let dict = find_dict(&self.dicts, dict_id).ok_or(err::DictNotProvided { dict_id })?;
+            state.decoder_scratch.init_from_dict(dict)?;
+        }
+        let window_size = state.frame.header.window_size()?;
+        snapshot::read_scratch(&mut reader, &mut state.decoder_scratch, window_size)?;
+        if !reader.is_empty() {
+            return Err(err::InvalidSnapshot);
+        }
+        Ok(())
+    }
+
     /// Returns how many bytes the frame contains after decompression
     pub fn content_size(&self) -> u64 {
         match &self.state {
@@ -329,6 +737,22 @@ impl FrameDecoder {
         }
     }
 
+    /// The decoded content that was not collected yet, including the window of an unfinished
+    /// frame that [FrameDecoder::collect] and friends hold back.
+    pub(crate) fn uncollected(&self) -> (&[u8], &[u8]) {
+        match &self.state {
+            Some(state) => state.decoder_scratch.buffer.as_slices(),
+            None => (&[], &[]),
+        }
+    }
+
+    /// Whether the current frame ends with a checksum.
+    pub(crate) fn has_checksum(&self) -> bool {
+        self.state
+            .as_ref()
+            .is_some_and(|s| s.frame.header.descriptor.content_checksum_flag())
+    }
+
     /// Returns the checksum that was read from the data. Only available after all bytes have been read. It is the last 4 bytes of a zstd-frame
     pub fn get_checksum_from_data(&self) -> Option<u32> {
         let state = match &self.state {
@@ -349,6 +773,9 @@ impl FrameDecoder {
             None => return None,
             Some(s) => s,
         };
+        if state.checksum_unavailable {
+            return None;
+        }
         let cksum_64bit = state.decoder_scratch.buffer.hash.finish();
         //truncate to lower 32bit because reasons...
         Some(cksum_64bit as u32)
@@ -363,6 +790,18 @@ impl FrameDecoder {
         state.bytes_read_counter
     }
 
+    /// How many bytes the last call to [FrameDecoder::decode_blocks] (or [FrameDecoder::reset], for
+    /// the frame header) read from the source.
+    ///
+    /// The decoder never reads more from the source than the frame contains, so once the frame is
+    /// finished the source is positioned right after it, and other data may follow in the same reader.
+    pub fn bytes_read_by_last_call(&self) -> u64 {
+        match &self.state {
+            None => 0,
+            Some(s) => s.bytes_read_counter - s.last_call_start,
+        }
+    }
+
     /// Whether the current frames last block has been decoded yet
     /// If this returns true you can call the drain* functions to get all content
     /// (the read() function will drain automatically if this returns true)
@@ -395,9 +834,60 @@ impl FrameDecoder {
         &mut self,
         mut source: impl Read,
         strat: BlockDecodingStrategy,
+    ) -> Result<bool, FrameDecoderError> {
+        self.decode_blocks_with(&mut source, strat, |block_dec, header, scratch, source| {
+            block_dec.decode_block_content(header, scratch, source)
+        })
+    }
+
+    /// Like [FrameDecoder::decode_blocks], but decodes directly from the compressed data in `source`
+    /// and advances it past everything that was decoded.
+    ///
+    /// Raw and compressed blocks are parsed in place instead of being copied into internal buffers
+    /// first, which makes this faster than [FrameDecoder::decode_blocks] when the input is in memory.
+    ///
+    /// ```
+    /// # #[cfg(feature = "encoder")] {
+    /// use ruzstd::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    ///
+    /// let compressed = ruzstd::compress(b"Hello, World!");
+    /// let mut source = &compressed[..];
+    /// let mut decoder = FrameDecoder::new();
+    /// decoder.reset(&mut source).unwrap();
+    /// assert!(decoder
+    ///     .decode_from_slice(&mut source, BlockDecodingStrategy::All)
+    ///     .unwrap());
+    /// assert!(source.is_empty());
+    /// assert_eq!(decoder.collect().unwrap(), b"Hello, World!");
+    /// # }
+    /// ```
+    pub fn decode_from_slice(
+        &mut self,
+        source: &mut &[u8],
+        strat: BlockDecodingStrategy,
+    ) -> Result<bool, FrameDecoderError> {
+        self.decode_blocks_with(source, strat, |block_dec, header, scratch, source| {
+            block_dec.decode_block_content_from_slice(header, scratch, source)
+        })
+    }
+
+    fn decode_blocks_with<R: Read>(
+        &mut self,
+        source: &mut R,
+        strat: BlockDecodingStrategy,
+        mut decode_block_content: impl FnMut(
+            &mut decoding::block_decoder::BlockDecoder,
+            &BlockHeader,
+            &mut DecoderScratch,
+            &mut R,
+        ) -> Result<
+            u64,
+            decoding::block_decoder::DecodeBlockContentError,
+        >,
     ) -> Result<bool, FrameDecoderError> {
         use FrameDecoderError as err;
         let state = self.state.as_mut().ok_or(err::NotYetInitialized)?;
+        state.last_call_start = state.bytes_read_counter;
 
         let mut block_dec = decoding::block_decoder::new();
 
@@ -408,9 +898,11 @@ impl FrameDecoder {
             vprintln!("Next Block: {}", state.block_counter);
             vprintln!("################");
             let (block_header, block_header_size) = block_dec
-                .read_block_header(&mut source)
+                .read_block_header(&mut *source)
                 .map_err(err::FailedToReadBlockHeader)?;
             state.bytes_read_counter += u64::from(block_header_size);
+            let timer = self.metrics.as_ref().map(|_| StageTimer::start());
+            let decoded_before = state.decoder_scratch.buffer.len();
 
             vprintln!();
             vprintln!(
@@ -419,11 +911,32 @@ impl FrameDecoder {
                 block_header.content_size,
                 block_header.decompressed_size
             );
+            trace_span!(
+                "decode_block",
+                index = state.block_counter,
+                block_type = %block_header.block_type,
+                size = block_header.content_size
+            );
 
-            let bytes_read_in_block_body = block_dec
-                .decode_block_content(&block_header, &mut state.decoder_scratch, &mut source)
-                .map_err(err::FailedToReadBlockBody)?;
+            let bytes_read_in_block_body = decode_block_content(
+                &mut block_dec,
+                &block_header,
+                &mut state.decoder_scratch,
+                source,
+            )
+            .map_err(err::FailedToReadBlockBody)?;
             state.bytes_read_counter += bytes_read_in_block_body;
+            if let (Some(metrics), Some(timer)) = (&self.metrics, timer) {
+                report_block(
+                    metrics.as_ref(),
+                    timer,
+                    &block_dec,
+                    &block_header,
+                    state.block_counter,
+                    u64::from(block_header_size) + bytes_read_in_block_body,
+                    state.decoder_scratch.buffer.len() - decoded_before,
+                );
+            }
 
             state.block_counter += 1;
 
@@ -439,7 +952,15 @@ impl FrameDecoder {
                     state.bytes_read_counter += 4;
                     let chksum = u32::from_le_bytes(chksum);
                     state.check_sum = Some(chksum);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.bytes_in(4);
+                    }
                 }
+                trace_event!(
+                    blocks = state.block_counter,
+                    bytes_read = state.bytes_read_counter,
+                    "frame end"
+                );
                 break;
             }
 
@@ -463,6 +984,9 @@ impl FrameDecoder {
 
     /// Collect bytes and retain window_size bytes while decoding is still going on.
     /// After decoding of the frame (is_finished() == true) has finished it will collect all remaining bytes
+    ///
+    /// Like collecting into a `Vec`, this aborts if there is not enough memory, see the Readme.
+    #[allow(clippy::disallowed_methods)]
     pub fn collect(&mut self) -> Option<Vec<u8>> {
         let finished = self.is_finished();
         let state = self.state.as_mut()?;
@@ -473,6 +997,18 @@ impl FrameDecoder {
         }
     }
 
+    /// Drop the bytes that [FrameDecoder::collect] would return, without collecting them.
+    pub(crate) fn discard(&mut self) {
+        let finished = self.is_finished();
+        if let Some(state) = &mut self.state {
+            if finished {
+                state.decoder_scratch.buffer.discard();
+            } else {
+                state.decoder_scratch.buffer.discard_to_window_size();
+            }
+        }
+    }
+
     /// Collect bytes and retain window_size bytes while decoding is still going on.
     /// After decoding of the frame (is_finished() == true) has finished it will collect all remaining bytes
     pub fn collect_to_writer(&mut self, w: impl Write) -> Result<usize, Error> {
@@ -508,6 +1044,37 @@ impl FrameDecoder {
         }
     }
 
+    /// The bytes that can be collected right now, see [FrameDecoder::can_collect], without
+    /// collecting them.
+    ///
+    /// This allows looking at the decoded data, e.g. to sniff its format, before deciding where to
+    /// copy it. The decoded data is kept in a ring buffer, so it is returned in two parts that
+    /// together are the collectable bytes. The second part is empty unless the data wraps around
+    /// the end of the buffer. This neither moves nor copies anything.
+    pub fn peek_slices(&self) -> (&[u8], &[u8]) {
+        let collectable = self.can_collect();
+        let (first, second) = self.uncollected();
+        if collectable <= first.len() {
+            (&first[..collectable], &[])
+        } else {
+            (first, &second[..collectable - first.len()])
+        }
+    }
+
+    /// Like [FrameDecoder::peek_slices], but returns the bytes as one slice.
+    ///
+    /// If the bytes wrap around the end of the ring buffer, the whole buffer is rotated in place to
+    /// make them contiguous, which is why this needs `&mut self`. That does not allocate, but takes
+    /// time proportional to the window size, so prefer [FrameDecoder::peek_slices] when peeking
+    /// after every call to the decoder.
+    pub fn peek(&mut self) -> &[u8] {
+        let collectable = self.can_collect();
+        match &mut self.state {
+            None => &[],
+            Some(state) => &state.decoder_scratch.buffer.make_contiguous()[..collectable],
+        }
+    }
+
     /// Decodes as many blocks as possible from the source slice and reads from the decodebuffer into the target slice
     /// The source slice may contain only parts of a frame but must contain at least one full block to make progress
     ///
@@ -543,7 +1110,7 @@ impl FrameDecoder {
             {
                 let state = match &mut self.state {
                     Some(s) => s,
-                    None => panic!("Bug in library"),
+                    None => return Err(err::NotYetInitialized),
                 };
                 let mut block_dec = decoding::block_decoder::new();
 
@@ -552,11 +1119,12 @@ impl FrameDecoder {
                     && state.check_sum.is_none()
                 {
                     //this block is needed if the checksum were the only 4 bytes that were not included in the last decode_from_to call for a frame
-                    if mt_source.len() >= 4 {
-                        let chksum = mt_source[..4].try_into().expect("optimized away");
+                    if let Some(chksum) = mt_source.first_chunk() {
                         state.bytes_read_counter += 4;
-                        let chksum = u32::from_le_bytes(chksum);
-                        state.check_sum = Some(chksum);
+                        state.check_sum = Some(u32::from_le_bytes(*chksum));
+                        if let Some(metrics) = &self.metrics {
+                            metrics.bytes_in(4);
+                        }
                     }
                     return Ok((4, 0));
                 }
@@ -576,6 +1144,8 @@ impl FrameDecoder {
                         break;
                     }
                     state.bytes_read_counter += u64::from(block_header_size);
+                    let timer = self.metrics.as_ref().map(|_| StageTimer::start());
+                    let decoded_before = state.decoder_scratch.buffer.len();
 
                     let bytes_read_in_block_body = block_dec
                         .decode_block_content(
@@ -585,17 +1155,29 @@ impl FrameDecoder {
                         )
                         .map_err(err::FailedToReadBlockBody)?;
                     state.bytes_read_counter += bytes_read_in_block_body;
+                    if let (Some(metrics), Some(timer)) = (&self.metrics, timer) {
+                        report_block(
+                            metrics.as_ref(),
+                            timer,
+                            &block_dec,
+                            &block_header,
+                            state.block_counter,
+                            u64::from(block_header_size) + bytes_read_in_block_body,
+                            state.decoder_scratch.buffer.len() - decoded_before,
+                        );
+                    }
                     state.block_counter += 1;
 
                     if block_header.last_block {
                         state.frame_finished = true;
                         if state.frame.header.descriptor.content_checksum_flag() {
                             //if there are enough bytes handle this here. Else the block at the start of this function will handle it at the next call
-                            if mt_source.len() >= 4 {
-                                let chksum = mt_source[..4].try_into().expect("optimized away");
+                            if let Some(chksum) = mt_source.first_chunk() {
                                 state.bytes_read_counter += 4;
-                                let chksum = u32::from_le_bytes(chksum);
-                                state.check_sum = Some(chksum);
+                                state.check_sum = Some(u32::from_le_bytes(*chksum));
+                                if let Some(metrics) = &self.metrics {
+                                    metrics.bytes_in(4);
+                                }
                             }
                         }
                         break;
@@ -607,7 +1189,7 @@ impl FrameDecoder {
         let result_len = self.read(target).map_err(err::FailedToDrainDecodebuffer)?;
         let bytes_read_at_end = match &mut self.state {
             Some(s) => s.bytes_read_counter,
-            None => panic!("Bug in library"),
+            None => return Err(err::NotYetInitialized),
         };
         let read_len = bytes_read_at_end - bytes_read_at_start;
         Ok((read_len as usize, result_len))
@@ -615,7 +1197,8 @@ impl FrameDecoder {
 
     /// Decode multiple frames into the output slice.
     ///
-    /// `input` must contain an exact number of frames.
+    /// `input` must contain an exact number of frames. If anything but another frame follows a
+    /// frame, [`FrameDecoderError::TrailingData`] is returned with the position of that data.
     ///
     /// `output` must be large enough to hold the decompressed data. If you don't know
     /// how large the output will be, use [`FrameDecoder::decode_blocks`] instead.
@@ -624,12 +1207,47 @@ impl FrameDecoder {
     ///
     /// Returns the number of bytes written to `output`.
     pub fn decode_all(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+    ) -> Result<usize, FrameDecoderError> {
+        self.decode_all_with(input, |decoder, written, _| {
+            decoder
+                .read(&mut output[written..])
+                .map_err(FrameDecoderError::FailedToDrainDecodebuffer)
+        })
+    }
+
+    /// Like [`FrameDecoder::decode_all`], but `output` does not have to be initialized, which saves
+    /// zeroing it first.
+    ///
+    /// Returns the number of bytes written to `output`. Exactly these first bytes of `output` are
+    /// initialized afterwards.
+    pub fn decode_all_uninit(
+        &mut self,
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+    ) -> Result<usize, FrameDecoderError> {
+        self.decode_all_with(input, |decoder, written, _| {
+            decoder
+                .read_uninit(&mut output[written..])
+                .map_err(FrameDecoderError::FailedToDrainDecodebuffer)
+        })
+    }
+
+    /// Decode all frames of `input`, calling `drain` with the number of bytes drained so far
+    /// whenever there is output to drain. The last argument of `drain` is true for the first call
+    /// after the header of a frame was read.
+    fn decode_all_with(
         &mut self,
         mut input: &[u8],
-        mut output: &mut [u8],
+        mut drain: impl FnMut(&mut Self, usize, bool) -> Result<usize, FrameDecoderError>,
     ) -> Result<usize, FrameDecoderError> {
         let mut total_bytes_written = 0;
+        let input_len = input.len();
+        let mut first_frame = true;
         while !input.is_empty() {
+            let position = (input_len - input.len()) as u64;
             match self.init(&mut input) {
                 Ok(_) => {}
                 Err(FrameDecoderError::ReadFrameHeaderError(
@@ -638,16 +1256,23 @@ impl FrameDecoder {
                     input = input
                         .get(length as usize..)
                         .ok_or(FrameDecoderError::FailedToSkipFrame)?;
+                    first_frame = false;
                     continue;
                 }
+                Err(FrameDecoderError::ReadFrameHeaderError(
+                    frame::ReadFrameHeaderError::BadMagicNumber(_)
+                    | frame::ReadFrameHeaderError::MagicNumberReadError(_),
+                )) if !first_frame => {
+                    return Err(FrameDecoderError::TrailingData { position });
+                }
                 Err(e) => return Err(e),
             };
+            first_frame = false;
+            let mut frame_start = true;
             loop {
-                self.decode_blocks(&mut input, BlockDecodingStrategy::UptoBytes(1024 * 1024))?;
-                let bytes_written = self
-                    .read(output)
-                    .map_err(FrameDecoderError::FailedToDrainDecodebuffer)?;
-                output = &mut output[bytes_written..];
+                self.decode_from_slice(&mut input, BlockDecodingStrategy::UptoBytes(1024 * 1024))?;
+                let bytes_written = drain(self, total_bytes_written, frame_start)?;
+                frame_start = false;
                 total_bytes_written += bytes_written;
                 if self.can_collect() != 0 {
                     return Err(FrameDecoderError::TargetTooSmall);
@@ -665,9 +1290,11 @@ impl FrameDecoder {
     ///
     /// `input` must contain an exact number of frames.
     ///
-    /// `output` must have enough extra capacity to hold the decompressed data.
-    /// This function will not reallocate or grow the vector. If you don't know
-    /// how large the output will be, use [`FrameDecoder::decode_blocks`] instead.
+    /// `output` must have enough extra capacity to hold the decompressed data, otherwise
+    /// [`FrameDecoderError::TargetTooSmall`] is returned. This function will not reallocate or
+    /// grow the vector, unless [`FrameDecoder::set_max_output_size`] allows more than its spare
+    /// capacity: then it behaves like [`FrameDecoder::decode_all_to_vec_with_limit`] with that
+    /// limit. If you don't know how large the output will be, use one of those.
     ///
     /// This calls [`FrameDecoder::init`], and all bytes currently in the decoder will be lost.
     ///
@@ -679,20 +1306,107 @@ impl FrameDecoder {
         output: &mut Vec<u8>,
     ) -> Result<(), FrameDecoderError> {
         let len = output.len();
-        let cap = output.capacity();
-        output.resize(cap, 0);
-        match self.decode_all(input, &mut output[len..]) {
-            Ok(bytes_written) => {
-                let new_len = core::cmp::min(len + bytes_written, cap); // Sanitizes `bytes_written`.
-                output.resize(new_len, 0);
-                Ok(())
-            }
-            Err(e) => {
-                output.resize(len, 0);
-                Err(e)
+        if self.max_output_size > output.capacity() - len {
+            return self.decode_all_to_vec_with_limit(input, output, self.max_output_size);
+        }
+        let bytes_written = self.decode_all_uninit(input, output.spare_capacity_mut())?;
+        // SAFETY: decode_all_uninit initialized the first `bytes_written` bytes of the spare capacity
+        unsafe { output.set_len(len + bytes_written) };
+        Ok(())
+    }
+
+    /// Decode multiple frames and append their content to the output vector, which grows as
+    /// needed up to `limit` bytes of decompressed data.
+    ///
+    /// `input` must contain an exact number of frames.
+    ///
+    /// For frames that declare their content size, exactly that much capacity is reserved up front
+    /// (as far as `limit` allows), so the vector is not reallocated while they are decoded.
+    /// If the content is larger than `limit`, [`FrameDecoderError::TargetTooSmall`] is returned.
+    ///
+    /// This calls [`FrameDecoder::init`], and all bytes currently in the decoder will be lost.
+    ///
+    /// The length of the output vector is not changed if an error occurs.
+    pub fn decode_all_to_vec_with_limit(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        limit: usize,
+    ) -> Result<(), FrameDecoderError> {
+        let len = output.len();
+        let result = self.decode_all_with(input, |decoder, written, frame_start| {
+            let remaining = limit - written;
+            if frame_start {
+                let declared = usize::try_from(decoder.content_size()).unwrap_or(usize::MAX);
+                let amount = declared.min(remaining);
+                output
+                    .try_reserve_exact(amount)
+                    .map_err(|_| AllocError::of::<u8>(amount))?;
             }
+            let amount = decoder.can_collect().min(remaining);
+            output.try_room(amount)?;
+            let bytes_written = decoder
+                .read_uninit(&mut output.spare_capacity_mut()[..amount])
+                .map_err(FrameDecoderError::FailedToDrainDecodebuffer)?;
+            // SAFETY: read_uninit initialized the first `bytes_written` bytes of the spare capacity
+            unsafe { output.set_len(output.len() + bytes_written) };
+            Ok(bytes_written)
+        });
+        if result.is_err() {
+            output.truncate(len);
         }
+        result.map(|_| ())
     }
+
+    /// Like [`Read::read`], but `target` does not have to be initialized.
+    ///
+    /// Returns the number of bytes written to `target`. Exactly these first bytes of `target` are
+    /// initialized afterwards.
+    pub fn read_uninit(&mut self, target: &mut [MaybeUninit<u8>]) -> Result<usize, Error> {
+        let state = match &mut self.state {
+            None => return Ok(0),
+            Some(s) => s,
+        };
+        if state.frame_finished {
+            Ok(state.decoder_scratch.buffer.read_all_uninit(target))
+        } else {
+            Ok(state.decoder_scratch.buffer.read_uninit(target))
+        }
+    }
+}
+
+/// Report the block with the `index` in its frame that `block_dec` just decoded from `bytes_read`
+/// bytes into `bytes_decoded` bytes.
+#[allow(clippy::too_many_arguments)]
+fn report_block(
+    metrics: &dyn CodecMetrics,
+    timer: StageTimer,
+    block_dec: &decoding::block_decoder::BlockDecoder,
+    header: &BlockHeader,
+    index: usize,
+    bytes_read: u64,
+    bytes_decoded: usize,
+) {
+    metrics.bytes_in(bytes_read);
+    metrics.bytes_out(bytes_decoded as u64);
+    metrics.block(header.block_type);
+    let sections = match header.block_type {
+        BlockType::Compressed => block_dec.last_sections.as_ref(),
+        _ => None,
+    };
+    metrics.decoded_block(&DecodedBlock {
+        index,
+        block_type: header.block_type,
+        last_block: header.last_block,
+        compressed_size: bytes_read,
+        decompressed_size: bytes_decoded as u64,
+        literals_type: sections.map(|(literals_type, _)| *literals_type),
+        num_sequences: sections.map_or(0, |(_, sequences)| sequences.num_sequences),
+        sequence_modes: sections
+            .and_then(|(_, sequences)| sequences.modes.filter(|_| sequences.num_sequences > 0))
+            .map(|modes| [modes.ll_mode(), modes.of_mode(), modes.ml_mode()]),
+    });
+    timer.finish(metrics, Stage::DecodeBlock);
 }
 
 /// Read bytes from the decode_buffer that are no longer needed. While the frame is not yet finished
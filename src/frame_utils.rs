@@ -0,0 +1,1046 @@
+//! Utilities that work on whole compressed frames, most of them without decompressing anything.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use core::ops::Range;
+
+use crate::encoding::frame_header::minify_val_fcs;
+#[cfg(feature = "encoder")]
+use crate::encoding::{CompressionLevel, EncodeError, FrameCompressor};
+use crate::fallible::{AllocError, TryVec};
+use crate::frame::{
+    inspect_frame_content, read_frame_header, FrameHeader, InspectFrameError, ReadFrameHeaderError,
+};
+#[cfg(feature = "encoder")]
+use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+use crate::io::{Error, ErrorKind, Read, Write};
+
+/// The magic number, the two descriptors, a 4 byte dictionary ID and an 8 byte content size.
+const MAX_FRAME_HEADER_SIZE: usize = 18;
+
+/// What to do with skippable frames found in the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkippableFrames {
+    /// Copy them to the output like any other frame.
+    Preserve,
+    /// Leave them out of the output.
+    Strip,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ConcatError {
+    /// The source with the index `source` does not consist of valid frames.
+    InvalidFrame {
+        source: usize,
+        error: InspectFrameError,
+    },
+    /// Reading the source with the index `source` failed.
+    ReadError {
+        source: usize,
+        error: Error,
+    },
+    WriteError(Error),
+}
+
+impl fmt::Display for ConcatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFrame { source, error } => {
+                write!(f, "Source {} contains an invalid frame: {}", source, error)
+            }
+            Self::ReadError { source, error } => {
+                write!(f, "Error while reading source {}: {}", source, error)
+            }
+            Self::WriteError(e) => write!(f, "Error while writing frames: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ConcatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConcatError::InvalidFrame { error, .. } => Some(error),
+            ConcatError::ReadError { error, .. } => Some(error),
+            ConcatError::WriteError(source) => Some(source),
+        }
+    }
+}
+
+/// Copy all frames from the `sources` into `target`, one source after the other.
+///
+/// Because frames are independent, the result decompresses to the concatenation of the
+/// decompressed sources. The frames are not recompressed, but the header and block headers of
+/// each frame are validated while they are copied. If an error is returned, the beginning of the
+/// invalid frame may already have been written to `target`.
+///
+/// Returns the number of zstd frames (not counting skippable frames) that were written.
+pub fn concat_frames<R: Read>(
+    sources: impl IntoIterator<Item = R>,
+    mut target: impl Write,
+    skippable_frames: SkippableFrames,
+) -> Result<usize, ConcatError> {
+    let mut frames = 0;
+    let mut header = Vec::new();
+    for (index, mut source) in sources.into_iter().enumerate() {
+        let map_err = |error| match error {
+            CopyError::Invalid(error) => ConcatError::InvalidFrame {
+                source: index,
+                error,
+            },
+            CopyError::Read(error) => ConcatError::ReadError {
+                source: index,
+                error,
+            },
+            CopyError::Write(error) => ConcatError::WriteError(error),
+        };
+        while let Some(start) = read_frame_start(&mut source, &mut header).map_err(map_err)? {
+            let keep = match start {
+                FrameStart::Zstd { .. } => {
+                    frames += 1;
+                    true
+                }
+                FrameStart::Skippable { .. } => skippable_frames == SkippableFrames::Preserve,
+            };
+            let target = if keep { Some(&mut target) } else { None };
+            copy_frame(&mut source, &header, start, target).map_err(map_err)?;
+        }
+    }
+    Ok(frames)
+}
+
+/// The kind of a frame found by [split_frames] or [frame_ranges].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameKind {
+    Zstd,
+    Skippable { magic_number: u32 },
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SplitError {
+    /// The frame starting at `position` is invalid.
+    InvalidFrame {
+        position: u64,
+        error: InspectFrameError,
+    },
+    /// Reading the frame starting at `position` failed.
+    ReadError {
+        position: u64,
+        error: Error,
+    },
+    WriteError(Error),
+    /// There was not enough memory for the list of frames.
+    OutOfMemory {
+        size: usize,
+    },
+}
+
+impl fmt::Display for SplitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFrame { position, error } => {
+                write!(f, "Invalid frame at byte {}: {}", position, error)
+            }
+            Self::ReadError { position, error } => {
+                write!(
+                    f,
+                    "Error while reading the frame at byte {}: {}",
+                    position, error
+                )
+            }
+            Self::WriteError(e) => write!(f, "Error while writing frames: {}", e),
+            Self::OutOfMemory { size } => {
+                write!(
+                    f,
+                    "Failed to allocate {} bytes for the list of frames",
+                    size
+                )
+            }
+        }
+    }
+}
+
+impl From<AllocError> for SplitError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SplitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SplitError::InvalidFrame { error, .. } => Some(error),
+            SplitError::ReadError { error, .. } => Some(error),
+            SplitError::WriteError(source) => Some(source),
+            SplitError::OutOfMemory { .. } => None,
+        }
+    }
+}
+
+/// Copy each frame in `source` into its own target, without decompressing anything.
+///
+/// `target_for` is called at the start of every frame with the kind of the frame and the position
+/// of the frame in the source, and returns the writer the frame is copied into.
+/// Returns the number of frames, including skippable frames.
+pub fn split_frames<R: Read, W: Write>(
+    source: R,
+    mut target_for: impl FnMut(FrameKind, u64) -> W,
+) -> Result<usize, SplitError> {
+    let mut source = Counting {
+        inner: source,
+        count: 0,
+    };
+    let mut frames = 0;
+    let mut header = Vec::new();
+    loop {
+        let position = source.count;
+        let map_err = |error| match error {
+            CopyError::Invalid(error) => SplitError::InvalidFrame { position, error },
+            CopyError::Read(error) => SplitError::ReadError { position, error },
+            CopyError::Write(error) => SplitError::WriteError(error),
+        };
+        let Some(start) = read_frame_start(&mut source, &mut header).map_err(map_err)? else {
+            return Ok(frames);
+        };
+        let target = target_for(start.kind(), position);
+        copy_frame(&mut source, &header, start, Some(target)).map_err(map_err)?;
+        frames += 1;
+    }
+}
+
+/// Find the byte ranges of all frames in `data`, without decompressing anything.
+pub fn frame_ranges(data: &[u8]) -> Result<Vec<(FrameKind, Range<usize>)>, SplitError> {
+    let mut ranges = Vec::new();
+    let mut source = data;
+    let mut header = Vec::new();
+    loop {
+        let position = data.len() - source.len();
+        let map_err = |error| match error {
+            CopyError::Invalid(error) => SplitError::InvalidFrame {
+                position: position as u64,
+                error,
+            },
+            CopyError::Read(error) => SplitError::ReadError {
+                position: position as u64,
+                error,
+            },
+            CopyError::Write(error) => SplitError::WriteError(error),
+        };
+        let Some(start) = read_frame_start(&mut source, &mut header).map_err(map_err)? else {
+            return Ok(ranges);
+        };
+        let kind = start.kind();
+        copy_frame(&mut source, &header, start, None::<Vec<u8>>).map_err(map_err)?;
+        ranges.try_push((kind, position..data.len() - source.len()))?;
+    }
+}
+
+#[cfg(feature = "encoder")]
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum RecompressError {
+    /// The frame starting at `position` could not be decoded.
+    DecodeError {
+        position: u64,
+        error: FrameDecoderError,
+    },
+    /// The checksum of the frame starting at `position` does not match its content.
+    ChecksumMismatch {
+        position: u64,
+    },
+    EncodeError(EncodeError),
+    /// Reading the skippable frame starting at `position` failed.
+    ReadError {
+        position: u64,
+        error: Error,
+    },
+    WriteError(Error),
+}
+
+#[cfg(feature = "encoder")]
+impl fmt::Display for RecompressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DecodeError { position, error } => {
+                write!(
+                    f,
+                    "Failed to decode the frame at byte {}: {}",
+                    position, error
+                )
+            }
+            Self::ChecksumMismatch { position } => {
+                write!(f, "Checksum mismatch in the frame at byte {}", position)
+            }
+            Self::EncodeError(e) => write!(f, "Failed to encode a frame: {}", e),
+            Self::ReadError { position, error } => {
+                write!(
+                    f,
+                    "Error while reading the frame at byte {}: {}",
+                    position, error
+                )
+            }
+            Self::WriteError(e) => write!(f, "Error while writing frames: {}", e),
+        }
+    }
+}
+
+#[cfg(all(feature = "std", feature = "encoder"))]
+impl std::error::Error for RecompressError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecompressError::DecodeError { error, .. } => Some(error),
+            RecompressError::EncodeError(source) => Some(source),
+            RecompressError::ReadError { error, .. } => Some(error),
+            RecompressError::WriteError(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "encoder")]
+impl From<EncodeError> for RecompressError {
+    fn from(error: EncodeError) -> Self {
+        Self::EncodeError(error)
+    }
+}
+
+/// Decode every frame in `source` and encode it again with the given `level` into `target`.
+///
+/// Every frame is re-encoded into exactly one frame, so the frame boundaries stay the same, and
+/// skippable frames are copied unchanged. Only one frame is held in memory at a time.
+///
+/// Frames that need a dictionary cannot be recompressed and result in an error.
+/// Returns the number of zstd frames that were recompressed.
+#[cfg(feature = "encoder")]
+pub fn recompress(
+    source: impl Read,
+    mut target: impl Write,
+    level: CompressionLevel,
+) -> Result<usize, RecompressError> {
+    let mut source = Counting {
+        inner: source,
+        count: 0,
+    };
+    let mut frame_dec = FrameDecoder::new();
+    let mut frames = 0;
+    loop {
+        let position = source.count;
+        let decode_error = |error| RecompressError::DecodeError { position, error };
+        match frame_dec.reset(&mut source) {
+            Ok(()) => {}
+            Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                magic_number,
+                length,
+            })) => {
+                let mut header = [0u8; 8];
+                header[..4].copy_from_slice(&magic_number.to_le_bytes());
+                header[4..].copy_from_slice(&length.to_le_bytes());
+                target
+                    .write_all(&header)
+                    .map_err(RecompressError::WriteError)?;
+                let mut tee = Tee::new(&mut source, Some(&mut target));
+                let result = skip_bytes(&mut tee, u64::from(length));
+                if let Some(error) = tee.write_error {
+                    return Err(RecompressError::WriteError(error));
+                }
+                result.map_err(|error| RecompressError::ReadError { position, error })?;
+                continue;
+            }
+            // The source ended after the last frame
+            Err(FrameDecoderError::ReadFrameHeaderError(
+                ReadFrameHeaderError::MagicNumberReadError(e),
+            )) if source.count == position && e.kind() == ErrorKind::UnexpectedEof => {
+                return Ok(frames);
+            }
+            Err(e) => return Err(decode_error(e)),
+        }
+
+        frame_dec
+            .decode_blocks(&mut source, BlockDecodingStrategy::All)
+            .map_err(decode_error)?;
+        // The checksum is calculated while the data is collected, into a Vec that does not grow
+        let mut decoded = Vec::new();
+        decoded
+            .try_room(frame_dec.can_collect())
+            .map_err(|err| decode_error(err.into()))?;
+        frame_dec
+            .collect_to_writer(&mut decoded)
+            .map_err(|err| decode_error(FrameDecoderError::FailedToDrainDecodebuffer(err)))?;
+        #[cfg(feature = "hash")]
+        if let Some(checksum) = frame_dec.get_checksum_from_data() {
+            if frame_dec.get_calculated_checksum() != Some(checksum) {
+                return Err(RecompressError::ChecksumMismatch { position });
+            }
+        }
+        FrameCompressor::new(decoded.as_slice(), &mut target, level).compress()?;
+        frames += 1;
+    }
+}
+
+/// New values for fields of a frame header, see [patch_frame_header] and [rewrite_frame_header].
+///
+/// Fields that are `None` are left as they are.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeaderPatch {
+    /// The size of the decompressed content of the frame.
+    pub content_size: Option<u64>,
+    /// The lower 32 bits of the XXH64 hash of the decompressed content.
+    pub checksum: Option<u32>,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum PatchHeaderError {
+    /// The data does not start with a zstd frame, or the frame is malformed.
+    InvalidFrame(InspectFrameError),
+    /// The data starts with a skippable frame, which has no header to patch.
+    SkippableFrame,
+    /// The header has no `Frame_Content_Size` field that could be overwritten.
+    NoContentSizeField,
+    /// The `Frame_Content_Size` field is too small for the new value.
+    ContentSizeDoesNotFit {
+        content_size: u64,
+        field_size: usize,
+    },
+    /// The frame has no checksum that could be overwritten, adding one needs 4 more bytes.
+    NoChecksumField,
+    ReadError(Error),
+    WriteError(Error),
+}
+
+impl fmt::Display for PatchHeaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFrame(e) => write!(f, "Invalid frame: {}", e),
+            Self::SkippableFrame => write!(f, "Skippable frames have no header to patch"),
+            Self::NoContentSizeField => write!(
+                f,
+                "The frame header has no Frame_Content_Size field, the header needs to be rewritten"
+            ),
+            Self::ContentSizeDoesNotFit {
+                content_size,
+                field_size,
+            } => write!(
+                f,
+                "A content size of {} does not fit into the {} byte Frame_Content_Size field, the header needs to be rewritten",
+                content_size, field_size
+            ),
+            Self::NoChecksumField => write!(
+                f,
+                "The frame has no checksum, the frame needs to be rewritten to add one"
+            ),
+            Self::ReadError(e) => write!(f, "Error while reading the frame: {}", e),
+            Self::WriteError(e) => write!(f, "Error while writing the frame: {}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PatchHeaderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PatchHeaderError::InvalidFrame(source) => Some(source),
+            PatchHeaderError::ReadError(source) => Some(source),
+            PatchHeaderError::WriteError(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Overwrite the content size and the checksum of the complete frame at the start of `frame` in place.
+///
+/// This only works if the frame already has fields of the right size: a `Frame_Content_Size` field
+/// that is large enough for the new value and a checksum. Otherwise an error explains what is
+/// missing, and [rewrite_frame_header] can be used instead, which copies the frame.
+/// Nothing is changed if an error is returned.
+///
+/// Single segment frames have no window descriptor, their content size is the window size too.
+pub fn patch_frame_header(frame: &mut [u8], patch: &HeaderPatch) -> Result<(), PatchHeaderError> {
+    let (start, header) = read_zstd_frame_start(&*frame)?;
+    let (frame_header, header_size) = start;
+    let descriptor = &frame_header.descriptor;
+    let has_checksum = descriptor.content_checksum_flag();
+    // The descriptor was already validated while reading the header
+    let fcs_len = descriptor.frame_content_size_bytes().unwrap_or(0) as usize;
+    let fcs_field = (header.len() - fcs_len)..header.len();
+
+    let new_fcs = match patch.content_size {
+        Some(content_size) => {
+            if fcs_len == 0 {
+                return Err(PatchHeaderError::NoContentSizeField);
+            }
+            let fits = match fcs_len {
+                1 => content_size <= 255,
+                2 => (256..=65791).contains(&content_size),
+                4 => content_size <= u64::from(u32::MAX),
+                _ => true,
+            };
+            if !fits {
+                return Err(PatchHeaderError::ContentSizeDoesNotFit {
+                    content_size,
+                    field_size: fcs_len,
+                });
+            }
+            let value = if fcs_len == 2 {
+                content_size - 256
+            } else {
+                content_size
+            };
+            Some(value.to_le_bytes())
+        }
+        None => None,
+    };
+    if patch.checksum.is_some() && !has_checksum {
+        return Err(PatchHeaderError::NoChecksumField);
+    }
+    let info = inspect_frame_content(frame_header, header_size, &frame[header.len()..])
+        .map_err(PatchHeaderError::InvalidFrame)?;
+
+    if let Some(value) = new_fcs {
+        frame[fcs_field].copy_from_slice(&value[..fcs_len]);
+    }
+    if let Some(checksum) = patch.checksum {
+        let end = info.compressed_size as usize;
+        frame[end - 4..end].copy_from_slice(&checksum.to_le_bytes());
+    }
+    Ok(())
+}
+
+/// Copy the frame at the start of `source` into `target` with a new header, adding the content
+/// size and the checksum if they are missing.
+///
+/// The blocks are copied unchanged, so this is much cheaper than compressing the data again.
+/// Unlike [patch_frame_header] this works for all frames, because the size of the header may change.
+/// Returns the size of the new frame.
+pub fn rewrite_frame_header(
+    mut source: impl Read,
+    mut target: impl Write,
+    patch: &HeaderPatch,
+) -> Result<u64, PatchHeaderError> {
+    let ((mut frame_header, header_size), header) = read_zstd_frame_start(&mut source)?;
+    let descriptor = frame_header.descriptor.0;
+    let had_checksum = frame_header.descriptor.content_checksum_flag();
+    let old_fcs_len = frame_header
+        .descriptor
+        .frame_content_size_bytes()
+        .unwrap_or(0) as usize;
+    let single_segment = frame_header.descriptor.single_segment_flag();
+
+    // Magic number, window descriptor and dictionary ID stay the same
+    let mut new_header = [0u8; MAX_FRAME_HEADER_SIZE];
+    let mut len = header.len() - old_fcs_len;
+    new_header[..len].copy_from_slice(&header[..len]);
+    let mut new_descriptor = descriptor;
+    if patch.checksum.is_some() {
+        new_descriptor |= 0b100;
+    }
+    if let Some(content_size) = patch.content_size {
+        let (fcs, fcs_len) = minify_val_fcs(content_size, single_segment);
+        let flag = match fcs_len {
+            1 => 0,
+            2 => 1,
+            4 => 2,
+            _ => 3,
+        };
+        new_descriptor = (new_descriptor & 0b0011_1111) | (flag << 6);
+        new_header[len..len + fcs_len].copy_from_slice(&fcs[..fcs_len]);
+        len += fcs_len;
+    } else {
+        new_header[len..len + old_fcs_len].copy_from_slice(&header[len..]);
+        len += old_fcs_len;
+    }
+    new_header[4] = new_descriptor;
+    let new_header = &new_header[..len];
+    target
+        .write_all(new_header)
+        .map_err(PatchHeaderError::WriteError)?;
+
+    // Copy the blocks, but not the checksum, which is handled below
+    frame_header.descriptor.0 &= !0b100;
+    let mut tee = Tee::new(&mut source, Some(&mut target));
+    let result = inspect_frame_content(frame_header, header_size, &mut tee);
+    if let Err(CopyError::Write(e)) = tee.check_write() {
+        return Err(PatchHeaderError::WriteError(e));
+    }
+    let info = result.map_err(PatchHeaderError::InvalidFrame)?;
+    let mut frame_size = info.compressed_size - u64::from(header_size) + new_header.len() as u64;
+
+    let mut old_checksum = None;
+    if had_checksum {
+        let mut checksum = [0u8; 4];
+        source
+            .read_exact(&mut checksum)
+            .map_err(|e| PatchHeaderError::InvalidFrame(InspectFrameError::ChecksumReadError(e)))?;
+        old_checksum = Some(u32::from_le_bytes(checksum));
+    }
+    if let Some(checksum) = patch.checksum.or(old_checksum) {
+        target
+            .write_all(&checksum.to_le_bytes())
+            .map_err(PatchHeaderError::WriteError)?;
+        frame_size += 4;
+    }
+    Ok(frame_size)
+}
+
+/// Read the header of the zstd frame at the start of `source`, returning the parsed header and its bytes.
+fn read_zstd_frame_start(
+    source: impl Read,
+) -> Result<((FrameHeader, u8), Vec<u8>), PatchHeaderError> {
+    let mut header = Vec::new();
+    match read_frame_start(source, &mut header) {
+        Ok(Some(FrameStart::Zstd {
+            header: frame_header,
+            header_size,
+        })) => Ok(((frame_header, header_size), header)),
+        Ok(Some(FrameStart::Skippable { .. })) => Err(PatchHeaderError::SkippableFrame),
+        Ok(None) => Err(PatchHeaderError::InvalidFrame(
+            ReadFrameHeaderError::MagicNumberReadError(Error::from(ErrorKind::UnexpectedEof))
+                .into(),
+        )),
+        Err(CopyError::Invalid(e)) => Err(PatchHeaderError::InvalidFrame(e)),
+        Err(CopyError::Read(e)) => Err(PatchHeaderError::ReadError(e)),
+        Err(CopyError::Write(e)) => Err(PatchHeaderError::WriteError(e)),
+    }
+}
+
+/// The beginning of a frame, as read by [read_frame_start].
+enum FrameStart {
+    Zstd {
+        header: FrameHeader,
+        header_size: u8,
+    },
+    Skippable {
+        magic_number: u32,
+        length: u32,
+    },
+}
+
+impl FrameStart {
+    fn kind(&self) -> FrameKind {
+        match self {
+            FrameStart::Zstd { .. } => FrameKind::Zstd,
+            FrameStart::Skippable { magic_number, .. } => FrameKind::Skippable {
+                magic_number: *magic_number,
+            },
+        }
+    }
+}
+
+enum CopyError {
+    Invalid(InspectFrameError),
+    Read(Error),
+    Write(Error),
+}
+
+/// Read the header of the next frame into `header`.
+///
+/// Returns `None` if the source ended right before the frame.
+fn read_frame_start(
+    mut source: impl Read,
+    header: &mut Vec<u8>,
+) -> Result<Option<FrameStart>, CopyError> {
+    header.clear();
+    let mut header_reader = Tee::new(&mut source, Some(&mut *header));
+    let result = read_frame_header(&mut header_reader);
+    header_reader.check_write()?;
+    match result {
+        Ok((frame, header_size)) => Ok(Some(FrameStart::Zstd {
+            header: frame.header,
+            header_size,
+        })),
+        Err(ReadFrameHeaderError::SkipFrame {
+            magic_number,
+            length,
+        }) => Ok(Some(FrameStart::Skippable {
+            magic_number,
+            length,
+        })),
+        // The source ended after the last frame
+        Err(ReadFrameHeaderError::MagicNumberReadError(e))
+            if header.is_empty() && e.kind() == ErrorKind::UnexpectedEof =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(CopyError::Invalid(e.into())),
+    }
+}
+
+/// Copy the frame that starts with `header` into `target`, or skip over it if there is no target.
+fn copy_frame(
+    mut source: impl Read,
+    header: &[u8],
+    start: FrameStart,
+    target: Option<impl Write>,
+) -> Result<(), CopyError> {
+    let mut tee = Tee::new(&mut source, target);
+    if let Some(target) = &mut tee.target {
+        target.write_all(header).map_err(CopyError::Write)?;
+    }
+    let result = match start {
+        FrameStart::Zstd {
+            header,
+            header_size,
+        } => inspect_frame_content(header, header_size, &mut tee)
+            .map(|_| ())
+            .map_err(CopyError::Invalid),
+        FrameStart::Skippable { length, .. } => {
+            skip_bytes(&mut tee, u64::from(length)).map_err(CopyError::Read)
+        }
+    };
+    tee.check_write()?;
+    result
+}
+
+/// Read and discard `length` bytes.
+fn skip_bytes(mut source: impl Read, mut length: u64) -> Result<(), Error> {
+    let mut buf = [0u8; crate::STACK_BUFFER_SIZE];
+    while length > 0 {
+        let chunk = u64::min(length, buf.len() as u64) as usize;
+        source.read_exact(&mut buf[..chunk])?;
+        length -= chunk as u64;
+    }
+    Ok(())
+}
+
+/// Writes everything that is read from `source` into `target`, if there is a target.
+///
+/// Errors of the target are kept apart, so they are not mistaken for errors in the source.
+struct Tee<R: Read, W: Write> {
+    source: R,
+    target: Option<W>,
+    write_error: Option<Error>,
+}
+
+impl<R: Read, W: Write> Tee<R, W> {
+    fn new(source: R, target: Option<W>) -> Self {
+        Tee {
+            source,
+            target,
+            write_error: None,
+        }
+    }
+
+    /// Report the error the target returned, if there was one.
+    fn check_write(self) -> Result<(), CopyError> {
+        match self.write_error {
+            Some(e) => Err(CopyError::Write(e)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R: Read, W: Write> Read for Tee<R, W> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = self.source.read(buf)?;
+        if let Some(target) = &mut self.target {
+            if let Err(e) = target.write_all(&buf[..read]) {
+                self.write_error = Some(e);
+                return Err(Error::from(ErrorKind::Other));
+            }
+        }
+        Ok(read)
+    }
+}
+
+/// Counts the bytes read from `inner`.
+struct Counting<R: Read> {
+    inner: R,
+    count: u64,
+}
+
+impl<R: Read> Read for Counting<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = self.inner.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::{
+        concat_frames, frame_ranges, recompress, split_frames, ConcatError, FrameKind,
+        RecompressError, SkippableFrames, SplitError,
+    };
+    use crate::encoding::CompressionLevel;
+    use crate::frame_decoder::FrameDecoder;
+    use crate::skippable_frame::SkippableFrame;
+    use alloc::rc::Rc;
+    use alloc::vec;
+    use alloc::vec::Vec;
+    use core::cell::RefCell;
+
+    #[test]
+    fn concat() {
+        let frame1 = include_bytes!("../decodecorpus_files/z000089.zst");
+        let frame2 = include_bytes!("../decodecorpus_files/z000088.zst");
+        let mut skippable = Vec::new();
+        SkippableFrame::new(3, vec![1, 2, 3])
+            .unwrap()
+            .write(&mut skippable)
+            .unwrap();
+        let mut source2 = skippable.clone();
+        source2.extend_from_slice(frame2);
+        source2.extend_from_slice(frame1);
+
+        let mut expected = Vec::new();
+        let mut decoder = FrameDecoder::new();
+        for frame in [&frame1[..], frame2, frame1] {
+            let mut decoded = Vec::with_capacity(1 << 20);
+            decoder.decode_all_to_vec(frame, &mut decoded).unwrap();
+            expected.extend(decoded);
+        }
+
+        let sources = [&frame1[..], &[], &source2];
+        let mut output = Vec::new();
+        let frames = concat_frames(sources, &mut output, SkippableFrames::Preserve).unwrap();
+        assert_eq!(frames, 3);
+        assert_eq!(output.len(), frame1.len() + source2.len());
+        let mut decoded = Vec::with_capacity(expected.len());
+        decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+        assert_eq!(decoded, expected);
+
+        let mut stripped = Vec::new();
+        concat_frames(sources, &mut stripped, SkippableFrames::Strip).unwrap();
+        assert_eq!(stripped.len(), output.len() - skippable.len());
+        let mut decoded = Vec::with_capacity(expected.len());
+        decoder.decode_all_to_vec(&stripped, &mut decoded).unwrap();
+        assert_eq!(decoded, expected);
+
+        // truncated frames and garbage are rejected
+        for source in [&frame1[..frame1.len() - 1], &[0xAB; 10], &skippable[..9]] {
+            let sources = [&frame1[..], source];
+            let result = concat_frames(sources, &mut Vec::new(), SkippableFrames::Preserve);
+            assert!(
+                matches!(
+                    result,
+                    Err(ConcatError::InvalidFrame { source: 1, .. }
+                        | ConcatError::ReadError { source: 1, .. })
+                ),
+                "{:?}",
+                result
+            );
+        }
+    }
+
+    #[test]
+    fn split() {
+        let frame1 = include_bytes!("../decodecorpus_files/z000089.zst");
+        let frame2 = include_bytes!("../decodecorpus_files/z000088.zst");
+        let mut skippable = Vec::new();
+        SkippableFrame::new(3, vec![1, 2, 3])
+            .unwrap()
+            .write(&mut skippable)
+            .unwrap();
+        let mut input = Vec::new();
+        input.extend_from_slice(frame1);
+        input.extend_from_slice(&skippable);
+        input.extend_from_slice(frame2);
+
+        let a = frame1.len();
+        let b = a + skippable.len();
+        let expected = [
+            (FrameKind::Zstd, 0..a),
+            (
+                FrameKind::Skippable {
+                    magic_number: 0x184D2A53,
+                },
+                a..b,
+            ),
+            (FrameKind::Zstd, b..input.len()),
+        ];
+        assert_eq!(frame_ranges(&input).unwrap(), expected);
+        assert!(frame_ranges(&[]).unwrap().is_empty());
+
+        // every frame goes into its own buffer
+        struct Part(Rc<RefCell<Vec<u8>>>);
+        impl crate::io::Write for Part {
+            fn write(&mut self, buf: &[u8]) -> Result<usize, crate::io::Error> {
+                self.0.borrow_mut().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> Result<(), crate::io::Error> {
+                Ok(())
+            }
+        }
+        let mut parts = Vec::new();
+        let frames = split_frames(input.as_slice(), |kind, position| {
+            let part = Rc::new(RefCell::new(Vec::new()));
+            parts.push((kind, position, part.clone()));
+            Part(part)
+        });
+        assert_eq!(frames.unwrap(), 3);
+        for ((kind, position, part), (expected_kind, range)) in parts.iter().zip(&expected) {
+            assert_eq!(kind, expected_kind);
+            assert_eq!(*position, range.start as u64);
+            assert_eq!(part.borrow().as_slice(), &input[range.clone()]);
+        }
+
+        let mut truncated = input.clone();
+        truncated.pop();
+        assert!(matches!(
+            frame_ranges(&truncated),
+            Err(SplitError::InvalidFrame { position, .. }) if position == b as u64
+        ));
+    }
+
+    #[test]
+    fn recompress_frames() {
+        let frame1 = include_bytes!("../decodecorpus_files/z000089.zst");
+        let frame2 = include_bytes!("../decodecorpus_files/z000088.zst");
+        let mut skippable = Vec::new();
+        SkippableFrame::new(3, vec![1, 2, 3])
+            .unwrap()
+            .write(&mut skippable)
+            .unwrap();
+        let mut input = Vec::new();
+        input.extend_from_slice(frame1);
+        input.extend_from_slice(&skippable);
+        input.extend_from_slice(frame2);
+
+        let mut decoder = FrameDecoder::new();
+        let mut expected = Vec::with_capacity(1 << 20);
+        decoder.decode_all_to_vec(&input, &mut expected).unwrap();
+
+        for level in [CompressionLevel::Uncompressed, CompressionLevel::Fastest] {
+            let mut output = Vec::new();
+            assert_eq!(recompress(input.as_slice(), &mut output, level).unwrap(), 2);
+
+            let ranges = frame_ranges(&output).unwrap();
+            let kinds: Vec<FrameKind> = ranges.iter().map(|(kind, _)| *kind).collect();
+            assert_eq!(
+                kinds,
+                [
+                    FrameKind::Zstd,
+                    FrameKind::Skippable {
+                        magic_number: 0x184D2A53
+                    },
+                    FrameKind::Zstd
+                ]
+            );
+            assert_eq!(&output[ranges[1].1.clone()], skippable.as_slice());
+
+            let mut decoded = Vec::with_capacity(expected.len());
+            decoder.decode_all_to_vec(&output, &mut decoded).unwrap();
+            assert_eq!(decoded, expected);
+        }
+
+        let result = recompress(
+            &input[..input.len() - 1],
+            &mut Vec::new(),
+            CompressionLevel::Fastest,
+        );
+        assert!(matches!(
+            result,
+            Err(RecompressError::DecodeError { position, .. }) if position == (frame1.len() + skippable.len()) as u64
+        ));
+    }
+
+    #[cfg(feature = "hash")]
+    #[test]
+    fn patch_header() {
+        use super::{patch_frame_header, rewrite_frame_header, HeaderPatch, PatchHeaderError};
+        use crate::conformance::{check_frame, Rule};
+        use crate::encoding::{EncodeOptions, FrameCompressor};
+
+        let data: Vec<u8> = (0..100_000u32)
+            .map(|i| (i % 17) as u8 ^ (i >> 9) as u8)
+            .collect();
+        let mut decoder = FrameDecoder::new();
+        let mut decoded = Vec::with_capacity(data.len());
+        let mut with_checksum = Vec::new();
+        FrameCompressor::with_options(
+            data.as_slice(),
+            &mut with_checksum,
+            EncodeOptions::cli_default(),
+        )
+        .compress()
+        .unwrap();
+        decoder
+            .decode_all_to_vec(&with_checksum, &mut decoded)
+            .unwrap();
+        let checksum = decoder.get_checksum_from_data().unwrap();
+        let correct = HeaderPatch {
+            content_size: Some(data.len() as u64),
+            checksum: Some(checksum),
+        };
+
+        // Frames without the fields can only be rewritten
+        let mut plain = Vec::new();
+        FrameCompressor::new(data.as_slice(), &mut plain, CompressionLevel::Fastest)
+            .compress()
+            .unwrap();
+        let original = plain.clone();
+        assert!(matches!(
+            patch_frame_header(&mut plain, &correct),
+            Err(PatchHeaderError::NoContentSizeField)
+        ));
+        assert!(matches!(
+            patch_frame_header(
+                &mut plain,
+                &HeaderPatch {
+                    checksum: Some(checksum),
+                    ..HeaderPatch::default()
+                }
+            ),
+            Err(PatchHeaderError::NoChecksumField)
+        ));
+        assert_eq!(plain, original);
+
+        let mut rewritten = Vec::new();
+        let size = rewrite_frame_header(plain.as_slice(), &mut rewritten, &correct).unwrap();
+        assert_eq!(size, rewritten.len() as u64);
+        assert_eq!(rewritten.len(), plain.len() + 8);
+        let report = check_frame(&rewritten);
+        assert!(report.is_conforming(), "{:?}", report.violations);
+        decoder.reset(rewritten.as_slice()).unwrap();
+        assert_eq!(decoder.content_size(), data.len() as u64);
+
+        // Frames that have the fields can be patched in place
+        let wrong = HeaderPatch {
+            content_size: Some(12345),
+            checksum: Some(!checksum),
+        };
+        patch_frame_header(&mut with_checksum, &wrong).unwrap();
+        let rules: Vec<Rule> = check_frame(&with_checksum)
+            .violations
+            .iter()
+            .map(|violation| violation.rule)
+            .collect();
+        // The frame is single segment, so the window shrinks with the content size
+        assert_eq!(
+            rules,
+            [
+                Rule::BlockMaximumSize,
+                Rule::FrameContentSize,
+                Rule::ContentChecksum
+            ]
+        );
+        patch_frame_header(&mut with_checksum, &correct).unwrap();
+        assert!(check_frame(&with_checksum).is_conforming());
+        assert!(matches!(
+            patch_frame_header(
+                &mut with_checksum,
+                &HeaderPatch {
+                    content_size: Some(u64::MAX),
+                    checksum: None
+                }
+            ),
+            Err(PatchHeaderError::ContentSizeDoesNotFit { field_size: 4, .. })
+        ));
+
+        let mut skippable = Vec::new();
+        SkippableFrame::new(0, vec![1, 2, 3])
+            .unwrap()
+            .write(&mut skippable)
+            .unwrap();
+        assert!(matches!(
+            rewrite_frame_header(skippable.as_slice(), Vec::new(), &correct),
+            Err(PatchHeaderError::SkippableFrame)
+        ));
+    }
+}
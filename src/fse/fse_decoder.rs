@@ -1,18 +1,23 @@
 use crate::decoding::bit_reader::BitReader;
 use crate::decoding::bit_reader_reverse::{BitReaderReversed, GetBitsError};
+use crate::decoding::table_vec::TableVec;
+use crate::fallible::{try_to_vec, AllocError};
 use alloc::vec::Vec;
 
 /// FSE decoding involves a decoding table that describes the probabilities of
 /// all literals from 0 to the highest present one
 ///
 /// <https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#fse-table-description>
-#[derive(Debug)]
+///
+/// The [Debug] output lists the probabilities and, for every state, the symbol it decodes to and
+/// the states that can follow it. The [alternate](core::fmt::Formatter::alternate) format prints
+/// one entry per line.
 pub struct FSETable {
     /// The maximum symbol in the table (inclusive). Limits the probabilities length to max_symbol + 1.
     max_symbol: u8,
     /// The actual table containing the decoded symbol and the compression data
     /// connected to that symbol.
-    pub decode: Vec<Entry>, //used to decode symbols, and calculate the next state
+    pub decode: TableVec<Entry, { 1 << MAX_TABLE_LOG }>, //used to decode symbols, and calculate the next state
     /// The size of the table is stored in logarithm base 2 format,
     /// with the **size of the table** being equal to `(1 << accuracy_log)`.
     /// This value is used so that the decoder knows how many bits to read from the bitstream.
@@ -27,10 +32,10 @@ pub struct FSETable {
     ///
     /// If a symbol probability is set to `-1`, it means that the probability of a symbol
     /// occurring in the data is less than one.
-    pub symbol_probabilities: Vec<i32>, //used while building the decode Vector
+    pub symbol_probabilities: TableVec<i32, 256>, //used while building the decode Vector
     /// The number of times each symbol occurs (The first entry being 0x0, the second being 0x1) and so on
     /// up until the highest possible symbol (255).
-    symbol_counter: Vec<u32>,
+    symbol_counter: TableVec<u32, 256>,
 }
 
 #[derive(Debug)]
@@ -50,6 +55,10 @@ pub enum FSETableError {
     TooManySymbols {
         got: usize,
     },
+    /// There is not enough memory for the table.
+    OutOfMemory {
+        size: usize,
+    },
 }
 
 #[cfg(feature = "std")]
@@ -93,16 +102,26 @@ impl core::fmt::Display for FSETableError {
                     got,
                 )
             }
+            FSETableError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for the FSE table", size)
+            }
         }
     }
 }
 
+impl From<AllocError> for FSETableError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
 impl From<GetBitsError> for FSETableError {
     fn from(val: GetBitsError) -> Self {
         Self::GetBitsError(val)
     }
 }
 
+/// Decodes a bitstream of FSE encoded symbols, using an [FSETable].
 pub struct FSEDecoder<'table> {
     /// An FSE state value represents an index in the FSE table.
     pub state: Entry,
@@ -144,8 +163,36 @@ impl From<GetBitsError> for FSEDecoderError {
     }
 }
 
+impl core::fmt::Debug for FSETable {
+    // Only for diagnostics, see the no-panic section of the Readme
+    #[allow(clippy::disallowed_methods)]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let probabilities: Vec<(usize, i32)> = self
+            .symbol_probabilities
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, probability)| *probability != 0)
+            .collect();
+        let states: Vec<super::Transition> = self
+            .decode
+            .iter()
+            .map(|entry| super::Transition {
+                symbol: entry.symbol,
+                num_bits: entry.num_bits,
+                baseline: entry.base_line,
+            })
+            .collect();
+        f.debug_struct("FSETable")
+            .field("accuracy_log", &self.accuracy_log)
+            .field("probabilities", &super::Probabilities(&probabilities))
+            .field("states", &super::States(&states))
+            .finish()
+    }
+}
+
 /// A single entry in an FSE table.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct Entry {
     /// This value is used as an offset value, and it is added
     /// to a value read from the stream to determine the next state value.
@@ -160,8 +207,21 @@ pub struct Entry {
 /// `Accuracy_Log`
 const ACC_LOG_OFFSET: u8 = 5;
 
+/// The accuracy log of the largest tables in a Zstandard frame, the ones of literal and match
+/// lengths. With the `small-target` feature, tables have room for this size and reject larger ones.
+pub(crate) const MAX_TABLE_LOG: u8 = 9;
+
+/// The largest accuracy log a table can be built with, `max_log` or less with the `small-target`
+/// feature.
+fn limit_log(max_log: u8) -> u8 {
+    if cfg!(feature = "small-target") {
+        max_log.min(MAX_TABLE_LOG)
+    } else {
+        max_log
+    }
+}
+
 fn highest_bit_set(x: u32) -> u32 {
-    assert!(x > 0);
     u32::BITS - x.leading_zeros()
 }
 
@@ -212,21 +272,23 @@ impl FSETable {
     pub fn new(max_symbol: u8) -> FSETable {
         FSETable {
             max_symbol,
-            symbol_probabilities: Vec::with_capacity(256), //will never be more than 256 symbols because u8
-            symbol_counter: Vec::with_capacity(256), //will never be more than 256 symbols because u8
-            decode: Vec::new(),                      //depending on acc_log.
+            symbol_probabilities: TableVec::new(), //will never be more than 256 symbols because u8
+            symbol_counter: TableVec::new(),       //will never be more than 256 symbols because u8
+            decode: TableVec::new(),               //depending on acc_log.
             accuracy_log: 0,
         }
     }
 
     /// Reset `self` and update `self`'s state to mirror the provided table.
-    pub fn reinit_from(&mut self, other: &Self) {
+    pub fn reinit_from(&mut self, other: &Self) -> Result<(), FSETableError> {
         self.reset();
-        self.symbol_counter.extend_from_slice(&other.symbol_counter);
+        self.symbol_counter
+            .try_extend_from_slice(&other.symbol_counter)?;
         self.symbol_probabilities
-            .extend_from_slice(&other.symbol_probabilities);
-        self.decode.extend_from_slice(&other.decode);
+            .try_extend_from_slice(&other.symbol_probabilities)?;
+        self.decode.try_extend_from_slice(&other.decode)?;
         self.accuracy_log = other.accuracy_log;
+        Ok(())
     }
 
     /// Empty the table and clear all internal state.
@@ -243,6 +305,11 @@ impl FSETable {
 
         let bytes_read = self.read_probabilities(source, max_log)?;
         self.build_decoding_table()?;
+        trace_event!(
+            accuracy_log = self.accuracy_log,
+            bytes = bytes_read,
+            "built FSE table"
+        );
 
         Ok(bytes_read)
     }
@@ -256,13 +323,24 @@ impl FSETable {
         if acc_log == 0 {
             return Err(FSETableError::AccLogIsZero);
         }
-        self.symbol_probabilities = probs.to_vec();
+        if acc_log > limit_log(acc_log) {
+            return Err(FSETableError::AccLogTooBig {
+                got: acc_log,
+                max: MAX_TABLE_LOG,
+            });
+        }
+        if probs.len() > self.max_symbol as usize + 1 {
+            return Err(FSETableError::TooManySymbols { got: probs.len() });
+        }
+        self.symbol_probabilities.clear();
+        self.symbol_probabilities.try_extend_from_slice(probs)?;
         self.accuracy_log = acc_log;
         self.build_decoding_table()
     }
 
     /// Build the actual decoding table after probabilities have been read into the table.
     /// After this function is called, the decoding process can begin.
+    #[allow(clippy::disallowed_macros)]
     fn build_decoding_table(&mut self) -> Result<(), FSETableError> {
         if self.symbol_probabilities.len() > self.max_symbol as usize + 1 {
             return Err(FSETableError::TooManySymbols {
@@ -273,18 +351,15 @@ impl FSETable {
         self.decode.clear();
 
         let table_size = 1 << self.accuracy_log;
-        if self.decode.len() < table_size {
-            self.decode.reserve(table_size - self.decode.len());
-        }
         //fill with dummy entries
-        self.decode.resize(
+        self.decode.try_resize(
             table_size,
             Entry {
                 base_line: 0,
                 num_bits: 0,
                 symbol: 0,
             },
-        );
+        )?;
 
         let mut negative_idx = table_size; //will point to the highest index with is already occupied by a negative-probability-symbol
 
@@ -324,7 +399,7 @@ impl FSETable {
         // baselines and num_bits can only be calculated when all symbols have been spread
         self.symbol_counter.clear();
         self.symbol_counter
-            .resize(self.symbol_probabilities.len(), 0);
+            .try_resize(self.symbol_probabilities.len(), 0)?;
         for idx in 0..negative_idx {
             let entry = &mut self.decode[idx];
             let symbol = entry.symbol;
@@ -335,7 +410,7 @@ impl FSETable {
 
             //println!("symbol: {:2}, table: {}, prob: {:3}, count: {:3}, bl: {:3}, nb: {:2}", symbol, table_size, prob, symbol_count, bl, nb);
 
-            assert!(nb <= self.accuracy_log);
+            debug_assert!(nb <= self.accuracy_log);
             self.symbol_counter[symbol as usize] += 1;
 
             entry.base_line = bl;
@@ -349,6 +424,7 @@ impl FSETable {
     fn read_probabilities(&mut self, source: &[u8], max_log: u8) -> Result<usize, FSETableError> {
         self.symbol_probabilities.clear(); //just clear, we will fill a probability for each entry anyways. No need to force new allocs here
 
+        let max_log = limit_log(max_log);
         let mut br = BitReader::new(source);
         self.accuracy_log = ACC_LOG_OFFSET + (br.get_bits(4)? as u8);
         if self.accuracy_log > max_log {
@@ -386,13 +462,13 @@ impl FSETable {
 
             let prob = (value as i32) - 1;
 
-            self.symbol_probabilities.push(prob);
+            self.check_symbols(1)?;
+            self.symbol_probabilities.try_push(prob)?;
             if prob != 0 {
                 if prob > 0 {
                     probability_counter += prob as u32;
                 } else {
-                    // probability -1 counts as 1
-                    assert!(prob == -1);
+                    // probability -1 counts as 1, no other value is below 0
                     probability_counter += 1;
                 }
             } else {
@@ -400,8 +476,9 @@ impl FSETable {
                 loop {
                     let skip_amount = br.get_bits(2)? as usize;
 
+                    self.check_symbols(skip_amount)?;
                     self.symbol_probabilities
-                        .resize(self.symbol_probabilities.len() + skip_amount, 0);
+                        .try_resize(self.symbol_probabilities.len() + skip_amount, 0)?;
                     if skip_amount != 3 {
                         break;
                     }
@@ -413,16 +490,11 @@ impl FSETable {
             return Err(FSETableError::ProbabilityCounterMismatch {
                 got: probability_counter,
                 expected_sum: probability_sum,
-                symbol_probabilities: self.symbol_probabilities.clone(),
-            });
-        }
-        if self.symbol_probabilities.len() > self.max_symbol as usize + 1 {
-            return Err(FSETableError::TooManySymbols {
-                got: self.symbol_probabilities.len(),
+                symbol_probabilities: try_to_vec(&self.symbol_probabilities).unwrap_or_default(),
             });
         }
 
-        let bytes_read = if br.bits_read() % 8 == 0 {
+        let bytes_read = if br.bits_read().is_multiple_of(8) {
             br.bits_read() / 8
         } else {
             (br.bits_read() / 8) + 1
@@ -430,6 +502,15 @@ impl FSETable {
 
         Ok(bytes_read)
     }
+
+    /// Fail if `additional` more probabilities would be more than the symbols of the table.
+    fn check_symbols(&self, additional: usize) -> Result<(), FSETableError> {
+        let got = self.symbol_probabilities.len() + additional;
+        if got > self.max_symbol as usize + 1 {
+            return Err(FSETableError::TooManySymbols { got });
+        }
+        Ok(())
+    }
 }
 
 //utility functions for building the decoding table from probabilities
@@ -1,20 +1,30 @@
+//! The FSE encoder, see the [parent module](super) for an example.
+
 use crate::encoding::bit_writer::BitWriter;
+use crate::fallible::{or_abort, try_collect, try_to_vec, AllocError, TryVec};
 use alloc::vec::Vec;
 
-pub(crate) struct FSEEncoder<'output, V: AsMut<Vec<u8>>> {
+/// Encodes symbols with an [FSETable] into a [BitWriter].
+pub struct FSEEncoder<'output, V: AsMut<Vec<u8>>> {
     pub(super) table: FSETable,
     writer: &'output mut BitWriter<V>,
 }
 
 impl<V: AsMut<Vec<u8>>> FSEEncoder<'_, V> {
+    /// Create an encoder that writes into `writer`, using `table`.
     pub fn new(table: FSETable, writer: &mut BitWriter<V>) -> FSEEncoder<'_, V> {
         FSEEncoder { table, writer }
     }
 
+    /// Destructures this object into the table, to be used for the next data.
     pub fn into_table(self) -> FSETable {
         self.table
     }
 
+    /// Write the table description, followed by a bitstream with the encoded `data`.
+    ///
+    /// The bitstream is read backwards by the decoder, which yields the symbols of `data` in order.
+    /// Panics if `data` is empty or contains symbols that have no probability in the table.
     pub fn encode(&mut self, data: &[u8]) {
         self.write_table();
 
@@ -36,6 +46,9 @@ impl<V: AsMut<Vec<u8>>> FSEEncoder<'_, V> {
         }
     }
 
+    /// Like [FSEEncoder::encode], but with two interleaved states as used for the weights of Huffman tables.
+    ///
+    /// Panics if `data` has less than four symbols.
     pub fn encode_interleaved(&mut self, data: &[u8]) {
         self.write_table();
 
@@ -93,7 +106,8 @@ impl<V: AsMut<Vec<u8>>> FSEEncoder<'_, V> {
         }
     }
 
-    fn write_table(&mut self) {
+    /// Write the table description, which ends byte aligned.
+    pub(crate) fn write_table(&mut self) {
         self.writer.write_bits(self.acc_log() - 5, 4);
         let mut probability_counter = 0usize;
         let probability_sum = 1 << self.acc_log();
@@ -137,12 +151,17 @@ impl<V: AsMut<Vec<u8>>> FSEEncoder<'_, V> {
         self.writer.write_bits(0u8, self.writer.misaligned());
     }
 
-    pub(super) fn acc_log(&self) -> u8 {
-        self.table.table_size.ilog2() as u8
+    /// The accuracy log of the table, which a decoder needs to read it back.
+    pub fn acc_log(&self) -> u8 {
+        self.table.accuracy_log()
     }
 }
 
-#[derive(Debug)]
+/// The encoding side of an FSE table, built with [build_table_from_data] or [build_table_from_probabilities].
+///
+/// The [Debug] output is the same as the one of the decoding [FSETable](crate::fse::FSETable) that
+/// is built from the same probabilities.
+#[derive(Clone)]
 pub struct FSETable {
     /// Indexed by symbol
     pub(super) states: [SymbolStates; 256],
@@ -150,6 +169,27 @@ pub struct FSETable {
 }
 
 impl FSETable {
+    /// Like `clone`, reporting when there is not enough memory for the copy.
+    pub(crate) fn try_clone(&self) -> Result<Self, AllocError> {
+        let mut states = core::array::from_fn::<SymbolStates, 256, _>(|_| SymbolStates {
+            states: Vec::new(),
+            probability: 0,
+        });
+        for (copy, states) in states.iter_mut().zip(&self.states) {
+            copy.states = try_to_vec(&states.states)?;
+            copy.probability = states.probability;
+        }
+        Ok(FSETable {
+            states,
+            table_size: self.table_size,
+        })
+    }
+
+    /// The table has `1 << accuracy_log` states.
+    pub fn accuracy_log(&self) -> u8 {
+        self.table_size.ilog2() as u8
+    }
+
     pub(crate) fn next_state(&self, symbol: u8, idx: usize) -> &State {
         let states = &self.states[symbol as usize];
         states.get(idx)
@@ -159,9 +199,66 @@ impl FSETable {
         let states = &self.states[symbol as usize];
         &states.states[0]
     }
+
+    /// Estimate how many bits encoding the symbols takes, when each symbol occurs as often as
+    /// `counts` says. Returns `None` if one of them has no probability in this table.
+    pub(crate) fn estimate_bits(&self, counts: &[usize]) -> Option<usize> {
+        let acc_log = usize::from(self.accuracy_log());
+        let mut bits = 0;
+        for (symbol, count) in counts.iter().copied().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            // A symbol with probability p out of 1 << acc_log takes about acc_log - log2(p) bits,
+            // "less than one" probabilities take acc_log bits
+            let probability = match self.states.get(symbol)?.probability {
+                0 => return None,
+                -1 => 1,
+                probability => probability as usize,
+            };
+            bits += count * ((acc_log << 8) - log2_fixed_point(probability));
+        }
+        Some(bits >> 8)
+    }
+}
+
+impl core::fmt::Debug for FSETable {
+    // Only for diagnostics, see the no-panic section of the Readme
+    #[allow(clippy::disallowed_methods)]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let probabilities: Vec<(usize, i32)> = self
+            .states
+            .iter()
+            .map(|states| states.probability)
+            .enumerate()
+            .filter(|(_, probability)| *probability != 0)
+            .collect();
+        // Each state of the encoder is one state of the decoder, where it comes from when decoding
+        let mut transitions = alloc::vec![super::Transition::default(); self.table_size];
+        for (symbol, states) in self.states.iter().enumerate() {
+            for state in &states.states {
+                transitions[state.index] = super::Transition {
+                    symbol: symbol as u8,
+                    num_bits: state.num_bits,
+                    baseline: state.baseline as u32,
+                };
+            }
+        }
+        f.debug_struct("FSETable")
+            .field("accuracy_log", &self.accuracy_log())
+            .field("probabilities", &super::Probabilities(&probabilities))
+            .field("states", &super::States(&transitions))
+            .finish()
+    }
+}
+
+/// `log2(x)` in 1/256 steps, interpolated linearly between powers of two.
+fn log2_fixed_point(x: usize) -> usize {
+    let log = x.ilog2() as usize;
+    (log << 8) + ((x << 8) >> log) - 256
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(super) struct SymbolStates {
     /// Sorted by baseline
     pub(super) states: Vec<State>,
@@ -170,15 +267,13 @@ pub(super) struct SymbolStates {
 
 impl SymbolStates {
     fn get(&self, idx: usize) -> &State {
-        // TODO we can do better, we can determin the correct state from the index with a bit of math
-        self.states
-            .iter()
-            .find(|state| state.contains(idx))
-            .unwrap()
+        // The states cover all indices, so the last one that starts at or before `idx` contains it
+        let pos = self.states.partition_point(|state| state.baseline <= idx);
+        &self.states[pos.saturating_sub(1)]
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub(crate) struct State {
     pub(crate) num_bits: u8,
     pub(crate) baseline: usize,
@@ -187,13 +282,23 @@ pub(crate) struct State {
     pub(crate) index: usize,
 }
 
-impl State {
-    fn contains(&self, idx: usize) -> bool {
-        self.baseline <= idx && self.last_index >= idx
-    }
+/// Build a table that fits the symbol distribution of `data`, with an accuracy log of at most `max_log`.
+///
+/// With `avoid_0_numbit`, no symbol gets more than half of the states, which is required for
+/// the weights of Huffman tables.
+///
+/// Aborts if there is not enough memory for the table, like the collections of `alloc` do.
+#[allow(clippy::disallowed_methods)]
+pub fn build_table_from_data(data: &[u8], max_log: u8, avoid_0_numbit: bool) -> FSETable {
+    or_abort(try_build_table_from_data(data, max_log, avoid_0_numbit))
 }
 
-pub fn build_table_from_data(data: &[u8], max_log: u8, avoid_0_numbit: bool) -> FSETable {
+/// Like [build_table_from_data], reporting when there is not enough memory for the table.
+pub(crate) fn try_build_table_from_data(
+    data: &[u8],
+    max_log: u8,
+    avoid_0_numbit: bool,
+) -> Result<FSETable, AllocError> {
     let mut counts = [0; 256];
     for x in data {
         counts[*x as usize] += 1;
@@ -201,7 +306,12 @@ pub fn build_table_from_data(data: &[u8], max_log: u8, avoid_0_numbit: bool) ->
     build_table_from_counts(&counts, max_log, avoid_0_numbit)
 }
 
-fn build_table_from_counts(counts: &[usize], max_log: u8, avoid_0_numbit: bool) -> FSETable {
+#[allow(clippy::disallowed_macros)]
+fn build_table_from_counts(
+    counts: &[usize],
+    max_log: u8,
+    avoid_0_numbit: bool,
+) -> Result<FSETable, AllocError> {
     let mut probs = [0; 256];
     let mut min_count = 0;
     for (idx, count) in counts.iter().copied().enumerate() {
@@ -219,10 +329,8 @@ fn build_table_from_counts(counts: &[usize], max_log: u8, avoid_0_numbit: bool)
         }
     }
 
-    // normalize probabilities to a 2^x
-    let sum = probs.iter().sum::<i32>();
-    assert!(sum > 0);
-    let sum = sum as usize;
+    // normalize probabilities to a 2^x, without any data symbol 0 gets all of them
+    let sum = probs.iter().sum::<i32>().max(1) as usize;
     let acc_log = (sum.ilog2() as u8 + 1).max(5);
     let acc_log = u8::min(acc_log, max_log);
 
@@ -230,34 +338,86 @@ fn build_table_from_counts(counts: &[usize], max_log: u8, avoid_0_numbit: bool)
         // just raise the maximum probability as much as possible
         // TODO is this optimal?
         let diff = (1 << acc_log) - sum;
-        let max = probs.iter_mut().max().unwrap();
-        *max += diff as i32;
+        if let Some(max) = probs.iter_mut().max() {
+            *max += diff as i32;
+        }
     } else {
         // decrease the smallest ones to 1 first
         let mut diff = sum - (1 << max_log);
         while diff > 0 {
-            let min = probs.iter_mut().filter(|prob| **prob > 1).min().unwrap();
+            let Some(min) = probs.iter_mut().filter(|prob| **prob > 1).min() else {
+                break;
+            };
             let decrease = usize::min(*min as usize - 1, diff);
             diff -= decrease;
             *min -= decrease as i32;
         }
     }
-    let max = probs.iter_mut().max().unwrap();
-    if avoid_0_numbit && *max > 1 << (acc_log - 1) {
+    let max = probs.iter_mut().max();
+    if let Some(max) = max.filter(|max| avoid_0_numbit && **max > 1 << (acc_log - 1)) {
         let redistribute = *max - (1 << (acc_log - 1));
         *max -= redistribute;
         let max = *max;
 
         // find first occurence of the second_max to avoid lifting the last zero
-        let second_max = *probs.iter_mut().filter(|x| **x != max).max().unwrap();
-        let second_max = probs.iter_mut().find(|x| **x == second_max).unwrap();
-        *second_max += redistribute;
-        assert!(*second_max <= max);
+        let second_max = probs.iter().copied().filter(|x| *x != max).max();
+        if let Some(second_max) =
+            second_max.and_then(|second_max| probs.iter_mut().find(|x| **x == second_max))
+        {
+            *second_max += redistribute;
+            debug_assert!(*second_max <= max);
+        }
+    }
+    try_build_table_from_probabilities(&probs, acc_log)
+}
+
+/// Scale the `counts` of the symbols to probabilities that add up to `1 << acc_log`, keeping
+/// every symbol that occurs at a probability of at least 1.
+///
+/// `acc_log` has to leave room for all symbols, i.e. `1 << acc_log` has to be at least the number
+/// of symbols with a count.
+pub(crate) fn normalize_counts(counts: &[usize], acc_log: u8) -> Result<Vec<i32>, AllocError> {
+    let target = 1usize << acc_log;
+    let total: usize = counts.iter().sum();
+    let mut probs: Vec<i32> = try_collect(counts.iter().map(|&count| match count {
+        0 => 0,
+        count => usize::max(1, count * target / total) as i32,
+    }))?;
+    let mut sum = probs.iter().sum::<i32>() as usize;
+    // Rounding leaves the sum a little off, which the most probable symbols absorb best
+    while sum != target {
+        let Some(max) = probs.iter_mut().max() else {
+            break;
+        };
+        if sum < target {
+            *max += (target - sum) as i32;
+            sum = target;
+        } else {
+            let decrease = usize::min(*max as usize - 1, sum - target);
+            *max -= decrease as i32;
+            sum -= decrease;
+        }
     }
-    build_table_from_probabilities(&probs, acc_log)
+    Ok(probs)
 }
 
-pub(super) fn build_table_from_probabilities(probs: &[i32], acc_log: u8) -> FSETable {
+/// Build a table from normalized probabilities, indexed by symbol.
+///
+/// The probabilities have to add up to `1 << acc_log`, where `-1` counts as `1` and marks a symbol
+/// with a "less than one" probability, as described in the
+/// [format](https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#fse-table-description).
+///
+/// Aborts if there is not enough memory for the table, like the collections of `alloc` do.
+#[allow(clippy::disallowed_methods)]
+pub fn build_table_from_probabilities(probs: &[i32], acc_log: u8) -> FSETable {
+    or_abort(try_build_table_from_probabilities(probs, acc_log))
+}
+
+/// Like [build_table_from_probabilities], reporting when there is not enough memory for the table.
+pub(crate) fn try_build_table_from_probabilities(
+    probs: &[i32],
+    acc_log: u8,
+) -> Result<FSETable, AllocError> {
     let mut states = core::array::from_fn::<SymbolStates, 256, _>(|_| SymbolStates {
         states: Vec::new(),
         probability: 0,
@@ -271,12 +431,12 @@ pub(super) fn build_table_from_probabilities(probs: &[i32], acc_log: u8) -> FSET
         .enumerate()
         .filter(|prob| prob.1 == -1)
     {
-        states[symbol].states.push(State {
+        states[symbol].states.try_push(State {
             num_bits: acc_log,
             baseline: 0,
             last_index: (1 << acc_log) - 1,
             index: negative_idx,
-        });
+        })?;
         states[symbol].probability = -1;
         negative_idx -= 1;
     }
@@ -289,20 +449,20 @@ pub(super) fn build_table_from_probabilities(probs: &[i32], acc_log: u8) -> FSET
         }
         states[symbol].probability = prob;
         let states = &mut states[symbol].states;
+        states.try_room(prob as usize)?;
         for _ in 0..prob {
-            states.push(State {
+            states.try_push(State {
                 num_bits: 0,
                 baseline: 0,
                 last_index: 0,
                 index: idx,
-            });
+            })?;
 
             idx = next_position(idx, 1 << acc_log);
             while idx > negative_idx {
                 idx = next_position(idx, 1 << acc_log);
             }
         }
-        assert_eq!(states.len(), prob as usize);
     }
 
     for (symbol, prob) in probs.iter().copied().enumerate() {
@@ -311,7 +471,8 @@ pub(super) fn build_table_from_probabilities(probs: &[i32], acc_log: u8) -> FSET
         }
         let prob = prob as u32;
         let state = &mut states[symbol];
-        state.states.sort_by(|l, r| l.index.cmp(&r.index));
+        // Indices and baselines are unique, so the order is the same as with a stable sort
+        state.states.sort_unstable_by_key(|l| l.index);
 
         let prob_log = if prob.is_power_of_two() {
             prob.ilog2()
@@ -339,13 +500,13 @@ pub(super) fn build_table_from_probabilities(probs: &[i32], acc_log: u8) -> FSET
                 baseline += 1 << num_bits;
             }
         }
-        state.states.sort_by(|l, r| l.baseline.cmp(&r.baseline));
+        state.states.sort_unstable_by_key(|l| l.baseline);
     }
 
-    FSETable {
+    Ok(FSETable {
         table_size: 1 << acc_log,
         states,
-    }
+    })
 }
 
 /// Calculate the position of the next entry of the table given the current
@@ -356,28 +517,28 @@ fn next_position(mut p: usize, table_size: usize) -> usize {
     p
 }
 
-const ML_DIST: &'static [i32] = &[
+const ML_DIST: &[i32] = &[
     1, 4, 3, 2, 2, 2, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
     1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1, -1, -1,
 ];
 
-const LL_DIST: &'static [i32] = &[
+const LL_DIST: &[i32] = &[
     4, 3, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 2, 1, 1, 1, 2, 2, 2, 2, 2, 2, 2, 2, 2, 3, 2, 1, 1, 1, 1, 1,
     -1, -1, -1, -1,
 ];
 
-const OF_DIST: &'static [i32] = &[
+const OF_DIST: &[i32] = &[
     1, 1, 1, 1, 1, 1, 2, 2, 2, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, -1, -1, -1, -1, -1,
 ];
 
-pub(crate) fn default_ml_table() -> FSETable {
-    build_table_from_probabilities(&ML_DIST, 6)
+pub(crate) fn default_ml_table() -> Result<FSETable, AllocError> {
+    try_build_table_from_probabilities(ML_DIST, 6)
 }
 
-pub(crate) fn default_ll_table() -> FSETable {
-    build_table_from_probabilities(&LL_DIST, 6)
+pub(crate) fn default_ll_table() -> Result<FSETable, AllocError> {
+    try_build_table_from_probabilities(LL_DIST, 6)
 }
 
-pub(crate) fn default_of_table() -> FSETable {
-    build_table_from_probabilities(&OF_DIST, 5)
+pub(crate) fn default_of_table() -> Result<FSETable, AllocError> {
+    try_build_table_from_probabilities(OF_DIST, 5)
 }
@@ -11,15 +11,100 @@
 //! <https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#fse>
 //!
 //! <https://arxiv.org/pdf/1311.2540>
+//!
+//! The encoder and decoder work on any data, not only on the symbols of Zstandard:
+//!
+//! ```
+//! # #[cfg(feature = "encoder")] {
+//! use ruzstd::decoding::bit_reader_reverse::BitReaderReversed;
+//! use ruzstd::encoding::bit_writer::BitWriter;
+//! use ruzstd::fse::fse_encoder::{build_table_from_data, FSEEncoder};
+//! use ruzstd::fse::{FSEDecoder, FSETable};
+//!
+//! let data = b"eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee, some more and other symbols";
+//! let mut writer = BitWriter::new();
+//! let mut encoder = FSEEncoder::new(build_table_from_data(data, 9, false), &mut writer);
+//! encoder.encode(data);
+//! let acc_log = encoder.acc_log();
+//! let encoded = writer.dump();
+//!
+//! // The table description comes first, then the bitstream which is read from the end
+//! let mut table = FSETable::new(255);
+//! let table_len = table.build_decoder(&encoded, acc_log).unwrap();
+//! let mut br = BitReaderReversed::new(&encoded[table_len..]);
+//! // Skip the padding up to and including the highest set bit
+//! while br.get_bits(1) == 0 {}
+//!
+//! let mut decoder = FSEDecoder::new(&table);
+//! decoder.init_state(&mut br).unwrap();
+//! let mut decoded = vec![decoder.decode_symbol()];
+//! while decoded.len() < data.len() {
+//!     decoder.update_state(&mut br);
+//!     decoded.push(decoder.decode_symbol());
+//! }
+//! assert_eq!(decoded, data);
+//! # }
+//! ```
 
 mod fse_decoder;
 
 pub use fse_decoder::*;
+
+/// The probabilities of the symbols in the [Debug](core::fmt::Debug) output of the FSE tables,
+/// symbols without any are left out.
+struct Probabilities<'a>(&'a [(usize, i32)]);
+
+impl core::fmt::Debug for Probabilities<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map()
+            .entries(
+                self.0
+                    .iter()
+                    .map(|(symbol, probability)| (symbol, probability)),
+            )
+            .finish()
+    }
+}
+
+/// The states of an FSE table in its [Debug](core::fmt::Debug) output, indexed by state.
+struct States<'a>(&'a [Transition]);
+
+impl core::fmt::Debug for States<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.0.iter().enumerate()).finish()
+    }
+}
+
+/// What a state decodes to and which states can follow it: the decoder reads `num_bits` bits and
+/// adds them to `baseline`. Printed as e.g. `3 -> 16..24 (3 bits)`.
+#[derive(Clone, Copy, Default)]
+struct Transition {
+    symbol: u8,
+    num_bits: u8,
+    baseline: u32,
+}
+
+impl core::fmt::Debug for Transition {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} -> {}..{} ({} bits)",
+            self.symbol,
+            self.baseline,
+            self.baseline + (1 << self.num_bits),
+            self.num_bits
+        )
+    }
+}
+#[cfg(feature = "encoder")]
 use fse_encoder::FSEEncoder;
 
+#[cfg(feature = "encoder")]
 use crate::{decoding::bit_reader_reverse::BitReaderReversed, encoding::bit_writer::BitWriter};
+#[cfg(feature = "encoder")]
 pub mod fse_encoder;
 
+#[cfg(feature = "encoder")]
 #[test]
 fn tables_equal() {
     let probs = &[0, 0, -1, 3, 2, 2, (1 << 6) - 8];
@@ -30,6 +115,26 @@ fn tables_equal() {
     check_tables(&dec_table, &enc_table);
 }
 
+#[cfg(feature = "encoder")]
+#[test]
+fn debug_output() {
+    let probs = &[-1, 0, 15, 16];
+    let mut dec_table = FSETable::new(255);
+    dec_table.build_from_probabilities(5, probs).unwrap();
+    let enc_table = fse_encoder::build_table_from_probabilities(probs, 5);
+
+    let output = alloc::format!("{:?}", dec_table);
+    assert!(output.starts_with(
+        "FSETable { accuracy_log: 5, probabilities: {0: -1, 2: 15, 3: 16}, states: {0: 2 -> 28..32 (2 bits), 1: 2 -> 0..2 (1 bits),"
+    ));
+    // The "less than 1" symbol is decoded by the last state and followed by any state
+    assert!(output.ends_with("31: 0 -> 0..32 (5 bits)} }"));
+    assert_eq!(output, alloc::format!("{:?}", enc_table));
+    assert!(alloc::format!("{:#?}", enc_table).contains("\n        31: 0 -> 0..32 (5 bits),\n"));
+}
+
+#[cfg(feature = "encoder")]
+#[allow(clippy::panic, clippy::unwrap_used, clippy::disallowed_macros)]
 fn check_tables(dec_table: &fse_decoder::FSETable, enc_table: &fse_encoder::FSETable) {
     for (idx, dec_state) in dec_table.decode.iter().enumerate() {
         let enc_states = &enc_table.states[dec_state.symbol as usize];
@@ -43,6 +148,7 @@ fn check_tables(dec_table: &fse_decoder::FSETable, enc_table: &fse_encoder::FSET
     }
 }
 
+#[cfg(feature = "encoder")]
 #[test]
 fn roundtrip() {
     round_trip(&(0..64).collect::<alloc::vec::Vec<_>>());
@@ -72,6 +178,15 @@ fn roundtrip() {
     }
 }
 
+/// Encode `data` with a table built for it and decode it again, panicking if anything differs.
+/// The `fse` fuzz target calls this.
+#[cfg(feature = "encoder")]
+#[allow(
+    clippy::panic,
+    clippy::unwrap_used,
+    clippy::disallowed_macros,
+    clippy::disallowed_methods
+)]
 pub fn round_trip(data: &[u8]) {
     if data.len() < 2 {
         return;
@@ -83,9 +198,15 @@ pub fn round_trip(data: &[u8]) {
         return;
     }
 
+    // The tables of the `small-target` feature have no room for larger accuracy logs
+    let max_log = if cfg!(feature = "small-target") {
+        fse_decoder::MAX_TABLE_LOG
+    } else {
+        22
+    };
     let mut writer = BitWriter::new();
     let mut encoder = FSEEncoder::new(
-        fse_encoder::build_table_from_data(data, 22, false),
+        fse_encoder::build_table_from_data(data, max_log, false),
         &mut writer,
     );
     let mut dec_table = FSETable::new(255);
@@ -0,0 +1,318 @@
+//! Entry points for fuzzing the decoder and encoder.
+//!
+//! These are the same functions the targets in the `fuzz` directory of this repository call.
+//! They are exposed so that downstream projects and OSS-Fuzz integrations can reuse
+//! them directly instead of copying the target bodies.
+//!
+//! All functions panic if they find a bug. They never panic on malformed input.
+
+// Panicking is how these functions report bugs, and running out of memory in a test harness may abort
+#![allow(
+    clippy::panic,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::disallowed_macros,
+    clippy::disallowed_methods
+)]
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::num::NonZeroU32;
+use std::io::Read;
+
+use crate::decoding::dictionary::Dictionary;
+use crate::encoding::{
+    compress_bound, CompressionLevel, EncodeError, FrameCompressorBuilder, Strategy, ZstdLevel,
+    MIN_BOUNDED_BLOCK_SIZE,
+};
+use crate::frame::ReadFrameHeaderError;
+use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+use crate::streaming_decoder::StreamingDecoder;
+
+pub use crate::fse::round_trip as fse_round_trip;
+pub use crate::huff0::round_trip as huff0_round_trip;
+
+/// Try to decode arbitrary bytes as a zstd frame.
+///
+/// Errors are ignored, the decoder just must never panic on invalid input.
+pub fn decode(data: &[u8]) {
+    let mut content = data;
+    let mut frame_dec = FrameDecoder::new();
+
+    if frame_dec.reset(&mut content).is_ok() {
+        let _ = frame_dec.decode_blocks(&mut content, BlockDecodingStrategy::All);
+    }
+}
+
+/// Compress `data` at every implemented level with this crate, decompress it again
+/// with this crate and assert that the result equals the input.
+///
+/// The levels are the ones [implemented_levels] returns, the ultra levels included.
+pub fn round_trip(data: &[u8]) {
+    for level in implemented_levels() {
+        let compressed = encode_ruzstd(data, level);
+
+        let mut decoded = Vec::with_capacity(data.len());
+        let mut decoder = FrameDecoder::new();
+        decoder
+            .decode_all_to_vec(&compressed, &mut decoded)
+            .unwrap();
+        assert_eq!(
+            data,
+            decoded.as_slice(),
+            "Decoded data did not match the original input at level {:?}",
+            level
+        );
+    }
+}
+
+/// Check interoperability with the reference implementation in both directions.
+///
+/// Data compressed by libzstd must be decoded correctly by both the [StreamingDecoder] and the
+/// [FrameDecoder], and data compressed by this crate at every level of [implemented_levels] must be
+/// decoded correctly by libzstd.
+pub fn interop(data: &[u8]) {
+    // Decoding
+    let compressed = zstd::stream::encode_all(data, 3).unwrap();
+    let decoded = decode_ruzstd(compressed.as_slice());
+    let decoded2 = decode_ruzstd_writer(compressed.as_slice());
+    assert!(
+        decoded == data,
+        "Decoded data did not match the original input during decompression"
+    );
+    assert_eq!(
+        decoded2, data,
+        "Decoded data did not match the original input during decompression"
+    );
+
+    // Encoding
+    for level in implemented_levels() {
+        let compressed = encode_ruzstd(data, level);
+        let mut decoded = Vec::new();
+        zstd::stream::copy_decode(compressed.as_slice(), &mut decoded).unwrap();
+        assert_eq!(
+            decoded, data,
+            "Decoded data did not match the original input during compression"
+        );
+    }
+}
+
+/// The levels this build compresses at: [CompressionLevel::Uncompressed], [CompressionLevel::Auto]
+/// and the zstd levels from -1 to 22 that
+/// [supports_level](crate::capabilities::Capabilities::supports_level) accepts.
+pub fn implemented_levels() -> impl Iterator<Item = CompressionLevel> {
+    let capabilities = crate::capabilities::capabilities();
+    let numbered = (-1..=22)
+        .filter(move |&level| capabilities.supports_level(level))
+        .map(|level| match NonZeroU32::new(level.unsigned_abs()) {
+            Some(speed) if level < 0 => CompressionLevel::Negative(speed),
+            _ => CompressionLevel::from(ZstdLevel::try_from(level).unwrap()),
+        });
+    [CompressionLevel::Uncompressed, CompressionLevel::Auto]
+        .iter()
+        .copied()
+        .chain(numbered)
+}
+
+/// Compress the input with settings that are taken from its first bytes, decompress it again
+/// with this crate and assert that the result equals the input.
+///
+/// Unlike [round_trip], this covers all combinations of levels, strategies, window logs, block
+/// sizes, checksums, content sizes, magicless frames, `pzstd` frames, rsyncable frames and
+/// dictionaries. Settings that can not be used together are skipped. The output must not be
+/// larger than [compress_bound](crate::encoding::compress_bound) allows where it applies.
+pub fn differential(data: &[u8]) {
+    check_differential(data, false);
+}
+
+/// Like [differential], and decompress the result with libzstd as well, unless it is in a form
+/// libzstd does not read by default (magicless frames, or a dictionary that only this crate knows
+/// by its ID).
+pub fn differential_interop(data: &[u8]) {
+    check_differential(data, true);
+}
+
+/// The number of bytes at the start of the fuzzer input that select the settings for [differential].
+const SETTINGS_SIZE: usize = 7;
+
+/// The content a frame is compressed against, in [differential].
+enum Reference {
+    None,
+    Dictionary(Vec<u8>),
+    Patch(Vec<u8>),
+}
+
+fn check_differential(data: &[u8], libzstd: bool) {
+    let mut settings = [0; SETTINGS_SIZE];
+    let split = data.len().min(SETTINGS_SIZE);
+    settings[..split].copy_from_slice(&data[..split]);
+    let data = &data[split..];
+    let [level, strategy, flags, window_log, block_size_low, block_size_high, frame_size] =
+        settings;
+
+    let level = match level % 8 {
+        0 => CompressionLevel::Uncompressed,
+        1 => CompressionLevel::Fastest,
+        2 => CompressionLevel::Auto,
+        3 => CompressionLevel::Negative(NonZeroU32::new(u32::from(level / 8) + 1).unwrap()),
+        4 => CompressionLevel::Other(ZstdLevel::try_from(level / 8 % 23).unwrap()),
+        5 => CompressionLevel::Default,
+        6 => CompressionLevel::Better,
+        _ => CompressionLevel::Best,
+    };
+    let strategies = [
+        Strategy::Fast,
+        Strategy::DFast,
+        Strategy::Greedy,
+        Strategy::Lazy,
+        Strategy::Lazy2,
+        Strategy::BtLazy2,
+        Strategy::BtOpt,
+        Strategy::BtUltra,
+        Strategy::RowHash,
+    ];
+    let magicless = flags & 4 != 0;
+    // The reference is the first half of the content, so the content has something to match
+    let reference_content = data[..data.len() / 2].to_vec();
+    let reference = match (flags >> 3) & 3 {
+        1 => Reference::Dictionary(reference_content),
+        2 => Reference::Patch(reference_content),
+        _ => Reference::None,
+    };
+
+    let mut builder = FrameCompressorBuilder::new()
+        .level(level)
+        .content_size(flags & 2 != 0)
+        .magicless(magicless)
+        .rsyncable(flags & 64 != 0);
+    if let Some(&strategy) = strategies.get(usize::from(strategy)) {
+        builder = builder.strategy(strategy);
+    }
+    #[cfg(feature = "hash")]
+    {
+        builder = builder.checksum(flags & 1 != 0);
+    }
+    if window_log != 0 {
+        builder = builder.window_log(10 + window_log % 14);
+    }
+    let block_size = u16::from_le_bytes([block_size_low, block_size_high]);
+    if block_size != 0 {
+        builder = builder.block_size(usize::from(block_size));
+    }
+    if flags & 32 != 0 {
+        builder = builder.pzstd_frame_size(Some(1 + usize::from(frame_size) * 64));
+    }
+    if let Reference::Dictionary(content) = &reference {
+        builder = builder.dictionary(Dictionary::from_raw_content(1, content.clone()));
+    }
+    let mut compressed = Vec::new();
+    let Ok(mut compressor) = builder.build(data, &mut compressed) else {
+        return;
+    };
+    if let Reference::Patch(content) = &reference {
+        compressor.set_patch_reference(content.clone());
+    }
+    match compressor.compress() {
+        Ok(()) => {}
+        Err(EncodeError::UnsupportedLevel { .. }) => return,
+        Err(e) => panic!("Compression failed at level {:?}: {}", level, e),
+    }
+    if flags & 32 == 0 && (block_size == 0 || usize::from(block_size) >= MIN_BOUNDED_BLOCK_SIZE) {
+        assert!(
+            compressed.len() <= compress_bound(data.len()),
+            "{} bytes took {} bytes at level {:?}, more than compress_bound",
+            data.len(),
+            compressed.len(),
+            level
+        );
+    }
+
+    let decoded = decode_differential(&compressed, magicless, &reference);
+    assert!(
+        decoded == data,
+        "Decoded data did not match the original input at level {:?}",
+        level
+    );
+
+    if libzstd && !magicless {
+        let mut decompressor = match &reference {
+            Reference::None => zstd::bulk::Decompressor::new().unwrap(),
+            Reference::Dictionary(_) => return,
+            // A reference that starts like a dictionary would not be read as raw content
+            Reference::Patch(content) if content.starts_with(&[0x37, 0xA4, 0x30, 0xEC]) => return,
+            Reference::Patch(content) => {
+                zstd::bulk::Decompressor::with_dictionary(content).unwrap()
+            }
+        };
+        let decoded = decompressor.decompress(&compressed, data.len()).unwrap();
+        assert!(
+            decoded == data,
+            "libzstd decoded different data at level {:?}",
+            level
+        );
+    }
+}
+
+fn decode_differential(mut data: &[u8], magicless: bool, reference: &Reference) -> Vec<u8> {
+    let mut decoder = FrameDecoder::new();
+    decoder.set_magicless(magicless);
+    match reference {
+        Reference::None => {}
+        Reference::Dictionary(content) | Reference::Patch(content) => decoder
+            .add_dict(Dictionary::from_raw_content(1, content.clone()))
+            .unwrap(),
+    }
+    let mut result = Vec::new();
+    while !data.is_empty() {
+        match decoder.reset(&mut data) {
+            Ok(()) => {}
+            Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                length,
+                ..
+            })) => {
+                data = &data[length as usize..];
+                continue;
+            }
+            Err(e) => panic!("Failed to read a frame header: {}", e),
+        }
+        if let Reference::Patch(_) = reference {
+            decoder.force_dict(1).unwrap();
+        }
+        decoder
+            .decode_blocks(&mut data, BlockDecodingStrategy::All)
+            .unwrap();
+        decoder.collect_to_writer(&mut result).unwrap();
+    }
+    result
+}
+
+fn encode_ruzstd(data: &[u8], level: CompressionLevel) -> Vec<u8> {
+    let mut output = Vec::new();
+    let mut compressor = FrameCompressorBuilder::new()
+        .level(level)
+        .ultra(true)
+        .build(data, &mut output)
+        .unwrap();
+    compressor.compress().unwrap();
+    output
+}
+
+fn decode_ruzstd(data: &[u8]) -> Vec<u8> {
+    let mut decoder = StreamingDecoder::new(data).unwrap();
+    let mut result: Vec<u8> = Vec::new();
+    decoder.read_to_end(&mut result).expect("Decoding failed");
+    result
+}
+
+fn decode_ruzstd_writer(mut data: &[u8]) -> Vec<u8> {
+    let mut decoder = FrameDecoder::new();
+    decoder.reset(&mut data).unwrap();
+    let mut result = Vec::new();
+    while !decoder.is_finished() || decoder.can_collect() > 0 {
+        decoder
+            .decode_blocks(&mut data, BlockDecodingStrategy::UptoBytes(1024 * 1024))
+            .unwrap();
+        decoder.collect_to_writer(&mut result).unwrap();
+    }
+    result
+}
@@ -1,25 +1,30 @@
 //! Utilities for decoding Huff0 encoded huffman data.
 
 use crate::decoding::bit_reader_reverse::{BitReaderReversed, GetBitsError};
+use crate::decoding::table_vec::TableVec;
+use crate::fallible::AllocError;
 use crate::fse::{FSEDecoder, FSEDecoderError, FSETable, FSETableError};
 use alloc::vec::Vec;
 #[cfg(feature = "std")]
 use std::error::Error as StdError;
 
+/// The decoding side of a Huffman table, read from a table description with [HuffmanTable::build_decoder].
 pub struct HuffmanTable {
-    decode: Vec<Entry>,
+    decode: TableVec<Entry, { 1 << MAX_MAX_NUM_BITS }>,
     /// The weight of a symbol is the number of occurences in a table.
     /// This value is used in constructing a binary tree referred to as
     /// a huffman tree.
-    weights: Vec<u8>,
+    ///
+    /// There are at most 255, the two FSE decoders may each add one more before they are counted.
+    weights: TableVec<u8, 257>,
     /// The maximum size in bits a prefix code in the encoded data can be.
     /// This value is used so that the decoder knows how many bits
     /// to read from the bitstream before checking the table. This
     /// value must be 11 or lower.
     pub max_num_bits: u8,
-    bits: Vec<u8>,
-    bit_ranks: Vec<u32>,
-    rank_indexes: Vec<usize>,
+    bits: TableVec<u8, 256>,
+    bit_ranks: TableVec<u32, { MAX_MAX_NUM_BITS as usize + 1 }>,
+    rank_indexes: TableVec<usize, { MAX_MAX_NUM_BITS as usize + 1 }>,
     /// In some cases, the list of weights is compressed using FSE compression.
     fse_table: FSETable,
 }
@@ -63,6 +68,10 @@ pub enum HuffmanTableError {
     MaxBitsTooHigh {
         got: u8,
     },
+    /// There is not enough memory for the table.
+    OutOfMemory {
+        size: usize,
+    },
 }
 
 #[cfg(feature = "std")]
@@ -149,10 +158,19 @@ impl core::fmt::Display for HuffmanTableError {
                     got, MAX_MAX_NUM_BITS,
                 )
             }
+            HuffmanTableError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for the Huffman table", size)
+            }
         }
     }
 }
 
+impl From<AllocError> for HuffmanTableError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
 impl From<GetBitsError> for HuffmanTableError {
     fn from(val: GetBitsError) -> Self {
         Self::GetBitsError(val)
@@ -207,9 +225,51 @@ impl From<GetBitsError> for HuffmanDecoderError {
     }
 }
 
+/// Prints the code of every symbol with a code, e.g. `0x61: 10` for the symbol `a` with the code `10`.
+/// The [alternate](core::fmt::Formatter::alternate) format prints one symbol per line.
+impl core::fmt::Debug for HuffmanTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("HuffmanTable")
+            .field("max_num_bits", &self.max_num_bits)
+            .field("codes", &Codes(self))
+            .finish()
+    }
+}
+
+struct Codes<'t>(&'t HuffmanTable);
+
+impl core::fmt::Debug for Codes<'_> {
+    // Only for diagnostics, see the no-panic section of the Readme
+    #[allow(clippy::disallowed_methods)]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // A code of n bits fills the entries from `code << (max_num_bits - n)` on, so the codes are
+        // found at the entries where such a range starts
+        let max_num_bits = self.0.max_num_bits;
+        let mut codes: Vec<(u8, super::Code)> = Vec::new();
+        let mut index = 0;
+        while let Some(entry) = self.0.decode.get(index) {
+            let shift = max_num_bits - entry.num_bits;
+            codes.push((
+                entry.symbol,
+                super::Code {
+                    value: (index >> shift) as u32,
+                    num_bits: entry.num_bits,
+                },
+            ));
+            index += 1 << shift;
+        }
+        codes.sort_unstable_by_key(|(symbol, _)| *symbol);
+        let mut map = f.debug_map();
+        for (symbol, code) in &codes {
+            map.entry(&format_args!("{:#04x}", symbol), code);
+        }
+        map.finish()
+    }
+}
+
 /// A single entry in the table contains the decoded symbol/literal and the
 /// size of the prefix code.
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Default)]
 pub struct Entry {
     /// The byte that the prefix code replaces during encoding.
     symbol: u8,
@@ -220,10 +280,8 @@ pub struct Entry {
 /// The Zstandard specification limits the maximum length of a code to 11 bits.
 const MAX_MAX_NUM_BITS: u8 = 11;
 
-/// Assert that the provided value is greater than zero, and returns the
-/// 32 - the number of leading zeros
+/// Returns 32 - the number of leading zeros, which is 0 for 0
 fn highest_bit_set(x: u32) -> u32 {
-    assert!(x > 0);
     u32::BITS - x.leading_zeros()
 }
 
@@ -286,27 +344,29 @@ impl HuffmanTable {
     /// Create a new, empty table.
     pub fn new() -> HuffmanTable {
         HuffmanTable {
-            decode: Vec::new(),
+            decode: TableVec::new(),
 
-            weights: Vec::with_capacity(256),
+            weights: TableVec::new(),
             max_num_bits: 0,
-            bits: Vec::with_capacity(256),
-            bit_ranks: Vec::with_capacity(11),
-            rank_indexes: Vec::with_capacity(11),
+            bits: TableVec::new(),
+            bit_ranks: TableVec::new(),
+            rank_indexes: TableVec::new(),
             fse_table: FSETable::new(255),
         }
     }
 
     /// Completely empty the table then repopulate as a replica
     /// of `other`.
-    pub fn reinit_from(&mut self, other: &Self) {
+    pub fn reinit_from(&mut self, other: &Self) -> Result<(), HuffmanTableError> {
         self.reset();
-        self.decode.extend_from_slice(&other.decode);
-        self.weights.extend_from_slice(&other.weights);
+        self.decode.try_extend_from_slice(&other.decode)?;
+        self.weights.try_extend_from_slice(&other.weights)?;
         self.max_num_bits = other.max_num_bits;
-        self.bits.extend_from_slice(&other.bits);
-        self.rank_indexes.extend_from_slice(&other.rank_indexes);
-        self.fse_table.reinit_from(&other.fse_table);
+        self.bits.try_extend_from_slice(&other.bits)?;
+        self.rank_indexes
+            .try_extend_from_slice(&other.rank_indexes)?;
+        self.fse_table.reinit_from(&other.fse_table)?;
+        Ok(())
     }
 
     /// Completely empty the table of all data.
@@ -328,9 +388,27 @@ impl HuffmanTable {
 
         let bytes_used = self.read_weights(source)?;
         self.build_table_from_weights()?;
+        trace_event!(
+            max_num_bits = self.max_num_bits,
+            bytes = bytes_used,
+            "built Huffman table"
+        );
         Ok(bytes_used)
     }
 
+    /// The weights of all symbols but the last one, which is inferred from the others.
+    pub(crate) fn weights(&self) -> &[u8] {
+        &self.weights
+    }
+
+    /// Build the table from weights that were taken from another table with [HuffmanTable::weights].
+    pub(crate) fn build_from_weights(&mut self, weights: &[u8]) -> Result<(), HuffmanTableError> {
+        self.decode.clear();
+        self.weights.clear();
+        self.weights.try_extend_from_slice(weights)?;
+        self.build_table_from_weights()
+    }
+
     /// Read weights from the provided source.
     ///
     /// The huffman table is represented in the encoded data as a list of weights
@@ -416,22 +494,22 @@ impl HuffmanTable {
                 // The two decoders take turns decoding a single symbol and updating their state.
                 loop {
                     let w = dec1.decode_symbol();
-                    self.weights.push(w);
+                    self.weights.try_push(w)?;
                     dec1.update_state(&mut br);
 
                     if br.bits_remaining() <= -1 {
                         //collect final states
-                        self.weights.push(dec2.decode_symbol());
+                        self.weights.try_push(dec2.decode_symbol())?;
                         break;
                     }
 
                     let w = dec2.decode_symbol();
-                    self.weights.push(w);
+                    self.weights.try_push(w)?;
                     dec2.update_state(&mut br);
 
                     if br.bits_remaining() <= -1 {
                         //collect final states
-                        self.weights.push(dec1.decode_symbol());
+                        self.weights.try_push(dec1.decode_symbol())?;
                         break;
                     }
                     //maximum number of weights is 255 because we use u8 symbols and the last weight is inferred from the sum of all others
@@ -441,6 +519,11 @@ impl HuffmanTable {
                         });
                     }
                 }
+                if self.weights.len() > 255 {
+                    return Err(err::TooManyWeights {
+                        got: self.weights.len(),
+                    });
+                }
             }
             // If the header byte is greater than or equal to 128,
             // weights are directly represented, where each weight is
@@ -452,9 +535,9 @@ impl HuffmanTable {
                 // weights are directly encoded
                 let weights_raw = &source[1..];
                 let num_weights = header - 127;
-                self.weights.resize(num_weights as usize, 0);
+                self.weights.try_resize(num_weights as usize, 0)?;
 
-                let bytes_needed = if num_weights % 2 == 0 {
+                let bytes_needed = if num_weights.is_multiple_of(2) {
                     num_weights as usize / 2
                 } else {
                     (num_weights as usize / 2) + 1
@@ -490,11 +573,12 @@ impl HuffmanTable {
     /// into a table, and use that table to decode the actual compressed data.
     ///
     /// This function populates the rest of the table from the series of weights.
+    #[allow(clippy::disallowed_macros)]
     fn build_table_from_weights(&mut self) -> Result<(), HuffmanTableError> {
         use HuffmanTableError as err;
 
         self.bits.clear();
-        self.bits.resize(self.weights.len() + 1, 0);
+        self.bits.try_resize(self.weights.len() + 1, 0)?;
 
         let mut weight_sum: u32 = 0;
         for w in &self.weights {
@@ -535,23 +619,23 @@ impl HuffmanTable {
         }
 
         self.bit_ranks.clear();
-        self.bit_ranks.resize((max_bits + 1) as usize, 0);
+        self.bit_ranks.try_resize((max_bits + 1) as usize, 0)?;
         for num_bits in &self.bits {
             self.bit_ranks[(*num_bits) as usize] += 1;
         }
 
         //fill with dummy symbols
-        self.decode.resize(
+        self.decode.try_resize(
             1 << self.max_num_bits,
             Entry {
                 symbol: 0,
                 num_bits: 0,
             },
-        );
+        )?;
 
         //starting codes for each rank
         self.rank_indexes.clear();
-        self.rank_indexes.resize((max_bits + 1) as usize, 0);
+        self.rank_indexes.try_resize((max_bits + 1) as usize, 0)?;
 
         self.rank_indexes[max_bits as usize] = 0;
         for bits in (1..self.rank_indexes.len() as u8).rev() {
@@ -559,8 +643,9 @@ impl HuffmanTable {
                 + self.bit_ranks[bits as usize] as usize * (1 << (max_bits - bits));
         }
 
-        assert!(
-            self.rank_indexes[0] == self.decode.len(),
+        debug_assert_eq!(
+            self.rank_indexes[0],
+            self.decode.len(),
             "rank_idx[0]: {} should be: {}",
             self.rank_indexes[0],
             self.decode.len()
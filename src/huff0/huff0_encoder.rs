@@ -1,33 +1,67 @@
+//! The Huffman encoder, see the [parent module](super) for an example.
+
 use alloc::vec::Vec;
 use core::cmp::Ordering;
 
 use crate::{
     encoding::bit_writer::BitWriter,
+    fallible::{or_abort, try_collect, try_to_vec, try_vec, try_with_capacity, AllocError, TryVec},
     fse::fse_encoder::{self, FSEEncoder},
 };
 
-pub(crate) struct HuffmanEncoder<'output, V: AsMut<Vec<u8>>> {
+/// Encodes symbols with a [HuffmanTable] into a [BitWriter].
+pub struct HuffmanEncoder<'output, V: AsMut<Vec<u8>>> {
     table: HuffmanTable,
     writer: &'output mut BitWriter<V>,
 }
 
 impl<V: AsMut<Vec<u8>>> HuffmanEncoder<'_, V> {
+    /// Create an encoder that writes into `writer`, using `table`.
     pub fn new(table: HuffmanTable, writer: &mut BitWriter<V>) -> HuffmanEncoder<'_, V> {
         HuffmanEncoder { table, writer }
     }
-    pub fn encode(&mut self, data: &[u8]) {
-        self.write_table();
-        Self::encode_stream(&self.table, &mut self.writer, data);
+
+    /// Write the table description, followed by a single bitstream with the encoded `data`.
+    ///
+    /// The bitstream is read backwards by the decoder, which yields the symbols of `data` in order.
+    /// Panics if `data` contains symbols that have no code in the table.
+    pub fn encode(&mut self, data: &[u8]) -> Result<(), HuffmanEncoderError> {
+        self.write_table()?;
+        Self::encode_stream(&self.table, self.writer, data);
+        Ok(())
+    }
+
+    /// Like [HuffmanEncoder::encode], but without the table description, for treeless literals
+    /// that are decoded with the table of an earlier block.
+    pub fn encode_treeless(&mut self, data: &[u8]) {
+        Self::encode_stream(&self.table, self.writer, data);
     }
-    pub fn encode4x(&mut self, data: &[u8]) {
-        assert!(data.len() >= 4);
-        let split_size = (data.len() + 3) / 4;
+
+    /// Like [HuffmanEncoder::encode], but splits `data` into four streams, preceded by a jump table
+    /// with the sizes of the first three.
+    ///
+    /// Fails if `data` has less than four symbols.
+    pub fn encode4x(&mut self, data: &[u8]) -> Result<(), HuffmanEncoderError> {
+        self.write_table()?;
+        self.encode4x_streams(data)
+    }
+
+    /// Like [HuffmanEncoder::encode4x], but without the table description, for treeless literals
+    /// that are decoded with the table of an earlier block.
+    pub fn encode4x_treeless(&mut self, data: &[u8]) -> Result<(), HuffmanEncoderError> {
+        self.encode4x_streams(data)
+    }
+
+    fn encode4x_streams(&mut self, data: &[u8]) -> Result<(), HuffmanEncoderError> {
+        if data.len() < 4 {
+            return Err(HuffmanEncoderError::TooFewSymbolsForFourStreams { got: data.len() });
+        }
+        let split_size = data.len().div_ceil(4);
         let src1 = &data[..split_size];
         let src2 = &data[split_size..split_size * 2];
         let src3 = &data[split_size * 2..split_size * 3];
         let src4 = &data[split_size * 3..];
 
-        self.write_table();
         let size_idx = self.writer.index();
         self.writer.write_bits(0u16, 16);
         self.writer.write_bits(0u16, 16);
@@ -47,13 +81,16 @@ impl<V: AsMut<Vec<u8>>> HuffmanEncoder<'_, V> {
 
         Self::encode_stream(&self.table, self.writer, src4);
 
-        assert!(size1 as u16 <= u16::MAX);
-        assert!(size2 as u16 <= u16::MAX);
-        assert!(size3 as u16 <= u16::MAX);
+        for size in [size1, size2, size3] {
+            if size > u16::MAX as usize {
+                return Err(HuffmanEncoderError::StreamTooLarge { size });
+            }
+        }
 
         self.writer.change_bits(size_idx, size1 as u16, 16);
         self.writer.change_bits(size_idx + 16, size2 as u16, 16);
         self.writer.change_bits(size_idx + 32, size3 as u16, 16);
+        Ok(())
     }
 
     fn encode_stream<VV: AsMut<Vec<u8>>>(
@@ -74,63 +111,305 @@ impl<V: AsMut<Vec<u8>>> HuffmanEncoder<'_, V> {
         }
     }
 
-    pub(super) fn weights(&self) -> Vec<u8> {
-        let max = self.table.codes.iter().map(|(_, nb)| nb).max().unwrap();
-        let weights = self
-            .table
-            .codes
-            .iter()
-            .copied()
-            .map(|(_, nb)| if nb == 0 { 0 } else { max - nb + 1 })
-            .collect::<Vec<u8>>();
-
-        weights
-    }
-
-    fn write_table(&mut self) {
+    /// Serialize the table, after making sure that a decoder can rebuild it from the weights.
+    pub(crate) fn write_table(&mut self) -> Result<(), HuffmanEncoderError> {
         // TODO strategy for determining this?
-        let weights = self.weights();
+        let weights = self.table.try_weights()?;
+        validate_weights(&weights)?;
         let weights = &weights[..weights.len() - 1]; // dont encode last weight
         if weights.len() > 16 {
             let size_idx = self.writer.index();
             self.writer.write_bits(0u8, 8);
             let idx_before = self.writer.index();
             let mut encoder = FSEEncoder::new(
-                fse_encoder::build_table_from_data(weights, 6, true),
+                fse_encoder::try_build_table_from_data(weights, 6, true)?,
                 self.writer,
             );
             encoder.encode_interleaved(weights);
             let encoded_len = (self.writer.index() - idx_before) / 8;
-            assert!(encoded_len < 128);
+            if encoded_len >= 128 {
+                return Err(HuffmanEncoderError::DescriptionTooLarge { size: encoded_len });
+            }
             self.writer.change_bits(size_idx, encoded_len as u8, 8);
         } else {
             self.writer.write_bits(weights.len() as u8 + 127, 8);
             let pairs = weights.chunks_exact(2);
             let remainder = pairs.remainder();
+            // The weights were validated to be at most 11, so they fit into 4 bits
             for pair in pairs.into_iter() {
                 let weight1 = pair[0];
                 let weight2 = pair[1];
-                assert!(weight1 < 16);
-                assert!(weight2 < 16);
                 self.writer.write_bits(weight2, 4);
                 self.writer.write_bits(weight1, 4);
             }
             if !remainder.is_empty() {
                 let weight = remainder[0];
-                assert!(weight < 16);
                 self.writer.write_bits(weight << 4, 8);
             }
         }
+        Ok(())
+    }
+}
+
+/// The ways in which a set of Huffman weights or the encoded streams can violate the format.
+///
+/// These indicate a bug in the encoder, as the tables built by the [HuffmanTable] constructors
+/// should always be valid and the streams are never larger than a block.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum HuffmanEncoderError {
+    TooFewSymbols {
+        got: usize,
+    },
+    TooManySymbols {
+        got: usize,
+    },
+    LastWeightIsZero,
+    WeightSumNotPowerOfTwo {
+        got: u32,
+    },
+    MaxBitsTooHigh {
+        got: u8,
+    },
+    StreamTooLarge {
+        size: usize,
+    },
+    TooFewSymbolsForFourStreams {
+        got: usize,
+    },
+    DescriptionTooLarge {
+        size: usize,
+    },
+    /// There was not enough memory for the table, unlike the other variants this is no bug.
+    OutOfMemory {
+        size: usize,
+    },
+}
+
+impl core::fmt::Display for HuffmanEncoderError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HuffmanEncoderError::TooFewSymbols { got } => {
+                write!(
+                    f,
+                    "Huffman tables need at least two symbols with non-zero weight, got: {}",
+                    got
+                )
+            }
+            HuffmanEncoderError::TooManySymbols { got } => {
+                write!(
+                    f,
+                    "Huffman tables can describe at most 256 symbols, got: {}",
+                    got
+                )
+            }
+            HuffmanEncoderError::LastWeightIsZero => {
+                write!(
+                    f,
+                    "The last symbol in a Huffman table must have a non-zero weight"
+                )
+            }
+            HuffmanEncoderError::WeightSumNotPowerOfTwo { got } => {
+                write!(f, "The weights must sum up to a power of two, got: {}", got)
+            }
+            HuffmanEncoderError::MaxBitsTooHigh { got } => {
+                write!(
+                    f,
+                    "Huffman codes can be at most {} bits long, got: {}",
+                    MAX_MAX_NUM_BITS, got
+                )
+            }
+            HuffmanEncoderError::StreamTooLarge { size } => {
+                write!(
+                    f,
+                    "The size of each of the four streams must fit into 16 bits, got: {}",
+                    size
+                )
+            }
+            HuffmanEncoderError::TooFewSymbolsForFourStreams { got } => {
+                write!(f, "Four streams need at least four symbols, got: {}", got)
+            }
+            HuffmanEncoderError::DescriptionTooLarge { size } => {
+                write!(
+                    f,
+                    "The compressed weights of a table must fit into 127 bytes, got: {}",
+                    size
+                )
+            }
+            HuffmanEncoderError::OutOfMemory { size } => {
+                write!(f, "Failed to allocate {} bytes for a Huffman table", size)
+            }
+        }
+    }
+}
+
+impl From<AllocError> for HuffmanEncoderError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HuffmanEncoderError {}
+
+/// The maximum code length allowed by the format
+const MAX_MAX_NUM_BITS: u8 = 11;
+
+/// Check that a decoder would rebuild exactly the table described by `weights`.
+///
+/// The weight of the last symbol is not transmitted, the decoder derives it from the others
+/// so the sum of all weights becomes a power of two. The other constraints are the same the
+/// decoder enforces when reading the table.
+pub fn validate_weights(weights: &[u8]) -> Result<(), HuffmanEncoderError> {
+    use HuffmanEncoderError as err;
+
+    if weights.len() > 256 {
+        return Err(err::TooManySymbols { got: weights.len() });
+    }
+    let used_symbols = weights.iter().filter(|w| **w > 0).count();
+    if used_symbols < 2 {
+        return Err(err::TooFewSymbols { got: used_symbols });
+    }
+    let Some((&last_weight, transmitted)) = weights.split_last() else {
+        return Err(err::TooFewSymbols { got: 0 });
+    };
+    if last_weight == 0 {
+        return Err(err::LastWeightIsZero);
+    }
+
+    let mut weight_sum: u32 = 0;
+    for weight in transmitted.iter().copied() {
+        if weight > MAX_MAX_NUM_BITS {
+            return Err(err::MaxBitsTooHigh { got: weight });
+        }
+        if weight > 0 {
+            weight_sum += 1 << (weight - 1);
+        }
+    }
+    if last_weight > MAX_MAX_NUM_BITS {
+        return Err(err::MaxBitsTooHigh { got: last_weight });
     }
+    let total = weight_sum + (1 << (last_weight - 1));
+    if !total.is_power_of_two() {
+        return Err(err::WeightSumNotPowerOfTwo { got: total });
+    }
+
+    // With a total that is a power of two, the last weight the decoder derives as the
+    // difference to the next power of two is the one the table uses.
+    let max_bits = total.ilog2() as u8;
+    if max_bits > MAX_MAX_NUM_BITS {
+        return Err(err::MaxBitsTooHigh { got: max_bits });
+    }
+    Ok(())
 }
 
+/// The encoding side of a Huffman table, which assigns a code to each symbol.
+///
+/// The [Debug] output lists the code of every symbol that has one, like the decoding
+/// [HuffmanTable](crate::huff0::HuffmanTable) built from the same weights does.
+#[derive(Clone)]
 pub struct HuffmanTable {
     /// Index is the symbol, values are the bitstring in the lower bits of the u32 and the amount of bits in the u8
     codes: Vec<(u32, u8)>,
 }
 
+impl core::fmt::Debug for HuffmanTable {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let codes = Codes(&self.codes);
+        f.debug_struct("HuffmanTable")
+            .field("max_num_bits", &codes.max_num_bits())
+            .field("codes", &codes)
+            .finish()
+    }
+}
+
+struct Codes<'t>(&'t [(u32, u8)]);
+
+impl Codes<'_> {
+    fn max_num_bits(&self) -> u8 {
+        self.0
+            .iter()
+            .map(|(_, num_bits)| *num_bits)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+impl core::fmt::Debug for Codes<'_> {
+    // Only for diagnostics, see the no-panic section of the Readme
+    #[allow(clippy::disallowed_methods)]
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut map = f.debug_map();
+        for (symbol, &(value, num_bits)) in self.0.iter().enumerate() {
+            if num_bits > 0 {
+                map.entry(
+                    &format_args!("{:#04x}", symbol),
+                    &super::Code { value, num_bits },
+                );
+            }
+        }
+        map.finish()
+    }
+}
+
 impl HuffmanTable {
+    /// Like `clone`, reporting when there is not enough memory for the copy.
+    pub(crate) fn try_clone(&self) -> Result<Self, AllocError> {
+        Ok(HuffmanTable {
+            codes: try_to_vec(&self.codes)?,
+        })
+    }
+
+    /// The weights that describe this table, indexed by symbol
+    ///
+    /// Aborts if there is not enough memory for them, like the collections of `alloc` do.
+    #[allow(clippy::disallowed_methods)]
+    pub fn weights(&self) -> Vec<u8> {
+        or_abort(self.try_weights())
+    }
+
+    /// Like [HuffmanTable::weights], reporting when there is not enough memory for them.
+    pub(crate) fn try_weights(&self) -> Result<Vec<u8>, AllocError> {
+        let max = self.codes.iter().map(|(_, nb)| *nb).max().unwrap_or(0);
+        try_collect(
+            self.codes
+                .iter()
+                .copied()
+                .map(|(_, nb)| if nb == 0 { 0 } else { max - nb + 1 }),
+        )
+    }
+
+    /// Count how many bits encoding the symbols takes, when each symbol occurs as often as `counts`
+    /// says. Returns `None` if one of them has no code in this table.
+    pub(crate) fn count_bits(&self, counts: &[usize]) -> Option<usize> {
+        let mut bits = 0;
+        for (symbol, count) in counts.iter().copied().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            match self.codes.get(symbol) {
+                Some(&(_, num_bits)) if num_bits > 0 => bits += count * usize::from(num_bits),
+                _ => return None,
+            }
+        }
+        Some(bits)
+    }
+
+    /// Check that this table can be serialized and read back by a decoder, see [validate_weights].
+    pub fn validate(&self) -> Result<(), HuffmanEncoderError> {
+        validate_weights(&self.try_weights()?)
+    }
+
+    /// Build a table that fits the symbol distribution of `data`.
+    ///
+    /// Like all constructors of the table, this aborts if there is not enough memory for it.
+    #[allow(clippy::disallowed_methods)]
     pub fn build_from_data(data: &[u8]) -> Self {
+        or_abort(Self::try_build_from_data(data))
+    }
+
+    /// Like [HuffmanTable::build_from_data], reporting when there is not enough memory.
+    pub(crate) fn try_build_from_data(data: &[u8]) -> Result<Self, AllocError> {
         let mut counts = [0; 256];
         let mut max = 0;
         for x in data {
@@ -138,62 +417,105 @@ impl HuffmanTable {
             max = max.max(*x);
         }
 
-        Self::build_from_counts(&counts[..=max as usize])
+        let counts = &counts[..=max as usize];
+        let symbols = counts.iter().filter(|x| **x > 0).count();
+        Self::try_build_from_counts_with_max_bits(counts, symbols.ilog2() as u8 + 2)
     }
 
+    /// Build a table from the number of occurrences of each symbol, indexed by symbol.
+    #[allow(clippy::disallowed_methods)]
     pub fn build_from_counts(counts: &[usize]) -> Self {
-        assert!(counts.len() <= 256);
+        let symbols = counts.iter().filter(|x| **x > 0).count();
+        or_abort(Self::try_build_from_counts_with_max_bits(
+            counts,
+            symbols.ilog2() as u8 + 2,
+        ))
+    }
+
+    /// Like [HuffmanTable::build_from_counts], with codes that are at most `max_num_bits` long.
+    ///
+    /// Longer codes make the table larger to describe, but give the frequent symbols shorter
+    /// codes. `max_num_bits` is raised to the fewest bits that leave room for a code for every
+    /// symbol, and lowered to the 11 bits the format allows. Counts beyond the 256 symbols of a
+    /// byte are ignored.
+    #[allow(clippy::disallowed_methods)]
+    pub fn build_from_counts_with_max_bits(counts: &[usize], max_num_bits: u8) -> Self {
+        or_abort(Self::try_build_from_counts_with_max_bits(
+            counts,
+            max_num_bits,
+        ))
+    }
+
+    /// Like [HuffmanTable::build_from_counts_with_max_bits], reporting when there is not enough
+    /// memory.
+    pub(crate) fn try_build_from_counts_with_max_bits(
+        counts: &[usize],
+        max_num_bits: u8,
+    ) -> Result<Self, AllocError> {
+        let counts = &counts[..usize::min(counts.len(), 256)];
         let zeros = counts.iter().filter(|x| **x == 0).count();
-        let mut weights = distribute_weights(counts.len() - zeros);
-        let limit = weights.len().ilog2() as usize + 2;
+        let mut weights = distribute_weights(counts.len() - zeros)?;
+        let limit = usize::from(max_num_bits)
+            .clamp(weights.len().ilog2() as usize + 1, MAX_MAX_NUM_BITS.into());
         redistribute_weights(&mut weights, limit);
 
         weights.reverse();
-        let mut counts_sorted = counts.iter().enumerate().collect::<Vec<_>>();
-        counts_sorted.sort_by(|(_, c1), (_, c2)| c1.cmp(c2));
+        let mut counts_sorted = try_collect(counts.iter().enumerate())?;
+        // Equal counts keep the order of their symbols, like with a stable sort
+        counts_sorted.sort_unstable_by_key(|(symbol, count)| (*count, *symbol));
 
-        let mut weights_distributed = alloc::vec![0; counts.len()];
+        let mut weights_distributed = try_vec(0, counts.len())?;
         for (idx, count) in counts_sorted {
             if *count == 0 {
                 weights_distributed[idx] = 0;
             } else {
-                weights_distributed[idx] = weights.pop().unwrap();
+                // There are as many weights as counts that are not zero
+                weights_distributed[idx] = weights.pop().unwrap_or_default();
             }
         }
 
-        Self::build_from_weights(&weights_distributed)
+        Self::try_build_from_weights(&weights_distributed)
     }
 
+    /// Build a table from the weights of the symbols, indexed by symbol. Symbols with weight 0 get no code,
+    /// the others get codes that are shorter the higher their weight is.
+    ///
+    /// Use [validate_weights] to check if the weights can be transmitted to a decoder. Weights that
+    /// do not add up to a power of two give a table that [HuffmanTable::validate] rejects.
+    #[allow(clippy::disallowed_methods)]
     pub fn build_from_weights(weights: &[usize]) -> Self {
-        let mut sorted = Vec::with_capacity(weights.len());
+        or_abort(Self::try_build_from_weights(weights))
+    }
+
+    /// Like [HuffmanTable::build_from_weights], reporting when there is not enough memory.
+    pub(crate) fn try_build_from_weights(weights: &[usize]) -> Result<Self, AllocError> {
+        let mut sorted = try_with_capacity(weights.len())?;
         struct SortEntry {
             symbol: u8,
             weight: usize,
         }
         for (symbol, weight) in weights.iter().copied().enumerate() {
             if weight > 0 {
-                sorted.push(SortEntry {
+                sorted.try_push(SortEntry {
                     symbol: symbol as u8,
                     weight,
-                });
+                })?;
             }
         }
-        sorted.sort_by(|left, right| match left.weight.cmp(&right.weight) {
+        sorted.sort_unstable_by(|left, right| match left.weight.cmp(&right.weight) {
             Ordering::Equal => left.symbol.cmp(&right.symbol),
             other => other,
         });
 
         let mut table = HuffmanTable {
-            codes: Vec::with_capacity(weights.len()),
+            codes: try_vec((0, 0), weights.len())?,
         };
-        for _ in 0..weights.len() {
-            table.codes.push((0, 0));
-        }
 
-        let weight_sum = sorted.iter().map(|e| 1 << (e.weight - 1)).sum::<usize>();
-        if !weight_sum.is_power_of_two() {
-            panic!("This is an internal error");
-        }
+        let weight_sum = sorted
+            .iter()
+            .map(|e| 1 << (e.weight - 1))
+            .sum::<usize>()
+            .next_power_of_two();
         let max_num_bits = highest_bit_set(weight_sum) - 1; // this is a log_2 of a clean power of two
 
         let mut current_value = 0;
@@ -209,13 +531,12 @@ impl HuffmanTable {
             current_value += 1;
         }
 
-        table
+        Ok(table)
     }
 }
 
-/// Assert that the provided value is greater than zero, and returns index of the first set bit
+/// Returns the index of the first set bit, which is 0 for 0
 fn highest_bit_set(x: usize) -> usize {
-    assert!(x > 0);
     usize::BITS as usize - x.leading_zeros() as usize
 }
 
@@ -237,15 +558,14 @@ fn huffman() {
     assert_eq!(table.codes[5], (1, 4));
 }
 
-fn distribute_weights(amount: usize) -> Vec<usize> {
-    assert!(amount >= 2);
-    assert!(amount <= 256);
-    let mut weights = Vec::new();
+/// At least two weights, even if `amount` is smaller.
+fn distribute_weights(amount: usize) -> Result<Vec<usize>, AllocError> {
+    let mut weights = try_with_capacity(amount.max(2))?;
     let mut target_weight = 1;
     let mut weight_counter = 2;
 
-    weights.push(1);
-    weights.push(1);
+    weights.try_push(1)?;
+    weights.try_push(1)?;
 
     while weights.len() < amount {
         let mut add_new = 1 << (weight_counter - target_weight);
@@ -257,12 +577,12 @@ fn distribute_weights(amount: usize) -> Vec<usize> {
         }
 
         for _ in 0..add_new {
-            weights.push(target_weight);
+            weights.try_push(target_weight)?;
         }
         weight_counter += 1;
     }
 
-    weights
+    Ok(weights)
 }
 
 fn redistribute_weights(weights: &mut [usize], max_num_bits: usize) {
@@ -311,11 +631,39 @@ fn redistribute_weights(weights: &mut [usize], max_num_bits: usize) {
     }
 }
 
+#[test]
+fn weight_validation() {
+    assert!(validate_weights(&[2, 2, 2, 1, 1]).is_ok());
+    assert!(validate_weights(&[4, 3, 2, 0, 1, 1]).is_ok());
+    assert!(
+        HuffmanTable::build_from_data(&[1, 1, 1, 1, 2, 3, 5, 45, 12, 90])
+            .validate()
+            .is_ok()
+    );
+
+    assert!(matches!(
+        validate_weights(&[1, 0]),
+        Err(HuffmanEncoderError::TooFewSymbols { got: 1 })
+    ));
+    assert!(matches!(
+        validate_weights(&[1, 1, 0]),
+        Err(HuffmanEncoderError::LastWeightIsZero)
+    ));
+    assert!(matches!(
+        validate_weights(&[2, 1, 1, 1]),
+        Err(HuffmanEncoderError::WeightSumNotPowerOfTwo { got: 5 })
+    ));
+    assert!(matches!(
+        validate_weights(&[12, 12]),
+        Err(HuffmanEncoderError::MaxBitsTooHigh { got: 12 })
+    ));
+}
+
 #[test]
 fn weights() {
     // assert_eq!(distribute_weights(5).as_slice(), &[1, 1, 2, 3, 4]);
     for amount in 2..=256 {
-        let mut weights = distribute_weights(amount);
+        let mut weights = distribute_weights(amount).unwrap();
         assert_eq!(weights.len(), amount);
         let sum = weights
             .iter()
@@ -395,3 +743,33 @@ fn from_data() {
 
     assert_eq!(table, table2);
 }
+
+#[test]
+fn max_num_bits() {
+    // Counts that grow exponentially ask for long codes
+    let counts: Vec<usize> = (0..20).map(|x| 1 << x).collect();
+    let longest = |table: &HuffmanTable| table.codes.iter().map(|(_, bits)| *bits).max().unwrap();
+
+    let mut previous = 0;
+    for max_num_bits in 5..=11 {
+        let table = HuffmanTable::build_from_counts_with_max_bits(&counts, max_num_bits);
+        assert!(table.validate().is_ok());
+        assert!(longest(&table) <= max_num_bits);
+        assert!(longest(&table) >= previous);
+        previous = longest(&table);
+    }
+    assert!(previous > 6);
+
+    // Too short for 20 symbols and too long for the format
+    let table = HuffmanTable::build_from_counts_with_max_bits(&counts, 1);
+    assert_eq!(longest(&table), 5);
+    assert_eq!(
+        HuffmanTable::build_from_counts_with_max_bits(&counts, 20).codes,
+        HuffmanTable::build_from_counts_with_max_bits(&counts, 11).codes
+    );
+
+    assert_eq!(
+        HuffmanTable::build_from_counts(&counts).codes,
+        HuffmanTable::build_from_counts_with_max_bits(&counts, 6).codes
+    );
+}
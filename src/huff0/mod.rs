@@ -1,15 +1,78 @@
-/// Huffman coding is a method of encoding where symbols are assigned a code,
-/// and more commonly used symbols get shorter codes, and less commonly
-/// used symbols get longer codes. Codes are prefix free, meaning no two codes
-/// will start with the same sequence of bits.
+//! Huffman coding is a method of encoding where symbols are assigned a code,
+//! and more commonly used symbols get shorter codes, and less commonly
+//! used symbols get longer codes. Codes are prefix free, meaning no two codes
+//! will start with the same sequence of bits.
+//!
+//! Zstandard uses it for literals, but the encoder and decoder work on any data
+//! with at least two different byte values:
+//!
+//! ```
+//! # #[cfg(feature = "encoder")] {
+//! use ruzstd::decoding::bit_reader_reverse::BitReaderReversed;
+//! use ruzstd::encoding::bit_writer::BitWriter;
+//! use ruzstd::huff0::huff0_encoder::{self, HuffmanEncoder};
+//! use ruzstd::huff0::{HuffmanDecoder, HuffmanTable};
+//!
+//! let data = b"abracadabra, abracadabra";
+//! let mut writer = BitWriter::new();
+//! let table = huff0_encoder::HuffmanTable::build_from_data(data);
+//! HuffmanEncoder::new(table, &mut writer).encode(data).unwrap();
+//! let encoded = writer.dump();
+//!
+//! // The table description comes first, then the bitstream which is read from the end
+//! let mut table = HuffmanTable::new();
+//! let table_len = table.build_decoder(&encoded).unwrap() as usize;
+//! let mut br = BitReaderReversed::new(&encoded[table_len..]);
+//! // Skip the padding up to and including the highest set bit
+//! while br.get_bits(1) == 0 {}
+//!
+//! let mut decoder = HuffmanDecoder::new(&table);
+//! decoder.init_state(&mut br);
+//! let mut decoded = Vec::new();
+//! while decoded.len() < data.len() {
+//!     decoded.push(decoder.decode_symbol());
+//!     decoder.next_state(&mut br);
+//! }
+//! assert_eq!(decoded, data);
+//! # }
+//! ```
 mod huff0_decoder;
+#[cfg(feature = "encoder")]
 use alloc::vec::Vec;
 
 pub use huff0_decoder::*;
 
+/// A prefix code in the [Debug](core::fmt::Debug) output of the Huffman tables, printed as bits.
+struct Code {
+    value: u32,
+    num_bits: u8,
+}
+
+impl core::fmt::Debug for Code {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{:0width$b}",
+            self.value,
+            width = usize::from(self.num_bits)
+        )
+    }
+}
+
+#[cfg(feature = "encoder")]
 use crate::{decoding::bit_reader_reverse::BitReaderReversed, encoding::bit_writer::BitWriter};
+#[cfg(feature = "encoder")]
 pub mod huff0_encoder;
 
+/// Encode `data` with a table built for it and decode it again, panicking if anything differs.
+/// The `huff0` fuzz target calls this.
+#[cfg(feature = "encoder")]
+#[allow(
+    clippy::panic,
+    clippy::unwrap_used,
+    clippy::disallowed_macros,
+    clippy::disallowed_methods
+)]
 pub fn round_trip(data: &[u8]) {
     if data.len() < 2 {
         return;
@@ -21,7 +84,7 @@ pub fn round_trip(data: &[u8]) {
     let encoder_table = huff0_encoder::HuffmanTable::build_from_data(data);
     let mut encoder = huff0_encoder::HuffmanEncoder::new(encoder_table, &mut writer);
 
-    encoder.encode(data);
+    encoder.encode(data).unwrap();
     let encoded = writer.dump();
     let mut decoder_table = HuffmanTable::new();
     let table_bytes = decoder_table.build_decoder(&encoded).unwrap();
@@ -50,6 +113,27 @@ pub fn round_trip(data: &[u8]) {
     assert_eq!(&decoded, data);
 }
 
+#[cfg(feature = "encoder")]
+#[test]
+fn debug_output() {
+    let data = b"abracadabra";
+    let enc_table = huff0_encoder::HuffmanTable::build_from_data(data);
+    let mut writer = BitWriter::new();
+    huff0_encoder::HuffmanEncoder::new(enc_table.clone(), &mut writer)
+        .encode(data)
+        .unwrap();
+    let mut dec_table = HuffmanTable::new();
+    dec_table.build_decoder(&writer.dump()).unwrap();
+
+    let output = alloc::format!("{:?}", dec_table);
+    assert_eq!(
+        output,
+        "HuffmanTable { max_num_bits: 3, codes: {0x61: 1, 0x62: 000, 0x63: 001, 0x64: 010, 0x72: 011} }"
+    );
+    assert_eq!(output, alloc::format!("{:?}", enc_table));
+}
+
+#[cfg(feature = "encoder")]
 #[test]
 fn roundtrip() {
     round_trip(&[1, 1, 1, 1, 2, 3]);
@@ -2,12 +2,15 @@
 
 use alloc::boxed::Box;
 
+use crate::fallible::TryVec;
+
 #[non_exhaustive]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Ord, PartialOrd)]
 pub enum ErrorKind {
     Interrupted,
     UnexpectedEof,
     WouldBlock,
+    OutOfMemory,
     Other,
 }
 
@@ -18,6 +21,7 @@ impl ErrorKind {
             Interrupted => "operation interrupted",
             UnexpectedEof => "unexpected end of file",
             WouldBlock => "operation would block",
+            OutOfMemory => "out of memory",
             Other => "other error",
         }
     }
@@ -109,6 +113,25 @@ pub trait Read {
             Ok(())
         }
     }
+
+    fn read_to_end(&mut self, output: &mut alloc::vec::Vec<u8>) -> Result<usize, Error> {
+        let mut buf = [0u8; crate::STACK_BUFFER_SIZE];
+        let mut bytes_read = 0;
+        loop {
+            match self.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    output
+                        .try_extend_from_slice(&buf[..n])
+                        .map_err(|_| Error::from(ErrorKind::OutOfMemory))?;
+                    bytes_read += n;
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(bytes_read)
+    }
 }
 
 impl Read for &[u8] {
@@ -139,6 +162,20 @@ where
 pub trait Write {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Error>;
     fn flush(&mut self) -> Result<(), Error>;
+
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            match self.write(buf) {
+                Ok(0) => {
+                    return Err(Error::from(ErrorKind::Other));
+                }
+                Ok(n) => buf = &buf[n..],
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<T> Write for &mut T
@@ -154,6 +191,18 @@ where
     }
 }
 
+impl Write for alloc::vec::Vec<u8> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.try_extend_from_slice(buf)
+            .map_err(|_| Error::from(ErrorKind::OutOfMemory))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
 impl Write for &mut [u8] {
     #[inline]
     fn write(&mut self, data: &[u8]) -> Result<usize, Error> {
@@ -1,19 +1,58 @@
 //! A pure Rust implementation of the [Zstandard compression algorithm](https://facebook.github.io/zstd/).
 //!
 //! # Getting Started
+//! [compress] and [decompress] handle whole buffers in a single call.
+//!
 //! ## Decompression
 //! The [decoding] module contains the internals for decompression.
 //! Decompression can be achieved by using the [`StreamingDecoder`] interface.
 //!
 //! ## Compression
-//! Although functionality has not yet been implemented past raw frames, refer to the
-//! [encoding] module for more info.
+//! The [encoding] module contains the compressor, which needs the `encoder` feature.
+//! [`encoding::FrameCompressor`] compresses whole inputs and [`encoding::StreamingEncoder`] data
+//! that is written to it. They implement the negative levels, the levels 1 (`Fastest`) to 19, and
+//! the levels 20 to 22 with [`encoding::EncodeOptions::ultra`], with checksums and dictionaries. The frames decode with any zstd decoder, but the compressed blocks are not the
+//! same as the ones of the reference implementation at the same level.
 //!
 //! # Speed
 //! The decoder has been measured to be roughly between 3.5 to 1.4 times slower
 //! than the original implementation.
+//!
+//! # Threads
+//! All decoders and encoders, e.g. [`FrameDecoder`], [`StreamingDecoder`],
+//! [`PushDecoder`], [`encoding::FrameCompressor`] and [`encoding::StreamingEncoder`],
+//! are `Send` and `Sync` as long as the reader or writer they wrap is. They keep no thread local
+//! state, so they can be moved to another thread at any time, also in the middle of a frame, e.g.
+//! between two calls on a thread pool. All of their methods that change state take `&mut self`,
+//! so a shared reference only allows looking at them. The errors are `Send` and `Sync` as well.
+//!
+//! Dictionaries ([`decoding::dictionary::Dictionary`] and [`encoding::EncoderDictionary`]) and
+//! [`metrics::CodecMetrics`] are shared between the decoders and encoders of several threads
+//! through an `Arc`.
 #![no_std]
 #![deny(trivial_casts, trivial_numeric_casts, rust_2018_idioms)]
+// With the `no-panic` feature, clippy rejects explicit panics and allocations that abort when
+// there is not enough memory in the library, see the Readme. `assert!` and its siblings, and the
+// methods that allocate without reporting failure, are disallowed in clippy.toml, which only counts
+// here. That includes `debug_assert!`, so functions whose only checks are debug assertions allow
+// the lint.
+#![cfg_attr(
+    all(feature = "no-panic", not(test)),
+    deny(
+        clippy::panic,
+        clippy::unreachable,
+        clippy::todo,
+        clippy::unimplemented,
+        clippy::unwrap_used,
+        clippy::expect_used,
+        clippy::disallowed_macros,
+        clippy::disallowed_methods
+    )
+)]
+#![cfg_attr(
+    not(all(feature = "no-panic", not(test))),
+    allow(clippy::disallowed_macros, clippy::disallowed_methods)
+)]
 
 #[cfg(feature = "std")]
 extern crate std;
@@ -24,6 +63,14 @@ extern crate alloc;
 #[cfg(feature = "std")]
 pub const VERBOSE: bool = false;
 
+/// The size of the buffers on the stack that data is copied through, e.g. the content of raw blocks
+/// or frames that are skipped. The decoder never recurses and keeps all other large data on the heap,
+/// so these buffers decide most of the stack it needs.
+#[cfg(not(feature = "bounded-stack"))]
+pub(crate) const STACK_BUFFER_SIZE: usize = 4 * 1024;
+#[cfg(feature = "bounded-stack")]
+pub(crate) const STACK_BUFFER_SIZE: usize = 256;
+
 macro_rules! vprintln {
     ($($x:expr),*) => {
         #[cfg(feature = "std")]
@@ -33,25 +80,72 @@ macro_rules! vprintln {
     }
 }
 
+/// Emits a `tracing` event at debug level if the `tracing` feature is enabled.
+macro_rules! trace_event {
+    ($($x:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::debug!($($x)*);
+    };
+}
+
+/// Enters a `tracing` span at debug level until the end of the current scope if the `tracing` feature is enabled.
+macro_rules! trace_span {
+    ($($x:tt)*) => {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!($($x)*).entered();
+    };
+}
+
+mod bits;
 pub mod blocks;
+pub mod capabilities;
+pub mod codec;
+pub mod conformance;
 pub mod decoding;
+pub mod dissect;
 pub mod encoding;
+pub mod error;
+mod fallible;
 pub mod frame;
 pub mod frame_decoder;
+pub mod frame_utils;
 pub mod fse;
 pub mod huff0;
+pub mod metrics;
+mod one_shot;
+pub mod prelude;
+pub mod push_decoder;
+pub mod raw;
+pub mod seekable;
+#[cfg(feature = "self-test")]
+pub mod self_test;
+pub mod skippable_frame;
 pub mod streaming_decoder;
 mod tests;
+#[cfg(feature = "hash")]
+pub mod xxhash;
 
 #[cfg(feature = "std")]
 pub mod io;
 
+#[cfg(feature = "fuzzing")]
+pub mod fuzzing;
+#[cfg(any(feature = "test-utils", all(test, feature = "encoder")))]
+pub mod test_utils;
+
 #[cfg(not(feature = "std"))]
 pub mod io_nostd;
 
 #[cfg(not(feature = "std"))]
 pub use io_nostd as io;
 
+pub use error::{Error, ErrorCategory};
 pub use frame_decoder::BlockDecodingStrategy;
 pub use frame_decoder::FrameDecoder;
+#[cfg(feature = "encoder")]
+pub use one_shot::compress;
+pub use one_shot::decompress;
+#[cfg(feature = "encoder")]
+pub use one_shot::try_compress;
+pub use push_decoder::PushDecoder;
 pub use streaming_decoder::StreamingDecoder;
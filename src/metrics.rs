@@ -0,0 +1,316 @@
+//! Hooks for collecting metrics from encoders and decoders.
+//!
+//! Implement [CodecMetrics] to forward the numbers to whatever metrics system is in use, and register
+//! it with [EncodeOptions::metrics](crate::encoding::EncodeOptions::metrics) or
+//! [FrameDecoder::set_metrics](crate::frame_decoder::FrameDecoder::set_metrics).
+//! All methods take `&self`, so one collector can be shared between many encoders and decoders.
+//!
+//! ```
+//! # #[cfg(feature = "encoder")] {
+//! use std::sync::atomic::{AtomicU64, Ordering};
+//! use std::sync::Arc;
+//! use ruzstd::encoding::{EncodeOptions, FrameCompressor};
+//! use ruzstd::metrics::CodecMetrics;
+//!
+//! #[derive(Default)]
+//! struct Counters {
+//!     bytes_in: AtomicU64,
+//!     bytes_out: AtomicU64,
+//! }
+//!
+//! impl CodecMetrics for Counters {
+//!     fn bytes_in(&self, bytes: u64) {
+//!         self.bytes_in.fetch_add(bytes, Ordering::Relaxed);
+//!     }
+//!     fn bytes_out(&self, bytes: u64) {
+//!         self.bytes_out.fetch_add(bytes, Ordering::Relaxed);
+//!     }
+//! }
+//!
+//! let counters = Arc::new(Counters::default());
+//! let options = EncodeOptions {
+//!     metrics: Some(counters.clone()),
+//!     ..EncodeOptions::default()
+//! };
+//! let data = b"Hello, Hello, Hello, World!";
+//! let mut compressed = Vec::new();
+//! FrameCompressor::with_options(&data[..], &mut compressed, options)
+//!     .compress()
+//!     .unwrap();
+//! assert_eq!(counters.bytes_in.load(Ordering::Relaxed), data.len() as u64);
+//! assert_eq!(counters.bytes_out.load(Ordering::Relaxed), compressed.len() as u64);
+//! # }
+//! ```
+
+use core::time::Duration;
+
+use crate::blocks::block::BlockType;
+use crate::blocks::literals_section::LiteralsSectionType;
+use crate::blocks::sequence_section::ModeType;
+
+/// The parts of encoding and decoding whose duration is reported to [CodecMetrics::stage_time].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Stage {
+    /// Compressing a block and writing it to the output.
+    EncodeBlock,
+    /// Reading a block and decoding it into the window.
+    DecodeBlock,
+}
+
+/// Receives metrics from encoders and decoders. All methods do nothing by default.
+pub trait CodecMetrics: Send + Sync {
+    /// `bytes` were consumed: uncompressed data by an encoder, compressed data by a decoder.
+    fn bytes_in(&self, bytes: u64) {
+        let _ = bytes;
+    }
+
+    /// `bytes` were produced: compressed data by an encoder, decompressed data by a decoder.
+    fn bytes_out(&self, bytes: u64) {
+        let _ = bytes;
+    }
+
+    /// A block of `block_type` was written or read.
+    fn block(&self, block_type: BlockType) {
+        let _ = block_type;
+    }
+
+    /// A decoder decoded a block, which is described by `block`. Called right after
+    /// [CodecMetrics::block], so the structure of a stream can be recorded while it is decoded.
+    fn decoded_block(&self, block: &DecodedBlock) {
+        let _ = block;
+    }
+
+    /// A `stage` took `time`. Only reported with the `std` feature, which provides the clock.
+    fn stage_time(&self, stage: Stage, time: Duration) {
+        let _ = (stage, time);
+    }
+}
+
+/// A block that was decoded, see [CodecMetrics::decoded_block].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DecodedBlock {
+    /// The index of the block in its frame, starting at 0.
+    pub index: usize,
+    pub block_type: BlockType,
+    /// Whether this is the last block of its frame.
+    pub last_block: bool,
+    /// The number of bytes the block takes up in the frame, including its 3 byte header.
+    pub compressed_size: u64,
+    /// The number of bytes the block decoded to.
+    pub decompressed_size: u64,
+    /// How the literals of a compressed block are stored, `None` for raw and RLE blocks.
+    pub literals_type: Option<LiteralsSectionType>,
+    /// The number of sequences of a compressed block, 0 for raw and RLE blocks.
+    pub num_sequences: u32,
+    /// The modes of the literal length, offset and match length codes of a compressed block, if
+    /// it has sequences.
+    pub sequence_modes: Option<[ModeType; 3]>,
+}
+
+impl core::fmt::Debug for dyn CodecMetrics {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("CodecMetrics")
+    }
+}
+
+/// Measures the time of a [Stage] if there is a clock.
+pub(crate) struct StageTimer {
+    #[cfg(feature = "std")]
+    start: std::time::Instant,
+}
+
+impl StageTimer {
+    pub(crate) fn start() -> Self {
+        Self {
+            #[cfg(feature = "std")]
+            start: std::time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn finish(self, metrics: &dyn CodecMetrics, stage: Stage) {
+        #[cfg(feature = "std")]
+        metrics.stage_time(stage, self.start.elapsed());
+        #[cfg(not(feature = "std"))]
+        let _ = (metrics, stage);
+    }
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::{CodecMetrics, Stage};
+    use crate::blocks::block::BlockType;
+    use crate::encoding::{CompressionLevel, FrameCompressorBuilder};
+    use crate::frame_decoder::FrameDecoder;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use core::time::Duration;
+
+    #[derive(Default)]
+    struct Counters {
+        bytes_in: AtomicU64,
+        bytes_out: AtomicU64,
+        raw_blocks: AtomicU64,
+        rle_blocks: AtomicU64,
+        compressed_blocks: AtomicU64,
+        timed_blocks: AtomicU64,
+    }
+
+    impl CodecMetrics for Counters {
+        fn bytes_in(&self, bytes: u64) {
+            self.bytes_in.fetch_add(bytes, Ordering::Relaxed);
+        }
+        fn bytes_out(&self, bytes: u64) {
+            self.bytes_out.fetch_add(bytes, Ordering::Relaxed);
+        }
+        fn block(&self, block_type: BlockType) {
+            let counter = match block_type {
+                BlockType::Raw => &self.raw_blocks,
+                BlockType::RLE => &self.rle_blocks,
+                BlockType::Compressed => &self.compressed_blocks,
+                BlockType::Reserved => panic!("Reserved block reported"),
+            };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        fn stage_time(&self, _stage: Stage, _time: Duration) {
+            self.timed_blocks.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn encoder_and_decoder_agree() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&[7; 200_000]);
+        data.extend(b"Hello, World! ".iter().cycle().take(100_000));
+        data.extend((0..100_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+
+        let encoded = Arc::new(Counters::default());
+        let mut compressed = Vec::new();
+        FrameCompressorBuilder::new()
+            .level(CompressionLevel::Fastest)
+            .metrics(encoded.clone())
+            .build(&data[..], &mut compressed)
+            .unwrap()
+            .compress()
+            .unwrap();
+
+        let decoded = Arc::new(Counters::default());
+        let mut decoder = FrameDecoder::new();
+        decoder.set_metrics(Some(decoded.clone()));
+        let mut decompressed = Vec::with_capacity(data.len());
+        decoder
+            .decode_all_to_vec(&compressed, &mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, data);
+
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        assert_eq!(load(&encoded.bytes_in), data.len() as u64);
+        assert_eq!(load(&encoded.bytes_out), compressed.len() as u64);
+        assert_eq!(load(&decoded.bytes_in), compressed.len() as u64);
+        assert_eq!(load(&decoded.bytes_out), data.len() as u64);
+        for (encoded, decoded) in [
+            (&encoded.raw_blocks, &decoded.raw_blocks),
+            (&encoded.rle_blocks, &decoded.rle_blocks),
+            (&encoded.compressed_blocks, &decoded.compressed_blocks),
+        ] {
+            assert_eq!(load(encoded), load(decoded));
+        }
+        assert!(load(&decoded.rle_blocks) > 0);
+        assert!(load(&decoded.compressed_blocks) > 0);
+        #[cfg(feature = "std")]
+        {
+            let blocks = load(&decoded.raw_blocks)
+                + load(&decoded.rle_blocks)
+                + load(&decoded.compressed_blocks);
+            assert_eq!(load(&decoded.timed_blocks), blocks);
+            assert_eq!(load(&encoded.timed_blocks), blocks);
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[derive(Default)]
+    struct Recorder {
+        blocks: std::sync::Mutex<Vec<super::DecodedBlock>>,
+    }
+
+    #[cfg(feature = "std")]
+    impl CodecMetrics for Recorder {
+        fn decoded_block(&self, block: &super::DecodedBlock) {
+            self.blocks.lock().unwrap().push(*block);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn decoded_blocks() {
+        use crate::io::Read;
+        use crate::streaming_decoder::StreamingDecoder;
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&[7; 200_000]);
+        data.extend(b"Hello, World! ".iter().cycle().take(100_000));
+        data.extend((0..100_000u32).map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8));
+        let mut compressed = Vec::new();
+        FrameCompressorBuilder::new()
+            .level(CompressionLevel::Fastest)
+            .build(&data[..], &mut compressed)
+            .unwrap()
+            .compress()
+            .unwrap();
+
+        // The blocks are reported as they are, whichever way the frame is decoded
+        let dissection = crate::dissect::dissect(&compressed);
+        let recorder = Arc::new(Recorder::default());
+        let mut decoder = FrameDecoder::new();
+        decoder.set_metrics(Some(recorder.clone()));
+        let mut decompressed = Vec::with_capacity(data.len());
+        decoder
+            .decode_all_to_vec(&compressed, &mut decompressed)
+            .unwrap();
+        let blocks = core::mem::take(&mut *recorder.blocks.lock().unwrap());
+
+        let mut decoder = FrameDecoder::new();
+        decoder.set_metrics(Some(recorder.clone()));
+        let mut streaming = StreamingDecoder::new_with_decoder(&compressed[..], decoder).unwrap();
+        let mut streamed = Vec::new();
+        streaming.read_to_end(&mut streamed).unwrap();
+        assert_eq!(streamed, data);
+        assert_eq!(*recorder.blocks.lock().unwrap(), blocks);
+
+        assert_eq!(blocks.len(), dissection.blocks.len());
+        let sizes: u64 = blocks.iter().map(|block| block.decompressed_size).sum();
+        assert_eq!(sizes, data.len() as u64);
+        for (index, (block, dissected)) in blocks.iter().zip(&dissection.blocks).enumerate() {
+            assert_eq!(block.index, index);
+            assert_eq!(block.block_type, dissected.block_type);
+            assert_eq!(block.last_block, dissected.last_block);
+            let content_size = match block.block_type {
+                BlockType::RLE => 1,
+                _ => u64::from(dissected.block_size),
+            };
+            assert_eq!(block.compressed_size, 3 + content_size);
+            assert_eq!(
+                block.literals_type,
+                dissected
+                    .literals
+                    .as_ref()
+                    .map(|literals| literals.section_type)
+            );
+            let sequences = dissected.sequences.as_ref();
+            assert_eq!(
+                block.num_sequences,
+                sequences.map_or(0, |sequences| sequences.num_sequences)
+            );
+            let modes = sequences
+                .filter(|sequences| !sequences.tables.is_empty())
+                .map(|sequences| [0, 1, 2].map(|i| sequences.tables[i].mode));
+            assert_eq!(block.sequence_modes, modes);
+        }
+        assert!(blocks.iter().any(|block| block.sequence_modes.is_some()));
+        assert!(blocks
+            .iter()
+            .any(|block| block.block_type == BlockType::RLE));
+    }
+}
@@ -0,0 +1,154 @@
+//! Compress or decompress a whole buffer with a single call.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+
+#[cfg(feature = "encoder")]
+use crate::encoding::{EncodeError, EncodeOptions, FrameCompressor};
+use crate::error::Error;
+use crate::frame::ReadFrameHeaderError;
+use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+
+/// A block of 4 bytes (an RLE block) decodes to at most 128 KiB, so no frame can decode to more
+/// than this many bytes per byte of input. Declared content sizes beyond that are bogus and
+/// are not used to allocate memory up front.
+const MAX_EXPANSION: u64 = 128 * 1024 / 4;
+
+/// Compress `data` into a single frame, like the zstd command line tool does by default
+/// (see [EncodeOptions::cli_default]).
+///
+/// Like collecting into a `Vec`, this panics if there is not enough memory, see the Readme.
+/// [try_compress] returns an error instead.
+///
+/// ```
+/// let compressed = ruzstd::compress(b"Hello, World!");
+/// assert_eq!(ruzstd::decompress(&compressed).unwrap(), b"Hello, World!");
+/// ```
+#[cfg(feature = "encoder")]
+#[allow(clippy::expect_used)]
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    try_compress(data).expect("compressing into a Vec failed")
+}
+
+/// Like [compress], but running out of memory is reported as [EncodeError::OutOfMemory].
+///
+/// Writing into a `Vec` can not fail otherwise, so any other error means the encoder has a bug.
+#[cfg(feature = "encoder")]
+pub fn try_compress(data: &[u8]) -> Result<Vec<u8>, EncodeError> {
+    let mut compressed = Vec::new();
+    FrameCompressor::with_options(data, &mut compressed, EncodeOptions::cli_default())
+        .compress()?;
+    Ok(compressed)
+}
+
+/// Decompress all frames in `data` and return their content, skippable frames are ignored.
+///
+/// The output is allocated up front if the frames declare their content size. With the `hash`
+/// feature, checksums are verified and mismatches are reported as
+/// [FrameDecoderError::ChecksumMismatch].
+///
+/// Errors are reported as [Error::Decode].
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut decoder = FrameDecoder::new();
+    let mut output = Vec::new();
+    let mut source = data;
+    let mut first_frame = true;
+    while !source.is_empty() {
+        let position = (data.len() - source.len()) as u64;
+        match decoder.reset(&mut source) {
+            Ok(()) => {}
+            Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                length,
+                ..
+            })) => {
+                source = source
+                    .get(length as usize..)
+                    .ok_or(FrameDecoderError::FailedToSkipFrame)?;
+                first_frame = false;
+                continue;
+            }
+            Err(FrameDecoderError::ReadFrameHeaderError(
+                ReadFrameHeaderError::BadMagicNumber(_)
+                | ReadFrameHeaderError::MagicNumberReadError(_),
+            )) if !first_frame => {
+                return Err(FrameDecoderError::TrailingData { position }.into());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        first_frame = false;
+
+        let limit = (source.len() as u64).saturating_mul(MAX_EXPANSION);
+        let expected = u64::min(decoder.content_size(), limit);
+        // The declared size is only a hint, the output still grows as needed if this fails
+        let _ = output.try_reserve(usize::try_from(expected).unwrap_or(0));
+        while !decoder.is_finished() {
+            decoder.decode_blocks(&mut source, BlockDecodingStrategy::UptoBytes(1024 * 1024))?;
+            decoder
+                .collect_to_writer(&mut output)
+                .map_err(FrameDecoderError::FailedToDrainDecodebuffer)?;
+        }
+        decoder
+            .collect_to_writer(&mut output)
+            .map_err(FrameDecoderError::FailedToDrainDecodebuffer)?;
+
+        #[cfg(feature = "hash")]
+        if let (Some(expected), Some(calculated)) = (
+            decoder.get_checksum_from_data(),
+            decoder.get_calculated_checksum(),
+        ) {
+            if expected != calculated {
+                return Err(FrameDecoderError::ChecksumMismatch {
+                    expected,
+                    calculated,
+                }
+                .into());
+            }
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::{compress, decompress, try_compress};
+    use crate::error::Error;
+    use crate::frame_decoder::FrameDecoderError;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn roundtrip() {
+        for input in [
+            Vec::new(),
+            b"a".to_vec(),
+            (0..300_000u32).map(|i| (i % 77) as u8).collect::<Vec<_>>(),
+        ] {
+            let compressed = compress(&input);
+            assert_eq!(try_compress(&input).unwrap(), compressed);
+            assert_eq!(decompress(&compressed).unwrap(), input);
+        }
+
+        // several frames and skippable frames are decoded like the zstd CLI does
+        let mut frames = compress(b"first ");
+        frames.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 2, 0, 0, 0, 0xAA, 0xBB]);
+        frames.extend(compress(b"second"));
+        assert_eq!(decompress(&frames).unwrap(), b"first second");
+
+        frames.extend_from_slice(b"garbage");
+        assert!(matches!(
+            decompress(&frames),
+            Err(Error::Decode(FrameDecoderError::TrailingData { .. }))
+        ));
+    }
+
+    #[cfg(feature = "hash")]
+    #[test]
+    fn checksum_is_verified() {
+        let mut compressed = compress(b"some content that is checked");
+        let len = compressed.len();
+        compressed[len - 1] ^= 1;
+        assert!(matches!(
+            decompress(&compressed),
+            Err(Error::Decode(FrameDecoderError::ChecksumMismatch { .. }))
+        ));
+    }
+}
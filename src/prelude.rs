@@ -0,0 +1,23 @@
+//! The types most programs need, so typical usage is a single `use`:
+//!
+//! ```
+//! # #[cfg(feature = "encoder")] {
+//! use ruzstd::prelude::*;
+//!
+//! let mut compressed = Vec::new();
+//! FrameCompressor::with_options(&b"some data"[..], &mut compressed, EncodeOptions::cli_default())
+//!     .compress()
+//!     .unwrap();
+//! let mut decoder = StreamingDecoder::new(compressed.as_slice()).unwrap();
+//! # }
+//! ```
+
+pub use crate::decoding::decode_options::DecodeOptions;
+#[cfg(feature = "encoder")]
+pub use crate::encoding::{
+    CompressionLevel, ConfigError, EncodeError, EncodeOptions, FrameCompressor,
+    FrameCompressorBuilder, StreamingEncoder,
+};
+pub use crate::error::{Error, ErrorCategory};
+pub use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+pub use crate::streaming_decoder::StreamingDecoder;
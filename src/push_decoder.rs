@@ -0,0 +1,337 @@
+//! Decoding of compressed data that arrives in chunks, see [PushDecoder].
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::mem::MaybeUninit;
+
+use crate::blocks::block::BlockHeader;
+use crate::fallible::TryVec;
+use crate::frame::ReadFrameHeaderError;
+use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+use crate::io::{Error, ErrorKind, Read};
+use crate::streaming_decoder::to_io_error;
+
+/// Decodes frames from chunks of compressed data that are pushed into it, without a reader.
+///
+/// Chunks of any size are handed to [PushDecoder::feed] as they arrive, e.g. from a socket, and
+/// [PushDecoder::read] decodes as much as it can from them. Data that does not form a whole block yet
+/// is kept until the rest of it is fed. Several frames can follow each other, skippable frames are
+/// skipped.
+///
+/// With [PushDecoder::read_from] the data is read from a non-blocking reader instead, which may run
+/// out of data at any point without losing what has been read so far.
+///
+/// ```
+/// # #[cfg(feature = "encoder")] {
+/// use ruzstd::push_decoder::PushDecoder;
+///
+/// let compressed = ruzstd::compress(b"Hello, World!");
+/// let mut decoder = PushDecoder::new();
+/// let mut output = Vec::new();
+/// let mut buf = [0; 4];
+/// for chunk in compressed.chunks(3) {
+///     decoder.feed(chunk).unwrap();
+///     loop {
+///         let n = decoder.read(&mut buf).unwrap();
+///         if n == 0 {
+///             break;
+///         }
+///         output.extend_from_slice(&buf[..n]);
+///     }
+/// }
+/// assert!(decoder.is_finished());
+/// assert_eq!(output, b"Hello, World!");
+/// # }
+/// ```
+pub struct PushDecoder {
+    decoder: FrameDecoder,
+    /// Data that was fed but not decoded yet, starting at `consumed`
+    input: Vec<u8>,
+    consumed: usize,
+    /// Whether the header of a frame has been read and the frame has not been read completely
+    in_frame: bool,
+    /// The number of bytes of a skippable frame that still have to be skipped
+    skip: u64,
+}
+
+impl PushDecoder {
+    /// Create a decoder that is waiting for the first frame.
+    pub fn new() -> Self {
+        Self::with_decoder(FrameDecoder::new())
+    }
+
+    /// Create a decoder that decodes with `decoder`, e.g. to use its dictionaries and window limit.
+    pub fn with_decoder(decoder: FrameDecoder) -> Self {
+        Self {
+            decoder,
+            input: Vec::new(),
+            consumed: 0,
+            in_frame: false,
+            skip: 0,
+        }
+    }
+
+    /// The [FrameDecoder] that decodes the frames.
+    pub fn decoder_mut(&mut self) -> &mut FrameDecoder {
+        &mut self.decoder
+    }
+
+    /// Discard the data that was fed and the output that was not read yet, and wait for a new frame.
+    pub fn reset(&mut self) {
+        // Leaves only the window of an unfinished frame, which the next frame replaces
+        self.decoder.discard();
+        self.input.clear();
+        self.consumed = 0;
+        self.in_frame = false;
+        self.skip = 0;
+    }
+
+    /// Add the next chunk of compressed data.
+    ///
+    /// Fails with [FrameDecoderError::BufferOutOfMemory] if there is no memory to keep it, then
+    /// nothing of the chunk was added.
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), FrameDecoderError> {
+        let skipped = usize::try_from(self.skip)
+            .unwrap_or(usize::MAX)
+            .min(chunk.len());
+        // Keep the buffer from growing with data that has been decoded already
+        if self.consumed > self.input.len() / 2 {
+            self.input.drain(..self.consumed);
+            self.consumed = 0;
+        }
+        self.input.try_room(chunk.len() - skipped)?;
+        self.skip -= skipped as u64;
+        self.input.try_extend_from_slice(&chunk[skipped..])?;
+        Ok(())
+    }
+
+    /// Decode into `target` and return the number of bytes written.
+    ///
+    /// Returns 0 if all data that was fed so far has been decoded and read, more has to be fed first.
+    pub fn read(&mut self, target: &mut [u8]) -> Result<usize, FrameDecoderError> {
+        while self.decoder.can_collect() < target.len() && self.decode_next()? {}
+        self.decoder
+            .read(target)
+            .map_err(FrameDecoderError::FailedToDrainDecodebuffer)
+    }
+
+    /// Like [PushDecoder::read], but `target` does not have to be initialized. Exactly the first
+    /// bytes of `target` that were written are initialized afterwards.
+    pub fn read_uninit(
+        &mut self,
+        target: &mut [MaybeUninit<u8>],
+    ) -> Result<usize, FrameDecoderError> {
+        while self.decoder.can_collect() < target.len() && self.decode_next()? {}
+        self.decoder
+            .read_uninit(target)
+            .map_err(FrameDecoderError::FailedToDrainDecodebuffer)
+    }
+
+    /// Decode into `target` with compressed data that is read from `source` as needed, and return
+    /// the number of bytes written.
+    ///
+    /// `source` may be non-blocking: if it fails with [ErrorKind::WouldBlock] before the data for the
+    /// next block is complete, that error is returned and everything read so far is kept, so the call
+    /// can be repeated once the source is ready again. Returns 0 once `source` has ended after
+    /// complete frames and fails with [ErrorKind::UnexpectedEof] if it ends within a frame.
+    pub fn read_from(&mut self, mut source: impl Read, target: &mut [u8]) -> Result<usize, Error> {
+        let mut chunk = [0; crate::STACK_BUFFER_SIZE];
+        loop {
+            let written = self.read(target).map_err(to_io_error)?;
+            if written != 0 || target.is_empty() {
+                return Ok(written);
+            }
+            match source.read(&mut chunk) {
+                Ok(0) if self.is_finished() => return Ok(0),
+                Ok(0) => return Err(Error::from(ErrorKind::UnexpectedEof)),
+                Ok(read) => self.feed(&chunk[..read]).map_err(to_io_error)?,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Whether all frames that were fed have been decoded and read completely, and no partial frame
+    /// is left.
+    pub fn is_finished(&self) -> bool {
+        !self.in_frame
+            && self.skip == 0
+            && self.consumed == self.input.len()
+            && self.decoder.can_collect() == 0
+    }
+
+    /// Read the next frame header or block from the input. Returns false if that needs more input,
+    /// or if the output of the last frame has to be read before the next frame can start.
+    fn decode_next(&mut self) -> Result<bool, FrameDecoderError> {
+        let mut input = &self.input[self.consumed..];
+        if input.is_empty() || self.skip > 0 {
+            return Ok(false);
+        }
+        let available = input.len();
+
+        if !self.in_frame {
+            if self.decoder.can_collect() != 0 {
+                // Starting the next frame would discard the remaining output of the last one
+                return Ok(false);
+            }
+            match self.decoder.reset(&mut input) {
+                Ok(()) => self.in_frame = true,
+                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                    length,
+                    ..
+                })) => {
+                    let skipped = usize::min(length as usize, input.len());
+                    self.skip = u64::from(length) - skipped as u64;
+                    input = &input[skipped..];
+                }
+                Err(FrameDecoderError::ReadFrameHeaderError(
+                    ReadFrameHeaderError::MagicNumberReadError(_)
+                    | ReadFrameHeaderError::FrameDescriptorReadError(_)
+                    | ReadFrameHeaderError::WindowDescriptorReadError(_)
+                    | ReadFrameHeaderError::DictionaryIdReadError(_)
+                    | ReadFrameHeaderError::FrameContentSizeReadError(_),
+                )) => return Ok(false),
+                Err(e) => return Err(e),
+            }
+        } else {
+            // Only decode the block once all of it is there
+            let header = match BlockHeader::parse(input) {
+                Ok(header) => header,
+                Err(_) if input.len() < 3 => return Ok(false),
+                Err(e) => return Err(FrameDecoderError::FailedToReadBlockHeader(e)),
+            };
+            let mut needed = BlockHeader::SIZE + header.content_size as usize;
+            if header.last_block && self.decoder.has_checksum() {
+                needed += 4;
+            }
+            if input.len() < needed {
+                return Ok(false);
+            }
+            self.decoder
+                .decode_from_slice(&mut input, BlockDecodingStrategy::UptoBlocks(1))?;
+            if self.decoder.is_finished() {
+                self.in_frame = false;
+            }
+        }
+        self.consumed += available - input.len();
+        Ok(true)
+    }
+}
+
+impl Default for PushDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::PushDecoder;
+    use crate::frame_decoder::FrameDecoderError;
+    use alloc::vec::Vec;
+
+    /// Feed `input` in chunks of `chunk_size` and read the output in pieces of `read_size`
+    fn decode(input: &[u8], chunk_size: usize, read_size: usize) -> Vec<u8> {
+        let mut decoder = PushDecoder::new();
+        let mut output = Vec::new();
+        let mut buf = alloc::vec![0; read_size];
+        for chunk in input.chunks(chunk_size) {
+            decoder.feed(chunk).unwrap();
+            loop {
+                let n = decoder.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                output.extend_from_slice(&buf[..n]);
+            }
+        }
+        assert!(decoder.is_finished());
+        output
+    }
+
+    #[test]
+    fn chunks_of_any_size() {
+        let first = crate::test_utils::text_like(400_000, 1);
+        let mut input = crate::compress(&first);
+        input.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 5, 0, 0, 0, 1, 2, 3, 4, 5]);
+        input.extend(crate::compress(b"second"));
+        input.extend_from_slice(include_bytes!("../decodecorpus_files/z000089.zst"));
+        let mut expected = first.clone();
+        expected.extend_from_slice(b"second");
+        expected.extend_from_slice(include_bytes!("../decodecorpus_files/z000089"));
+
+        for (chunk_size, read_size) in [(1, 7), (3, 1000), (1000, 100_000), (input.len(), 1)] {
+            assert_eq!(decode(&input, chunk_size, read_size), expected);
+        }
+    }
+
+    #[test]
+    fn non_blocking_source() {
+        use crate::io::{Error, ErrorKind, Read};
+
+        /// Hands out a few bytes at a time, with a `WouldBlock` in between
+        struct NonBlocking<'a> {
+            data: &'a [u8],
+            blocked: bool,
+        }
+
+        impl Read for NonBlocking<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+                self.blocked = !self.blocked;
+                if self.blocked {
+                    return Err(Error::from(ErrorKind::WouldBlock));
+                }
+                let n = buf.len().min(self.data.len()).min(5);
+                buf[..n].copy_from_slice(&self.data[..n]);
+                self.data = &self.data[n..];
+                Ok(n)
+            }
+        }
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 97) as u8).collect();
+        let compressed = crate::compress(&data);
+        let mut source = NonBlocking {
+            data: &compressed,
+            blocked: false,
+        };
+        let mut decoder = PushDecoder::new();
+        let mut output = Vec::new();
+        let mut buf = alloc::vec![0; 1000];
+        let mut would_block = 0;
+        loop {
+            match decoder.read_from(&mut source, &mut buf) {
+                Ok(0) => break,
+                Ok(n) => output.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => would_block += 1,
+                Err(e) => panic!("{:?}", e),
+            }
+        }
+        assert!(would_block > 0);
+        assert_eq!(output, data);
+
+        let mut decoder = PushDecoder::new();
+        let truncated = &compressed[..compressed.len() - 1];
+        let err = decoder.read_from(truncated, &mut buf).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn partial_input() {
+        let input = crate::compress(b"some content");
+        let mut decoder = PushDecoder::new();
+        decoder.feed(&input[..input.len() - 1]).unwrap();
+        assert_eq!(decoder.read(&mut [0; 100]).unwrap(), 0);
+        assert!(!decoder.is_finished());
+        decoder.feed(&input[input.len() - 1..]).unwrap();
+        let mut output = [0; 100];
+        assert_eq!(decoder.read(&mut output).unwrap(), 12);
+        assert_eq!(&output[..12], b"some content");
+        assert!(decoder.is_finished());
+
+        decoder.feed(b"garbage").unwrap();
+        assert!(matches!(
+            decoder.read(&mut output),
+            Err(FrameDecoderError::ReadFrameHeaderError(_))
+        ));
+    }
+}
@@ -0,0 +1,1024 @@
+//! Compression and decompression between caller-provided buffers, in the style of the `flate2` crate.
+//!
+//! [Compress] and [Decompress] never read or write on their own. Each call takes the input and the
+//! output buffer it may use, and the caller learns from [Compress::total_in] and
+//! [Compress::total_out] (or the ones of [Decompress]) how much of the input was consumed and how
+//! much output was produced. Applications that are built around `flate2::Compress` and
+//! `flate2::Decompress` can switch to zstd without changing how they move data around.
+//!
+//! ```
+//! # #[cfg(feature = "encoder")] {
+//! use ruzstd::encoding::CompressionLevel;
+//! use ruzstd::raw::{Compress, Decompress, FlushCompress, FlushDecompress, Status};
+//!
+//! let data = b"Hello, World! Hello, World! Hello, World!";
+//! let mut compress = Compress::new(CompressionLevel::Fastest).unwrap();
+//! let mut compressed = [0; 100];
+//! let status = compress
+//!     .compress(data, &mut compressed, FlushCompress::Finish)
+//!     .unwrap();
+//! assert_eq!(status, Status::StreamEnd);
+//! let compressed = &compressed[..compress.total_out() as usize];
+//!
+//! let mut decompress = Decompress::new();
+//! let mut decompressed = Vec::with_capacity(100);
+//! let status = decompress
+//!     .decompress_vec(compressed, &mut decompressed, FlushDecompress::Finish)
+//!     .unwrap();
+//! assert_eq!(status, Status::StreamEnd);
+//! assert_eq!(decompressed, data);
+//! # }
+//! ```
+
+#[cfg(feature = "encoder")]
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::mem::MaybeUninit;
+
+#[cfg(feature = "encoder")]
+use crate::encoding::{CompressionLevel, EncodeError, EncodeOptions, IncrementalEncoder};
+#[cfg(feature = "encoder")]
+use crate::fallible::{try_with_capacity, TryVec};
+use crate::frame_decoder::{FrameDecoder, FrameDecoderError};
+use crate::push_decoder::PushDecoder;
+#[cfg(feature = "bytes")]
+use bytes::{Buf, BufMut};
+
+/// How much of the input [Decompress] hands to its decoder at once.
+const DECOMPRESS_CHUNK_SIZE: usize = 32 * 1024;
+
+/// What a call to [Compress::compress] or [Decompress::decompress] achieved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    /// Some input was consumed or some output was produced, more calls are needed.
+    Ok,
+    /// Neither input was consumed nor output produced, because the output buffer is full or more
+    /// input is needed.
+    BufError,
+    /// The frame was finished, respectively all input ended with a complete frame, and all output
+    /// was produced.
+    StreamEnd,
+}
+
+/// What [Compress::compress] does once it has consumed all input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushCompress {
+    /// Keep input that does not fill a block until more input arrives, for the best compression.
+    None,
+    /// End the current block, so everything that was passed in so far can be decompressed from
+    /// the output.
+    Sync,
+    /// End the frame. Calls after the end of the frame only produce the rest of the output, until
+    /// the compressor is [reset](Compress::reset).
+    Finish,
+}
+
+/// What [Compress::compress_stream] does with the input, like `ZSTD_EndDirective` of the zstd
+/// library. The flate2 shaped [FlushCompress] converts into it.
+///
+/// The output of each call is a part of one frame: the frame header, whole blocks and, at the end,
+/// the checksum. A block is never split between the output of a call and input that is held
+/// back, so everything up to the end of a block can be decompressed once it was handed out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndDirective {
+    /// Compress the input that fills blocks, and keep the rest until more input arrives. The output
+    /// may not contain all input yet, in exchange blocks are as large as they can be.
+    Continue,
+    /// End the current block once all input was consumed, but keep the frame open. Once the call
+    /// reports no remaining work, the output contains everything that was passed in so far, so a
+    /// decoder can produce all of it. More input continues the same frame and can reference the
+    /// earlier input.
+    Flush,
+    /// End the frame once all input was consumed: the last block and the checksum, if one was
+    /// requested. Once the call reports no remaining work, the output is a complete frame. Later
+    /// calls take no input and produce no output until the compressor is
+    /// [reset](Compress::reset).
+    End,
+}
+
+impl From<FlushCompress> for EndDirective {
+    fn from(flush: FlushCompress) -> Self {
+        match flush {
+            FlushCompress::None => EndDirective::Continue,
+            FlushCompress::Sync => EndDirective::Flush,
+            FlushCompress::Finish => EndDirective::End,
+        }
+    }
+}
+
+impl From<EndDirective> for FlushCompress {
+    fn from(directive: EndDirective) -> Self {
+        match directive {
+            EndDirective::Continue => FlushCompress::None,
+            EndDirective::Flush => FlushCompress::Sync,
+            EndDirective::End => FlushCompress::Finish,
+        }
+    }
+}
+
+/// How [Decompress::decompress] is called, which only exists to match the shape of `flate2`.
+///
+/// The decompressor always produces as much output as it can, so all variants behave the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushDecompress {
+    None,
+    Sync,
+    Finish,
+}
+
+#[cfg(feature = "encoder")]
+enum CompressState {
+    Encoding(Box<IncrementalEncoder<Vec<u8>>>),
+    /// The output of the finished frame
+    Finished(Vec<u8>),
+}
+
+/// Compresses into one frame, one buffer at a time, see the [module documentation](self).
+///
+/// The compressed data that does not fit into the output buffer is kept until the next call,
+/// and no new input is accepted before it was handed out. Input that does not fill a block is
+/// kept as well, until [FlushCompress::Sync] or [FlushCompress::Finish] ends the block.
+#[cfg(feature = "encoder")]
+pub struct Compress {
+    options: EncodeOptions,
+    state: CompressState,
+    /// Input that does not fill a block yet
+    pending: Vec<u8>,
+    /// The number of bytes at the start of the output of the encoder that were handed out
+    handed_out: usize,
+    total_in: u64,
+    total_out: u64,
+}
+
+#[cfg(feature = "encoder")]
+impl Compress {
+    /// Start a frame with the given level and the [default options](EncodeOptions::default).
+    ///
+    /// Returns [EncodeError::UnsupportedLevel] for compression levels that are not implemented yet,
+    /// and for levels 20 to 22 without [EncodeOptions::ultra].
+    pub fn new(level: CompressionLevel) -> Result<Self, EncodeError> {
+        Self::with_options(EncodeOptions {
+            level,
+            ..EncodeOptions::default()
+        })
+    }
+
+    /// Start a frame with the given options, see [IncrementalEncoder::start].
+    ///
+    /// `options.content_size` is not used, the size of the content is not known in advance.
+    pub fn with_options(options: EncodeOptions) -> Result<Self, EncodeError> {
+        let encoder = IncrementalEncoder::start(Vec::new(), &options, None)?;
+        let pending = try_with_capacity(encoder.max_block_size())?;
+        // There is no fallible version of `Box::new` on stable Rust, see the Readme
+        #[allow(clippy::disallowed_methods)]
+        let encoder = Box::new(encoder);
+        Ok(Self {
+            pending,
+            state: CompressState::Encoding(encoder),
+            options,
+            handed_out: 0,
+            total_in: 0,
+            total_out: 0,
+        })
+    }
+
+    /// Compress from `input` into `output`, and end the block or the frame once all input was
+    /// consumed, as `flush` says.
+    ///
+    /// Use [Compress::total_in] and [Compress::total_out] to find out how much input was consumed
+    /// and how much output was produced. They are updated when an error is returned as well.
+    pub fn compress(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        flush: FlushCompress,
+    ) -> Result<Status, EncodeError> {
+        self.compress_uninit(input, as_uninit(output), flush)
+    }
+
+    /// Like [Compress::compress], but `output` does not have to be initialized. Exactly the bytes
+    /// that were written to the start of `output` are initialized afterwards.
+    fn compress_uninit(
+        &mut self,
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+        flush: FlushCompress,
+    ) -> Result<Status, EncodeError> {
+        let (mut consumed, mut written) = (0, 0);
+        let result = self.compress_counted(input, output, flush, &mut consumed, &mut written);
+        self.total_in += consumed as u64;
+        self.total_out += written as u64;
+        result?;
+        let finished = matches!(self.state, CompressState::Finished(_));
+        Ok(if finished && self.output_buffer().is_empty() {
+            Status::StreamEnd
+        } else if consumed == 0 && written == 0 {
+            Status::BufError
+        } else {
+            Status::Ok
+        })
+    }
+
+    /// The work of [Compress::compress], which counts the input that was consumed and the output
+    /// that was written in `consumed` and `written` as it goes, so they are right after an error.
+    fn compress_counted(
+        &mut self,
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+        flush: FlushCompress,
+        consumed: &mut usize,
+        written: &mut usize,
+    ) -> Result<(), EncodeError> {
+        *written += self.hand_out(output);
+        while self.output_buffer().is_empty() {
+            let CompressState::Encoding(encoder) = &mut self.state else {
+                break;
+            };
+            let block_size = encoder.max_block_size();
+            let remaining = &input[*consumed..];
+            if !remaining.is_empty() {
+                // Full blocks can be compressed without copying them first
+                if self.pending.is_empty() && remaining.len() >= block_size {
+                    encoder.write_chunk(&remaining[..block_size])?;
+                    *consumed += block_size;
+                } else {
+                    let taken = usize::min(remaining.len(), block_size - self.pending.len());
+                    self.pending.try_extend_from_slice(&remaining[..taken])?;
+                    *consumed += taken;
+                    if self.pending.len() == block_size {
+                        encoder.write_chunk(&self.pending)?;
+                        self.pending.clear();
+                    }
+                }
+            } else {
+                match flush {
+                    FlushCompress::None => break,
+                    FlushCompress::Sync if self.pending.is_empty() => break,
+                    FlushCompress::Sync => {
+                        encoder.write_chunk(&self.pending)?;
+                        self.pending.clear();
+                    }
+                    FlushCompress::Finish => {
+                        let state = core::mem::replace(
+                            &mut self.state,
+                            CompressState::Finished(Vec::new()),
+                        );
+                        // Checked at the start of the loop
+                        let CompressState::Encoding(encoder) = state else {
+                            break;
+                        };
+                        self.state = CompressState::Finished(encoder.finish(&self.pending)?);
+                        self.pending.clear();
+                    }
+                }
+            }
+            *written += self.hand_out(&mut output[*written..]);
+        }
+        Ok(())
+    }
+
+    /// Like [Compress::compress], but write into the spare capacity of `output`. The vector does
+    /// not grow, its length is increased by the number of bytes that were written.
+    pub fn compress_vec(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        flush: FlushCompress,
+    ) -> Result<Status, EncodeError> {
+        let len = output.len();
+        let total_out = self.total_out;
+        let result = self.compress_uninit(input, output.spare_capacity_mut(), flush);
+        // SAFETY: compress_uninit initialized the bytes it wrote to the start of the spare capacity,
+        // which total_out counts even if it failed
+        unsafe { output.set_len(len + (self.total_out - total_out) as usize) };
+        result
+    }
+
+    /// Like [Compress::compress], but take the input from a [bytes::Buf] and write into a
+    /// [bytes::BufMut], e.g. the buffers of a network stack, which are advanced past the input that
+    /// was consumed and the output that was written. Input that is split into several chunks is
+    /// compressed from each chunk in place, and `flush` only applies once all of it was consumed.
+    #[cfg(feature = "bytes")]
+    pub fn compress_buf(
+        &mut self,
+        input: &mut impl Buf,
+        output: &mut impl BufMut,
+        flush: FlushCompress,
+    ) -> Result<Status, EncodeError> {
+        let (total_in, total_out) = (self.total_in, self.total_out);
+        loop {
+            let (consumed, written) = (self.total_in, self.total_out);
+            let chunk = input.chunk();
+            let chunk_flush = if chunk.len() == input.remaining() {
+                flush
+            } else {
+                FlushCompress::None
+            };
+            let status = self.compress(chunk, initialized_chunk_mut(output), chunk_flush)?;
+            input.advance((self.total_in - consumed) as usize);
+            // SAFETY: the bytes that were written are initialized
+            unsafe { output.advance_mut((self.total_out - written) as usize) };
+            if status == Status::StreamEnd {
+                return Ok(status);
+            }
+            if status == Status::BufError {
+                break;
+            }
+        }
+        Ok(
+            if (self.total_in, self.total_out) == (total_in, total_out) {
+                Status::BufError
+            } else {
+                Status::Ok
+            },
+        )
+    }
+
+    /// Like [Compress::compress], with the semantics of `ZSTD_compressStream2`: `input` and
+    /// `output` are advanced past the input that was consumed and the output that was written, and
+    /// the result is the work that is left.
+    ///
+    /// Compression stops once `output` is full. The result is the number of compressed bytes that
+    /// wait for the next call, or at least 1 as long as `end_op` is not done: the input was not all
+    /// consumed, or the block or frame could not be ended yet. 0 means that all input is consumed
+    /// and, with [EndDirective::Flush] and [EndDirective::End], that the block respectively the
+    /// frame was ended and handed out completely. Call again with a fresh `output` until then.
+    ///
+    /// ```
+    /// # #[cfg(feature = "encoder")] {
+    /// use ruzstd::encoding::CompressionLevel;
+    /// use ruzstd::raw::{Compress, EndDirective};
+    ///
+    /// let mut compress = Compress::new(CompressionLevel::Fastest).unwrap();
+    /// let mut input: &[u8] = b"Send this in packets of 16 bytes. Send this in packets of 16 bytes.";
+    /// let mut packets = Vec::new();
+    /// loop {
+    ///     let mut packet = [0; 16];
+    ///     let mut output = &mut packet[..];
+    ///     let remaining = compress
+    ///         .compress_stream(&mut input, &mut output, EndDirective::End)
+    ///         .unwrap();
+    ///     let written = 16 - output.len();
+    ///     packets.extend_from_slice(&packet[..written]);
+    ///     if remaining == 0 {
+    ///         break;
+    ///     }
+    /// }
+    /// assert!(input.is_empty());
+    /// assert_eq!(
+    ///     ruzstd::decompress(&packets).unwrap(),
+    ///     b"Send this in packets of 16 bytes. Send this in packets of 16 bytes."
+    /// );
+    /// # }
+    /// ```
+    pub fn compress_stream(
+        &mut self,
+        input: &mut &[u8],
+        output: &mut &mut [u8],
+        end_op: EndDirective,
+    ) -> Result<usize, EncodeError> {
+        let (total_in, total_out) = (self.total_in, self.total_out);
+        let status = self.compress(input, output, end_op.into())?;
+        *input = &input[(self.total_in - total_in) as usize..];
+        let written = (self.total_out - total_out) as usize;
+        *output = &mut core::mem::take(output)[written..];
+
+        let handed_out = self.handed_out;
+        let buffered = self.output_buffer().len() - handed_out;
+        let done = match end_op {
+            EndDirective::Continue => true,
+            EndDirective::Flush => input.is_empty() && self.pending.is_empty(),
+            EndDirective::End => status == Status::StreamEnd,
+        };
+        Ok(if done { buffered } else { buffered.max(1) })
+    }
+
+    /// Start a new frame with the same options. Input and output that were not handed out yet are
+    /// discarded, and the totals start at 0 again.
+    pub fn reset(&mut self) -> Result<(), EncodeError> {
+        let encoder = IncrementalEncoder::start(Vec::new(), &self.options, None)?;
+        // There is no fallible version of `Box::new` on stable Rust, see the Readme
+        #[allow(clippy::disallowed_methods)]
+        let encoder = Box::new(encoder);
+        self.state = CompressState::Encoding(encoder);
+        self.pending.clear();
+        self.handed_out = 0;
+        self.total_in = 0;
+        self.total_out = 0;
+        Ok(())
+    }
+
+    /// The number of bytes of input that were consumed.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// The number of bytes of output that were produced.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+
+    /// The compressed data that was not handed out yet, starting at `handed_out`.
+    fn output_buffer(&mut self) -> &mut Vec<u8> {
+        match &mut self.state {
+            CompressState::Encoding(encoder) => encoder.get_mut(),
+            CompressState::Finished(buffer) => buffer,
+        }
+    }
+
+    /// Copy as much of the compressed data that was not handed out yet as fits into `output`.
+    fn hand_out(&mut self, output: &mut [MaybeUninit<u8>]) -> usize {
+        let handed_out = self.handed_out;
+        let buffer = self.output_buffer();
+        let available = &buffer[handed_out..];
+        let amount = available.len().min(output.len());
+        write_slice(&mut output[..amount], &available[..amount]);
+        if handed_out + amount == buffer.len() {
+            buffer.clear();
+            self.handed_out = 0;
+        } else {
+            self.handed_out += amount;
+        }
+        amount
+    }
+}
+
+/// Decompresses one buffer at a time, see the [module documentation](self).
+///
+/// Several frames may follow each other, skippable frames are skipped. Input is only consumed as
+/// far as it is needed to fill the output buffer, but parts of a block that were consumed are
+/// kept until the rest of the block arrives.
+pub struct Decompress {
+    decoder: PushDecoder,
+    /// The number of bytes at the start of the uncollected content of the decoder that were
+    /// handed out already. The decoder keeps the window of an unfinished frame, but its content is
+    /// handed out as soon as it is decoded, so a [FlushCompress::Sync] takes effect immediately.
+    ahead: usize,
+    total_in: u64,
+    total_out: u64,
+}
+
+impl Decompress {
+    /// Create a decompressor that is waiting for the first frame.
+    pub fn new() -> Self {
+        Self::with_decoder(FrameDecoder::new())
+    }
+
+    /// Create a decompressor that decodes with `decoder`, e.g. to use its dictionaries and window
+    /// limit.
+    pub fn with_decoder(decoder: FrameDecoder) -> Self {
+        Self {
+            decoder: PushDecoder::with_decoder(decoder),
+            ahead: 0,
+            total_in: 0,
+            total_out: 0,
+        }
+    }
+
+    /// Decompress from `input` into `output`.
+    ///
+    /// Use [Decompress::total_in] and [Decompress::total_out] to find out how much input was
+    /// consumed and how much output was produced, they are updated when an error is returned as
+    /// well. Returns [Status::StreamEnd] once all input that was passed in so far ends with a
+    /// complete frame and all of its content was produced.
+    pub fn decompress(
+        &mut self,
+        input: &[u8],
+        output: &mut [u8],
+        _flush: FlushDecompress,
+    ) -> Result<Status, FrameDecoderError> {
+        self.decompress_uninit(input, as_uninit(output))
+    }
+
+    /// Like [Decompress::decompress], but `output` does not have to be initialized. Exactly the
+    /// bytes that were written to the start of `output` are initialized afterwards.
+    fn decompress_uninit(
+        &mut self,
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+    ) -> Result<Status, FrameDecoderError> {
+        let (mut consumed, mut written) = (0, 0);
+        let result = self.decompress_counted(input, output, &mut consumed, &mut written);
+        self.total_in += consumed as u64;
+        self.total_out += written as u64;
+        result?;
+        Ok(
+            if self.total_in > 0 && consumed == input.len() && self.decoder.is_finished() {
+                Status::StreamEnd
+            } else if consumed == 0 && written == 0 {
+                Status::BufError
+            } else {
+                Status::Ok
+            },
+        )
+    }
+
+    /// The work of [Decompress::decompress], which counts the input that was consumed and the
+    /// output that was written in `consumed` and `written` as it goes, so they are right after an
+    /// error.
+    fn decompress_counted(
+        &mut self,
+        input: &[u8],
+        output: &mut [MaybeUninit<u8>],
+        consumed: &mut usize,
+        written: &mut usize,
+    ) -> Result<(), FrameDecoderError> {
+        loop {
+            let read = self.decoder.read_uninit(&mut output[*written..])?;
+            let skipped = read.min(self.ahead);
+            output.copy_within(*written + skipped..*written + read, *written);
+            self.ahead -= skipped;
+            *written += read - skipped;
+            if *written == output.len() {
+                break;
+            }
+            if read == 0 {
+                if *consumed == input.len() {
+                    break;
+                }
+                let amount = usize::min(input.len() - *consumed, DECOMPRESS_CHUNK_SIZE);
+                self.decoder.feed(&input[*consumed..*consumed + amount])?;
+                *consumed += amount;
+            }
+        }
+        *written += self.hand_out_window(&mut output[*written..]);
+        Ok(())
+    }
+
+    /// Copy the content the decoder holds back as the window of an unfinished frame, as far as it
+    /// was not handed out yet.
+    fn hand_out_window(&mut self, output: &mut [MaybeUninit<u8>]) -> usize {
+        let (first, second) = self.decoder.decoder_mut().uncollected();
+        let mut written = 0;
+        let mut skip = self.ahead;
+        for part in [first, second] {
+            let skipped = skip.min(part.len());
+            skip -= skipped;
+            let part = &part[skipped..];
+            let amount = part.len().min(output.len() - written);
+            write_slice(&mut output[written..written + amount], &part[..amount]);
+            written += amount;
+        }
+        self.ahead += written;
+        written
+    }
+
+    /// Like [Decompress::decompress], but write into the spare capacity of `output`. The vector
+    /// does not grow, its length is increased by the number of bytes that were written.
+    pub fn decompress_vec(
+        &mut self,
+        input: &[u8],
+        output: &mut Vec<u8>,
+        _flush: FlushDecompress,
+    ) -> Result<Status, FrameDecoderError> {
+        let len = output.len();
+        let total_out = self.total_out;
+        let result = self.decompress_uninit(input, output.spare_capacity_mut());
+        // SAFETY: decompress_uninit initialized the bytes it wrote to the start of the spare
+        // capacity, which total_out counts even if it failed
+        unsafe { output.set_len(len + (self.total_out - total_out) as usize) };
+        result
+    }
+
+    /// Like [Decompress::decompress], but take the input from a [bytes::Buf] and write into a
+    /// [bytes::BufMut], e.g. the buffers of a network stack, which are advanced past the input that
+    /// was consumed and the output that was written. Input that is split into several chunks is
+    /// decompressed from each chunk in place.
+    #[cfg(feature = "bytes")]
+    pub fn decompress_buf(
+        &mut self,
+        input: &mut impl Buf,
+        output: &mut impl BufMut,
+        flush: FlushDecompress,
+    ) -> Result<Status, FrameDecoderError> {
+        let (total_in, total_out) = (self.total_in, self.total_out);
+        loop {
+            let (consumed, written) = (self.total_in, self.total_out);
+            let status = self.decompress(input.chunk(), initialized_chunk_mut(output), flush)?;
+            input.advance((self.total_in - consumed) as usize);
+            // SAFETY: the bytes that were written are initialized
+            unsafe { output.advance_mut((self.total_out - written) as usize) };
+            if status == Status::StreamEnd && !input.has_remaining() {
+                return Ok(status);
+            }
+            if status == Status::BufError {
+                break;
+            }
+        }
+        Ok(
+            if (self.total_in, self.total_out) == (total_in, total_out) {
+                Status::BufError
+            } else {
+                Status::Ok
+            },
+        )
+    }
+
+    /// Forget all input and output that was not handed out yet, and wait for a new frame. The
+    /// totals start at 0 again.
+    pub fn reset(&mut self) {
+        self.decoder.reset();
+        self.ahead = 0;
+        self.total_in = 0;
+        self.total_out = 0;
+    }
+
+    /// The number of bytes of input that were consumed.
+    pub fn total_in(&self) -> u64 {
+        self.total_in
+    }
+
+    /// The number of bytes of output that were produced.
+    pub fn total_out(&self) -> u64 {
+        self.total_out
+    }
+}
+
+impl Default for Decompress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// View initialized bytes as possibly uninitialized ones, to write into them.
+fn as_uninit(output: &mut [u8]) -> &mut [MaybeUninit<u8>] {
+    // SAFETY: `MaybeUninit<u8>` has the layout of `u8`, and only initialized bytes are written
+    // through the slice, so `output` stays initialized
+    unsafe { core::slice::from_raw_parts_mut(output.as_mut_ptr().cast(), output.len()) }
+}
+
+/// Copy `src` into `target`, which has the same length.
+fn write_slice(target: &mut [MaybeUninit<u8>], src: &[u8]) {
+    for (dst, src) in target.iter_mut().zip(src) {
+        dst.write(*src);
+    }
+}
+
+/// The next chunk of the spare capacity of `output`, zeroed so it can be written like any slice.
+#[cfg(feature = "bytes")]
+fn initialized_chunk_mut(output: &mut impl BufMut) -> &mut [u8] {
+    let chunk = output.chunk_mut();
+    let len = chunk.len();
+    let ptr = chunk.as_mut_ptr();
+    // SAFETY: the chunk is valid for writes of `len` bytes, which are initialized before the slice
+    // is created
+    unsafe {
+        core::ptr::write_bytes(ptr, 0, len);
+        core::slice::from_raw_parts_mut(ptr, len)
+    }
+}
+
+#[cfg(all(test, feature = "encoder"))]
+mod tests {
+    use super::{Compress, Decompress, EndDirective, FlushCompress, FlushDecompress, Status};
+    use crate::encoding::{CompressionLevel, EncodeOptions};
+    use crate::test_utils::text_like;
+    use alloc::vec::Vec;
+
+    /// Compress `data` in pieces of `input_size`, into buffers of `output_size`
+    fn compress(data: &[u8], input_size: usize, output_size: usize) -> Vec<u8> {
+        let mut compress = Compress::new(CompressionLevel::Fastest).unwrap();
+        let mut compressed = Vec::new();
+        let mut buf = alloc::vec![0; output_size];
+        for chunk in data.chunks(input_size) {
+            let mut chunk = chunk;
+            while !chunk.is_empty() {
+                let (total_in, total_out) = (compress.total_in(), compress.total_out());
+                let status = compress
+                    .compress(chunk, &mut buf, FlushCompress::None)
+                    .unwrap();
+                assert_ne!(status, Status::StreamEnd);
+                chunk = &chunk[(compress.total_in() - total_in) as usize..];
+                compressed.extend_from_slice(&buf[..(compress.total_out() - total_out) as usize]);
+            }
+        }
+        loop {
+            let total_out = compress.total_out();
+            let status = compress
+                .compress(&[], &mut buf, FlushCompress::Finish)
+                .unwrap();
+            compressed.extend_from_slice(&buf[..(compress.total_out() - total_out) as usize]);
+            if status == Status::StreamEnd {
+                break;
+            }
+        }
+        assert_eq!(compress.total_in(), data.len() as u64);
+        assert_eq!(compress.total_out(), compressed.len() as u64);
+        compressed
+    }
+
+    /// Decompress `data` in pieces of `input_size`, into buffers of `output_size`
+    fn decompress(data: &[u8], input_size: usize, output_size: usize) -> Vec<u8> {
+        let mut decompress = Decompress::new();
+        let mut decompressed = Vec::new();
+        let mut buf = alloc::vec![0; output_size];
+        let mut status = Status::Ok;
+        for chunk in data.chunks(input_size) {
+            let mut chunk = chunk;
+            loop {
+                let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+                status = decompress
+                    .decompress(chunk, &mut buf, FlushDecompress::None)
+                    .unwrap();
+                chunk = &chunk[(decompress.total_in() - total_in) as usize..];
+                let written = (decompress.total_out() - total_out) as usize;
+                decompressed.extend_from_slice(&buf[..written]);
+                if chunk.is_empty() && written < buf.len() {
+                    break;
+                }
+            }
+        }
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(decompress.total_in(), data.len() as u64);
+        decompressed
+    }
+
+    #[test]
+    fn buffers_of_any_size() {
+        let data = text_like(300_000, 1);
+        for (input_size, output_size) in [(1, 7), (1000, 1), (200_000, 1000), (data.len(), 1 << 20)]
+        {
+            let compressed = compress(&data, input_size, output_size);
+            let mut decoded = Vec::with_capacity(data.len());
+            crate::FrameDecoder::new()
+                .decode_all_to_vec(&compressed, &mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+            assert_eq!(decompress(&compressed, input_size, output_size), data);
+        }
+
+        // The window of the first frame is handed out before the second frame starts
+        let mut two_frames = compress(&data[..100_000], 1000, 1000);
+        two_frames.extend(compress(&data[100_000..], 1000, 1000));
+        for (input_size, output_size) in [(3, 5000), (5000, 3)] {
+            assert_eq!(decompress(&two_frames, input_size, output_size), data);
+        }
+    }
+
+    #[test]
+    fn sync_flush() {
+        let mut compress = Compress::new(CompressionLevel::Fastest).unwrap();
+        let mut compressed = Vec::with_capacity(1000);
+        let status = compress
+            .compress_vec(b"first part, ", &mut compressed, FlushCompress::None)
+            .unwrap();
+        assert_eq!(status, Status::Ok);
+        // Only the frame header, the input waits for more
+        let header_size = compressed.len();
+        compress
+            .compress_vec(b"second part", &mut compressed, FlushCompress::Sync)
+            .unwrap();
+        assert!(compressed.len() > header_size);
+
+        // Everything so far can be decompressed, but the frame is not complete
+        let mut decompress = Decompress::new();
+        let mut decompressed = Vec::with_capacity(100);
+        let status = decompress
+            .decompress_vec(&compressed, &mut decompressed, FlushDecompress::Sync)
+            .unwrap();
+        assert_eq!(status, Status::Ok);
+        assert_eq!(decompressed, b"first part, second part");
+
+        let flushed = compressed.len();
+        let status = compress
+            .compress_vec(&[], &mut compressed, FlushCompress::Finish)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        let status = decompress
+            .decompress_vec(
+                &compressed[flushed..],
+                &mut decompressed,
+                FlushDecompress::Finish,
+            )
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert_eq!(decompressed, b"first part, second part");
+    }
+
+    #[test]
+    fn no_progress() {
+        let mut compress = Compress::new(CompressionLevel::Fastest).unwrap();
+        assert_eq!(
+            compress
+                .compress(b"data", &mut [], FlushCompress::Finish)
+                .unwrap(),
+            Status::BufError
+        );
+        // The frame header is handed out first, the input is only taken once it was
+        assert_eq!(compress.total_in(), 0);
+        let mut output = [0; 100];
+        assert_eq!(
+            compress
+                .compress(b"data", &mut output, FlushCompress::Finish)
+                .unwrap(),
+            Status::StreamEnd
+        );
+        assert_eq!(compress.total_in(), 4);
+        // The frame is finished, only a reset starts a new one
+        assert_eq!(
+            compress
+                .compress(b"more", &mut output, FlushCompress::Finish)
+                .unwrap(),
+            Status::StreamEnd
+        );
+        assert_eq!(compress.total_in(), 4);
+        let first = compress.total_out();
+        compress.reset().unwrap();
+        compress
+            .compress(b"data", &mut output, FlushCompress::Finish)
+            .unwrap();
+        assert_eq!(compress.total_out(), first);
+
+        let mut decompress = Decompress::new();
+        assert_eq!(
+            decompress
+                .decompress(&[], &mut output, FlushDecompress::Finish)
+                .unwrap(),
+            Status::BufError
+        );
+        let frame = crate::compress(b"data");
+        decompress
+            .decompress(&frame[..5], &mut output, FlushDecompress::None)
+            .unwrap();
+        decompress.reset();
+        assert_eq!(decompress.total_in(), 0);
+        assert_eq!(
+            decompress
+                .decompress(&frame, &mut output, FlushDecompress::Finish)
+                .unwrap(),
+            Status::StreamEnd
+        );
+        assert_eq!(&output[..4], b"data");
+    }
+
+    #[test]
+    fn totals_after_errors() {
+        // Small raw blocks, so the content of the blocks in the first chunk of input is handed out
+        // before the garbage behind the frame is found
+        let mut compress = Compress::with_options(EncodeOptions {
+            level: CompressionLevel::Uncompressed,
+            block_size: Some(1024),
+            ..EncodeOptions::default()
+        })
+        .unwrap();
+        let data = text_like(50_000, 2);
+        let mut input = Vec::with_capacity(60_000);
+        compress
+            .compress_vec(&data, &mut input, FlushCompress::Finish)
+            .unwrap();
+        input.extend_from_slice(b"garbage!");
+
+        let mut decompress = Decompress::new();
+        let mut output = alloc::vec![0; 100_000];
+        assert!(decompress
+            .decompress(&input, &mut output, FlushDecompress::Finish)
+            .is_err());
+        // All input was handed to the decoder, and the content that was written is counted
+        assert_eq!(decompress.total_in(), input.len() as u64);
+        let written = decompress.total_out() as usize;
+        assert!(written > 0);
+        assert_eq!(output[..written], data[..written]);
+    }
+
+    #[test]
+    fn compress_stream() {
+        let data = text_like(300_000, 1);
+        let mut compress = Compress::new(CompressionLevel::Fastest).unwrap();
+        let mut compressed = Vec::new();
+        // Compress into a buffer of `size` bytes, returns the remaining work and whether the
+        // buffer was filled
+        let mut send = |input: &mut &[u8], size: usize, end_op: EndDirective| {
+            let mut packet = [0; 1000];
+            let mut output = &mut packet[..size];
+            let remaining = compress
+                .compress_stream(input, &mut output, end_op)
+                .unwrap();
+            let written = size - output.len();
+            compressed.extend_from_slice(&packet[..written]);
+            (remaining, written == size)
+        };
+
+        // Without a flush, input that does not fill a block waits, and only the output that
+        // does not fit into the buffer is left
+        let mut input = &data[..200_000];
+        while !input.is_empty() {
+            let (remaining, full) = send(&mut input, 1000, EndDirective::Continue);
+            assert!(remaining == 0 || full);
+        }
+
+        // The block that is ended by the flush does not fit either
+        let mut input = &data[200_000..250_000];
+        let (remaining, full) = send(&mut input, 10, EndDirective::Flush);
+        assert!(remaining > 0 && full);
+        while send(&mut input, 1000, EndDirective::Flush).0 != 0 {}
+        assert!(input.is_empty());
+
+        let mut input = &data[250_000..];
+        while send(&mut input, 3, EndDirective::End).0 != 0 {}
+        assert!(input.is_empty());
+        // Nothing is left after the end of the frame
+        assert_eq!(send(&mut input, 1000, EndDirective::End), (0, false));
+
+        assert_eq!(compress.total_out(), compressed.len() as u64);
+        let mut decoded = Vec::with_capacity(data.len());
+        crate::FrameDecoder::new()
+            .decode_all_to_vec(&compressed, &mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn end_directives() {
+        let mut compress = Compress::new(CompressionLevel::Fastest).unwrap();
+        let mut decompress = Decompress::new();
+        let mut compressed = [0; 1000];
+        let mut decompressed = Vec::with_capacity(1000);
+        // Compress `input`, decompress everything that was handed out and return the content
+        // that was decompressed so far
+        let mut step = |mut input: &[u8], end_op: EndDirective| {
+            let mut output = &mut compressed[..];
+            let remaining = compress
+                .compress_stream(&mut input, &mut output, end_op)
+                .unwrap();
+            assert_eq!(remaining, 0);
+            let written = 1000 - output.len();
+            let status = decompress
+                .decompress_vec(
+                    &compressed[..written],
+                    &mut decompressed,
+                    FlushDecompress::Sync,
+                )
+                .unwrap();
+            (status, decompressed.clone())
+        };
+
+        // Input that does not fill a block waits, only the frame header is handed out
+        assert_eq!(
+            step(b"first, ", EndDirective::Continue),
+            (Status::Ok, Vec::new())
+        );
+        // The frame stays open, but all input so far can be decompressed
+        let flushed = b"first, second, ".to_vec();
+        assert_eq!(
+            step(b"second, ", EndDirective::Flush),
+            (Status::Ok, flushed.clone())
+        );
+        assert_eq!(step(b"", EndDirective::Flush), (Status::BufError, flushed));
+        // The frame ends, nothing follows
+        let content = b"first, second, third".to_vec();
+        assert_eq!(
+            step(b"third", EndDirective::End),
+            (Status::StreamEnd, content.clone())
+        );
+        assert_eq!(
+            step(b"more", EndDirective::End),
+            (Status::StreamEnd, content)
+        );
+
+        for flush in [
+            FlushCompress::None,
+            FlushCompress::Sync,
+            FlushCompress::Finish,
+        ] {
+            assert_eq!(FlushCompress::from(EndDirective::from(flush)), flush);
+        }
+    }
+
+    #[cfg(feature = "bytes")]
+    #[test]
+    fn bytes_buffers() {
+        use bytes::{Buf, BufMut, BytesMut};
+
+        let data = text_like(300_000, 3);
+        let (first, second) = data.split_at(100_000);
+
+        // Input in two chunks, into a growing buffer
+        let mut compress = Compress::new(CompressionLevel::Fastest).unwrap();
+        let mut input = first.chain(second);
+        let mut compressed = BytesMut::new();
+        let status = compress
+            .compress_buf(&mut input, &mut compressed, FlushCompress::Finish)
+            .unwrap();
+        assert_eq!(status, Status::StreamEnd);
+        assert!(!input.has_remaining());
+        assert_eq!(crate::decompress(&compressed).unwrap(), data);
+
+        // Input in two chunks, into buffers that only take 1000 bytes at a time
+        let (first, second) = compressed.split_at(compressed.len() / 3);
+        let mut input = first.chain(second);
+        let mut decompress = Decompress::new();
+        let mut decompressed = Vec::new();
+        loop {
+            let mut output = (&mut decompressed).limit(1000);
+            let status = decompress
+                .decompress_buf(&mut input, &mut output, FlushDecompress::None)
+                .unwrap();
+            if status == Status::StreamEnd {
+                break;
+            }
+            assert_eq!(status, Status::Ok);
+        }
+        assert_eq!(decompressed, data);
+        assert_eq!(decompress.total_in(), compressed.len() as u64);
+    }
+}
@@ -0,0 +1,662 @@
+//! Decompressing byte ranges of archives that consist of many frames, using a seek table.
+//!
+//! A [SeekTable] lists where each frame starts in the compressed data and which part of the
+//! content it holds, so [SeekTable::decompress_range] only decodes the frames that overlap the
+//! requested range, optionally on several threads with [SeekTable::decompress_range_parallel].
+//!
+//! Tables are read from the seek table of the [seekable format] at the end of the data, or built
+//! from the frames themselves: the hints `pzstd` writes in front of each frame (see
+//! [FrameCompressor::set_pzstd_frame_size](crate::encoding::FrameCompressor::set_pzstd_frame_size))
+//! give their compressed sizes, and the content sizes in the frame headers give their decompressed
+//! sizes. Other frames are found by walking their block headers.
+//!
+//! [seekable format]: https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::ops::Range;
+
+use crate::fallible::{try_vec, try_with_capacity, AllocError, TryVec};
+use crate::frame::{
+    inspect_frame_content, read_frame_header, InspectFrameError, ReadFrameHeaderError,
+};
+use crate::frame_decoder::{FrameDecoder, FrameDecoderError};
+use crate::skippable_frame::SKIPPABLE_MAGIC_NUM;
+
+/// The magic number at the very end of data in the seekable format.
+pub const SEEKABLE_MAGIC_NUM: u32 = 0x8F92_EAB1;
+
+/// The magic number of the skippable frame that holds the seek table of the seekable format.
+const SEEK_TABLE_MAGIC_NUM: u32 = 0x184D_2A5E;
+
+/// `Number_Of_Frames`, `Seek_Table_Descriptor` and `Seekable_Magic_Number`.
+const FOOTER_SIZE: usize = 9;
+
+/// The most memory that is reserved up front for the content of a frame that is only needed in
+/// part. The decompressed sizes of a seek table are not trusted beyond that, larger content grows
+/// the buffer as it is decoded.
+const MAX_PREALLOCATION: usize = 1 << 20;
+
+/// One frame of a [SeekTable].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SeekEntry {
+    /// Where the frame starts in the compressed data.
+    pub compressed_offset: u64,
+    /// The size of the frame, without the skippable frames around it.
+    pub compressed_size: u64,
+    /// Where the content of the frame starts in the decompressed data.
+    pub decompressed_offset: u64,
+    /// The size of the content of the frame.
+    pub decompressed_size: u64,
+}
+
+impl SeekEntry {
+    fn compressed_range(&self) -> Range<usize> {
+        let start = self.compressed_offset as usize;
+        start..start + self.compressed_size as usize
+    }
+
+    fn decompressed_end(&self) -> u64 {
+        self.decompressed_offset + self.decompressed_size
+    }
+}
+
+/// Where the frames of an archive are and what they contain, see the [module documentation](self).
+///
+/// ```
+/// # #[cfg(feature = "encoder")]
+/// # {
+/// use ruzstd::encoding::FrameCompressorBuilder;
+/// use ruzstd::seekable::SeekTable;
+/// use ruzstd::FrameDecoder;
+///
+/// let data: Vec<u8> = (0..100_000u32).map(|i| (i.wrapping_mul(i) >> 9) as u8).collect();
+/// let mut archive = Vec::new();
+/// FrameCompressorBuilder::new()
+///     .content_size(true)
+///     .pzstd_frame_size(Some(16 * 1024))
+///     .build(data.as_slice(), &mut archive)
+///     .unwrap()
+///     .compress()
+///     .unwrap();
+///
+/// let table = SeekTable::read(&archive).unwrap();
+/// assert_eq!(table.entries().len(), 7);
+/// let range = table
+///     .decompress_range(&archive, 40_000..50_000, &mut FrameDecoder::new())
+///     .unwrap();
+/// assert_eq!(range, &data[40_000..50_000]);
+/// # }
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SeekTable {
+    entries: Vec<SeekEntry>,
+}
+
+impl SeekTable {
+    /// Read the seek table at the end of `data` if it is in the seekable format, and build one
+    /// from its frames with [SeekTable::from_frames] otherwise.
+    pub fn read(data: &[u8]) -> Result<Self, SeekableError> {
+        if data.ends_with(&SEEKABLE_MAGIC_NUM.to_le_bytes()) {
+            Self::from_seekable(data)
+        } else {
+            Self::from_frames(data)
+        }
+    }
+
+    /// Read the seek table of `data` in the seekable format, without looking at the frames.
+    ///
+    /// The checksums of the table are not verified.
+    pub fn from_seekable(data: &[u8]) -> Result<Self, SeekableError> {
+        let footer_start = data
+            .len()
+            .checked_sub(FOOTER_SIZE)
+            .ok_or(SeekableError::InvalidSeekTable)?;
+        let footer = &data[footer_start..];
+        if footer[5..] != SEEKABLE_MAGIC_NUM.to_le_bytes() {
+            return Err(SeekableError::InvalidSeekTable);
+        }
+        let frames = u32::from_le_bytes([footer[0], footer[1], footer[2], footer[3]]) as usize;
+        let descriptor = footer[4];
+        // The reserved bits have to be zero
+        if descriptor & 0x7C != 0 {
+            return Err(SeekableError::InvalidSeekTable);
+        }
+        let entry_size = if descriptor & 0x80 != 0 { 12 } else { 8 };
+        let table_size = frames
+            .checked_mul(entry_size)
+            .and_then(|size| size.checked_add(FOOTER_SIZE))
+            .ok_or(SeekableError::InvalidSeekTable)?;
+        // The table is the content of a skippable frame
+        let frame_start = table_size
+            .checked_add(8)
+            .and_then(|frame_size| (footer_start + FOOTER_SIZE).checked_sub(frame_size))
+            .ok_or(SeekableError::InvalidSeekTable)?;
+        let table_start = frame_start + 8;
+        let header = &data[frame_start..table_start];
+        let declared_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if header[..4] != SEEK_TABLE_MAGIC_NUM.to_le_bytes()
+            || u64::from(declared_size) != table_size as u64
+        {
+            return Err(SeekableError::InvalidSeekTable);
+        }
+
+        let mut entries = try_with_capacity(frames)?;
+        let mut compressed_offset = 0u64;
+        let mut decompressed_offset = 0u64;
+        for entry in data[table_start..footer_start].chunks_exact(entry_size) {
+            let compressed_size = u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]);
+            let decompressed_size = u32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]);
+            entries.try_push(SeekEntry {
+                compressed_offset,
+                compressed_size: u64::from(compressed_size),
+                decompressed_offset,
+                decompressed_size: u64::from(decompressed_size),
+            })?;
+            compressed_offset += u64::from(compressed_size);
+            decompressed_offset += u64::from(decompressed_size);
+        }
+        // The frames fill the data in front of the seek table
+        if compressed_offset != frame_start as u64 {
+            return Err(SeekableError::InvalidSeekTable);
+        }
+        Ok(Self { entries })
+    }
+
+    /// Build the table from the frames in `data`, skipping skippable frames.
+    ///
+    /// The compressed size of a frame is taken from the `pzstd` hint in front of it, or found by
+    /// walking its block headers. Every frame has to declare its content size.
+    pub fn from_frames(data: &[u8]) -> Result<Self, SeekableError> {
+        let mut entries = Vec::new();
+        let mut decompressed_offset = 0u64;
+        let mut hint = None;
+        let mut position = 0;
+        while position < data.len() {
+            let mut source = &data[position..];
+            let (frame, header_size) = match read_frame_header(&mut source) {
+                Ok(header) => header,
+                Err(ReadFrameHeaderError::SkipFrame {
+                    magic_number,
+                    length,
+                }) => {
+                    let end = position + 8 + length as usize;
+                    if end > data.len() {
+                        return Err(SeekableError::Truncated {
+                            position: position as u64,
+                        });
+                    }
+                    hint = (magic_number == SKIPPABLE_MAGIC_NUM && length == 4)
+                        .then(|| u32::from_le_bytes([source[0], source[1], source[2], source[3]]));
+                    position = end;
+                    continue;
+                }
+                Err(error) => {
+                    return Err(SeekableError::InvalidFrame {
+                        position: position as u64,
+                        error: error.into(),
+                    })
+                }
+            };
+            let decompressed_size = match frame.header.descriptor.frame_content_size_bytes() {
+                Ok(0) | Err(_) => {
+                    return Err(SeekableError::MissingContentSize {
+                        position: position as u64,
+                    })
+                }
+                Ok(_) => frame.header.frame_content_size(),
+            };
+            let compressed_size = match hint.take() {
+                Some(size) => u64::from(size),
+                None => {
+                    inspect_frame_content(frame.header, header_size, source)
+                        .map_err(|error| SeekableError::InvalidFrame {
+                            position: position as u64,
+                            error,
+                        })?
+                        .compressed_size
+                }
+            };
+            let end = usize::try_from(compressed_size)
+                .ok()
+                .and_then(|size| position.checked_add(size))
+                .filter(|end| *end <= data.len())
+                .ok_or(SeekableError::Truncated {
+                    position: position as u64,
+                })?;
+            entries.try_push(SeekEntry {
+                compressed_offset: position as u64,
+                compressed_size,
+                decompressed_offset,
+                decompressed_size,
+            })?;
+            decompressed_offset += decompressed_size;
+            position = end;
+        }
+        Ok(Self { entries })
+    }
+
+    /// The frames in the order of their content.
+    pub fn entries(&self) -> &[SeekEntry] {
+        &self.entries
+    }
+
+    /// The size of the whole decompressed content.
+    pub fn decompressed_size(&self) -> u64 {
+        self.entries.last().map_or(0, SeekEntry::decompressed_end)
+    }
+
+    /// The indices of the frames whose content overlaps `range`.
+    pub fn frames_for(&self, range: Range<u64>) -> Range<usize> {
+        let first = self
+            .entries
+            .partition_point(|entry| entry.decompressed_end() <= range.start);
+        let last = self
+            .entries
+            .partition_point(|entry| entry.decompressed_offset < range.end);
+        first..last.max(first)
+    }
+
+    /// Decompress the bytes `range` of the content of `data`, the archive this table describes,
+    /// decoding only the frames that overlap it.
+    ///
+    /// `decoder` decodes each of the frames, with its dictionaries and window limit.
+    pub fn decompress_range(
+        &self,
+        data: &[u8],
+        range: Range<u64>,
+        decoder: &mut FrameDecoder,
+    ) -> Result<Vec<u8>, SeekableError> {
+        let frames = self.check_range(&range)?;
+        let mut output = try_vec(0, (range.end - range.start) as usize)?;
+        for index in frames {
+            let entry = &self.entries[index];
+            let target = output_range(entry, &range);
+            decode_frame(data, entry, &range, decoder, &mut output[target])?;
+        }
+        Ok(output)
+    }
+
+    /// Like [SeekTable::decompress_range], with the frames spread over up to `threads` threads.
+    /// Each thread decodes its frames with a decoder created by `new_decoder`.
+    ///
+    /// A `threads` of 0 is treated like 1.
+    #[cfg(feature = "std")]
+    pub fn decompress_range_parallel(
+        &self,
+        data: &[u8],
+        range: Range<u64>,
+        threads: usize,
+        new_decoder: impl Fn() -> FrameDecoder + Sync,
+    ) -> Result<Vec<u8>, SeekableError> {
+        let frames = self.check_range(&range)?;
+        let mut output = try_vec(0, (range.end - range.start) as usize)?;
+        let per_thread = frames.len().div_ceil(threads.max(1)).max(1);
+        let new_decoder = &new_decoder;
+        std::thread::scope(|scope| {
+            let mut rest = output.as_mut_slice();
+            let mut rest_start = range.start;
+            let mut workers = Vec::new();
+            for group in self.entries[frames].chunks(per_thread) {
+                // Chunks are never empty
+                let Some(last) = group.last() else {
+                    continue;
+                };
+                let end = last.decompressed_end().min(range.end);
+                let (target, tail) =
+                    core::mem::take(&mut rest).split_at_mut((end - rest_start) as usize);
+                let target_start = rest_start;
+                rest = tail;
+                rest_start = end;
+                let range = range.clone();
+                // Panics like std::thread::spawn if the thread can not be created, see the Readme
+                #[allow(clippy::disallowed_methods)]
+                let worker = scope.spawn(move || {
+                    let mut decoder = new_decoder();
+                    // The part of the range this thread writes
+                    let part = target_start..range.end;
+                    for entry in group {
+                        let target = &mut target[output_range(entry, &part)];
+                        decode_frame(data, entry, &range, &mut decoder, target)?;
+                    }
+                    Ok::<_, SeekableError>(())
+                });
+                workers.try_push(worker)?;
+            }
+            workers.into_iter().try_for_each(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+        })?;
+        Ok(output)
+    }
+
+    /// The frames that overlap `range`, if it lies within the content.
+    fn check_range(&self, range: &Range<u64>) -> Result<Range<usize>, SeekableError> {
+        let size = self.decompressed_size();
+        if range.start > range.end || range.end > size {
+            return Err(SeekableError::RangeOutOfBounds {
+                range: range.clone(),
+                size,
+            });
+        }
+        Ok(self.frames_for(range.clone()))
+    }
+}
+
+/// The part of the output of [SeekTable::decompress_range] that the content of `entry` goes to.
+fn output_range(entry: &SeekEntry, range: &Range<u64>) -> Range<usize> {
+    let start = entry.decompressed_offset.max(range.start) - range.start;
+    let end = entry.decompressed_end().min(range.end) - range.start;
+    start as usize..end as usize
+}
+
+/// Decode the frame of `entry` and copy the part of its content that lies in `range` to `target`.
+fn decode_frame(
+    data: &[u8],
+    entry: &SeekEntry,
+    range: &Range<u64>,
+    decoder: &mut FrameDecoder,
+    target: &mut [u8],
+) -> Result<(), SeekableError> {
+    let position = entry.compressed_offset;
+    let frame = data
+        .get(entry.compressed_range())
+        .ok_or(SeekableError::Truncated { position })?;
+    let expected = entry.decompressed_size;
+    let check_size = |actual: usize| {
+        if actual as u64 == expected {
+            Ok(())
+        } else {
+            Err(SeekableError::SizeMismatch { position, expected })
+        }
+    };
+    let map_err = |error| match error {
+        // The frame holds more than the table says
+        FrameDecoderError::TargetTooSmall => SeekableError::SizeMismatch { position, expected },
+        error => SeekableError::DecodeError { position, error },
+    };
+    if target.len() as u64 == expected {
+        // The whole content is needed, so it is decoded in place
+        let written = decoder.decode_all(frame, target).map_err(map_err)?;
+        return check_size(written);
+    }
+    let limit = usize::try_from(expected).unwrap_or(usize::MAX);
+    let mut content = try_with_capacity(limit.min(MAX_PREALLOCATION))?;
+    decoder
+        .decode_all_to_vec_with_limit(frame, &mut content, limit)
+        .map_err(map_err)?;
+    check_size(content.len())?;
+    let start = (range.start.saturating_sub(entry.decompressed_offset)) as usize;
+    target.copy_from_slice(&content[start..start + target.len()]);
+    Ok(())
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SeekableError {
+    /// The frame starting at `position` is invalid.
+    InvalidFrame {
+        position: u64,
+        error: InspectFrameError,
+    },
+    /// The frame starting at `position` ends behind the data.
+    Truncated { position: u64 },
+    /// The frame starting at `position` does not declare the size of its content, so the table
+    /// can not be built without decompressing it.
+    MissingContentSize { position: u64 },
+    /// The seek table at the end of the data is damaged, or does not match the frames in front of it.
+    InvalidSeekTable,
+    /// The requested range is not within the content, which is `size` bytes long.
+    RangeOutOfBounds { range: Range<u64>, size: u64 },
+    /// The frame starting at `position` could not be decoded.
+    DecodeError {
+        position: u64,
+        error: FrameDecoderError,
+    },
+    /// The content of the frame starting at `position` is not `expected` bytes long, like the
+    /// table says.
+    SizeMismatch { position: u64, expected: u64 },
+    /// There was not enough memory for the table or the decompressed content.
+    OutOfMemory { size: usize },
+}
+
+impl fmt::Display for SeekableError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidFrame { position, error } => {
+                write!(f, "Invalid frame at byte {}: {}", position, error)
+            }
+            Self::Truncated { position } => {
+                write!(f, "The frame at byte {} ends behind the data", position)
+            }
+            Self::MissingContentSize { position } => write!(
+                f,
+                "The frame at byte {} does not declare its content size",
+                position
+            ),
+            Self::InvalidSeekTable => write!(f, "The seek table is invalid"),
+            Self::RangeOutOfBounds { range, size } => write!(
+                f,
+                "The range {}..{} is not within the content of {} bytes",
+                range.start, range.end, size
+            ),
+            Self::DecodeError { position, error } => {
+                write!(
+                    f,
+                    "Failed to decode the frame at byte {}: {}",
+                    position, error
+                )
+            }
+            Self::SizeMismatch { position, expected } => write!(
+                f,
+                "The frame at byte {} does not hold {} bytes like the seek table says",
+                position, expected
+            ),
+            Self::OutOfMemory { size } => write!(f, "Failed to allocate {} bytes", size),
+        }
+    }
+}
+
+impl From<AllocError> for SeekableError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SeekableError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SeekableError::InvalidFrame { error, .. } => Some(error),
+            SeekableError::DecodeError { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SeekTable, SeekableError, SEEKABLE_MAGIC_NUM};
+    use crate::frame_decoder::FrameDecoder;
+    use alloc::vec::Vec;
+
+    /// A frame that stores `content` in one raw block and declares its size
+    fn raw_frame(content: &[u8]) -> Vec<u8> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&crate::frame::MAGIC_NUM.to_le_bytes());
+        // A 4 byte content size and a 64 KiB window
+        frame.extend_from_slice(&[0x80, 6 << 3]);
+        frame.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        let block_header = 1 | ((content.len() as u32) << 3);
+        frame.extend_from_slice(&block_header.to_le_bytes()[..3]);
+        frame.extend_from_slice(content);
+        frame
+    }
+
+    fn content() -> Vec<u8> {
+        (0..50_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// The content in frames of `frame_size` bytes, in the seekable format
+    fn seekable(content: &[u8], frame_size: usize) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut table = Vec::new();
+        for chunk in content.chunks(frame_size) {
+            let frame = raw_frame(chunk);
+            table.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            table.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+            data.extend_from_slice(&frame);
+        }
+        let frames = content.chunks(frame_size).count() as u32;
+        table.extend_from_slice(&frames.to_le_bytes());
+        table.push(0);
+        table.extend_from_slice(&SEEKABLE_MAGIC_NUM.to_le_bytes());
+        data.extend_from_slice(&0x184D_2A5Eu32.to_le_bytes());
+        data.extend_from_slice(&(table.len() as u32).to_le_bytes());
+        data.extend_from_slice(&table);
+        data
+    }
+
+    fn check_ranges(table: &SeekTable, data: &[u8], content: &[u8]) {
+        let mut decoder = FrameDecoder::new();
+        let size = content.len() as u64;
+        for range in [
+            0..size,
+            0..0,
+            5..6,
+            9_999..10_001,
+            12_345..37_000,
+            size - 1..size,
+        ] {
+            let expected = &content[range.start as usize..range.end as usize];
+            let decoded = table
+                .decompress_range(data, range.clone(), &mut decoder)
+                .unwrap();
+            assert_eq!(decoded, expected, "{:?}", range);
+            #[cfg(feature = "std")]
+            for threads in [1, 3, 16] {
+                let decoded = table
+                    .decompress_range_parallel(data, range.clone(), threads, FrameDecoder::new)
+                    .unwrap();
+                assert_eq!(decoded, expected, "{:?} on {} threads", range, threads);
+            }
+        }
+        assert!(matches!(
+            table.decompress_range(data, 10..size + 1, &mut decoder),
+            Err(SeekableError::RangeOutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn seekable_format() {
+        let content = content();
+        let data = seekable(&content, 10_000);
+        let table = SeekTable::read(&data).unwrap();
+        assert_eq!(table.entries().len(), 5);
+        assert_eq!(table.decompressed_size(), 50_000);
+        assert_eq!(table.entries()[1].decompressed_offset, 10_000);
+        assert_eq!(table.entries()[1].compressed_offset, 10_000 + 13);
+        assert_eq!(table.frames_for(9_999..10_001), 0..2);
+        assert_eq!(table.frames_for(10_000..10_000), 1..1);
+        check_ranges(&table, &data, &content);
+
+        // The frames have to add up to the data in front of the table
+        let mut damaged = data.clone();
+        damaged.remove(0);
+        assert!(matches!(
+            SeekTable::read(&damaged),
+            Err(SeekableError::InvalidSeekTable)
+        ));
+        // A table that is wrong about the content sizes is found when the frames are decoded
+        let mut damaged = data.clone();
+        let entries_start = data.len() - 9 - 5 * 8;
+        damaged[entries_start + 4] += 1;
+        let table = SeekTable::read(&damaged).unwrap();
+        assert!(matches!(
+            table.decompress_range(&damaged, 0..100, &mut FrameDecoder::new()),
+            Err(SeekableError::SizeMismatch {
+                position: 0,
+                expected: 10_001
+            })
+        ));
+        // Huge sizes are not allocated up front
+        damaged[entries_start + 4..entries_start + 8].copy_from_slice(&u32::MAX.to_le_bytes());
+        let table = SeekTable::read(&damaged).unwrap();
+        assert!(matches!(
+            table.decompress_range(&damaged, 0..100, &mut FrameDecoder::new()),
+            Err(SeekableError::SizeMismatch {
+                position: 0,
+                expected: 0xFFFF_FFFF
+            })
+        ));
+        assert!(SeekTable::read(&data[data.len() - 4..]).is_err());
+    }
+
+    #[test]
+    fn frames() {
+        let content = content();
+        let mut data = Vec::new();
+        for (index, chunk) in content.chunks(7_000).enumerate() {
+            let frame = raw_frame(chunk);
+            // Every other frame has a pzstd hint, and there is other application data in between
+            if index % 2 == 0 {
+                data.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 4, 0, 0, 0]);
+                data.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            } else {
+                data.extend_from_slice(&[0x53, 0x2A, 0x4D, 0x18, 2, 0, 0, 0, 1, 2]);
+            }
+            data.extend_from_slice(&frame);
+        }
+        let table = SeekTable::read(&data).unwrap();
+        assert_eq!(table.entries().len(), 8);
+        assert_eq!(table.entries()[1].compressed_offset, 12 + 7_013 + 10);
+        check_ranges(&table, &data, &content);
+
+        // A wrong hint points into the middle of a frame
+        let mut wrong_hint = data.clone();
+        wrong_hint[8] -= 1;
+        assert!(matches!(
+            SeekTable::read(&wrong_hint),
+            Err(SeekableError::InvalidFrame {
+                position: 7_024,
+                ..
+            })
+        ));
+
+        assert!(matches!(
+            SeekTable::read(&data[..data.len() - 1]),
+            Err(SeekableError::InvalidFrame { .. })
+        ));
+    }
+
+    #[cfg(feature = "encoder")]
+    #[test]
+    fn pzstd_frames() {
+        use crate::encoding::FrameCompressorBuilder;
+
+        let content = content();
+        let compress = |content_size: bool| {
+            let mut data = Vec::new();
+            FrameCompressorBuilder::new()
+                .content_size(content_size)
+                .pzstd_frame_size(Some(4096))
+                .build(content.as_slice(), &mut data)
+                .unwrap()
+                .compress()
+                .unwrap();
+            data
+        };
+        let data = compress(true);
+        let table = SeekTable::read(&data).unwrap();
+        assert_eq!(table.entries().len(), 13);
+        check_ranges(&table, &data, &content);
+
+        assert!(matches!(
+            SeekTable::read(&compress(false)),
+            Err(SeekableError::MissingContentSize { position: 12 })
+        ));
+    }
+}
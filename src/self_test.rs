@@ -0,0 +1,230 @@
+//! A check that the decoder works correctly on the target it was compiled for, see [self_test].
+//!
+//! The golden frames come from the reference `decodecorpus` tool (the files in `decodecorpus_files`),
+//! converted to single segment frames so the decoder only needs a window as large as their content.
+//! The content of `z000017` is larger than 8 KiB, it was compressed again with
+//! `zstd -19 --zstd=wlog=13` to fit the windows of the `small-target` feature.
+//! Together they cover every block type, literals section type and sequence table mode:
+//!
+//! - `z000094`: raw and RLE blocks, raw, compressed (1 stream) and treeless (4 streams) literals,
+//!   predefined, RLE and FSE compressed sequence tables
+//! - `z000053`: compressed literals with 4 streams, RLE literals, repeated literal length and match
+//!   length tables
+//! - `z000034`: treeless literals with 1 stream
+//! - `z000083`: compressed blocks without sequences
+//! - `z000090`: predefined literal length tables
+//! - `z000017`: repeated offset tables
+
+use core::fmt;
+
+use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+use crate::io::Read;
+
+/// A frame and what it decodes to.
+struct Golden<'a> {
+    name: &'static str,
+    frame: &'a [u8],
+    content_size: usize,
+    /// The 64 bit FNV-1a hash of the content
+    content_hash: u64,
+}
+
+const GOLDEN: [Golden<'static>; 6] = [
+    Golden {
+        name: "z000094",
+        frame: include_bytes!("self_test/z000094.zst"),
+        content_size: 689,
+        content_hash: 0x81FB_0E1A_1123_68F8,
+    },
+    Golden {
+        name: "z000053",
+        frame: include_bytes!("self_test/z000053.zst"),
+        content_size: 745,
+        content_hash: 0xDADC_D8DE_F23A_5322,
+    },
+    Golden {
+        name: "z000034",
+        frame: include_bytes!("self_test/z000034.zst"),
+        content_size: 840,
+        content_hash: 0x757E_DC10_FF4F_173D,
+    },
+    Golden {
+        name: "z000083",
+        frame: include_bytes!("self_test/z000083.zst"),
+        content_size: 153,
+        content_hash: 0x8A02_20C0_ADF3_E65C,
+    },
+    Golden {
+        name: "z000090",
+        frame: include_bytes!("self_test/z000090.zst"),
+        content_size: 410,
+        content_hash: 0xE9AC_9E40_BB92_A507,
+    },
+    Golden {
+        name: "z000017",
+        frame: include_bytes!("self_test/z000017.zst"),
+        content_size: 26402,
+        content_hash: 0x524A_2B4F_D863_E99D,
+    },
+];
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SelfTestError {
+    /// The golden frame `frame` could not be decoded.
+    DecodeError {
+        frame: &'static str,
+        error: FrameDecoderError,
+    },
+    /// The golden frame `frame` decoded to different content than expected.
+    WrongContent { frame: &'static str },
+    /// The content of the golden frame `frame` is correct, but the checksum calculated from it is not.
+    ChecksumMismatch { frame: &'static str },
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::DecodeError { frame, error } => {
+                write!(f, "Failed to decode the golden frame {}: {}", frame, error)
+            }
+            Self::WrongContent { frame } => {
+                write!(f, "The golden frame {} decoded to wrong content", frame)
+            }
+            Self::ChecksumMismatch { frame } => {
+                write!(
+                    f,
+                    "Wrong checksum calculated for the golden frame {}",
+                    frame
+                )
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SelfTestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::DecodeError { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Decode a small set of golden frames and check their content, to make sure the decoder works on
+/// an unusual target, e.g. one with an uncommon word size or endianness, or a compiler that has not
+/// been tested much.
+///
+/// The frames take about 8 KiB of space in the binary. Checking them needs a window of 8 KiB and a
+/// block of 8 KiB on the heap, the content is checked while it is decoded and is not kept. This also
+/// works with the `small-target` feature.
+///
+/// ```
+/// ruzstd::self_test::self_test().expect("the decoder does not work on this target");
+/// ```
+pub fn self_test() -> Result<(), SelfTestError> {
+    let mut decoder = FrameDecoder::new();
+    for golden in &GOLDEN {
+        check(&mut decoder, golden)?;
+    }
+    Ok(())
+}
+
+fn check(decoder: &mut FrameDecoder, golden: &Golden<'_>) -> Result<(), SelfTestError> {
+    let decode_error = |error| SelfTestError::DecodeError {
+        frame: golden.name,
+        error,
+    };
+    let mut source = golden.frame;
+    decoder.reset(&mut source).map_err(decode_error)?;
+
+    let mut hash = FNV_OFFSET_BASIS;
+    let mut size = 0;
+    let mut buf = [0; crate::STACK_BUFFER_SIZE];
+    loop {
+        let finished = decoder
+            .decode_from_slice(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+            .map_err(decode_error)?;
+        loop {
+            let read = decoder
+                .read(&mut buf)
+                .map_err(|e| decode_error(FrameDecoderError::FailedToDrainDecodebuffer(e)))?;
+            if read == 0 {
+                break;
+            }
+            hash = fnv1a(hash, &buf[..read]);
+            size += read;
+        }
+        if finished {
+            break;
+        }
+    }
+
+    if size != golden.content_size || hash != golden.content_hash || !source.is_empty() {
+        return Err(SelfTestError::WrongContent { frame: golden.name });
+    }
+    #[cfg(feature = "hash")]
+    if decoder.get_checksum_from_data() != decoder.get_calculated_checksum() {
+        return Err(SelfTestError::ChecksumMismatch { frame: golden.name });
+    }
+    Ok(())
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+
+fn fnv1a(mut hash: u64, data: &[u8]) -> u64 {
+    for &byte in data {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, self_test, Golden, SelfTestError, GOLDEN};
+    use crate::frame_decoder::FrameDecoder;
+
+    #[test]
+    fn golden_frames() {
+        self_test().unwrap();
+    }
+
+    /// The frames decode to the same content as the corpus files they were made from
+    #[cfg(feature = "std")]
+    #[test]
+    fn golden_content() {
+        use super::{fnv1a, FNV_OFFSET_BASIS};
+
+        for golden in &GOLDEN {
+            let path = std::format!("decodecorpus_files/{}", golden.name);
+            let content = std::fs::read(path).unwrap();
+            assert_eq!(content.len(), golden.content_size);
+            assert_eq!(fnv1a(FNV_OFFSET_BASIS, &content), golden.content_hash);
+        }
+    }
+
+    #[test]
+    fn wrong_content() {
+        let golden = Golden {
+            content_hash: GOLDEN[0].content_hash ^ 1,
+            ..GOLDEN[0]
+        };
+        assert!(matches!(
+            check(&mut FrameDecoder::new(), &golden),
+            Err(SelfTestError::WrongContent { frame: "z000094" })
+        ));
+
+        let mut frame = GOLDEN[3].frame.to_vec();
+        frame.truncate(frame.len() - 10);
+        let golden = Golden {
+            frame: &frame,
+            ..GOLDEN[3]
+        };
+        assert!(matches!(
+            check(&mut FrameDecoder::new(), &golden),
+            Err(SelfTestError::DecodeError { .. })
+        ));
+    }
+}
@@ -0,0 +1,302 @@
+//! Reading and writing skippable frames, which carry application data that decoders skip over.
+//!
+//! A skippable frame consists of a magic number between `0x184D2A50` and `0x184D2A5F`, the length
+//! of the data as a 32 bit little endian number and the data itself. The lowest four bits of the
+//! magic number are free to choose, this module calls them the magic variant.
+//!
+//! Besides raw data, [SkippableFrame] can also store a list of key/value pairs. Each key and each
+//! value is stored as its length (32 bit little endian) followed by its bytes.
+//!
+//! <https://github.com/facebook/zstd/blob/dev/doc/zstd_compression_format.md#skippable-frames>
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+
+use crate::fallible::{try_vec, AllocError, TryVec};
+use crate::io::{Error, Read, Write};
+
+/// The magic number of skippable frames with the magic variant 0.
+pub const SKIPPABLE_MAGIC_NUM: u32 = 0x184D_2A50;
+
+/// A key and its value, see [SkippableFrame::from_key_values].
+pub type KeyValue<'a> = (&'a [u8], &'a [u8]);
+
+/// A skippable frame, holding arbitrary data that zstd decoders ignore.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SkippableFrame {
+    magic_variant: u8,
+    data: Vec<u8>,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SkippableFrameError {
+    /// Only the values 0 to 15 can be stored in the magic number.
+    InvalidMagicVariant {
+        got: u8,
+    },
+    /// The data of a skippable frame cannot be larger than `u32::MAX` bytes.
+    DataTooLarge {
+        size: usize,
+    },
+    NotSkippable {
+        magic_number: u32,
+    },
+    /// The key/value pairs ended in the middle of a key or value.
+    MalformedKeyValues {
+        position: usize,
+    },
+    ReadError(Error),
+    WriteError(Error),
+    /// There was not enough memory for the data of the frame.
+    OutOfMemory {
+        size: usize,
+    },
+}
+
+impl fmt::Display for SkippableFrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMagicVariant { got } => {
+                write!(f, "Invalid magic variant {}, must be between 0 and 15", got)
+            }
+            Self::DataTooLarge { size } => write!(
+                f,
+                "Skippable frames can hold at most {} bytes, got {} bytes",
+                u32::MAX,
+                size
+            ),
+            Self::NotSkippable { magic_number } => write!(
+                f,
+                "Magic number 0x{:X} does not belong to a skippable frame",
+                magic_number
+            ),
+            Self::MalformedKeyValues { position } => write!(
+                f,
+                "Key/value data ended in the middle of an entry at byte {}",
+                position
+            ),
+            Self::ReadError(e) => write!(f, "Error while reading skippable frame: {}", e),
+            Self::WriteError(e) => write!(f, "Error while writing skippable frame: {}", e),
+            Self::OutOfMemory { size } => write!(
+                f,
+                "Failed to allocate {} bytes for the data of a skippable frame",
+                size
+            ),
+        }
+    }
+}
+
+impl From<AllocError> for SkippableFrameError {
+    fn from(val: AllocError) -> Self {
+        Self::OutOfMemory { size: val.size }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SkippableFrameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SkippableFrameError::ReadError(source) => Some(source),
+            SkippableFrameError::WriteError(source) => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl SkippableFrame {
+    /// Create a skippable frame holding `data`, with a magic variant between 0 and 15.
+    pub fn new(magic_variant: u8, data: Vec<u8>) -> Result<Self, SkippableFrameError> {
+        if magic_variant > 0xF {
+            return Err(SkippableFrameError::InvalidMagicVariant { got: magic_variant });
+        }
+        if u32::try_from(data.len()).is_err() {
+            return Err(SkippableFrameError::DataTooLarge { size: data.len() });
+        }
+        Ok(Self {
+            magic_variant,
+            data,
+        })
+    }
+
+    /// Create a skippable frame holding the key/value pairs in `entries`.
+    pub fn from_key_values<'a>(
+        magic_variant: u8,
+        entries: impl IntoIterator<Item = KeyValue<'a>>,
+    ) -> Result<Self, SkippableFrameError> {
+        let mut data = Vec::new();
+        for (key, value) in entries {
+            for field in [key, value] {
+                let len = u32::try_from(field.len())
+                    .map_err(|_| SkippableFrameError::DataTooLarge { size: field.len() })?;
+                data.try_extend_from_slice(&len.to_le_bytes())?;
+                data.try_extend_from_slice(field)?;
+            }
+        }
+        Self::new(magic_variant, data)
+    }
+
+    /// Read a whole skippable frame, including the magic number.
+    ///
+    /// Returns [SkippableFrameError::NotSkippable] if the source starts with a different frame.
+    pub fn read(mut source: impl Read) -> Result<Self, SkippableFrameError> {
+        let mut buf = [0u8; 8];
+        source
+            .read_exact(&mut buf)
+            .map_err(SkippableFrameError::ReadError)?;
+        let magic_number = u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let length = u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]);
+        Self::read_data(source, magic_number, length)
+    }
+
+    /// Read the data of a skippable frame whose magic number and length were already read, e.g. by
+    /// [read_frame_header](crate::frame::read_frame_header) which reports them as
+    /// [ReadFrameHeaderError::SkipFrame](crate::frame::ReadFrameHeaderError::SkipFrame).
+    pub fn read_data(
+        mut source: impl Read,
+        magic_number: u32,
+        length: u32,
+    ) -> Result<Self, SkippableFrameError> {
+        if magic_number & !0xF != SKIPPABLE_MAGIC_NUM {
+            return Err(SkippableFrameError::NotSkippable { magic_number });
+        }
+        let mut data = try_vec(0, length as usize)?;
+        source
+            .read_exact(&mut data)
+            .map_err(SkippableFrameError::ReadError)?;
+        Ok(Self {
+            magic_variant: (magic_number & 0xF) as u8,
+            data,
+        })
+    }
+
+    /// Write the frame including the magic number and the length.
+    pub fn write(&self, mut target: impl Write) -> Result<(), SkippableFrameError> {
+        let mut header = [0u8; 8];
+        header[..4].copy_from_slice(&self.magic_number().to_le_bytes());
+        header[4..].copy_from_slice(&(self.data.len() as u32).to_le_bytes());
+        target
+            .write_all(&header)
+            .map_err(SkippableFrameError::WriteError)?;
+        target
+            .write_all(&self.data)
+            .map_err(SkippableFrameError::WriteError)
+    }
+
+    /// The lowest four bits of the magic number.
+    pub fn magic_variant(&self) -> u8 {
+        self.magic_variant
+    }
+
+    /// The full magic number this frame is written with.
+    pub fn magic_number(&self) -> u32 {
+        SKIPPABLE_MAGIC_NUM | u32::from(self.magic_variant)
+    }
+
+    /// The raw data of the frame.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Take the raw data out of the frame.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// Parse the data as key/value pairs, like they are written by [SkippableFrame::from_key_values].
+    pub fn key_values(&self) -> Result<Vec<KeyValue<'_>>, SkippableFrameError> {
+        let mut entries = Vec::new();
+        let mut position = 0;
+        let next_field = |position: &mut usize| {
+            let malformed = SkippableFrameError::MalformedKeyValues {
+                position: *position,
+            };
+            let len = self.data.get(*position..*position + 4).ok_or(malformed)?;
+            let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            let start = *position + 4;
+            let field = self
+                .data
+                .get(start..start.saturating_add(len))
+                .ok_or(SkippableFrameError::MalformedKeyValues { position: start })?;
+            *position = start + len;
+            Ok::<_, SkippableFrameError>(field)
+        };
+        while position < self.data.len() {
+            let key = next_field(&mut position)?;
+            let value = next_field(&mut position)?;
+            entries.try_push((key, value))?;
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeyValue, SkippableFrame, SkippableFrameError};
+    use crate::frame_decoder::FrameDecoder;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn write_and_read() {
+        let frame = SkippableFrame::new(7, vec![1, 2, 3]).unwrap();
+        assert_eq!(frame.magic_number(), 0x184D2A57);
+        let mut written = Vec::new();
+        frame.write(&mut written).unwrap();
+        assert_eq!(written, [0x57, 0x2A, 0x4D, 0x18, 3, 0, 0, 0, 1, 2, 3]);
+        assert_eq!(SkippableFrame::read(written.as_slice()).unwrap(), frame);
+
+        // decoders skip the frame
+        let mut decoder = FrameDecoder::new();
+        let mut output = Vec::new();
+        decoder.decode_all_to_vec(&written, &mut output).unwrap();
+        assert!(output.is_empty());
+
+        assert!(matches!(
+            SkippableFrame::new(16, vec![]),
+            Err(SkippableFrameError::InvalidMagicVariant { got: 16 })
+        ));
+        assert!(matches!(
+            SkippableFrame::read(&crate::frame::MAGIC_NUM.to_le_bytes()[..]),
+            Err(SkippableFrameError::ReadError(_))
+        ));
+        let mut zstd_frame = crate::frame::MAGIC_NUM.to_le_bytes().to_vec();
+        zstd_frame.extend_from_slice(&[0; 4]);
+        assert!(matches!(
+            SkippableFrame::read(zstd_frame.as_slice()),
+            Err(SkippableFrameError::NotSkippable { .. })
+        ));
+        assert!(matches!(
+            SkippableFrame::read(&written[..written.len() - 1]),
+            Err(SkippableFrameError::ReadError(_))
+        ));
+    }
+
+    #[test]
+    fn key_values() {
+        let entries: [KeyValue<'_>; 3] = [
+            (b"name", b"archive.tar"),
+            (b"", b"empty key"),
+            (b"empty value", b""),
+        ];
+        let frame = SkippableFrame::from_key_values(0, entries).unwrap();
+        assert_eq!(frame.key_values().unwrap(), entries);
+
+        let empty = SkippableFrame::new(0, vec![]).unwrap();
+        assert!(empty.key_values().unwrap().is_empty());
+
+        for len in 1..frame.data().len() {
+            let truncated = SkippableFrame::new(0, frame.data()[..len].to_vec()).unwrap();
+            // cutting right after a complete entry is still valid
+            if let Ok(parsed) = truncated.key_values() {
+                assert_eq!(parsed, entries[..parsed.len()]);
+            }
+        }
+        let truncated = SkippableFrame::new(0, frame.data()[..6].to_vec()).unwrap();
+        assert!(matches!(
+            truncated.key_values(),
+            Err(SkippableFrameError::MalformedKeyValues { position: 4 })
+        ));
+    }
+}
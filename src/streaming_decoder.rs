@@ -1,5 +1,13 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::borrow::BorrowMut;
 
+#[cfg(feature = "hash")]
+use crate::decoding::decode_options::ChecksumPolicy;
+use crate::decoding::decode_options::DecodeOptions;
+use crate::decoding::dictionary::Dictionary;
+use crate::fallible::TryVec;
+use crate::frame::ReadFrameHeaderError;
 use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
 use crate::io::{Error, ErrorKind, Read};
 
@@ -14,14 +22,22 @@ use crate::io::{Error, ErrorKind, Read};
 /// [FrameDecoder::decode_blocks] repeatedly to decode the entire frame.
 ///
 /// ## Caveat
-/// [StreamingDecoder] expects the underlying stream to only contain a single frame,
+/// By default [StreamingDecoder] expects the underlying stream to only contain a single frame,
 /// yet the specification states that a single archive may contain multiple frames.
 ///
-/// To decode all the frames in a finite stream, the calling code needs to recreate
-/// the instance of the decoder and handle
+/// To decode all the frames in a stream, create the decoder with [DecodeOptions::multi_frame]
+/// set. Otherwise the calling code needs to recreate the instance of the decoder and handle
 /// [crate::frame::ReadFrameHeaderError::SkipFrame]
 /// errors by skipping forward the `length` amount of bytes, see <https://github.com/KillingSpark/zstd-rs/issues/57>
 ///
+/// The source is expected to block until data is available. It may return fewer bytes than asked
+/// for and fail with `Interrupted` at any point, reading simply continues. Any other error of the
+/// source, including `WouldBlock`, can not be recovered from, so for non-blocking sources use
+/// [PushDecoder::read_from](crate::push_decoder::PushDecoder::read_from) instead.
+///
+/// If the stream is known to contain exactly one frame, [StreamingDecoder::set_reject_trailing_data]
+/// turns any data after the frame into an error, instead of silently ignoring it.
+///
 /// ```no_run
 /// // `read_to_end` is not implemented by the no_std implementation.
 /// #[cfg(feature = "std")]
@@ -37,9 +53,51 @@ use crate::io::{Error, ErrorKind, Read};
 ///     Read::read_to_end(&mut decoder, &mut result).unwrap();
 /// }
 /// ```
+/// Where a frame was found in the source and where its content ended up in the output,
+/// see [StreamingDecoder::drain_finished_frames].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameBoundary {
+    /// The offset of the first byte of the frame in the source.
+    pub compressed_start: u64,
+    /// The offset right after the last byte of the frame in the source.
+    pub compressed_end: u64,
+    /// The offset of the first byte of the content of the frame in the output.
+    pub decompressed_start: u64,
+    /// The offset right after the last byte of the content of the frame in the output.
+    pub decompressed_end: u64,
+}
+
+/// A frame whose checksum did not match its content,
+/// see [ChecksumPolicy::Report](crate::decoding::decode_options::ChecksumPolicy::Report).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    /// The offset of the first byte of the frame in the source.
+    pub compressed_start: u64,
+    /// The checksum stored in the frame.
+    pub expected: u32,
+    /// The checksum of the content that was decoded.
+    pub calculated: u32,
+}
+
 pub struct StreamingDecoder<READ: Read, DEC: BorrowMut<FrameDecoder>> {
     pub decoder: DEC,
     source: READ,
+    reject_trailing_data: bool,
+    multi_frame: bool,
+    #[cfg(feature = "hash")]
+    checksum: ChecksumPolicy,
+    /// The number of bytes of the source in front of the current frame
+    frame_start: u64,
+    /// The number of bytes of output in front of the content of the current frame
+    frame_output_start: u64,
+    /// The number of bytes of output so far
+    output_position: u64,
+    /// Whether the end of the current frame was recorded in `finished_frames`
+    frame_recorded: bool,
+    /// Whether the checksum of the current frame was checked
+    frame_checked: bool,
+    finished_frames: Vec<FrameBoundary>,
+    checksum_mismatches: Vec<ChecksumMismatch>,
 }
 
 impl<READ: Read, DEC: BorrowMut<FrameDecoder>> StreamingDecoder<READ, DEC> {
@@ -48,21 +106,88 @@ impl<READ: Read, DEC: BorrowMut<FrameDecoder>> StreamingDecoder<READ, DEC> {
         mut decoder: DEC,
     ) -> Result<StreamingDecoder<READ, DEC>, FrameDecoderError> {
         decoder.borrow_mut().init(&mut source)?;
-        Ok(StreamingDecoder { decoder, source })
+        Ok(StreamingDecoder {
+            decoder,
+            source,
+            reject_trailing_data: false,
+            multi_frame: false,
+            #[cfg(feature = "hash")]
+            checksum: ChecksumPolicy::Ignore,
+            frame_start: 0,
+            frame_output_start: 0,
+            output_position: 0,
+            frame_recorded: false,
+            frame_checked: false,
+            finished_frames: Vec::new(),
+            checksum_mismatches: Vec::new(),
+        })
     }
 }
 
 impl<READ: Read> StreamingDecoder<READ, FrameDecoder> {
-    pub fn new(
+    pub fn new(source: READ) -> Result<StreamingDecoder<READ, FrameDecoder>, FrameDecoderError> {
+        Self::new_with_decoder(source, FrameDecoder::new())
+    }
+
+    /// Create a decoder for frames that were compressed with `dictionary`.
+    pub fn new_with_dict(
+        source: READ,
+        dictionary: impl Into<Arc<Dictionary>>,
+    ) -> Result<StreamingDecoder<READ, FrameDecoder>, FrameDecoderError> {
+        Self::with_dictionaries(source, [dictionary.into()], DecodeOptions::default())
+    }
+
+    /// Create a decoder with the given options.
+    pub fn with_options(
+        source: READ,
+        options: DecodeOptions,
+    ) -> Result<StreamingDecoder<READ, FrameDecoder>, FrameDecoderError> {
+        Self::with_dictionaries(source, core::iter::empty::<Arc<Dictionary>>(), options)
+    }
+
+    /// Create a decoder with the given options, for frames that were compressed with any of
+    /// `dictionaries`. Each frame picks the dictionary it needs by its ID.
+    pub fn with_dictionaries(
         mut source: READ,
+        dictionaries: impl IntoIterator<Item = impl Into<Arc<Dictionary>>>,
+        options: DecodeOptions,
     ) -> Result<StreamingDecoder<READ, FrameDecoder>, FrameDecoderError> {
         let mut decoder = FrameDecoder::new();
+        decoder.set_max_window_size(options.max_window_size);
+        decoder.set_magicless(options.magicless);
+        decoder.set_decode_without_dict(options.decode_without_dict);
+        for dictionary in dictionaries {
+            decoder.add_dict(dictionary)?;
+        }
         decoder.init(&mut source)?;
-        Ok(StreamingDecoder { decoder, source })
+        Ok(StreamingDecoder {
+            decoder,
+            source,
+            reject_trailing_data: false,
+            multi_frame: options.multi_frame,
+            #[cfg(feature = "hash")]
+            checksum: options.checksum,
+            frame_start: 0,
+            frame_output_start: 0,
+            output_position: 0,
+            frame_recorded: false,
+            frame_checked: false,
+            finished_frames: Vec::new(),
+            checksum_mismatches: Vec::new(),
+        })
     }
 }
 
 impl<READ: Read, DEC: BorrowMut<FrameDecoder>> StreamingDecoder<READ, DEC> {
+    /// If enabled, reading past the end of the frame fails with
+    /// [`FrameDecoderError::TrailingData`] if the source contains any more data.
+    ///
+    /// This catches truncated or corrupted archives that would otherwise decode without
+    /// error, but also rejects sources with multiple frames. Disabled by default.
+    pub fn set_reject_trailing_data(&mut self, reject: bool) {
+        self.reject_trailing_data = reject;
+    }
+
     /// Gets a reference to the underlying reader.
     pub fn get_ref(&self) -> &READ {
         &self.source
@@ -95,15 +220,143 @@ impl<READ: Read, DEC: BorrowMut<FrameDecoder>> StreamingDecoder<READ, DEC> {
     pub fn into_frame_decoder(self) -> DEC {
         self.decoder
     }
+
+    /// Take the boundaries of the frames whose content has been read completely since the last call.
+    ///
+    /// Together with [StreamingDecoder::current_frame_start] this tells where each frame started and
+    /// ended in the source and in the output, e.g. to build a seek table while decoding.
+    pub fn drain_finished_frames(&mut self) -> alloc::vec::Drain<'_, FrameBoundary> {
+        self.finished_frames.drain(..)
+    }
+
+    /// The frames whose checksum did not match their content so far. Only filled with
+    /// [ChecksumPolicy::Report](crate::decoding::decode_options::ChecksumPolicy::Report),
+    /// the content of these frames has been returned from `read` anyway.
+    pub fn checksum_mismatches(&self) -> &[ChecksumMismatch] {
+        &self.checksum_mismatches
+    }
+
+    /// The offsets of the frame that is decoded at the moment, in the source and in the output.
+    pub fn current_frame_start(&self) -> (u64, u64) {
+        (self.frame_start, self.frame_output_start)
+    }
+
+    /// Record the boundary of the current frame once its content has been read completely.
+    fn record_frame_end(&mut self) -> Result<(), FrameDecoderError> {
+        let decoder = self.decoder.borrow_mut();
+        if self.frame_recorded || !decoder.is_finished() || decoder.can_collect() != 0 {
+            return Ok(());
+        }
+        self.finished_frames.try_push(FrameBoundary {
+            compressed_start: self.frame_start,
+            compressed_end: self.frame_start + decoder.bytes_read_from_source(),
+            decompressed_start: self.frame_output_start,
+            decompressed_end: self.output_position,
+        })?;
+        self.frame_recorded = true;
+        Ok(())
+    }
+
+    /// Check the checksum of the frame that was just decoded, if the policy asks for it and it was
+    /// not checked yet.
+    fn check_frame(&mut self) -> Result<(), FrameDecoderError> {
+        if self.frame_checked {
+            return Ok(());
+        }
+        #[cfg(feature = "hash")]
+        if self.checksum != ChecksumPolicy::Ignore {
+            let decoder = self.decoder.borrow_mut();
+            if let (Some(expected), Some(calculated)) = (
+                decoder.get_checksum_from_data(),
+                decoder.get_calculated_checksum(),
+            ) {
+                if expected != calculated {
+                    if self.checksum == ChecksumPolicy::Verify {
+                        return Err(FrameDecoderError::ChecksumMismatch {
+                            expected,
+                            calculated,
+                        });
+                    }
+                    self.checksum_mismatches.try_push(ChecksumMismatch {
+                        compressed_start: self.frame_start,
+                        expected,
+                        calculated,
+                    })?;
+                }
+            }
+        }
+        self.frame_checked = true;
+        Ok(())
+    }
+
+    /// Start decoding the next frame in the source, skipping skippable frames on the way.
+    /// Returns false if the source has ended, the last frame stays the current one then.
+    fn next_frame(&mut self) -> Result<bool, Error> {
+        let decoder = self.decoder.borrow_mut();
+        let mut frame_start = self.frame_start + decoder.bytes_read_from_source();
+        loop {
+            let mut first = [0];
+            if read_uninterrupted(&mut self.source, &mut first)? == 0 {
+                return Ok(false);
+            }
+            let mut source = Prepend {
+                first: &first[..],
+                rest: &mut self.source,
+            };
+            match decoder.init(&mut source) {
+                Ok(()) => {
+                    self.frame_start = frame_start;
+                    self.frame_output_start = self.output_position;
+                    self.frame_recorded = false;
+                    self.frame_checked = false;
+                    return Ok(true);
+                }
+                Err(FrameDecoderError::ReadFrameHeaderError(ReadFrameHeaderError::SkipFrame {
+                    length,
+                    ..
+                })) => {
+                    skip(&mut self.source, length).map_err(to_io_error)?;
+                    frame_start += 8 + u64::from(length);
+                }
+                Err(FrameDecoderError::ReadFrameHeaderError(
+                    ReadFrameHeaderError::BadMagicNumber(_)
+                    | ReadFrameHeaderError::MagicNumberReadError(_),
+                )) => {
+                    return Err(to_io_error(FrameDecoderError::TrailingData {
+                        position: frame_start,
+                    }))
+                }
+                Err(e) => return Err(to_io_error(e)),
+            }
+        }
+    }
 }
 
 impl<READ: Read, DEC: BorrowMut<FrameDecoder>> Read for StreamingDecoder<READ, DEC> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        let decoder = self.decoder.borrow_mut();
-        if decoder.is_finished() && decoder.can_collect() == 0 {
-            //No more bytes can ever be decoded
-            return Ok(0);
+        loop {
+            let decoder = self.decoder.borrow_mut();
+            if !decoder.is_finished() || decoder.can_collect() != 0 {
+                break;
+            }
+            //No more bytes can ever be decoded from this frame
+            self.record_frame_end().map_err(to_io_error)?;
+            self.check_frame().map_err(to_io_error)?;
+            if !self.multi_frame {
+                let decoder = self.decoder.borrow_mut();
+                if self.reject_trailing_data && read_uninterrupted(&mut self.source, &mut [0])? > 0
+                {
+                    return Err(to_io_error(FrameDecoderError::TrailingData {
+                        position: decoder.bytes_read_from_source(),
+                    }));
+                }
+                return Ok(0);
+            }
+            if !self.next_frame()? {
+                return Ok(0);
+            }
         }
+        let decoder = self.decoder.borrow_mut();
 
         // need to loop. The UpToBytes strategy doesn't take any effort to actually reach that limit.
         // The first few calls can result in just filling the decode buffer but these bytes can not be collected.
@@ -118,21 +371,69 @@ impl<READ: Read, DEC: BorrowMut<FrameDecoder>> Read for StreamingDecoder<READ, D
                 BlockDecodingStrategy::UptoBytes(additional_bytes_needed),
             ) {
                 Ok(_) => { /*Nothing to do*/ }
-                Err(e) => {
-                    let err;
-                    #[cfg(feature = "std")]
-                    {
-                        err = Error::new(ErrorKind::Other, e);
-                    }
-                    #[cfg(not(feature = "std"))]
-                    {
-                        err = Error::new(ErrorKind::Other, alloc::boxed::Box::new(e));
-                    }
-                    return Err(err);
-                }
+                Err(e) => return Err(to_io_error(e)),
             }
         }
 
-        decoder.read(buf)
+        let bytes_read = decoder.read(buf)?;
+        self.output_position += bytes_read as u64;
+        // The bytes were read already, the next call records the frame again and reports the error
+        let _ = self.record_frame_end();
+        Ok(bytes_read)
+    }
+}
+
+/// A reader that yields `first` before the content of `rest`.
+struct Prepend<'a, R: Read> {
+    first: &'a [u8],
+    rest: R,
+}
+
+impl<R: Read> Read for Prepend<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.first.is_empty() {
+            return self.rest.read(buf);
+        }
+        self.first.read(buf)
+    }
+}
+
+/// Read into `buf` like [Read::read], but try again if the source was interrupted.
+fn read_uninterrupted(mut source: impl Read, buf: &mut [u8]) -> Result<usize, Error> {
+    loop {
+        match source.read(buf) {
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            result => return result,
+        }
+    }
+}
+
+/// Read and discard `length` bytes of `source`.
+fn skip(mut source: impl Read, length: u32) -> Result<(), FrameDecoderError> {
+    let mut remaining = length as usize;
+    let mut buf = [0; crate::STACK_BUFFER_SIZE];
+    while remaining > 0 {
+        let amount = remaining.min(buf.len());
+        source
+            .read_exact(&mut buf[..amount])
+            .map_err(|_| FrameDecoderError::FailedToSkipFrame)?;
+        remaining -= amount;
+    }
+    Ok(())
+}
+
+pub(crate) fn to_io_error(e: FrameDecoderError) -> Error {
+    let err;
+    #[cfg(feature = "std")]
+    {
+        err = Error::other(e);
+    }
+    #[cfg(not(feature = "std"))]
+    {
+        // There is no fallible version of `Box::new` on stable Rust, see the Readme
+        #[allow(clippy::disallowed_methods)]
+        let e = alloc::boxed::Box::new(e);
+        err = Error::new(crate::io::ErrorKind::Other, e);
     }
+    err
 }
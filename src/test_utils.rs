@@ -0,0 +1,260 @@
+//! Helpers for property testing code that uses this crate.
+//!
+//! The generators produce deterministic inputs (for a given `seed`) that resemble data
+//! commonly found in the wild, so integrations can cheaply check that everything survives
+//! a round trip through the encoder and decoder:
+//!
+//! ```
+//! use ruzstd::encoding::CompressionLevel;
+//! use ruzstd::test_utils::{assert_roundtrip, text_like};
+//!
+//! for seed in 0..10 {
+//!     let input = text_like(10_000, seed);
+//!     assert_roundtrip(&input, CompressionLevel::Fastest);
+//! }
+//! ```
+
+// The round trip assertions report failures by panicking, and running out of memory in a test harness may abort
+#![allow(
+    clippy::panic,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::disallowed_macros,
+    clippy::disallowed_methods
+)]
+
+use alloc::vec::Vec;
+
+use crate::decoding::dictionary::Dictionary;
+use crate::encoding::{CompressionLevel, EncodeOptions, FrameCompressor};
+use crate::frame_decoder::FrameDecoder;
+
+/// Compress `input` with the given level, decompress it again and assert that the result
+/// is equal to the input.
+///
+/// Panics if compression or decompression fail or if the decoded data differs from `input`.
+pub fn assert_roundtrip(input: &[u8], level: CompressionLevel) {
+    assert_roundtrip_with(
+        input,
+        &EncodeOptions {
+            level,
+            ..EncodeOptions::default()
+        },
+    );
+}
+
+/// Like [assert_roundtrip], but compress with all of `options`, e.g. a block size, window log,
+/// checksum or dictionary.
+///
+/// The decoder is set up to read what the options produce: it gets the dictionary, expects
+/// magicless frames if they are written, and accepts windows of any size. Checksums are verified
+/// with the `hash` feature.
+///
+/// ```
+/// use ruzstd::encoding::EncodeOptions;
+/// use ruzstd::test_utils::{assert_roundtrip_with, binary_records};
+///
+/// let options = EncodeOptions {
+///     block_size: Some(4096),
+///     window_log: Some(12),
+///     ..EncodeOptions::cli_default()
+/// };
+/// assert_roundtrip_with(&binary_records(50_000, 1), &options);
+/// ```
+pub fn assert_roundtrip_with(input: &[u8], options: &EncodeOptions) {
+    let mut compressed = Vec::new();
+    let mut compressor = FrameCompressor::with_options(input, &mut compressed, options.clone());
+    compressor.compress().expect("Compressing the input failed");
+
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut decoder = FrameDecoder::new();
+    decoder.set_magicless(options.magicless);
+    decoder.set_max_window_size(u64::MAX);
+    if let Some(dictionary) = &options.dictionary {
+        // The encoder only uses the content and repeat offsets of the dictionary
+        let dictionary = dictionary.dictionary();
+        let mut copy = Dictionary::from_raw_content(dictionary.id, dictionary.dict_content.clone());
+        copy.offset_hist = dictionary.offset_hist;
+        decoder
+            .add_dict(copy)
+            .expect("Adding the dictionary to the decoder failed");
+    }
+    decoder
+        .decode_all_to_vec(&compressed, &mut decoded)
+        .expect("Decoding the compressed data failed");
+
+    assert_eq!(decoded.len(), input.len(), "Decoded length differs");
+    if let Some(idx) = decoded.iter().zip(input).position(|(a, b)| a != b) {
+        panic!("Decoded data differs from the input at byte {}", idx);
+    }
+}
+
+/// Runs of repeated bytes with random lengths between 1 and 512 bytes.
+pub fn runs(len: usize, seed: u64) -> Vec<u8> {
+    let mut rng = Rng::new(seed);
+    let mut output = Vec::with_capacity(len);
+    while output.len() < len {
+        let byte = rng.next() as u8;
+        let run = 1 + rng.below(512);
+        let run = usize::min(run, len - output.len());
+        output.resize(output.len() + run, byte);
+    }
+    output
+}
+
+/// Uniformly random bytes, which are not compressible at all.
+pub fn random(len: usize, seed: u64) -> Vec<u8> {
+    let mut rng = Rng::new(seed);
+    (0..len).map(|_| rng.next() as u8).collect()
+}
+
+/// Words from a small vocabulary separated by spaces, punctuation and newlines.
+pub fn text_like(len: usize, seed: u64) -> Vec<u8> {
+    const WORDS: &[&str] = &[
+        "the",
+        "of",
+        "and",
+        "to",
+        "in",
+        "is",
+        "was",
+        "that",
+        "for",
+        "on",
+        "compression",
+        "frame",
+        "block",
+        "literal",
+        "sequence",
+        "window",
+        "offset",
+        "zstandard",
+        "decoder",
+        "encoder",
+        "a",
+        "with",
+        "as",
+        "by",
+        "table",
+    ];
+    const SEPARATORS: &[&str] = &[" ", " ", " ", " ", ", ", ". ", ".\n"];
+
+    let mut rng = Rng::new(seed);
+    let mut output = Vec::with_capacity(len + 16);
+    while output.len() < len {
+        output.extend_from_slice(WORDS[rng.below(WORDS.len())].as_bytes());
+        output.extend_from_slice(SEPARATORS[rng.below(SEPARATORS.len())].as_bytes());
+    }
+    output.truncate(len);
+    output
+}
+
+/// Fixed size records like they would appear in a binary log or database file.
+///
+/// Each 32 byte record contains an incrementing id, a monotonic timestamp, a type tag out of a
+/// small set of values, a random payload and zero padding.
+pub fn binary_records(len: usize, seed: u64) -> Vec<u8> {
+    let mut rng = Rng::new(seed);
+    let mut output = Vec::with_capacity(len + 32);
+    let mut id = rng.next() as u32;
+    let mut timestamp = rng.next() >> 24;
+    while output.len() < len {
+        id = id.wrapping_add(1);
+        timestamp += rng.below(1000) as u64;
+        output.extend_from_slice(&id.to_le_bytes());
+        output.extend_from_slice(&timestamp.to_le_bytes());
+        output.push(rng.below(4) as u8);
+        output.extend_from_slice(&rng.next().to_le_bytes()[..7]);
+        output.extend_from_slice(&[0; 12]);
+    }
+    output.truncate(len);
+    output
+}
+
+/// `len` bytes from a 32 bit linear congruential generator that starts at the low 32 bits of
+/// `seed`. They do not repeat within 16 MiB, so they do not compress at all, and other test data
+/// can pick from them.
+pub fn lcg_bytes(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed as u32;
+    (0..len)
+        .map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (state >> 16) as u8
+        })
+        .collect()
+}
+
+/// A small xorshift generator, good enough to generate test data.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // One splitmix64 step spreads similar seeds apart, xorshift must not start at zero
+        let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Rng((state ^ (state >> 31)) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generators_roundtrip() {
+        let generators: [fn(usize, u64) -> Vec<u8>; 5] =
+            [runs, random, text_like, binary_records, lcg_bytes];
+        for generator in generators {
+            for (len, seed) in [(0, 0), (1, 1), (100, 2), (10_000, 3), (300_000, 4)] {
+                let input = generator(len, seed);
+                assert_eq!(input.len(), len);
+                assert_eq!(input, generator(len, seed));
+                assert_roundtrip(&input, CompressionLevel::Uncompressed);
+                assert_roundtrip(&input, CompressionLevel::Fastest);
+            }
+        }
+
+        // The seed that used to give xorshift a state of zero
+        let input = random(100, 0x9E37_79B9_7F4A_7C15);
+        assert!(input.iter().any(|byte| *byte != 0));
+    }
+
+    #[test]
+    fn roundtrip_with_options() {
+        use crate::encoding::{EncoderDictionary, Strategy};
+        use alloc::sync::Arc;
+
+        let input = text_like(100_000, 5);
+        let dictionary = Arc::new(EncoderDictionary::new(
+            Dictionary::from_raw_content(9, text_like(20_000, 6)),
+            Strategy::Fast,
+        ));
+        let options = [
+            EncodeOptions {
+                block_size: Some(1000),
+                window_log: Some(10),
+                ..EncodeOptions::default()
+            },
+            EncodeOptions {
+                dictionary: Some(dictionary),
+                magicless: true,
+                ..EncodeOptions::cli_default()
+            },
+        ];
+        for options in &options {
+            assert_roundtrip_with(&input, options);
+        }
+    }
+}
@@ -77,3 +77,66 @@ fn test_bitreader_normal() {
         );
     }
 }
+
+#[test]
+fn golden_bit_streams() {
+    use crate::decoding::bit_reader::BitReader;
+    use crate::decoding::bit_reader_reverse::BitReaderReversed;
+    use crate::encoding::bit_writer::BitWriter;
+
+    /// Fields of different widths and the bytes zstd stores them as, lowest bit first. The bytes
+    /// were worked out independently of the code under test, so the test fails if reading or
+    /// writing ever depends on the byte order of the target.
+    const GOLDEN_FIELDS: [(u64, u8); 12] = [
+        (0b101, 3),
+        (0x1FF, 9),
+        (0, 1),
+        (0xABCDE, 20),
+        (1, 1),
+        (0x2_F0F0_F0F0, 34),
+        (0x7F, 7),
+        (0x12_3456_789A_BCDE, 56),
+        (0x5A, 8),
+        (0x3, 2),
+        (0, 0),
+        (0x1_2345, 17),
+    ];
+    const GOLDEN_STREAM: [u8; 20] = [
+        0xFD, 0xCF, 0x9B, 0x57, 0xC3, 0xC3, 0xC3, 0xC3, 0xFB, 0xF7, 0xE6, 0xD5, 0xC4, 0xB3, 0xA2,
+        0x91, 0xD0, 0xBA, 0x68, 0x24,
+    ];
+    /// The same fields followed by the 1 bit that marks the start of a stream read backwards
+    const GOLDEN_REVERSED_STREAM: [u8; 20] = [
+        0xFD, 0xCF, 0x9B, 0x57, 0xC3, 0xC3, 0xC3, 0xC3, 0xFB, 0xF7, 0xE6, 0xD5, 0xC4, 0xB3, 0xA2,
+        0x91, 0xD0, 0xBA, 0x68, 0x64,
+    ];
+
+    let mut writer = BitWriter::new();
+    for (value, bits) in GOLDEN_FIELDS {
+        writer.write_bits(value, usize::from(bits));
+    }
+    writer.write_bits(0u8, writer.misaligned());
+    assert_eq!(writer.dump(), GOLDEN_STREAM);
+
+    let mut writer = BitWriter::new();
+    for (value, bits) in GOLDEN_FIELDS {
+        writer.write_bits(value, usize::from(bits));
+    }
+    writer.write_bits(1u8, 1);
+    writer.write_bits(0u8, writer.misaligned());
+    assert_eq!(writer.dump(), GOLDEN_REVERSED_STREAM);
+
+    let mut reader = BitReader::new(&GOLDEN_STREAM);
+    for (value, bits) in GOLDEN_FIELDS {
+        assert_eq!(reader.get_bits(usize::from(bits)).unwrap(), value);
+    }
+    assert_eq!(reader.bits_left(), 2);
+
+    let mut reader = BitReaderReversed::new(&GOLDEN_REVERSED_STREAM);
+    let padding = GOLDEN_REVERSED_STREAM[19].leading_zeros() as u8 + 1;
+    assert_eq!(reader.get_bits(padding), 1);
+    for (value, bits) in GOLDEN_FIELDS.iter().rev() {
+        assert_eq!(reader.get_bits(*bits), *value);
+    }
+    assert_eq!(reader.bits_remaining(), 0);
+}
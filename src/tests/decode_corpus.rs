@@ -82,7 +82,10 @@ fn test_decode_corpus_files() {
         let mut original_p = p.clone();
         original_p.truncate(original_p.len() - 4);
         let original_f = fs::File::open(original_p).unwrap();
-        let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+        let original: Vec<u8> = std::io::BufReader::new(original_f)
+            .bytes()
+            .map(|x| x.unwrap())
+            .collect();
 
         println!("Results for file: {}", p.clone());
         let mut success = true;
@@ -191,3 +194,64 @@ fn test_decode_corpus_files() {
 
     assert!(failed.is_empty());
 }
+
+/// The decoders never recurse and keep large buffers on the heap, so all corpus files can be decoded
+/// on a thread with a small stack, even in debug builds, which need much more stack than release builds.
+#[test]
+fn test_decode_corpus_with_small_stack() {
+    extern crate std;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use crate::io::Read;
+    use crate::{PushDecoder, StreamingDecoder};
+    use alloc::vec::Vec;
+
+    let inputs: Vec<Vec<u8>> = std::fs::read_dir("./decodecorpus_files")
+        .unwrap()
+        .map(|file| file.unwrap().path())
+        .filter(|path| path.extension() == Some("zst".as_ref()))
+        .map(|path| std::fs::read(path).unwrap())
+        .collect();
+
+    std::thread::Builder::new()
+        .stack_size(64 * 1024)
+        .spawn(move || {
+            let mut frame_dec = FrameDecoder::new();
+            for input in &inputs {
+                let mut source = input.as_slice();
+                frame_dec.reset(&mut source).unwrap();
+                frame_dec
+                    .decode_blocks(&mut source, BlockDecodingStrategy::All)
+                    .unwrap();
+                let expected = frame_dec.collect().unwrap();
+
+                let mut source = input.as_slice();
+                frame_dec.reset(&mut source).unwrap();
+                frame_dec
+                    .decode_from_slice(&mut source, BlockDecodingStrategy::All)
+                    .unwrap();
+                assert_eq!(frame_dec.collect().unwrap(), expected);
+
+                let mut output = Vec::new();
+                StreamingDecoder::new(input.as_slice())
+                    .unwrap()
+                    .read_to_end(&mut output)
+                    .unwrap();
+                assert_eq!(output, expected);
+
+                let mut decoder = PushDecoder::new();
+                let mut output = Vec::new();
+                let mut buf = [0; 1000];
+                let mut source = input.as_slice();
+                loop {
+                    match decoder.read_from(&mut source, &mut buf).unwrap() {
+                        0 => break,
+                        n => output.extend_from_slice(&buf[..n]),
+                    }
+                }
+                assert_eq!(output, expected);
+            }
+        })
+        .unwrap()
+        .join()
+        .unwrap();
+}
@@ -72,6 +72,14 @@ fn test_dict_parsing() {
         Ok(_) => panic!("The dict got decoded but the magic num was incorrect!"),
         Err(_) => { /* This is what should happen*/ }
     }
+    // dictionaries that end early are rejected instead of panicking
+    raw[..4].copy_from_slice(&[0x37, 0xA4, 0x30, 0xEC]);
+    for len in [0, 7, 8 + raw_tables.len() + 11] {
+        assert!(matches!(
+            Dictionary::decode_dict(&raw[..len]),
+            Err(crate::decoding::dictionary::DictionaryDecodeError::NotEnoughBytes { .. })
+        ));
+    }
 }
 
 #[test]
@@ -97,7 +105,10 @@ fn test_dict_decoding() {
 
     let mut files: Vec<_> = fs::read_dir("./dict_tests/files").unwrap().collect();
     let dict = fs::File::open("./dict_tests/dictionary").unwrap();
-    let dict: Vec<u8> = dict.bytes().map(|x| x.unwrap()).collect();
+    let dict: Vec<u8> = std::io::BufReader::new(dict)
+        .bytes()
+        .map(|x| x.unwrap())
+        .collect();
 
     files.sort_by_key(|x| match x {
         Err(_) => "".to_owned(),
@@ -155,7 +166,10 @@ fn test_dict_decoding() {
         let mut original_p = p.clone();
         original_p.truncate(original_p.len() - 4);
         let original_f = fs::File::open(original_p).unwrap();
-        let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+        let original: Vec<u8> = std::io::BufReader::new(original_f)
+            .bytes()
+            .map(|x| x.unwrap())
+            .collect();
 
         println!("Results for file: {}", p.clone());
         let mut success = true;
@@ -263,3 +277,99 @@ fn test_dict_decoding() {
 
     assert!(failed.is_empty());
 }
+
+#[cfg(feature = "encoder")]
+#[test]
+fn test_decode_without_dict() {
+    use crate::decoding::dictionary::Dictionary;
+    use crate::encoding::{CompressionLevel, FrameCompressorBuilder};
+    use crate::frame_decoder::{FrameDecoder, FrameDecoderError};
+    use alloc::vec::Vec;
+
+    let dict_content = crate::test_utils::lcg_bytes(5000, 1);
+    let compress = |data: &[u8]| {
+        let mut output = Vec::new();
+        FrameCompressorBuilder::new()
+            .level(CompressionLevel::Fastest)
+            .dictionary(Dictionary::from_raw_content(7, dict_content.clone()))
+            .build(data, &mut output)
+            .unwrap()
+            .compress()
+            .unwrap();
+        output
+    };
+
+    // nothing in here references the dictionary
+    let data = b"Hello, Hello, Hello, World!".repeat(100);
+    let compressed = compress(&data);
+    let mut decoder = FrameDecoder::new();
+    let mut output = Vec::with_capacity(data.len());
+    assert!(matches!(
+        decoder.decode_all_to_vec(&compressed, &mut output),
+        Err(FrameDecoderError::DictNotProvided { dict_id: 7 })
+    ));
+    decoder.set_decode_without_dict(true);
+    decoder.decode_all_to_vec(&compressed, &mut output).unwrap();
+    assert_eq!(output, data);
+
+    // this does, so it can't be decoded without it
+    let compressed = compress(&dict_content[1000..3000]);
+    let mut output = Vec::with_capacity(2000);
+    assert!(decoder.decode_all_to_vec(&compressed, &mut output).is_err());
+}
+
+/// One dictionary is shared by decoders on several threads
+#[cfg(all(feature = "std", feature = "encoder"))]
+#[test]
+fn test_shared_dict() {
+    use crate::decoding::dictionary::Dictionary;
+    use crate::encoding::{CompressionLevel, FrameCompressorBuilder};
+    use crate::frame_decoder::FrameDecoder;
+    use crate::streaming_decoder::StreamingDecoder;
+    use alloc::sync::Arc;
+    use alloc::vec::Vec;
+    use std::io::Read;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Dictionary>();
+
+    let raw_dict = include_bytes!("../../dict_tests/dictionary");
+    let dict = Arc::new(Dictionary::decode_dict(raw_dict).unwrap());
+    let data = include_bytes!("../../dict_tests/files/ModemManager.service");
+    let mut compressed = Vec::new();
+    FrameCompressorBuilder::new()
+        .level(CompressionLevel::Fastest)
+        .dictionary(Dictionary::decode_dict(raw_dict).unwrap())
+        .build(&data[..], &mut compressed)
+        .unwrap()
+        .compress()
+        .unwrap();
+
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let dict = Arc::clone(&dict);
+            let compressed = compressed.clone();
+            std::thread::spawn(move || {
+                let mut decoder = FrameDecoder::new();
+                decoder.add_dict(dict.clone()).unwrap();
+                for _ in 0..10 {
+                    let mut output = Vec::with_capacity(data.len());
+                    decoder.decode_all_to_vec(&compressed, &mut output).unwrap();
+                    assert_eq!(output, data);
+                }
+
+                let mut output = Vec::new();
+                StreamingDecoder::new_with_dict(&compressed[..], dict)
+                    .unwrap()
+                    .read_to_end(&mut output)
+                    .unwrap();
+                assert_eq!(output, data);
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    // The decoders dropped their references
+    assert_eq!(Arc::strong_count(&dict), 1);
+}
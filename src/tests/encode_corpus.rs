@@ -35,7 +35,7 @@ fn test_encode_corpus_files_uncompressed_our_decompressor() {
             &mut compressed_file,
             crate::encoding::CompressionLevel::Fastest,
         );
-        compressor.compress();
+        compressor.compress().unwrap();
         let mut decompressed_output = Vec::new();
         let mut decoder =
             crate::streaming_decoder::StreamingDecoder::new(compressed_file.as_slice()).unwrap();
@@ -92,7 +92,7 @@ fn test_encode_corpus_files_uncompressed_original_decompressor() {
             &mut compressed_file,
             crate::encoding::CompressionLevel::Fastest,
         );
-        compressor.compress();
+        compressor.compress().unwrap();
         let mut decompressed_output = Vec::new();
         // zstd::stream::copy_decode(compressed_file.as_slice(), &mut decompressed_output).unwrap();
         match zstd::stream::copy_decode(compressed_file.as_slice(), &mut decompressed_output) {
@@ -155,7 +155,7 @@ fn test_encode_corpus_files_compressed_our_decompressor() {
             &mut compressed_file,
             crate::encoding::CompressionLevel::Fastest,
         );
-        compressor.compress();
+        compressor.compress().unwrap();
         let mut decompressed_output = Vec::new();
         let mut decoder =
             crate::streaming_decoder::StreamingDecoder::new(compressed_file.as_slice()).unwrap();
@@ -212,7 +212,7 @@ fn test_encode_corpus_files_compressed_original_decompressor() {
             &mut compressed_file,
             crate::encoding::CompressionLevel::Fastest,
         );
-        compressor.compress();
+        compressor.compress().unwrap();
         let mut decompressed_output = Vec::new();
         // zstd::stream::copy_decode(compressed_file.as_slice(), &mut decompressed_output).unwrap();
         match zstd::stream::copy_decode(compressed_file.as_slice(), &mut decompressed_output) {
@@ -237,3 +237,66 @@ fn test_encode_corpus_files_compressed_original_decompressor() {
         }
     }
 }
+
+/// Compress `input` at the Fastest level and check that this crate and the reference
+/// implementation both decompress it to the input again
+#[cfg(test)]
+fn assert_fastest_roundtrip(input: &[u8]) {
+    extern crate std;
+    use crate::encoding::{CompressionLevel, FrameCompressor};
+    use alloc::vec::Vec;
+    use std::io::Read;
+
+    let mut compressed = Vec::new();
+    let mut compressor = FrameCompressor::new(input, &mut compressed, CompressionLevel::Fastest);
+    compressor.compress().unwrap();
+
+    let mut decompressed = Vec::new();
+    let mut decoder =
+        crate::streaming_decoder::StreamingDecoder::new(compressed.as_slice()).unwrap();
+    decoder.read_to_end(&mut decompressed).unwrap();
+    assert!(decompressed == input, "our decoder");
+
+    let mut decompressed = Vec::new();
+    zstd::stream::copy_decode(compressed.as_slice(), &mut decompressed).unwrap();
+    assert!(decompressed == input, "reference decoder");
+}
+
+#[test]
+fn test_encode_offsets() {
+    use alloc::vec::Vec;
+
+    // Matches at offsets of 1 to 3 must not be mistaken for repeat offsets
+    for offset in 1..=8 {
+        let input: Vec<u8> = (0..200).map(|i| b"abcdefgh"[i % offset]).collect();
+        assert_fastest_roundtrip(&input);
+    }
+}
+
+#[test]
+fn test_encode_single_sequence() {
+    // A block whose only sequence is a single match
+    let mut input = crate::test_utils::lcg_bytes(100, 1);
+    input.extend_from_within(..);
+    assert_fastest_roundtrip(&input);
+}
+
+#[test]
+fn test_encode_short_inputs() {
+    // Inputs shorter than the minimum match length
+    for len in 0..8 {
+        assert_fastest_roundtrip(&crate::test_utils::lcg_bytes(len, 1));
+    }
+}
+
+#[test]
+fn test_encode_long_sequences() {
+    // Literal lengths with the codes 32 and 33
+    for literals in [10_000, 20_000] {
+        let mut input = crate::test_utils::lcg_bytes(literals, 1);
+        input.extend_from_within(..1000);
+        input.extend_from_slice(&crate::test_utils::lcg_bytes(10, 1));
+        input.extend_from_within(..1000);
+        assert_fastest_roundtrip(&input);
+    }
+}
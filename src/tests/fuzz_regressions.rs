@@ -24,3 +24,34 @@ fn test_all_artifacts() {
         });
     }
 }
+
+#[cfg(feature = "fuzzing")]
+#[test]
+fn test_fuzzing_entry_points() {
+    extern crate std;
+    use std::fs;
+
+    for file in fs::read_dir("./fuzz/artifacts/decode").unwrap() {
+        let data = fs::read(file.unwrap().path()).unwrap();
+        crate::fuzzing::decode(&data);
+    }
+
+    for file in fs::read_dir("./fuzz/artifacts/interop").unwrap() {
+        let data = fs::read(file.unwrap().path()).unwrap();
+        crate::fuzzing::round_trip(&data);
+        crate::fuzzing::interop(&data);
+        crate::fuzzing::differential_interop(&data);
+    }
+
+    // Every level and combination of flags, with and without a strategy, window log and block size
+    let content = b"The fuzzer picks the settings from the first bytes of its input. ".repeat(20);
+    for level in 0..16 {
+        for flags in 0..64 {
+            for (strategy, window_log, block_size) in [(255, 0, 0), (level % 9, 3, 100)] {
+                let mut data = std::vec![level, strategy, flags, window_log, block_size, 0, 2];
+                data.extend_from_slice(&content);
+                crate::fuzzing::differential_interop(&data);
+            }
+        }
+    }
+}
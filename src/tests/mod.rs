@@ -44,6 +44,75 @@ fn assure_decoder_send_sync() {
     });
 }
 
+/// The types that hold the state of an operation, and their errors, can be moved to and shared
+/// with other threads, see "Threads" in the crate documentation
+#[test]
+fn send_sync() {
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    assert_send_sync::<crate::frame_decoder::FrameDecoder>();
+    assert_send_sync::<
+        crate::streaming_decoder::StreamingDecoder<&[u8], crate::frame_decoder::FrameDecoder>,
+    >();
+    assert_send_sync::<crate::push_decoder::PushDecoder>();
+    assert_send_sync::<crate::raw::Decompress>();
+    assert_send_sync::<crate::codec::Decoder>();
+    assert_send_sync::<crate::decoding::block_decompressor::BlockDecompressor>();
+    assert_send_sync::<crate::decoding::dictionary::Dictionary>();
+    assert_send_sync::<crate::decoding::decode_options::DecodeOptions>();
+    assert_send_sync::<crate::conformance::Checker>();
+    assert_send_sync::<crate::frame_decoder::FrameDecoderError>();
+    assert_send_sync::<crate::error::Error>();
+    assert_send_sync::<crate::io::Error>();
+
+    #[cfg(feature = "encoder")]
+    {
+        use crate::encoding::*;
+        assert_send_sync::<FrameCompressor<&[u8], Vec<u8>>>();
+        assert_send_sync::<FrameCompressorBuilder>();
+        assert_send_sync::<StreamingEncoder<Vec<u8>>>();
+        assert_send_sync::<IncrementalEncoder<Vec<u8>>>();
+        assert_send_sync::<BlockCompressor>();
+        assert_send_sync::<EncoderDictionary>();
+        assert_send_sync::<EncodeOptions>();
+        assert_send_sync::<EncodeError>();
+        assert_send_sync::<crate::raw::Compress>();
+        assert_send_sync::<crate::codec::Encoder>();
+    }
+}
+
+/// A decoder and an encoder that are moved to another thread in the middle of a frame continue
+/// where they left off
+#[cfg(all(feature = "std", feature = "encoder"))]
+#[test]
+fn move_between_threads() {
+    use crate::encoding::{CompressionLevel, StreamingEncoder};
+    use crate::streaming_decoder::StreamingDecoder;
+    use std::io::{Read, Write};
+
+    let data = crate::test_utils::text_like(300_000, 1);
+    let mut encoder = StreamingEncoder::new(Vec::new(), CompressionLevel::Fastest).unwrap();
+    encoder.write_all(&data[..100_000]).unwrap();
+    let rest = data[100_000..].to_vec();
+    let compressed = std::thread::spawn(move || {
+        encoder.write_all(&rest).unwrap();
+        encoder.finish().unwrap()
+    })
+    .join()
+    .unwrap();
+
+    let mut decoder = StreamingDecoder::new(std::io::Cursor::new(compressed)).unwrap();
+    let mut decompressed = vec![0; 100_000];
+    decoder.read_exact(&mut decompressed).unwrap();
+    let decompressed = std::thread::spawn(move || {
+        decoder.read_to_end(&mut decompressed).unwrap();
+        decompressed
+    })
+    .join()
+    .unwrap();
+    assert_eq!(decompressed, data);
+}
+
 #[test]
 fn skippable_frame() {
     use crate::frame;
@@ -132,7 +201,10 @@ fn test_decode_from_to() {
     let f = File::open("./decodecorpus_files/z000088.zst").unwrap();
     let mut frame_dec = frame_decoder::FrameDecoder::new();
 
-    let content: Vec<u8> = f.bytes().map(|x| x.unwrap()).collect();
+    let content: Vec<u8> = std::io::BufReader::new(f)
+        .bytes()
+        .map(|x| x.unwrap())
+        .collect();
 
     let mut target = vec![0u8; 1024 * 1024];
 
@@ -196,7 +268,10 @@ fn test_decode_from_to() {
     }
 
     let original_f = File::open("./decodecorpus_files/z000088").unwrap();
-    let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+    let original: Vec<u8> = std::io::BufReader::new(original_f)
+        .bytes()
+        .map(|x| x.unwrap())
+        .collect();
 
     if original.len() != result.len() {
         panic!(
@@ -254,7 +329,10 @@ fn test_specific_file() {
     let result = frame_dec.collect().unwrap();
 
     let original_f = fs::File::open("./decodecorpus_files/z000088").unwrap();
-    let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+    let original: Vec<u8> = std::io::BufReader::new(original_f)
+        .bytes()
+        .map(|x| x.unwrap())
+        .collect();
 
     std::println!("Results for file: {}", path);
 
@@ -299,7 +377,10 @@ fn test_streaming() {
     Read::read_to_end(&mut stream, &mut result).unwrap();
 
     let original_f = fs::File::open("./decodecorpus_files/z000088").unwrap();
-    let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+    let original: Vec<u8> = std::io::BufReader::new(original_f)
+        .bytes()
+        .map(|x| x.unwrap())
+        .collect();
 
     if original.len() != result.len() {
         panic!(
@@ -341,7 +422,10 @@ fn test_streaming() {
     Read::read_to_end(&mut stream, &mut result).unwrap();
 
     let original_f = fs::File::open("./decodecorpus_files/z000068").unwrap();
-    let original: Vec<u8> = original_f.bytes().map(|x| x.unwrap()).collect();
+    let original: Vec<u8> = std::io::BufReader::new(original_f)
+        .bytes()
+        .map(|x| x.unwrap())
+        .collect();
 
     std::println!("Results for file:");
 
@@ -564,11 +648,806 @@ fn test_decode_all() {
     output.reserve_exact(original.len() + 1);
     decoder.decode_all_to_vec(&input, &mut output).unwrap();
     assert_eq!(output, original);
+
+    // decode_all_to_vec appends to existing content.
+    let mut output = b"prefix".to_vec();
+    output.reserve_exact(original.len());
+    decoder.decode_all_to_vec(&input, &mut output).unwrap();
+    assert_eq!(&output[..6], b"prefix");
+    assert_eq!(&output[6..], original);
+
+    // decode_all_to_vec_with_limit grows the output up to the limit.
+    let mut output = b"prefix".to_vec();
+    decoder
+        .decode_all_to_vec_with_limit(&input, &mut output, original.len())
+        .unwrap();
+    assert_eq!(&output[..6], b"prefix");
+    assert_eq!(&output[6..], original);
+
+    // decode_all_to_vec_with_limit with a smaller limit.
+    let mut output = b"prefix".to_vec();
+    let result = decoder.decode_all_to_vec_with_limit(&input, &mut output, original.len() - 1);
+    assert!(
+        matches!(result, Err(FrameDecoderError::TargetTooSmall)),
+        "{:?}",
+        result
+    );
+    assert_eq!(output, b"prefix");
+
+    // decode_all_to_vec_with_limit reserves the declared content size up front.
+    #[cfg(feature = "encoder")]
+    {
+        let content: Vec<u8> = (0..500_000u32).map(|i| (i % 99) as u8).collect();
+        let mut output = Vec::new();
+        decoder
+            .decode_all_to_vec_with_limit(&crate::compress(&content), &mut output, usize::MAX)
+            .unwrap();
+        assert_eq!(output, content);
+        assert_eq!(output.capacity(), content.len());
+    }
+
+    // decode_all_to_vec grows the output once set_max_output_size allows it.
+    decoder.set_max_output_size(original.len());
+    let mut output = Vec::new();
+    decoder.decode_all_to_vec(&input, &mut output).unwrap();
+    assert_eq!(output, original);
+    decoder.set_max_output_size(original.len() - 1);
+    let mut output = b"prefix".to_vec();
+    let result = decoder.decode_all_to_vec(&input, &mut output);
+    assert!(
+        matches!(result, Err(FrameDecoderError::TargetTooSmall)),
+        "{:?}",
+        result
+    );
+    assert_eq!(output, b"prefix");
+    // The spare capacity of the output counts even if it is above the limit.
+    let mut output = Vec::with_capacity(original.len());
+    decoder.decode_all_to_vec(&input, &mut output).unwrap();
+    assert_eq!(output, original);
+    decoder.set_max_output_size(0);
+
+    // decode_all_uninit with larger output length.
+    let mut output = vec![core::mem::MaybeUninit::uninit(); original.len() + 1];
+    let result = decoder.decode_all_uninit(&input, &mut output).unwrap();
+    assert_eq!(result, original.len());
+    // SAFETY: decode_all_uninit initialized the first `result` bytes
+    let output: Vec<u8> = output[..result]
+        .iter()
+        .map(|byte| unsafe { byte.assume_init() })
+        .collect();
+    assert_eq!(output, original);
+
+    // decode_all_uninit with smaller output length.
+    let mut output = vec![core::mem::MaybeUninit::uninit(); original.len() - 1];
+    let result = decoder.decode_all_uninit(&input, &mut output);
+    assert!(
+        matches!(result, Err(FrameDecoderError::TargetTooSmall)),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn test_trailing_data() {
+    use crate::frame_decoder::{FrameDecoder, FrameDecoderError};
+    use crate::io::Read;
+
+    let frame = include_bytes!("../../decodecorpus_files/z000089.zst");
+    let original = include_bytes!("../../decodecorpus_files/z000089");
+
+    for garbage in [&[1u8, 2, 3][..], &[0xAB; 100][..]] {
+        let mut input = Vec::new();
+        input.extend_from_slice(frame);
+        input.extend_from_slice(garbage);
+
+        // decode_all reports where the garbage starts
+        let mut decoder = FrameDecoder::new();
+        let mut output = vec![0; original.len()];
+        let result = decoder.decode_all(&input, &mut output);
+        assert!(
+            matches!(result, Err(FrameDecoderError::TrailingData { position }) if position == frame.len() as u64),
+            "{:?}",
+            result
+        );
+
+        // the streaming decoder ignores it, unless asked not to
+        let mut output = Vec::new();
+        let mut decoder = crate::StreamingDecoder::new(input.as_slice()).unwrap();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, original);
+
+        let mut output = Vec::new();
+        let mut decoder = crate::StreamingDecoder::new(input.as_slice()).unwrap();
+        decoder.set_reject_trailing_data(true);
+        assert!(decoder.read_to_end(&mut output).is_err());
+        assert_eq!(output, original);
+    }
+
+    // garbage as the only input is not trailing data
+    let mut decoder = FrameDecoder::new();
+    let result = decoder.decode_all(&[0xAB; 100], &mut []);
+    assert!(
+        matches!(result, Err(FrameDecoderError::ReadFrameHeaderError(_))),
+        "{:?}",
+        result
+    );
+
+    // without trailing data the check passes
+    let mut output = Vec::new();
+    let mut decoder = crate::StreamingDecoder::new(&frame[..]).unwrap();
+    decoder.set_reject_trailing_data(true);
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, original);
+}
+
+#[cfg(feature = "encoder")]
+#[test]
+fn test_streaming_decoder_options() {
+    use crate::decoding::decode_options::DecodeOptions;
+    use crate::decoding::dictionary::Dictionary;
+    use crate::encoding::{EncodeOptions, FrameCompressor};
+    use crate::frame_decoder::FrameDecoderError;
+    use crate::io::Read;
+    use crate::StreamingDecoder;
+
+    let multi_frame = DecodeOptions {
+        multi_frame: true,
+        ..DecodeOptions::default()
+    };
+
+    // all frames are decoded and skippable frames are skipped
+    let mut input = crate::compress(b"first ");
+    input.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 2, 0, 0, 0, 0xAA, 0xBB]);
+    input.extend(crate::compress(b"second"));
+    let mut output = Vec::new();
+    StreamingDecoder::with_options(input.as_slice(), multi_frame.clone())
+        .unwrap()
+        .read_to_end(&mut output)
+        .unwrap();
+    assert_eq!(output, b"first second");
+
+    // anything else after a frame is an error
+    input.extend_from_slice(b"garbage");
+    let mut output = Vec::new();
+    let mut decoder = StreamingDecoder::with_options(input.as_slice(), multi_frame).unwrap();
+    assert!(decoder.read_to_end(&mut output).is_err());
+    assert_eq!(output, b"first second");
+
+    // without the option only the first frame is decoded
+    let mut output = Vec::new();
+    StreamingDecoder::new(input.as_slice())
+        .unwrap()
+        .read_to_end(&mut output)
+        .unwrap();
+    assert_eq!(output, b"first ");
+
+    // the window limit is applied before anything is decoded
+    let frame = include_bytes!("../../decodecorpus_files/z000089.zst");
+    let result = StreamingDecoder::with_options(
+        &frame[..],
+        DecodeOptions {
+            max_window_size: 1024,
+            ..DecodeOptions::default()
+        },
+    );
+    assert!(matches!(
+        result,
+        Err(FrameDecoderError::WindowSizeTooBig { .. })
+    ));
+
+    // dictionaries are available for the first frame
+    let raw_dict = include_bytes!("../../dict_tests/dictionary");
+    let data = include_bytes!("../../dict_tests/files/ModemManager.service");
+    let mut compressed = Vec::new();
+    let mut compressor =
+        FrameCompressor::with_options(&data[..], &mut compressed, EncodeOptions::cli_default());
+    compressor.set_dictionary(Some(Dictionary::decode_dict(raw_dict).unwrap()));
+    compressor.compress().unwrap();
+    let mut output = Vec::new();
+    StreamingDecoder::new_with_dict(
+        compressed.as_slice(),
+        Dictionary::decode_dict(raw_dict).unwrap(),
+    )
+    .unwrap()
+    .read_to_end(&mut output)
+    .unwrap();
+    assert_eq!(output, data);
+
+    // checksums are only verified if asked to
+    #[cfg(feature = "hash")]
+    {
+        use crate::decoding::decode_options::ChecksumPolicy;
+
+        let mut corrupted = crate::compress(b"checked content");
+        let len = corrupted.len();
+        corrupted[len - 1] ^= 1;
+        let mut output = Vec::new();
+        StreamingDecoder::new(corrupted.as_slice())
+            .unwrap()
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, b"checked content");
+
+        let verify = DecodeOptions {
+            checksum: ChecksumPolicy::Verify,
+            ..DecodeOptions::default()
+        };
+        let mut output = Vec::new();
+        let mut decoder = StreamingDecoder::with_options(corrupted.as_slice(), verify).unwrap();
+        assert!(decoder.read_to_end(&mut output).is_err());
+
+        // or only reported, while the content is still returned
+        let mut input = crate::compress(b"good ");
+        let good_len = input.len() as u64;
+        input.extend_from_slice(&corrupted);
+        let report = DecodeOptions {
+            checksum: ChecksumPolicy::Report,
+            multi_frame: true,
+            ..DecodeOptions::default()
+        };
+        let mut output = Vec::new();
+        let mut decoder = StreamingDecoder::with_options(input.as_slice(), report).unwrap();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, b"good checked content");
+        let mismatches = decoder.checksum_mismatches();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].compressed_start, good_len);
+        assert_eq!(mismatches[0].expected ^ (1 << 24), mismatches[0].calculated);
+    }
+}
+
+#[test]
+fn test_bytes_read_by_last_call() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+
+    let frame = include_bytes!("../../decodecorpus_files/z000089.zst");
+    let mut input = frame.to_vec();
+    input.extend_from_slice(b"other data");
+
+    let mut source = input.as_slice();
+    let mut decoder = FrameDecoder::new();
+    decoder.reset(&mut source).unwrap();
+    let mut consumed = decoder.bytes_read_by_last_call();
+    assert_eq!(consumed, (input.len() - source.len()) as u64);
+    while !decoder.is_finished() {
+        decoder
+            .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+            .unwrap();
+        consumed += decoder.bytes_read_by_last_call();
+        assert_eq!(consumed, (input.len() - source.len()) as u64);
+    }
+    assert_eq!(consumed, frame.len() as u64);
+    assert_eq!(source, b"other data");
+}
+
+#[test]
+fn test_decode_from_slice() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+
+    let frame = include_bytes!("../../decodecorpus_files/z000088.zst");
+    let original = include_bytes!("../../decodecorpus_files/z000088");
+    let mut input = frame.to_vec();
+    input.extend_from_slice(b"other data");
+
+    let mut source = input.as_slice();
+    let mut decoder = FrameDecoder::new();
+    decoder.reset(&mut source).unwrap();
+    let mut output = Vec::new();
+    while !decoder
+        .decode_from_slice(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+        .unwrap()
+    {
+        output.extend(decoder.collect().unwrap_or_default());
+        assert_eq!(
+            decoder.bytes_read_from_source(),
+            (input.len() - source.len()) as u64
+        );
+    }
+    output.extend(decoder.collect().unwrap());
+    assert_eq!(output, original);
+    assert_eq!(source, b"other data");
+    assert_eq!(decoder.bytes_read_from_source(), frame.len() as u64);
+
+    // Truncated blocks fail the same way as with a reader
+    let mut source = &frame[..frame.len() - 100];
+    decoder.reset(&mut source).unwrap();
+    assert!(matches!(
+        decoder.decode_from_slice(&mut source, BlockDecodingStrategy::All),
+        Err(FrameDecoderError::FailedToReadBlockBody(_))
+    ));
+}
+
+#[test]
+fn test_suspend_resume() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+
+    let frame = include_bytes!("../../decodecorpus_files/z000088.zst");
+    let original = include_bytes!("../../decodecorpus_files/z000088");
+
+    // move the frame to a new decoder after every block, with some output collected in between
+    let mut source = &frame[..];
+    let mut decoder = FrameDecoder::new();
+    decoder.reset(&mut source).unwrap();
+    let mut output = Vec::new();
+    let mut blocks = 0;
+    while !decoder.is_finished() {
+        decoder
+            .decode_from_slice(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+            .unwrap();
+        blocks += 1;
+        if blocks % 2 == 0 {
+            output.extend(decoder.collect().unwrap_or_default());
+        }
+        let snapshot = match decoder.suspend() {
+            // Collecting leaves only the window
+            Err(FrameDecoderError::TooMuchUncollected { .. }) => {
+                output.extend(decoder.collect().unwrap_or_default());
+                decoder.suspend().unwrap()
+            }
+            snapshot => snapshot.unwrap(),
+        };
+        decoder = FrameDecoder::new();
+        decoder.resume(&snapshot).unwrap();
+        assert_eq!(decoder.blocks_decoded(), blocks);
+        assert_eq!(
+            decoder.bytes_read_from_source(),
+            (frame.len() - source.len()) as u64
+        );
+    }
+    assert!(blocks > 2);
+    output.extend(decoder.collect().unwrap());
+    assert_eq!(output, original);
+
+    // the checksum survives as long as nothing was collected before suspending
+    #[cfg(all(feature = "hash", feature = "encoder"))]
+    {
+        use crate::encoding::FrameCompressorBuilder;
+
+        let data = b"Hello, World! ".repeat(50_000);
+        let mut compressed = Vec::new();
+        FrameCompressorBuilder::new()
+            .window_log(17)
+            .checksum(true)
+            .build(data.as_slice(), &mut compressed)
+            .unwrap()
+            .compress()
+            .unwrap();
+        for collect in [false, true] {
+            let mut source = compressed.as_slice();
+            let mut decoder = FrameDecoder::new();
+            decoder.reset(&mut source).unwrap();
+            // One block fills the window, after a second one the decoder holds more than the
+            // window and has to collect before suspending
+            let blocks = if collect { 2 } else { 1 };
+            decoder
+                .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(blocks))
+                .unwrap();
+            let mut output = Vec::new();
+            if collect {
+                assert!(matches!(
+                    decoder.suspend(),
+                    Err(FrameDecoderError::TooMuchUncollected {
+                        window_size: 131_072,
+                        ..
+                    })
+                ));
+                output.extend(decoder.collect().unwrap());
+                assert!(!output.is_empty());
+            }
+            let mut resumed = FrameDecoder::new();
+            resumed.resume(&decoder.suspend().unwrap()).unwrap();
+            resumed
+                .decode_blocks(&mut source, BlockDecodingStrategy::All)
+                .unwrap();
+            output.extend(resumed.collect().unwrap());
+            assert_eq!(output, data);
+            assert!(resumed.get_checksum_from_data().is_some());
+            if collect {
+                assert_eq!(resumed.get_calculated_checksum(), None);
+            } else {
+                assert_eq!(
+                    resumed.get_calculated_checksum(),
+                    resumed.get_checksum_from_data()
+                );
+            }
+        }
+    }
+
+    // damaged snapshots are rejected
+    let mut source = &frame[..];
+    let mut decoder = FrameDecoder::new();
+    decoder.reset(&mut source).unwrap();
+    decoder
+        .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+        .unwrap();
+    let snapshot = decoder.suspend().unwrap();
+    let mut resumed = FrameDecoder::new();
+    for damaged in [&snapshot[..snapshot.len() - 1], &snapshot[1..]] {
+        assert!(matches!(
+            resumed.resume(damaged),
+            Err(FrameDecoderError::InvalidSnapshot)
+        ));
+    }
+    assert!(matches!(
+        resumed.decode_blocks(&mut source, BlockDecodingStrategy::All),
+        Err(FrameDecoderError::NotYetInitialized)
+    ));
+}
+
+#[test]
+fn test_damaged_snapshots() {
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder, FrameDecoderError};
+    use core::convert::TryInto;
+
+    let frame = include_bytes!("../../decodecorpus_files/z000088.zst");
+    let mut source = &frame[..];
+    let mut decoder = FrameDecoder::new();
+    decoder.reset(&mut source).unwrap();
+    decoder
+        .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+        .unwrap();
+    let snapshot = decoder.suspend().unwrap();
+    FrameDecoder::new().resume(&snapshot).unwrap();
+
+    let u32_at = |pos: usize| u32::from_le_bytes(snapshot[pos..pos + 4].try_into().unwrap());
+    let u64_at = |pos: usize| u64::from_le_bytes(snapshot[pos..pos + 8].try_into().unwrap());
+    // The snapshot with `value` in place of the bytes of `range`
+    let replaced = |range: core::ops::Range<usize>, value: &[u8]| {
+        let mut damaged = snapshot[..range.start].to_vec();
+        damaged.extend_from_slice(value);
+        damaged.extend_from_slice(&snapshot[range.end..]);
+        damaged
+    };
+
+    // Walk the fields in the order FrameDecoder::suspend writes them, and damage each one that
+    // can be out of range. The counters and the checksum can have any value.
+    let mut damaged = vec![
+        ("magic", replaced(0..1, b"X")),
+        ("version", replaced(4..5, &[2])),
+        ("truncated", snapshot[..snapshot.len() - 1].to_vec()),
+        ("trailing data", [&snapshot[..], &[0]].concat()),
+    ];
+    let header_len = u64_at(5) as usize;
+    let mut pos = 13 + header_len;
+    damaged.push(("header length", replaced(5..13, &[0; 8])));
+    damaged.push(("frame finished", replaced(pos..pos + 1, &[2])));
+    pos += 1 + 8 + 8;
+    damaged.push(("checksum", replaced(pos..pos + 1, &[2])));
+    pos += 5;
+    damaged.push(("dictionary", replaced(pos..pos + 1, &[2])));
+    let dictionary = pos;
+    pos += 5;
+    damaged.push(("checksum available", replaced(pos..pos + 1, &[2])));
+    pos += 1;
+    damaged.push(("output counter", replaced(pos..pos + 8, &[0; 8])));
+    let window_size = 327_680;
+    let content_len = u64_at(pos + 8) as usize;
+    let content_end = pos + 16 + content_len;
+    // More than the window, with an output counter that would fit
+    let mut too_much = u64::MAX.to_le_bytes().to_vec();
+    too_much.extend_from_slice(&(window_size + 1u64).to_le_bytes());
+    too_much.resize(too_much.len() + window_size as usize + 1, 0);
+    damaged.push((
+        "content beyond the window",
+        replaced(pos..content_end, &too_much),
+    ));
+    pos = content_end;
+    for _ in 0..3 {
+        damaged.push(("repeat offset", replaced(pos..pos + 4, &[0; 4])));
+        pos += 4;
+    }
+    // The literal length, match length and offset tables, with their largest accuracy log and
+    // number of symbols
+    for (max_log, max_symbols) in [(9, 36u8), (9, 53), (8, 32)] {
+        damaged.push(("table rle", replaced(pos..pos + 1, &[2])));
+        damaged.push((
+            "table rle symbol",
+            replaced(pos..pos + 2, &[1, max_symbols]),
+        ));
+        damaged.push(("accuracy log", replaced(pos + 2..pos + 3, &[max_log + 1])));
+        let symbols = u32_at(pos + 3) as usize;
+        let table_end = pos + 7 + 4 * symbols;
+        // One more symbol than the table can have, with a probability of 0
+        let mut too_many = (u32::from(max_symbols) + 1).to_le_bytes().to_vec();
+        too_many.extend_from_slice(&snapshot[pos + 7..table_end]);
+        too_many.resize(4 + 4 * (usize::from(max_symbols) + 1), 0);
+        damaged.push(("symbols", replaced(pos + 3..table_end, &too_many)));
+        if symbols > 0 {
+            // The probabilities no longer add up
+            let probability = u32_at(pos + 7) + 1;
+            damaged.push((
+                "probability",
+                replaced(pos + 7..pos + 11, &probability.to_le_bytes()),
+            ));
+        }
+        pos = table_end;
+    }
+    damaged.push(("weights", replaced(pos..pos + 8, &256u64.to_le_bytes())));
+    if u64_at(pos) > 0 {
+        damaged.push(("weight", replaced(pos + 8..pos + 9, &[200])));
+    }
+    assert_eq!(pos + 8 + u64_at(pos) as usize, snapshot.len());
+
+    for (field, damaged) in damaged {
+        let mut resumed = FrameDecoder::new();
+        let result = resumed.resume(&damaged);
+        assert!(
+            matches!(result, Err(FrameDecoderError::InvalidSnapshot)),
+            "{}: {:?}",
+            field,
+            result
+        );
+    }
+
+    // A dictionary the decoder does not have
+    let mut damaged = snapshot.clone();
+    damaged[dictionary..dictionary + 5].copy_from_slice(&[1, 7, 0, 0, 0]);
+    assert!(matches!(
+        FrameDecoder::new().resume(&damaged),
+        Err(FrameDecoderError::DictNotProvided { dict_id: 7 })
+    ));
+}
+
+#[cfg(feature = "encoder")]
+#[test]
+fn test_streaming_decoder_frame_boundaries() {
+    use crate::decoding::decode_options::DecodeOptions;
+    use crate::io::Read;
+    use crate::streaming_decoder::FrameBoundary;
+    use crate::StreamingDecoder;
+
+    let first = crate::compress(&[1; 1000]);
+    let second = crate::compress(b"second");
+    let mut input = first.clone();
+    input.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 2, 0, 0, 0, 0xAA, 0xBB]);
+    input.extend_from_slice(&second);
+
+    let options = DecodeOptions {
+        multi_frame: true,
+        ..DecodeOptions::default()
+    };
+    let mut decoder = StreamingDecoder::with_options(input.as_slice(), options).unwrap();
+    assert_eq!(decoder.current_frame_start(), (0, 0));
+    let mut output = [0; 1000];
+    decoder.read_exact(&mut output).unwrap();
+    let frames: Vec<_> = decoder.drain_finished_frames().collect();
+    assert_eq!(
+        frames,
+        [FrameBoundary {
+            compressed_start: 0,
+            compressed_end: first.len() as u64,
+            decompressed_start: 0,
+            decompressed_end: 1000,
+        }]
+    );
+
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output).unwrap();
+    assert_eq!(output, b"second");
+    let second_start = first.len() as u64 + 10;
+    let frames: Vec<_> = decoder.drain_finished_frames().collect();
+    assert_eq!(
+        frames,
+        [FrameBoundary {
+            compressed_start: second_start,
+            compressed_end: second_start + second.len() as u64,
+            decompressed_start: 1000,
+            decompressed_end: 1006,
+        }]
+    );
+    assert_eq!(decoder.drain_finished_frames().count(), 0);
+}
+
+/// Sources that return fewer bytes than asked for and fail with `Interrupted` in between, like
+/// network sockets, decode the same as a slice
+#[cfg(all(feature = "encoder", feature = "hash"))]
+#[test]
+fn test_streaming_decoder_short_reads() {
+    use crate::decoding::decode_options::{ChecksumPolicy, DecodeOptions};
+    use crate::io::{Error, ErrorKind, Read};
+    use crate::StreamingDecoder;
+
+    /// Hands out up to 3 bytes at a time, with an `Interrupted` before each
+    struct Flaky<'a> {
+        data: &'a [u8],
+        interrupted: bool,
+    }
+
+    impl Read for Flaky<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.interrupted = !self.interrupted;
+            if self.interrupted {
+                return Err(Error::from(ErrorKind::Interrupted));
+            }
+            let n = buf.len().min(self.data.len()).min(3);
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    let first = crate::test_utils::text_like(100_000, 2);
+    let mut input = crate::compress(&first);
+    let first_len = input.len() as u64;
+    input.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 2, 0, 0, 0, 0xAA, 0xBB]);
+    let mut corrupted = crate::compress(b"second");
+    let len = corrupted.len();
+    corrupted[len - 1] ^= 1;
+    input.extend_from_slice(&corrupted);
+
+    let options = DecodeOptions {
+        multi_frame: true,
+        checksum: ChecksumPolicy::Report,
+        ..DecodeOptions::default()
+    };
+    let source = Flaky {
+        data: &input,
+        interrupted: false,
+    };
+    let mut decoder = StreamingDecoder::with_options(source, options).unwrap();
+    let mut output = Vec::new();
+    let mut buf = [0; 1000];
+    let mut ends = 0;
+    // Reads after the end keep returning 0
+    while ends < 3 {
+        match decoder.read(&mut buf) {
+            Ok(0) => ends += 1,
+            Ok(n) => output.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => panic!("{:?}", e),
+        }
+    }
+    let mut expected = first.clone();
+    expected.extend_from_slice(b"second");
+    assert_eq!(output, expected);
+    assert_eq!(decoder.checksum_mismatches().len(), 1);
+    assert_eq!(
+        decoder.checksum_mismatches()[0].compressed_start,
+        first_len + 10
+    );
+    let ends: Vec<_> = decoder
+        .drain_finished_frames()
+        .map(|frame| frame.compressed_end)
+        .collect();
+    assert_eq!(ends, [first_len, input.len() as u64]);
+}
+
+#[cfg(feature = "encoder")]
+#[test]
+fn test_peek() {
+    use crate::encoding::FrameCompressorBuilder;
+    use crate::frame_decoder::{BlockDecodingStrategy, FrameDecoder};
+    use crate::io::Read;
+
+    // a window that is a lot smaller than the content, so that the ring buffer wraps around
+    let original: Vec<u8> = (0..600_000u32)
+        .map(|i| (i.wrapping_mul(i) >> 9) as u8)
+        .collect();
+    let mut compressed = Vec::new();
+    FrameCompressorBuilder::new()
+        .window_log(17)
+        .build(original.as_slice(), &mut compressed)
+        .unwrap()
+        .compress()
+        .unwrap();
+    let mut source = compressed.as_slice();
+
+    let mut decoder = FrameDecoder::new();
+    assert_eq!(decoder.peek(), b"");
+    decoder.reset(&mut source).unwrap();
+    let mut output = Vec::new();
+    let mut wrapped = false;
+    loop {
+        let (first, second) = decoder.peek_slices();
+        wrapped |= !second.is_empty();
+        let mut peeked = first.to_vec();
+        peeked.extend_from_slice(second);
+        assert_eq!(peeked.len(), decoder.can_collect());
+        assert_eq!(decoder.peek(), peeked.as_slice());
+
+        // only collect half, so that the rest has to be peeked at again
+        let mut buf = vec![
+            0;
+            if decoder.is_finished() {
+                peeked.len()
+            } else {
+                peeked.len() / 2
+            }
+        ];
+        let read = decoder.read(&mut buf).unwrap();
+        assert_eq!(buf[..read], peeked[..read]);
+        output.extend_from_slice(&buf[..read]);
+        if decoder.is_finished() {
+            break;
+        }
+        decoder
+            .decode_blocks(&mut source, BlockDecodingStrategy::UptoBlocks(1))
+            .unwrap();
+    }
+    assert!(wrapped);
+    assert_eq!(decoder.peek(), b"");
+    assert_eq!(output, original);
+}
+
+#[test]
+fn test_inspect_frame() {
+    use crate::frame::{inspect_frame, InspectFrameError, ReadFrameHeaderError};
+
+    let frame = include_bytes!("../../decodecorpus_files/z000089.zst");
+    let original = include_bytes!("../../decodecorpus_files/z000089");
+
+    let info = inspect_frame(&frame[..]).unwrap();
+    assert_eq!(info.compressed_size, frame.len() as u64);
+    assert!(info.block_count > 0);
+    if let Some(size) = info.decompressed_size() {
+        assert_eq!(size, original.len() as u64);
+    }
+    #[cfg(feature = "hash")]
+    if let Some(checksum) = info.checksum {
+        let mut hasher = twox_hash::XxHash64::with_seed(0);
+        core::hash::Hasher::write(&mut hasher, original);
+        assert_eq!(checksum, core::hash::Hasher::finish(&hasher) as u32);
+    }
+
+    // the reader is left at the start of the next frame
+    let mut input = Vec::new();
+    input.extend_from_slice(frame);
+    input.extend_from_slice(&[0x50, 0x2A, 0x4D, 0x18, 3, 0, 0, 0, 1, 2, 3]);
+    let mut source = input.as_slice();
+    inspect_frame(&mut source).unwrap();
+    assert!(matches!(
+        inspect_frame(&mut source),
+        Err(InspectFrameError::ReadFrameHeaderError(
+            ReadFrameHeaderError::SkipFrame { length: 3, .. }
+        ))
+    ));
+
+    // truncated frames are errors
+    assert!(inspect_frame(&frame[..frame.len() - 1]).is_err());
+}
+
+#[test]
+fn test_large_window() {
+    use crate::frame_decoder::{FrameDecoder, FrameDecoderError, LIBZSTD_MAX_WINDOW_SIZE};
+
+    // A frame with a 2 GiB window, a content size of 5 bytes and one raw block
+    let mut frame = vec![0x28, 0xB5, 0x2F, 0xFD, 0x80, 21 << 3, 5, 0, 0, 0];
+    frame.extend_from_slice(&[1 | (5 << 3), 0, 0]);
+    frame.extend_from_slice(b"hello");
+
+    let mut decoder = FrameDecoder::new();
+    let mut output = Vec::new();
+    assert!(matches!(
+        decoder.decode_all_to_vec(&frame, &mut output),
+        Err(FrameDecoderError::WindowSizeTooBig {
+            requested: LIBZSTD_MAX_WINDOW_SIZE,
+            ..
+        })
+    ));
+
+    // Only the content is allocated, not the whole window
+    decoder.set_max_window_size(LIBZSTD_MAX_WINDOW_SIZE);
+    let mut output = Vec::with_capacity(5);
+    decoder.decode_all_to_vec(&frame, &mut output).unwrap();
+    assert_eq!(output, b"hello");
+
+    // Without a content size the window is allocated up front
+    #[cfg(target_pointer_width = "64")]
+    {
+        let mut frame = vec![0x28, 0xB5, 0x2F, 0xFD, 0x00, 21 << 3];
+        frame.extend_from_slice(&[1 | (5 << 3), 0, 0]);
+        frame.extend_from_slice(b"hello");
+        let mut output = Vec::with_capacity(5);
+        decoder.decode_all_to_vec(&frame, &mut output).unwrap();
+        assert_eq!(output, b"hello");
+    }
 }
 
 pub mod bit_reader;
 pub mod decode_corpus;
 pub mod dict_test;
-#[cfg(feature = "std")]
+#[cfg(all(feature = "std", feature = "encoder"))]
 pub mod encode_corpus;
 pub mod fuzz_regressions;
+#[cfg(all(test, feature = "small-target"))]
+pub mod small_target;
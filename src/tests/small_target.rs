@@ -0,0 +1,84 @@
+//! The configuration of the `small-target` feature, see the Readme. The other tests decode frames
+//! with larger windows, CI runs these ones on their own with
+//! `cargo test --no-default-features --features small-target small_target`.
+
+use crate::frame_decoder::{FrameDecoder, FrameDecoderError, SMALL_TARGET_MAX_WINDOW_SIZE};
+use crate::fse::{FSETable, FSETableError};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A frame with the window of `window_descriptor` and one raw block of `content`.
+fn frame_with_window(window_descriptor: u8, content: &[u8]) -> Vec<u8> {
+    let mut frame = vec![0x28, 0xB5, 0x2F, 0xFD, 0, window_descriptor];
+    let block_header = 1 | (content.len() as u32) << 3;
+    frame.extend_from_slice(&block_header.to_le_bytes()[..3]);
+    frame.extend_from_slice(content);
+    frame
+}
+
+#[test]
+fn small_target_decodes_the_golden_frames() {
+    let frames: [(&[u8], &[u8]); 3] = [
+        (
+            include_bytes!("../self_test/z000094.zst"),
+            include_bytes!("../../decodecorpus_files/z000094"),
+        ),
+        (
+            include_bytes!("../self_test/z000053.zst"),
+            include_bytes!("../../decodecorpus_files/z000053"),
+        ),
+        (
+            include_bytes!("../self_test/z000017.zst"),
+            include_bytes!("../../decodecorpus_files/z000017"),
+        ),
+    ];
+    let mut decoder = FrameDecoder::new();
+    for (frame, content) in frames {
+        let mut output = Vec::new();
+        decoder
+            .decode_all_to_vec_with_limit(frame, &mut output, content.len())
+            .unwrap();
+        assert_eq!(output, content);
+    }
+}
+
+#[test]
+fn small_target_limits_the_window() {
+    let mut decoder = FrameDecoder::new();
+    decoder.set_max_window_size(1 << 20);
+    decoder.set_ultra(true);
+    assert_eq!(decoder.max_window_size(), SMALL_TARGET_MAX_WINDOW_SIZE);
+
+    // 8 KiB windows are accepted
+    let mut output = Vec::new();
+    decoder
+        .decode_all_to_vec_with_limit(&frame_with_window(3 << 3, b"hello"), &mut output, 5)
+        .unwrap();
+    assert_eq!(output, b"hello");
+
+    // 16 KiB windows are not
+    let mut output = Vec::new();
+    assert!(matches!(
+        decoder.decode_all_to_vec_with_limit(&frame_with_window(4 << 3, b"hello"), &mut output, 5),
+        Err(FrameDecoderError::WindowSizeTooBig {
+            requested: 16384,
+            max: SMALL_TARGET_MAX_WINDOW_SIZE,
+        })
+    ));
+}
+
+#[test]
+fn small_target_tables_only_fit_zstandard_tables() {
+    let mut table = FSETable::new(255);
+    // Literal and match length tables have an accuracy log of up to 9
+    let mut probabilities = vec![0; 36];
+    probabilities[0] = 1 << 9;
+    table.build_from_probabilities(9, &probabilities).unwrap();
+    assert_eq!(table.decode.len(), 1 << 9);
+
+    probabilities[0] = 1 << 10;
+    assert!(matches!(
+        table.build_from_probabilities(10, &probabilities),
+        Err(FSETableError::AccLogTooBig { got: 10, max: 9 })
+    ));
+}
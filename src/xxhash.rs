@@ -0,0 +1,130 @@
+//! The 64 bit xxHash (XXH64) that frames use for their checksums, see [xxh64] and [XxHash64].
+//!
+//! This is the same implementation that checks the checksums of frames, so applications that need
+//! XXH64 for other purposes, e.g. to check the content of a container, do not need a second crate
+//! for it.
+//!
+//! ```
+//! use ruzstd::xxhash::{xxh64, XxHash64};
+//!
+//! let mut hasher = XxHash64::with_seed(0);
+//! hasher.update(b"Hello, ");
+//! hasher.update(b"World!");
+//! assert_eq!(hasher.digest(), xxh64(b"Hello, World!", 0));
+//! ```
+
+use core::hash::Hasher;
+
+/// The XXH64 hash of `data` with the given `seed`.
+pub fn xxh64(data: &[u8], seed: u64) -> u64 {
+    let mut hasher = XxHash64::with_seed(seed);
+    hasher.update(data);
+    hasher.digest()
+}
+
+/// The checksum of a frame with the content `content`, i.e. the lower 32 bits of its XXH64 hash
+/// with seed 0.
+pub fn frame_checksum(content: &[u8]) -> u32 {
+    xxh64(content, 0) as u32
+}
+
+/// Calculates the XXH64 hash of data that arrives in pieces.
+///
+/// It also implements [Hasher], so it can be used with [core::hash::Hash] and hash maps, where
+/// [Hasher::finish] is the same as [XxHash64::digest].
+#[derive(Clone)]
+pub struct XxHash64 {
+    hasher: twox_hash::XxHash64,
+}
+
+impl XxHash64 {
+    /// Start hashing with the given `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            hasher: twox_hash::XxHash64::with_seed(seed),
+        }
+    }
+
+    /// Add the next piece of data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.write(data);
+    }
+
+    /// The hash of all data that was added so far. More data can be added afterwards.
+    pub fn digest(&self) -> u64 {
+        self.hasher.finish()
+    }
+}
+
+impl Default for XxHash64 {
+    /// Start hashing with seed 0, like the checksums of frames.
+    fn default() -> Self {
+        Self::with_seed(0)
+    }
+}
+
+impl Hasher for XxHash64 {
+    fn finish(&self) -> u64 {
+        self.digest()
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.update(bytes);
+    }
+}
+
+impl core::fmt::Debug for XxHash64 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("XxHash64")
+            .field("digest", &self.digest())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{xxh64, XxHash64};
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(xxh64(b"", 0), 0xEF46_DB37_51D8_E999);
+        assert_eq!(xxh64(b"a", 0), 0xD24E_C4F1_A98C_6E5B);
+        assert_eq!(xxh64(b"abc", 0), 0x44BC_2CF5_AD77_0999);
+        assert_eq!(xxh64(b"", 1), 0xD5AF_BA13_36A3_BE4B);
+    }
+
+    #[test]
+    fn pieces() {
+        let data: alloc::vec::Vec<u8> = (0..1000u32).map(|i| (i * 7) as u8).collect();
+        for piece_size in [1, 3, 31, 32, 33, 1000] {
+            let mut hasher = XxHash64::with_seed(42);
+            for piece in data.chunks(piece_size) {
+                hasher.update(piece);
+            }
+            assert_eq!(hasher.digest(), xxh64(&data, 42));
+        }
+    }
+
+    #[cfg(feature = "encoder")]
+    #[test]
+    fn frame_checksums() {
+        use super::frame_checksum;
+        use crate::encoding::{EncodeOptions, FrameCompressor};
+        use core::convert::TryInto;
+
+        let data = b"The checksum is the lower half of the hash".repeat(10);
+        let mut compressed = alloc::vec::Vec::new();
+        let options = EncodeOptions {
+            checksum: true,
+            ..EncodeOptions::default()
+        };
+        FrameCompressor::with_options(&data[..], &mut compressed, options)
+            .compress()
+            .unwrap();
+        let checksum = &compressed[compressed.len() - 4..];
+        assert_eq!(
+            u32::from_le_bytes(checksum.try_into().unwrap()),
+            frame_checksum(&data)
+        );
+    }
+}